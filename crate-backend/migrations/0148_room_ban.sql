@@ -0,0 +1,12 @@
+create table room_ban (
+    room_id uuid not null,
+    user_id uuid not null,
+    creator_id uuid not null,
+    reason text,
+    created_at timestamp not null default now(),
+    expires_at timestamp,
+    primary key (room_id, user_id),
+    foreign key (room_id) references room(id),
+    foreign key (user_id) references usr(id),
+    foreign key (creator_id) references usr(id)
+);
@@ -0,0 +1,21 @@
+create table totp (
+    user_id uuid primary key,
+    secret bytea not null,
+    is_valid boolean not null default false,
+    last_used_step bigint,
+    failed_attempts int not null default 0,
+    failed_at timestamp,
+    created_at timestamp not null default now(),
+    foreign key (user_id) references usr(id)
+);
+
+create table totp_recovery_code (
+    user_id uuid not null,
+    code_hash text not null,
+    code_hint text not null,
+    used_at timestamp,
+    used_by uuid,
+    primary key (user_id, code_hash),
+    foreign key (user_id) references usr(id),
+    foreign key (used_by) references session(id) on delete set null
+);
@@ -0,0 +1,38 @@
+-- the old inbox table was never written to
+drop table inbox;
+
+create type notification_reason as enum ('Mention', 'MentionBulk', 'Reply', 'New');
+
+create table inbox (
+    id uuid primary key,
+    user_id uuid not null,
+    room_id uuid,
+    thread_id uuid not null,
+    message_id uuid not null,
+    reason notification_reason not null,
+    added_at timestamp not null default now(),
+    read_at timestamp,
+    foreign key (user_id) references usr(id),
+    foreign key (room_id) references room(id),
+    foreign key (thread_id) references thread(id)
+);
+
+create index inbox_user_id_idx on inbox (user_id, id);
+
+create table notification_config_room (
+    user_id uuid not null,
+    room_id uuid not null,
+    config jsonb not null,
+    primary key (user_id, room_id),
+    foreign key (user_id) references usr(id),
+    foreign key (room_id) references room(id)
+);
+
+create table notification_config_thread (
+    user_id uuid not null,
+    thread_id uuid not null,
+    config jsonb not null,
+    primary key (user_id, thread_id),
+    foreign key (user_id) references usr(id),
+    foreign key (thread_id) references thread(id)
+);
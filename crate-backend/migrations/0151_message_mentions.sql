@@ -0,0 +1 @@
+alter table message add column mentions jsonb;
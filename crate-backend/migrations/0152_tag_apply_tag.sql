@@ -0,0 +1,15 @@
+create table tag_apply_tag (
+    target_id uuid,
+    tag_id uuid,
+    primary key (target_id, tag_id),
+    foreign key (target_id) references tag(id),
+    foreign key (tag_id) references tag(id)
+);
+
+-- every tag applied to a thread, including tags implied by tag composition
+create recursive view tag_apply_thread_implied (thread_id, tag_id) as
+    select thread_id, tag_id from tag_apply_thread
+    union
+    select i.thread_id, t.tag_id
+    from tag_apply_thread_implied i
+    join tag_apply_tag t on t.target_id = i.tag_id;
@@ -0,0 +1,27 @@
+alter type thread_type add value 'Report';
+alter type message_type add value 'ModerationReport';
+
+create type report_destination as enum ('Room', 'Server');
+create type report_status as enum ('Open', 'Duplicate', 'Invalid', 'Resolved');
+
+create table report_thread (
+    thread_id uuid primary key,
+    destination report_destination not null,
+    target jsonb not null,
+    status report_status not null default 'Open',
+    foreign key (thread_id) references thread(id)
+);
+
+create unique index report_thread_open on report_thread (destination, target) where status = 'Open';
+
+create table report (
+    id uuid primary key,
+    thread_id uuid not null,
+    reporter_id uuid not null,
+    reason jsonb not null,
+    note text,
+    created_at timestamp not null default now(),
+    foreign key (thread_id) references report_thread(thread_id),
+    foreign key (reporter_id) references usr(id),
+    unique (thread_id, reporter_id)
+);
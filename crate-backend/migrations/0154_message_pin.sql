@@ -0,0 +1,12 @@
+alter type message_type add value 'MessagePinned';
+alter type message_type add value 'MessageUnpinned';
+
+create table message_pin (
+    message_id uuid primary key,
+    thread_id uuid not null,
+    position int not null,
+    pinned_at timestamp not null default now(),
+    foreign key (thread_id) references thread(id)
+);
+
+create index message_pin_thread on message_pin (thread_id, position);
@@ -0,0 +1 @@
+alter type message_type add value 'MessagesMoved';
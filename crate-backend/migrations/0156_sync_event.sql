@@ -0,0 +1,12 @@
+create table sync_event (
+    id uuid primary key,
+    -- the order events were committed in, since ids are created before writing.
+    -- assigned from sync_event_pos after the event itself is committed
+    pos bigint unique,
+    payload jsonb not null,
+    created_at timestamp not null default now()
+);
+
+create sequence sync_event_pos;
+
+create index sync_event_created_at on sync_event (created_at);
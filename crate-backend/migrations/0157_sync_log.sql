@@ -0,0 +1,14 @@
+create table sync_log_seq (
+    user_id uuid primary key references usr (id),
+    seq bigint not null default 0,
+    trimmed_seq bigint not null default 0
+);
+
+create table sync_log (
+    user_id uuid not null references usr (id),
+    seq bigint not null,
+    event_id uuid not null references sync_event (id) on delete cascade,
+    primary key (user_id, seq)
+);
+
+create index sync_log_event_id on sync_log (event_id);
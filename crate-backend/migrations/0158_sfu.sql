@@ -0,0 +1,19 @@
+create table sfu (
+    id uuid primary key,
+    url text not null,
+    region text not null,
+    token_name text not null,
+    load real not null,
+    seen_at timestamp not null default now()
+);
+
+create table sfu_thread (
+    thread_id uuid primary key references thread (id) on delete cascade,
+    sfu_id uuid not null references sfu (id) on delete cascade
+);
+
+-- the call each user is in, as reported by the sfu hosting it
+create table voice_member (
+    user_id uuid primary key references usr (id) on delete cascade,
+    thread_id uuid not null references thread (id) on delete cascade
+);
@@ -0,0 +1,2 @@
+alter table room_member add column voice_mute boolean not null default false;
+alter table room_member add column voice_deaf boolean not null default false;
@@ -0,0 +1,39 @@
+-- threads a user can view, for searching. overwrites are applied like in
+-- permission_thread_get: room then thread, roles then user. threads outside
+-- rooms start with no permissions, so only their own overwrites let people in.
+create function thread_viewable(viewer_id uuid) returns table (thread_id uuid)
+language sql stable as $$
+    with view_overwrite as (
+        select target_id,
+            bool_or(allow ? 'View') filter (where actor_id <> viewer_id) as role_allow,
+            bool_or(deny ? 'View') filter (where actor_id <> viewer_id) as role_deny,
+            bool_or(allow ? 'View') filter (where actor_id = viewer_id) as user_allow,
+            bool_or(deny ? 'View') filter (where actor_id = viewer_id) as user_deny
+        from permission_overwrite
+        where actor_id = viewer_id
+           or actor_id in (select role_id from role_member where user_id = viewer_id)
+        group by target_id
+    )
+    select thread.id from thread
+    left join room_member on thread.room_id = room_member.room_id
+        and room_member.user_id = viewer_id and room_member.membership = 'Join'
+    left join view_overwrite room_view on room_view.target_id = thread.room_id
+    left join view_overwrite thread_view on thread_view.target_id = thread.id
+    where (thread.room_id is null or room_member.user_id is not null)
+      and thread.deleted_at is null
+      and thread.type <> 'Report'
+      and case
+          when thread_view.user_deny then false
+          when thread_view.user_allow then true
+          else not coalesce(thread_view.role_deny, false) and (
+              coalesce(thread_view.role_allow, false) or (thread.room_id is not null and case
+                  when room_view.user_deny then false
+                  when room_view.user_allow then true
+                  else not coalesce(room_view.role_deny, false)
+              end)
+          )
+      end
+$$;
+
+create index thread_fts on thread using gin (to_tsvector('english', name || ' ' || coalesce(description, '')));
+create index room_fts on room using gin (to_tsvector('english', name || ' ' || coalesce(description, '')));
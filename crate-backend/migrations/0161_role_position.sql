@@ -0,0 +1,25 @@
+alter table role add column position bigint not null default 0;
+alter table room add column owner_id uuid references usr (id);
+
+-- roles that grant more permissions outrank the ones that grant less, like before
+update role set position = ranked.position
+from (
+    select id, rank() over (
+        partition by room_id
+        order by 'Admin' = any(permissions), cardinality(permissions)
+    ) - 1 as position
+    from role
+) ranked
+where role.id = ranked.id;
+
+-- existing rooms are owned by whichever of their admins has been a member longest
+update room set owner_id = owners.user_id
+from (
+    select distinct on (room_member.room_id) room_member.room_id, room_member.user_id
+    from room_member
+    join role_member on role_member.user_id = room_member.user_id
+    join role on role.id = role_member.role_id and role.room_id = room_member.room_id
+    where room_member.membership = 'Join' and 'Admin' = any(role.permissions)
+    order by room_member.room_id, room_member.membership_updated_at, room_member.user_id
+) owners
+where room.id = owners.room_id;
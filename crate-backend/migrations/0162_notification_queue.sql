@@ -0,0 +1,12 @@
+-- messages waiting to have notifications created for them
+create table notification_queue (
+    message_id uuid primary key,
+    thread_id uuid not null references thread (id),
+    author_id uuid not null references usr (id),
+    status text not null default 'pending',
+    retries integer not null default 0,
+    last_attempt_at timestamp,
+    error_message text,
+    created_at timestamp not null default now(),
+    claimed_at timestamp
+);
@@ -29,12 +29,16 @@ select
     msg.edited_at,
     msg.deleted_at,
     msg.removed_at,
+    msg.mentions,
     coalesce(att_json.attachments, '{}') as "attachments!",
     msg.embeds as "embeds",
-    r.json as "reactions"
+    r.json as "reactions",
+    pin.pinned_at as "pinned_at?",
+    pin.position as "pinned_order?"
 from message as msg
 left join att_json on att_json.version_id = msg.version_id
 left join message_reaction r on r.message_id = msg.id
+left join (select message_id, pinned_at, position from message_pin) pin on pin.message_id = msg.id
 where is_latest and thread_id = $1 and msg.deleted_at is null
   and msg.id > $3 AND msg.id < $4
 order by (CASE WHEN $5 = 'f' THEN msg.id END), msg.id DESC LIMIT $6
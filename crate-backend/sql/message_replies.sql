@@ -56,13 +56,17 @@ select
     msg.edited_at,
     msg.deleted_at,
     msg.removed_at,
+    msg.mentions,
     coalesce(att_json.attachments, '{}') as "attachments!",
     msg.embeds as "embeds",
-    r.json as "reactions"
+    r.json as "reactions",
+    pin.pinned_at as "pinned_at?",
+    pin.position as "pinned_order?"
 from message as msg
 join ranked_messages rm on msg.id = rm.id
 left join att_json on att_json.version_id = msg.version_id
 left join message_reaction r on r.message_id = msg.id
+left join (select message_id, pinned_at, position from message_pin) pin on pin.message_id = msg.id
 where is_latest and thread_id = $1 and msg.deleted_at is null and (rm.rn <= $4 or $4 is null)
   and msg.id > $5 AND msg.id < $6
 order by (CASE WHEN $7 = 'f' THEN msg.id END), msg.id DESC LIMIT $8
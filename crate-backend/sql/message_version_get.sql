@@ -1,6 +1,6 @@
 with
 reaction_counts as (
-    select message_id, key, min(position) as pos, count(*) as count, bool_or(user_id = $2) as self_reacted
+    select message_id, key, min(position) as pos, count(*) as count, bool_or(user_id = $3) as self_reacted
     from reaction
     group by message_id, key
 ),
@@ -29,10 +29,14 @@ select
     msg.edited_at,
     msg.deleted_at,
     msg.removed_at,
+    msg.mentions,
     coalesce(att_json.attachments, '{}') as "attachments!",
     msg.embeds as "embeds",
-    r.json as "reactions"
+    r.json as "reactions",
+    pin.pinned_at as "pinned_at?",
+    pin.position as "pinned_order?"
 from message as msg
 left join att_json on att_json.version_id = msg.version_id
 left join message_reaction r on r.message_id = msg.id
-where thread_id = $1 and msg.version_id = $3 and msg.deleted_at is null
+left join (select message_id, pinned_at, position from message_pin) pin on pin.message_id = msg.id
+where thread_id = $1 and msg.version_id = $2 and msg.deleted_at is null
@@ -29,12 +29,16 @@ select
     msg.edited_at,
     msg.deleted_at,
     msg.removed_at,
+    msg.mentions,
     coalesce(att_json.attachments, '{}') as "attachments!",
     msg.embeds as "embeds",
-    r.json as "reactions"
+    r.json as "reactions",
+    pin.pinned_at as "pinned_at?",
+    pin.position as "pinned_order?"
 from message as msg
 left join att_json on att_json.version_id = msg.version_id
 left join message_reaction r on r.message_id = msg.id
+left join (select message_id, pinned_at, position from message_pin) pin on pin.message_id = msg.id
 where thread_id = $1 and msg.id = $2 and msg.deleted_at is null
   and msg.id > $4 and msg.id < $5
 order by (case when $6 = 'f' then msg.version_id end), msg.version_id desc limit $7
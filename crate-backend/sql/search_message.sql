@@ -1,8 +1,36 @@
 with
+    muted_room as (
+        select room_id from notification_config_room
+        where user_id = $1 and jsonb_typeof(config->'mute') = 'object'
+          and (config->'mute'->>'expires_at' is null or (config->'mute'->>'expires_at')::timestamptz > now())
+    ),
+    muted_thread as (
+        select thread_id from notification_config_thread
+        where user_id = $1 and jsonb_typeof(config->'mute') = 'object'
+          and (config->'mute'->>'expires_at' is null or (config->'mute'->>'expires_at')::timestamptz > now())
+    ),
     thread_viewer as (
         select thread.id from thread
-        join room_member on thread.room_id = room_member.room_id
-        where room_member.user_id = $1
+        join thread_viewable($1) viewable on viewable.thread_id = thread.id
+        left join room on thread.room_id = room.id
+        where (cardinality($7::uuid[]) = 0 or thread.room_id = any($7))
+          and (cardinality($8::uuid[]) = 0 or thread.id = any($8))
+          and ($15 or exists (
+              select 1 from thread_member
+              where thread_member.thread_id = thread.id and thread_member.user_id = $1 and thread_member.membership = 'Join'
+          ))
+          and ($16 or (
+              (thread.room_id is null or thread.room_id not in (select room_id from muted_room))
+              and thread.id not in (select thread_id from muted_thread)
+          ))
+          and ($17::boolean is null or coalesce(room.type = 'Dm', false) = $17)
+    ),
+    hidden_author as (
+        select other_id from user_relationship
+        where user_id = $1 and (
+            (rel = 'Block' and not $14)
+            or ((ignore_forever or ignore_until > now()) and not ($13 or $14))
+        )
     ),
     reaction_counts as (
         select message_id, key, min(position) as pos, count(*) as count, bool_or(user_id = $1) as self_reacted
@@ -34,14 +62,35 @@ select
     msg.edited_at,
     msg.deleted_at,
     msg.removed_at,
+    msg.mentions,
     coalesce(att_json.attachments, '{}') as "attachments!",
     msg.embeds as "embeds",
-    r.json as "reactions"
+    r.json as "reactions",
+    pin.pinned_at as "pinned_at?",
+    pin.position as "pinned_order?"
 from message as msg
 join thread_viewer on msg.thread_id = thread_viewer.id
 left join att_json on att_json.version_id = msg.version_id
 left join message_reaction r on r.message_id = msg.id
+left join (select message_id, pinned_at, position from message_pin) pin on pin.message_id = msg.id
 where is_latest and msg.deleted_at is null
-  and msg.id > $2 AND msg.id < $3
-  and content @@ websearch_to_tsquery($6)
-order by (CASE WHEN $4 = 'f' THEN msg.id END), msg.id DESC LIMIT $5
+  and to_tsvector('english', content) @@ websearch_to_tsquery('english', $6)
+  and (cardinality($9::uuid[]) = 0 or msg.author_id = any($9))
+  and msg.author_id not in (select other_id from hidden_author)
+  and (
+      select bool_and(exists (
+          select 1 from message_attachment ma
+          join media m on m.id = ma.media_id
+          where ma.version_id = msg.version_id
+            and coalesce(m.data->'source'->>'mime', m.data->'tracks'->0->>'mime') like mime_prefix || '%'
+      ))
+      from unnest($10::text[]) as mime_prefix
+  ) is not false
+  and (not $11 or content ~ 'https?://')
+  and (not $12 or jsonb_array_length(coalesce(msg.embeds, '[]')) > 0)
+  and (not $18 or pin.message_id is not null)
+  and msg.id > $2 and msg.id < $3
+order by
+    (case when $19 then ts_rank(to_tsvector('english', content), websearch_to_tsquery('english', $6)) end) desc nulls last,
+    (case when $4 = 'f' then msg.id end), msg.id desc
+limit $5
@@ -1,10 +1,53 @@
 with
+    muted_room as (
+        select room_id from notification_config_room
+        where user_id = $1 and jsonb_typeof(config->'mute') = 'object'
+          and (config->'mute'->>'expires_at' is null or (config->'mute'->>'expires_at')::timestamptz > now())
+    ),
+    muted_thread as (
+        select thread_id from notification_config_thread
+        where user_id = $1 and jsonb_typeof(config->'mute') = 'object'
+          and (config->'mute'->>'expires_at' is null or (config->'mute'->>'expires_at')::timestamptz > now())
+    ),
     thread_viewer as (
         select thread.id from thread
-        join room_member on thread.room_id = room_member.room_id
-        where room_member.user_id = $1
+        join thread_viewable($1) viewable on viewable.thread_id = thread.id
+        left join room on thread.room_id = room.id
+        where (cardinality($3::uuid[]) = 0 or thread.room_id = any($3))
+          and (cardinality($4::uuid[]) = 0 or thread.id = any($4))
+          and ($11 or exists (
+              select 1 from thread_member
+              where thread_member.thread_id = thread.id and thread_member.user_id = $1 and thread_member.membership = 'Join'
+          ))
+          and ($12 or (
+              (thread.room_id is null or thread.room_id not in (select room_id from muted_room))
+              and thread.id not in (select thread_id from muted_thread)
+          ))
+          and ($13::boolean is null or coalesce(room.type = 'Dm', false) = $13)
+    ),
+    hidden_author as (
+        select other_id from user_relationship
+        where user_id = $1 and (
+            (rel = 'Block' and not $10)
+            or ((ignore_forever or ignore_until > now()) and not ($9 or $10))
+        )
     )
 select count(*) -- unsure about the performance?
 from message as msg
+join thread_viewer on msg.thread_id = thread_viewer.id
 where is_latest and msg.deleted_at is null
-  and content @@ websearch_to_tsquery($2)
+  and to_tsvector('english', content) @@ websearch_to_tsquery('english', $2)
+  and (cardinality($5::uuid[]) = 0 or msg.author_id = any($5))
+  and msg.author_id not in (select other_id from hidden_author)
+  and (
+      select bool_and(exists (
+          select 1 from message_attachment ma
+          join media m on m.id = ma.media_id
+          where ma.version_id = msg.version_id
+            and coalesce(m.data->'source'->>'mime', m.data->'tracks'->0->>'mime') like mime_prefix || '%'
+      ))
+      from unnest($6::text[]) as mime_prefix
+  ) is not false
+  and (not $7 or content ~ 'https?://')
+  and (not $8 or jsonb_array_length(coalesce(msg.embeds, '[]')) > 0)
+  and (not $14 or exists (select 1 from message_pin where message_pin.message_id = msg.id))
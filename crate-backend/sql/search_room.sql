@@ -0,0 +1,25 @@
+with muted_room as (
+    select room_id from notification_config_room
+    where user_id = $1 and jsonb_typeof(config->'mute') = 'object'
+      and (config->'mute'->>'expires_at' is null or (config->'mute'->>'expires_at')::timestamptz > now())
+)
+select
+    room.id,
+    room.version_id,
+    room.name,
+    room.description,
+    room.icon,
+    room.owner_id,
+    NULL::uuid as dm_uid_a,
+    NULL::uuid as dm_uid_b
+from room
+join room_member on room_member.room_id = room.id
+where room_member.user_id = $1 and room_member.membership = 'Join'
+  and room.id > $2 and room.id < $3
+  and to_tsvector('english', room.name || ' ' || coalesce(room.description, '')) @@ websearch_to_tsquery('english', $6)
+  and ($7::boolean is null or (room.type = 'Dm') = $7)
+  and ($8 or room.id not in (select room_id from muted_room))
+order by
+    (case when $9 then ts_rank(to_tsvector('english', room.name || ' ' || coalesce(room.description, '')), websearch_to_tsquery('english', $6)) end) desc nulls last,
+    (case when $4 = 'f' then room.id end), room.id desc
+limit $5
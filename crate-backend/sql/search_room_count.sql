@@ -0,0 +1,12 @@
+with muted_room as (
+    select room_id from notification_config_room
+    where user_id = $1 and jsonb_typeof(config->'mute') = 'object'
+      and (config->'mute'->>'expires_at' is null or (config->'mute'->>'expires_at')::timestamptz > now())
+)
+select count(*)
+from room
+join room_member on room_member.room_id = room.id
+where room_member.user_id = $1 and room_member.membership = 'Join'
+  and to_tsvector('english', room.name || ' ' || coalesce(room.description, '')) @@ websearch_to_tsquery('english', $2)
+  and ($3::boolean is null or (room.type = 'Dm') = $3)
+  and ($4 or room.id not in (select room_id from muted_room))
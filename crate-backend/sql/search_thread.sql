@@ -0,0 +1,79 @@
+with last_id as (
+    select thread_id, max(version_id) as last_version_id
+    from message
+    where deleted_at is null
+    group by thread_id
+),
+message_count as (
+    select thread_id, count(*) as count
+    from message
+    where is_latest
+    group by thread_id
+),
+permission_overwrites as (
+    select target_id, json_agg(jsonb_build_object('id', actor_id, 'type', type, 'allow', allow, 'deny', deny)) as overwrites
+    from permission_overwrite
+    group by target_id
+),
+muted_room as (
+    select room_id from notification_config_room
+    where user_id = $1 and jsonb_typeof(config->'mute') = 'object'
+      and (config->'mute'->>'expires_at' is null or (config->'mute'->>'expires_at')::timestamptz > now())
+),
+muted_thread as (
+    select thread_id from notification_config_thread
+    where user_id = $1 and jsonb_typeof(config->'mute') = 'object'
+      and (config->'mute'->>'expires_at' is null or (config->'mute'->>'expires_at')::timestamptz > now())
+),
+thread_viewer as (
+    select thread.id from thread
+    join thread_viewable($1) viewable on viewable.thread_id = thread.id
+    left join room on thread.room_id = room.id
+    where (cardinality($9::uuid[]) = 0 or thread.room_id = any($9))
+      and ($11::boolean is null or coalesce(room.type = 'Dm', false) = $11)
+      and ($7 or exists (
+          select 1 from thread_member
+          where thread_member.thread_id = thread.id and thread_member.user_id = $1 and thread_member.membership = 'Join'
+      ))
+      and ($8 or (
+          (thread.room_id is null or thread.room_id not in (select room_id from muted_room))
+          and thread.id not in (select thread_id from muted_thread)
+      ))
+      and not exists (
+          select unnest($10::uuid[])
+          except
+          select tag_id from tag_apply_thread_implied where tag_apply_thread_implied.thread_id = thread.id
+      )
+)
+select
+    thread.id,
+    thread.type as "ty: DbThreadType",
+    thread.room_id,
+    thread.creator_id,
+    thread.name,
+    thread.version_id,
+    thread.description,
+    coalesce(count, 0) as "message_count!",
+    last_version_id as "last_version_id!",
+    coalesce(permission_overwrites.overwrites, '[]') as "permission_overwrites!",
+    coalesce((select array_agg(tag_id order by tag_id) from tag_apply_thread where tag_apply_thread.thread_id = thread.id), '{}') as "tags!",
+    (
+        select jsonb_build_object(
+            'target', r.target,
+            'destination', r.destination,
+            'status', r.status,
+            'report_count', (select count(*) from report where report.thread_id = r.thread_id)
+        )
+        from report_thread r where r.thread_id = thread.id
+    ) as report
+from thread
+join thread_viewer on thread_viewer.id = thread.id
+join message_count on message_count.thread_id = thread.id
+join last_id on last_id.thread_id = thread.id
+left join permission_overwrites on permission_overwrites.target_id = thread.id
+where thread.id > $2 and thread.id < $3
+  and to_tsvector('english', thread.name || ' ' || coalesce(thread.description, '')) @@ websearch_to_tsquery('english', $6)
+order by
+    (case when $12 then ts_rank(to_tsvector('english', thread.name || ' ' || coalesce(thread.description, '')), websearch_to_tsquery('english', $6)) end) desc nulls last,
+    (case when $4 = 'f' then thread.id end), thread.id desc
+limit $5
@@ -0,0 +1,48 @@
+with last_id as (
+    select thread_id, max(version_id) as last_version_id
+    from message
+    where deleted_at is null
+    group by thread_id
+),
+message_count as (
+    select thread_id, count(*) as count
+    from message
+    where is_latest
+    group by thread_id
+),
+muted_room as (
+    select room_id from notification_config_room
+    where user_id = $1 and jsonb_typeof(config->'mute') = 'object'
+      and (config->'mute'->>'expires_at' is null or (config->'mute'->>'expires_at')::timestamptz > now())
+),
+muted_thread as (
+    select thread_id from notification_config_thread
+    where user_id = $1 and jsonb_typeof(config->'mute') = 'object'
+      and (config->'mute'->>'expires_at' is null or (config->'mute'->>'expires_at')::timestamptz > now())
+),
+thread_viewer as (
+    select thread.id from thread
+    join thread_viewable($1) viewable on viewable.thread_id = thread.id
+    left join room on thread.room_id = room.id
+    where (cardinality($5::uuid[]) = 0 or thread.room_id = any($5))
+      and ($7::boolean is null or coalesce(room.type = 'Dm', false) = $7)
+      and ($3 or exists (
+          select 1 from thread_member
+          where thread_member.thread_id = thread.id and thread_member.user_id = $1 and thread_member.membership = 'Join'
+      ))
+      and ($4 or (
+          (thread.room_id is null or thread.room_id not in (select room_id from muted_room))
+          and thread.id not in (select thread_id from muted_thread)
+      ))
+      and not exists (
+          select unnest($6::uuid[])
+          except
+          select tag_id from tag_apply_thread_implied where tag_apply_thread_implied.thread_id = thread.id
+      )
+)
+select count(*)
+from thread
+join thread_viewer on thread_viewer.id = thread.id
+join message_count on message_count.thread_id = thread.id
+join last_id on last_id.thread_id = thread.id
+where to_tsvector('english', thread.name || ' ' || coalesce(thread.description, '')) @@ websearch_to_tsquery('english', $2)
@@ -26,7 +26,17 @@ select
     thread.description,
     coalesce(count, 0) as "message_count!",
     last_version_id as "last_version_id!",
-    coalesce(permission_overwrites.overwrites, '[]') as "permission_overwrites!"
+    coalesce(permission_overwrites.overwrites, '[]') as "permission_overwrites!",
+    coalesce((select array_agg(tag_id order by tag_id) from tag_apply_thread where tag_apply_thread.thread_id = thread.id), '{}') as "tags!",
+    (
+        select jsonb_build_object(
+            'target', r.target,
+            'destination', r.destination,
+            'status', r.status,
+            'report_count', (select count(*) from report where report.thread_id = r.thread_id)
+        )
+        from report_thread r where r.thread_id = thread.id
+    ) as report
 from thread
 join message_count on message_count.thread_id = thread.id
 join last_id on last_id.thread_id = thread.id
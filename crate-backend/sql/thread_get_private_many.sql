@@ -0,0 +1,29 @@
+with last_id as (
+    select thread_id, max(version_id) as last_version_id
+    from message
+    where deleted_at is null
+    group by thread_id
+)
+select
+    thread.id,
+    thread.type as "ty: DbThreadType",
+    unread.message_id as "last_read_id?",
+    coalesce(unread.version_id < last_version_id, true) as "is_unread!",
+    (
+        select count(*) from message
+        where message.thread_id = thread.id
+          and message.is_latest and message.deleted_at is null
+          and (unread.message_id is null or message.id > unread.message_id)
+          and (
+              message.mentions->'users' ? usr.id::text
+              or coalesce((message.mentions->>'all_in_thread')::boolean, false)
+              or coalesce((message.mentions->>'all_in_room')::boolean, false)
+          )
+    ) as "mention_count!",
+    notification_config_thread.config as "notifications?"
+from thread
+join last_id on last_id.thread_id = thread.id
+full outer join usr on true
+left join unread on usr.id = unread.user_id and thread.id = unread.thread_id
+left join notification_config_thread on usr.id = notification_config_thread.user_id and thread.id = notification_config_thread.thread_id
+where thread.id = any($1) and usr.id = $2
@@ -25,10 +25,28 @@ select
     thread.description,
     coalesce(count, 0) as "message_count!",
     last_version_id as "last_version_id!",
-    coalesce(permission_overwrites.overwrites, '[]') as "permission_overwrites!"
+    coalesce(permission_overwrites.overwrites, '[]') as "permission_overwrites!",
+    coalesce((select array_agg(tag_id order by tag_id) from tag_apply_thread where tag_apply_thread.thread_id = thread.id), '{}') as "tags!",
+    (
+        select jsonb_build_object(
+            'target', r.target,
+            'destination', r.destination,
+            'status', r.status,
+            'report_count', (select count(*) from report where report.thread_id = r.thread_id)
+        )
+        from report_thread r where r.thread_id = thread.id
+    ) as report
 from thread
 join message_count on message_count.thread_id = thread.id
 join last_id on last_id.thread_id = thread.id
 left join permission_overwrites on permission_overwrites.target_id = thread.id
 where room_id = $1 AND thread.id > $2 AND thread.id < $3 and thread.deleted_at is null
+  and ($6::uuid is null or exists (
+      select 1 from tag_apply_thread_implied i
+      where i.thread_id = thread.id and i.tag_id = $6
+  ))
+  and (thread.id in (select thread_id from thread_viewable($7)) or exists (
+      select 1 from report_thread r
+      where r.thread_id = thread.id and r.destination = any($8)
+  ))
 order by (CASE WHEN $4 = 'f' THEN thread.id END), thread.id DESC LIMIT $5
@@ -0,0 +1,385 @@
+//! server management from the command line
+//!
+//! these operate directly on the database. commands that running nodes need to
+//! hear about, like suspending users, revoking sessions or changing server
+//! permissions, are announced through the postgres event bus. without it they
+//! still make the change, and warn that running nodes need restarting.
+
+use clap::ValueEnum;
+use common::v1::types::{
+    user::Suspended, util::Time, InviteCode, MessageId, MessageSync, MessageType,
+    PaginationDirection, PaginationQuery, Permission, RoomId, RoomMembership, SessionId,
+    SessionStatus, SessionToken, ThreadId, UserId,
+};
+use linkify::LinkFinder;
+use nanoid::nanoid;
+use tracing::warn;
+use url::Url;
+use uuid::Uuid;
+
+use crate::{
+    bus::EventBusPostgres,
+    cli::{
+        AdminCommand, AdminEmbedCommand, AdminInviteCommand, AdminMediaCommand, AdminPermsCommand,
+        AdminSessionCommand, AdminUserCommand, ServerPermission,
+    },
+    config::ConfigEventBus,
+    data::Data,
+    types::{DbRoleCreate, DbUserCreate, MediaLinkType, MessageRef, Role},
+    Error, Result, ServerState,
+};
+
+pub async fn run(s: &ServerState, command: AdminCommand) -> Result<()> {
+    match command {
+        AdminCommand::User { command } => user(s, command).await,
+        AdminCommand::Invite { command } => invite(s, command).await,
+        AdminCommand::Perms { command } => perms(s, command).await,
+        AdminCommand::Session { command } => session(s, command).await,
+        AdminCommand::Media { command } => media(s, command).await,
+        AdminCommand::Embed { command } => embed(s, command).await,
+    }
+}
+
+async fn user(s: &ServerState, command: AdminUserCommand) -> Result<()> {
+    let data = s.data();
+    match command {
+        AdminUserCommand::Create { name } => {
+            let user = data
+                .user_create(DbUserCreate {
+                    parent_id: None,
+                    name,
+                    description: None,
+                    bot: None,
+                    puppet: None,
+                    registered_at: Some(Time::now_utc()),
+                })
+                .await?;
+            println!("{}", user.id);
+        }
+        AdminUserCommand::Suspend {
+            user_id,
+            reason,
+            until,
+        } => {
+            let suspended = Suspended {
+                at: Time::now_utc(),
+                until,
+                reason,
+            };
+            // suspended users are logged out everywhere
+            let tx = data.begin().await?;
+            tx.user_set_suspended(user_id, Some(suspended)).await?;
+            let session_ids = session_ids(&*tx, user_id).await?;
+            for session_id in &session_ids {
+                tx.session_delete(*session_id).await?;
+            }
+            tx.commit().await?;
+            let mut msgs = sessions_deleted(user_id, session_ids);
+            let user = data.user_get(user_id).await?;
+            msgs.push(MessageSync::UserUpdate { user });
+            notify_nodes(s, msgs).await?;
+        }
+        AdminUserCommand::Unsuspend { user_id } => {
+            data.user_set_suspended(user_id, None).await?;
+            let user = data.user_get(user_id).await?;
+            notify_nodes(s, vec![MessageSync::UserUpdate { user }]).await?;
+        }
+        AdminUserCommand::Delete { user_id } => {
+            let tx = data.begin().await?;
+            tx.user_delete(user_id).await?;
+            tx.media_link_delete(user_id.into_inner(), MediaLinkType::AvatarUser)
+                .await?;
+            let session_ids = session_ids(&*tx, user_id).await?;
+            for session_id in &session_ids {
+                tx.session_delete(*session_id).await?;
+            }
+            tx.commit().await?;
+            let mut msgs = sessions_deleted(user_id, session_ids);
+            msgs.push(MessageSync::UserDelete { id: user_id });
+            notify_nodes(s, msgs).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn invite(s: &ServerState, command: AdminInviteCommand) -> Result<()> {
+    match command {
+        AdminInviteCommand::Create {
+            creator,
+            max_uses,
+            expires_at,
+        } => {
+            let data = s.data();
+            // make sure the creator exists
+            data.user_get(creator).await?;
+            let alphabet: Vec<char> =
+                "0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ"
+                    .chars()
+                    .collect();
+            let code = InviteCode(nanoid!(8, &alphabet));
+            data.invite_insert_server(creator, code.clone(), expires_at, max_uses)
+                .await?;
+            println!("{}", code.0);
+        }
+    }
+    Ok(())
+}
+
+async fn perms(s: &ServerState, command: AdminPermsCommand) -> Result<()> {
+    match command {
+        AdminPermsCommand::Grant {
+            user_id,
+            permission,
+        } => {
+            let room_id = server_room(s, permission)?;
+            let tx = s.data().begin().await?;
+            tx.user_get(user_id).await?;
+            let joined = match tx.room_member_get(room_id, user_id).await {
+                Ok(member) => matches!(member.membership, RoomMembership::Join { .. }),
+                Err(Error::NotFound) => false,
+                Err(err) => return Err(err),
+            };
+            if !joined {
+                tx.room_member_put(
+                    room_id,
+                    user_id,
+                    RoomMembership::Join {
+                        override_name: None,
+                        override_description: None,
+                        roles: vec![],
+                    },
+                )
+                .await?;
+                tx.role_apply_default(room_id, user_id).await?;
+            }
+            let role = match server_role(&*tx, room_id, permission).await? {
+                Some(role) => role,
+                None => {
+                    tx.role_create(DbRoleCreate {
+                        room_id,
+                        name: server_role_name(permission),
+                        description: None,
+                        permissions: vec![permission.into()],
+                        is_self_applicable: false,
+                        is_mentionable: false,
+                        is_default: false,
+                        position: 0,
+                    })
+                    .await?
+                }
+            };
+            tx.role_member_put(user_id, role.id).await?;
+            tx.commit().await?;
+            let member = s.data().room_member_get(room_id, user_id).await?;
+            let msg = MessageSync::RoomMemberUpsert { member };
+            notify_nodes(s, vec![msg]).await?;
+        }
+        AdminPermsCommand::Revoke {
+            user_id,
+            permission,
+        } => {
+            let room_id = server_room(s, permission)?;
+            let data = s.data();
+            let Some(role) = server_role(&*data, room_id, permission).await? else {
+                return Ok(());
+            };
+            data.role_member_delete(user_id, role.id).await?;
+            let member = match data.room_member_get(room_id, user_id).await {
+                Ok(member) => member,
+                Err(Error::NotFound) => return Ok(()),
+                Err(err) => return Err(err),
+            };
+            let msg = MessageSync::RoomMemberUpsert { member };
+            notify_nodes(s, vec![msg]).await?;
+        }
+    }
+    Ok(())
+}
+
+/// tell running nodes about a change
+///
+/// nodes cache users, sessions and permissions until they hear about changes,
+/// and only the postgres event bus reaches them from here
+async fn notify_nodes(s: &ServerState, msgs: Vec<MessageSync>) -> Result<()> {
+    match (&s.config.event_bus, s.db.pool()) {
+        (ConfigEventBus::Postgres, Some(pool)) => EventBusPostgres::publish_now(pool, msgs).await,
+        _ => {
+            warn!("event_bus isn't postgres, so restart any running nodes for them to see this change");
+            Ok(())
+        }
+    }
+}
+
+fn sessions_deleted(user_id: UserId, session_ids: Vec<SessionId>) -> Vec<MessageSync> {
+    session_ids
+        .into_iter()
+        .map(|id| MessageSync::SessionDelete {
+            id,
+            user_id: Some(user_id),
+        })
+        .collect()
+}
+
+/// the room where a server permission is granted
+///
+/// moderators need to be in the room that reports are sent to, everything else
+/// is managed in the admin room
+#[allow(clippy::result_large_err)]
+fn server_room(s: &ServerState, permission: ServerPermission) -> Result<RoomId> {
+    match permission {
+        ServerPermission::Reports => s.config.report_room_id.ok_or(Error::BadStatic(
+            "report_room_id must be set to grant server report permissions",
+        )),
+        _ => s.config.admin_room_id.ok_or(Error::BadStatic(
+            "admin_room_id must be set to manage server permissions",
+        )),
+    }
+}
+
+fn server_role_name(permission: ServerPermission) -> String {
+    let name = permission
+        .to_possible_value()
+        .expect("no server permissions are skipped");
+    format!("server {}", name.get_name())
+}
+
+/// find the role used to grant a server permission
+async fn server_role(
+    data: &dyn Data,
+    room_id: RoomId,
+    permission: ServerPermission,
+) -> Result<Option<Role>> {
+    let name = server_role_name(permission);
+    let permission: Permission = permission.into();
+    let mut from = None;
+    loop {
+        let res = data
+            .role_list(
+                room_id,
+                PaginationQuery {
+                    from,
+                    to: None,
+                    dir: Some(PaginationDirection::F),
+                    limit: Some(100),
+                },
+            )
+            .await?;
+        from = res.items.last().map(|r| r.id);
+        if let Some(role) = res
+            .items
+            .into_iter()
+            .find(|r| r.name == name && r.permissions.contains(&permission))
+        {
+            return Ok(Some(role));
+        }
+        if !res.has_more {
+            return Ok(None);
+        }
+    }
+}
+
+async fn session_ids(data: &dyn Data, user_id: UserId) -> Result<Vec<SessionId>> {
+    let mut ids = vec![];
+    let mut from = None;
+    loop {
+        let res = data
+            .session_list(
+                user_id,
+                PaginationQuery {
+                    from,
+                    to: None,
+                    dir: Some(PaginationDirection::F),
+                    limit: Some(100),
+                },
+            )
+            .await?;
+        from = res.items.last().map(|s| s.id);
+        ids.extend(res.items.into_iter().map(|s| s.id));
+        if !res.has_more {
+            return Ok(ids);
+        }
+    }
+}
+
+async fn session(s: &ServerState, command: AdminSessionCommand) -> Result<()> {
+    let data = s.data();
+    match command {
+        AdminSessionCommand::Create { user_id } => {
+            data.user_get(user_id).await?;
+            let token = SessionToken(Uuid::new_v4().to_string());
+            let session = data
+                .session_create(token.clone(), Some("admin".to_owned()))
+                .await?;
+            data.session_set_status(session.id, SessionStatus::Authorized { user_id })
+                .await?;
+            println!("{}", token.0);
+        }
+        AdminSessionCommand::List { user_id } => {
+            for session_id in session_ids(&*data, user_id).await? {
+                let session = data.session_get(session_id).await?;
+                println!("{}", serde_json::to_string(&session)?);
+            }
+        }
+        AdminSessionCommand::Revoke { session_id } => {
+            let session = data.session_get(session_id).await?;
+            data.session_delete(session_id).await?;
+            let msg = MessageSync::SessionDelete {
+                id: session_id,
+                user_id: session.user_id(),
+            };
+            notify_nodes(s, vec![msg]).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn media(s: &ServerState, command: AdminMediaCommand) -> Result<()> {
+    match command {
+        AdminMediaCommand::Thumbnails { media_ids } => {
+            for media_id in media_ids {
+                let media = s.services.media.regenerate_thumbnails(media_id).await?;
+                println!("{}", serde_json::to_string(&media)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn embed(s: &ServerState, command: AdminEmbedCommand) -> Result<()> {
+    match command {
+        AdminEmbedCommand::Refetch {
+            thread_id,
+            message_id,
+        } => refetch_embeds(s, thread_id, message_id).await,
+    }
+}
+
+/// queue every link in a message to be fetched by a running node's embed workers
+async fn refetch_embeds(s: &ServerState, thread_id: ThreadId, message_id: MessageId) -> Result<()> {
+    let data = s.data();
+    // the user only affects reactions, which aren't needed here
+    let message = data
+        .message_get(thread_id, message_id, Uuid::nil().into())
+        .await?;
+    let content = match &message.message_type {
+        MessageType::DefaultMarkdown(m) => m.content.clone(),
+        _ => None,
+    };
+    let Some(content) = content else {
+        return Err(Error::BadStatic("this message has no content"));
+    };
+    for link in LinkFinder::new().links(&content) {
+        let Ok(url) = link.as_str().parse::<Url>() else {
+            continue;
+        };
+        let message_ref = MessageRef {
+            thread_id: message.thread_id,
+            message_id: message.id,
+            version_id: message.version_id,
+        };
+        data.url_embed_queue_insert(Some(message_ref), message.author_id, url.to_string())
+            .await?;
+        println!("{url}");
+    }
+    Ok(())
+}
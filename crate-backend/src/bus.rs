@@ -0,0 +1,404 @@
+//! fan out sync events to every node
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+
+use common::v1::types::emoji::EmojiOwner;
+use common::v1::types::{InviteTarget, InviteTargetId, MessageSync};
+use sqlx::{postgres::PgListener, query, PgPool};
+use tokio::sync::{broadcast::Sender, mpsc};
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use crate::data::{postgres::Postgres, Data, DataSyncLog, Database};
+use crate::types::{SyncAudience, SyncEvent};
+use crate::Result;
+
+const CHANNEL: &str = "sync_event";
+
+/// the most queued events to write to the sync log at once
+const LOG_BATCH: usize = 256;
+
+/// notifications must be shorter than this, in bytes
+const MAX_NOTIFY_LEN: usize = 8000;
+
+/// the longest to wait before retrying something that failed
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// an event to log, with its id and who to log it for
+type LogEntry = (Uuid, MessageSync, Vec<SyncAudience>);
+
+pub trait EventBus: Send + Sync {
+    /// send an event to every node, including this one
+    fn publish(&self, msg: MessageSync);
+}
+
+/// only deliver events to this node, for single node deployments
+pub struct EventBusLocal {
+    tx: mpsc::UnboundedSender<LogEntry>,
+    sushi: Sender<SyncEvent>,
+}
+
+impl EventBusLocal {
+    pub fn new(db: &Database, sushi: Sender<SyncEvent>, max_age: Duration) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(Self::publisher(db.data(), rx, sushi.clone()));
+        tokio::spawn(trim(db.data(), max_age));
+        Self { tx, sushi }
+    }
+
+    /// log events in the order they were published
+    async fn publisher(
+        data: Box<dyn Data>,
+        mut rx: mpsc::UnboundedReceiver<LogEntry>,
+        sushi: Sender<SyncEvent>,
+    ) {
+        let mut entries = Vec::with_capacity(LOG_BATCH);
+        while rx.recv_many(&mut entries, LOG_BATCH).await > 0 {
+            for event in log_with_retry(&*data, std::mem::take(&mut entries)).await {
+                let _ = sushi.send(event);
+            }
+        }
+    }
+}
+
+impl EventBus for EventBusLocal {
+    fn publish(&self, msg: MessageSync) {
+        let Some(audience) = audience(&msg) else {
+            // ephemeral events don't wait for the log
+            let _ = self.sushi.send(SyncEvent { msg, seqs: None });
+            return;
+        };
+        if self.tx.send((Uuid::now_v7(), msg, audience)).is_err() {
+            error!("sync event publisher has stopped");
+        }
+    }
+}
+
+/// deliver events through postgres
+///
+/// events are written to the sync_event table and announced with NOTIFY. every
+/// node LISTENs for announcements and forwards the events to its own clients.
+/// ephemeral events aren't written, and are sent in the announcement instead.
+pub struct EventBusPostgres {
+    tx: mpsc::UnboundedSender<LogEntry>,
+    ephemeral: mpsc::UnboundedSender<MessageSync>,
+}
+
+impl EventBusPostgres {
+    pub fn new(pool: PgPool, sushi: Sender<SyncEvent>, max_age: Duration) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (ephemeral, ephemeral_rx) = mpsc::unbounded_channel();
+        tokio::spawn(Self::publisher(pool.clone(), rx));
+        tokio::spawn(Self::announcer(pool.clone(), ephemeral_rx, tx.clone()));
+        tokio::spawn(Self::listener(pool.clone(), sushi));
+        tokio::spawn(trim(Box::new(Postgres::new(pool)), max_age));
+        Self { tx, ephemeral }
+    }
+
+    /// write events in the order they were published
+    async fn publisher(pool: PgPool, mut rx: mpsc::UnboundedReceiver<LogEntry>) {
+        let data = Postgres::new(pool);
+        let mut entries = Vec::with_capacity(LOG_BATCH);
+        while rx.recv_many(&mut entries, LOG_BATCH).await > 0 {
+            let entries = std::mem::take(&mut entries);
+            let ids = Self::ids(&entries);
+            log_with_retry(&data, entries).await;
+            retry("announce sync events", || Self::notify(&data.pool, &ids)).await;
+        }
+    }
+
+    /// announce ephemeral events, separately so they don't wait for the log
+    async fn announcer(
+        pool: PgPool,
+        mut rx: mpsc::UnboundedReceiver<MessageSync>,
+        log: mpsc::UnboundedSender<LogEntry>,
+    ) {
+        let mut msgs = Vec::with_capacity(LOG_BATCH);
+        while rx.recv_many(&mut msgs, LOG_BATCH).await > 0 {
+            let (entries, ephemeral) = match Self::prepare(std::mem::take(&mut msgs)) {
+                Ok(prepared) => prepared,
+                Err(err) => {
+                    error!("failed to publish sync events: {err}");
+                    continue;
+                }
+            };
+            // the ones too big to announce are written instead
+            for entry in entries {
+                let _ = log.send(entry);
+            }
+            retry("announce sync events", || Self::notify(&pool, &ephemeral)).await;
+        }
+    }
+
+    /// write events and announce them to every node before returning
+    ///
+    /// for short lived processes that may exit before a publisher gets to them
+    pub async fn publish_now(pool: &PgPool, msgs: Vec<MessageSync>) -> Result<()> {
+        let (entries, ephemeral) = Self::prepare(msgs)?;
+        Self::notify(pool, &ephemeral).await?;
+        let ids = Self::ids(&entries);
+        Postgres::new(pool.clone()).sync_log_append(entries).await?;
+        Self::notify(pool, &ids).await
+    }
+
+    /// split events into the ones to write and the ephemeral ones to announce directly
+    #[allow(clippy::result_large_err)]
+    fn prepare(msgs: Vec<MessageSync>) -> Result<(Vec<LogEntry>, Vec<String>)> {
+        let mut entries = vec![];
+        let mut ephemeral = vec![];
+        for msg in msgs {
+            let audience = match audience(&msg) {
+                Some(audience) => audience,
+                None => {
+                    let payload = serde_json::to_string(&msg)?;
+                    if payload.len() < MAX_NOTIFY_LEN {
+                        ephemeral.push(payload);
+                        continue;
+                    }
+                    // too big to announce, so it's written without being logged for anyone
+                    vec![]
+                }
+            };
+            entries.push((Uuid::now_v7(), msg, audience));
+        }
+        Ok((entries, ephemeral))
+    }
+
+    /// the announcements for written events
+    fn ids(entries: &[LogEntry]) -> Vec<String> {
+        entries.iter().map(|(id, _, _)| id.to_string()).collect()
+    }
+
+    async fn notify(pool: &PgPool, notifications: &[String]) -> Result<()> {
+        query!(
+            "SELECT pg_notify($1, payload) FROM unnest($2::text[]) AS payload",
+            CHANNEL,
+            notifications,
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn listener(pool: PgPool, sushi: Sender<SyncEvent>) {
+        let data = Postgres::new(pool.clone());
+        let mut listener = retry("listen for sync events", || Self::listen(&pool)).await;
+        // start listening before this so nothing published in between is missed
+        let mut last_pos = retry("get the latest sync event", || data.sync_log_last_pos())
+            .await
+            .unwrap_or_default();
+        loop {
+            let notifications = match listener.try_recv().await {
+                Ok(Some(notif)) => {
+                    let mut notifications = vec![notif.payload().to_owned()];
+                    while let Some(notif) = listener.next_buffered() {
+                        notifications.push(notif.payload().to_owned());
+                    }
+                    notifications
+                }
+                Ok(None) => {
+                    warn!("lost connection to sync event channel, reconnecting");
+                    listener = retry("listen for sync events", || Self::listen(&pool)).await;
+                    // anything published while reconnecting was never announced here.
+                    // this goes by position rather than id, since an event that took
+                    // a while to write can have an older id than ones written after it
+                    retry("catch up on sync events", || {
+                        data.sync_log_list_after(last_pos)
+                    })
+                    .await
+                    .into_iter()
+                    .map(|id| id.to_string())
+                    .collect()
+                }
+                Err(err) => {
+                    error!("failed to receive sync event: {err}");
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+            if let Err(err) = Self::deliver(&data, &sushi, notifications, &mut last_pos).await {
+                error!("failed to read sync events: {err}");
+            }
+        }
+    }
+
+    /// forward announced events to this node's connections
+    async fn deliver(
+        data: &Postgres,
+        sushi: &Sender<SyncEvent>,
+        notifications: Vec<String>,
+        last_pos: &mut u64,
+    ) -> Result<()> {
+        // written events are read all at once
+        let ids: Vec<Uuid> = notifications
+            .iter()
+            .filter_map(|n| n.parse().ok())
+            .collect();
+        let mut written: HashMap<Uuid, (u64, SyncEvent)> = data
+            .sync_log_get_many(&ids)
+            .await?
+            .into_iter()
+            .map(|(id, pos, event)| (id, (pos, event)))
+            .collect();
+        for notification in notifications {
+            let event = match notification.parse::<Uuid>() {
+                Ok(id) => {
+                    let Some((pos, mut event)) = written.remove(&id) else {
+                        warn!("sync event {id} doesn't exist");
+                        continue;
+                    };
+                    *last_pos = pos.max(*last_pos);
+                    if audience(&event.msg).is_none() {
+                        event.seqs = None;
+                    }
+                    event
+                }
+                Err(_) => match serde_json::from_str(&notification) {
+                    Ok(msg) => SyncEvent { msg, seqs: None },
+                    Err(err) => {
+                        warn!("invalid sync event {notification}: {err}");
+                        continue;
+                    }
+                },
+            };
+            let _ = sushi.send(event);
+        }
+        Ok(())
+    }
+
+    async fn listen(pool: &PgPool) -> Result<PgListener> {
+        let mut listener = PgListener::connect_with(pool).await?;
+        listener.listen(CHANNEL).await?;
+        Ok(listener)
+    }
+}
+
+impl EventBus for EventBusPostgres {
+    fn publish(&self, msg: MessageSync) {
+        let sent = match audience(&msg) {
+            Some(audience) => self.tx.send((Uuid::now_v7(), msg, audience)).is_ok(),
+            None => self.ephemeral.send(msg).is_ok(),
+        };
+        if !sent {
+            error!("sync event publisher has stopped");
+        }
+    }
+}
+
+/// log events, retrying until it works
+///
+/// dropping events would leave gaps in everyone's sync log, so later events
+/// wait until these are logged
+async fn log_with_retry(data: &dyn Data, entries: Vec<LogEntry>) -> Vec<SyncEvent> {
+    retry("log sync events", || data.sync_log_append(entries.clone())).await
+}
+
+/// keep trying something until it works, backing off between attempts
+async fn retry<T, F, Fut>(what: &str, mut f: F) -> T
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut backoff = Duration::from_millis(100);
+    loop {
+        match f().await {
+            Ok(t) => return t,
+            Err(err) => {
+                error!("failed to {what}, retrying in {backoff:?}: {err}");
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+/// periodically remove old events from the sync log
+async fn trim(data: Box<dyn Data>, max_age: Duration) {
+    let mut interval = tokio::time::interval(Duration::from_secs(60 * 5));
+    loop {
+        interval.tick().await;
+        if let Err(err) = data.sync_log_trim(max_age).await {
+            error!("failed to trim sync log: {err}");
+        }
+    }
+}
+
+/// who should have an event in their sync log
+///
+/// this only needs to be a superset of who can see the event, since the sync
+/// connection checks permissions before sending anything. returns None for
+/// ephemeral events that aren't worth logging.
+fn audience(msg: &MessageSync) -> Option<Vec<SyncAudience>> {
+    use SyncAudience as A;
+    let audience = match msg {
+        MessageSync::RoomCreate { room } => vec![A::Room(room.id)],
+        MessageSync::RoomUpdate { room } => vec![A::Room(room.id)],
+        MessageSync::ThreadCreate { thread } => vec![A::Thread(thread.id)],
+        MessageSync::ThreadUpdate { thread } => vec![A::Thread(thread.id)],
+        MessageSync::ThreadTyping { .. } => return None,
+        MessageSync::ThreadAck { user_id, .. } => vec![A::User(*user_id)],
+        MessageSync::MessageCreate { message } => vec![A::Thread(message.thread_id)],
+        MessageSync::MessageUpdate { message } => vec![A::Thread(message.thread_id)],
+        MessageSync::MessageDelete { thread_id, .. } => vec![A::Thread(*thread_id)],
+        MessageSync::MessageVersionDelete { thread_id, .. } => vec![A::Thread(*thread_id)],
+        MessageSync::MessageDeleteBulk { thread_id, .. } => vec![A::Thread(*thread_id)],
+        MessageSync::MessageMoveBulk {
+            source_id,
+            target_id,
+            ..
+        } => vec![A::Thread(*source_id), A::Thread(*target_id)],
+        MessageSync::MessagePinsUpdate { thread_id, .. } => vec![A::Thread(*thread_id)],
+        MessageSync::RoomMemberUpsert { member } => {
+            vec![A::Room(member.room_id), A::User(member.user_id)]
+        }
+        MessageSync::BanCreate { room_id, .. } => vec![A::Room(*room_id)],
+        MessageSync::BanDelete { room_id, .. } => vec![A::Room(*room_id)],
+        MessageSync::ThreadMemberUpsert { member } => {
+            vec![A::Thread(member.thread_id), A::User(member.user_id)]
+        }
+        MessageSync::RoleCreate { role } => vec![A::Room(role.room_id)],
+        MessageSync::RoleUpdate { role } => vec![A::Room(role.room_id)],
+        MessageSync::RoleDelete { room_id, .. } => vec![A::Room(*room_id)],
+        MessageSync::TagCreate { tag } => vec![A::Room(tag.room_id)],
+        MessageSync::TagUpdate { tag } => vec![A::Room(tag.room_id)],
+        MessageSync::TagDelete { room_id, .. } => vec![A::Room(*room_id)],
+        MessageSync::InviteCreate { invite } | MessageSync::InviteUpdate { invite } => {
+            match &invite.invite.target {
+                InviteTarget::Room { room } => vec![A::Room(room.id)],
+                InviteTarget::Thread { thread, .. } => vec![A::Thread(thread.id)],
+                InviteTarget::Server => vec![],
+            }
+        }
+        MessageSync::InviteDelete { target, .. } => match target {
+            InviteTargetId::Room { room_id } => vec![A::Room(*room_id)],
+            InviteTargetId::Thread { thread_id, .. } => vec![A::Thread(*thread_id)],
+            InviteTargetId::Server => vec![],
+        },
+        MessageSync::ReactionCreate { thread_id, .. } => vec![A::Thread(*thread_id)],
+        MessageSync::ReactionDelete { thread_id, .. } => vec![A::Thread(*thread_id)],
+        MessageSync::ReactionPurge { thread_id, .. } => vec![A::Thread(*thread_id)],
+        MessageSync::EmojiCreate { emoji } => match emoji.owner {
+            EmojiOwner::Room { room_id } => vec![A::Room(room_id)],
+            EmojiOwner::User => vec![A::User(emoji.creator_id)],
+        },
+        MessageSync::EmojiDelete { room_id, .. } => vec![A::Room(*room_id)],
+        MessageSync::VoiceDispatch { .. } => return None,
+        MessageSync::VoiceState { state, user_id, .. } => match state {
+            Some(state) => vec![A::Thread(state.thread_id)],
+            None => vec![A::User(*user_id)],
+        },
+        MessageSync::UserCreate { user } => vec![A::UserMutual(user.id)],
+        MessageSync::UserUpdate { user } => vec![A::UserMutual(user.id)],
+        MessageSync::UserConfig { user_id, .. } => vec![A::User(*user_id)],
+        MessageSync::UserDelete { id } => vec![A::UserMutual(*id)],
+        MessageSync::SessionCreate { session } | MessageSync::SessionUpdate { session } => {
+            session.user_id().map(A::User).into_iter().collect()
+        }
+        MessageSync::SessionDelete { user_id, .. } => user_id.map(A::User).into_iter().collect(),
+        MessageSync::RelationshipUpsert { user_id, .. } => vec![A::User(*user_id)],
+        MessageSync::RelationshipDelete { user_id } => vec![A::User(*user_id)],
+    };
+    Some(audience)
+}
@@ -1,6 +1,8 @@
-use std::path::PathBuf;
+use std::{net::SocketAddr, path::PathBuf};
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use common::v1::types::util::Time;
+use common::v1::types::{MediaId, MessageId, Permission, SessionId, ThreadId, UserId};
 
 /// Simple program to greet a person
 #[derive(Debug, Parser)]
@@ -16,21 +18,231 @@ pub struct Args {
 
 #[derive(Debug, Subcommand)]
 pub enum Command {
-    /// start the main server
-    Serve {},
+    /// start the main server, running every node role
+    Serve {
+        #[command(flatten)]
+        serve: ServeArgs,
+    },
 
     /// check config
     Check {},
-    // TODO
-    // /// admin server management
-    // Admin {},
 
-    // /// start a syncing node
-    // ServeSyncer {},
+    /// admin server management
+    Admin {
+        #[command(subcommand)]
+        command: AdminCommand,
+    },
 
-    // /// start a voip node
-    // ServeVoip {},
+    /// start an api node
+    ///
+    /// serves every route except sync and media
+    ServeApi {
+        #[command(flatten)]
+        serve: ServeArgs,
+    },
 
-    // /// start a media processing node
-    // ServeMedia {},
+    /// start a syncing node
+    ///
+    /// serves the sync websocket
+    ServeSyncer {
+        #[command(flatten)]
+        serve: ServeArgs,
+    },
+
+    // voip nodes are run by crate-sfu
+    /// start a media processing node
+    ///
+    /// serves media routes, including uploads. uploads are kept in memory
+    /// until they're done, so every request for an upload needs to reach the
+    /// same node.
+    ServeMedia {
+        #[command(flatten)]
+        serve: ServeArgs,
+    },
+
+    /// start a background worker node
+    ///
+    /// runs the email and url embed workers, and doesn't serve anything
+    ServeWorker {},
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ServeArgs {
+    /// the address to listen on
+    #[arg(long, default_value = "0.0.0.0:4000")]
+    pub listen: SocketAddr,
+}
+
+/// a part of the server that can be run on its own
+///
+/// nodes running different roles share the same database, and need the
+/// postgres event bus to see each other's events. other nodes can send media
+/// to media nodes to process through their internal rpc.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeRole {
+    Api,
+    Syncer,
+    Media,
+    Worker,
+}
+
+impl NodeRole {
+    pub const ALL: &[NodeRole] = &[
+        NodeRole::Api,
+        NodeRole::Syncer,
+        NodeRole::Media,
+        NodeRole::Worker,
+    ];
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AdminCommand {
+    /// manage users
+    User {
+        #[command(subcommand)]
+        command: AdminUserCommand,
+    },
+
+    /// manage server invites
+    Invite {
+        #[command(subcommand)]
+        command: AdminInviteCommand,
+    },
+
+    /// manage server permissions
+    ///
+    /// these are granted with roles in the room set by `admin_room_id`, or by
+    /// `report_room_id` for reports
+    Perms {
+        #[command(subcommand)]
+        command: AdminPermsCommand,
+    },
+
+    /// manage sessions
+    Session {
+        #[command(subcommand)]
+        command: AdminSessionCommand,
+    },
+
+    /// manage uploaded media
+    Media {
+        #[command(subcommand)]
+        command: AdminMediaCommand,
+    },
+
+    /// manage url embeds
+    Embed {
+        #[command(subcommand)]
+        command: AdminEmbedCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AdminUserCommand {
+    /// create a registered user
+    Create { name: String },
+
+    /// suspend a user
+    Suspend {
+        user_id: UserId,
+
+        /// shown to the user
+        #[arg(long)]
+        reason: String,
+
+        /// when the suspension ends, in rfc3339. suspends forever if unset.
+        #[arg(long, value_parser = parse_time)]
+        until: Option<Time>,
+    },
+
+    /// lift a user's suspension
+    Unsuspend { user_id: UserId },
+
+    /// delete a user and all of their sessions
+    Delete { user_id: UserId },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AdminInviteCommand {
+    /// create an invite that allows registration on this server
+    Create {
+        /// who the invite is from
+        #[arg(long)]
+        creator: UserId,
+
+        /// how many times the invite can be used. unlimited if unset.
+        #[arg(long)]
+        max_uses: Option<u16>,
+
+        /// when the invite expires, in rfc3339. never expires if unset.
+        #[arg(long, value_parser = parse_time)]
+        expires_at: Option<Time>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AdminPermsCommand {
+    /// grant a server permission to a user
+    Grant {
+        user_id: UserId,
+        permission: ServerPermission,
+    },
+
+    /// revoke a server permission from a user
+    Revoke {
+        user_id: UserId,
+        permission: ServerPermission,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AdminSessionCommand {
+    /// create an authorized session for a user and print its token
+    Create { user_id: UserId },
+
+    /// list a user's sessions
+    List { user_id: UserId },
+
+    /// revoke a session
+    Revoke { session_id: SessionId },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AdminMediaCommand {
+    /// generate thumbnails for media again
+    Thumbnails { media_ids: Vec<MediaId> },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AdminEmbedCommand {
+    /// queue embeds for every link in a message to be fetched again
+    Refetch {
+        thread_id: ThreadId,
+        message_id: MessageId,
+    },
+}
+
+/// a permission that applies to the whole server
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ServerPermission {
+    Admin,
+    Metrics,
+    Oversee,
+    Reports,
+}
+
+impl From<ServerPermission> for Permission {
+    fn from(value: ServerPermission) -> Self {
+        match value {
+            ServerPermission::Admin => Permission::ServerAdmin,
+            ServerPermission::Metrics => Permission::ServerMetrics,
+            ServerPermission::Oversee => Permission::ServerOversee,
+            ServerPermission::Reports => Permission::ServerReports,
+        }
+    }
+}
+
+fn parse_time(s: &str) -> Result<Time, time::error::Parse> {
+    let t = time::OffsetDateTime::parse(s, &time::format_description::well_known::Rfc3339)?;
+    Ok(t.into())
 }
@@ -1,13 +1,19 @@
 use std::collections::HashMap;
 
+use common::v1::types::RoomId;
 use ipnet::IpNet;
 use serde::Deserialize;
+use subtle::ConstantTimeEq;
 use url::Url;
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub rust_log: String,
+    /// `postgres://...`, or `memory://` to keep everything in memory
     pub database_url: String,
+    /// the most connections to keep open to postgres
+    #[serde(default = "default_database_max_connections")]
+    pub database_max_connections: u32,
     pub api_url: Url,
     pub cdn_url: Url,
     /// for media/file uploads
@@ -16,19 +22,88 @@ pub struct Config {
     pub url_preview: ConfigUrlPreview,
     pub smtp: ConfigSmtp,
     pub otel_trace_endpoint: Option<String>,
-    pub sfu_token: String,
+    /// tokens that sfus authenticate with, by name. each sfu is configured
+    /// with one of them, and the backend uses the same one to reach it.
+    /// removing a token stops calls from going to the sfus using it.
+    #[serde(default)]
+    pub sfu_tokens: HashMap<String, String>,
     #[serde(default = "default_max_user_emails")]
     pub max_user_emails: usize,
     #[serde(default = "default_email_queue_workers")]
     pub email_queue_workers: usize,
     #[serde(default = "default_require_server_invite")]
     pub require_server_invite: bool,
+    /// the room that server reports are sent to. members with ServerReports in
+    /// this room act as server moderators.
+    pub report_room_id: Option<RoomId>,
+    /// the room that manages the server. members with ServerAdmin in this
+    /// room act as server admins.
+    pub admin_room_id: Option<RoomId>,
+    /// how sync events reach other nodes
+    #[serde(default)]
+    pub event_bus: ConfigEventBus,
+    /// how long events are kept for clients resuming their sync connection
+    #[serde(default = "default_sync_log_max_age_hours")]
+    pub sync_log_max_age_hours: u64,
+    /// how nodes running different roles reach each other
+    pub nodes: Option<ConfigNodes>,
+}
+
+impl Config {
+    /// get the name of the sfu token in an authorization header
+    pub fn sfu_token_name(&self, authorization: &str) -> Option<&str> {
+        let token = authorization.strip_prefix("Server ")?;
+        self.sfu_tokens
+            .iter()
+            .find(|(_, t)| bool::from(t.as_bytes().ct_eq(token.as_bytes())))
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// check whether an authorization header has the token nodes share
+    pub fn node_token_valid(&self, authorization: &str) -> bool {
+        let Some(token) = authorization.strip_prefix("Node ") else {
+            return false;
+        };
+        self.nodes
+            .as_ref()
+            .is_some_and(|n| bool::from(n.token.as_bytes().ct_eq(token.as_bytes())))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConfigNodes {
+    /// the token nodes authenticate to each other's internal rpc with. every
+    /// node needs the same one.
+    pub token: String,
+
+    /// where media nodes can be reached. nodes without the media role send
+    /// media to be imported there instead of processing it themselves.
+    pub media_url: Option<Url>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigEventBus {
+    /// only send events to clients connected to this node
+    #[default]
+    Local,
+
+    /// send events through postgres, required when running multiple nodes
+    Postgres,
+}
+
+fn default_database_max_connections() -> u32 {
+    crate::data::DEFAULT_MAX_CONNECTIONS
 }
 
 fn default_require_server_invite() -> bool {
     true
 }
 
+fn default_sync_log_max_age_hours() -> u64 {
+    72
+}
+
 fn default_max_user_emails() -> usize {
     50
 }
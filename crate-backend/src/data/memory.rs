@@ -0,0 +1,344 @@
+//! keeps everything in memory instead of a database
+//!
+//! this is meant for tests and tiny single user instances. nothing is
+//! persisted, so all data is lost when the server stops.
+
+// every write is a closure returning the crate's Result
+#![allow(clippy::result_large_err)]
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+
+use async_trait::async_trait;
+use common::v1::types::application::Application;
+use common::v1::types::notifications::{Notification, NotifsRoom, NotifsThread};
+use common::v1::types::tag::Tag;
+use common::v1::types::user::Relationship;
+use common::v1::types::util::Time;
+use common::v1::types::{
+    ApplicationId, AuditLogId, EmojiId, NotificationId, PaginationKey, PermissionOverwrite,
+    ReportId, RoomBan, TagId,
+};
+use uuid::Uuid;
+
+use crate::data::postgres::Pagination;
+use crate::data::Data;
+use crate::error::{Error, Result};
+use crate::types::{
+    DbTotp, DbVoiceModeration, InviteCode, Media, MediaId, MediaLink, MessageId, MessageVerId,
+    PaginationDirection, PaginationResponse, Role, RoleId, Room, RoomId, SessionId,
+    SyncLogPosition, ThreadId, UrlEmbedQueue, UserId,
+};
+
+mod application;
+mod audit_logs;
+mod auth;
+mod email_queue;
+mod embed;
+mod emoji;
+mod invite;
+mod media;
+mod message;
+mod notification;
+mod permission;
+mod reaction;
+mod report;
+mod role;
+mod role_member;
+mod room;
+mod room_ban;
+mod room_member;
+mod search;
+mod session;
+mod sync_log;
+mod tag;
+mod thread;
+mod thread_member;
+mod unread;
+mod user;
+mod user_config;
+mod user_email;
+mod user_relationship;
+mod voice;
+
+#[derive(Clone, Default)]
+pub struct Memory {
+    store: Arc<RwLock<Store>>,
+
+    /// if set, everything is read from and written to this transaction instead of the store
+    tx: Option<Arc<Mutex<Tx>>>,
+
+    /// whether this handle joined a transaction started by another handle
+    nested: bool,
+}
+
+#[derive(Default)]
+struct Store {
+    tables: Tables,
+
+    /// incremented on every write, to check if a transaction can skip replaying its writes
+    version: u64,
+}
+
+/// a snapshot of the store along with every write made to it
+struct Tx {
+    tables: Tables,
+    version: u64,
+    ops: Vec<Op>,
+
+    /// set once the outermost handle commits or rolls back
+    finished: bool,
+
+    /// set when a nested handle rolls back, so the outermost one can't commit
+    rolled_back: bool,
+}
+
+/// a write that can be replayed on the store when its transaction is committed
+type Op = Box<dyn Fn(&mut Tables) -> Result<()> + Send + Sync>;
+
+/// every table, each of which is shared with transactions until either side writes to it
+#[derive(Clone, Default)]
+struct Tables {
+    rooms: Table<BTreeMap<RoomId, Room>>,
+    room_members: Table<BTreeMap<(RoomId, UserId), room_member::Row>>,
+    room_bans: Table<BTreeMap<(RoomId, UserId), RoomBan>>,
+    roles: Table<BTreeMap<RoleId, Role>>,
+    role_members: Table<BTreeSet<(RoleId, UserId)>>,
+    tags: Table<BTreeMap<TagId, Tag>>,
+    tag_threads: Table<BTreeSet<(ThreadId, TagId)>>,
+    tag_tags: Table<BTreeSet<(TagId, TagId)>>,
+    report_threads: Table<BTreeMap<ThreadId, report::ThreadRow>>,
+    reports: Table<BTreeMap<ReportId, report::Row>>,
+    permission_overwrites: Table<BTreeMap<(Uuid, Uuid), PermissionOverwrite>>,
+    invites: Table<BTreeMap<InviteCode, invite::Row>>,
+    media: Table<BTreeMap<MediaId, (Media, UserId)>>,
+    media_links: Table<Vec<MediaLink>>,
+    messages: Table<BTreeMap<MessageVerId, message::Row>>,
+    message_pins: Table<BTreeMap<MessageId, message::Pin>>,
+    sessions: Table<BTreeMap<SessionId, session::Row>>,
+    threads: Table<BTreeMap<ThreadId, thread::Row>>,
+    unreads: Table<BTreeMap<(ThreadId, UserId), unread::Row>>,
+    users: Table<BTreeMap<UserId, user::Row>>,
+    oauth: Table<BTreeMap<(String, UserId), String>>,
+    totp: Table<BTreeMap<UserId, DbTotp>>,
+    /// failed attempts in a row, and when the last one was
+    totp_failures: Table<BTreeMap<UserId, (u32, Time)>>,
+    totp_recovery: Table<BTreeMap<(UserId, String), auth::RecoveryRow>>,
+    audit_logs: Table<BTreeMap<AuditLogId, audit_logs::Row>>,
+    thread_members: Table<BTreeMap<(ThreadId, UserId), room_member::Row>>,
+    relationships: Table<BTreeMap<(UserId, UserId), Relationship>>,
+    reactions: Table<Vec<reaction::Row>>,
+    applications: Table<BTreeMap<ApplicationId, Application>>,
+    emoji: Table<BTreeMap<EmojiId, emoji::Row>>,
+    url_embed_queue: Table<BTreeMap<Uuid, UrlEmbedQueue>>,
+    user_emails: Table<Vec<user_email::Row>>,
+    email_verifications: Table<Vec<user_email::Verification>>,
+    email_queue: Table<BTreeMap<Uuid, email_queue::Row>>,
+    notifications: Table<BTreeMap<NotificationId, (UserId, Notification)>>,
+    notification_config_room: Table<BTreeMap<(UserId, RoomId), NotifsRoom>>,
+    notification_config_thread: Table<BTreeMap<(UserId, ThreadId), NotifsThread>>,
+    notification_queue: Table<BTreeMap<MessageId, notification::QueueRow>>,
+    sync_events: Table<BTreeMap<Uuid, sync_log::Event>>,
+    /// the position of the last event written to the sync log
+    sync_event_pos: Table<u64>,
+    sync_log: Table<BTreeMap<(UserId, u64), Uuid>>,
+    sync_log_seqs: Table<HashMap<UserId, SyncLogPosition>>,
+    sfus: Table<BTreeMap<Uuid, voice::Row>>,
+    sfu_threads: Table<BTreeMap<ThreadId, Uuid>>,
+    voice_moderation: Table<BTreeMap<(RoomId, UserId), DbVoiceModeration>>,
+    voice_members: Table<BTreeMap<UserId, ThreadId>>,
+}
+
+/// a table that is only copied when it's written to while it's shared
+struct Table<T>(Arc<T>);
+
+impl<T: Default> Default for Table<T> {
+    fn default() -> Self {
+        Self(Arc::default())
+    }
+}
+
+impl<T> Clone for Table<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Deref for Table<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Clone> DerefMut for Table<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        Arc::make_mut(&mut self.0)
+    }
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock_tx(tx: &Mutex<Tx>) -> MutexGuard<'_, Tx> {
+        tx.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// read from the store, or from the transaction if there is one
+    fn read<T>(&self, f: impl FnOnce(&Tables) -> T) -> T {
+        if let Some(tx) = &self.tx {
+            let tx = Self::lock_tx(tx);
+            if !tx.finished {
+                return f(&tx.tables);
+            }
+        }
+        f(&self
+            .store
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .tables)
+    }
+
+    /// write to the store, or to the transaction if there is one
+    ///
+    /// writes in a transaction are replayed when it's committed, so `f` must
+    /// not generate ids or timestamps itself. it should also check everything
+    /// before changing anything, since nothing is undone if it fails.
+    fn write<T>(&self, f: impl Fn(&mut Tables) -> Result<T> + Send + Sync + 'static) -> Result<T> {
+        match &self.tx {
+            Some(tx) => {
+                let mut tx = Self::lock_tx(tx);
+                if tx.finished {
+                    return Err(Error::Internal(
+                        "transaction is already finished".to_owned(),
+                    ));
+                }
+                let res = f(&mut tx.tables)?;
+                tx.ops.push(Box::new(move |t| f(t).map(|_| ())));
+                Ok(res)
+            }
+            None => {
+                let mut store = self.store.write().unwrap_or_else(|err| err.into_inner());
+                let res = f(&mut store.tables)?;
+                store.version += 1;
+                Ok(res)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Data for Memory {
+    async fn begin(&self) -> Result<Box<dyn Data>> {
+        let tx = match &self.tx {
+            // nested transactions join the outer one
+            Some(tx) => tx.clone(),
+            None => {
+                // this only clones each table's Arc, tables are copied on write
+                let store = self.store.read().unwrap_or_else(|err| err.into_inner());
+                Arc::new(Mutex::new(Tx {
+                    tables: store.tables.clone(),
+                    version: store.version,
+                    ops: vec![],
+                    finished: false,
+                    rolled_back: false,
+                }))
+            }
+        };
+        Ok(Box::new(Memory {
+            store: self.store.clone(),
+            tx: Some(tx),
+            nested: self.tx.is_some(),
+        }))
+    }
+
+    async fn commit(self: Box<Self>) -> Result<()> {
+        // only the outermost handle actually commits
+        let (Some(tx), false) = (&self.tx, self.nested) else {
+            return Ok(());
+        };
+        let mut tx = Self::lock_tx(tx);
+        if tx.rolled_back {
+            return Err(Error::Internal(
+                "a nested transaction was rolled back".to_owned(),
+            ));
+        }
+        if tx.finished {
+            return Ok(());
+        }
+        tx.finished = true;
+        let ops = std::mem::take(&mut tx.ops);
+        if ops.is_empty() {
+            return Ok(());
+        }
+        let mut store = self.store.write().unwrap_or_else(|err| err.into_inner());
+        if store.version == tx.version {
+            // nothing else was written in the meantime
+            store.tables = std::mem::take(&mut tx.tables);
+        } else {
+            let mut tables = store.tables.clone();
+            for op in &ops {
+                op(&mut tables)?;
+            }
+            store.tables = tables;
+        }
+        store.version += 1;
+        Ok(())
+    }
+
+    async fn rollback(self: Box<Self>) -> Result<()> {
+        if let Some(tx) = &self.tx {
+            // nested handles roll back everything, and the outermost one has
+            // to find out instead of committing what they meant to undo
+            let mut tx = Self::lock_tx(tx);
+            if !tx.finished {
+                tx.finished = true;
+                tx.rolled_back = self.nested;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// paginate through items the same way as the postgres queries do
+///
+/// `total` is the number of items before filtering by the pagination range.
+fn paginate<K: PaginationKey, T>(
+    p: Pagination<K>,
+    items: impl IntoIterator<Item = T>,
+    key: impl Fn(&T) -> K,
+) -> PaginationResponse<T> {
+    let mut items: Vec<T> = items.into_iter().collect();
+    let total = items.len() as u64;
+    items.retain(|i| {
+        let k = key(i);
+        k > p.after && k < p.before
+    });
+    items.sort_by_key(|i| key(i));
+    if p.dir == PaginationDirection::B {
+        items.reverse();
+    }
+    let has_more = items.len() > p.limit as usize;
+    items.truncate(p.limit as usize);
+    if p.dir == PaginationDirection::B {
+        items.reverse();
+    }
+    PaginationResponse {
+        items,
+        total,
+        has_more,
+    }
+}
+
+/// iterate over every entry in a map whose key starts with `prefix`
+fn with_prefix<A: Ord + Copy, B: PaginationKey + Copy, V>(
+    map: &BTreeMap<(A, B), V>,
+    prefix: A,
+) -> impl Iterator<Item = (B, &V)> {
+    map.range((prefix, PaginationKey::min())..=(prefix, PaginationKey::max()))
+        .map(|((_, b), v)| (*b, v))
+}
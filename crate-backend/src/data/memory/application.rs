@@ -0,0 +1,62 @@
+use async_trait::async_trait;
+use common::v1::types::{
+    application::Application, ApplicationId, PaginationQuery, PaginationResponse, UserId,
+};
+
+use crate::data::DataApplication;
+use crate::error::{Error, Result};
+
+use super::{paginate, Memory, Pagination};
+
+#[async_trait]
+impl DataApplication for Memory {
+    async fn application_insert(&self, app: Application) -> Result<()> {
+        self.write(move |t| {
+            if !t.users.contains_key(&app.owner_id) {
+                return Err(Error::NotFound);
+            }
+            t.applications.insert(app.id, app.clone());
+            Ok(())
+        })
+    }
+
+    async fn application_update(&self, app: Application) -> Result<()> {
+        self.write(move |t| {
+            if let Some(existing) = t.applications.get_mut(&app.id) {
+                existing.name = app.name.clone();
+                existing.description = app.description.clone();
+                existing.bridge = app.bridge;
+                existing.public = app.public;
+            }
+            Ok(())
+        })
+    }
+
+    async fn application_delete(&self, id: ApplicationId) -> Result<()> {
+        self.write(move |t| {
+            t.applications.remove(&id);
+            Ok(())
+        })
+    }
+
+    async fn application_get(&self, id: ApplicationId) -> Result<Application> {
+        self.read(|t| t.applications.get(&id).cloned())
+            .ok_or(Error::NotFound)
+    }
+
+    async fn application_list(
+        &self,
+        owner_id: UserId,
+        q: PaginationQuery<ApplicationId>,
+    ) -> Result<PaginationResponse<Application>> {
+        let p: Pagination<_> = q.try_into()?;
+        Ok(self.read(|t| {
+            let apps = t
+                .applications
+                .values()
+                .filter(|a| a.owner_id == owner_id)
+                .cloned();
+            paginate(p, apps, |a| a.id)
+        }))
+    }
+}
@@ -0,0 +1,77 @@
+use async_trait::async_trait;
+use common::v1::types::{
+    AuditLog, AuditLogId, MessageSync, PaginationQuery, PaginationResponse, RoomId, UserId,
+};
+
+use crate::data::DataAuditLogs;
+use crate::error::Result;
+
+use super::{paginate, Memory, Pagination};
+
+#[derive(Clone)]
+pub struct Row {
+    pub log: AuditLog,
+
+    /// the serialized payload, used to find the previous payload for a target
+    pub payload: serde_json::Value,
+}
+
+impl Row {
+    fn has_target(&self, target_id: &str) -> bool {
+        ["/thread/id", "/user/id", "/role/id", "/member/user_id"]
+            .iter()
+            .any(|path| self.payload.pointer(path).and_then(|v| v.as_str()) == Some(target_id))
+    }
+}
+
+#[async_trait]
+impl DataAuditLogs for Memory {
+    async fn audit_logs_room_fetch(
+        &self,
+        room_id: RoomId,
+        paginate_query: PaginationQuery<AuditLogId>,
+    ) -> Result<PaginationResponse<AuditLog>> {
+        let p: Pagination<_> = paginate_query.try_into()?;
+        Ok(self.read(|t| {
+            let logs = t
+                .audit_logs
+                .values()
+                .filter(|row| row.log.room_id == room_id)
+                .map(|row| row.log.clone());
+            paginate(p, logs, |log| log.id)
+        }))
+    }
+
+    async fn audit_logs_room_append(
+        &self,
+        room_id: RoomId,
+        user_id: UserId,
+        reason: Option<String>,
+        payload: MessageSync,
+    ) -> Result<()> {
+        let id = AuditLogId::new();
+        let target_id = payload.get_audit_target_id().expect("couldn't get id?");
+        let row = Row {
+            payload: serde_json::to_value(&payload)?,
+            log: AuditLog {
+                id,
+                room_id,
+                user_id,
+                reason,
+                payload: Box::new(payload),
+                payload_prev: None,
+            },
+        };
+        self.write(move |t| {
+            let mut row = row.clone();
+            row.log.payload_prev = t
+                .audit_logs
+                .values()
+                .rev()
+                .find(|r| r.has_target(&target_id))
+                .map(|r| r.log.payload.clone());
+            t.audit_logs.insert(id, row);
+            Ok(())
+        })
+    }
+}
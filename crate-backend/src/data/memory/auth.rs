@@ -0,0 +1,230 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use common::v1::types::auth::{TotpRecoveryCode, TotpRecoveryCodeUsed};
+use common::v1::types::util::Time;
+
+use crate::error::{Error, Result};
+use crate::types::{DbTotp, SessionId, UserId};
+
+use crate::data::DataAuth;
+
+use super::Memory;
+
+#[derive(Clone)]
+pub struct RecoveryRow {
+    pub code_hint: String,
+    pub used_at: Option<Time>,
+    pub used_by: Option<SessionId>,
+}
+
+#[async_trait]
+impl DataAuth for Memory {
+    async fn auth_oauth_put(
+        &self,
+        provider: String,
+        user_id: UserId,
+        remote_id: String,
+        // nothing checks this yet
+        _can_auth: bool,
+    ) -> Result<()> {
+        self.write(move |t| {
+            if t.oauth.contains_key(&(provider.clone(), user_id)) {
+                return Err(Error::BadStatic("oauth provider is already linked"));
+            }
+            t.oauth
+                .insert((provider.clone(), user_id), remote_id.clone());
+            Ok(())
+        })
+    }
+
+    async fn auth_oauth_get_remote(&self, provider: String, remote_id: String) -> Result<UserId> {
+        self.read(|t| {
+            t.oauth
+                .iter()
+                .find(|((p, _), remote)| *p == provider && **remote == remote_id)
+                .map(|((_, user_id), _)| *user_id)
+        })
+        .ok_or(Error::NotFound)
+    }
+
+    async fn auth_oauth_delete(&self, provider: String, user_id: UserId) -> Result<()> {
+        self.write(move |t| {
+            t.oauth.remove(&(provider.clone(), user_id));
+            Ok(())
+        })
+    }
+
+    async fn auth_password_set(&self, user_id: UserId, hash: &[u8], salt: &[u8]) -> Result<()> {
+        let password = (hash.to_vec(), salt.to_vec());
+        self.write(move |t| {
+            if let Some(row) = t.users.get_mut(&user_id) {
+                row.password = Some(password.clone());
+            }
+            Ok(())
+        })
+    }
+
+    async fn auth_password_get(&self, user_id: UserId) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        Ok(self.read(|t| t.users.get(&user_id).and_then(|row| row.password.clone())))
+    }
+
+    async fn auth_password_delete(&self, user_id: UserId) -> Result<()> {
+        self.write(move |t| {
+            if let Some(row) = t.users.get_mut(&user_id) {
+                row.password = None;
+            }
+            Ok(())
+        })
+    }
+
+    async fn auth_oauth_list(&self, user_id: UserId) -> Result<Vec<String>> {
+        Ok(self.read(|t| {
+            t.oauth
+                .keys()
+                .filter(|(_, u)| *u == user_id)
+                .map(|(provider, _)| provider.clone())
+                .collect()
+        }))
+    }
+
+    async fn auth_totp_set(&self, user_id: UserId, secret: &[u8]) -> Result<()> {
+        let totp = DbTotp {
+            secret: secret.to_vec(),
+            is_valid: false,
+            last_used_step: None,
+        };
+        self.write(move |t| {
+            t.totp.insert(user_id, totp.clone());
+            t.totp_failures.remove(&user_id);
+            Ok(())
+        })
+    }
+
+    async fn auth_totp_get(&self, user_id: UserId) -> Result<Option<DbTotp>> {
+        Ok(self.read(|t| t.totp.get(&user_id).cloned()))
+    }
+
+    async fn auth_totp_use(&self, user_id: UserId, step: i64) -> Result<bool> {
+        self.write(move |t| {
+            let Some(totp) = t.totp.get_mut(&user_id) else {
+                return Ok(false);
+            };
+            if totp.last_used_step.is_some_and(|s| s >= step) {
+                return Ok(false);
+            }
+            totp.is_valid = true;
+            totp.last_used_step = Some(step);
+            Ok(true)
+        })
+    }
+
+    async fn auth_totp_attempt(
+        &self,
+        user_id: UserId,
+        max_failures: u32,
+        lockout: Duration,
+    ) -> Result<bool> {
+        let now = Time::now_utc();
+        self.write(move |t| {
+            if !t.totp.contains_key(&user_id) {
+                return Ok(false);
+            }
+            let (failures, failed_at) = t.totp_failures.entry(user_id).or_insert((0, now.clone()));
+            let expired = **failed_at + lockout <= *now;
+            if *failures >= max_failures && !expired {
+                return Ok(false);
+            }
+            // failures from before the last lockout expired don't count anymore
+            *failures = if expired { 1 } else { *failures + 1 };
+            *failed_at = now.clone();
+            Ok(true)
+        })
+    }
+
+    async fn auth_totp_attempt_reset(&self, user_id: UserId) -> Result<()> {
+        self.write(move |t| {
+            t.totp_failures.remove(&user_id);
+            Ok(())
+        })
+    }
+
+    async fn auth_totp_delete(&self, user_id: UserId) -> Result<()> {
+        self.write(move |t| {
+            t.totp_recovery.retain(|(u, _), _| *u != user_id);
+            t.totp_failures.remove(&user_id);
+            t.totp.remove(&user_id);
+            Ok(())
+        })
+    }
+
+    async fn auth_totp_recovery_set(
+        &self,
+        user_id: UserId,
+        codes: Vec<(String, String)>,
+    ) -> Result<()> {
+        self.write(move |t| {
+            t.totp_recovery.retain(|(u, _), _| *u != user_id);
+            for (code_hash, code_hint) in &codes {
+                t.totp_recovery.insert(
+                    (user_id, code_hash.clone()),
+                    RecoveryRow {
+                        code_hint: code_hint.clone(),
+                        used_at: None,
+                        used_by: None,
+                    },
+                );
+            }
+            Ok(())
+        })
+    }
+
+    async fn auth_totp_recovery_list(&self, user_id: UserId) -> Result<Vec<TotpRecoveryCode>> {
+        Ok(self.read(|t| {
+            let mut codes: Vec<_> = t
+                .totp_recovery
+                .iter()
+                .filter(|((u, _), _)| *u == user_id)
+                .map(|(_, row)| TotpRecoveryCode {
+                    code: row.code_hint.clone(),
+                    used: row.used_at.clone().map(|used_at| TotpRecoveryCodeUsed {
+                        used_at,
+                        used_by: row.used_by.filter(|id| t.sessions.contains_key(id)),
+                    }),
+                })
+                .collect();
+            codes.sort_by(|a, b| a.code.cmp(&b.code));
+            codes
+        }))
+    }
+
+    async fn auth_totp_recovery_unused(&self, user_id: UserId) -> Result<Vec<(String, String)>> {
+        Ok(self.read(|t| {
+            t.totp_recovery
+                .iter()
+                .filter(|((u, _), row)| *u == user_id && row.used_at.is_none())
+                .map(|((_, code_hash), row)| (code_hash.clone(), row.code_hint.clone()))
+                .collect()
+        }))
+    }
+
+    async fn auth_totp_recovery_use(
+        &self,
+        user_id: UserId,
+        code_hash: &str,
+        session_id: SessionId,
+    ) -> Result<bool> {
+        let code_hash = code_hash.to_owned();
+        let now = Time::now_utc();
+        self.write(
+            move |t| match t.totp_recovery.get_mut(&(user_id, code_hash.clone())) {
+                Some(row) if row.used_at.is_none() => {
+                    row.used_at = Some(now.clone());
+                    row.used_by = Some(session_id);
+                    Ok(true)
+                }
+                _ => Ok(false),
+            },
+        )
+    }
+}
@@ -0,0 +1,108 @@
+use async_trait::async_trait;
+use common::v1::types::util::Time;
+use time::Duration;
+use uuid::Uuid;
+
+use crate::data::DataEmailQueue;
+use crate::error::Result;
+use crate::types::DbEmailQueue;
+
+use super::Memory;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pending,
+    Claimed,
+    Sent,
+    Failed,
+}
+
+#[derive(Clone)]
+pub struct Row {
+    pub email: DbEmailQueue,
+    pub status: Status,
+    pub retries: u32,
+    pub created_at: Time,
+    pub last_attempt_at: Option<Time>,
+    pub error_message: Option<String>,
+}
+
+#[async_trait]
+impl DataEmailQueue for Memory {
+    async fn email_queue_insert(
+        &self,
+        to: String,
+        from: String,
+        subject: String,
+        plain_text_body: String,
+        html_body: Option<String>,
+    ) -> Result<Uuid> {
+        let id = Uuid::new_v4();
+        let row = Row {
+            email: DbEmailQueue {
+                id,
+                to_addr: to,
+                from_addr: from,
+                subject,
+                plain_text_body,
+                html_body,
+            },
+            status: Status::Pending,
+            retries: 0,
+            created_at: Time::now_utc(),
+            last_attempt_at: None,
+            error_message: None,
+        };
+        self.write(move |t| {
+            t.email_queue.insert(id, row.clone());
+            Ok(())
+        })?;
+        Ok(id)
+    }
+
+    async fn email_queue_claim(&self) -> Result<Option<DbEmailQueue>> {
+        let retry_before = Time::now_utc().into_inner() - Duration::minutes(5);
+        self.write(move |t| {
+            let row = t
+                .email_queue
+                .values_mut()
+                .filter(|e| match e.status {
+                    Status::Pending => true,
+                    Status::Failed => {
+                        e.retries < 5
+                            && e.last_attempt_at
+                                .as_ref()
+                                .is_some_and(|a| **a < retry_before)
+                    }
+                    _ => false,
+                })
+                .min_by_key(|e| e.created_at.clone());
+            Ok(row.map(|row| {
+                row.status = Status::Claimed;
+                row.email.clone()
+            }))
+        })
+    }
+
+    async fn email_queue_finish(&self, id: Uuid) -> Result<()> {
+        self.write(move |t| {
+            if let Some(row) = t.email_queue.get_mut(&id) {
+                row.status = Status::Sent;
+            }
+            Ok(())
+        })
+    }
+
+    async fn email_queue_fail(&self, error_message: String, id: Uuid) -> Result<()> {
+        let now = Time::now_utc();
+        self.write(move |t| {
+            if let Some(row) = t.email_queue.get_mut(&id) {
+                row.status = Status::Failed;
+                row.retries += 1;
+                row.last_attempt_at = Some(now.clone());
+                row.error_message = Some(error_message.clone());
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,75 @@
+use async_trait::async_trait;
+use common::v1::types::util::Time;
+use common::v1::types::{Embed, MessageType, UserId};
+use uuid::Uuid;
+
+use crate::data::DataEmbed;
+use crate::error::Result;
+use crate::types::{MessageRef, UrlEmbedQueue};
+
+use super::Memory;
+
+#[async_trait]
+impl DataEmbed for Memory {
+    async fn url_embed_queue_insert(
+        &self,
+        message_ref: Option<MessageRef>,
+        user_id: UserId,
+        url: String,
+    ) -> Result<Uuid> {
+        let id = Uuid::now_v7();
+        let row = UrlEmbedQueue {
+            id,
+            message_ref: message_ref.map(serde_json::to_value).transpose()?,
+            user_id: *user_id,
+            url,
+            created_at: Time::now_utc().into(),
+            claimed_at: None,
+            finished_at: None,
+        };
+        self.write(move |t| {
+            t.url_embed_queue.insert(id, row.clone());
+            Ok(())
+        })?;
+        Ok(id)
+    }
+
+    async fn url_embed_queue_claim(&self) -> Result<Option<UrlEmbedQueue>> {
+        let now = Time::now_utc();
+        self.write(move |t| {
+            let row = t
+                .url_embed_queue
+                .values_mut()
+                .find(|e| e.claimed_at.is_none() && e.finished_at.is_none());
+            Ok(row.map(|row| {
+                row.claimed_at = Some(now.clone().into());
+                row.clone()
+            }))
+        })
+    }
+
+    async fn url_embed_queue_finish(&self, id: Uuid, embed: Option<&Embed>) -> Result<()> {
+        let embed = embed.cloned();
+        let now = Time::now_utc();
+        self.write(move |t| {
+            let Some(row) = t.url_embed_queue.get_mut(&id) else {
+                return Ok(());
+            };
+            row.finished_at = Some(now.clone().into());
+            let message_ref: Option<MessageRef> = row
+                .message_ref
+                .clone()
+                .and_then(|m| serde_json::from_value(m).ok());
+            if let (Some(embed), Some(message_ref)) = (&embed, message_ref) {
+                if let Some(m) = t.messages.get_mut(&message_ref.version_id) {
+                    match &mut m.message.message_type {
+                        MessageType::DefaultMarkdown(m) => m.embeds.push(embed.clone()),
+                        MessageType::DefaultTagged(m) => m.embeds.push(embed.clone()),
+                        _ => {}
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+}
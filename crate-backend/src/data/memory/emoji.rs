@@ -0,0 +1,98 @@
+use async_trait::async_trait;
+use common::v1::types::emoji::{EmojiCustom, EmojiCustomCreate, EmojiCustomPatch, EmojiOwner};
+use common::v1::types::util::Time;
+use common::v1::types::EmojiId;
+
+use crate::data::DataEmoji;
+use crate::error::{Error, Result};
+use crate::types::{PaginationQuery, PaginationResponse, RoomId, UserId};
+
+use super::{paginate, Memory, Pagination};
+
+#[derive(Clone)]
+pub struct Row {
+    pub emoji: EmojiCustom,
+    pub deleted_at: Option<Time>,
+}
+
+#[async_trait]
+impl DataEmoji for Memory {
+    async fn emoji_create(
+        &self,
+        creator_id: UserId,
+        room_id: RoomId,
+        create: EmojiCustomCreate,
+    ) -> Result<EmojiCustom> {
+        let emoji_id = EmojiId::new();
+        let row = Row {
+            emoji: EmojiCustom {
+                id: emoji_id,
+                name: create.name,
+                creator_id,
+                owner: EmojiOwner::Room { room_id },
+                animated: false,
+                media_id: create.media_id,
+            },
+            deleted_at: None,
+        };
+        self.write(move |t| {
+            if !t.rooms.contains_key(&room_id) || !t.media.contains_key(&row.emoji.media_id) {
+                return Err(Error::NotFound);
+            }
+            t.emoji.insert(emoji_id, row.clone());
+            Ok(())
+        })?;
+        self.emoji_get(emoji_id).await
+    }
+
+    async fn emoji_get(&self, emoji_id: EmojiId) -> Result<EmojiCustom> {
+        self.read(|t| t.emoji.get(&emoji_id).map(|row| row.emoji.clone()))
+            .ok_or(Error::NotFound)
+    }
+
+    async fn emoji_list(
+        &self,
+        room_id: RoomId,
+        pagination: PaginationQuery<EmojiId>,
+    ) -> Result<PaginationResponse<EmojiCustom>> {
+        let p: Pagination<_> = pagination.try_into()?;
+        Ok(self.read(|t| {
+            let emoji: Vec<_> = t
+                .emoji
+                .values()
+                .filter(|row| matches!(row.emoji.owner, EmojiOwner::Room { room_id: r } if r == room_id))
+                .collect();
+            let total = emoji.len() as u64;
+            let emoji = emoji
+                .into_iter()
+                .filter(|row| row.deleted_at.is_none())
+                .map(|row| row.emoji.clone());
+            let mut res = paginate(p, emoji, |e| e.id);
+            res.total = total;
+            res
+        }))
+    }
+
+    async fn emoji_update(&self, emoji_id: EmojiId, patch: EmojiCustomPatch) -> Result<()> {
+        self.write(move |t| {
+            let row = t.emoji.get_mut(&emoji_id).ok_or(Error::NotFound)?;
+            if let Some(name) = &patch.name {
+                row.emoji.name = name.clone();
+            }
+            if let Some(owner) = &patch.owner {
+                row.emoji.owner = owner.clone();
+            }
+            Ok(())
+        })
+    }
+
+    async fn emoji_delete(&self, emoji_id: EmojiId) -> Result<()> {
+        let now = Time::now_utc();
+        self.write(move |t| {
+            if let Some(row) = t.emoji.get_mut(&emoji_id) {
+                row.deleted_at = Some(now.clone());
+            }
+            Ok(())
+        })
+    }
+}
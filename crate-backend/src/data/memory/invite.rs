@@ -0,0 +1,190 @@
+use async_trait::async_trait;
+use common::v1::types::util::Time;
+use common::v1::types::{
+    InvitePatch, InviteTarget, InviteWithMetadata, PaginationQuery, PaginationResponse,
+};
+
+use crate::data::DataInvite;
+use crate::error::{Error, Result};
+use crate::types::{Invite, InviteCode, RoomId, UserId};
+
+use super::{paginate, Memory, Pagination, Tables};
+
+#[derive(Clone)]
+pub struct Row {
+    /// the room this invite is for, or None for server invites
+    pub room_id: Option<RoomId>,
+    pub creator_id: UserId,
+    pub created_at: Time,
+    pub expires_at: Option<Time>,
+    pub uses: u64,
+    pub max_uses: Option<u16>,
+    pub description: Option<String>,
+}
+
+impl Tables {
+    fn invite(&self, code: &InviteCode) -> Result<InviteWithMetadata> {
+        let row = self.invites.get(code).ok_or(Error::NotFound)?;
+        let target = match row.room_id {
+            Some(room_id) => InviteTarget::Room {
+                room: self.rooms.get(&room_id).cloned().ok_or(Error::NotFound)?,
+            },
+            None => InviteTarget::Server,
+        };
+        let creator = self.user(row.creator_id).ok_or(Error::NotFound)?;
+        let invite = Invite::new(
+            code.clone(),
+            target,
+            creator,
+            row.creator_id,
+            row.created_at.clone(),
+            row.expires_at.clone(),
+            row.description.clone(),
+            false,
+        );
+        Ok(InviteWithMetadata {
+            invite,
+            uses: row.uses,
+            max_uses: row.max_uses,
+        })
+    }
+}
+
+impl Memory {
+    fn invite_insert(
+        &self,
+        room_id: Option<RoomId>,
+        creator_id: UserId,
+        code: InviteCode,
+        expires_at: Option<Time>,
+        max_uses: Option<u16>,
+    ) -> Result<()> {
+        let row = Row {
+            room_id,
+            creator_id,
+            created_at: Time::now_utc(),
+            expires_at,
+            uses: 0,
+            max_uses,
+            description: None,
+        };
+        self.write(move |t| {
+            if t.invites.contains_key(&code) {
+                return Err(Error::BadStatic("invite code already exists"));
+            }
+            if room_id.is_some_and(|id| !t.rooms.contains_key(&id))
+                || !t.users.contains_key(&creator_id)
+            {
+                return Err(Error::NotFound);
+            }
+            t.invites.insert(code.clone(), row.clone());
+            Ok(())
+        })
+    }
+
+    fn invite_list(
+        &self,
+        room_id: Option<RoomId>,
+        paginate_query: PaginationQuery<InviteCode>,
+    ) -> Result<PaginationResponse<InviteWithMetadata>> {
+        let p: Pagination<_> = paginate_query.try_into()?;
+        self.read(|t| {
+            let codes = t
+                .invites
+                .iter()
+                .filter(|(_, i)| i.room_id == room_id)
+                .map(|(code, _)| code.clone());
+            let res = paginate(p, codes, |code| code.clone());
+            Ok(PaginationResponse {
+                items: res
+                    .items
+                    .iter()
+                    .map(|code| t.invite(code))
+                    .collect::<Result<_>>()?,
+                total: res.total,
+                has_more: res.has_more,
+            })
+        })
+    }
+}
+
+#[async_trait]
+impl DataInvite for Memory {
+    async fn invite_insert_room(
+        &self,
+        room_id: RoomId,
+        creator_id: UserId,
+        code: InviteCode,
+        expires_at: Option<Time>,
+        max_uses: Option<u16>,
+    ) -> Result<()> {
+        self.invite_insert(Some(room_id), creator_id, code, expires_at, max_uses)
+    }
+
+    async fn invite_insert_server(
+        &self,
+        creator_id: UserId,
+        code: InviteCode,
+        expires_at: Option<Time>,
+        max_uses: Option<u16>,
+    ) -> Result<()> {
+        self.invite_insert(None, creator_id, code, expires_at, max_uses)
+    }
+
+    async fn invite_select(&self, code: InviteCode) -> Result<InviteWithMetadata> {
+        self.read(|t| t.invite(&code))
+    }
+
+    async fn invite_delete(&self, code: InviteCode) -> Result<()> {
+        self.write(move |t| {
+            t.invites.remove(&code);
+            Ok(())
+        })
+    }
+
+    async fn invite_list_room(
+        &self,
+        room_id: RoomId,
+        paginate_query: PaginationQuery<InviteCode>,
+    ) -> Result<PaginationResponse<InviteWithMetadata>> {
+        self.invite_list(Some(room_id), paginate_query)
+    }
+
+    async fn invite_list_server(
+        &self,
+        paginate_query: PaginationQuery<InviteCode>,
+    ) -> Result<PaginationResponse<InviteWithMetadata>> {
+        self.invite_list(None, paginate_query)
+    }
+
+    async fn invite_incr_use(&self, code: InviteCode) -> Result<()> {
+        self.write(move |t| {
+            if let Some(invite) = t.invites.get_mut(&code) {
+                invite.uses += 1;
+            }
+            Ok(())
+        })
+    }
+
+    async fn invite_update(
+        &self,
+        code: InviteCode,
+        patch: InvitePatch,
+    ) -> Result<InviteWithMetadata> {
+        let key = code.clone();
+        self.write(move |t| {
+            let invite = t.invites.get_mut(&key).ok_or(Error::NotFound)?;
+            if let Some(expires_at) = &patch.expires_at {
+                invite.expires_at = expires_at.clone();
+            }
+            if let Some(max_uses) = patch.max_uses {
+                invite.max_uses = max_uses;
+            }
+            if let Some(description) = &patch.description {
+                invite.description = description.clone();
+            }
+            Ok(())
+        })?;
+        self.invite_select(code).await
+    }
+}
@@ -0,0 +1,113 @@
+use async_trait::async_trait;
+use common::v1::types::MediaPatch;
+use uuid::Uuid;
+
+use crate::error::{Error, Result};
+use crate::types::{Media, MediaId, MediaLink, MediaLinkType, ThreadId, UserId};
+
+use crate::data::DataMedia;
+
+use super::Memory;
+
+#[async_trait]
+impl DataMedia for Memory {
+    async fn media_insert(&self, user_id: UserId, media: Media) -> Result<()> {
+        self.write(move |t| {
+            if t.media.contains_key(&media.id) {
+                return Err(Error::CantOverwrite);
+            }
+            t.media.insert(media.id, (media.clone(), user_id));
+            Ok(())
+        })
+    }
+
+    async fn media_select(&self, media_id: MediaId) -> Result<(Media, UserId)> {
+        self.read(|t| t.media.get(&media_id).cloned())
+            .ok_or(Error::NotFound)
+    }
+
+    async fn media_update(&self, media_id: MediaId, patch: MediaPatch) -> Result<()> {
+        self.write(move |t| {
+            let (media, _) = t.media.get_mut(&media_id).ok_or(Error::NotFound)?;
+            media.alt = patch.alt.clone().flatten();
+            Ok(())
+        })
+    }
+
+    async fn media_replace(&self, media: Media) -> Result<()> {
+        self.write(move |t| {
+            let (existing, _) = t.media.get_mut(&media.id).ok_or(Error::NotFound)?;
+            *existing = media.clone();
+            Ok(())
+        })
+    }
+
+    async fn media_link_insert(
+        &self,
+        media_id: MediaId,
+        target_id: Uuid,
+        link_type: MediaLinkType,
+    ) -> Result<()> {
+        self.write(move |t| {
+            if !t.media.contains_key(&media_id) {
+                return Err(Error::NotFound);
+            }
+            t.media_links.push(MediaLink {
+                media_id,
+                target_id,
+                link_type,
+            });
+            Ok(())
+        })
+    }
+
+    async fn media_link_select(&self, media_id: MediaId) -> Result<Vec<MediaLink>> {
+        Ok(self.read(|t| {
+            t.media_links
+                .iter()
+                .filter(|l| l.media_id == media_id)
+                .cloned()
+                .collect()
+        }))
+    }
+
+    async fn media_link_threads(&self, media_id: MediaId) -> Result<Vec<ThreadId>> {
+        Ok(self.read(|t| {
+            let mut thread_ids: Vec<ThreadId> = t
+                .media_links
+                .iter()
+                .filter(|l| l.media_id == media_id)
+                .flat_map(|l| {
+                    t.messages.values().filter(move |row| {
+                        let m = &row.message;
+                        m.deleted_at.is_none()
+                            && match l.link_type {
+                                MediaLinkType::Message => *m.id == l.target_id,
+                                MediaLinkType::MessageVersion => *m.version_id == l.target_id,
+                                _ => false,
+                            }
+                    })
+                })
+                .map(|row| row.message.thread_id)
+                .collect();
+            thread_ids.sort();
+            thread_ids.dedup();
+            thread_ids
+        }))
+    }
+
+    async fn media_link_delete(&self, target_id: Uuid, link_type: MediaLinkType) -> Result<()> {
+        self.write(move |t| {
+            t.media_links
+                .retain(|l| l.target_id != target_id || l.link_type != link_type);
+            Ok(())
+        })
+    }
+
+    async fn media_link_delete_all(&self, target_id: Uuid) -> Result<()> {
+        self.write(move |t| {
+            t.media_links.retain(|l| l.target_id != target_id);
+            Ok(())
+        })
+    }
+}
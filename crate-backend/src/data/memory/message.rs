@@ -0,0 +1,520 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use async_trait::async_trait;
+use common::v1::types::emoji::{Emoji, EmojiUnicode};
+use common::v1::types::reaction::{ReactionCount, ReactionCounts};
+use common::v1::types::util::Time;
+use common::v1::types::{MessageType, UserId};
+
+use crate::data::DataMessage;
+use crate::error::{Error, Result};
+use crate::types::{
+    DbMessageCreate, MediaId, Message, MessageId, MessageVerId, PaginationQuery,
+    PaginationResponse, ThreadId,
+};
+
+use super::{paginate, Memory, Pagination, Tables};
+
+#[derive(Clone)]
+pub struct Row {
+    /// the message without its attachments, reactions, or pin
+    pub message: Message,
+    pub attachment_ids: Vec<MediaId>,
+    pub reply_id: Option<MessageId>,
+    pub is_latest: bool,
+}
+
+#[derive(Clone)]
+pub struct Pin {
+    pub thread_id: ThreadId,
+    pub pinned_at: Time,
+    pub position: u32,
+}
+
+impl Row {
+    fn new(create: DbMessageCreate, id: MessageId, version_id: MessageVerId) -> Row {
+        let reply_id = create.reply_id();
+        let mut message_type = create.message_type;
+        match &mut message_type {
+            MessageType::DefaultMarkdown(m) => m.embeds = create.embeds,
+            MessageType::DefaultTagged(m) => m.embeds = create.embeds,
+            _ => {}
+        }
+        Row {
+            message: Message {
+                id,
+                message_type,
+                thread_id: create.thread_id,
+                version_id,
+                nonce: None,
+                author_id: create.author_id,
+                mentions: create.mentions,
+                pinned_at: None,
+                pinned_order: None,
+                created_at: create.created_at.map(Time::from),
+                deleted_at: None,
+                removed_at: None,
+                edited_at: create.edited_at.map(Time::from),
+            },
+            attachment_ids: create.attachment_ids,
+            reply_id,
+            is_latest: true,
+        }
+    }
+}
+
+impl Tables {
+    /// the latest version of every message in a thread, including deleted messages
+    pub(super) fn thread_messages(&self, thread_id: ThreadId) -> impl Iterator<Item = &Row> {
+        self.messages
+            .values()
+            .filter(move |m| m.is_latest && m.message.thread_id == thread_id)
+    }
+
+    fn message_latest(&self, message_id: MessageId) -> Option<&Row> {
+        self.messages
+            .values()
+            .find(|m| m.is_latest && m.message.id == message_id)
+    }
+
+    /// fill in the attachments, reactions, and pin for a message
+    pub(super) fn message(&self, row: &Row, user_id: UserId) -> Message {
+        let mut message = row.message.clone();
+        let attachments = row
+            .attachment_ids
+            .iter()
+            .filter_map(|id| self.media.get(id))
+            .map(|(media, _)| media.clone())
+            .collect();
+        let mut reactions: BTreeMap<&str, (u32, u64, bool)> = BTreeMap::new();
+        for r in self.reactions.iter().filter(|r| r.message_id == message.id) {
+            let e = reactions.entry(&r.key).or_insert((r.position, 0, false));
+            e.0 = e.0.min(r.position);
+            e.1 += 1;
+            e.2 |= r.user_id == user_id;
+        }
+        let mut reactions: Vec<_> = reactions.into_iter().collect();
+        reactions.sort_by_key(|(_, (pos, _, _))| *pos);
+        let reactions = ReactionCounts(
+            reactions
+                .into_iter()
+                .map(|(key, (_, count, self_reacted))| ReactionCount {
+                    key: Emoji::Unicode(EmojiUnicode(key.to_owned())),
+                    count,
+                    self_reacted,
+                })
+                .collect(),
+        );
+        match &mut message.message_type {
+            MessageType::DefaultMarkdown(m) => {
+                m.attachments = attachments;
+                m.reactions = reactions;
+            }
+            MessageType::DefaultTagged(m) => {
+                m.attachments = attachments;
+                m.reactions = reactions;
+            }
+            _ => {}
+        }
+        if let Some(pin) = self.message_pins.get(&message.id) {
+            message.pinned_at = Some(pin.pinned_at.clone());
+            message.pinned_order = pin.position.try_into().ok();
+        }
+        message
+    }
+
+    /// close the gaps in a thread's pin list after pins are removed
+    fn pin_renumber(&mut self, thread_id: ThreadId) {
+        let mut pins: Vec<_> = self
+            .message_pins
+            .iter_mut()
+            .filter(|(_, p)| p.thread_id == thread_id)
+            .map(|(_, p)| p)
+            .collect();
+        pins.sort_by_key(|p| p.position);
+        for (i, pin) in pins.into_iter().enumerate() {
+            pin.position = i as u32;
+        }
+    }
+
+    fn message_delete(&mut self, thread_id: ThreadId, message_ids: &[MessageId], now: &Time) {
+        for m in self.messages.values_mut() {
+            if message_ids.contains(&m.message.id) {
+                m.message.deleted_at = Some(now.clone());
+            }
+        }
+        self.message_pins.retain(|id, _| !message_ids.contains(id));
+        self.pin_renumber(thread_id);
+    }
+}
+
+#[async_trait]
+impl DataMessage for Memory {
+    async fn message_create(&self, mut create: DbMessageCreate) -> Result<MessageId> {
+        let message_id = MessageId::new();
+        create.created_at = Some(create.created_at.unwrap_or_else(|| Time::now_utc().into()));
+        let row = Row::new(create, message_id, message_id.into_inner().into());
+        self.write(move |t| {
+            if !t.threads.contains_key(&row.message.thread_id) {
+                return Err(Error::NotFound);
+            }
+            t.messages.insert(row.message.version_id, row.clone());
+            Ok(())
+        })?;
+        Ok(message_id)
+    }
+
+    async fn message_update(
+        &self,
+        _thread_id: ThreadId,
+        message_id: MessageId,
+        mut create: DbMessageCreate,
+    ) -> Result<MessageVerId> {
+        let version_id = MessageVerId::new();
+        create.edited_at = Some(create.edited_at.unwrap_or_else(|| Time::now_utc().into()));
+        let row = Row::new(create, message_id, version_id);
+        self.write(move |t| {
+            if !t.threads.contains_key(&row.message.thread_id) {
+                return Err(Error::NotFound);
+            }
+            let mut row = row.clone();
+            for m in t.messages.values_mut() {
+                if m.message.id == message_id {
+                    m.is_latest = false;
+                    // edits keep when the message was first sent
+                    row.message.created_at =
+                        row.message.created_at.or(m.message.created_at.clone());
+                }
+            }
+            t.messages.insert(version_id, row);
+            Ok(())
+        })?;
+        Ok(version_id)
+    }
+
+    async fn message_get(
+        &self,
+        thread_id: ThreadId,
+        id: MessageId,
+        user_id: UserId,
+    ) -> Result<Message> {
+        self.read(|t| {
+            t.message_latest(id)
+                .filter(|m| m.message.thread_id == thread_id && m.message.deleted_at.is_none())
+                .map(|m| t.message(m, user_id))
+        })
+        .ok_or(Error::NotFound)
+    }
+
+    async fn message_get_many(
+        &self,
+        thread_id: ThreadId,
+        message_ids: &[MessageId],
+        user_id: UserId,
+    ) -> Result<Vec<Message>> {
+        let mut message_ids = message_ids.to_vec();
+        message_ids.sort();
+        message_ids.dedup();
+        Ok(self.read(|t| {
+            message_ids
+                .iter()
+                .filter_map(|id| t.message_latest(*id))
+                .filter(|m| m.message.thread_id == thread_id && m.message.deleted_at.is_none())
+                .map(|m| t.message(m, user_id))
+                .collect()
+        }))
+    }
+
+    async fn message_list(
+        &self,
+        thread_id: ThreadId,
+        user_id: UserId,
+        pagination: PaginationQuery<MessageId>,
+    ) -> Result<PaginationResponse<Message>> {
+        let p: Pagination<_> = pagination.try_into()?;
+        Ok(self.read(|t| {
+            let rows: Vec<_> = t.thread_messages(thread_id).collect();
+            let total = rows.len() as u64;
+            let rows = rows.into_iter().filter(|m| m.message.deleted_at.is_none());
+            let res = paginate(p, rows, |m| m.message.id);
+            PaginationResponse {
+                items: res
+                    .items
+                    .into_iter()
+                    .map(|m| t.message(m, user_id))
+                    .collect(),
+                total,
+                has_more: res.has_more,
+            }
+        }))
+    }
+
+    async fn message_delete(&self, thread_id: ThreadId, message_id: MessageId) -> Result<()> {
+        let now = Time::now_utc();
+        self.write(move |t| {
+            t.message_delete(thread_id, &[message_id], &now);
+            Ok(())
+        })
+    }
+
+    async fn message_delete_bulk(
+        &self,
+        thread_id: ThreadId,
+        message_ids: &[MessageId],
+    ) -> Result<()> {
+        let now = Time::now_utc();
+        let message_ids = message_ids.to_vec();
+        self.write(move |t| {
+            t.message_delete(thread_id, &message_ids, &now);
+            Ok(())
+        })
+    }
+
+    async fn message_version_get(
+        &self,
+        thread_id: ThreadId,
+        version_id: MessageVerId,
+        user_id: UserId,
+    ) -> Result<Message> {
+        self.read(|t| {
+            t.messages
+                .get(&version_id)
+                .filter(|m| m.message.thread_id == thread_id && m.message.deleted_at.is_none())
+                .map(|m| t.message(m, user_id))
+        })
+        .ok_or(Error::NotFound)
+    }
+
+    async fn message_version_delete(
+        &self,
+        _thread_id: ThreadId,
+        version_id: MessageVerId,
+    ) -> Result<()> {
+        let now = Time::now_utc();
+        self.write(move |t| {
+            if let Some(m) = t.messages.get_mut(&version_id) {
+                m.message.deleted_at = Some(now.clone());
+            }
+            Ok(())
+        })
+    }
+
+    async fn message_version_list(
+        &self,
+        thread_id: ThreadId,
+        message_id: MessageId,
+        user_id: UserId,
+        pagination: PaginationQuery<MessageVerId>,
+    ) -> Result<PaginationResponse<Message>> {
+        let p: Pagination<_> = pagination.try_into()?;
+        Ok(self.read(|t| {
+            let rows: Vec<_> = t
+                .messages
+                .values()
+                .filter(|m| m.message.thread_id == thread_id && m.message.id == message_id)
+                .collect();
+            let total = rows.len() as u64;
+            let rows = rows.into_iter().filter(|m| m.message.deleted_at.is_none());
+            let res = paginate(p, rows, |m| m.message.version_id);
+            PaginationResponse {
+                items: res
+                    .items
+                    .into_iter()
+                    .map(|m| t.message(m, user_id))
+                    .collect(),
+                total,
+                has_more: res.has_more,
+            }
+        }))
+    }
+
+    async fn message_replies(
+        &self,
+        thread_id: ThreadId,
+        message_id: MessageId,
+        user_id: UserId,
+        depth: u16,
+        breadth: Option<u16>,
+        pagination: PaginationQuery<MessageId>,
+    ) -> Result<PaginationResponse<Message>> {
+        let p: Pagination<_> = pagination.try_into()?;
+        Ok(self.read(|t| {
+            // walk the reply tree, keeping the first `breadth` replies to each message
+            let mut tree = vec![message_id];
+            let mut ranked = BTreeSet::from([message_id]);
+            let mut parents = vec![message_id];
+            for _ in 1..depth {
+                let mut children = vec![];
+                for parent in &parents {
+                    let mut replies: Vec<MessageId> = t
+                        .messages
+                        .values()
+                        .filter(|m| m.is_latest && m.reply_id == Some(*parent))
+                        .map(|m| m.message.id)
+                        .collect();
+                    replies.sort();
+                    tree.extend(&replies);
+                    ranked.extend(
+                        replies
+                            .iter()
+                            .take(breadth.map_or(usize::MAX, |b| b as usize)),
+                    );
+                    children.extend(replies);
+                }
+                parents = children;
+            }
+            let rows: Vec<_> = tree
+                .iter()
+                .filter_map(|id| t.message_latest(*id))
+                .filter(|m| m.message.thread_id == thread_id && m.message.deleted_at.is_none())
+                .collect();
+            let total = rows.len() as u64;
+            let rows = rows.into_iter().filter(|m| ranked.contains(&m.message.id));
+            let res = paginate(p, rows, |m| m.message.id);
+            PaginationResponse {
+                items: res
+                    .items
+                    .into_iter()
+                    .map(|m| t.message(m, user_id))
+                    .collect(),
+                total,
+                has_more: res.has_more,
+            }
+        }))
+    }
+
+    async fn message_pin_create(
+        &self,
+        thread_id: ThreadId,
+        message_id: MessageId,
+        max_pins: usize,
+    ) -> Result<bool> {
+        let now = Time::now_utc();
+        self.write(move |t| {
+            if t.message_pins.contains_key(&message_id) {
+                return Err(Error::BadStatic("message is already pinned"));
+            }
+            let pins = t
+                .message_pins
+                .values()
+                .filter(|p| p.thread_id == thread_id)
+                .count();
+            if pins >= max_pins {
+                return Ok(false);
+            }
+            for pin in t.message_pins.values_mut() {
+                if pin.thread_id == thread_id {
+                    pin.position += 1;
+                }
+            }
+            t.message_pins.insert(
+                message_id,
+                Pin {
+                    thread_id,
+                    pinned_at: now.clone(),
+                    position: 0,
+                },
+            );
+            Ok(true)
+        })
+    }
+
+    async fn message_pin_delete(&self, thread_id: ThreadId, message_id: MessageId) -> Result<bool> {
+        self.write(move |t| {
+            if t.message_pins
+                .get(&message_id)
+                .is_none_or(|p| p.thread_id != thread_id)
+            {
+                return Ok(false);
+            }
+            t.message_pins.remove(&message_id);
+            t.pin_renumber(thread_id);
+            Ok(true)
+        })
+    }
+
+    async fn message_pin_reorder(
+        &self,
+        thread_id: ThreadId,
+        message_ids: &[MessageId],
+    ) -> Result<()> {
+        let message_ids = message_ids.to_vec();
+        self.write(move |t| {
+            let mut pinned: Vec<MessageId> = t
+                .message_pins
+                .iter()
+                .filter(|(_, p)| p.thread_id == thread_id)
+                .map(|(id, _)| *id)
+                .collect();
+            let mut included = message_ids.clone();
+            pinned.sort();
+            included.sort();
+            if pinned != included {
+                return Err(Error::BadStatic("must include every pinned message"));
+            }
+            for (position, message_id) in message_ids.iter().enumerate() {
+                if let Some(pin) = t.message_pins.get_mut(message_id) {
+                    pin.position = position as u32;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    async fn message_id_range(
+        &self,
+        thread_id: ThreadId,
+        start_id: MessageId,
+        end_id: MessageId,
+        limit: u16,
+    ) -> Result<Vec<MessageId>> {
+        Ok(self.read(|t| {
+            let mut ids: Vec<MessageId> = t
+                .thread_messages(thread_id)
+                .filter(|m| m.message.deleted_at.is_none())
+                .map(|m| m.message.id)
+                .filter(|id| *id >= start_id && *id <= end_id)
+                .collect();
+            ids.sort();
+            ids.truncate(limit as usize);
+            ids
+        }))
+    }
+
+    async fn message_move(
+        &self,
+        source_id: ThreadId,
+        target_id: ThreadId,
+        message_ids: &[MessageId],
+    ) -> Result<()> {
+        let message_ids = message_ids.to_vec();
+        self.write(move |t| {
+            if !t.threads.contains_key(&target_id) {
+                return Err(Error::NotFound);
+            }
+            // every version is moved, attachments and reactions follow the message ids
+            for m in t.messages.values_mut() {
+                if m.message.thread_id == source_id && message_ids.contains(&m.message.id) {
+                    m.message.thread_id = target_id;
+                }
+            }
+            t.message_pins.retain(|id, _| !message_ids.contains(id));
+            t.pin_renumber(source_id);
+            Ok(())
+        })
+    }
+
+    async fn message_pin_list(&self, thread_id: ThreadId, user_id: UserId) -> Result<Vec<Message>> {
+        Ok(self.read(|t| {
+            let mut rows: Vec<_> = t
+                .thread_messages(thread_id)
+                .filter(|m| m.message.deleted_at.is_none())
+                .filter_map(|m| Some((t.message_pins.get(&m.message.id)?.position, m)))
+                .collect();
+            rows.sort_by_key(|(position, _)| *position);
+            rows.into_iter()
+                .map(|(_, m)| t.message(m, user_id))
+                .collect()
+        }))
+    }
+}
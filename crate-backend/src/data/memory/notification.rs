@@ -0,0 +1,270 @@
+use async_trait::async_trait;
+use common::v1::types::notifications::{Notification, NotifsRoom, NotifsThread};
+use common::v1::types::util::Time;
+use common::v1::types::{NotificationId, PaginationQuery, PaginationResponse};
+use time::Duration;
+
+use crate::data::DataNotification;
+use crate::error::Result;
+use crate::types::{
+    DbMembership, DbNotificationQueue, DbNotificationRecipient, MessageId, RoleId, RoomId,
+    ThreadId, UserId,
+};
+
+use super::{paginate, with_prefix, Memory, Pagination};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum QueueStatus {
+    Pending,
+    Claimed,
+    Failed,
+}
+
+#[derive(Clone)]
+pub struct QueueRow {
+    pub item: DbNotificationQueue,
+    pub status: QueueStatus,
+    pub retries: u32,
+    pub created_at: Time,
+    pub claimed_at: Option<Time>,
+    pub last_attempt_at: Option<Time>,
+    pub error_message: Option<String>,
+}
+
+#[async_trait]
+impl DataNotification for Memory {
+    async fn notification_add(&self, notifs: Vec<(UserId, Notification)>) -> Result<()> {
+        self.write(move |t| {
+            for (user_id, notif) in &notifs {
+                t.notifications.insert(notif.id, (*user_id, notif.clone()));
+            }
+            Ok(())
+        })
+    }
+
+    async fn notification_list(
+        &self,
+        user_id: UserId,
+        paginate_query: PaginationQuery<NotificationId>,
+        include_read: bool,
+    ) -> Result<PaginationResponse<Notification>> {
+        let p: Pagination<_> = paginate_query.try_into()?;
+        Ok(self.read(|t| {
+            let notifs = t
+                .notifications
+                .values()
+                .filter(|(u, n)| *u == user_id && (include_read || n.read_at.is_none()))
+                .map(|(_, n)| n.clone());
+            paginate(p, notifs, |n| n.id)
+        }))
+    }
+
+    async fn notification_mark_read(&self, user_id: UserId, ids: &[NotificationId]) -> Result<()> {
+        let ids = ids.to_vec();
+        let now = Time::now_utc();
+        self.write(move |t| {
+            for id in &ids {
+                if let Some((u, n)) = t.notifications.get_mut(id) {
+                    if *u == user_id && n.read_at.is_none() {
+                        n.read_at = Some(now.clone());
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    async fn notification_mark_unread(
+        &self,
+        user_id: UserId,
+        ids: &[NotificationId],
+    ) -> Result<()> {
+        let ids = ids.to_vec();
+        self.write(move |t| {
+            for id in &ids {
+                if let Some((u, n)) = t.notifications.get_mut(id) {
+                    if *u == user_id {
+                        n.read_at = None;
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    async fn notification_recipients(
+        &self,
+        thread_id: ThreadId,
+    ) -> Result<Vec<DbNotificationRecipient>> {
+        Ok(self.read(|t| {
+            let Some(thread) = t.threads.get(&thread_id) else {
+                return vec![];
+            };
+            let members: Vec<(UserId, Vec<RoleId>)> = match thread.room_id {
+                Some(room_id) => with_prefix(&t.room_members, room_id)
+                    .filter(|(_, m)| m.membership == DbMembership::Join)
+                    .map(|(user_id, _)| (user_id, t.member_roles(room_id, user_id)))
+                    .collect(),
+                None => with_prefix(&t.thread_members, thread_id)
+                    .filter(|(_, m)| m.membership == DbMembership::Join)
+                    .map(|(user_id, _)| (user_id, vec![]))
+                    .collect(),
+            };
+            members
+                .into_iter()
+                .filter_map(|(user_id, roles)| {
+                    let global = t.users.get(&user_id)?.config.clone().unwrap_or_default();
+                    let room = thread
+                        .room_id
+                        .and_then(|room_id| t.notification_config_room.get(&(user_id, room_id)))
+                        .cloned()
+                        .unwrap_or_default();
+                    let thread = t
+                        .notification_config_thread
+                        .get(&(user_id, thread_id))
+                        .cloned()
+                        .unwrap_or_default();
+                    Some(DbNotificationRecipient {
+                        user_id,
+                        roles,
+                        global: global.notifs,
+                        room,
+                        thread,
+                    })
+                })
+                .collect()
+        }))
+    }
+
+    async fn notification_config_room_get(
+        &self,
+        user_id: UserId,
+        room_id: RoomId,
+    ) -> Result<NotifsRoom> {
+        Ok(self.read(|t| {
+            t.notification_config_room
+                .get(&(user_id, room_id))
+                .cloned()
+                .unwrap_or_default()
+        }))
+    }
+
+    async fn notification_config_room_set(
+        &self,
+        user_id: UserId,
+        room_id: RoomId,
+        config: &NotifsRoom,
+    ) -> Result<()> {
+        let config = config.clone();
+        self.write(move |t| {
+            t.notification_config_room
+                .insert((user_id, room_id), config.clone());
+            Ok(())
+        })
+    }
+
+    async fn notification_config_thread_get(
+        &self,
+        user_id: UserId,
+        thread_id: ThreadId,
+    ) -> Result<NotifsThread> {
+        Ok(self.read(|t| {
+            t.notification_config_thread
+                .get(&(user_id, thread_id))
+                .cloned()
+                .unwrap_or_default()
+        }))
+    }
+
+    async fn notification_config_thread_set(
+        &self,
+        user_id: UserId,
+        thread_id: ThreadId,
+        config: &NotifsThread,
+    ) -> Result<()> {
+        let config = config.clone();
+        self.write(move |t| {
+            t.notification_config_thread
+                .insert((user_id, thread_id), config.clone());
+            Ok(())
+        })
+    }
+
+    async fn notification_queue_insert(
+        &self,
+        thread_id: ThreadId,
+        message_id: MessageId,
+        author_id: UserId,
+    ) -> Result<()> {
+        let row = QueueRow {
+            item: DbNotificationQueue {
+                message_id,
+                thread_id,
+                author_id,
+            },
+            status: QueueStatus::Pending,
+            retries: 0,
+            created_at: Time::now_utc(),
+            claimed_at: None,
+            last_attempt_at: None,
+            error_message: None,
+        };
+        self.write(move |t| {
+            t.notification_queue.insert(message_id, row.clone());
+            Ok(())
+        })
+    }
+
+    async fn notification_queue_claim(&self) -> Result<Option<DbNotificationQueue>> {
+        let now = Time::now_utc();
+        let reclaim_before = *now - Duration::minutes(5);
+        let retry_before = *now - Duration::minutes(1);
+        self.write(move |t| {
+            let row = t
+                .notification_queue
+                .values_mut()
+                .filter(|n| match n.status {
+                    QueueStatus::Pending => true,
+                    QueueStatus::Claimed => {
+                        n.claimed_at.as_ref().is_some_and(|c| **c < reclaim_before)
+                    }
+                    QueueStatus::Failed => {
+                        n.retries < 5
+                            && n.last_attempt_at
+                                .as_ref()
+                                .is_some_and(|a| **a < retry_before)
+                    }
+                })
+                .min_by_key(|n| n.created_at.clone());
+            Ok(row.map(|row| {
+                row.status = QueueStatus::Claimed;
+                row.claimed_at = Some(now.clone());
+                row.item.clone()
+            }))
+        })
+    }
+
+    async fn notification_queue_finish(&self, message_id: MessageId) -> Result<()> {
+        self.write(move |t| {
+            t.notification_queue.remove(&message_id);
+            Ok(())
+        })
+    }
+
+    async fn notification_queue_fail(
+        &self,
+        error_message: String,
+        message_id: MessageId,
+    ) -> Result<()> {
+        let now = Time::now_utc();
+        self.write(move |t| {
+            if let Some(row) = t.notification_queue.get_mut(&message_id) {
+                row.status = QueueStatus::Failed;
+                row.retries += 1;
+                row.last_attempt_at = Some(now.clone());
+                row.error_message = Some(error_message.clone());
+            }
+            Ok(())
+        })
+    }
+}
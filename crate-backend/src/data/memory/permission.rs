@@ -0,0 +1,130 @@
+use async_trait::async_trait;
+use common::v1::types::moderation::ReportDestination;
+use common::v1::types::{
+    Permission, PermissionOverwrite, PermissionOverwriteType, RoomId, ThreadId, UserId,
+};
+use uuid::Uuid;
+
+use crate::data::DataPermission;
+use crate::error::{Error, Result};
+use crate::types::{DbMembership, Permissions};
+
+use super::{Memory, Tables};
+
+impl Tables {
+    /// apply the role and user overwrites for a room or thread
+    fn apply_overwrites(&self, perms: &mut Permissions, target_id: Uuid, user_id: UserId) {
+        let role_overwrites = self
+            .permission_overwrites
+            .iter()
+            .filter(|((target, actor), _)| {
+                *target == target_id && self.role_members.contains(&((*actor).into(), user_id))
+            })
+            .map(|(_, o)| o);
+        let user_overwrite = self.permission_overwrites.get(&(target_id, *user_id));
+        for overwrite in role_overwrites.chain(user_overwrite) {
+            for p in &overwrite.allow {
+                perms.add(*p);
+            }
+            for p in &overwrite.deny {
+                perms.remove(*p);
+            }
+        }
+    }
+
+    pub(super) fn permission_room(&self, user_id: UserId, room_id: RoomId) -> Permissions {
+        let mut perms: Permissions = match self.room_members.get(&(room_id, user_id)) {
+            Some(m) if m.membership != DbMembership::Ban => self
+                .member_roles(room_id, user_id)
+                .into_iter()
+                .filter_map(|role_id| self.roles.get(&role_id))
+                .flat_map(|role| role.permissions.iter().copied())
+                .chain([Permission::View])
+                .collect(),
+            _ => Permissions::empty(),
+        };
+        self.apply_overwrites(&mut perms, *room_id, user_id);
+        perms
+    }
+
+    pub(super) fn permission_thread(
+        &self,
+        user_id: UserId,
+        thread_id: ThreadId,
+    ) -> Result<Permissions> {
+        let thread = self.threads.get(&thread_id).ok_or(Error::NotFound)?;
+        let mut perms = match thread.room_id {
+            Some(room_id) => self.permission_room(user_id, room_id),
+            None => Permissions::empty(),
+        };
+        self.apply_overwrites(&mut perms, *thread_id, user_id);
+
+        // report threads are only visible to moderators
+        if let Some(report) = self.report_threads.get(&thread_id) {
+            let required = match report.destination {
+                ReportDestination::Room => Permission::MemberBan,
+                ReportDestination::Server => Permission::ServerReports,
+            };
+            if !perms.has(required) {
+                return Ok(Permissions::empty());
+            }
+        }
+
+        Ok(perms)
+    }
+}
+
+#[async_trait]
+impl DataPermission for Memory {
+    async fn permission_room_get(&self, user_id: UserId, room_id: RoomId) -> Result<Permissions> {
+        Ok(self.read(|t| t.permission_room(user_id, room_id)))
+    }
+
+    async fn permission_thread_get(
+        &self,
+        user_id: UserId,
+        thread_id: ThreadId,
+    ) -> Result<Permissions> {
+        self.read(|t| t.permission_thread(user_id, thread_id))
+    }
+
+    async fn permission_is_mutual(&self, a: UserId, b: UserId) -> Result<bool> {
+        Ok(self.read(|t| {
+            t.room_members.keys().any(|(room_id, user_id)| {
+                *user_id == a && t.room_members.contains_key(&(*room_id, b))
+            })
+        }))
+    }
+
+    async fn permission_overwrite_upsert(
+        &self,
+        target_id: ThreadId,
+        actor_id: Uuid,
+        ty: PermissionOverwriteType,
+        allow: Vec<Permission>,
+        deny: Vec<Permission>,
+    ) -> Result<()> {
+        let overwrite = PermissionOverwrite {
+            id: actor_id,
+            ty,
+            allow,
+            deny,
+        };
+        self.write(move |t| {
+            t.permission_overwrites
+                .insert((*target_id, actor_id), overwrite.clone());
+            Ok(())
+        })
+    }
+
+    async fn permission_overwrite_delete(
+        &self,
+        thread_id: ThreadId,
+        overwrite_id: Uuid,
+    ) -> Result<()> {
+        self.write(move |t| {
+            t.permission_overwrites.remove(&(*thread_id, overwrite_id));
+            Ok(())
+        })
+    }
+}
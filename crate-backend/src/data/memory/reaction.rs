@@ -0,0 +1,118 @@
+use async_trait::async_trait;
+use common::v1::types::emoji::Emoji;
+use common::v1::types::reaction::{ReactionKey, ReactionListItem};
+use common::v1::types::{MessageId, PaginationQuery, PaginationResponse, ThreadId};
+use tracing::debug;
+
+use crate::error::Result;
+use crate::types::UserId;
+
+use crate::data::DataReaction;
+
+use super::{paginate, Memory, Pagination};
+
+#[derive(Clone)]
+pub struct Row {
+    pub message_id: MessageId,
+    pub user_id: UserId,
+    pub key: String,
+    pub position: u32,
+}
+
+/// reactions are keyed by the custom emoji id or the unicode emoji itself
+fn key_str(key: &ReactionKey) -> String {
+    match &key.0 {
+        Emoji::Custom(e) => e.id.to_string(),
+        Emoji::Unicode(e) => e.0.to_owned(),
+    }
+}
+
+#[async_trait]
+impl DataReaction for Memory {
+    async fn reaction_put(
+        &self,
+        user_id: UserId,
+        _thread_id: ThreadId,
+        message_id: MessageId,
+        key: ReactionKey,
+    ) -> Result<()> {
+        debug!("reaction put user_id={user_id} message_id={message_id} key={key:?}");
+        let key = key_str(&key);
+        self.write(move |t| {
+            let reactions = t.reactions.iter().filter(|r| r.message_id == message_id);
+            if reactions
+                .clone()
+                .any(|r| r.user_id == user_id && r.key == key)
+            {
+                return Ok(());
+            }
+            let position = match reactions.clone().find(|r| r.key == key) {
+                Some(r) => r.position,
+                None => reactions.map(|r| r.position + 1).max().unwrap_or(0),
+            };
+            t.reactions.push(Row {
+                message_id,
+                user_id,
+                key: key.clone(),
+                position,
+            });
+            Ok(())
+        })
+    }
+
+    async fn reaction_delete(
+        &self,
+        user_id: UserId,
+        _thread_id: ThreadId,
+        message_id: MessageId,
+        key: ReactionKey,
+    ) -> Result<()> {
+        debug!("reaction delete user_id={user_id} message_id={message_id} key={key:?}");
+        let key = key_str(&key);
+        self.write(move |t| {
+            t.reactions
+                .retain(|r| r.message_id != message_id || r.user_id != user_id || r.key != key);
+            Ok(())
+        })
+    }
+
+    async fn reaction_list(
+        &self,
+        _thread_id: ThreadId,
+        message_id: MessageId,
+        key: ReactionKey,
+        pagination: PaginationQuery<UserId>,
+    ) -> Result<PaginationResponse<ReactionListItem>> {
+        let p: Pagination<_> = pagination.try_into()?;
+        let key = key_str(&key);
+        Ok(self.read(|t| {
+            let items = t
+                .reactions
+                .iter()
+                .filter(|r| r.message_id == message_id && r.key == key)
+                .map(|r| ReactionListItem { user_id: r.user_id });
+            paginate(p, items, |i| i.user_id)
+        }))
+    }
+
+    async fn reaction_purge(&self, _thread_id: ThreadId, message_id: MessageId) -> Result<()> {
+        self.write(move |t| {
+            t.reactions.retain(|r| r.message_id != message_id);
+            Ok(())
+        })
+    }
+
+    async fn reaction_purge_key(
+        &self,
+        _thread_id: ThreadId,
+        message_id: MessageId,
+        key: ReactionKey,
+    ) -> Result<()> {
+        let key = key_str(&key);
+        self.write(move |t| {
+            t.reactions
+                .retain(|r| r.message_id != message_id || r.key != key);
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,146 @@
+use async_trait::async_trait;
+use common::v1::types::moderation::{
+    Report, ReportCreate, ReportDestination, ReportReason, ReportStatus, ReportTarget,
+};
+use common::v1::types::ReportId;
+
+use crate::data::DataReport;
+use crate::error::{Error, Result};
+use crate::types::{ThreadId, ThreadVerId, UserId};
+
+use super::{Memory, Tables};
+
+#[derive(Clone)]
+pub struct ThreadRow {
+    pub destination: ReportDestination,
+    pub target: ReportTarget,
+    pub status: ReportStatus,
+}
+
+#[derive(Clone)]
+pub struct Row {
+    pub thread_id: ThreadId,
+    pub reporter_id: UserId,
+    pub reason: ReportReason,
+    pub note: Option<String>,
+}
+
+impl Tables {
+    fn report(&self, report_id: ReportId) -> Option<Report> {
+        let row = self.reports.get(&report_id)?;
+        let thread = self.report_threads.get(&row.thread_id)?;
+        Some(Report {
+            id: report_id,
+            reporter_id: row.reporter_id,
+            reason: row.reason.clone(),
+            note: row.note.clone(),
+            destination: thread.destination.clone(),
+            target: thread.target.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl DataReport for Memory {
+    async fn report_thread_find(
+        &self,
+        destination: ReportDestination,
+        target: &ReportTarget,
+    ) -> Result<Option<ThreadId>> {
+        Ok(self.read(|t| {
+            t.report_threads
+                .iter()
+                .find(|(_, r)| {
+                    r.destination == destination
+                        && r.target == *target
+                        && r.status == ReportStatus::Open
+                })
+                .map(|(thread_id, _)| *thread_id)
+        }))
+    }
+
+    async fn report_thread_create(
+        &self,
+        thread_id: ThreadId,
+        destination: ReportDestination,
+        target: &ReportTarget,
+    ) -> Result<bool> {
+        let row = ThreadRow {
+            destination,
+            target: target.clone(),
+            status: ReportStatus::Open,
+        };
+        self.write(move |t| {
+            if !t.threads.contains_key(&thread_id) {
+                return Err(Error::NotFound);
+            }
+            let is_open = t.report_threads.values().any(|r| {
+                r.destination == row.destination
+                    && r.target == row.target
+                    && r.status == ReportStatus::Open
+            });
+            if is_open {
+                return Ok(false);
+            }
+            t.report_threads.insert(thread_id, row.clone());
+            Ok(true)
+        })
+    }
+
+    async fn report_thread_set_status(
+        &self,
+        thread_id: ThreadId,
+        status: ReportStatus,
+    ) -> Result<()> {
+        let version_id = ThreadVerId::new();
+        self.write(move |t| {
+            if let Some(report) = t.report_threads.get_mut(&thread_id) {
+                report.status = status;
+            }
+            if let Some(thread) = t.threads.get_mut(&thread_id) {
+                thread.version_id = version_id;
+            }
+            Ok(())
+        })
+    }
+
+    async fn report_create(
+        &self,
+        thread_id: ThreadId,
+        reporter_id: UserId,
+        create: ReportCreate,
+    ) -> Result<Report> {
+        let report_id = ReportId::new();
+        let row = Row {
+            thread_id,
+            reporter_id,
+            reason: create.reason,
+            note: create.note,
+        };
+        self.write(move |t| {
+            if !t.report_threads.contains_key(&thread_id) || !t.users.contains_key(&reporter_id) {
+                return Err(Error::NotFound);
+            }
+            t.reports.insert(report_id, row.clone());
+            Ok(())
+        })?;
+        self.report_get(report_id).await
+    }
+
+    async fn report_get(&self, report_id: ReportId) -> Result<Report> {
+        self.read(|t| t.report(report_id)).ok_or(Error::NotFound)
+    }
+
+    async fn report_get_by_reporter(
+        &self,
+        thread_id: ThreadId,
+        reporter_id: UserId,
+    ) -> Result<Option<Report>> {
+        Ok(self.read(|t| {
+            t.reports
+                .iter()
+                .find(|(_, r)| r.thread_id == thread_id && r.reporter_id == reporter_id)
+                .and_then(|(id, _)| t.report(*id))
+        }))
+    }
+}
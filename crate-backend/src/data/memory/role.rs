@@ -0,0 +1,136 @@
+use async_trait::async_trait;
+use common::v1::types::UserId;
+
+use crate::error::{Error, Result};
+use crate::types::{
+    DbRoleCreate, PaginationQuery, PaginationResponse, Role, RoleId, RolePatch, RoleVerId, RoomId,
+};
+
+use crate::data::DataRole;
+
+use super::{paginate, Memory, Pagination};
+
+#[async_trait]
+impl DataRole for Memory {
+    async fn role_create(&self, create: DbRoleCreate) -> Result<Role> {
+        let role_id = RoleId::new();
+        let role = Role {
+            id: role_id,
+            version_id: role_id.into_inner().into(),
+            room_id: create.room_id,
+            name: create.name,
+            description: create.description,
+            permissions: create.permissions,
+            is_self_applicable: create.is_self_applicable,
+            is_mentionable: create.is_mentionable,
+            is_default: create.is_default,
+            position: create.position,
+        };
+        let row = role.clone();
+        self.write(move |t| {
+            if !t.rooms.contains_key(&row.room_id) {
+                return Err(Error::NotFound);
+            }
+            t.roles.insert(row.id, row.clone());
+            Ok(())
+        })?;
+        Ok(role)
+    }
+
+    async fn role_list(
+        &self,
+        room_id: RoomId,
+        paginate_query: PaginationQuery<RoleId>,
+    ) -> Result<PaginationResponse<Role>> {
+        let p: Pagination<_> = paginate_query.try_into()?;
+        self.read(|t| {
+            let roles = t.roles.values().filter(|r| r.room_id == room_id).cloned();
+            Ok(paginate(p, roles, |r| r.id))
+        })
+    }
+
+    async fn role_delete(&self, _room_id: RoomId, role_id: RoleId) -> Result<()> {
+        self.write(move |t| {
+            t.role_members.retain(|(r, _)| *r != role_id);
+            t.roles.remove(&role_id);
+            Ok(())
+        })
+    }
+
+    async fn role_select(&self, room_id: RoomId, role_id: RoleId) -> Result<Role> {
+        self.read(|t| {
+            t.roles
+                .get(&role_id)
+                .filter(|r| r.room_id == room_id)
+                .cloned()
+        })
+        .ok_or(Error::NotFound)
+    }
+
+    async fn role_update(
+        &self,
+        room_id: RoomId,
+        role_id: RoleId,
+        patch: RolePatch,
+    ) -> Result<RoleVerId> {
+        let version_id = RoleVerId::new();
+        self.write(move |t| {
+            let role = t
+                .roles
+                .get_mut(&role_id)
+                .filter(|r| r.room_id == room_id)
+                .ok_or(Error::NotFound)?;
+            role.version_id = version_id;
+            if let Some(name) = &patch.name {
+                role.name = name.clone();
+            }
+            if let Some(description) = &patch.description {
+                role.description = description.clone();
+            }
+            if let Some(permissions) = &patch.permissions {
+                role.permissions = permissions.clone();
+            }
+            if let Some(is_mentionable) = patch.is_mentionable {
+                role.is_mentionable = is_mentionable;
+            }
+            if let Some(is_self_applicable) = patch.is_self_applicable {
+                role.is_self_applicable = is_self_applicable;
+            }
+            if let Some(is_default) = patch.is_default {
+                role.is_default = is_default;
+            }
+            if let Some(position) = patch.position {
+                role.position = position;
+            }
+            Ok(version_id)
+        })
+    }
+
+    async fn role_apply_default(&self, room_id: RoomId, user_id: UserId) -> Result<()> {
+        self.write(move |t| {
+            let defaults: Vec<RoleId> = t
+                .roles
+                .values()
+                .filter(|r| r.room_id == room_id && r.is_default)
+                .map(|r| r.id)
+                .collect();
+            for role_id in defaults {
+                t.role_members.insert((role_id, user_id));
+            }
+            Ok(())
+        })
+    }
+
+    async fn role_position_max(&self, room_id: RoomId, user_id: UserId) -> Result<u64> {
+        Ok(self.read(|t| {
+            t.role_members
+                .iter()
+                .filter(|(_, u)| *u == user_id)
+                .filter_map(|(role_id, _)| t.roles.get(role_id))
+                .filter(|r| r.room_id == room_id)
+                .map(|r| r.position)
+                .max()
+                .unwrap_or(0)
+        }))
+    }
+}
@@ -0,0 +1,57 @@
+use async_trait::async_trait;
+use common::v1::types::{PaginationQuery, PaginationResponse, RoomMember};
+
+use crate::error::{Error, Result};
+use crate::types::{RoleId, UserId};
+
+use crate::data::DataRoleMember;
+
+use super::{paginate, Memory, Pagination};
+
+#[async_trait]
+impl DataRoleMember for Memory {
+    async fn role_member_put(&self, user_id: UserId, role_id: RoleId) -> Result<()> {
+        self.write(move |t| {
+            if !t.roles.contains_key(&role_id) || !t.users.contains_key(&user_id) {
+                return Err(Error::NotFound);
+            }
+            t.role_members.insert((role_id, user_id));
+            Ok(())
+        })
+    }
+
+    async fn role_member_delete(&self, user_id: UserId, role_id: RoleId) -> Result<()> {
+        self.write(move |t| {
+            t.role_members.remove(&(role_id, user_id));
+            Ok(())
+        })
+    }
+
+    async fn role_member_list(
+        &self,
+        role_id: RoleId,
+        paginate_query: PaginationQuery<UserId>,
+    ) -> Result<PaginationResponse<RoomMember>> {
+        let p: Pagination<_> = paginate_query.try_into()?;
+        self.read(|t| {
+            let user_ids: Vec<UserId> = t
+                .role_members
+                .iter()
+                .filter(|(r, _)| *r == role_id)
+                .map(|(_, u)| *u)
+                .collect();
+            let total = user_ids.len() as u64;
+            let room_id = t.roles.get(&role_id).map(|r| r.room_id);
+            let members = user_ids
+                .into_iter()
+                .filter_map(|user_id| t.room_member(room_id?, user_id));
+            let mut res = paginate(p, members, |m| m.user_id);
+            res.total = total;
+            Ok(res)
+        })
+    }
+
+    async fn role_member_count(&self, role_id: RoleId) -> Result<u64> {
+        Ok(self.read(|t| t.role_members.iter().filter(|(r, _)| *r == role_id).count()) as u64)
+    }
+}
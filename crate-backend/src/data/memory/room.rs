@@ -0,0 +1,79 @@
+use async_trait::async_trait;
+
+use crate::error::{Error, Result};
+use crate::types::{
+    DbMembership, PaginationQuery, PaginationResponse, Room, RoomCreate, RoomId, RoomPatch,
+    RoomType, RoomVerId, UserId,
+};
+
+use crate::data::DataRoom;
+
+use super::{paginate, Memory, Pagination};
+
+#[async_trait]
+impl DataRoom for Memory {
+    async fn room_create(&self, create: RoomCreate, owner_id: Option<UserId>) -> Result<Room> {
+        let room_id = RoomId::new();
+        #[allow(deprecated)]
+        let room = Room {
+            id: room_id,
+            version_id: *room_id,
+            name: create.name,
+            description: create.description,
+            icon: create.icon,
+            room_type: RoomType::Default,
+            owner_id,
+            member_count: Default::default(),
+            online_count: Default::default(),
+            thread_count: Default::default(),
+            archived_at: None,
+        };
+        let row = room.clone();
+        self.write(move |t| {
+            t.rooms.insert(row.id, row.clone());
+            Ok(())
+        })?;
+        Ok(room)
+    }
+
+    async fn room_get(&self, room_id: RoomId) -> Result<Room> {
+        self.read(|t| t.rooms.get(&room_id).cloned())
+            .ok_or(Error::NotFound)
+    }
+
+    async fn room_list(
+        &self,
+        user_id: UserId,
+        pagination: PaginationQuery<RoomId>,
+    ) -> Result<PaginationResponse<Room>> {
+        let p: Pagination<_> = pagination.try_into()?;
+        self.read(|t| {
+            let members = t.room_members.iter().filter(|((_, u), _)| *u == user_id);
+            let total = members.clone().count() as u64;
+            let rooms = members
+                .filter(|(_, m)| m.membership == DbMembership::Join)
+                .filter_map(|((room_id, _), _)| t.rooms.get(room_id).cloned());
+            let mut res = paginate(p, rooms, |r| r.id);
+            res.total = total;
+            Ok(res)
+        })
+    }
+
+    async fn room_update(&self, room_id: RoomId, patch: RoomPatch) -> Result<RoomVerId> {
+        let version_id = RoomVerId::new();
+        self.write(move |t| {
+            let room = t.rooms.get_mut(&room_id).ok_or(Error::NotFound)?;
+            room.version_id = *version_id;
+            if let Some(name) = &patch.name {
+                room.name = name.clone();
+            }
+            if let Some(description) = &patch.description {
+                room.description = description.clone();
+            }
+            if let Some(icon) = patch.icon {
+                room.icon = icon;
+            }
+            Ok(version_id)
+        })
+    }
+}
@@ -0,0 +1,85 @@
+use async_trait::async_trait;
+use common::v1::types::util::Time;
+use common::v1::types::{PaginationQuery, PaginationResponse, RoomBan};
+
+use crate::data::DataRoomBan;
+use crate::error::{Error, Result};
+use crate::types::{DbMembership, RoomId, UserId};
+
+use super::{paginate, room_member, with_prefix, Memory, Pagination};
+
+#[async_trait]
+impl DataRoomBan for Memory {
+    async fn room_ban_create(
+        &self,
+        room_id: RoomId,
+        user_id: UserId,
+        creator_id: UserId,
+        reason: Option<String>,
+        expires_at: Option<Time>,
+    ) -> Result<()> {
+        let ban = RoomBan {
+            user_id,
+            room_id,
+            creator_id,
+            reason,
+            created_at: Time::now_utc(),
+            expires_at,
+        };
+        self.write(move |t| {
+            if !t.rooms.contains_key(&room_id) || !t.users.contains_key(&user_id) {
+                return Err(Error::NotFound);
+            }
+            t.room_bans.insert((room_id, user_id), ban.clone());
+            let member =
+                t.room_members
+                    .entry((room_id, user_id))
+                    .or_insert_with(|| room_member::Row {
+                        membership: DbMembership::Ban,
+                        override_name: None,
+                        override_description: None,
+                        membership_updated_at: ban.created_at.clone(),
+                    });
+            member.membership = DbMembership::Ban;
+            member.membership_updated_at = ban.created_at.clone();
+            Ok(())
+        })
+    }
+
+    async fn room_ban_delete(&self, room_id: RoomId, user_id: UserId) -> Result<()> {
+        let now = Time::now_utc();
+        self.write(move |t| {
+            t.room_bans.remove(&(room_id, user_id));
+            if let Some(member) = t.room_members.get_mut(&(room_id, user_id)) {
+                if member.membership == DbMembership::Ban {
+                    member.membership = DbMembership::Leave;
+                    member.membership_updated_at = now.clone();
+                }
+            }
+            Ok(())
+        })
+    }
+
+    async fn room_ban_get(&self, room_id: RoomId, user_id: UserId) -> Result<RoomBan> {
+        self.read(|t| {
+            t.is_banned(room_id, user_id)
+                .then(|| t.room_bans.get(&(room_id, user_id)).cloned())
+                .flatten()
+        })
+        .ok_or(Error::NotFound)
+    }
+
+    async fn room_ban_list(
+        &self,
+        room_id: RoomId,
+        paginate_query: PaginationQuery<UserId>,
+    ) -> Result<PaginationResponse<RoomBan>> {
+        let p: Pagination<_> = paginate_query.try_into()?;
+        self.read(|t| {
+            let bans = with_prefix(&t.room_bans, room_id)
+                .filter(|(user_id, _)| t.is_banned(room_id, *user_id))
+                .map(|(_, ban)| ban.clone());
+            Ok(paginate(p, bans, |b| b.user_id))
+        })
+    }
+}
@@ -0,0 +1,160 @@
+use async_trait::async_trait;
+use common::v1::types::util::Time;
+use common::v1::types::{
+    PaginationQuery, PaginationResponse, RoomMember, RoomMemberPatch, RoomMembership,
+};
+
+use crate::error::{Error, Result};
+use crate::types::{DbMembership, RoleId, RoomId, UserId};
+
+use crate::data::DataRoomMember;
+
+use super::{paginate, with_prefix, Memory, Pagination, Tables};
+
+#[derive(Clone)]
+pub struct Row {
+    pub membership: DbMembership,
+    pub override_name: Option<String>,
+    pub override_description: Option<String>,
+    pub membership_updated_at: Time,
+}
+
+impl Tables {
+    /// get the roles a user has in a room
+    pub(super) fn member_roles(&self, room_id: RoomId, user_id: UserId) -> Vec<RoleId> {
+        self.role_members
+            .iter()
+            .filter(|(role_id, u)| {
+                *u == user_id
+                    && self
+                        .roles
+                        .get(role_id)
+                        .is_some_and(|r| r.room_id == room_id)
+            })
+            .map(|(role_id, _)| *role_id)
+            .collect()
+    }
+
+    pub(super) fn room_member(&self, room_id: RoomId, user_id: UserId) -> Option<RoomMember> {
+        let row = self.room_members.get(&(room_id, user_id))?;
+        Some(RoomMember {
+            user_id,
+            room_id,
+            membership: match row.membership {
+                DbMembership::Join => RoomMembership::Join {
+                    override_name: row.override_name.clone(),
+                    override_description: row.override_description.clone(),
+                    roles: self.member_roles(room_id, user_id),
+                },
+                DbMembership::Leave => RoomMembership::Leave {},
+                DbMembership::Ban => RoomMembership::Ban {},
+            },
+            membership_updated_at: row.membership_updated_at.clone(),
+        })
+    }
+
+    /// whether a user has a ban in a room that hasn't expired yet
+    pub(super) fn is_banned(&self, room_id: RoomId, user_id: UserId) -> bool {
+        self.room_bans
+            .get(&(room_id, user_id))
+            .is_some_and(|ban| ban.expires_at.as_ref().is_none_or(|e| *e > Time::now_utc()))
+    }
+}
+
+#[async_trait]
+impl DataRoomMember for Memory {
+    async fn room_member_put(
+        &self,
+        room_id: RoomId,
+        user_id: UserId,
+        membership: RoomMembership,
+    ) -> Result<()> {
+        let membership: DbMembership = membership.into();
+        if membership == DbMembership::Join && self.read(|t| t.is_banned(room_id, user_id)) {
+            return Err(Error::Banned);
+        }
+        let now = Time::now_utc();
+        self.write(move |t| {
+            if !t.rooms.contains_key(&room_id) || !t.users.contains_key(&user_id) {
+                return Err(Error::NotFound);
+            }
+            t.room_members
+                .entry((room_id, user_id))
+                .and_modify(|m| m.membership = membership)
+                .or_insert_with(|| Row {
+                    membership,
+                    override_name: None,
+                    override_description: None,
+                    membership_updated_at: now.clone(),
+                });
+            Ok(())
+        })
+    }
+
+    async fn room_member_delete(&self, room_id: RoomId, user_id: UserId) -> Result<()> {
+        self.write(move |t| {
+            t.room_members.remove(&(room_id, user_id));
+            Ok(())
+        })
+    }
+
+    async fn room_member_list(
+        &self,
+        room_id: RoomId,
+        pagination: PaginationQuery<UserId>,
+    ) -> Result<PaginationResponse<RoomMember>> {
+        let p: Pagination<_> = pagination.try_into()?;
+        self.read(|t| {
+            let members = with_prefix(&t.room_members, room_id)
+                .filter(|(_, m)| m.membership == DbMembership::Join)
+                .filter_map(|(user_id, _)| t.room_member(room_id, user_id));
+            Ok(paginate(p, members, |m| m.user_id))
+        })
+    }
+
+    async fn room_member_get(&self, room_id: RoomId, user_id: UserId) -> Result<RoomMember> {
+        self.read(|t| t.room_member(room_id, user_id))
+            .ok_or(Error::NotFound)
+    }
+
+    async fn room_member_patch(
+        &self,
+        room_id: RoomId,
+        user_id: UserId,
+        patch: RoomMemberPatch,
+    ) -> Result<()> {
+        self.write(move |t| {
+            let member = t
+                .room_members
+                .get_mut(&(room_id, user_id))
+                .ok_or(Error::NotFound)?;
+            if member.membership != DbMembership::Join {
+                return Ok(());
+            }
+            if let Some(override_name) = &patch.override_name {
+                member.override_name = override_name.clone();
+            }
+            if let Some(override_description) = &patch.override_description {
+                member.override_description = override_description.clone();
+            }
+            Ok(())
+        })
+    }
+
+    async fn room_member_set_membership(
+        &self,
+        room_id: RoomId,
+        user_id: UserId,
+        membership: RoomMembership,
+    ) -> Result<()> {
+        let membership: DbMembership = membership.into();
+        let now = Time::now_utc();
+        self.write(move |t| {
+            if let Some(member) = t.room_members.get_mut(&(room_id, user_id)) {
+                member.membership = membership;
+                member.membership_updated_at = now.clone();
+            }
+            Ok(())
+        })
+    }
+}
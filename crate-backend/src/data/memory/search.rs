@@ -0,0 +1,369 @@
+use std::cmp::Ordering;
+
+use async_trait::async_trait;
+use common::v1::types::notifications::Mute;
+use common::v1::types::search::{
+    SearchMessageFeatures, SearchMessageRequest, SearchOrder, SearchRoomFeatures,
+    SearchRoomsRequest, SearchThreadFeatures, SearchThreadsRequest,
+};
+use common::v1::types::user::{Ignore, RelationshipType};
+use common::v1::types::util::Time;
+use common::v1::types::{
+    Message, MessageId, MessageType, PaginationDirection, PaginationQuery, PaginationResponse,
+    Permission, Room, RoomId, RoomType, Thread, ThreadId, UserId,
+};
+
+use crate::data::DataSearch;
+use crate::error::Result;
+use crate::types::{DbMembership, DbThreadType};
+
+use super::{Memory, Pagination, Tables};
+
+impl Tables {
+    /// whether a user can view a thread, like `thread_viewable` in postgres
+    ///
+    /// report threads are left out, since only their moderators can see them
+    pub(super) fn is_viewable_thread(&self, user_id: UserId, thread_id: ThreadId) -> bool {
+        self.threads
+            .get(&thread_id)
+            .is_some_and(|thread| !matches!(thread.ty, DbThreadType::Report))
+            && self
+                .permission_thread(user_id, thread_id)
+                .is_ok_and(|perms| perms.has(Permission::View))
+    }
+
+    /// threads outside rooms aren't in a dm
+    fn is_dm(&self, room_id: Option<RoomId>) -> bool {
+        room_id
+            .and_then(|room_id| self.rooms.get(&room_id))
+            .is_some_and(|r| matches!(r.room_type, RoomType::Dm { .. }))
+    }
+
+    /// threads outside rooms don't need a room to be joined
+    fn is_joined_room(&self, user_id: UserId, room_id: Option<RoomId>) -> bool {
+        room_id.is_none_or(|room_id| {
+            self.room_members
+                .get(&(room_id, user_id))
+                .is_some_and(|m| m.membership == DbMembership::Join)
+        })
+    }
+
+    fn is_room_muted(&self, user_id: UserId, room_id: Option<RoomId>, now: &Time) -> bool {
+        room_id
+            .and_then(|room_id| self.notification_config_room.get(&(user_id, room_id)))
+            .is_some_and(|c| is_muted(&c.mute, now))
+    }
+
+    fn is_thread_muted(&self, user_id: UserId, thread_id: ThreadId, now: &Time) -> bool {
+        self.notification_config_thread
+            .get(&(user_id, thread_id))
+            .is_some_and(|c| is_muted(&c.mute, now))
+    }
+
+    fn is_joined_thread(&self, user_id: UserId, thread_id: ThreadId) -> bool {
+        self.thread_members
+            .get(&(thread_id, user_id))
+            .is_some_and(|m| m.membership == DbMembership::Join)
+    }
+
+    /// rooms the user has joined
+    fn joined_rooms(&self, user_id: UserId) -> impl Iterator<Item = RoomId> + '_ {
+        self.room_members
+            .iter()
+            .filter(move |((_, u), m)| *u == user_id && m.membership == DbMembership::Join)
+            .map(|((room_id, _), _)| *room_id)
+    }
+
+    /// whether the user has hidden messages from this author
+    fn is_hidden_author(
+        &self,
+        user_id: UserId,
+        author_id: UserId,
+        include_ignored: bool,
+        include_blocked: bool,
+        now: &Time,
+    ) -> bool {
+        let Some(rel) = self.relationships.get(&(user_id, author_id)) else {
+            return false;
+        };
+        let blocked = rel.relation == Some(RelationshipType::Block);
+        let ignored = match &rel.ignore {
+            Some(Ignore::Forever) => true,
+            Some(Ignore::Until { ignore_until }) => ignore_until > now,
+            None => false,
+        };
+        (blocked && !include_blocked) || (ignored && !(include_ignored || include_blocked))
+    }
+}
+
+fn is_muted(mute: &Option<Mute>, now: &Time) -> bool {
+    mute.as_ref()
+        .is_some_and(|m| m.expires_at.as_ref().is_none_or(|e| e > now))
+}
+
+/// a rough stand in for full text search
+///
+/// returns None if any word in the query is missing from the text, otherwise
+/// how many times the query's words show up
+fn rank(text: &str, query: &str) -> Option<usize> {
+    let text = text.to_lowercase();
+    let mut words = query.split_whitespace().peekable();
+    words.peek()?;
+    words
+        .map(|w| match text.matches(&w.to_lowercase()).count() {
+            0 => None,
+            n => Some(n),
+        })
+        .sum()
+}
+
+fn message_content(message: &Message) -> Option<&str> {
+    match &message.message_type {
+        MessageType::DefaultMarkdown(m) => m.content.as_deref(),
+        MessageType::DefaultTagged(m) => m.content.as_deref(),
+        _ => None,
+    }
+}
+
+fn has_embeds(message: &Message) -> bool {
+    match &message.message_type {
+        MessageType::DefaultMarkdown(m) => !m.embeds.is_empty(),
+        MessageType::DefaultTagged(m) => !m.embeds.is_empty(),
+        _ => false,
+    }
+}
+
+fn has_attachment_mime(message: &Message, prefix: &str) -> bool {
+    let attachments = match &message.message_type {
+        MessageType::DefaultMarkdown(m) => &m.attachments,
+        MessageType::DefaultTagged(m) => &m.attachments,
+        _ => return false,
+    };
+    attachments
+        .iter()
+        .any(|a| a.source.mime.starts_with(prefix))
+}
+
+/// paginate through ranked search results the same way as the postgres
+/// search queries do
+///
+/// `rank` is only used when ordering by relevance
+fn paginate_ranked<K: Ord + Copy, T>(
+    p: &Pagination<K>,
+    items: Vec<(usize, K, T)>,
+    relevance: bool,
+) -> PaginationResponse<T> {
+    let total = items.len() as u64;
+    let mut items: Vec<_> = items
+        .into_iter()
+        .filter(|(_, k, _)| *k > p.after && *k < p.before)
+        .collect();
+    items.sort_by(|(rank_a, a, _), (rank_b, b, _)| {
+        let by_rank = if relevance {
+            rank_b.cmp(rank_a)
+        } else {
+            Ordering::Equal
+        };
+        let by_id = match p.dir {
+            PaginationDirection::F => a.cmp(b),
+            PaginationDirection::B => b.cmp(a),
+        };
+        by_rank.then(by_id)
+    });
+    let has_more = items.len() > p.limit as usize;
+    items.truncate(p.limit as usize);
+    if p.dir == PaginationDirection::B {
+        items.reverse();
+    }
+    PaginationResponse {
+        items: items.into_iter().map(|(_, _, i)| i).collect(),
+        total,
+        has_more,
+    }
+}
+
+#[async_trait]
+impl DataSearch for Memory {
+    async fn search_message(
+        &self,
+        user_id: UserId,
+        query: SearchMessageRequest,
+        paginate: PaginationQuery<MessageId>,
+    ) -> Result<PaginationResponse<Message>> {
+        let p = Pagination::for_search(paginate, &query.order_by)?;
+        let relevance = query.order_by == SearchOrder::Relevance;
+        let f = &query.features_message;
+        let mut mime_prefixes = vec![];
+        for (feature, prefix) in [
+            (SearchMessageFeatures::Attachment, ""),
+            (SearchMessageFeatures::Image, "image/"),
+            (SearchMessageFeatures::Audio, "audio/"),
+            (SearchMessageFeatures::Video, "video/"),
+        ] {
+            if f.contains(&feature) {
+                mime_prefixes.push(prefix);
+            }
+        }
+        let is_pinned = f.contains(&SearchMessageFeatures::Pinned);
+        let has_link = f.contains(&SearchMessageFeatures::Link);
+        let has_embed = f.contains(&SearchMessageFeatures::Embed);
+        let include_ignored = f.contains(&SearchMessageFeatures::Ignored);
+        let include_blocked = f.contains(&SearchMessageFeatures::Blocked);
+        let include_all = !query.thread_id.is_empty()
+            || query.features_thread.contains(&SearchThreadFeatures::All);
+        let include_muted = !query.room_id.is_empty()
+            || !query.thread_id.is_empty()
+            || query.features_thread.contains(&SearchThreadFeatures::Muted)
+            || query.features_room.contains(&SearchRoomFeatures::Muted);
+        let dm = dm_filter(&query.features_room);
+        let now = Time::now_utc();
+        Ok(self.read(|t| {
+            let threads: Vec<ThreadId> = t
+                .threads
+                .iter()
+                .filter(|(thread_id, thread)| {
+                    let room_id = thread.room_id;
+                    t.is_joined_room(user_id, room_id)
+                        && thread.deleted_at.is_none()
+                        && t.is_viewable_thread(user_id, **thread_id)
+                        && (query.room_id.is_empty()
+                            || room_id.is_some_and(|r| query.room_id.contains(&r)))
+                        && (query.thread_id.is_empty() || query.thread_id.contains(thread_id))
+                        && (include_all || t.is_joined_thread(user_id, **thread_id))
+                        && (include_muted
+                            || !(t.is_room_muted(user_id, room_id, &now)
+                                || t.is_thread_muted(user_id, **thread_id, &now)))
+                        && dm.is_none_or(|dm| t.is_dm(room_id) == dm)
+                })
+                .map(|(thread_id, _)| *thread_id)
+                .collect();
+            let found: Vec<(usize, MessageId, Message)> = threads
+                .into_iter()
+                .flat_map(|thread_id| t.thread_messages(thread_id))
+                .filter(|row| row.message.deleted_at.is_none())
+                .filter(|row| {
+                    query.user_id.is_empty() || query.user_id.contains(&row.message.author_id)
+                })
+                .filter(|row| {
+                    !t.is_hidden_author(
+                        user_id,
+                        row.message.author_id,
+                        include_ignored,
+                        include_blocked,
+                        &now,
+                    )
+                })
+                .filter_map(|row| {
+                    let content = message_content(&row.message)?;
+                    let rank = rank(content, &query.query)?;
+                    if has_link && !(content.contains("http://") || content.contains("https://")) {
+                        return None;
+                    }
+                    let message = t.message(row, user_id);
+                    let keep = mime_prefixes
+                        .iter()
+                        .all(|prefix| has_attachment_mime(&message, prefix))
+                        && (!has_embed || has_embeds(&message))
+                        && (!is_pinned || message.pinned_at.is_some());
+                    keep.then_some((rank, message.id, message))
+                })
+                .collect();
+            let mut res = paginate_ranked(&p, found, relevance);
+            p.sort_search(&mut res.items, &query.order_by);
+            res
+        }))
+    }
+
+    async fn search_thread(
+        &self,
+        user_id: UserId,
+        query: SearchThreadsRequest,
+        paginate: PaginationQuery<ThreadId>,
+    ) -> Result<PaginationResponse<Thread>> {
+        let p = Pagination::for_search(paginate, &query.order_by)?;
+        let include_all = query.features_thread.contains(&SearchThreadFeatures::All);
+        let include_muted = !query.room_id.is_empty()
+            || query.features_thread.contains(&SearchThreadFeatures::Muted)
+            || query.features_room.contains(&SearchRoomFeatures::Muted);
+        let dm = dm_filter(&query.features_room);
+        let relevance = query.order_by == SearchOrder::Relevance;
+        let now = Time::now_utc();
+        Ok(self.read(|t| {
+            let found: Vec<(usize, ThreadId, Thread)> = t
+                .threads
+                .iter()
+                .filter(|(thread_id, thread)| {
+                    let room_id = thread.room_id;
+                    t.is_joined_room(user_id, room_id)
+                        && (query.room_id.is_empty()
+                            || room_id.is_some_and(|r| query.room_id.contains(&r)))
+                        && dm.is_none_or(|dm| t.is_dm(room_id) == dm)
+                        && (include_muted || !t.is_room_muted(user_id, room_id, &now))
+                        && thread.deleted_at.is_none()
+                        && t.is_viewable_thread(user_id, **thread_id)
+                        && (include_all || t.is_joined_thread(user_id, **thread_id))
+                        && (include_muted || !t.is_thread_muted(user_id, **thread_id, &now))
+                })
+                .filter(|(thread_id, _)| {
+                    let implied = t.thread_tags_implied(**thread_id);
+                    query.tag_id.iter().all(|tag_id| implied.contains(tag_id))
+                })
+                .filter_map(|(thread_id, thread)| {
+                    let text = match &thread.description {
+                        Some(d) => format!("{} {d}", thread.name),
+                        None => thread.name.clone(),
+                    };
+                    let rank = rank(&text, &query.query)?;
+                    Some((rank, *thread_id, t.thread(*thread_id)?))
+                })
+                .collect();
+            let mut res = paginate_ranked(&p, found, relevance);
+            p.sort_search(&mut res.items, &query.order_by);
+            res
+        }))
+    }
+
+    async fn search_room(
+        &self,
+        user_id: UserId,
+        query: SearchRoomsRequest,
+        paginate: PaginationQuery<RoomId>,
+    ) -> Result<PaginationResponse<Room>> {
+        let p = Pagination::for_search(paginate, &query.order_by)?;
+        // there are no discoverable public rooms yet, so `Public` doesn't add anything
+        let include_muted = query.features_room.contains(&SearchRoomFeatures::Muted);
+        let dm = dm_filter(&query.features_room);
+        let relevance = query.order_by == SearchOrder::Relevance;
+        let now = Time::now_utc();
+        Ok(self.read(|t| {
+            let found: Vec<(usize, RoomId, Room)> = t
+                .joined_rooms(user_id)
+                .filter(|room_id| dm.is_none_or(|dm| t.is_dm(Some(*room_id)) == dm))
+                .filter(|room_id| include_muted || !t.is_room_muted(user_id, Some(*room_id), &now))
+                .filter_map(|room_id| {
+                    let room = t.rooms.get(&room_id)?;
+                    let text = match &room.description {
+                        Some(d) => format!("{} {d}", room.name),
+                        None => room.name.clone(),
+                    };
+                    let rank = rank(&text, &query.query)?;
+                    Some((rank, room_id, room.clone()))
+                })
+                .collect();
+            let mut res = paginate_ranked(&p, found, relevance);
+            p.sort_search(&mut res.items, &query.order_by);
+            res
+        }))
+    }
+}
+
+/// whether to only return dm rooms (`Some(true)`), only non-dm rooms (`Some(false)`), or both
+fn dm_filter(features: &[SearchRoomFeatures]) -> Option<bool> {
+    if features.contains(&SearchRoomFeatures::NotDm) {
+        Some(false)
+    } else if features.contains(&SearchRoomFeatures::Dm) {
+        Some(true)
+    } else {
+        None
+    }
+}
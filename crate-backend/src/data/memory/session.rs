@@ -0,0 +1,95 @@
+use async_trait::async_trait;
+use common::v1::types::{SessionPatch, SessionStatus, SessionToken};
+
+use crate::error::{Error, Result};
+use crate::types::{PaginationQuery, PaginationResponse, Session, SessionId, UserId};
+
+use crate::data::DataSession;
+
+use super::{paginate, Memory, Pagination};
+
+#[derive(Clone)]
+pub struct Row {
+    pub session: Session,
+    pub token: SessionToken,
+}
+
+#[async_trait]
+impl DataSession for Memory {
+    async fn session_create(&self, token: SessionToken, name: Option<String>) -> Result<Session> {
+        let session = Session {
+            id: SessionId::new(),
+            status: SessionStatus::Unauthorized,
+            name,
+        };
+        let row = Row {
+            session: session.clone(),
+            token,
+        };
+        self.write(move |t| {
+            if t.sessions.values().any(|s| s.token == row.token) {
+                return Err(Error::BadStatic("session token already exists"));
+            }
+            t.sessions.insert(row.session.id, row.clone());
+            Ok(())
+        })?;
+        Ok(session)
+    }
+
+    async fn session_get(&self, id: SessionId) -> Result<Session> {
+        self.read(|t| t.sessions.get(&id).map(|s| s.session.clone()))
+            .ok_or(Error::NotFound)
+    }
+
+    async fn session_get_by_token(&self, token: SessionToken) -> Result<Session> {
+        self.read(|t| {
+            t.sessions
+                .values()
+                .find(|s| s.token == token)
+                .map(|s| s.session.clone())
+        })
+        .ok_or(Error::NotFound)
+    }
+
+    async fn session_set_status(&self, session_id: SessionId, status: SessionStatus) -> Result<()> {
+        self.write(move |t| {
+            if let Some(row) = t.sessions.get_mut(&session_id) {
+                row.session.status = status.clone();
+            }
+            Ok(())
+        })
+    }
+
+    async fn session_list(
+        &self,
+        user_id: UserId,
+        pagination: PaginationQuery<SessionId>,
+    ) -> Result<PaginationResponse<Session>> {
+        let p: Pagination<_> = pagination.try_into()?;
+        Ok(self.read(|t| {
+            let sessions = t
+                .sessions
+                .values()
+                .filter(|s| s.session.status.user_id() == Some(user_id))
+                .map(|s| s.session.clone());
+            paginate(p, sessions, |s| s.id)
+        }))
+    }
+
+    async fn session_delete(&self, session_id: SessionId) -> Result<()> {
+        self.write(move |t| {
+            t.sessions.remove(&session_id);
+            Ok(())
+        })
+    }
+
+    async fn session_update(&self, session_id: SessionId, patch: SessionPatch) -> Result<()> {
+        self.write(move |t| {
+            let row = t.sessions.get_mut(&session_id).ok_or(Error::NotFound)?;
+            if let Some(name) = &patch.name {
+                row.session.name = name.clone();
+            }
+            Ok(())
+        })
+    }
+}
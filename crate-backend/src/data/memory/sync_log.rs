@@ -0,0 +1,185 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use common::v1::types::util::Time;
+use uuid::Uuid;
+
+use crate::data::DataSyncLog;
+use crate::error::{Error, Result};
+use crate::types::{DbMembership, MessageSync, SyncAudience, SyncEvent, SyncLogPosition, UserId};
+
+use super::{with_prefix, Memory, Tables};
+
+#[derive(Clone)]
+pub struct Event {
+    pub pos: u64,
+    pub payload: MessageSync,
+    pub created_at: Time,
+}
+
+impl Tables {
+    /// every user an event should be logged for
+    fn sync_audience(&self, audience: &[SyncAudience]) -> BTreeSet<UserId> {
+        let joined = |room_id| {
+            with_prefix(&self.room_members, room_id)
+                .filter(|(_, m)| m.membership == DbMembership::Join)
+                .map(|(user_id, _)| user_id)
+        };
+        let mut user_ids = BTreeSet::new();
+        for a in audience {
+            match a {
+                SyncAudience::Room(room_id) => user_ids.extend(joined(*room_id)),
+                SyncAudience::Thread(thread_id) => {
+                    if let Some(room_id) = self.threads.get(thread_id).and_then(|t| t.room_id) {
+                        user_ids.extend(joined(room_id));
+                    }
+                    user_ids.extend(
+                        with_prefix(&self.thread_members, *thread_id)
+                            .filter(|(_, m)| m.membership == DbMembership::Join)
+                            .map(|(user_id, _)| user_id),
+                    );
+                }
+                SyncAudience::User(user_id) => {
+                    user_ids.insert(*user_id);
+                }
+                SyncAudience::UserMutual(user_id) => {
+                    for ((room_id, u), m) in self.room_members.iter() {
+                        if u == user_id && m.membership == DbMembership::Join {
+                            user_ids.extend(joined(*room_id));
+                        }
+                    }
+                    user_ids.insert(*user_id);
+                }
+            }
+        }
+        user_ids
+    }
+}
+
+#[async_trait]
+impl DataSyncLog for Memory {
+    async fn sync_log_append(
+        &self,
+        events: Vec<(Uuid, MessageSync, Vec<SyncAudience>)>,
+    ) -> Result<Vec<SyncEvent>> {
+        let created_at = Time::now_utc();
+        self.write(move |t| {
+            let mut logged = Vec::with_capacity(events.len());
+            for (event_id, msg, audience) in &events {
+                *t.sync_event_pos += 1;
+                let event = Event {
+                    pos: *t.sync_event_pos,
+                    payload: msg.clone(),
+                    created_at: created_at.clone(),
+                };
+                t.sync_events.insert(*event_id, event);
+                let user_ids = t.sync_audience(audience);
+                let mut seqs = HashMap::new();
+                for user_id in user_ids {
+                    let pos = t.sync_log_seqs.entry(user_id).or_default();
+                    pos.seq += 1;
+                    t.sync_log.insert((user_id, pos.seq), *event_id);
+                    seqs.insert(user_id, pos.seq);
+                }
+                logged.push(SyncEvent {
+                    msg: msg.clone(),
+                    seqs: Some(Arc::new(seqs)),
+                });
+            }
+            Ok(logged)
+        })
+    }
+
+    async fn sync_log_get_many(&self, event_ids: &[Uuid]) -> Result<Vec<(Uuid, u64, SyncEvent)>> {
+        Ok(self.read(|t| {
+            event_ids
+                .iter()
+                .filter_map(|event_id| {
+                    let event = t.sync_events.get(event_id)?;
+                    let seqs = t
+                        .sync_log
+                        .iter()
+                        .filter(|(_, id)| *id == event_id)
+                        .map(|((user_id, seq), _)| (*user_id, *seq))
+                        .collect();
+                    let pos = event.pos;
+                    let event = SyncEvent {
+                        msg: event.payload.clone(),
+                        seqs: Some(Arc::new(seqs)),
+                    };
+                    Some((*event_id, pos, event))
+                })
+                .collect()
+        }))
+    }
+
+    async fn sync_log_list_after(&self, pos: u64) -> Result<Vec<Uuid>> {
+        Ok(self.read(|t| {
+            let mut events: Vec<_> = t
+                .sync_events
+                .iter()
+                .filter(|(_, e)| e.pos > pos)
+                .map(|(id, e)| (e.pos, *id))
+                .collect();
+            events.sort_unstable();
+            events.into_iter().map(|(_, id)| id).collect()
+        }))
+    }
+
+    async fn sync_log_last_pos(&self) -> Result<Option<u64>> {
+        Ok(self.read(|t| t.sync_events.values().map(|e| e.pos).max()))
+    }
+
+    async fn sync_log_replay(
+        &self,
+        user_id: UserId,
+        seq: u64,
+        limit: u16,
+    ) -> Result<Vec<(u64, MessageSync)>> {
+        Ok(self.read(|t| {
+            t.sync_log
+                .range((user_id, seq + 1)..=(user_id, u64::MAX))
+                .filter_map(|((_, seq), event_id)| {
+                    Some((*seq, t.sync_events.get(event_id)?.payload.clone()))
+                })
+                .take(limit as usize)
+                .collect()
+        }))
+    }
+
+    async fn sync_log_position(&self, user_id: UserId) -> Result<SyncLogPosition> {
+        Ok(self.read(|t| t.sync_log_seqs.get(&user_id).copied().unwrap_or_default()))
+    }
+
+    async fn sync_log_trim(&self, max_age: Duration) -> Result<()> {
+        let cutoff = Time::now_utc()
+            .into_inner()
+            .checked_sub(
+                max_age
+                    .try_into()
+                    .map_err(|_| Error::BadStatic("sync log max age is too large"))?,
+            )
+            .ok_or(Error::BadStatic("sync log max age is too large"))?;
+        self.write(move |t| {
+            let expired: BTreeSet<Uuid> = t
+                .sync_events
+                .iter()
+                .filter(|(_, e)| *e.created_at < cutoff)
+                .map(|(id, _)| *id)
+                .collect();
+            let sync_log_seqs = &mut t.sync_log_seqs;
+            t.sync_log.retain(|(user_id, seq), event_id| {
+                if !expired.contains(event_id) {
+                    return true;
+                }
+                let pos = sync_log_seqs.entry(*user_id).or_default();
+                pos.trimmed_seq = pos.trimmed_seq.max(*seq);
+                false
+            });
+            t.sync_events.retain(|id, _| !expired.contains(id));
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::BTreeSet;
+
+use async_trait::async_trait;
+use common::v1::types::tag::{Tag, TagCreate, TagPatch};
+use common::v1::types::{PaginationQuery, PaginationResponse, TagId, TagVerId};
+
+use crate::data::DataTag;
+use crate::error::{Error, Result};
+use crate::types::{RoomId, ThreadId, ThreadVerId};
+
+use super::{paginate, Memory, Pagination, Tables};
+
+impl Tables {
+    pub(super) fn tag(&self, tag_id: TagId) -> Option<Tag> {
+        let mut tag = self.tags.get(&tag_id)?.clone();
+        tag.tags = self
+            .tag_tags
+            .iter()
+            .filter(|(target_id, _)| *target_id == tag_id)
+            .map(|(_, tag_id)| *tag_id)
+            .collect();
+        Some(tag)
+    }
+
+    /// every tag implied by this tag, following tag composition transitively
+    pub(super) fn tag_implied(&self, tag_id: TagId) -> BTreeSet<TagId> {
+        let mut implied = BTreeSet::new();
+        let mut queue = vec![tag_id];
+        while let Some(target_id) = queue.pop() {
+            for (t, tag_id) in self.tag_tags.iter() {
+                if *t == target_id && implied.insert(*tag_id) {
+                    queue.push(*tag_id);
+                }
+            }
+        }
+        implied
+    }
+
+    /// the tags applied to a thread, including tags implied by them
+    pub(super) fn thread_tags_implied(&self, thread_id: ThreadId) -> BTreeSet<TagId> {
+        let mut tags = BTreeSet::new();
+        for (_, tag_id) in self.tag_threads.iter().filter(|(t, _)| *t == thread_id) {
+            tags.insert(*tag_id);
+            tags.extend(self.tag_implied(*tag_id));
+        }
+        tags
+    }
+
+    fn thread_bump(&mut self, thread_id: ThreadId, version_id: ThreadVerId) {
+        if let Some(thread) = self.threads.get_mut(&thread_id) {
+            thread.version_id = version_id;
+        }
+    }
+
+    fn tag_bump(&mut self, tag_id: TagId, version_id: TagVerId) {
+        if let Some(tag) = self.tags.get_mut(&tag_id) {
+            tag.version_id = version_id;
+        }
+    }
+}
+
+#[async_trait]
+impl DataTag for Memory {
+    async fn tag_create(&self, create: TagCreate) -> Result<Tag> {
+        let tag_id = TagId::new();
+        let tag = Tag {
+            id: tag_id,
+            version_id: tag_id.into_inner().into(),
+            room_id: create.room_id,
+            name: create.name,
+            description: create.description,
+            color: create.color,
+            is_archived: false,
+            tags: vec![],
+        };
+        let row = tag.clone();
+        self.write(move |t| {
+            if !t.rooms.contains_key(&row.room_id) {
+                return Err(Error::NotFound);
+            }
+            t.tags.insert(row.id, row.clone());
+            Ok(())
+        })?;
+        Ok(tag)
+    }
+
+    async fn tag_get(&self, tag_id: TagId) -> Result<Tag> {
+        self.read(|t| t.tag(tag_id)).ok_or(Error::NotFound)
+    }
+
+    async fn tag_list(
+        &self,
+        room_id: RoomId,
+        paginate_query: PaginationQuery<TagId>,
+    ) -> Result<PaginationResponse<Tag>> {
+        let p: Pagination<_> = paginate_query.try_into()?;
+        self.read(|t| {
+            let tags = t
+                .tags
+                .values()
+                .filter(|tag| tag.room_id == room_id)
+                .filter_map(|tag| t.tag(tag.id));
+            Ok(paginate(p, tags, |tag| tag.id))
+        })
+    }
+
+    async fn tag_update(&self, tag_id: TagId, patch: TagPatch) -> Result<TagVerId> {
+        let version_id = TagVerId::new();
+        self.write(move |t| {
+            let tag = t.tags.get_mut(&tag_id).ok_or(Error::NotFound)?;
+            tag.version_id = version_id;
+            if let Some(name) = &patch.name {
+                tag.name = name.clone();
+            }
+            if let Some(description) = &patch.description {
+                tag.description = description.clone();
+            }
+            if let Some(color) = &patch.color {
+                tag.color = color.clone();
+            }
+            if let Some(is_archived) = patch.is_archived {
+                tag.is_archived = is_archived;
+            }
+            Ok(version_id)
+        })
+    }
+
+    async fn tag_delete(&self, tag_id: TagId) -> Result<()> {
+        self.write(move |t| {
+            t.tag_threads.retain(|(_, tag)| *tag != tag_id);
+            t.tag_tags
+                .retain(|(target, tag)| *target != tag_id && *tag != tag_id);
+            t.tags.remove(&tag_id);
+            Ok(())
+        })
+    }
+
+    async fn tag_thread_count(&self, tag_id: TagId) -> Result<u64> {
+        Ok(self.read(|t| {
+            t.tag_threads
+                .iter()
+                .filter(|(_, tag)| *tag == tag_id)
+                .count()
+        }) as u64)
+    }
+
+    async fn tag_thread_set(&self, thread_id: ThreadId, tag_ids: &[TagId]) -> Result<()> {
+        let tag_ids = tag_ids.to_vec();
+        let version_id = ThreadVerId::new();
+        self.write(move |t| {
+            if tag_ids.iter().any(|tag_id| !t.tags.contains_key(tag_id)) {
+                return Err(Error::NotFound);
+            }
+            t.tag_threads
+                .retain(|(thread, tag)| *thread != thread_id || tag_ids.contains(tag));
+            for tag_id in &tag_ids {
+                t.tag_threads.insert((thread_id, *tag_id));
+            }
+            t.thread_bump(thread_id, version_id);
+            Ok(())
+        })
+    }
+
+    async fn tag_thread_apply(&self, thread_id: ThreadId, tag_id: TagId) -> Result<()> {
+        let version_id = ThreadVerId::new();
+        self.write(move |t| {
+            if !t.tags.contains_key(&tag_id) || !t.threads.contains_key(&thread_id) {
+                return Err(Error::NotFound);
+            }
+            t.tag_threads.insert((thread_id, tag_id));
+            t.thread_bump(thread_id, version_id);
+            Ok(())
+        })
+    }
+
+    async fn tag_thread_unapply(&self, thread_id: ThreadId, tag_id: TagId) -> Result<()> {
+        let version_id = ThreadVerId::new();
+        self.write(move |t| {
+            t.tag_threads.remove(&(thread_id, tag_id));
+            t.thread_bump(thread_id, version_id);
+            Ok(())
+        })
+    }
+
+    async fn tag_tag_apply(&self, target_id: TagId, tag_id: TagId) -> Result<()> {
+        let version_id = TagVerId::new();
+        self.write(move |t| {
+            if !t.tags.contains_key(&tag_id) || !t.tags.contains_key(&target_id) {
+                return Err(Error::NotFound);
+            }
+            t.tag_tags.insert((target_id, tag_id));
+            t.tag_bump(target_id, version_id);
+            Ok(())
+        })
+    }
+
+    async fn tag_tag_unapply(&self, target_id: TagId, tag_id: TagId) -> Result<()> {
+        let version_id = TagVerId::new();
+        self.write(move |t| {
+            t.tag_tags.remove(&(target_id, tag_id));
+            t.tag_bump(target_id, version_id);
+            Ok(())
+        })
+    }
+
+    async fn tag_implied(&self, tag_id: TagId) -> Result<Vec<TagId>> {
+        Ok(self.read(|t| t.tag_implied(tag_id)).into_iter().collect())
+    }
+}
@@ -0,0 +1,258 @@
+use async_trait::async_trait;
+use common::v1::types::util::Time;
+use common::v1::types::{moderation::ReportDestination, ThreadPrivate};
+
+use crate::data::DataThread;
+use crate::error::{Error, Result};
+use crate::types::{
+    DbThread, DbThreadCreate, DbThreadPrivate, DbThreadType, PaginationQuery, PaginationResponse,
+    RoomId, TagId, Thread, ThreadId, ThreadPatch, ThreadVerId, UserId,
+};
+
+use super::{paginate, Memory, Pagination, Tables};
+
+#[derive(Clone)]
+pub struct Row {
+    pub room_id: Option<RoomId>,
+    pub creator_id: UserId,
+    pub version_id: ThreadVerId,
+    pub name: String,
+    pub description: Option<String>,
+    pub ty: DbThreadType,
+    pub archived_at: Option<Time>,
+    pub deleted_at: Option<Time>,
+}
+
+impl Tables {
+    /// get a thread, returns None if there are no messages
+    pub(super) fn thread(&self, thread_id: ThreadId) -> Option<Thread> {
+        let row = self.threads.get(&thread_id)?;
+        let messages: Vec<_> = self
+            .messages
+            .values()
+            .filter(|m| m.message.thread_id == thread_id)
+            .collect();
+        let last_version_id = messages
+            .iter()
+            .filter(|m| m.message.deleted_at.is_none())
+            .map(|m| m.message.version_id)
+            .max()?;
+        let overwrites: Vec<_> = self
+            .permission_overwrites
+            .iter()
+            .filter(|((target_id, _), _)| *target_id == *thread_id)
+            .map(|(_, o)| o)
+            .collect();
+        let report = self.report_threads.get(&thread_id).map(|r| {
+            serde_json::json!({
+                "target": r.target,
+                "destination": r.destination,
+                "status": r.status,
+                "report_count": self.reports.values().filter(|r| r.thread_id == thread_id).count(),
+            })
+        });
+        Some(
+            DbThread {
+                id: thread_id,
+                room_id: row.room_id.map(|id| *id),
+                creator_id: row.creator_id,
+                version_id: row.version_id,
+                name: row.name.clone(),
+                description: row.description.clone(),
+                ty: row.ty,
+                last_version_id,
+                message_count: messages.iter().filter(|m| m.is_latest).count() as i64,
+                permission_overwrites: serde_json::to_value(overwrites).ok()?,
+                tags: self
+                    .tag_threads
+                    .iter()
+                    .filter(|(t, _)| *t == thread_id)
+                    .map(|(_, tag_id)| **tag_id)
+                    .collect(),
+                report,
+            }
+            .into(),
+        )
+    }
+
+    fn thread_private(&self, thread_id: ThreadId, user_id: UserId) -> Option<ThreadPrivate> {
+        let row = self.threads.get(&thread_id)?;
+        if !self.users.contains_key(&user_id) {
+            return None;
+        }
+        let last_version_id = self
+            .messages
+            .values()
+            .filter(|m| m.message.thread_id == thread_id && m.message.deleted_at.is_none())
+            .map(|m| m.message.version_id)
+            .max()?;
+        let unread = self.unreads.get(&(thread_id, user_id));
+        let mention_count = self
+            .thread_messages(thread_id)
+            .filter(|m| m.message.deleted_at.is_none())
+            .filter(|m| unread.is_none_or(|u| m.message.id > u.message_id))
+            .filter(|m| {
+                let mentions = &m.message.mentions;
+                mentions.users.contains(&user_id) || mentions.all_in_thread || mentions.all_in_room
+            })
+            .count();
+        Some(
+            DbThreadPrivate {
+                id: thread_id,
+                ty: row.ty,
+                last_read_id: unread.map(|u| *u.message_id),
+                is_unread: unread.is_none_or(|u| u.version_id < last_version_id),
+                mention_count: mention_count as i64,
+                notifications: self
+                    .notification_config_thread
+                    .get(&(user_id, thread_id))
+                    .and_then(|c| serde_json::to_value(c).ok()),
+            }
+            .into(),
+        )
+    }
+
+    /// update a thread, bumping its version
+    fn thread_set(
+        &mut self,
+        thread_id: ThreadId,
+        version_id: ThreadVerId,
+        f: impl FnOnce(&mut Row),
+    ) -> Result<()> {
+        let thread = self.threads.get_mut(&thread_id).ok_or(Error::NotFound)?;
+        thread.version_id = version_id;
+        f(thread);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DataThread for Memory {
+    async fn thread_create(&self, create: DbThreadCreate) -> Result<ThreadId> {
+        let thread_id = ThreadId::new();
+        let row = Row {
+            room_id: create.room_id.map(Into::into),
+            creator_id: create.creator_id,
+            version_id: thread_id.into_inner().into(),
+            name: create.name,
+            description: create.description,
+            ty: create.ty,
+            archived_at: None,
+            deleted_at: None,
+        };
+        self.write(move |t| {
+            if row.room_id.is_some_and(|id| !t.rooms.contains_key(&id))
+                || !t.users.contains_key(&row.creator_id)
+            {
+                return Err(Error::NotFound);
+            }
+            t.threads.insert(thread_id, row.clone());
+            Ok(())
+        })?;
+        Ok(thread_id)
+    }
+
+    async fn thread_get(&self, thread_id: ThreadId) -> Result<Thread> {
+        self.read(|t| t.thread(thread_id)).ok_or(Error::NotFound)
+    }
+
+    async fn thread_list(
+        &self,
+        room_id: RoomId,
+        user_id: UserId,
+        pagination: PaginationQuery<ThreadId>,
+        tag_id: Option<TagId>,
+        reports: Vec<ReportDestination>,
+    ) -> Result<PaginationResponse<Thread>> {
+        let p: Pagination<_> = pagination.try_into()?;
+        Ok(self.read(|t| {
+            let ids: Vec<ThreadId> = t
+                .threads
+                .iter()
+                .filter(|(_, thread)| {
+                    thread.room_id == Some(room_id) && thread.deleted_at.is_none()
+                })
+                .map(|(id, _)| *id)
+                .filter(|id| {
+                    tag_id.is_none_or(|tag_id| t.thread_tags_implied(*id).contains(&tag_id))
+                })
+                .filter(|id| match t.report_threads.get(id) {
+                    Some(report) => reports.contains(&report.destination),
+                    None => t.is_viewable_thread(user_id, *id),
+                })
+                .collect();
+            let total = ids.len() as u64;
+            let threads = ids.into_iter().filter_map(|id| t.thread(id));
+            let mut res = paginate(p, threads, |thread| thread.id);
+            res.total = total;
+            res
+        }))
+    }
+
+    async fn thread_get_private(
+        &self,
+        thread_id: ThreadId,
+        user_id: UserId,
+    ) -> Result<ThreadPrivate> {
+        self.read(|t| t.thread_private(thread_id, user_id))
+            .ok_or(Error::NotFound)
+    }
+
+    async fn thread_get_private_many(
+        &self,
+        thread_ids: &[ThreadId],
+        user_id: UserId,
+    ) -> Result<Vec<(ThreadId, ThreadPrivate)>> {
+        Ok(self.read(|t| {
+            thread_ids
+                .iter()
+                .filter_map(|id| Some((*id, t.thread_private(*id, user_id)?)))
+                .collect()
+        }))
+    }
+
+    async fn thread_update(&self, thread_id: ThreadId, patch: ThreadPatch) -> Result<ThreadVerId> {
+        let version_id = ThreadVerId::new();
+        self.write(move |t| {
+            t.thread_set(thread_id, version_id, |thread| {
+                if let Some(name) = &patch.name {
+                    thread.name = name.clone();
+                }
+                if let Some(description) = &patch.description {
+                    thread.description = description.clone();
+                }
+            })
+        })?;
+        Ok(version_id)
+    }
+
+    async fn thread_delete(&self, thread_id: ThreadId, _user_id: UserId) -> Result<()> {
+        let version_id = ThreadVerId::new();
+        let now = Time::now_utc();
+        self.write(move |t| {
+            t.thread_set(thread_id, version_id, |thread| {
+                thread.deleted_at = Some(now.clone())
+            })
+        })
+    }
+
+    async fn thread_undelete(&self, thread_id: ThreadId, _user_id: UserId) -> Result<()> {
+        let version_id = ThreadVerId::new();
+        self.write(move |t| t.thread_set(thread_id, version_id, |thread| thread.deleted_at = None))
+    }
+
+    async fn thread_archive(&self, thread_id: ThreadId, _user_id: UserId) -> Result<()> {
+        let version_id = ThreadVerId::new();
+        let now = Time::now_utc();
+        self.write(move |t| {
+            t.thread_set(thread_id, version_id, |thread| {
+                thread.archived_at = Some(now.clone())
+            })
+        })
+    }
+
+    async fn thread_unarchive(&self, thread_id: ThreadId, _user_id: UserId) -> Result<()> {
+        let version_id = ThreadVerId::new();
+        self.write(move |t| t.thread_set(thread_id, version_id, |thread| thread.archived_at = None))
+    }
+}
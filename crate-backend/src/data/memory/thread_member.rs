@@ -0,0 +1,135 @@
+use async_trait::async_trait;
+use common::v1::types::util::Time;
+use common::v1::types::{
+    PaginationQuery, PaginationResponse, ThreadId, ThreadMember, ThreadMemberPatch,
+    ThreadMembership, UserId,
+};
+
+use crate::data::DataThreadMember;
+use crate::error::{Error, Result};
+use crate::types::DbMembership;
+
+use super::room_member::Row;
+use super::{paginate, with_prefix, Memory, Pagination, Tables};
+
+impl Tables {
+    pub(super) fn thread_member(
+        &self,
+        thread_id: ThreadId,
+        user_id: UserId,
+    ) -> Option<ThreadMember> {
+        let row = self.thread_members.get(&(thread_id, user_id))?;
+        Some(ThreadMember {
+            user_id,
+            thread_id,
+            membership: match row.membership {
+                DbMembership::Join => ThreadMembership::Join {
+                    override_name: row.override_name.clone(),
+                    override_description: row.override_description.clone(),
+                },
+                DbMembership::Leave => ThreadMembership::Leave {},
+                DbMembership::Ban => ThreadMembership::Ban {},
+            },
+            membership_updated_at: row.membership_updated_at.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl DataThreadMember for Memory {
+    async fn thread_member_put(
+        &self,
+        thread_id: ThreadId,
+        user_id: UserId,
+        membership: ThreadMembership,
+    ) -> Result<()> {
+        let membership: DbMembership = membership.into();
+        let now = Time::now_utc();
+        self.write(move |t| {
+            if !t.threads.contains_key(&thread_id) || !t.users.contains_key(&user_id) {
+                return Err(Error::NotFound);
+            }
+            t.thread_members
+                .entry((thread_id, user_id))
+                .and_modify(|m| m.membership = membership)
+                .or_insert_with(|| Row {
+                    membership,
+                    override_name: None,
+                    override_description: None,
+                    membership_updated_at: now.clone(),
+                });
+            Ok(())
+        })
+    }
+
+    async fn thread_member_patch(
+        &self,
+        thread_id: ThreadId,
+        user_id: UserId,
+        patch: ThreadMemberPatch,
+    ) -> Result<()> {
+        self.write(move |t| {
+            let member = t
+                .thread_members
+                .get_mut(&(thread_id, user_id))
+                .ok_or(Error::NotFound)?;
+            if member.membership != DbMembership::Join {
+                return Ok(());
+            }
+            if let Some(override_name) = &patch.override_name {
+                member.override_name = override_name.clone();
+            }
+            if let Some(override_description) = &patch.override_description {
+                member.override_description = override_description.clone();
+            }
+            Ok(())
+        })
+    }
+
+    async fn thread_member_set_membership(
+        &self,
+        thread_id: ThreadId,
+        user_id: UserId,
+        membership: ThreadMembership,
+    ) -> Result<()> {
+        let membership: DbMembership = membership.into();
+        let now = Time::now_utc();
+        self.write(move |t| {
+            if let Some(member) = t.thread_members.get_mut(&(thread_id, user_id)) {
+                member.membership = membership;
+                member.membership_updated_at = now.clone();
+            }
+            Ok(())
+        })
+    }
+
+    async fn thread_member_delete(&self, thread_id: ThreadId, user_id: UserId) -> Result<()> {
+        self.write(move |t| {
+            t.thread_members.remove(&(thread_id, user_id));
+            Ok(())
+        })
+    }
+
+    async fn thread_member_get(
+        &self,
+        thread_id: ThreadId,
+        user_id: UserId,
+    ) -> Result<ThreadMember> {
+        self.read(|t| t.thread_member(thread_id, user_id))
+            .ok_or(Error::NotFound)
+    }
+
+    async fn thread_member_list(
+        &self,
+        thread_id: ThreadId,
+        pagination: PaginationQuery<UserId>,
+    ) -> Result<PaginationResponse<ThreadMember>> {
+        let p: Pagination<_> = pagination.try_into()?;
+        self.read(|t| {
+            let members = with_prefix(&t.thread_members, thread_id)
+                .filter(|(_, m)| m.membership == DbMembership::Join)
+                .filter_map(|(user_id, _)| t.thread_member(thread_id, user_id));
+            Ok(paginate(p, members, |m| m.user_id))
+        })
+    }
+}
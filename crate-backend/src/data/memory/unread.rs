@@ -0,0 +1,37 @@
+use async_trait::async_trait;
+use common::v1::types::MessageId;
+
+use crate::error::Result;
+use crate::types::{MessageVerId, ThreadId, UserId};
+
+use crate::data::DataUnread;
+
+use super::Memory;
+
+#[derive(Clone)]
+pub struct Row {
+    pub message_id: MessageId,
+    pub version_id: MessageVerId,
+}
+
+#[async_trait]
+impl DataUnread for Memory {
+    async fn unread_put(
+        &self,
+        user_id: UserId,
+        thread_id: ThreadId,
+        message_id: MessageId,
+        version_id: MessageVerId,
+    ) -> Result<()> {
+        self.write(move |t| {
+            t.unreads.insert(
+                (thread_id, user_id),
+                Row {
+                    message_id,
+                    version_id,
+                },
+            );
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,142 @@
+use async_trait::async_trait;
+use common::v1::types::user::Suspended;
+use common::v1::types::user_config::UserConfig;
+use common::v1::types::user_status::Status;
+use common::v1::types::util::Time;
+
+use crate::error::{Error, Result};
+use crate::types::{DbUserCreate, User, UserId, UserPatch, UserVerId};
+
+use crate::data::DataUser;
+
+use super::{Memory, Tables};
+
+#[derive(Clone)]
+pub struct Row {
+    pub user: User,
+    pub parent_id: Option<UserId>,
+    pub config: Option<UserConfig>,
+
+    /// the password hash and salt
+    pub password: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Tables {
+    pub(super) fn user(&self, user_id: UserId) -> Option<User> {
+        let mut user = self.users.get(&user_id)?.user.clone();
+        user.status = Status::offline();
+        Some(user)
+    }
+}
+
+#[async_trait]
+impl DataUser for Memory {
+    async fn user_create(&self, patch: DbUserCreate) -> Result<User> {
+        let user_id = UserId::new();
+        let row = Row {
+            user: User {
+                id: user_id,
+                version_id: user_id.into_inner().into(),
+                name: patch.name,
+                description: patch.description,
+                avatar: None,
+                status: Status::offline(),
+                bot: patch.bot,
+                system: false,
+                puppet: patch.puppet,
+                suspended: None,
+                registered_at: patch.registered_at,
+                deleted_at: None,
+            },
+            parent_id: patch.parent_id,
+            config: None,
+            password: None,
+        };
+        self.write(move |t| {
+            t.users.insert(user_id, row.clone());
+            Ok(())
+        })?;
+        self.user_get(user_id).await
+    }
+
+    async fn user_update(&self, user_id: UserId, patch: UserPatch) -> Result<UserVerId> {
+        let version_id = UserVerId::new();
+        self.write(move |t| {
+            let user = &mut t.users.get_mut(&user_id).ok_or(Error::NotFound)?.user;
+            user.version_id = version_id;
+            if let Some(name) = &patch.name {
+                user.name = name.clone();
+            }
+            if let Some(description) = &patch.description {
+                user.description = description.clone();
+            }
+            if let Some(avatar) = patch.avatar {
+                user.avatar = avatar;
+            }
+            Ok(version_id)
+        })
+    }
+
+    async fn user_delete(&self, user_id: UserId) -> Result<()> {
+        let now = Time::now_utc();
+        self.write(move |t| {
+            if let Some(row) = t.users.get_mut(&user_id) {
+                row.user.deleted_at = Some(now.clone());
+            }
+            Ok(())
+        })
+    }
+
+    async fn user_get(&self, id: UserId) -> Result<User> {
+        self.read(|t| t.user(id)).ok_or(Error::NotFound)
+    }
+
+    async fn user_lookup_puppet(
+        &self,
+        owner_id: UserId,
+        external_id: &str,
+    ) -> Result<Option<UserId>> {
+        Ok(self.read(|t| {
+            t.users
+                .values()
+                .find(|row| {
+                    row.parent_id == Some(owner_id)
+                        && row
+                            .user
+                            .puppet
+                            .as_ref()
+                            .is_some_and(|p| p.external_id == external_id)
+                })
+                .map(|row| row.user.id)
+        }))
+    }
+
+    async fn user_set_registered_at(
+        &self,
+        user_id: UserId,
+        registered_at: Option<Time>,
+    ) -> Result<UserVerId> {
+        let version_id = UserVerId::new();
+        self.write(move |t| {
+            if let Some(row) = t.users.get_mut(&user_id) {
+                row.user.version_id = version_id;
+                row.user.registered_at = registered_at.clone();
+            }
+            Ok(version_id)
+        })
+    }
+
+    async fn user_set_suspended(
+        &self,
+        user_id: UserId,
+        suspended: Option<Suspended>,
+    ) -> Result<UserVerId> {
+        let version_id = UserVerId::new();
+        self.write(move |t| {
+            let row = t.users.get_mut(&user_id).ok_or(Error::NotFound)?;
+            row.user.version_id = version_id;
+            row.user.suspended = suspended.clone();
+            Ok(version_id)
+        })
+    }
+}
@@ -0,0 +1,28 @@
+use async_trait::async_trait;
+use common::v1::types::user_config::UserConfig;
+
+use crate::error::{Error, Result};
+use crate::types::UserId;
+
+use crate::data::DataUserConfig;
+
+use super::Memory;
+
+#[async_trait]
+impl DataUserConfig for Memory {
+    async fn user_config_set(&self, user_id: UserId, config: &UserConfig) -> Result<()> {
+        let config = config.clone();
+        self.write(move |t| {
+            if let Some(row) = t.users.get_mut(&user_id) {
+                row.config = Some(config.clone());
+            }
+            Ok(())
+        })
+    }
+
+    async fn user_config_get(&self, user_id: UserId) -> Result<UserConfig> {
+        self.read(|t| t.users.get(&user_id).map(|row| row.config.clone()))
+            .map(Option::unwrap_or_default)
+            .ok_or(Error::NotFound)
+    }
+}
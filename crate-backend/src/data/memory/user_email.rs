@@ -0,0 +1,158 @@
+use async_trait::async_trait;
+use common::v1::types::email::{EmailAddr, EmailInfo};
+use common::v1::types::util::Time;
+use time::Duration;
+use uuid::Uuid;
+
+use crate::data::DataUserEmail;
+use crate::error::{Error, Result};
+use crate::types::UserId;
+
+use super::Memory;
+
+#[derive(Clone)]
+pub struct Row {
+    pub user_id: UserId,
+    pub info: EmailInfo,
+}
+
+#[derive(Clone)]
+pub struct Verification {
+    pub user_id: UserId,
+    pub addr: String,
+    pub code: String,
+    pub expires_at: Time,
+}
+
+#[async_trait]
+impl DataUserEmail for Memory {
+    async fn user_email_add(
+        &self,
+        user_id: UserId,
+        email: EmailInfo,
+        max_user_emails: usize,
+    ) -> Result<()> {
+        self.write(move |t| {
+            let addr = email.email.as_ref();
+            if t.user_emails
+                .iter()
+                .filter(|e| e.user_id == user_id)
+                .count()
+                >= max_user_emails
+            {
+                return Err(Error::BadRequest(
+                    "Maximum number of email addresses reached.".to_string(),
+                ));
+            }
+            if t.user_emails
+                .iter()
+                .any(|e| e.info.email.as_ref() == addr && e.info.is_verified)
+            {
+                return Err(Error::BadRequest(
+                    "Email address is already in use.".to_string(),
+                ));
+            }
+            if t.user_emails
+                .iter()
+                .any(|e| e.user_id == user_id && e.info.email.as_ref() == addr)
+            {
+                return Err(Error::EmailAlreadyExists);
+            }
+            t.user_emails.push(Row {
+                user_id,
+                info: email.clone(),
+            });
+            Ok(())
+        })
+    }
+
+    async fn user_email_delete(&self, user_id: UserId, email_addr: EmailAddr) -> Result<()> {
+        self.write(move |t| {
+            let len = t.user_emails.len();
+            t.user_emails
+                .retain(|e| e.user_id != user_id || e.info.email != email_addr);
+            if t.user_emails.len() == len {
+                return Err(Error::NotFound);
+            }
+            Ok(())
+        })
+    }
+
+    async fn user_email_list(&self, user_id: UserId) -> Result<Vec<EmailInfo>> {
+        Ok(self.read(|t| {
+            t.user_emails
+                .iter()
+                .filter(|e| e.user_id == user_id)
+                .map(|e| e.info.clone())
+                .collect()
+        }))
+    }
+
+    async fn user_email_verify_use(
+        &self,
+        user_id: UserId,
+        email_addr: EmailAddr,
+        code: String,
+    ) -> Result<()> {
+        let now = Time::now_utc();
+        self.write(move |t| {
+            let addr = email_addr.as_ref();
+            if t.user_emails.iter().any(|e| {
+                e.info.email.as_ref() == addr && e.info.is_verified && e.user_id != user_id
+            }) {
+                return Err(Error::BadRequest(
+                    "Email address is already in use.".to_string(),
+                ));
+            }
+            let is_valid = t.email_verifications.iter().any(|v| {
+                v.user_id == user_id && v.addr == addr && v.code == code && now < v.expires_at
+            });
+            if !is_valid {
+                return Err(Error::InvalidCredentials);
+            }
+            for e in t.user_emails.iter_mut() {
+                if e.user_id == user_id && e.info.email.as_ref() == addr {
+                    e.info.is_verified = true;
+                }
+            }
+            // other users can't verify this address anymore
+            t.user_emails.retain(|e| {
+                e.info.email.as_ref() != addr || e.info.is_verified || e.user_id == user_id
+            });
+            t.email_verifications
+                .retain(|v| v.user_id != user_id || v.addr != addr || v.code != code);
+            Ok(())
+        })
+    }
+
+    async fn user_email_verify_create(
+        &self,
+        user_id: UserId,
+        email_addr: EmailAddr,
+    ) -> Result<String> {
+        let code = ((Uuid::new_v4().as_u128() % 900_000) + 100_000).to_string();
+        let verification = Verification {
+            user_id,
+            addr: email_addr.into_inner(),
+            code: code.clone(),
+            expires_at: Time::now_utc()
+                .into_inner()
+                .saturating_add(Duration::minutes(15))
+                .into(),
+        };
+        self.write(move |t| {
+            if !t
+                .user_emails
+                .iter()
+                .any(|e| e.user_id == user_id && e.info.email.as_ref() == verification.addr)
+            {
+                return Err(Error::NotFound);
+            }
+            t.email_verifications
+                .retain(|v| v.user_id != user_id || v.addr != verification.addr);
+            t.email_verifications.push(verification.clone());
+            Ok(())
+        })?;
+        Ok(code)
+    }
+}
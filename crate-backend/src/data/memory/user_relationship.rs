@@ -0,0 +1,82 @@
+use async_trait::async_trait;
+use common::v1::types::{
+    PaginationQuery, PaginationResponse, Relationship, RelationshipPatch, RelationshipWithUserId,
+};
+
+use crate::error::Result;
+use crate::types::UserId;
+
+use crate::data::DataUserRelationship;
+
+use super::{paginate, with_prefix, Memory, Pagination};
+
+#[async_trait]
+impl DataUserRelationship for Memory {
+    async fn user_relationship_put(
+        &self,
+        user_id: UserId,
+        other_id: UserId,
+        rel: Relationship,
+    ) -> Result<()> {
+        self.write(move |t| {
+            t.relationships.insert((user_id, other_id), rel.clone());
+            Ok(())
+        })
+    }
+
+    async fn user_relationship_edit(
+        &self,
+        user_id: UserId,
+        other_id: UserId,
+        patch: RelationshipPatch,
+    ) -> Result<()> {
+        self.write(move |t| {
+            let rel = t.relationships.entry((user_id, other_id)).or_default();
+            if let Some(note) = &patch.note {
+                rel.note = note.clone();
+            }
+            if let Some(relation) = &patch.relation {
+                rel.relation = relation.clone();
+            }
+            if let Some(petname) = &patch.petname {
+                rel.petname = petname.clone();
+            }
+            if let Some(ignore) = &patch.ignore {
+                rel.ignore = ignore.clone();
+            }
+            Ok(())
+        })
+    }
+
+    async fn user_relationship_delete(&self, user_id: UserId, other_id: UserId) -> Result<()> {
+        self.write(move |t| {
+            t.relationships.remove(&(user_id, other_id));
+            Ok(())
+        })
+    }
+
+    async fn user_relationship_get(
+        &self,
+        user_id: UserId,
+        other_id: UserId,
+    ) -> Result<Option<Relationship>> {
+        Ok(self.read(|t| t.relationships.get(&(user_id, other_id)).cloned()))
+    }
+
+    async fn user_relationship_list(
+        &self,
+        user_id: UserId,
+        pagination: PaginationQuery<UserId>,
+    ) -> Result<PaginationResponse<RelationshipWithUserId>> {
+        let p: Pagination<_> = pagination.try_into()?;
+        Ok(self.read(|t| {
+            let rels = with_prefix(&t.relationships, user_id).map(|(other_id, rel)| {
+                RelationshipWithUserId {
+                    user_id: other_id,
+                    inner: rel.clone(),
+                }
+            });
+            paginate(p, rels, |r| r.user_id)
+        }))
+    }
+}
@@ -0,0 +1,140 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use common::v1::types::util::Time;
+use uuid::Uuid;
+
+use crate::data::DataVoice;
+use crate::error::{Error, Result};
+use crate::types::{DbSfu, DbVoiceModeration, RoomId, ThreadId, UserId};
+
+use super::Memory;
+
+#[derive(Clone)]
+pub struct Row {
+    pub sfu: DbSfu,
+    pub seen_at: Time,
+}
+
+#[async_trait]
+impl DataVoice for Memory {
+    async fn sfu_upsert(&self, sfu: DbSfu) -> Result<()> {
+        self.write(move |t| {
+            // another token's sfu can't be taken over
+            let taken = t.sfus.values().any(|row| {
+                (row.sfu.id == sfu.id || row.sfu.url == sfu.url)
+                    && row.sfu.token_name != sfu.token_name
+            });
+            if taken {
+                return Err(Error::MissingPermissions);
+            }
+            // the same sfu gets a new id when it restarts
+            let replaced: Vec<Uuid> = t
+                .sfus
+                .values()
+                .filter(|row| row.sfu.url == sfu.url && row.sfu.id != sfu.id)
+                .map(|row| row.sfu.id)
+                .collect();
+            for id in &replaced {
+                t.sfus.remove(id);
+            }
+            t.sfu_threads.retain(|_, sfu_id| !replaced.contains(sfu_id));
+            t.sfus.insert(
+                sfu.id,
+                Row {
+                    sfu: sfu.clone(),
+                    seen_at: Time::now_utc(),
+                },
+            );
+            Ok(())
+        })
+    }
+
+    async fn sfu_list(&self, max_age: Duration) -> Result<Vec<DbSfu>> {
+        let cutoff = Time::now_utc()
+            .into_inner()
+            .checked_sub(
+                max_age
+                    .try_into()
+                    .map_err(|_| Error::BadStatic("sfu max age is too large"))?,
+            )
+            .ok_or(Error::BadStatic("sfu max age is too large"))?;
+        Ok(self.read(|t| {
+            t.sfus
+                .values()
+                .filter(|row| *row.seen_at > cutoff)
+                .map(|row| row.sfu.clone())
+                .collect()
+        }))
+    }
+
+    async fn sfu_thread_get(&self, thread_id: ThreadId) -> Result<Option<Uuid>> {
+        Ok(self.read(|t| t.sfu_threads.get(&thread_id).copied()))
+    }
+
+    async fn sfu_thread_set(
+        &self,
+        thread_id: ThreadId,
+        sfu_id: Uuid,
+        replace: Option<Uuid>,
+    ) -> Result<Uuid> {
+        self.write(move |t| {
+            if !t.sfus.contains_key(&sfu_id) {
+                return Err(Error::NotFound);
+            }
+            let current = t.sfu_threads.entry(thread_id).or_insert(sfu_id);
+            if Some(*current) == replace {
+                *current = sfu_id;
+            }
+            Ok(*current)
+        })
+    }
+
+    async fn voice_moderation_get(
+        &self,
+        room_id: RoomId,
+        user_id: UserId,
+    ) -> Result<DbVoiceModeration> {
+        Ok(self.read(|t| {
+            t.voice_moderation
+                .get(&(room_id, user_id))
+                .copied()
+                .unwrap_or_default()
+        }))
+    }
+
+    async fn voice_moderation_set(
+        &self,
+        room_id: RoomId,
+        user_id: UserId,
+        moderation: DbVoiceModeration,
+    ) -> Result<()> {
+        self.write(move |t| {
+            if !t.room_members.contains_key(&(room_id, user_id)) {
+                return Err(Error::NotFound);
+            }
+            t.voice_moderation.insert((room_id, user_id), moderation);
+            Ok(())
+        })
+    }
+
+    async fn voice_member_get(&self, user_id: UserId) -> Result<Option<ThreadId>> {
+        Ok(self.read(|t| t.voice_members.get(&user_id).copied()))
+    }
+
+    async fn voice_member_set(&self, user_id: UserId, thread_id: ThreadId) -> Result<()> {
+        self.write(move |t| {
+            t.voice_members.insert(user_id, thread_id);
+            Ok(())
+        })
+    }
+
+    async fn voice_member_delete(&self, user_id: UserId, thread_id: ThreadId) -> Result<()> {
+        self.write(move |t| {
+            if t.voice_members.get(&user_id) == Some(&thread_id) {
+                t.voice_members.remove(&user_id);
+            }
+            Ok(())
+        })
+    }
+}
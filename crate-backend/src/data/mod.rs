@@ -1,36 +1,55 @@
+use std::time::Duration;
+
 use async_trait::async_trait;
 use common::v1::types::application::Application;
+use common::v1::types::auth::TotpRecoveryCode;
 use common::v1::types::email::{EmailAddr, EmailInfo};
 use common::v1::types::emoji::{EmojiCustom, EmojiCustomCreate, EmojiCustomPatch};
+use common::v1::types::moderation::{
+    Report, ReportCreate, ReportDestination, ReportStatus, ReportTarget,
+};
+use common::v1::types::notifications::{Notification, NotifsRoom, NotifsThread};
 use common::v1::types::reaction::{ReactionKey, ReactionListItem};
-use common::v1::types::search::SearchMessageRequest;
+use common::v1::types::search::{SearchMessageRequest, SearchRoomsRequest, SearchThreadsRequest};
+use common::v1::types::tag::{Tag, TagCreate, TagPatch};
+use common::v1::types::user::Suspended;
 use common::v1::types::user_config::UserConfig;
 use common::v1::types::{
     ApplicationId, AuditLog, AuditLogId, Embed, EmojiId, InvitePatch, InviteWithMetadata,
-    MediaPatch, MessageSync, Permission, PermissionOverwriteType, Relationship, RelationshipPatch,
-    RelationshipWithUserId, Role, RoomMember, RoomMemberPatch, RoomMembership, SessionPatch,
-    SessionStatus, SessionToken, ThreadMember, ThreadMemberPatch, ThreadMembership, ThreadPrivate,
+    MediaPatch, MessageSync, NotificationId, Permission, PermissionOverwriteType, Relationship,
+    RelationshipPatch, RelationshipWithUserId, ReportId, Role, RoomBan, RoomMember,
+    RoomMemberPatch, RoomMembership, SessionPatch, SessionStatus, SessionToken, TagId, TagVerId,
+    ThreadMember, ThreadMemberPatch, ThreadMembership, ThreadPrivate,
 };
 
+use sqlx::postgres::PgPoolOptions;
+use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::types::{
-    DbEmailQueue, DbMessageCreate, DbRoleCreate, DbThreadCreate, DbUserCreate, InviteCode, Media,
-    MediaId, MediaLink, MediaLinkType, Message, MessageId, MessageRef, MessageVerId,
-    PaginationQuery, PaginationResponse, Permissions, RoleId, RolePatch, RoleVerId, Room,
-    RoomCreate, RoomId, RoomPatch, RoomVerId, Session, SessionId, Thread, ThreadId, ThreadPatch,
-    ThreadVerId, UrlEmbedQueue, User, UserId, UserPatch, UserVerId,
+    DbEmailQueue, DbMessageCreate, DbNotificationQueue, DbNotificationRecipient, DbRoleCreate,
+    DbSfu, DbThreadCreate, DbTotp, DbUserCreate, DbVoiceModeration, InviteCode, Media, MediaId,
+    MediaLink, MediaLinkType, Message, MessageId, MessageRef, MessageVerId, PaginationQuery,
+    PaginationResponse, Permissions, RoleId, RolePatch, RoleVerId, Room, RoomCreate, RoomId,
+    RoomPatch, RoomVerId, Session, SessionId, SyncAudience, SyncEvent, SyncLogPosition, Thread,
+    ThreadId, ThreadPatch, ThreadVerId, UrlEmbedQueue, User, UserId, UserPatch, UserVerId,
 };
 
+#[cfg(feature = "memory")]
+pub mod memory;
 pub mod postgres;
 
 // #[async_trait]
+#[async_trait]
 pub trait Data:
     DataRoom
     + DataRoomMember
+    + DataRoomBan
     + DataRole
     + DataRoleMember
+    + DataTag
+    + DataReport
     + DataPermission
     + DataInvite
     + DataMedia
@@ -51,16 +70,104 @@ pub trait Data:
     + DataEmbed
     + DataUserEmail
     + DataEmailQueue
+    + DataNotification
+    + DataSyncLog
+    + DataVoice
     + Send
     + Sync
 {
-    // async fn commit(self) -> Result<()>;
-    // async fn rollback(self) -> Result<()>;
+    /// start a transaction
+    ///
+    /// everything done through the returned handle is only persisted once
+    /// it's committed, and is rolled back if it's dropped. starting a
+    /// transaction on a handle that's already in one joins the outer
+    /// transaction instead of nesting.
+    async fn begin(&self) -> Result<Box<dyn Data>>;
+
+    /// commit this transaction
+    ///
+    /// does nothing if this handle isn't the outermost transaction. the
+    /// outermost handle always commits, and any nested handles still alive
+    /// can't write anything afterwards.
+    async fn commit(self: Box<Self>) -> Result<()>;
+
+    /// roll back this transaction
+    ///
+    /// rolling back a nested handle rolls back the outer transaction too, and
+    /// committing the outermost handle afterwards returns an error.
+    async fn rollback(self: Box<Self>) -> Result<()>;
+}
+
+/// where everything is stored, picked from the scheme of `database_url`
+#[derive(Clone)]
+pub enum Database {
+    /// `postgres://` or `postgresql://`
+    Postgres(PgPool),
+
+    /// `memory://`, nothing is persisted
+    #[cfg(feature = "memory")]
+    Memory(memory::Memory),
+}
+
+/// how many connections are kept open to postgres unless configured otherwise
+pub const DEFAULT_MAX_CONNECTIONS: u32 = 10;
+
+impl Database {
+    pub async fn connect(url: &str) -> Result<Self> {
+        Self::connect_with(url, DEFAULT_MAX_CONNECTIONS).await
+    }
+
+    /// connect with a limit on how many connections postgres pools keep open
+    pub async fn connect_with(url: &str, max_connections: u32) -> Result<Self> {
+        let (scheme, _) = url.split_once("://").unwrap_or_default();
+        match scheme {
+            "postgres" | "postgresql" => {
+                let pool = PgPoolOptions::new()
+                    .max_connections(max_connections)
+                    .acquire_timeout(Duration::from_secs(5))
+                    .connect(url)
+                    .await?;
+                Ok(Database::Postgres(pool))
+            }
+            #[cfg(feature = "memory")]
+            "memory" => Ok(Database::Memory(memory::Memory::new())),
+            _ => Err(Error::Internal(format!(
+                "unsupported database url scheme {scheme:?}"
+            ))),
+        }
+    }
+
+    /// run any pending migrations
+    pub async fn migrate(&self) -> Result<()> {
+        match self {
+            Database::Postgres(pool) => sqlx::migrate!("./migrations").run(pool).await?,
+            #[cfg(feature = "memory")]
+            Database::Memory(_) => {}
+        }
+        Ok(())
+    }
+
+    pub fn data(&self) -> Box<dyn Data> {
+        match self {
+            Database::Postgres(pool) => Box::new(postgres::Postgres::new(pool.clone())),
+            #[cfg(feature = "memory")]
+            Database::Memory(memory) => Box::new(memory.clone()),
+        }
+    }
+
+    /// the postgres pool, if this is a postgres database
+    pub fn pool(&self) -> Option<&PgPool> {
+        match self {
+            Database::Postgres(pool) => Some(pool),
+            #[cfg(feature = "memory")]
+            Database::Memory(_) => None,
+        }
+    }
 }
 
 #[async_trait]
 pub trait DataRoom {
-    async fn room_create(&self, create: RoomCreate) -> Result<Room>;
+    async fn room_create(&self, create: RoomCreate, owner_id: Option<UserId>) -> Result<Room>;
     async fn room_get(&self, room_id: RoomId) -> Result<Room>;
     async fn room_list(
         &self,
@@ -99,6 +206,28 @@ pub trait DataRoomMember {
     ) -> Result<PaginationResponse<RoomMember>>;
 }
 
+#[async_trait]
+pub trait DataRoomBan {
+    /// ban a user, replacing any existing ban and setting their membership to `Ban`
+    async fn room_ban_create(
+        &self,
+        room_id: RoomId,
+        user_id: UserId,
+        creator_id: UserId,
+        reason: Option<String>,
+        expires_at: Option<common::v1::types::util::Time>,
+    ) -> Result<()>;
+    async fn room_ban_delete(&self, room_id: RoomId, user_id: UserId) -> Result<()>;
+
+    /// only returns bans that haven't expired yet
+    async fn room_ban_get(&self, room_id: RoomId, user_id: UserId) -> Result<RoomBan>;
+    async fn room_ban_list(
+        &self,
+        room_id: RoomId,
+        paginate: PaginationQuery<UserId>,
+    ) -> Result<PaginationResponse<RoomBan>>;
+}
+
 #[async_trait]
 pub trait DataRole {
     async fn role_create(&self, create: DbRoleCreate) -> Result<Role>;
@@ -116,6 +245,72 @@ pub trait DataRole {
         patch: RolePatch,
     ) -> Result<RoleVerId>;
     async fn role_apply_default(&self, room_id: RoomId, user_id: UserId) -> Result<()>;
+
+    /// get the highest position of any role a user has in a room, or 0 if they have none
+    async fn role_position_max(&self, room_id: RoomId, user_id: UserId) -> Result<u64>;
+}
+
+#[async_trait]
+pub trait DataTag {
+    async fn tag_create(&self, create: TagCreate) -> Result<Tag>;
+    async fn tag_get(&self, tag_id: TagId) -> Result<Tag>;
+    async fn tag_list(
+        &self,
+        room_id: RoomId,
+        paginate: PaginationQuery<TagId>,
+    ) -> Result<PaginationResponse<Tag>>;
+    async fn tag_update(&self, tag_id: TagId, patch: TagPatch) -> Result<TagVerId>;
+    async fn tag_delete(&self, tag_id: TagId) -> Result<()>;
+
+    /// count the threads this tag is directly applied to
+    async fn tag_thread_count(&self, tag_id: TagId) -> Result<u64>;
+
+    /// replace all tags applied to a thread
+    async fn tag_thread_set(&self, thread_id: ThreadId, tag_ids: &[TagId]) -> Result<()>;
+    async fn tag_thread_apply(&self, thread_id: ThreadId, tag_id: TagId) -> Result<()>;
+    async fn tag_thread_unapply(&self, thread_id: ThreadId, tag_id: TagId) -> Result<()>;
+    async fn tag_tag_apply(&self, target_id: TagId, tag_id: TagId) -> Result<()>;
+    async fn tag_tag_unapply(&self, target_id: TagId, tag_id: TagId) -> Result<()>;
+
+    /// get every tag implied by this tag, following tag composition transitively
+    async fn tag_implied(&self, tag_id: TagId) -> Result<Vec<TagId>>;
+}
+
+#[async_trait]
+pub trait DataReport {
+    /// get the open report thread for this target, if there is one
+    async fn report_thread_find(
+        &self,
+        destination: ReportDestination,
+        target: &ReportTarget,
+    ) -> Result<Option<ThreadId>>;
+
+    /// make a thread the open report thread for this target
+    ///
+    /// returns false if the target already has an open report thread
+    async fn report_thread_create(
+        &self,
+        thread_id: ThreadId,
+        destination: ReportDestination,
+        target: &ReportTarget,
+    ) -> Result<bool>;
+    async fn report_thread_set_status(
+        &self,
+        thread_id: ThreadId,
+        status: ReportStatus,
+    ) -> Result<()>;
+    async fn report_create(
+        &self,
+        thread_id: ThreadId,
+        reporter_id: UserId,
+        create: ReportCreate,
+    ) -> Result<Report>;
+    async fn report_get(&self, report_id: ReportId) -> Result<Report>;
+    async fn report_get_by_reporter(
+        &self,
+        thread_id: ThreadId,
+        reporter_id: UserId,
+    ) -> Result<Option<Report>>;
 }
 
 #[async_trait]
@@ -211,6 +406,9 @@ pub trait DataMedia {
 
     async fn media_update(&self, media_id: MediaId, patch: MediaPatch) -> Result<()>;
 
+    /// replace everything about a media item except its owner, eg. after regenerating thumbnails
+    async fn media_replace(&self, media: Media) -> Result<()>;
+
     async fn media_link_insert(
         &self,
         media_id: MediaId,
@@ -220,6 +418,9 @@ pub trait DataMedia {
 
     async fn media_link_select(&self, media_id: MediaId) -> Result<Vec<MediaLink>>;
 
+    /// list the threads with messages that link to media, skipping deleted messages
+    async fn media_link_threads(&self, media_id: MediaId) -> Result<Vec<ThreadId>>;
+
     async fn media_link_delete(&self, target_id: Uuid, link_type: MediaLinkType) -> Result<()>;
 
     async fn media_link_delete_all(&self, target_id: Uuid) -> Result<()>;
@@ -279,6 +480,57 @@ pub trait DataMessage {
         breadth: Option<u16>,
         pagination: PaginationQuery<MessageId>,
     ) -> Result<PaginationResponse<Message>>;
+
+    /// get several messages in a thread at once, ordered by id
+    ///
+    /// messages that don't exist are left out
+    async fn message_get_many(
+        &self,
+        thread_id: ThreadId,
+        message_ids: &[MessageId],
+        user_id: UserId,
+    ) -> Result<Vec<Message>>;
+
+    /// get the ids of every message between `start_id` and `end_id`, inclusive
+    async fn message_id_range(
+        &self,
+        thread_id: ThreadId,
+        start_id: MessageId,
+        end_id: MessageId,
+        limit: u16,
+    ) -> Result<Vec<MessageId>>;
+
+    /// move messages to another thread, keeping their ids and versions
+    async fn message_move(
+        &self,
+        source_id: ThreadId,
+        target_id: ThreadId,
+        message_ids: &[MessageId],
+    ) -> Result<()>;
+
+    /// pin a message, putting it at the start of the pin list
+    ///
+    /// returns false without pinning anything if the thread already has
+    /// `max_pins` pinned messages
+    async fn message_pin_create(
+        &self,
+        thread_id: ThreadId,
+        message_id: MessageId,
+        max_pins: usize,
+    ) -> Result<bool>;
+
+    /// unpin a message, returning false if it wasn't pinned
+    async fn message_pin_delete(&self, thread_id: ThreadId, message_id: MessageId) -> Result<bool>;
+
+    /// set the order of pinned messages
+    ///
+    /// errors without changing anything unless `message_ids` contains every pinned message
+    async fn message_pin_reorder(
+        &self,
+        thread_id: ThreadId,
+        message_ids: &[MessageId],
+    ) -> Result<()>;
+    async fn message_pin_list(&self, thread_id: ThreadId, user_id: UserId) -> Result<Vec<Message>>;
 }
 
 #[async_trait]
@@ -305,10 +557,27 @@ pub trait DataThread {
         thread_id: ThreadId,
         user_id: UserId,
     ) -> Result<ThreadPrivate>;
+
+    /// get a user's private data for several threads at once
+    ///
+    /// threads that don't exist are left out
+    async fn thread_get_private_many(
+        &self,
+        thread_ids: &[ThreadId],
+        user_id: UserId,
+    ) -> Result<Vec<(ThreadId, ThreadPrivate)>>;
+
+    /// list the threads in a room that a user can view
+    ///
+    /// report threads are only listed for the destinations in `reports`, which
+    /// the user moderates in this room
     async fn thread_list(
         &self,
         room_id: RoomId,
+        user_id: UserId,
         pagination: PaginationQuery<ThreadId>,
+        tag_id: Option<TagId>,
+        reports: Vec<ReportDestination>,
     ) -> Result<PaginationResponse<Thread>>;
     async fn thread_update(&self, thread_id: ThreadId, patch: ThreadPatch) -> Result<ThreadVerId>;
     async fn thread_delete(&self, thread_id: ThreadId, user_id: UserId) -> Result<()>;
@@ -344,6 +613,11 @@ pub trait DataUser {
         user_id: UserId,
         registered_at: Option<common::v1::types::util::Time>,
     ) -> Result<UserVerId>;
+    async fn user_set_suspended(
+        &self,
+        user_id: UserId,
+        suspended: Option<Suspended>,
+    ) -> Result<UserVerId>;
 }
 
 #[async_trait]
@@ -361,6 +635,54 @@ pub trait DataAuth {
     async fn auth_password_set(&self, user_id: UserId, hash: &[u8], salt: &[u8]) -> Result<()>;
     async fn auth_password_get(&self, user_id: UserId) -> Result<Option<(Vec<u8>, Vec<u8>)>>;
     async fn auth_password_delete(&self, user_id: UserId) -> Result<()>;
+    async fn auth_oauth_list(&self, user_id: UserId) -> Result<Vec<String>>;
+
+    /// set a new, not yet verified totp secret
+    async fn auth_totp_set(&self, user_id: UserId, secret: &[u8]) -> Result<()>;
+    async fn auth_totp_get(&self, user_id: UserId) -> Result<Option<DbTotp>>;
+
+    /// mark a time step as used and the secret as valid
+    ///
+    /// returns false if this step (or a later one) was already used
+    async fn auth_totp_use(&self, user_id: UserId, step: i64) -> Result<bool>;
+
+    /// count an attempt at verifying a totp or recovery code, before checking it
+    ///
+    /// attempts count as failed until auth_totp_attempt_reset is called.
+    /// returns false if the last `max_failures` attempts failed and the latest
+    /// one was less than `lockout` ago. once that long has passed since the
+    /// latest failure, counting starts over.
+    async fn auth_totp_attempt(
+        &self,
+        user_id: UserId,
+        max_failures: u32,
+        lockout: Duration,
+    ) -> Result<bool>;
+
+    /// forget failed attempts after a successful one
+    async fn auth_totp_attempt_reset(&self, user_id: UserId) -> Result<()>;
+
+    /// delete the totp secret and all recovery codes
+    async fn auth_totp_delete(&self, user_id: UserId) -> Result<()>;
+
+    /// replace all recovery codes with new (code_hash, code_hint) pairs
+    async fn auth_totp_recovery_set(
+        &self,
+        user_id: UserId,
+        codes: Vec<(String, String)>,
+    ) -> Result<()>;
+    async fn auth_totp_recovery_list(&self, user_id: UserId) -> Result<Vec<TotpRecoveryCode>>;
+
+    /// list the (code_hash, code_hint) pairs of recovery codes that haven't been used
+    async fn auth_totp_recovery_unused(&self, user_id: UserId) -> Result<Vec<(String, String)>>;
+
+    /// mark a recovery code as used, returning false if it doesn't exist or was already used
+    async fn auth_totp_recovery_use(
+        &self,
+        user_id: UserId,
+        code_hash: &str,
+        session_id: SessionId,
+    ) -> Result<bool>;
 }
 
 #[async_trait]
@@ -371,6 +693,20 @@ pub trait DataSearch {
         query: SearchMessageRequest,
         paginate: PaginationQuery<MessageId>,
     ) -> Result<PaginationResponse<Message>>;
+
+    async fn search_thread(
+        &self,
+        user_id: UserId,
+        query: SearchThreadsRequest,
+        paginate: PaginationQuery<ThreadId>,
+    ) -> Result<PaginationResponse<Thread>>;
+
+    async fn search_room(
+        &self,
+        user_id: UserId,
+        query: SearchRoomsRequest,
+        paginate: PaginationQuery<RoomId>,
+    ) -> Result<PaginationResponse<Room>>;
 }
 
 #[async_trait]
@@ -569,3 +905,154 @@ pub trait DataEmailQueue {
     async fn email_queue_finish(&self, id: Uuid) -> Result<()>;
     async fn email_queue_fail(&self, error_message: String, id: Uuid) -> Result<()>;
 }
+
+#[async_trait]
+pub trait DataNotification {
+    /// add notifications to each user's inbox
+    async fn notification_add(&self, notifs: Vec<(UserId, Notification)>) -> Result<()>;
+    async fn notification_list(
+        &self,
+        user_id: UserId,
+        paginate: PaginationQuery<NotificationId>,
+        include_read: bool,
+    ) -> Result<PaginationResponse<Notification>>;
+    async fn notification_mark_read(&self, user_id: UserId, ids: &[NotificationId]) -> Result<()>;
+    async fn notification_mark_unread(&self, user_id: UserId, ids: &[NotificationId])
+        -> Result<()>;
+
+    /// list everyone who may be notified about messages in a thread, along
+    /// with their room roles and notification configs
+    async fn notification_recipients(
+        &self,
+        thread_id: ThreadId,
+    ) -> Result<Vec<DbNotificationRecipient>>;
+
+    async fn notification_config_room_get(
+        &self,
+        user_id: UserId,
+        room_id: RoomId,
+    ) -> Result<NotifsRoom>;
+    async fn notification_config_room_set(
+        &self,
+        user_id: UserId,
+        room_id: RoomId,
+        config: &NotifsRoom,
+    ) -> Result<()>;
+    async fn notification_config_thread_get(
+        &self,
+        user_id: UserId,
+        thread_id: ThreadId,
+    ) -> Result<NotifsThread>;
+    async fn notification_config_thread_set(
+        &self,
+        user_id: UserId,
+        thread_id: ThreadId,
+        config: &NotifsThread,
+    ) -> Result<()>;
+
+    /// queue a message to have notifications created for it
+    async fn notification_queue_insert(
+        &self,
+        thread_id: ThreadId,
+        message_id: MessageId,
+        author_id: UserId,
+    ) -> Result<()>;
+
+    /// claim the oldest queued message, including ones claimed by workers that never finished
+    async fn notification_queue_claim(&self) -> Result<Option<DbNotificationQueue>>;
+    async fn notification_queue_finish(&self, message_id: MessageId) -> Result<()>;
+    async fn notification_queue_fail(
+        &self,
+        error_message: String,
+        message_id: MessageId,
+    ) -> Result<()>;
+}
+
+#[async_trait]
+pub trait DataSyncLog {
+    /// persist a batch of events and append each one to the logs of everyone in its audience
+    ///
+    /// events are appended in order, in a single transaction. this writes a row
+    /// for every member of each audience, so events for big rooms are slow to
+    /// append, but only appends sharing a user wait on each other. positions
+    /// are given out afterwards, and events without one can't be listed or got.
+    async fn sync_log_append(
+        &self,
+        events: Vec<(Uuid, MessageSync, Vec<SyncAudience>)>,
+    ) -> Result<Vec<SyncEvent>>;
+    /// get persisted events and their positions by id, skipping any that don't exist
+    async fn sync_log_get_many(&self, event_ids: &[Uuid]) -> Result<Vec<(Uuid, u64, SyncEvent)>>;
+
+    /// list the ids of events persisted after this position, in the order they were persisted
+    ///
+    /// positions follow the order events were committed in, unlike their ids
+    async fn sync_log_list_after(&self, pos: u64) -> Result<Vec<Uuid>>;
+
+    /// get the position of the most recently persisted event
+    async fn sync_log_last_pos(&self) -> Result<Option<u64>>;
+
+    /// get events from a user's log, starting after `seq`
+    async fn sync_log_replay(
+        &self,
+        user_id: UserId,
+        seq: u64,
+        limit: u16,
+    ) -> Result<Vec<(u64, MessageSync)>>;
+    async fn sync_log_position(&self, user_id: UserId) -> Result<SyncLogPosition>;
+
+    /// remove events older than `max_age`
+    async fn sync_log_trim(&self, max_age: Duration) -> Result<()>;
+}
+
+#[async_trait]
+pub trait DataVoice {
+    /// add or refresh an sfu, replacing any other sfu with the same url
+    ///
+    /// fails if the id or url belongs to an sfu registered with another token
+    async fn sfu_upsert(&self, sfu: DbSfu) -> Result<()>;
+
+    /// list sfus seen within `max_age`
+    async fn sfu_list(&self, max_age: Duration) -> Result<Vec<DbSfu>>;
+
+    /// get the sfu hosting a voice thread's call
+    async fn sfu_thread_get(&self, thread_id: ThreadId) -> Result<Option<Uuid>>;
+
+    /// host a voice thread's call on an sfu
+    ///
+    /// an existing sfu is only replaced if it's `replace`. returns the sfu
+    /// that ends up hosting the call.
+    async fn sfu_thread_set(
+        &self,
+        thread_id: ThreadId,
+        sfu_id: Uuid,
+        replace: Option<Uuid>,
+    ) -> Result<Uuid>;
+
+    /// get whether a moderator muted or deafened a room member in voice calls
+    ///
+    /// people who aren't in the room are neither
+    async fn voice_moderation_get(
+        &self,
+        room_id: RoomId,
+        user_id: UserId,
+    ) -> Result<DbVoiceModeration>;
+
+    /// set whether a moderator muted or deafened a room member in voice calls
+    async fn voice_moderation_set(
+        &self,
+        room_id: RoomId,
+        user_id: UserId,
+        moderation: DbVoiceModeration,
+    ) -> Result<()>;
+
+    /// get the voice thread whose call someone is in
+    async fn voice_member_get(&self, user_id: UserId) -> Result<Option<ThreadId>>;
+
+    /// record someone joining or moving to a voice thread's call
+    async fn voice_member_set(&self, user_id: UserId, thread_id: ThreadId) -> Result<()>;
+
+    /// record someone leaving a voice thread's call
+    ///
+    /// does nothing if they've since moved to another thread
+    async fn voice_member_delete(&self, user_id: UserId, thread_id: ThreadId) -> Result<()>;
+}
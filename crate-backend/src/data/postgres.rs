@@ -1,13 +1,143 @@
-use sqlx::PgPool;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
 
+use async_trait::async_trait;
+use sqlx::pool::PoolConnection;
+use sqlx::{PgConnection, PgPool, Transaction};
+use tokio::sync::{Mutex, OwnedMappedMutexGuard, OwnedMutexGuard};
+
+type PgTransaction = Transaction<'static, sqlx::Postgres>;
+
+/// a transaction shared between nested handles
 #[derive(Debug)]
+pub(crate) struct SharedTxState {
+    /// taken out once it's committed or rolled back
+    tx: Option<PgTransaction>,
+
+    /// set when a nested handle rolls back, so the outermost one can't commit
+    rolled_back: bool,
+}
+
+type SharedTx = Arc<Mutex<SharedTxState>>;
+
+#[derive(Debug, Clone)]
 pub struct Postgres {
     pub(crate) pool: PgPool,
-    // TODO: make postgres use one transaction + smaller queries?
-    // pub(crate) conn: PgConnection,
+
+    /// if set, every query runs inside this transaction instead of the pool
+    tx: Option<SharedTx>,
+
+    /// whether this handle joined a transaction started by another handle
+    nested: bool,
 }
 
-impl Data for Postgres {}
+/// a connection from the pool or the shared transaction
+pub(crate) enum PgConn {
+    Pool(PoolConnection<sqlx::Postgres>),
+    Tx(OwnedMappedMutexGuard<SharedTxState, PgTransaction>),
+}
+
+impl Postgres {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            tx: None,
+            nested: false,
+        }
+    }
+
+    /// get a connection to run queries on
+    ///
+    /// inside a transaction, every handle shares one connection, and this locks
+    /// it until the returned connection is dropped. only one can be held at a
+    /// time, so drop it before calling any other method on a handle to the same
+    /// transaction. doing otherwise returns an error instead of deadlocking.
+    pub(crate) async fn conn(&self) -> Result<PgConn> {
+        match &self.tx {
+            Some(tx) => {
+                let tx = tx.clone().try_lock_owned().map_err(|_| {
+                    Error::Internal(
+                        "transaction is already in use, drop its connection first".to_owned(),
+                    )
+                })?;
+                let tx = OwnedMutexGuard::try_map(tx, |s| s.tx.as_mut())
+                    .map_err(|_| Error::Internal("transaction is already finished".to_owned()))?;
+                Ok(PgConn::Tx(tx))
+            }
+            None => Ok(PgConn::Pool(self.pool.acquire().await?)),
+        }
+    }
+}
+
+impl Deref for PgConn {
+    type Target = PgConnection;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            PgConn::Pool(conn) => conn,
+            PgConn::Tx(tx) => tx,
+        }
+    }
+}
+
+impl DerefMut for PgConn {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            PgConn::Pool(conn) => conn,
+            PgConn::Tx(tx) => tx,
+        }
+    }
+}
+
+#[async_trait]
+impl Data for Postgres {
+    async fn begin(&self) -> Result<Box<dyn Data>> {
+        let tx = match &self.tx {
+            // nested transactions join the outer one
+            Some(tx) => tx.clone(),
+            None => Arc::new(Mutex::new(SharedTxState {
+                tx: Some(self.pool.begin().await?),
+                rolled_back: false,
+            })),
+        };
+        Ok(Box::new(Postgres {
+            pool: self.pool.clone(),
+            tx: Some(tx),
+            nested: self.tx.is_some(),
+        }))
+    }
+
+    async fn commit(self: Box<Self>) -> Result<()> {
+        // only the outermost handle actually commits
+        let (Some(tx), false) = (self.tx, self.nested) else {
+            return Ok(());
+        };
+        let mut state = tx.lock().await;
+        if state.rolled_back {
+            return Err(Error::Internal(
+                "a nested transaction was rolled back".to_owned(),
+            ));
+        }
+        if let Some(tx) = state.tx.take() {
+            tx.commit().await?;
+        }
+        Ok(())
+    }
+
+    async fn rollback(self: Box<Self>) -> Result<()> {
+        let Some(tx) = self.tx else {
+            return Ok(());
+        };
+        // nested handles roll back everything, and the outermost one has to
+        // find out instead of committing what they meant to undo
+        let mut state = tx.lock().await;
+        if let Some(tx) = state.tx.take() {
+            state.rolled_back = self.nested;
+            tx.rollback().await?;
+        }
+        Ok(())
+    }
+}
 
 mod application;
 mod audit_logs;
@@ -18,14 +148,19 @@ mod emoji;
 mod invite;
 mod media;
 mod message;
+mod notification;
 mod permission;
 mod reaction;
+mod report;
 mod role;
 mod role_member;
 mod room;
+mod room_ban;
 mod room_member;
 mod search;
 mod session;
+mod sync_log;
+mod tag;
 mod thread;
 mod thread_member;
 mod unread;
@@ -34,7 +169,9 @@ mod user_config;
 mod user_email;
 mod user_relationship;
 mod util;
+mod voice;
 
 pub use util::Pagination;
 
 use crate::data::Data;
+use crate::error::{Error, Result};
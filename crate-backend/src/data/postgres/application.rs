@@ -28,7 +28,7 @@ impl DataApplication for Postgres {
             app.bridge,
             app.public,
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
@@ -49,14 +49,14 @@ impl DataApplication for Postgres {
             app.bridge,
             app.public,
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
 
     async fn application_delete(&self, id: ApplicationId) -> Result<()> {
         query_as!(Application, r#"DELETE FROM application WHERE id = $1"#, *id)
-            .execute(&self.pool)
+            .execute(&mut *self.conn().await?)
             .await?;
         Ok(())
     }
@@ -71,7 +71,7 @@ impl DataApplication for Postgres {
             "#,
             *id
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.conn().await?)
         .await?;
         Ok(app)
     }
@@ -84,7 +84,7 @@ impl DataApplication for Postgres {
         let p: Pagination<_> = q.try_into()?;
         gen_paginate!(
             p,
-            self.pool,
+            self,
             query_as!(
                 Application,
                 r#"
@@ -29,7 +29,7 @@ impl DataAuditLogs for Postgres {
         let p: Pagination<_> = paginate.try_into()?;
         gen_paginate!(
             p,
-            self.pool,
+            self,
             query_as!(
                 DbAuditLog,
                 "
@@ -90,7 +90,7 @@ impl DataAuditLogs for Postgres {
             payload,
             target_id,
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
@@ -1,8 +1,11 @@
+use std::time::Duration;
+
 use async_trait::async_trait;
-use sqlx::{query, query_scalar};
+use common::v1::types::auth::{TotpRecoveryCode, TotpRecoveryCodeUsed};
+use sqlx::{query, query_as, query_scalar, Acquire};
 
 use crate::error::Result;
-use crate::types::UserId;
+use crate::types::{DbTotp, SessionId, UserId};
 
 use crate::data::DataAuth;
 
@@ -24,7 +27,7 @@ impl DataAuth for Postgres {
             remote_id,
             can_auth,
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
@@ -35,7 +38,7 @@ impl DataAuth for Postgres {
             remote_id,
             provider,
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.conn().await?)
         .await?;
         Ok(remote_id.into())
     }
@@ -46,7 +49,7 @@ impl DataAuth for Postgres {
             provider,
             user_id.into_inner(),
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
@@ -58,7 +61,7 @@ impl DataAuth for Postgres {
             hash,
             salt
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
@@ -68,7 +71,7 @@ impl DataAuth for Postgres {
             "select password_hash, password_salt from usr where id = $1",
             *user_id,
         )
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.conn().await?)
         .await?;
         let Some(row) = row else { return Ok(None) };
         match (row.password_hash, row.password_salt) {
@@ -82,8 +85,205 @@ impl DataAuth for Postgres {
             "update usr set password_hash = null, password_salt = null where id = $1",
             *user_id,
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
+        .await?;
+        Ok(())
+    }
+
+    async fn auth_oauth_list(&self, user_id: UserId) -> Result<Vec<String>> {
+        let providers = query_scalar!(
+            "SELECT provider FROM oauth WHERE user_id = $1 ORDER BY provider",
+            *user_id,
+        )
+        .fetch_all(&mut *self.conn().await?)
+        .await?;
+        Ok(providers)
+    }
+
+    async fn auth_totp_set(&self, user_id: UserId, secret: &[u8]) -> Result<()> {
+        query!(
+            r#"
+            INSERT INTO totp (user_id, secret, is_valid, last_used_step)
+            VALUES ($1, $2, false, null)
+            ON CONFLICT (user_id) DO UPDATE SET
+                secret = excluded.secret,
+                is_valid = false,
+                last_used_step = null,
+                failed_attempts = 0,
+                failed_at = null,
+                created_at = now()
+            "#,
+            *user_id,
+            secret,
+        )
+        .execute(&mut *self.conn().await?)
+        .await?;
+        Ok(())
+    }
+
+    async fn auth_totp_get(&self, user_id: UserId) -> Result<Option<DbTotp>> {
+        let row = query_as!(
+            DbTotp,
+            "SELECT secret, is_valid, last_used_step FROM totp WHERE user_id = $1",
+            *user_id,
+        )
+        .fetch_optional(&mut *self.conn().await?)
+        .await?;
+        Ok(row)
+    }
+
+    async fn auth_totp_use(&self, user_id: UserId, step: i64) -> Result<bool> {
+        let res = query!(
+            r#"
+            UPDATE totp SET is_valid = true, last_used_step = $2
+            WHERE user_id = $1 AND (last_used_step IS NULL OR last_used_step < $2)
+            "#,
+            *user_id,
+            step,
+        )
+        .execute(&mut *self.conn().await?)
+        .await?;
+        Ok(res.rows_affected() == 1)
+    }
+
+    async fn auth_totp_attempt(
+        &self,
+        user_id: UserId,
+        max_failures: u32,
+        lockout: Duration,
+    ) -> Result<bool> {
+        let res = query!(
+            r#"
+            UPDATE totp SET
+                -- failures from before the last lockout expired don't count anymore
+                failed_attempts = CASE
+                    WHEN failed_at < now() - make_interval(secs => $3) THEN 1
+                    ELSE failed_attempts + 1
+                END,
+                failed_at = now()
+            WHERE user_id = $1
+              AND (failed_attempts < $2 OR failed_at < now() - make_interval(secs => $3))
+            "#,
+            *user_id,
+            max_failures as i32,
+            lockout.as_secs_f64(),
+        )
+        .execute(&mut *self.conn().await?)
+        .await?;
+        Ok(res.rows_affected() == 1)
+    }
+
+    async fn auth_totp_attempt_reset(&self, user_id: UserId) -> Result<()> {
+        query!(
+            "UPDATE totp SET failed_attempts = 0, failed_at = null WHERE user_id = $1",
+            *user_id,
+        )
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
+
+    async fn auth_totp_delete(&self, user_id: UserId) -> Result<()> {
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
+        query!(
+            "DELETE FROM totp_recovery_code WHERE user_id = $1",
+            *user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        query!("DELETE FROM totp WHERE user_id = $1", *user_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn auth_totp_recovery_set(
+        &self,
+        user_id: UserId,
+        codes: Vec<(String, String)>,
+    ) -> Result<()> {
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
+        query!(
+            "DELETE FROM totp_recovery_code WHERE user_id = $1",
+            *user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        let (hashes, hints): (Vec<String>, Vec<String>) = codes.into_iter().unzip();
+        query!(
+            r#"
+            INSERT INTO totp_recovery_code (user_id, code_hash, code_hint)
+            SELECT $1, * FROM UNNEST($2::text[], $3::text[])
+            "#,
+            *user_id,
+            &hashes,
+            &hints,
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn auth_totp_recovery_list(&self, user_id: UserId) -> Result<Vec<TotpRecoveryCode>> {
+        let rows = query!(
+            r#"
+            SELECT code_hint, used_at, used_by FROM totp_recovery_code
+            WHERE user_id = $1
+            ORDER BY code_hint
+            "#,
+            *user_id,
+        )
+        .fetch_all(&mut *self.conn().await?)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| TotpRecoveryCode {
+                code: row.code_hint,
+                used: row.used_at.map(|used_at| TotpRecoveryCodeUsed {
+                    used_at: used_at.into(),
+                    used_by: row.used_by.map(Into::into),
+                }),
+            })
+            .collect())
+    }
+
+    async fn auth_totp_recovery_unused(&self, user_id: UserId) -> Result<Vec<(String, String)>> {
+        let rows = query!(
+            r#"
+            SELECT code_hash, code_hint FROM totp_recovery_code
+            WHERE user_id = $1 AND used_at IS NULL
+            "#,
+            *user_id,
+        )
+        .fetch_all(&mut *self.conn().await?)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.code_hash, row.code_hint))
+            .collect())
+    }
+
+    async fn auth_totp_recovery_use(
+        &self,
+        user_id: UserId,
+        code_hash: &str,
+        session_id: SessionId,
+    ) -> Result<bool> {
+        let res = query!(
+            r#"
+            UPDATE totp_recovery_code SET used_at = now(), used_by = $3
+            WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+            "#,
+            *user_id,
+            code_hash,
+            *session_id,
+        )
+        .execute(&mut *self.conn().await?)
+        .await?;
+        Ok(res.rows_affected() == 1)
+    }
 }
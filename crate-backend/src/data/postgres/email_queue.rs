@@ -31,7 +31,7 @@ impl DataEmailQueue for Postgres {
             plain_text_body,
             html_body
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(id)
     }
@@ -53,7 +53,7 @@ impl DataEmailQueue for Postgres {
             RETURNING id, to_addr, from_addr, subject, plain_text_body, html_body
             "#
         )
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.conn().await?)
         .await?;
         Ok(email_item)
     }
@@ -67,7 +67,7 @@ impl DataEmailQueue for Postgres {
             "#,
             id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
@@ -82,7 +82,7 @@ impl DataEmailQueue for Postgres {
             error_message,
             id,
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
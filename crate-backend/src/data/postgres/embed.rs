@@ -25,14 +25,14 @@ impl DataEmbed for Postgres {
             user_id.into_inner(),
             url
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(id)
     }
 
     async fn url_embed_queue_claim(&self) -> Result<Option<UrlEmbedQueue>> {
         let row = query_as!(UrlEmbedQueue, "UPDATE url_embed_queue SET claimed_at = NOW() WHERE id = (SELECT id FROM url_embed_queue WHERE claimed_at IS NULL AND finished_at IS NULL ORDER BY created_at ASC LIMIT 1 FOR UPDATE SKIP LOCKED) RETURNING *")
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.conn().await?)
         .await?;
         Ok(row)
     }
@@ -42,7 +42,7 @@ impl DataEmbed for Postgres {
             "UPDATE url_embed_queue SET finished_at = NOW() WHERE id = $1",
             id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         if let Some(embed) = embed {
             query!(
@@ -50,7 +50,7 @@ impl DataEmbed for Postgres {
                 serde_json::to_value(embed)?,
                 id
             )
-            .execute(&self.pool)
+            .execute(&mut *self.conn().await?)
             .await?;
         }
         Ok(())
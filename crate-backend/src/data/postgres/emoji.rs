@@ -45,7 +45,6 @@ impl DataEmoji for Postgres {
         room_id: RoomId,
         create: EmojiCustomCreate,
     ) -> Result<EmojiCustom> {
-        let mut conn = self.pool.acquire().await?;
         let emoji_id = Uuid::now_v7();
         query!(
             "
@@ -58,7 +57,7 @@ impl DataEmoji for Postgres {
             *create.media_id,
             *room_id,
         )
-        .execute(&mut *conn)
+        .execute(&mut *self.conn().await?)
         .await?;
         info!("inserted room");
         self.emoji_get(emoji_id.into()).await
@@ -66,7 +65,7 @@ impl DataEmoji for Postgres {
 
     async fn emoji_get(&self, emoji_id: EmojiId) -> Result<EmojiCustom> {
         let id: Uuid = emoji_id.into();
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.conn().await?;
         let row = query_as!(
             DbEmojiCustom,
             r#"SELECT id, name, creator_id, animated, media_id, room_id FROM custom_emoji WHERE id = $1"#,
@@ -85,7 +84,7 @@ impl DataEmoji for Postgres {
         let p: Pagination<_> = pagination.try_into()?;
         gen_paginate!(
             p,
-            self.pool,
+            self,
             query_as!(
                 DbEmojiCustom,
                 r#"
@@ -117,7 +116,7 @@ impl DataEmoji for Postgres {
             "UPDATE custom_emoji SET deleted_at = now() WHERE id = $1",
             *emoji_id
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
@@ -34,7 +34,7 @@ impl DataInvite for Postgres {
             expires_at.map(|t| PrimitiveDateTime::new(t.date(), t.time())),
             max_uses.map(|n| n as i32),
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
@@ -56,14 +56,12 @@ impl DataInvite for Postgres {
             expires_at.map(|t| PrimitiveDateTime::new(t.date(), t.time())),
             max_uses.map(|n| n as i32),
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
 
     async fn invite_select(&self, code: InviteCode) -> Result<InviteWithMetadata> {
-        let mut conn = self.pool.begin().await?;
-        let mut tx = conn.begin().await?;
         let row = query_as!(
             DbInvite,
             r#"
@@ -73,7 +71,7 @@ impl DataInvite for Postgres {
         "#,
             code.0
         )
-        .fetch_one(&mut *tx)
+        .fetch_one(&mut *self.conn().await?)
         .await?;
         let target = match row.target_type.as_str() {
             "room" => {
@@ -114,7 +112,7 @@ impl DataInvite for Postgres {
 
     async fn invite_delete(&self, code: InviteCode) -> Result<()> {
         query!("DELETE FROM invite WHERE code = $1", code.0)
-            .execute(&self.pool)
+            .execute(&mut *self.conn().await?)
             .await?;
         Ok(())
     }
@@ -125,7 +123,7 @@ impl DataInvite for Postgres {
         paginate: PaginationQuery<InviteCode>,
     ) -> Result<PaginationResponse<InviteWithMetadata>> {
         let p: Pagination<_> = paginate.try_into()?;
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.conn().await?;
         let mut tx = conn.begin().await?;
         let raw = query_as!(
             DbInvite,
@@ -150,6 +148,7 @@ impl DataInvite for Postgres {
         .fetch_one(&mut *tx)
         .await?;
         tx.rollback().await?;
+        drop(conn);
         let has_more = raw.len() > p.limit as usize;
         let mut items = vec![];
         let room = self.room_get(room_id).await?;
@@ -194,7 +193,7 @@ impl DataInvite for Postgres {
         paginate: PaginationQuery<InviteCode>,
     ) -> Result<PaginationResponse<InviteWithMetadata>> {
         let p: Pagination<_> = paginate.try_into()?;
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.conn().await?;
         let mut tx = conn.begin().await?;
         let raw = query_as!(
             DbInvite,
@@ -215,6 +214,7 @@ impl DataInvite for Postgres {
             .fetch_one(&mut *tx)
             .await?;
         tx.rollback().await?;
+        drop(conn);
         let has_more = raw.len() > p.limit as usize;
         let mut items = vec![];
         for row in raw.into_iter().take(p.limit as usize) {
@@ -254,7 +254,7 @@ impl DataInvite for Postgres {
 
     async fn invite_incr_use(&self, code: InviteCode) -> Result<()> {
         query!("update invite set uses = uses + 1 where code = $1", code.0)
-            .execute(&self.pool)
+            .execute(&mut *self.conn().await?)
             .await?;
         Ok(())
     }
@@ -264,7 +264,7 @@ impl DataInvite for Postgres {
         code: InviteCode,
         patch: InvitePatch,
     ) -> Result<InviteWithMetadata> {
-        let mut conn = self.pool.begin().await?;
+        let mut conn = self.conn().await?;
         let mut tx = conn.begin().await?;
 
         let invite = query_as!(
@@ -306,6 +306,7 @@ impl DataInvite for Postgres {
         .await?;
 
         tx.commit().await?;
+        drop(conn);
 
         self.invite_select(code).await
     }
@@ -1,12 +1,12 @@
 use async_trait::async_trait;
 use common::v1::types::{MediaPatch, MediaTrack};
 use serde::{Deserialize, Serialize};
-use sqlx::{query, query_as};
+use sqlx::{query, query_as, query_scalar, Acquire};
 use tracing::info;
 use uuid::Uuid;
 
-use crate::error::Result;
-use crate::types::{Media, MediaId, MediaLink, MediaLinkType, UserId};
+use crate::error::{Error, Result};
+use crate::types::{Media, MediaId, MediaLink, MediaLinkType, ThreadId, UserId};
 
 use crate::data::DataMedia;
 
@@ -73,7 +73,8 @@ impl From<DbMediaRaw> for Media {
 #[async_trait]
 impl DataMedia for Postgres {
     async fn media_insert(&self, user_id: UserId, media: Media) -> Result<()> {
-        let mut tx = self.pool.begin().await?;
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
         let media_id = media.id;
         let data =
             serde_json::to_value(&DbMediaData::V1(media)).expect("failed to serialize media");
@@ -103,14 +104,15 @@ impl DataMedia for Postgres {
         "#,
             media_id.into_inner(),
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.conn().await?)
         .await?;
         let parsed: DbMediaData = serde_json::from_value(media.data).unwrap();
         Ok((parsed.into(), media.user_id.into()))
     }
 
     async fn media_update(&self, media_id: MediaId, patch: MediaPatch) -> Result<()> {
-        let mut tx = self.pool.begin().await?;
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
         let mut media = query_as!(
             DbMedia,
             r#"
@@ -144,6 +146,22 @@ impl DataMedia for Postgres {
         Ok(())
     }
 
+    async fn media_replace(&self, media: Media) -> Result<()> {
+        let media_id = media.id;
+        let data = serde_json::to_value(DbMediaData::V1(media)).expect("failed to serialize media");
+        let res = query!(
+            "UPDATE media SET data = $2 WHERE id = $1",
+            media_id.into_inner(),
+            data,
+        )
+        .execute(&mut *self.conn().await?)
+        .await?;
+        if res.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+        Ok(())
+    }
+
     async fn media_link_insert(
         &self,
         media_id: MediaId,
@@ -159,7 +177,7 @@ impl DataMedia for Postgres {
             target_id,
             link_type as _
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
@@ -174,25 +192,41 @@ impl DataMedia for Postgres {
         "#,
             media_id.into_inner(),
         )
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.conn().await?)
         .await?;
         Ok(links)
     }
 
+    async fn media_link_threads(&self, media_id: MediaId) -> Result<Vec<ThreadId>> {
+        let thread_ids = query_scalar!(
+            r#"
+            SELECT DISTINCT message.thread_id
+            FROM media_link link
+            JOIN message ON (link.link_type = 'Message' AND message.id = link.target_id)
+                OR (link.link_type = 'MessageVersion' AND message.version_id = link.target_id)
+            WHERE link.media_id = $1 AND message.deleted_at IS NULL
+            "#,
+            media_id.into_inner(),
+        )
+        .fetch_all(&mut *self.conn().await?)
+        .await?;
+        Ok(thread_ids.into_iter().map(Into::into).collect())
+    }
+
     async fn media_link_delete(&self, target_id: Uuid, link_type: MediaLinkType) -> Result<()> {
         query!(
             "DELETE FROM media_link WHERE target_id = $1 AND link_type = $2",
             target_id,
             link_type as _
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
 
     async fn media_link_delete_all(&self, target_id: Uuid) -> Result<()> {
         query!("DELETE FROM media_link WHERE target_id = $1", target_id)
-            .execute(&self.pool)
+            .execute(&mut *self.conn().await?)
             .await?;
         Ok(())
     }
@@ -1,14 +1,14 @@
 use async_trait::async_trait;
 use common::v1::types::util::Time;
 use common::v1::types::{
-    Embed, Interactions, Mentions, MessageDefaultMarkdown, MessageDefaultTagged,
-    MessageThreadUpdate, MessageType, UserId,
+    Embed, Interactions, MessageDefaultMarkdown, MessageDefaultTagged, MessageThreadUpdate,
+    MessageType, UserId,
 };
 use sqlx::{query, query_file_as, query_file_scalar, query_scalar, Acquire};
 use tracing::info;
 use uuid::Uuid;
 
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::gen_paginate;
 use crate::types::{
     DbMessageCreate, Message, MessageId, MessageVerId, PaginationDirection, PaginationQuery,
@@ -39,6 +39,9 @@ pub struct DbMessage {
     pub edited_at: Option<time::PrimitiveDateTime>,
     pub deleted_at: Option<time::PrimitiveDateTime>,
     pub removed_at: Option<time::PrimitiveDateTime>,
+    pub mentions: Option<serde_json::Value>,
+    pub pinned_at: Option<time::PrimitiveDateTime>,
+    pub pinned_order: Option<i32>,
 }
 
 #[derive(Debug, sqlx::Type)]
@@ -47,6 +50,10 @@ pub enum DbMessageType {
     DefaultMarkdown,
     DefaultTagged,
     ThreadUpdate,
+    ModerationReport,
+    MessagePinned,
+    MessageUnpinned,
+    MessagesMoved,
 }
 
 impl From<MessageType> for DbMessageType {
@@ -55,6 +62,10 @@ impl From<MessageType> for DbMessageType {
             MessageType::DefaultMarkdown(_) => DbMessageType::DefaultMarkdown,
             MessageType::DefaultTagged(_) => DbMessageType::DefaultTagged,
             MessageType::ThreadUpdate(_) => DbMessageType::ThreadUpdate,
+            MessageType::ModerationReport(_) => DbMessageType::ModerationReport,
+            MessageType::MessagePinned(_) => DbMessageType::MessagePinned,
+            MessageType::MessageUnpinned(_) => DbMessageType::MessageUnpinned,
+            MessageType::MessagesMoved(_) => DbMessageType::MessagesMoved,
             _ => todo!(),
         }
     }
@@ -111,16 +122,41 @@ impl From<DbMessage> for Message {
                         .and_then(|m| serde_json::from_value(m).ok())
                         .unwrap_or_default(),
                 }),
+                DbMessageType::ModerationReport => MessageType::ModerationReport(
+                    row.metadata
+                        .and_then(|m| serde_json::from_value(m).ok())
+                        .expect("invalid data in db!"),
+                ),
+                DbMessageType::MessagePinned => MessageType::MessagePinned(
+                    row.metadata
+                        .and_then(|m| serde_json::from_value(m).ok())
+                        .expect("invalid data in db!"),
+                ),
+                DbMessageType::MessageUnpinned => MessageType::MessageUnpinned(
+                    row.metadata
+                        .and_then(|m| serde_json::from_value(m).ok())
+                        .expect("invalid data in db!"),
+                ),
+                DbMessageType::MessagesMoved => MessageType::MessagesMoved(
+                    row.metadata
+                        .and_then(|m| serde_json::from_value(m).ok())
+                        .expect("invalid data in db!"),
+                ),
             },
             thread_id: row.thread_id,
             version_id: row.version_id,
             nonce: None,
             author_id: row.author_id,
-            mentions: Mentions::default(),
+            mentions: row
+                .mentions
+                .and_then(|m| serde_json::from_value(m).ok())
+                .unwrap_or_default(),
             deleted_at: row.deleted_at.map(Time::from),
             edited_at: row.edited_at.map(Time::from),
             created_at: row.created_at.map(Time::from),
             removed_at: row.removed_at.map(Time::from),
+            pinned_at: row.pinned_at.map(Time::from),
+            pinned_order: row.pinned_order.and_then(|o| o.try_into().ok()),
         }
     }
 }
@@ -130,11 +166,13 @@ impl DataMessage for Postgres {
     async fn message_create(&self, create: DbMessageCreate) -> Result<MessageId> {
         let message_id = Uuid::now_v7();
         let message_type: DbMessageType = create.message_type.clone().into();
-        let mut tx = self.pool.begin().await?;
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
         let embeds = serde_json::to_value(create.embeds.clone())?;
+        let mentions = serde_json::to_value(&create.mentions)?;
         query!(r#"
-    	    INSERT INTO message (id, thread_id, version_id, ordering, content, metadata, reply_id, author_id, type, override_name, is_latest, embeds, created_at)
-    	    VALUES ($1, $2, $3, (SELECT coalesce(max(ordering), 0) FROM message WHERE thread_id = $2), $4, $5, $6, $7, $8, $9, true, $10, coalesce($11, now()))
+    	    INSERT INTO message (id, thread_id, version_id, ordering, content, metadata, reply_id, author_id, type, override_name, is_latest, embeds, created_at, mentions)
+    	    VALUES ($1, $2, $3, (SELECT coalesce(max(ordering), 0) FROM message WHERE thread_id = $2), $4, $5, $6, $7, $8, $9, true, $10, coalesce($11, now()), $12)
         "#,
             message_id,
             create.thread_id.into_inner(),
@@ -147,6 +185,7 @@ impl DataMessage for Postgres {
             create.override_name(),
             embeds,
             create.created_at.map(|t| t.assume_utc()),
+            mentions,
         )
         .execute(&mut *tx)
         .await?;
@@ -176,7 +215,8 @@ impl DataMessage for Postgres {
     ) -> Result<MessageVerId> {
         let ver_id = Uuid::now_v7();
         let message_type: DbMessageType = create.message_type.clone().into();
-        let mut tx = self.pool.begin().await?;
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
         query!(
             r#"UPDATE message SET is_latest = false WHERE id = $1"#,
             message_id.into_inner(),
@@ -184,9 +224,10 @@ impl DataMessage for Postgres {
         .execute(&mut *tx)
         .await?;
         let embeds = serde_json::to_value(create.embeds.clone())?;
+        let mentions = serde_json::to_value(&create.mentions)?;
         query!(r#"
-    	    INSERT INTO message (id, thread_id, version_id, ordering, content, metadata, reply_id, author_id, type, override_name, is_latest, embeds, created_at, edited_at)
-    	    VALUES ($1, $2, $3, (SELECT coalesce(max(ordering), 0) FROM message WHERE thread_id = $2), $4, $5, $6, $7, $8, $9, true, $10, $11, coalesce($12, now()))
+    	    INSERT INTO message (id, thread_id, version_id, ordering, content, metadata, reply_id, author_id, type, override_name, is_latest, embeds, created_at, edited_at, mentions)
+    	    VALUES ($1, $2, $3, (SELECT coalesce(max(ordering), 0) FROM message WHERE thread_id = $2), $4, $5, $6, $7, $8, $9, true, $10, coalesce($11, (SELECT min(created_at) FROM message WHERE id = $1)), coalesce($12, now()), $13)
         "#,
             *message_id,
             *create.thread_id,
@@ -200,6 +241,7 @@ impl DataMessage for Postgres {
             embeds,
             create.created_at,
             create.edited_at.map(|t| t.assume_utc()),
+            mentions,
         )
         .execute(&mut *tx)
         .await?;
@@ -228,11 +270,30 @@ impl DataMessage for Postgres {
         user_id: UserId,
     ) -> Result<Message> {
         let row = query_file_as!(DbMessage, "sql/message_get.sql", *thread_id, *id, *user_id)
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *self.conn().await?)
             .await?;
         Ok(row.into())
     }
 
+    async fn message_get_many(
+        &self,
+        thread_id: ThreadId,
+        message_ids: &[MessageId],
+        user_id: UserId,
+    ) -> Result<Vec<Message>> {
+        let ids: Vec<Uuid> = message_ids.iter().map(|i| i.into_inner()).collect();
+        let rows = query_file_as!(
+            DbMessage,
+            "sql/message_get_many.sql",
+            *thread_id,
+            &ids[..],
+            *user_id
+        )
+        .fetch_all(&mut *self.conn().await?)
+        .await?;
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+
     async fn message_list(
         &self,
         thread_id: ThreadId,
@@ -242,7 +303,7 @@ impl DataMessage for Postgres {
         let p: Pagination<_> = pagination.try_into()?;
         gen_paginate!(
             p,
-            self.pool,
+            self,
             query_file_as!(
                 DbMessage,
                 r"sql/message_paginate.sql",
@@ -257,34 +318,54 @@ impl DataMessage for Postgres {
         )
     }
 
-    async fn message_delete(&self, _thread_id: ThreadId, message_id: MessageId) -> Result<()> {
+    async fn message_delete(&self, thread_id: ThreadId, message_id: MessageId) -> Result<()> {
         let now = time::OffsetDateTime::now_utc();
         let now = time::PrimitiveDateTime::new(now.date(), now.time());
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
         query!(
             "UPDATE message SET deleted_at = $2 WHERE id = $1",
             message_id.into_inner(),
             now,
         )
-        .execute(&self.pool)
+        .execute(&mut *tx)
+        .await?;
+        query!(
+            "DELETE FROM message_pin WHERE message_id = $1",
+            message_id.into_inner(),
+        )
+        .execute(&mut *tx)
         .await?;
+        pin_renumber(&mut tx, thread_id).await?;
+        tx.commit().await?;
         Ok(())
     }
 
     async fn message_delete_bulk(
         &self,
-        _thread_id: ThreadId,
+        thread_id: ThreadId,
         message_ids: &[MessageId],
     ) -> Result<()> {
         let now = time::OffsetDateTime::now_utc();
         let now = time::PrimitiveDateTime::new(now.date(), now.time());
         let ids: Vec<Uuid> = message_ids.iter().map(|i| i.into_inner()).collect();
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
         query!(
             "UPDATE message SET deleted_at = $2 WHERE id = ANY($1)",
             &ids[..],
             now,
         )
-        .execute(&self.pool)
+        .execute(&mut *tx)
+        .await?;
+        query!(
+            "DELETE FROM message_pin WHERE message_id = ANY($1)",
+            &ids[..]
+        )
+        .execute(&mut *tx)
         .await?;
+        pin_renumber(&mut tx, thread_id).await?;
+        tx.commit().await?;
         Ok(())
     }
 
@@ -301,7 +382,7 @@ impl DataMessage for Postgres {
             *version_id,
             *user_id,
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.conn().await?)
         .await?;
         Ok(row.into())
     }
@@ -318,7 +399,7 @@ impl DataMessage for Postgres {
             version_id.into_inner(),
             now
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
@@ -333,7 +414,7 @@ impl DataMessage for Postgres {
         let p: Pagination<_> = pagination.try_into()?;
         gen_paginate!(
             p,
-            self.pool,
+            self,
             query_file_as!(
                 DbMessage,
                 "sql/message_version_paginate.sql",
@@ -365,7 +446,7 @@ impl DataMessage for Postgres {
         let p: Pagination<_> = pagination.try_into()?;
         gen_paginate!(
             p,
-            self.pool,
+            self,
             query_file_as!(
                 DbMessage,
                 r"sql/message_replies.sql",
@@ -387,4 +468,178 @@ impl DataMessage for Postgres {
             )
         )
     }
+
+    async fn message_pin_create(
+        &self,
+        thread_id: ThreadId,
+        message_id: MessageId,
+        max_pins: usize,
+    ) -> Result<bool> {
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
+        // pins in a thread are counted one at a time, so they can't go over the limit together
+        query!("SELECT id FROM thread WHERE id = $1 FOR UPDATE", *thread_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        let pins = query_scalar!(
+            r#"SELECT count(*) AS "count!" FROM message_pin WHERE thread_id = $1"#,
+            *thread_id,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if pins as usize >= max_pins {
+            return Ok(false);
+        }
+        query!(
+            "UPDATE message_pin SET position = position + 1 WHERE thread_id = $1",
+            *thread_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        query!(
+            "INSERT INTO message_pin (message_id, thread_id, position) VALUES ($1, $2, 0)",
+            *message_id,
+            *thread_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn message_pin_delete(&self, thread_id: ThreadId, message_id: MessageId) -> Result<bool> {
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
+        query!("SELECT id FROM thread WHERE id = $1 FOR UPDATE", *thread_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        let deleted = query!(
+            "DELETE FROM message_pin WHERE thread_id = $1 AND message_id = $2",
+            *thread_id,
+            *message_id,
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        pin_renumber(&mut tx, thread_id).await?;
+        tx.commit().await?;
+        Ok(deleted > 0)
+    }
+
+    async fn message_pin_reorder(
+        &self,
+        thread_id: ThreadId,
+        message_ids: &[MessageId],
+    ) -> Result<()> {
+        let ids: Vec<Uuid> = message_ids.iter().map(|i| i.into_inner()).collect();
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
+        // pins can't be added or removed while checking that every one is included
+        query!("SELECT id FROM thread WHERE id = $1 FOR UPDATE", *thread_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        let mut pinned = query_scalar!(
+            "SELECT message_id FROM message_pin WHERE thread_id = $1",
+            *thread_id,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        let mut included = ids.clone();
+        pinned.sort();
+        included.sort();
+        if pinned != included {
+            return Err(Error::BadStatic("must include every pinned message"));
+        }
+        query!(
+            r#"
+            UPDATE message_pin SET position = p.position - 1
+            FROM unnest($2::uuid[]) WITH ORDINALITY AS p(message_id, position)
+            WHERE message_pin.thread_id = $1 AND message_pin.message_id = p.message_id
+            "#,
+            *thread_id,
+            &ids[..],
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn message_id_range(
+        &self,
+        thread_id: ThreadId,
+        start_id: MessageId,
+        end_id: MessageId,
+        limit: u16,
+    ) -> Result<Vec<MessageId>> {
+        let ids = query_scalar!(
+            r#"
+            SELECT id FROM message
+            WHERE thread_id = $1 AND is_latest AND deleted_at IS NULL AND id >= $2 AND id <= $3
+            ORDER BY id
+            LIMIT $4
+            "#,
+            *thread_id,
+            *start_id,
+            *end_id,
+            limit as i64,
+        )
+        .fetch_all(&mut *self.conn().await?)
+        .await?;
+        Ok(ids.into_iter().map(Into::into).collect())
+    }
+
+    async fn message_move(
+        &self,
+        source_id: ThreadId,
+        target_id: ThreadId,
+        message_ids: &[MessageId],
+    ) -> Result<()> {
+        let ids: Vec<Uuid> = message_ids.iter().map(|i| i.into_inner()).collect();
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
+        // every version is moved, attachments and reactions follow the message ids
+        query!(
+            "UPDATE message SET thread_id = $2 WHERE thread_id = $1 AND id = ANY($3)",
+            *source_id,
+            *target_id,
+            &ids[..],
+        )
+        .execute(&mut *tx)
+        .await?;
+        query!(
+            "DELETE FROM message_pin WHERE message_id = ANY($1)",
+            &ids[..]
+        )
+        .execute(&mut *tx)
+        .await?;
+        pin_renumber(&mut tx, source_id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn message_pin_list(&self, thread_id: ThreadId, user_id: UserId) -> Result<Vec<Message>> {
+        let rows = query_file_as!(DbMessage, "sql/message_pin_list.sql", *thread_id, *user_id)
+            .fetch_all(&mut *self.conn().await?)
+            .await?;
+        Ok(rows.into_iter().map(Into::into).collect())
+    }
+}
+
+/// close the gaps in a thread's pin list after pins are removed
+async fn pin_renumber(conn: &mut sqlx::PgConnection, thread_id: ThreadId) -> Result<()> {
+    query!(
+        r#"
+        UPDATE message_pin SET position = p.position
+        FROM (
+            SELECT message_id, row_number() OVER (ORDER BY position) - 1 AS position
+            FROM message_pin WHERE thread_id = $1
+        ) p
+        WHERE message_pin.message_id = p.message_id
+        "#,
+        *thread_id,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
 }
@@ -0,0 +1,362 @@
+use async_trait::async_trait;
+use common::v1::types::notifications::{Notification, NotifsRoom, NotifsThread};
+use common::v1::types::user_config::UserConfig;
+use common::v1::types::{NotificationId, PaginationDirection, PaginationQuery, PaginationResponse};
+use sqlx::{query, query_as, query_scalar, Acquire};
+use time::PrimitiveDateTime;
+use uuid::Uuid;
+
+use crate::data::DataNotification;
+use crate::error::Result;
+use crate::gen_paginate;
+use crate::types::{
+    DbNotificationQueue, DbNotificationReason, DbNotificationRecipient, MessageId, RoomId,
+    ThreadId, UserId,
+};
+
+use super::{Pagination, Postgres};
+
+pub struct DbNotification {
+    pub id: Uuid,
+    pub room_id: Option<Uuid>,
+    pub thread_id: Uuid,
+    pub message_id: Uuid,
+    pub reason: DbNotificationReason,
+    pub added_at: PrimitiveDateTime,
+    pub read_at: Option<PrimitiveDateTime>,
+}
+
+impl From<DbNotification> for Notification {
+    fn from(row: DbNotification) -> Self {
+        Notification {
+            id: row.id.into(),
+            room_id: row.room_id.map(Into::into),
+            thread_id: row.thread_id.into(),
+            message_id: row.message_id.into(),
+            reason: row.reason.into(),
+            created_at: row.added_at.into(),
+            read_at: row.read_at.map(Into::into),
+        }
+    }
+}
+
+#[async_trait]
+impl DataNotification for Postgres {
+    async fn notification_add(&self, notifs: Vec<(UserId, Notification)>) -> Result<()> {
+        let mut ids = Vec::with_capacity(notifs.len());
+        let mut user_ids = Vec::with_capacity(notifs.len());
+        let mut room_ids = Vec::with_capacity(notifs.len());
+        let mut thread_ids = Vec::with_capacity(notifs.len());
+        let mut message_ids = Vec::with_capacity(notifs.len());
+        let mut reasons = Vec::with_capacity(notifs.len());
+        let mut added_ats = Vec::with_capacity(notifs.len());
+        for (user_id, notif) in notifs {
+            ids.push(*notif.id);
+            user_ids.push(*user_id);
+            room_ids.push(notif.room_id.map(|id| *id));
+            thread_ids.push(*notif.thread_id);
+            message_ids.push(*notif.message_id);
+            reasons.push(DbNotificationReason::from(notif.reason));
+            added_ats.push(PrimitiveDateTime::from(notif.created_at));
+        }
+        query!(
+            r#"
+            INSERT INTO inbox (id, user_id, room_id, thread_id, message_id, reason, added_at)
+            SELECT * FROM unnest(
+                $1::uuid[], $2::uuid[], $3::uuid[], $4::uuid[], $5::uuid[],
+                $6::notification_reason[], $7::timestamp[]
+            )
+            "#,
+            &ids,
+            &user_ids,
+            &room_ids as &[Option<Uuid>],
+            &thread_ids,
+            &message_ids,
+            &reasons as &[DbNotificationReason],
+            &added_ats,
+        )
+        .execute(&mut *self.conn().await?)
+        .await?;
+        Ok(())
+    }
+
+    async fn notification_list(
+        &self,
+        user_id: UserId,
+        paginate: PaginationQuery<NotificationId>,
+        include_read: bool,
+    ) -> Result<PaginationResponse<Notification>> {
+        let p: Pagination<_> = paginate.try_into()?;
+        gen_paginate!(
+            p,
+            self,
+            query_as!(
+                DbNotification,
+                r#"
+                SELECT id, room_id, thread_id, message_id, reason as "reason: _", added_at, read_at
+                FROM inbox
+                WHERE user_id = $1 AND id > $2 AND id < $3 AND ($6 OR read_at IS NULL)
+                ORDER BY (CASE WHEN $4 = 'f' THEN id END), id DESC LIMIT $5
+                "#,
+                *user_id,
+                *p.after,
+                *p.before,
+                p.dir.to_string(),
+                (p.limit + 1) as i32,
+                include_read,
+            ),
+            query_scalar!(
+                "SELECT count(*) FROM inbox WHERE user_id = $1 AND ($2 OR read_at IS NULL)",
+                *user_id,
+                include_read,
+            )
+        )
+    }
+
+    async fn notification_mark_read(&self, user_id: UserId, ids: &[NotificationId]) -> Result<()> {
+        let ids: Vec<Uuid> = ids.iter().map(|id| **id).collect();
+        query!(
+            r#"
+            UPDATE inbox SET read_at = now()
+            WHERE user_id = $1 AND id = ANY($2) AND read_at IS NULL
+            "#,
+            *user_id,
+            &ids,
+        )
+        .execute(&mut *self.conn().await?)
+        .await?;
+        Ok(())
+    }
+
+    async fn notification_mark_unread(
+        &self,
+        user_id: UserId,
+        ids: &[NotificationId],
+    ) -> Result<()> {
+        let ids: Vec<Uuid> = ids.iter().map(|id| **id).collect();
+        query!(
+            "UPDATE inbox SET read_at = null WHERE user_id = $1 AND id = ANY($2)",
+            *user_id,
+            &ids,
+        )
+        .execute(&mut *self.conn().await?)
+        .await?;
+        Ok(())
+    }
+
+    async fn notification_recipients(
+        &self,
+        thread_id: ThreadId,
+    ) -> Result<Vec<DbNotificationRecipient>> {
+        let rows = query!(
+            r#"
+            WITH recipient AS (
+                SELECT m.user_id, coalesce(array_agg(r.id) FILTER (WHERE r.id IS NOT NULL), '{}') as roles
+                FROM thread t
+                JOIN room_member m ON m.room_id = t.room_id AND m.membership = 'Join'
+                LEFT JOIN role_member rm ON rm.user_id = m.user_id
+                LEFT JOIN role r ON r.id = rm.role_id AND r.room_id = t.room_id
+                WHERE t.id = $1
+                GROUP BY m.user_id
+                UNION ALL
+                SELECT tm.user_id, '{}'
+                FROM thread t
+                JOIN thread_member tm ON tm.thread_id = t.id AND tm.membership = 'Join'
+                WHERE t.id = $1 AND t.room_id IS NULL
+            )
+            SELECT
+                recipient.user_id as "user_id!",
+                recipient.roles as "roles!",
+                usr.config as global_config,
+                room_config.config as "room_config?",
+                thread_config.config as "thread_config?"
+            FROM recipient
+            JOIN usr ON usr.id = recipient.user_id
+            JOIN thread ON thread.id = $1
+            LEFT JOIN notification_config_room room_config
+                ON room_config.user_id = recipient.user_id AND room_config.room_id = thread.room_id
+            LEFT JOIN notification_config_thread thread_config
+                ON thread_config.user_id = recipient.user_id AND thread_config.thread_id = thread.id
+            "#,
+            *thread_id,
+        )
+        .fetch_all(&mut *self.conn().await?)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| DbNotificationRecipient {
+                user_id: row.user_id.into(),
+                roles: row.roles.into_iter().map(Into::into).collect(),
+                global: row
+                    .global_config
+                    .and_then(|v| serde_json::from_value::<UserConfig>(v).ok())
+                    .unwrap_or_default()
+                    .notifs,
+                room: row
+                    .room_config
+                    .and_then(|v| serde_json::from_value(v).ok())
+                    .unwrap_or_default(),
+                thread: row
+                    .thread_config
+                    .and_then(|v| serde_json::from_value(v).ok())
+                    .unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    async fn notification_config_room_get(
+        &self,
+        user_id: UserId,
+        room_id: RoomId,
+    ) -> Result<NotifsRoom> {
+        let conf = query_scalar!(
+            "SELECT config FROM notification_config_room WHERE user_id = $1 AND room_id = $2",
+            *user_id,
+            *room_id,
+        )
+        .fetch_optional(&mut *self.conn().await?)
+        .await?;
+        let conf = conf
+            .map(serde_json::from_value)
+            .and_then(|v| v.ok())
+            .unwrap_or_default();
+        Ok(conf)
+    }
+
+    async fn notification_config_room_set(
+        &self,
+        user_id: UserId,
+        room_id: RoomId,
+        config: &NotifsRoom,
+    ) -> Result<()> {
+        query!(
+            r#"
+            INSERT INTO notification_config_room (user_id, room_id, config)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id, room_id) DO UPDATE SET config = excluded.config
+            "#,
+            *user_id,
+            *room_id,
+            serde_json::to_value(config)?,
+        )
+        .execute(&mut *self.conn().await?)
+        .await?;
+        Ok(())
+    }
+
+    async fn notification_config_thread_get(
+        &self,
+        user_id: UserId,
+        thread_id: ThreadId,
+    ) -> Result<NotifsThread> {
+        let conf = query_scalar!(
+            "SELECT config FROM notification_config_thread WHERE user_id = $1 AND thread_id = $2",
+            *user_id,
+            *thread_id,
+        )
+        .fetch_optional(&mut *self.conn().await?)
+        .await?;
+        let conf = conf
+            .map(serde_json::from_value)
+            .and_then(|v| v.ok())
+            .unwrap_or_default();
+        Ok(conf)
+    }
+
+    async fn notification_config_thread_set(
+        &self,
+        user_id: UserId,
+        thread_id: ThreadId,
+        config: &NotifsThread,
+    ) -> Result<()> {
+        query!(
+            r#"
+            INSERT INTO notification_config_thread (user_id, thread_id, config)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id, thread_id) DO UPDATE SET config = excluded.config
+            "#,
+            *user_id,
+            *thread_id,
+            serde_json::to_value(config)?,
+        )
+        .execute(&mut *self.conn().await?)
+        .await?;
+        Ok(())
+    }
+
+    async fn notification_queue_insert(
+        &self,
+        thread_id: ThreadId,
+        message_id: MessageId,
+        author_id: UserId,
+    ) -> Result<()> {
+        query!(
+            r#"
+            INSERT INTO notification_queue (message_id, thread_id, author_id)
+            VALUES ($1, $2, $3)
+            "#,
+            *message_id,
+            *thread_id,
+            *author_id,
+        )
+        .execute(&mut *self.conn().await?)
+        .await?;
+        Ok(())
+    }
+
+    async fn notification_queue_claim(&self) -> Result<Option<DbNotificationQueue>> {
+        let row = query!(
+            r#"
+            UPDATE notification_queue
+            SET status = 'claimed', claimed_at = NOW()
+            WHERE message_id = (
+                SELECT message_id
+                FROM notification_queue
+                WHERE status = 'pending'
+                    OR (status = 'claimed' AND claimed_at < NOW() - INTERVAL '5 minutes')
+                    OR (status = 'failed' AND retries < 5 AND last_attempt_at < NOW() - INTERVAL '1 minute')
+                ORDER BY created_at ASC
+                FOR UPDATE SKIP LOCKED
+                LIMIT 1
+            )
+            RETURNING message_id, thread_id, author_id
+            "#
+        )
+        .fetch_optional(&mut *self.conn().await?)
+        .await?;
+        Ok(row.map(|row| DbNotificationQueue {
+            message_id: row.message_id.into(),
+            thread_id: row.thread_id.into(),
+            author_id: row.author_id.into(),
+        }))
+    }
+
+    async fn notification_queue_finish(&self, message_id: MessageId) -> Result<()> {
+        query!(
+            "DELETE FROM notification_queue WHERE message_id = $1",
+            *message_id
+        )
+        .execute(&mut *self.conn().await?)
+        .await?;
+        Ok(())
+    }
+
+    async fn notification_queue_fail(
+        &self,
+        error_message: String,
+        message_id: MessageId,
+    ) -> Result<()> {
+        query!(
+            r#"
+            UPDATE notification_queue
+            SET status = 'failed', retries = retries + 1, last_attempt_at = NOW(), error_message = $1
+            WHERE message_id = $2
+            "#,
+            error_message,
+            *message_id,
+        )
+        .execute(&mut *self.conn().await?)
+        .await?;
+        Ok(())
+    }
+}
@@ -5,7 +5,7 @@ use uuid::Uuid;
 
 use crate::{
     data::DataPermission,
-    types::{DbPermission, Permissions},
+    types::{DbPermission, DbReportDestination, Permissions},
     Result,
 };
 
@@ -21,9 +21,11 @@ impl DataPermission for Postgres {
                 FROM room_member AS m
                 JOIN role_member AS r ON r.user_id = m.user_id
                 JOIN role ON r.role_id = role.id AND role.room_id = m.room_id
+                WHERE m.membership != 'Ban'
                 UNION
                 SELECT room_id, user_id, 'View' AS permission
                 FROM room_member
+                WHERE membership != 'Ban'
             )
             SELECT permission as "permission!: DbPermission"
             FROM perms
@@ -32,7 +34,7 @@ impl DataPermission for Postgres {
             user_id.into_inner(),
             room_id.into_inner()
         )
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.conn().await?)
         .await?
         .into_iter()
         .map(Into::into)
@@ -51,7 +53,7 @@ impl DataPermission for Postgres {
             room_id.into_inner(),
             user_id.into_inner(),
         )
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.conn().await?)
         .await?;
 
         for overwrite in role_overwrites {
@@ -75,7 +77,7 @@ impl DataPermission for Postgres {
             room_id.into_inner(),
             user_id.into_inner(),
         )
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.conn().await?)
         .await?;
 
         if let Some(overwrite) = user_overwrites {
@@ -99,7 +101,7 @@ impl DataPermission for Postgres {
             "SELECT room_id FROM thread WHERE id = $1",
             thread_id.into_inner()
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.conn().await?)
         .await?;
 
         let mut perms = if let Some(room_id_uuid) = room_id {
@@ -122,7 +124,7 @@ impl DataPermission for Postgres {
             thread_id.into_inner(),
             user_id.into_inner(),
         )
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.conn().await?)
         .await?;
 
         for overwrite in role_overwrites {
@@ -146,7 +148,7 @@ impl DataPermission for Postgres {
             thread_id.into_inner(),
             user_id.into_inner(),
         )
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.conn().await?)
         .await?;
 
         if let Some(overwrite) = user_overwrites {
@@ -158,6 +160,24 @@ impl DataPermission for Postgres {
             }
         }
 
+        // report threads are only visible to moderators
+        let report_destination = query_scalar!(
+            r#"SELECT destination as "destination: DbReportDestination" FROM report_thread WHERE thread_id = $1"#,
+            thread_id.into_inner()
+        )
+        .fetch_optional(&mut *self.conn().await?)
+        .await?;
+
+        if let Some(destination) = report_destination {
+            let required = match destination {
+                DbReportDestination::Room => Permission::MemberBan,
+                DbReportDestination::Server => Permission::ServerReports,
+            };
+            if !perms.has(required) {
+                return Ok(Permissions::empty());
+            }
+        }
+
         Ok(perms.into_iter().collect())
     }
 
@@ -172,7 +192,7 @@ impl DataPermission for Postgres {
             a.into_inner(),
             b.into_inner(),
         )
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.conn().await?)
         .await?
         .is_some();
         Ok(exists)
@@ -201,7 +221,7 @@ impl DataPermission for Postgres {
             serde_json::to_value(&allow)?,
             serde_json::to_value(&deny)?,
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
@@ -216,7 +236,7 @@ impl DataPermission for Postgres {
             *thread_id,
             overwrite_id,
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
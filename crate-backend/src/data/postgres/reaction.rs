@@ -51,7 +51,7 @@ impl DataReaction for Postgres {
             key,
             emoji_id,
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         // let mut tx = self.pool.begin().await?;
         // let pos_new  = query_scalar!(
@@ -107,7 +107,7 @@ impl DataReaction for Postgres {
             user_id.into_inner(),
             key,
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
@@ -127,7 +127,7 @@ impl DataReaction for Postgres {
 
         gen_paginate!(
             p,
-            self.pool,
+            self,
             query_as!(
                 ReactionListItem,
                 r#"
@@ -155,7 +155,7 @@ impl DataReaction for Postgres {
             r#"DELETE FROM reaction WHERE message_id = $1"#,
             message_id.into_inner(),
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
@@ -175,7 +175,7 @@ impl DataReaction for Postgres {
             message_id.into_inner(),
             key,
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
@@ -0,0 +1,166 @@
+use async_trait::async_trait;
+use common::v1::types::moderation::{
+    Report, ReportCreate, ReportDestination, ReportStatus, ReportTarget,
+};
+use common::v1::types::ReportId;
+use sqlx::{query, query_as, query_scalar, types::Json, Acquire};
+use uuid::Uuid;
+
+use crate::data::DataReport;
+use crate::error::Result;
+use crate::types::{DbReportDestination, DbReportStatus, ThreadId, ThreadVerId, UserId};
+
+use super::Postgres;
+
+pub struct DbReport {
+    pub id: Uuid,
+    pub reporter_id: Uuid,
+    pub reason: serde_json::Value,
+    pub note: Option<String>,
+    pub destination: DbReportDestination,
+    pub target: serde_json::Value,
+}
+
+impl From<DbReport> for Report {
+    fn from(row: DbReport) -> Self {
+        Report {
+            id: row.id.into(),
+            reporter_id: row.reporter_id.into(),
+            reason: serde_json::from_value(row.reason).expect("invalid data in db!"),
+            note: row.note,
+            destination: row.destination.into(),
+            target: serde_json::from_value(row.target).expect("invalid data in db!"),
+        }
+    }
+}
+
+#[async_trait]
+impl DataReport for Postgres {
+    async fn report_thread_find(
+        &self,
+        destination: ReportDestination,
+        target: &ReportTarget,
+    ) -> Result<Option<ThreadId>> {
+        let thread_id = query_scalar!(
+            r#"
+            SELECT thread_id FROM report_thread
+            WHERE destination = $1 AND target = $2 AND status = 'Open'
+            "#,
+            DbReportDestination::from(destination) as _,
+            Json(target) as _,
+        )
+        .fetch_optional(&mut *self.conn().await?)
+        .await?;
+        Ok(thread_id.map(Into::into))
+    }
+
+    async fn report_thread_create(
+        &self,
+        thread_id: ThreadId,
+        destination: ReportDestination,
+        target: &ReportTarget,
+    ) -> Result<bool> {
+        let res = query!(
+            r#"
+            INSERT INTO report_thread (thread_id, destination, target, status)
+            VALUES ($1, $2, $3, 'Open')
+            ON CONFLICT (destination, target) WHERE status = 'Open' DO NOTHING
+            "#,
+            *thread_id,
+            DbReportDestination::from(destination) as _,
+            Json(target) as _,
+        )
+        .execute(&mut *self.conn().await?)
+        .await?;
+        Ok(res.rows_affected() == 1)
+    }
+
+    async fn report_thread_set_status(
+        &self,
+        thread_id: ThreadId,
+        status: ReportStatus,
+    ) -> Result<()> {
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
+        query!(
+            "UPDATE report_thread SET status = $2 WHERE thread_id = $1",
+            *thread_id,
+            DbReportStatus::from(status) as _,
+        )
+        .execute(&mut *tx)
+        .await?;
+        query!(
+            "UPDATE thread SET version_id = $2 WHERE id = $1",
+            *thread_id,
+            *ThreadVerId::new(),
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn report_create(
+        &self,
+        thread_id: ThreadId,
+        reporter_id: UserId,
+        create: ReportCreate,
+    ) -> Result<Report> {
+        let report_id = ReportId::new();
+        query!(
+            r#"
+            INSERT INTO report (id, thread_id, reporter_id, reason, note)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            *report_id,
+            *thread_id,
+            *reporter_id,
+            Json(&create.reason) as _,
+            create.note,
+        )
+        .execute(&mut *self.conn().await?)
+        .await?;
+        self.report_get(report_id).await
+    }
+
+    async fn report_get(&self, report_id: ReportId) -> Result<Report> {
+        let report = query_as!(
+            DbReport,
+            r#"
+            SELECT
+                r.id, r.reporter_id, r.reason, r.note,
+                t.destination as "destination: _", t.target
+            FROM report r
+            JOIN report_thread t ON t.thread_id = r.thread_id
+            WHERE r.id = $1
+            "#,
+            *report_id,
+        )
+        .fetch_one(&mut *self.conn().await?)
+        .await?;
+        Ok(report.into())
+    }
+
+    async fn report_get_by_reporter(
+        &self,
+        thread_id: ThreadId,
+        reporter_id: UserId,
+    ) -> Result<Option<Report>> {
+        let report = query_as!(
+            DbReport,
+            r#"
+            SELECT
+                r.id, r.reporter_id, r.reason, r.note,
+                t.destination as "destination: _", t.target
+            FROM report r
+            JOIN report_thread t ON t.thread_id = r.thread_id
+            WHERE r.thread_id = $1 AND r.reporter_id = $2
+            "#,
+            *thread_id,
+            *reporter_id,
+        )
+        .fetch_optional(&mut *self.conn().await?)
+        .await?;
+        Ok(report.map(Into::into))
+    }
+}
@@ -22,11 +22,11 @@ impl DataRole for Postgres {
         let role_id = Uuid::now_v7();
         let perms: Vec<DbPermission> = create.permissions.into_iter().map(Into::into).collect();
         let role = query_as!(DbRole, r#"
-            INSERT INTO role (id, version_id, room_id, name, description, permissions, is_mentionable, is_self_applicable, is_default)
-            VALUES ($1, $1, $2, $3, $4, $5, $6, $7, $8)
-            RETURNING id, version_id, room_id, name, description, permissions as "permissions: _", is_mentionable, is_self_applicable, is_default
-        "#, role_id, create.room_id.into_inner(), create.name, create.description, perms as _, create.is_mentionable, create.is_self_applicable, create.is_default)
-    	    .fetch_one(&self.pool)
+            INSERT INTO role (id, version_id, room_id, name, description, permissions, is_mentionable, is_self_applicable, is_default, position)
+            VALUES ($1, $1, $2, $3, $4, $5, $6, $7, $8, $9)
+            RETURNING id, version_id, room_id, name, description, permissions as "permissions: _", is_mentionable, is_self_applicable, is_default, position
+        "#, role_id, create.room_id.into_inner(), create.name, create.description, perms as _, create.is_mentionable, create.is_self_applicable, create.is_default, create.position as i64)
+    	    .fetch_one(&mut *self.conn().await?)
         	.await?;
         info!("inserted role");
         Ok(role.into())
@@ -40,7 +40,7 @@ impl DataRole for Postgres {
         let p: Pagination<_> = paginate.try_into()?;
         gen_paginate!(
             p,
-            self.pool,
+            self,
             query_as!(
                 DbRole,
                 r#"
@@ -53,7 +53,8 @@ impl DataRole for Postgres {
                 	version_id,
                 	room_id,
                 	is_self_applicable,
-                	name
+                	name,
+                	position
                 FROM role
             	WHERE room_id = $1 AND id > $2 AND id < $3
             	ORDER BY (CASE WHEN $4 = 'f' THEN id END), id DESC LIMIT $5
@@ -72,7 +73,7 @@ impl DataRole for Postgres {
     }
 
     async fn role_delete(&self, _room_id: RoomId, role_id: RoleId) -> Result<()> {
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.conn().await?;
         let mut tx = conn.begin().await?;
         query!(
             "DELETE FROM role_member WHERE role_id = $1",
@@ -89,11 +90,11 @@ impl DataRole for Postgres {
 
     async fn role_select(&self, room_id: RoomId, role_id: RoleId) -> Result<Role> {
         let role = query_as!(DbRole, r#"
-            SELECT id, version_id, room_id, name, description, permissions as "permissions: _", is_mentionable, is_self_applicable, is_default
+            SELECT id, version_id, room_id, name, description, permissions as "permissions: _", is_mentionable, is_self_applicable, is_default, position
             FROM role
             WHERE room_id = $1 AND id = $2
         "#, room_id.into_inner(), role_id.into_inner())
-    	    .fetch_one(&self.pool)
+    	    .fetch_one(&mut *self.conn().await?)
         	.await?;
         Ok(role.into())
     }
@@ -104,13 +105,13 @@ impl DataRole for Postgres {
         role_id: RoleId,
         patch: RolePatch,
     ) -> Result<RoleVerId> {
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.conn().await?;
         let mut tx = conn.begin().await?;
         let perms = patch
             .permissions
             .map(|p| p.into_iter().map(Into::into).collect());
         let role = query_as!(DbRole, r#"
-            SELECT id, version_id, room_id, name, description, permissions as "permissions: _", is_mentionable, is_self_applicable, is_default
+            SELECT id, version_id, room_id, name, description, permissions as "permissions: _", is_mentionable, is_self_applicable, is_default, position
             FROM role
             WHERE room_id = $1 AND id = $2
             FOR UPDATE
@@ -127,7 +128,8 @@ impl DataRole for Postgres {
                 permissions = $5,
                 is_mentionable = $6,
                 is_self_applicable = $7,
-                is_default = $8
+                is_default = $8,
+                position = $9
             WHERE id = $1
         "#,
             role_id.into_inner(),
@@ -138,6 +140,7 @@ impl DataRole for Postgres {
             patch.is_mentionable.unwrap_or(role.is_mentionable),
             patch.is_self_applicable.unwrap_or(role.is_self_applicable),
             patch.is_default.unwrap_or(role.is_default),
+            patch.position.map(|p| p as i64).unwrap_or(role.position),
         )
         .execute(&mut *tx)
         .await?;
@@ -156,8 +159,23 @@ impl DataRole for Postgres {
             room_id.into_inner(),
             user_id.into_inner()
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
+
+    async fn role_position_max(&self, room_id: RoomId, user_id: UserId) -> Result<u64> {
+        let position = query_scalar!(
+            r#"
+            SELECT max(role.position) FROM role_member
+            JOIN role ON role.id = role_member.role_id
+            WHERE role.room_id = $1 AND role_member.user_id = $2
+            "#,
+            room_id.into_inner(),
+            user_id.into_inner(),
+        )
+        .fetch_one(&mut *self.conn().await?)
+        .await?;
+        Ok(position.unwrap_or(0) as u64)
+    }
 }
@@ -14,7 +14,7 @@ use super::{Pagination, Postgres};
 #[async_trait]
 impl DataRoleMember for Postgres {
     async fn role_member_put(&self, user_id: UserId, role_id: RoleId) -> Result<()> {
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.conn().await?;
         query!(
             "INSERT INTO role_member (user_id, role_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
             user_id.into_inner(),
@@ -27,7 +27,7 @@ impl DataRoleMember for Postgres {
     }
 
     async fn role_member_delete(&self, user_id: UserId, role_id: RoleId) -> Result<()> {
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.conn().await?;
         query!(
             "DELETE FROM role_member WHERE role_id = $1 AND user_id = $2",
             role_id.into_inner(),
@@ -45,7 +45,7 @@ impl DataRoleMember for Postgres {
         paginate: PaginationQuery<UserId>,
     ) -> Result<PaginationResponse<RoomMember>> {
         let p: Pagination<_> = paginate.try_into()?;
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.conn().await?;
         let mut tx = conn.begin().await?;
         let items = query_as!(
             DbRoomMember,
@@ -106,7 +106,7 @@ impl DataRoleMember for Postgres {
             "SELECT count(*) FROM role_member WHERE role_id = $1",
             role_id.into_inner()
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.conn().await?)
         .await?;
         Ok(total.unwrap_or(0) as u64)
     }
@@ -16,21 +16,21 @@ use super::{Pagination, Postgres};
 
 #[async_trait]
 impl DataRoom for Postgres {
-    async fn room_create(&self, create: RoomCreate) -> Result<Room> {
-        let mut conn = self.pool.acquire().await?;
+    async fn room_create(&self, create: RoomCreate, owner_id: Option<UserId>) -> Result<Room> {
         let room_id = Uuid::now_v7();
         query!(
             "
-    	    INSERT INTO room (id, version_id, name, description, icon)
-    	    VALUES ($1, $2, $3, $4, $5)
+    	    INSERT INTO room (id, version_id, name, description, icon, owner_id)
+    	    VALUES ($1, $2, $3, $4, $5, $6)
         ",
             room_id,
             room_id,
             create.name,
             create.description,
             create.icon.map(|i| *i),
+            owner_id.map(|id| id.into_inner()),
         )
-        .execute(&mut *conn)
+        .execute(&mut *self.conn().await?)
         .await?;
         info!("inserted room");
         self.room_get(room_id.into()).await
@@ -38,7 +38,7 @@ impl DataRoom for Postgres {
 
     async fn room_get(&self, id: RoomId) -> Result<Room> {
         let id: Uuid = id.into();
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.conn().await?;
         let room = query_as!(
             DbRoom,
             r#"
@@ -48,6 +48,7 @@ impl DataRoom for Postgres {
                 room.name,
                 room.description,
                 room.icon,
+                room.owner_id,
                 NULL::uuid as dm_uid_a,
                 NULL::uuid as dm_uid_b
             FROM room
@@ -68,7 +69,7 @@ impl DataRoom for Postgres {
         let p: Pagination<_> = pagination.try_into()?;
         gen_paginate!(
             p,
-            self.pool,
+            self,
             query_as!(
                 DbRoom,
                 r#"
@@ -78,6 +79,7 @@ impl DataRoom for Postgres {
                     room.name,
                     room.description,
                     room.icon,
+                    room.owner_id,
                     NULL::uuid as dm_uid_a,
                 NULL::uuid as dm_uid_b
                 FROM room_member
@@ -99,7 +101,7 @@ impl DataRoom for Postgres {
     }
 
     async fn room_update(&self, id: RoomId, patch: RoomPatch) -> Result<RoomVerId> {
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.conn().await?;
         let mut tx = conn.begin().await?;
         let room = query!(
             r#"
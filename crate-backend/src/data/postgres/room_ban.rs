@@ -0,0 +1,160 @@
+use async_trait::async_trait;
+use common::v1::types::util::Time;
+use common::v1::types::{PaginationDirection, PaginationQuery, PaginationResponse, RoomBan};
+use sqlx::{query, query_as, query_scalar, Acquire};
+use time::PrimitiveDateTime;
+use uuid::Uuid;
+
+use crate::data::DataRoomBan;
+use crate::error::Result;
+use crate::gen_paginate;
+use crate::types::{DbMembership, RoomId, UserId};
+
+use super::{Pagination, Postgres};
+
+pub struct DbRoomBan {
+    pub user_id: Uuid,
+    pub room_id: Uuid,
+    pub creator_id: Uuid,
+    pub reason: Option<String>,
+    pub created_at: PrimitiveDateTime,
+    pub expires_at: Option<PrimitiveDateTime>,
+}
+
+impl From<DbRoomBan> for RoomBan {
+    fn from(row: DbRoomBan) -> Self {
+        RoomBan {
+            user_id: row.user_id.into(),
+            room_id: row.room_id.into(),
+            creator_id: row.creator_id.into(),
+            reason: row.reason,
+            created_at: row.created_at.into(),
+            expires_at: row.expires_at.map(Into::into),
+        }
+    }
+}
+
+#[async_trait]
+impl DataRoomBan for Postgres {
+    async fn room_ban_create(
+        &self,
+        room_id: RoomId,
+        user_id: UserId,
+        creator_id: UserId,
+        reason: Option<String>,
+        expires_at: Option<Time>,
+    ) -> Result<()> {
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
+        query!(
+            r#"
+            INSERT INTO room_ban (room_id, user_id, creator_id, reason, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT ON CONSTRAINT room_ban_pkey DO UPDATE SET
+                creator_id = excluded.creator_id,
+                reason = excluded.reason,
+                created_at = now(),
+                expires_at = excluded.expires_at
+            "#,
+            *room_id,
+            *user_id,
+            *creator_id,
+            reason,
+            expires_at.map(PrimitiveDateTime::from),
+        )
+        .execute(&mut *tx)
+        .await?;
+        query!(
+            r#"
+            INSERT INTO room_member (user_id, room_id, membership)
+            VALUES ($1, $2, $3)
+            ON CONFLICT ON CONSTRAINT room_member_pkey DO UPDATE SET
+                membership = excluded.membership,
+                membership_updated_at = now()
+            "#,
+            *user_id,
+            *room_id,
+            DbMembership::Ban as _,
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn room_ban_delete(&self, room_id: RoomId, user_id: UserId) -> Result<()> {
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
+        query!(
+            "DELETE FROM room_ban WHERE room_id = $1 AND user_id = $2",
+            *room_id,
+            *user_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        query!(
+            r#"
+            UPDATE room_member
+            SET membership = 'Leave', membership_updated_at = now()
+            WHERE room_id = $1 AND user_id = $2 AND membership = 'Ban'
+            "#,
+            *room_id,
+            *user_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn room_ban_get(&self, room_id: RoomId, user_id: UserId) -> Result<RoomBan> {
+        let row = query_as!(
+            DbRoomBan,
+            r#"
+            SELECT user_id, room_id, creator_id, reason, created_at, expires_at
+            FROM room_ban
+            WHERE room_id = $1 AND user_id = $2
+              AND (expires_at IS NULL OR expires_at > now())
+            "#,
+            *room_id,
+            *user_id,
+        )
+        .fetch_one(&mut *self.conn().await?)
+        .await?;
+        Ok(row.into())
+    }
+
+    async fn room_ban_list(
+        &self,
+        room_id: RoomId,
+        paginate: PaginationQuery<UserId>,
+    ) -> Result<PaginationResponse<RoomBan>> {
+        let p: Pagination<_> = paginate.try_into()?;
+        gen_paginate!(
+            p,
+            self,
+            query_as!(
+                DbRoomBan,
+                r#"
+                SELECT user_id, room_id, creator_id, reason, created_at, expires_at
+                FROM room_ban
+                WHERE room_id = $1 AND user_id > $2 AND user_id < $3
+                  AND (expires_at IS NULL OR expires_at > now())
+                ORDER BY (CASE WHEN $4 = 'f' THEN user_id END), user_id DESC LIMIT $5
+                "#,
+                *room_id,
+                *p.after,
+                *p.before,
+                p.dir.to_string(),
+                (p.limit + 1) as i32
+            ),
+            query_scalar!(
+                r#"
+                SELECT count(*) FROM room_ban
+                WHERE room_id = $1 AND (expires_at IS NULL OR expires_at > now())
+                "#,
+                *room_id
+            )
+        )
+    }
+}
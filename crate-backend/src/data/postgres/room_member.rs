@@ -7,7 +7,7 @@ use sqlx::{query, query_as, query_scalar, Acquire};
 use tracing::info;
 use uuid::Uuid;
 
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::gen_paginate;
 use crate::types::{DbMembership, RoomId, UserId};
 
@@ -54,6 +54,23 @@ impl DataRoomMember for Postgres {
         membership: RoomMembership,
     ) -> Result<()> {
         let membership: DbMembership = membership.into();
+        if membership == DbMembership::Join {
+            let is_banned = query_scalar!(
+                r#"
+                SELECT 1 FROM room_ban
+                WHERE room_id = $1 AND user_id = $2
+                  AND (expires_at IS NULL OR expires_at > now())
+                "#,
+                room_id.into_inner(),
+                user_id.into_inner(),
+            )
+            .fetch_optional(&mut *self.conn().await?)
+            .await?
+            .is_some();
+            if is_banned {
+                return Err(Error::Banned);
+            }
+        }
         query!(
             r#"
             INSERT INTO room_member (user_id, room_id, membership)
@@ -65,7 +82,7 @@ impl DataRoomMember for Postgres {
             room_id.into_inner(),
             membership as _
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         info!("inserted room member");
         Ok(())
@@ -77,7 +94,7 @@ impl DataRoomMember for Postgres {
             room_id.into_inner(),
             user_id.into_inner(),
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         info!("deleted room member");
         Ok(())
@@ -91,7 +108,7 @@ impl DataRoomMember for Postgres {
         let p: Pagination<_> = pagination.try_into()?;
         gen_paginate!(
             p,
-            self.pool,
+            self,
             query_as!(
                 DbRoomMember,
                 r#"
@@ -150,7 +167,7 @@ impl DataRoomMember for Postgres {
             room_id.into_inner(),
             user_id.into_inner(),
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.conn().await?)
         .await?;
         Ok(item.into())
     }
@@ -161,7 +178,7 @@ impl DataRoomMember for Postgres {
         user_id: UserId,
         patch: RoomMemberPatch,
     ) -> Result<()> {
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.conn().await?;
         let mut tx = conn.begin().await?;
         let item = query_as!(
             DbRoomMember,
@@ -224,7 +241,7 @@ impl DataRoomMember for Postgres {
             user_id.into_inner(),
             membership as _,
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
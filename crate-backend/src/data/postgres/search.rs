@@ -1,14 +1,20 @@
 use async_trait::async_trait;
-use common::v1::types::search::SearchMessageRequest;
+use common::v1::types::search::{
+    SearchMessageFeatures, SearchMessageRequest, SearchOrder, SearchRoomFeatures,
+    SearchRoomsRequest, SearchThreadFeatures, SearchThreadsRequest,
+};
 use common::v1::types::{
-    Message, MessageId, PaginationDirection, PaginationQuery, PaginationResponse,
+    Message, MessageId, PaginationDirection, PaginationQuery, PaginationResponse, Room, RoomId,
+    Thread, ThreadId,
 };
 use sqlx::{query_file_as, query_file_scalar, Acquire};
+use uuid::Uuid;
 
 use crate::data::postgres::message::{DbMessage, DbMessageType};
 use crate::data::postgres::Pagination;
 use crate::error::Result;
 use crate::gen_paginate;
+use crate::types::{DbRoom, DbThread, DbThreadType};
 use common::v1::types::UserId;
 
 use crate::data::DataSearch;
@@ -23,10 +29,38 @@ impl DataSearch for Postgres {
         query: SearchMessageRequest,
         paginate: PaginationQuery<MessageId>,
     ) -> Result<PaginationResponse<Message>> {
-        let p: Pagination<MessageId> = paginate.try_into()?;
-        gen_paginate!(
+        let p = Pagination::for_search(paginate, &query.order_by)?;
+        let room_ids: Vec<Uuid> = query.room_id.iter().map(|id| id.into_inner()).collect();
+        let thread_ids: Vec<Uuid> = query.thread_id.iter().map(|id| id.into_inner()).collect();
+        let author_ids: Vec<Uuid> = query.user_id.iter().map(|id| id.into_inner()).collect();
+        let f = &query.features_message;
+        let mut mime_prefixes = vec![];
+        for (feature, prefix) in [
+            (SearchMessageFeatures::Attachment, ""),
+            (SearchMessageFeatures::Image, "image/"),
+            (SearchMessageFeatures::Audio, "audio/"),
+            (SearchMessageFeatures::Video, "video/"),
+        ] {
+            if f.contains(&feature) {
+                mime_prefixes.push(prefix.to_owned());
+            }
+        }
+        let is_pinned = f.contains(&SearchMessageFeatures::Pinned);
+        let has_link = f.contains(&SearchMessageFeatures::Link);
+        let has_embed = f.contains(&SearchMessageFeatures::Embed);
+        let include_ignored = f.contains(&SearchMessageFeatures::Ignored);
+        let include_blocked = f.contains(&SearchMessageFeatures::Blocked);
+        let include_all =
+            !thread_ids.is_empty() || query.features_thread.contains(&SearchThreadFeatures::All);
+        let include_muted = !room_ids.is_empty()
+            || !thread_ids.is_empty()
+            || query.features_thread.contains(&SearchThreadFeatures::Muted)
+            || query.features_room.contains(&SearchRoomFeatures::Muted);
+        let dm = dm_filter(&query.features_room);
+        let relevance = query.order_by == SearchOrder::Relevance;
+        let res: Result<PaginationResponse<Message>> = gen_paginate!(
             p,
-            self.pool,
+            self,
             query_file_as!(
                 DbMessage,
                 "sql/search_message.sql",
@@ -36,12 +70,141 @@ impl DataSearch for Postgres {
                 p.dir.to_string(),
                 (p.limit + 1) as i32,
                 query.query,
+                &room_ids,
+                &thread_ids,
+                &author_ids,
+                &mime_prefixes,
+                has_link,
+                has_embed,
+                include_ignored,
+                include_blocked,
+                include_all,
+                include_muted,
+                dm,
+                is_pinned,
+                relevance,
             ),
             query_file_scalar!(
                 "sql/search_message_count.sql",
                 user_id.into_inner(),
                 query.query,
+                &room_ids,
+                &thread_ids,
+                &author_ids,
+                &mime_prefixes,
+                has_link,
+                has_embed,
+                include_ignored,
+                include_blocked,
+                include_all,
+                include_muted,
+                dm,
+                is_pinned,
+            )
+        );
+        let mut res = res?;
+        p.sort_search(&mut res.items, &query.order_by);
+        Ok(res)
+    }
+
+    async fn search_thread(
+        &self,
+        user_id: UserId,
+        query: SearchThreadsRequest,
+        paginate: PaginationQuery<ThreadId>,
+    ) -> Result<PaginationResponse<Thread>> {
+        let p = Pagination::for_search(paginate, &query.order_by)?;
+        let include_all = query.features_thread.contains(&SearchThreadFeatures::All);
+        let include_muted = !query.room_id.is_empty()
+            || query.features_thread.contains(&SearchThreadFeatures::Muted)
+            || query.features_room.contains(&SearchRoomFeatures::Muted);
+        let room_ids: Vec<Uuid> = query.room_id.iter().map(|id| id.into_inner()).collect();
+        let tag_ids: Vec<Uuid> = query.tag_id.iter().map(|id| id.into_inner()).collect();
+        let dm = dm_filter(&query.features_room);
+        let relevance = query.order_by == SearchOrder::Relevance;
+        let res: Result<PaginationResponse<Thread>> = gen_paginate!(
+            p,
+            self,
+            query_file_as!(
+                DbThread,
+                "sql/search_thread.sql",
+                user_id.into_inner(),
+                p.after.into_inner(),
+                p.before.into_inner(),
+                p.dir.to_string(),
+                (p.limit + 1) as i32,
+                query.query,
+                include_all,
+                include_muted,
+                &room_ids,
+                &tag_ids,
+                dm,
+                relevance,
+            ),
+            query_file_scalar!(
+                "sql/search_thread_count.sql",
+                user_id.into_inner(),
+                query.query,
+                include_all,
+                include_muted,
+                &room_ids,
+                &tag_ids,
+                dm,
+            )
+        );
+        let mut res = res?;
+        p.sort_search(&mut res.items, &query.order_by);
+        Ok(res)
+    }
+
+    async fn search_room(
+        &self,
+        user_id: UserId,
+        query: SearchRoomsRequest,
+        paginate: PaginationQuery<RoomId>,
+    ) -> Result<PaginationResponse<Room>> {
+        let p = Pagination::for_search(paginate, &query.order_by)?;
+        // there are no discoverable public rooms yet, so `Public` doesn't add anything
+        let include_muted = query.features_room.contains(&SearchRoomFeatures::Muted);
+        let dm = dm_filter(&query.features_room);
+        let relevance = query.order_by == SearchOrder::Relevance;
+        let res: Result<PaginationResponse<Room>> = gen_paginate!(
+            p,
+            self,
+            query_file_as!(
+                DbRoom,
+                "sql/search_room.sql",
+                user_id.into_inner(),
+                p.after.into_inner(),
+                p.before.into_inner(),
+                p.dir.to_string(),
+                (p.limit + 1) as i32,
+                query.query,
+                dm,
+                include_muted,
+                relevance,
+            ),
+            query_file_scalar!(
+                "sql/search_room_count.sql",
+                user_id.into_inner(),
+                query.query,
+                dm,
+                include_muted,
             )
-        )
+        );
+        let mut res = res?;
+        p.sort_search(&mut res.items, &query.order_by);
+        Ok(res)
+    }
+}
+
+/// whether to only return dm rooms (`Some(true)`), only non-dm rooms (`Some(false)`), or both
+fn dm_filter(features: &[SearchRoomFeatures]) -> Option<bool> {
+    if features.contains(&SearchRoomFeatures::NotDm) {
+        Some(false)
+    } else if features.contains(&SearchRoomFeatures::Dm) {
+        Some(true)
+    } else {
+        None
     }
 }
@@ -28,7 +28,7 @@ impl DataSession for Postgres {
             token.0,
             name,
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.conn().await?)
         .await?;
         Ok(session.into())
     }
@@ -39,7 +39,7 @@ impl DataSession for Postgres {
             r#"SELECT id, user_id, token, status as "status: _", name FROM session WHERE id = $1"#,
             id.into_inner()
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.conn().await?)
         .await?;
         Ok(session.into())
     }
@@ -50,7 +50,7 @@ impl DataSession for Postgres {
             r#"SELECT id, user_id, token, status as "status: _", name FROM session WHERE token = $1"#,
             token.0
         )
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *self.conn().await?)
             .await?;
         Ok(session.into())
     }
@@ -64,7 +64,7 @@ impl DataSession for Postgres {
             status_db as _,
             user_id,
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
@@ -77,7 +77,7 @@ impl DataSession for Postgres {
         let p: Pagination<_> = pagination.try_into()?;
         gen_paginate!(
             p,
-            self.pool,
+            self,
             query_as!(
                 DbSession,
                 r#"
@@ -103,13 +103,13 @@ impl DataSession for Postgres {
             r#"DELETE FROM session WHERE id = $1"#,
             session_id.into_inner()
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
 
     async fn session_update(&self, session_id: SessionId, patch: SessionPatch) -> Result<()> {
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.conn().await?;
         let mut tx = conn.begin().await?;
         let session = query_as!(
             DbSession,
@@ -121,7 +121,7 @@ impl DataSession for Postgres {
             "#,
             session_id.into_inner()
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *tx)
         .await?;
         query!(
             "UPDATE session SET name = $2 WHERE id = $1",
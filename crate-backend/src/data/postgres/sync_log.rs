@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use sqlx::postgres::types::PgInterval;
+use sqlx::{query, query_scalar, Acquire};
+use uuid::Uuid;
+
+use crate::data::DataSyncLog;
+use crate::error::{Error, Result};
+use crate::types::{MessageSync, SyncAudience, SyncEvent, SyncLogPosition, UserId};
+
+use super::Postgres;
+
+/// the advisory lock held while giving events their positions
+const SYNC_EVENT_POS_LOCK: i64 = 0x7379_6e63;
+
+#[async_trait]
+impl DataSyncLog for Postgres {
+    async fn sync_log_append(
+        &self,
+        events: Vec<(Uuid, MessageSync, Vec<SyncAudience>)>,
+    ) -> Result<Vec<SyncEvent>> {
+        if events.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut event_ids = Vec::with_capacity(events.len());
+        let mut payloads = Vec::with_capacity(events.len());
+        let mut audience_event_ids = vec![];
+        let mut audience_kinds = vec![];
+        let mut audience_target_ids = vec![];
+        for (event_id, msg, audience) in &events {
+            event_ids.push(*event_id);
+            payloads.push(serde_json::to_value(msg)?);
+            for a in audience {
+                let (kind, target_id) = match a {
+                    SyncAudience::Room(room_id) => ("Room", room_id.into_inner()),
+                    SyncAudience::Thread(thread_id) => ("Thread", thread_id.into_inner()),
+                    SyncAudience::User(user_id) => ("User", user_id.into_inner()),
+                    SyncAudience::UserMutual(user_id) => ("UserMutual", user_id.into_inner()),
+                };
+                audience_event_ids.push(*event_id);
+                audience_kinds.push(kind.to_owned());
+                audience_target_ids.push(target_id);
+            }
+        }
+
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
+        query!(
+            r#"
+            INSERT INTO sync_event (id, payload)
+            SELECT id, payload FROM unnest($1::uuid[], $2::jsonb[]) WITH ORDINALITY AS e(id, payload, n)
+            ORDER BY n
+            "#,
+            &event_ids,
+            &payloads,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        // audiences are resolved for the whole batch at once. every user's seq
+        // is bumped once for the whole batch, then handed out to their events
+        // in order. seqs are locked in a consistent order to avoid deadlocking
+        // with other nodes.
+        let mut seqs: HashMap<Uuid, HashMap<UserId, u64>> = HashMap::new();
+        if !audience_event_ids.is_empty() {
+            let rows = query!(
+                r#"
+                WITH events AS (
+                    SELECT * FROM unnest($1::uuid[]) WITH ORDINALITY AS e(event_id, n)
+                ),
+                audience AS (
+                    SELECT * FROM unnest($2::uuid[], $3::text[], $4::uuid[]) AS a(event_id, kind, target_id)
+                ),
+                recipients AS (
+                    SELECT a.event_id, m.user_id FROM audience a
+                    JOIN room_member m ON m.room_id = a.target_id AND m.membership = 'Join'
+                    WHERE a.kind = 'Room'
+                    UNION
+                    SELECT a.event_id, m.user_id FROM audience a
+                    JOIN thread t ON t.id = a.target_id
+                    JOIN room_member m ON m.room_id = t.room_id AND m.membership = 'Join'
+                    WHERE a.kind = 'Thread'
+                    UNION
+                    SELECT a.event_id, m.user_id FROM audience a
+                    JOIN thread_member m ON m.thread_id = a.target_id AND m.membership = 'Join'
+                    WHERE a.kind = 'Thread'
+                    UNION
+                    SELECT a.event_id, a.target_id FROM audience a
+                    WHERE a.kind IN ('User', 'UserMutual')
+                    UNION
+                    SELECT a.event_id, other.user_id FROM audience a
+                    JOIN room_member m ON m.user_id = a.target_id AND m.membership = 'Join'
+                    JOIN room_member other ON other.room_id = m.room_id AND other.membership = 'Join'
+                    WHERE a.kind = 'UserMutual'
+                ),
+                entries AS (
+                    SELECT r.event_id, r.user_id, e.n FROM recipients r JOIN events e ON e.event_id = r.event_id
+                ),
+                seqs AS (
+                    INSERT INTO sync_log_seq (user_id, seq)
+                    SELECT user_id, count(*) FROM entries GROUP BY user_id ORDER BY user_id
+                    ON CONFLICT (user_id) DO UPDATE SET seq = sync_log_seq.seq + excluded.seq
+                    RETURNING user_id, seq
+                )
+                INSERT INTO sync_log (user_id, seq, event_id)
+                SELECT
+                    e.user_id,
+                    s.seq - count(*) OVER (PARTITION BY e.user_id)
+                        + row_number() OVER (PARTITION BY e.user_id ORDER BY e.n),
+                    e.event_id
+                FROM entries e
+                JOIN seqs s ON s.user_id = e.user_id
+                RETURNING user_id, seq, event_id
+                "#,
+                &event_ids,
+                &audience_event_ids,
+                &audience_kinds,
+                &audience_target_ids,
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+            for row in rows {
+                seqs.entry(row.event_id)
+                    .or_default()
+                    .insert(row.user_id.into(), row.seq as u64);
+            }
+        }
+        tx.commit().await?;
+
+        // positions are handed out in their own transaction holding this until
+        // commit, so events get positions in the same order they become visible
+        // without every writer waiting on each other's audiences. if this fails,
+        // the events stay in everyone's sync log but are never announced.
+        let mut tx = conn.begin().await?;
+        query!("SELECT pg_advisory_xact_lock($1)", SYNC_EVENT_POS_LOCK)
+            .execute(&mut *tx)
+            .await?;
+        let last = query_scalar!(
+            r#"SELECT setval('sync_event_pos', nextval('sync_event_pos') + $1 - 1) AS "last!""#,
+            event_ids.len() as i64,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        query!(
+            r#"
+            UPDATE sync_event SET pos = $2::bigint - $3::bigint + e.n
+            FROM unnest($1::uuid[]) WITH ORDINALITY AS e(id, n)
+            WHERE sync_event.id = e.id
+            "#,
+            &event_ids,
+            last,
+            event_ids.len() as i64,
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(events
+            .into_iter()
+            .map(|(event_id, msg, _)| SyncEvent {
+                msg,
+                seqs: Some(Arc::new(seqs.remove(&event_id).unwrap_or_default())),
+            })
+            .collect())
+    }
+
+    async fn sync_log_get_many(&self, event_ids: &[Uuid]) -> Result<Vec<(Uuid, u64, SyncEvent)>> {
+        let rows = query!(
+            r#"
+            SELECT
+                event.id,
+                event.pos AS "pos!",
+                event.payload,
+                coalesce(array_agg(log.user_id) FILTER (WHERE log.user_id IS NOT NULL), '{}') AS "user_ids!",
+                coalesce(array_agg(log.seq) FILTER (WHERE log.user_id IS NOT NULL), '{}') AS "seqs!"
+            FROM sync_event event
+            LEFT JOIN sync_log log ON log.event_id = event.id
+            WHERE event.id = any($1) AND event.pos IS NOT NULL
+            GROUP BY event.id
+            "#,
+            event_ids,
+        )
+        .fetch_all(&mut *self.conn().await?)
+        .await?;
+        let mut events = Vec::with_capacity(rows.len());
+        for row in rows {
+            let seqs = row
+                .user_ids
+                .into_iter()
+                .zip(row.seqs)
+                .map(|(user_id, seq)| (user_id.into(), seq as u64))
+                .collect();
+            let event = SyncEvent {
+                msg: serde_json::from_value(row.payload)?,
+                seqs: Some(Arc::new(seqs)),
+            };
+            events.push((row.id, row.pos as u64, event));
+        }
+        Ok(events)
+    }
+
+    async fn sync_log_list_after(&self, pos: u64) -> Result<Vec<Uuid>> {
+        let ids = query_scalar!(
+            "SELECT id FROM sync_event WHERE pos > $1 ORDER BY pos",
+            pos as i64
+        )
+        .fetch_all(&mut *self.conn().await?)
+        .await?;
+        Ok(ids)
+    }
+
+    async fn sync_log_last_pos(&self) -> Result<Option<u64>> {
+        let pos = query_scalar!("SELECT max(pos) FROM sync_event")
+            .fetch_one(&mut *self.conn().await?)
+            .await?;
+        Ok(pos.map(|pos| pos as u64))
+    }
+
+    async fn sync_log_replay(
+        &self,
+        user_id: UserId,
+        seq: u64,
+        limit: u16,
+    ) -> Result<Vec<(u64, MessageSync)>> {
+        let rows = query!(
+            r#"
+            SELECT log.seq, event.payload
+            FROM sync_log log
+            JOIN sync_event event ON event.id = log.event_id
+            WHERE log.user_id = $1 AND log.seq > $2
+            ORDER BY log.seq
+            LIMIT $3
+            "#,
+            user_id.into_inner(),
+            seq as i64,
+            limit as i64,
+        )
+        .fetch_all(&mut *self.conn().await?)
+        .await?;
+        let mut events = Vec::with_capacity(rows.len());
+        for row in rows {
+            events.push((row.seq as u64, serde_json::from_value(row.payload)?));
+        }
+        Ok(events)
+    }
+
+    async fn sync_log_position(&self, user_id: UserId) -> Result<SyncLogPosition> {
+        let row = query!(
+            "SELECT seq, trimmed_seq FROM sync_log_seq WHERE user_id = $1",
+            user_id.into_inner()
+        )
+        .fetch_optional(&mut *self.conn().await?)
+        .await?;
+        Ok(match row {
+            Some(row) => SyncLogPosition {
+                seq: row.seq as u64,
+                trimmed_seq: row.trimmed_seq as u64,
+            },
+            None => SyncLogPosition {
+                seq: 0,
+                trimmed_seq: 0,
+            },
+        })
+    }
+
+    async fn sync_log_trim(&self, max_age: Duration) -> Result<()> {
+        let max_age = PgInterval::try_from(max_age)
+            .map_err(|_| Error::BadStatic("sync log max age is too large"))?;
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
+        query!(
+            r#"
+            WITH trimmed AS (
+                DELETE FROM sync_log
+                WHERE event_id IN (SELECT id FROM sync_event WHERE created_at < now() - $1::interval)
+                RETURNING user_id, seq
+            )
+            UPDATE sync_log_seq
+            SET trimmed_seq = greatest(sync_log_seq.trimmed_seq, latest.seq)
+            FROM (SELECT user_id, max(seq) AS seq FROM trimmed GROUP BY user_id) latest
+            WHERE sync_log_seq.user_id = latest.user_id
+            "#,
+            max_age.clone(),
+        )
+        .execute(&mut *tx)
+        .await?;
+        query!(
+            "DELETE FROM sync_event WHERE created_at < now() - $1::interval",
+            max_age,
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+}
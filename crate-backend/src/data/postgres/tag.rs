@@ -0,0 +1,317 @@
+use async_trait::async_trait;
+use common::v1::types::misc::Color;
+use common::v1::types::tag::{Tag, TagCreate, TagPatch};
+use common::v1::types::{
+    PaginationDirection, PaginationQuery, PaginationResponse, TagId, TagVerId,
+};
+use sqlx::{query, query_as, query_scalar, Acquire};
+use uuid::Uuid;
+
+use crate::data::DataTag;
+use crate::error::Result;
+use crate::gen_paginate;
+use crate::types::{RoomId, ThreadId, ThreadVerId};
+
+use super::{Pagination, Postgres};
+
+pub struct DbTag {
+    pub id: Uuid,
+    pub version_id: Uuid,
+    pub room_id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub color: Option<String>,
+    pub is_archived: bool,
+    pub tags: Vec<Uuid>,
+}
+
+impl From<DbTag> for Tag {
+    fn from(row: DbTag) -> Self {
+        Tag {
+            id: row.id.into(),
+            version_id: row.version_id.into(),
+            room_id: row.room_id.into(),
+            name: row.name,
+            description: row.description,
+            color: row.color.map(Color::from_hex_string),
+            is_archived: row.is_archived,
+            tags: row.tags.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl DataTag for Postgres {
+    async fn tag_create(&self, create: TagCreate) -> Result<Tag> {
+        let tag_id = TagId::new();
+        query!(
+            r#"
+            INSERT INTO tag (id, version_id, room_id, name, description, color, is_archived)
+            VALUES ($1, $1, $2, $3, $4, $5, false)
+            "#,
+            *tag_id,
+            *create.room_id,
+            create.name,
+            create.description,
+            create.color.as_ref().map(|c| c.as_ref()),
+        )
+        .execute(&mut *self.conn().await?)
+        .await?;
+        self.tag_get(tag_id).await
+    }
+
+    async fn tag_get(&self, tag_id: TagId) -> Result<Tag> {
+        let tag = query_as!(
+            DbTag,
+            r#"
+            SELECT
+                id, version_id, room_id, name, description, color, is_archived,
+                coalesce(
+                    (SELECT array_agg(tag_id ORDER BY tag_id) FROM tag_apply_tag WHERE target_id = tag.id),
+                    '{}'
+                ) as "tags!"
+            FROM tag
+            WHERE id = $1
+            "#,
+            *tag_id,
+        )
+        .fetch_one(&mut *self.conn().await?)
+        .await?;
+        Ok(tag.into())
+    }
+
+    async fn tag_list(
+        &self,
+        room_id: RoomId,
+        paginate: PaginationQuery<TagId>,
+    ) -> Result<PaginationResponse<Tag>> {
+        let p: Pagination<_> = paginate.try_into()?;
+        gen_paginate!(
+            p,
+            self,
+            query_as!(
+                DbTag,
+                r#"
+                SELECT
+                    id, version_id, room_id, name, description, color, is_archived,
+                    coalesce(
+                        (SELECT array_agg(tag_id ORDER BY tag_id) FROM tag_apply_tag WHERE target_id = tag.id),
+                        '{}'
+                    ) as "tags!"
+                FROM tag
+                WHERE room_id = $1 AND id > $2 AND id < $3
+                ORDER BY (CASE WHEN $4 = 'f' THEN id END), id DESC LIMIT $5
+                "#,
+                *room_id,
+                *p.after,
+                *p.before,
+                p.dir.to_string(),
+                (p.limit + 1) as i32
+            ),
+            query_scalar!("SELECT count(*) FROM tag WHERE room_id = $1", *room_id)
+        )
+    }
+
+    async fn tag_update(&self, tag_id: TagId, patch: TagPatch) -> Result<TagVerId> {
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
+        let tag = query!(
+            "SELECT name, description, color, is_archived FROM tag WHERE id = $1 FOR UPDATE",
+            *tag_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        let version_id = TagVerId::new();
+        query!(
+            r#"
+            UPDATE tag SET
+                version_id = $2,
+                name = $3,
+                description = $4,
+                color = $5,
+                is_archived = $6
+            WHERE id = $1
+            "#,
+            *tag_id,
+            *version_id,
+            patch.name.unwrap_or(tag.name),
+            patch.description.unwrap_or(tag.description),
+            patch
+                .color
+                .map(|c| c.map(|c| c.as_ref().to_owned()))
+                .unwrap_or(tag.color),
+            patch.is_archived.unwrap_or(tag.is_archived),
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(version_id)
+    }
+
+    async fn tag_delete(&self, tag_id: TagId) -> Result<()> {
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
+        query!("DELETE FROM tag_apply_thread WHERE tag_id = $1", *tag_id)
+            .execute(&mut *tx)
+            .await?;
+        query!("DELETE FROM tag_apply_room WHERE tag_id = $1", *tag_id)
+            .execute(&mut *tx)
+            .await?;
+        query!(
+            "DELETE FROM tag_apply_tag WHERE tag_id = $1 OR target_id = $1",
+            *tag_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        query!("DELETE FROM tag WHERE id = $1", *tag_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn tag_thread_count(&self, tag_id: TagId) -> Result<u64> {
+        let count = query_scalar!(
+            "SELECT count(*) FROM tag_apply_thread WHERE tag_id = $1",
+            *tag_id
+        )
+        .fetch_one(&mut *self.conn().await?)
+        .await?;
+        Ok(count.unwrap_or(0) as u64)
+    }
+
+    async fn tag_thread_set(&self, thread_id: ThreadId, tag_ids: &[TagId]) -> Result<()> {
+        let tag_ids: Vec<Uuid> = tag_ids.iter().map(|id| id.into_inner()).collect();
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
+        query!(
+            "DELETE FROM tag_apply_thread WHERE thread_id = $1 AND tag_id <> ALL($2)",
+            *thread_id,
+            &tag_ids,
+        )
+        .execute(&mut *tx)
+        .await?;
+        query!(
+            r#"
+            INSERT INTO tag_apply_thread (thread_id, tag_id)
+            SELECT $1, unnest($2::uuid[])
+            ON CONFLICT DO NOTHING
+            "#,
+            *thread_id,
+            &tag_ids,
+        )
+        .execute(&mut *tx)
+        .await?;
+        query!(
+            "UPDATE thread SET version_id = $2 WHERE id = $1",
+            *thread_id,
+            *ThreadVerId::new(),
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn tag_thread_apply(&self, thread_id: ThreadId, tag_id: TagId) -> Result<()> {
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
+        query!(
+            "INSERT INTO tag_apply_thread (thread_id, tag_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            *thread_id,
+            *tag_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        query!(
+            "UPDATE thread SET version_id = $2 WHERE id = $1",
+            *thread_id,
+            *ThreadVerId::new(),
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn tag_thread_unapply(&self, thread_id: ThreadId, tag_id: TagId) -> Result<()> {
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
+        query!(
+            "DELETE FROM tag_apply_thread WHERE thread_id = $1 AND tag_id = $2",
+            *thread_id,
+            *tag_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        query!(
+            "UPDATE thread SET version_id = $2 WHERE id = $1",
+            *thread_id,
+            *ThreadVerId::new(),
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn tag_tag_apply(&self, target_id: TagId, tag_id: TagId) -> Result<()> {
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
+        query!(
+            "INSERT INTO tag_apply_tag (target_id, tag_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            *target_id,
+            *tag_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        query!(
+            "UPDATE tag SET version_id = $2 WHERE id = $1",
+            *target_id,
+            *TagVerId::new(),
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn tag_tag_unapply(&self, target_id: TagId, tag_id: TagId) -> Result<()> {
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
+        query!(
+            "DELETE FROM tag_apply_tag WHERE target_id = $1 AND tag_id = $2",
+            *target_id,
+            *tag_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        query!(
+            "UPDATE tag SET version_id = $2 WHERE id = $1",
+            *target_id,
+            *TagVerId::new(),
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn tag_implied(&self, tag_id: TagId) -> Result<Vec<TagId>> {
+        let tags = query_scalar!(
+            r#"
+            WITH RECURSIVE implied (tag_id) AS (
+                SELECT tag_id FROM tag_apply_tag WHERE target_id = $1
+                UNION
+                SELECT t.tag_id FROM implied i
+                JOIN tag_apply_tag t ON t.target_id = i.tag_id
+            )
+            SELECT tag_id as "tag_id!" FROM implied
+            "#,
+            *tag_id,
+        )
+        .fetch_all(&mut *self.conn().await?)
+        .await?;
+        Ok(tags.into_iter().map(Into::into).collect())
+    }
+}
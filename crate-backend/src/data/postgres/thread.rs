@@ -1,13 +1,15 @@
 use async_trait::async_trait;
-use common::v1::types::ThreadPrivate;
+use common::v1::types::{moderation::ReportDestination, ThreadPrivate};
 use sqlx::{query, query_file_as, query_scalar, Acquire};
 use tracing::info;
+use uuid::Uuid;
 
 use crate::error::Result;
 use crate::gen_paginate;
 use crate::types::{
-    DbThread, DbThreadCreate, DbThreadPrivate, DbThreadType, PaginationDirection, PaginationQuery,
-    PaginationResponse, RoomId, Thread, ThreadId, ThreadPatch, ThreadVerId, UserId,
+    DbReportDestination, DbThread, DbThreadCreate, DbThreadPrivate, DbThreadType,
+    PaginationDirection, PaginationQuery, PaginationResponse, RoomId, TagId, Thread, ThreadId,
+    ThreadPatch, ThreadVerId, UserId,
 };
 
 use crate::data::DataThread;
@@ -31,7 +33,7 @@ impl DataThread for Postgres {
             create.description,
             create.ty as _,
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         info!("inserted thread");
         Ok(thread_id)
@@ -40,7 +42,7 @@ impl DataThread for Postgres {
     /// get a thread, panics if there are no messages
     async fn thread_get(&self, thread_id: ThreadId) -> Result<Thread> {
         let thread = query_file_as!(DbThread, "sql/thread_get.sql", thread_id.into_inner())
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *self.conn().await?)
             .await?;
         Ok(thread.into())
     }
@@ -48,12 +50,16 @@ impl DataThread for Postgres {
     async fn thread_list(
         &self,
         room_id: RoomId,
+        user_id: UserId,
         pagination: PaginationQuery<ThreadId>,
+        tag_id: Option<TagId>,
+        reports: Vec<ReportDestination>,
     ) -> Result<PaginationResponse<Thread>> {
         let p: Pagination<_> = pagination.try_into()?;
+        let reports: Vec<DbReportDestination> = reports.into_iter().map(Into::into).collect();
         gen_paginate!(
             p,
-            self.pool,
+            self,
             query_file_as!(
                 DbThread,
                 "sql/thread_paginate.sql",
@@ -61,11 +67,28 @@ impl DataThread for Postgres {
                 p.after.into_inner(),
                 p.before.into_inner(),
                 p.dir.to_string(),
-                (p.limit + 1) as i32
+                (p.limit + 1) as i32,
+                tag_id.map(|id| id.into_inner()),
+                *user_id,
+                &reports as &[DbReportDestination],
             ),
             query_scalar!(
-                r#"SELECT count(*) FROM thread WHERE room_id = $1 AND deleted_at IS NULL"#,
-                room_id.into_inner()
+                r#"
+                SELECT count(*) FROM thread
+                WHERE room_id = $1 AND deleted_at IS NULL
+                  AND ($2::uuid IS NULL OR EXISTS (
+                      SELECT 1 FROM tag_apply_thread_implied i
+                      WHERE i.thread_id = thread.id AND i.tag_id = $2
+                  ))
+                  AND (thread.id IN (SELECT thread_id FROM thread_viewable($3)) OR EXISTS (
+                      SELECT 1 FROM report_thread r
+                      WHERE r.thread_id = thread.id AND r.destination = ANY($4)
+                  ))
+                "#,
+                room_id.into_inner(),
+                tag_id.map(|id| id.into_inner()),
+                user_id.into_inner(),
+                &reports as &[DbReportDestination],
             )
         )
     }
@@ -81,16 +104,33 @@ impl DataThread for Postgres {
             *thread_id,
             *user_id,
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.conn().await?)
         .await?;
         Ok(thread_private.into())
     }
 
+    async fn thread_get_private_many(
+        &self,
+        thread_ids: &[ThreadId],
+        user_id: UserId,
+    ) -> Result<Vec<(ThreadId, ThreadPrivate)>> {
+        let ids: Vec<Uuid> = thread_ids.iter().map(|i| i.into_inner()).collect();
+        let rows = query_file_as!(
+            DbThreadPrivate,
+            "sql/thread_get_private_many.sql",
+            &ids[..],
+            *user_id,
+        )
+        .fetch_all(&mut *self.conn().await?)
+        .await?;
+        Ok(rows.into_iter().map(|row| (row.id, row.into())).collect())
+    }
+
     async fn thread_update(&self, thread_id: ThreadId, patch: ThreadPatch) -> Result<ThreadVerId> {
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.conn().await?;
         let mut tx = conn.begin().await?;
         let thread = query_file_as!(DbThread, "sql/thread_get.sql", *thread_id,)
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *tx)
             .await?;
         let thread: Thread = thread.into();
         let version_id = ThreadVerId::new();
@@ -114,7 +154,7 @@ impl DataThread for Postgres {
     }
 
     async fn thread_delete(&self, thread_id: ThreadId, _user_id: UserId) -> Result<()> {
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.conn().await?;
         let mut tx = conn.begin().await?;
         let version_id = ThreadVerId::new();
         query!(
@@ -134,7 +174,7 @@ impl DataThread for Postgres {
     }
 
     async fn thread_undelete(&self, thread_id: ThreadId, _user_id: UserId) -> Result<()> {
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.conn().await?;
         let mut tx = conn.begin().await?;
         let version_id = ThreadVerId::new();
         query!(
@@ -154,7 +194,7 @@ impl DataThread for Postgres {
     }
 
     async fn thread_archive(&self, thread_id: ThreadId, _user_id: UserId) -> Result<()> {
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.conn().await?;
         let mut tx = conn.begin().await?;
         let version_id = ThreadVerId::new();
         query!(
@@ -174,7 +214,7 @@ impl DataThread for Postgres {
     }
 
     async fn thread_unarchive(&self, thread_id: ThreadId, _user_id: UserId) -> Result<()> {
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.conn().await?;
         let mut tx = conn.begin().await?;
         let version_id = ThreadVerId::new();
         query!(
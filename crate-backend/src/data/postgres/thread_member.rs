@@ -63,7 +63,7 @@ impl DataThreadMember for Postgres {
             thread_id.into_inner(),
             membership as _
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         info!("inserted thread member");
         Ok(())
@@ -75,7 +75,7 @@ impl DataThreadMember for Postgres {
         user_id: UserId,
         patch: ThreadMemberPatch,
     ) -> Result<()> {
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.conn().await?;
         let mut tx = conn.begin().await?;
         let item = query_as!(
             DbThreadMember,
@@ -131,7 +131,7 @@ impl DataThreadMember for Postgres {
             user_id.into_inner(),
             membership as _,
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
@@ -142,7 +142,7 @@ impl DataThreadMember for Postgres {
             thread_id.into_inner(),
             user_id.into_inner(),
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         info!("deleted thread member");
         Ok(())
@@ -169,7 +169,7 @@ impl DataThreadMember for Postgres {
             thread_id.into_inner(),
             user_id.into_inner(),
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.conn().await?)
         .await?;
         Ok(item.into())
     }
@@ -182,7 +182,7 @@ impl DataThreadMember for Postgres {
         let p: Pagination<_> = pagination.try_into()?;
         gen_paginate!(
             p,
-            self.pool,
+            self,
             query_as!(
                 DbThreadMember,
                 r#"
@@ -31,7 +31,7 @@ impl DataUnread for Postgres {
             message_id.into_inner(),
             version_id.into_inner()
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
@@ -1,11 +1,11 @@
 use async_trait::async_trait;
-use common::v1::types::{self};
+use common::v1::types::{self, user::Suspended};
 use serde::Deserialize;
 use serde_json::Value;
 use sqlx::{query, query_as, query_scalar, Acquire};
 use uuid::Uuid;
 
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::types::{DbUserCreate, User, UserId, UserPatch, UserVerId};
 
 use crate::data::DataUser;
@@ -140,13 +140,13 @@ impl DataUser for Postgres {
             serde_json::to_value(user.suspended)?,
             user.registered_at.map(|t| time::PrimitiveDateTime::from(t)),
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         self.user_get(user_id.into()).await
     }
 
     async fn user_update(&self, user_id: UserId, patch: UserPatch) -> Result<UserVerId> {
-        let mut conn = self.pool.acquire().await?;
+        let mut conn = self.conn().await?;
         let mut tx = conn.begin().await?;
         let user = query_as!(
             DbUser,
@@ -184,7 +184,7 @@ impl DataUser for Postgres {
             *user_id,
             now
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
@@ -198,7 +198,7 @@ impl DataUser for Postgres {
         "#,
             id.into_inner()
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.conn().await?)
         .await?;
         Ok(row.into())
     }
@@ -216,7 +216,7 @@ impl DataUser for Postgres {
             owner_id.into_inner(),
             external_id,
         )
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.conn().await?)
         .await?;
         Ok(id.map(Into::into))
     }
@@ -233,8 +233,28 @@ impl DataUser for Postgres {
             *version_id,
             registered_at.map(|t| time::PrimitiveDateTime::from(t)),
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(version_id)
     }
+
+    async fn user_set_suspended(
+        &self,
+        user_id: UserId,
+        suspended: Option<Suspended>,
+    ) -> Result<UserVerId> {
+        let version_id = UserVerId::new();
+        let res = query!(
+            "UPDATE usr SET version_id = $2, suspended = $3 WHERE id = $1",
+            *user_id,
+            *version_id,
+            suspended.map(serde_json::to_value).transpose()?,
+        )
+        .execute(&mut *self.conn().await?)
+        .await?;
+        if res.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+        Ok(version_id)
+    }
 }
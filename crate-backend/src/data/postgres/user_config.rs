@@ -17,14 +17,14 @@ impl DataUserConfig for Postgres {
             user_id.into_inner(),
             serde_json::to_value(config)?,
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
 
     async fn user_config_get(&self, user_id: UserId) -> Result<UserConfig> {
         let conf = query_scalar!("select config from usr where id = $1", user_id.into_inner(),)
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *self.conn().await?)
             .await?;
         let conf = conf
             .map(serde_json::from_value)
@@ -1,7 +1,7 @@
 use async_trait::async_trait;
 use common::v1::types::email::{EmailAddr, EmailInfo};
 
-use sqlx::{query, query_as, query_scalar};
+use sqlx::{query, query_as, query_scalar, Acquire};
 use time::{Duration, OffsetDateTime, PrimitiveDateTime};
 use uuid::Uuid;
 
@@ -26,7 +26,8 @@ impl DataUserEmail for Postgres {
         email: EmailInfo,
         max_user_emails: usize,
     ) -> Result<()> {
-        let mut tx = self.pool.begin().await?;
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
 
         let email_count: i64 =
             sqlx::query_scalar("SELECT count(*) FROM user_email_addresses WHERE user_id = $1")
@@ -83,7 +84,7 @@ impl DataUserEmail for Postgres {
             *user_id,
             email_addr.into_inner()
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
 
         if res.rows_affected() == 0 {
@@ -99,7 +100,7 @@ impl DataUserEmail for Postgres {
             r#"SELECT addr, is_verified, is_primary FROM user_email_addresses WHERE user_id = $1"#,
             *user_id,
         )
-        .fetch_all(&self.pool)
+        .fetch_all(&mut *self.conn().await?)
         .await?;
 
         let mut emails = Vec::with_capacity(db_emails.len());
@@ -120,7 +121,8 @@ impl DataUserEmail for Postgres {
         email_addr: EmailAddr,
         code: String,
     ) -> Result<()> {
-        let mut tx = self.pool.begin().await?;
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
 
         let existing_verified_owner: Option<Uuid> = sqlx::query_scalar(
             "SELECT user_id FROM user_email_addresses WHERE addr = $1 AND is_verified = true",
@@ -193,7 +195,9 @@ impl DataUserEmail for Postgres {
         let code = ((Uuid::new_v4().as_u128() % 900_000) + 100_000).to_string();
         let expires_at = OffsetDateTime::now_utc() + Duration::minutes(15);
 
-        let mut tx = self.pool.begin().await?;
+        let mut conn = self.conn().await?;
+
+        let mut tx = conn.begin().await?;
 
         query!(
             "DELETE FROM email_address_verification WHERE user_id = $1 AND addr = $2",
@@ -146,7 +146,7 @@ impl DataUserRelationship for Postgres {
             rel.ignore_forever,
             rel.ignore_until,
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
@@ -157,7 +157,8 @@ impl DataUserRelationship for Postgres {
         other_id: UserId,
         patch: RelationshipPatch,
     ) -> Result<()> {
-        let mut tx = self.pool.begin().await?;
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
         let row = query_as!(
             DbUserRel,
             r#"
@@ -209,7 +210,7 @@ impl DataUserRelationship for Postgres {
             user_id.into_inner(),
             other_id.into_inner(),
         )
-        .execute(&self.pool)
+        .execute(&mut *self.conn().await?)
         .await?;
         Ok(())
     }
@@ -228,7 +229,7 @@ impl DataUserRelationship for Postgres {
             user_id.into_inner(),
             other_id.into_inner(),
         )
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.conn().await?)
         .await?;
         Ok(row.map(Into::into))
     }
@@ -241,7 +242,7 @@ impl DataUserRelationship for Postgres {
         let p: Pagination<_> = pagination.try_into()?;
         gen_paginate!(
             p,
-            self.pool,
+            self,
             query_as!(
                 DbUserRelWithId,
                 r#"
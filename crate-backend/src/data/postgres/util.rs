@@ -1,6 +1,6 @@
 use std::result::Result;
 
-use common::v1::types::{Media, PaginationKey};
+use common::v1::types::{search::SearchOrder, Media, PaginationKey};
 use serde_json::Value;
 
 use crate::{
@@ -46,10 +46,49 @@ impl<K: PaginationKey> TryInto<Pagination<K>> for PaginationQuery<K> {
     }
 }
 
+impl<K: PaginationKey> Pagination<K> {
+    /// paginate search results, going in the direction of `order` unless another one is given
+    ///
+    /// results ordered by relevance aren't sorted by id, so they can't be
+    /// paginated with id cursors
+    #[allow(clippy::result_large_err)]
+    pub fn for_search(query: PaginationQuery<K>, order: &SearchOrder) -> Result<Self, Error> {
+        if *order == SearchOrder::Relevance && (query.from.is_some() || query.to.is_some()) {
+            return Err(Error::BadStatic(
+                "searches ordered by relevance can't be paginated",
+            ));
+        }
+        let dir = query.dir.unwrap_or(match order {
+            SearchOrder::Oldest => PaginationDirection::F,
+            SearchOrder::Newest | SearchOrder::Relevance => PaginationDirection::B,
+        });
+        PaginationQuery {
+            dir: Some(dir),
+            ..query
+        }
+        .try_into()
+    }
+
+    /// put a page of search results in the order that was asked for
+    ///
+    /// pages are sorted by id like every other paginated list, or by relevance
+    /// and then reversed if paginating backwards
+    pub fn sort_search<T>(&self, items: &mut [T], order: &SearchOrder) {
+        let reverse = match order {
+            SearchOrder::Newest => true,
+            SearchOrder::Oldest => false,
+            SearchOrder::Relevance => self.dir == PaginationDirection::B,
+        };
+        if reverse {
+            items.reverse();
+        }
+    }
+}
+
 #[macro_export]
 macro_rules! gen_paginate {
-    ($p:expr, $pool:expr, $qlist:expr, $qtotal:expr, $map:expr) => {{
-        let mut conn = $pool.acquire().await?;
+    ($p:expr, $data:expr, $qlist:expr, $qtotal:expr, $map:expr) => {{
+        let mut conn = $data.conn().await?;
         let mut tx = conn.begin().await?;
 
         let items = $qlist.fetch_all(&mut *tx).await?;
@@ -72,8 +111,8 @@ macro_rules! gen_paginate {
             has_more,
         })
     }};
-    ($p:expr, $pool:expr, $qlist:expr, $qtotal:expr) => {
-        gen_paginate!($p, $pool, $qlist, $qtotal, Into::into)
+    ($p:expr, $data:expr, $qlist:expr, $qtotal:expr) => {
+        gen_paginate!($p, $data, $qlist, $qtotal, Into::into)
     };
 }
 
@@ -0,0 +1,200 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use sqlx::postgres::types::PgInterval;
+use sqlx::{query, query_scalar, Acquire};
+use uuid::Uuid;
+
+use crate::data::DataVoice;
+use crate::error::{Error, Result};
+use crate::types::{DbSfu, DbVoiceModeration, RoomId, ThreadId, UserId};
+
+use super::Postgres;
+
+#[async_trait]
+impl DataVoice for Postgres {
+    async fn sfu_upsert(&self, sfu: DbSfu) -> Result<()> {
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
+        // another token's sfu can't be taken over
+        let taken = query_scalar!(
+            r#"SELECT exists(SELECT 1 FROM sfu WHERE (id = $1 OR url = $2) AND token_name != $3) as "exists!""#,
+            sfu.id,
+            sfu.url,
+            sfu.token_name,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if taken {
+            return Err(Error::MissingPermissions);
+        }
+        // the same sfu gets a new id when it restarts
+        query!(
+            "DELETE FROM sfu WHERE url = $1 AND id != $2",
+            sfu.url,
+            sfu.id
+        )
+        .execute(&mut *tx)
+        .await?;
+        query!(
+            r#"
+            INSERT INTO sfu (id, url, region, load, token_name, seen_at)
+            VALUES ($1, $2, $3, $4, $5, now())
+            ON CONFLICT (id) DO UPDATE SET
+                url = excluded.url,
+                region = excluded.region,
+                load = excluded.load,
+                token_name = excluded.token_name,
+                seen_at = excluded.seen_at
+            "#,
+            sfu.id,
+            sfu.url,
+            sfu.region,
+            sfu.load,
+            sfu.token_name,
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn sfu_list(&self, max_age: Duration) -> Result<Vec<DbSfu>> {
+        let max_age = PgInterval::try_from(max_age)
+            .map_err(|_| Error::BadStatic("sfu max age is too large"))?;
+        let rows = query!(
+            "SELECT id, url, region, load, token_name FROM sfu WHERE seen_at > now() - $1::interval",
+            max_age,
+        )
+        .fetch_all(&mut *self.conn().await?)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| DbSfu {
+                id: row.id,
+                url: row.url,
+                region: row.region,
+                load: row.load,
+                token_name: row.token_name,
+            })
+            .collect())
+    }
+
+    async fn sfu_thread_get(&self, thread_id: ThreadId) -> Result<Option<Uuid>> {
+        let sfu_id = query_scalar!(
+            "SELECT sfu_id FROM sfu_thread WHERE thread_id = $1",
+            *thread_id
+        )
+        .fetch_optional(&mut *self.conn().await?)
+        .await?;
+        Ok(sfu_id)
+    }
+
+    async fn sfu_thread_set(
+        &self,
+        thread_id: ThreadId,
+        sfu_id: Uuid,
+        replace: Option<Uuid>,
+    ) -> Result<Uuid> {
+        let mut conn = self.conn().await?;
+        let mut tx = conn.begin().await?;
+        query!(
+            r#"
+            INSERT INTO sfu_thread (thread_id, sfu_id)
+            VALUES ($1, $2)
+            ON CONFLICT (thread_id) DO UPDATE SET sfu_id = excluded.sfu_id
+            WHERE sfu_thread.sfu_id = $3
+            "#,
+            *thread_id,
+            sfu_id,
+            replace,
+        )
+        .execute(&mut *tx)
+        .await?;
+        let sfu_id = query_scalar!(
+            "SELECT sfu_id FROM sfu_thread WHERE thread_id = $1",
+            *thread_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(sfu_id)
+    }
+
+    async fn voice_moderation_get(
+        &self,
+        room_id: RoomId,
+        user_id: UserId,
+    ) -> Result<DbVoiceModeration> {
+        let row = query!(
+            "SELECT voice_mute, voice_deaf FROM room_member WHERE room_id = $1 AND user_id = $2",
+            *room_id,
+            *user_id,
+        )
+        .fetch_optional(&mut *self.conn().await?)
+        .await?;
+        Ok(row
+            .map(|row| DbVoiceModeration {
+                mute: row.voice_mute,
+                deaf: row.voice_deaf,
+            })
+            .unwrap_or_default())
+    }
+
+    async fn voice_moderation_set(
+        &self,
+        room_id: RoomId,
+        user_id: UserId,
+        moderation: DbVoiceModeration,
+    ) -> Result<()> {
+        let res = query!(
+            "UPDATE room_member SET voice_mute = $3, voice_deaf = $4 WHERE room_id = $1 AND user_id = $2",
+            *room_id,
+            *user_id,
+            moderation.mute,
+            moderation.deaf,
+        )
+        .execute(&mut *self.conn().await?)
+        .await?;
+        if res.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn voice_member_get(&self, user_id: UserId) -> Result<Option<ThreadId>> {
+        let thread_id = query_scalar!(
+            "SELECT thread_id FROM voice_member WHERE user_id = $1",
+            *user_id
+        )
+        .fetch_optional(&mut *self.conn().await?)
+        .await?;
+        Ok(thread_id.map(Into::into))
+    }
+
+    async fn voice_member_set(&self, user_id: UserId, thread_id: ThreadId) -> Result<()> {
+        query!(
+            r#"
+            INSERT INTO voice_member (user_id, thread_id)
+            VALUES ($1, $2)
+            ON CONFLICT (user_id) DO UPDATE SET thread_id = excluded.thread_id
+            "#,
+            *user_id,
+            *thread_id,
+        )
+        .execute(&mut *self.conn().await?)
+        .await?;
+        Ok(())
+    }
+
+    async fn voice_member_delete(&self, user_id: UserId, thread_id: ThreadId) -> Result<()> {
+        query!(
+            "DELETE FROM voice_member WHERE user_id = $1 AND thread_id = $2",
+            *user_id,
+            *thread_id,
+        )
+        .execute(&mut *self.conn().await?)
+        .await?;
+        Ok(())
+    }
+}
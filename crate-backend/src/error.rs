@@ -1,6 +1,6 @@
 use std::num::{ParseFloatError, ParseIntError};
 
-use axum::{extract::ws::Message, http::StatusCode, response::IntoResponse, Json};
+use axum::{http::StatusCode, response::IntoResponse, Json};
 use common::v1::types::{MessageEnvelope, MessagePayload};
 use opentelemetry_otlp::ExporterBuildError;
 use serde_json::json;
@@ -15,6 +15,8 @@ pub enum Error {
     Sqlx(sqlx::Error),
     #[error("blocked by other user")]
     Blocked,
+    #[error("banned from this room")]
+    Banned,
     #[error("missing authentication")]
     MissingAuth,
     #[error("bad header")]
@@ -41,6 +43,10 @@ pub enum Error {
     Tempfile(#[from] async_tempfile::Error),
     #[error("serde error: {0}")]
     Serde(#[from] serde_json::Error),
+    #[error("msgpack encode error: {0}")]
+    MsgpackEncode(#[from] rmp_serde::encode::Error),
+    #[error("msgpack decode error: {0}")]
+    MsgpackDecode(#[from] rmp_serde::decode::Error),
     #[error("axum error")]
     Axum(#[from] axum::Error),
     #[error("sushi send error: {0}")]
@@ -98,6 +104,18 @@ pub enum Error {
     #[error("invalid credentials")]
     InvalidCredentials,
 
+    #[error("too many attempts, try again later")]
+    TooManyAttempts,
+
+    #[error("reconnection info is expired or invalid")]
+    ResumeExpired,
+
+    #[error("user is suspended")]
+    Suspended,
+
+    #[error("conflict: {0}")]
+    Conflict(&'static str),
+
     #[error("email address already exists for this user")]
     EmailAlreadyExists,
 
@@ -124,19 +142,31 @@ impl From<axum::http::header::ToStrError> for Error {
 }
 
 impl Error {
+    /// whether the request was at fault rather than the server
+    pub fn is_client_error(&self) -> bool {
+        self.get_status().is_client_error()
+    }
+
     fn get_status(&self) -> StatusCode {
         match self {
             Error::Blocked => StatusCode::FORBIDDEN,
+            Error::Banned => StatusCode::FORBIDDEN,
+            Error::Suspended => StatusCode::FORBIDDEN,
             Error::NotFound => StatusCode::NOT_FOUND,
             Error::BadHeader => StatusCode::BAD_REQUEST,
             Error::BadStatic(_) => StatusCode::BAD_REQUEST,
             Error::BadRequest(_) => StatusCode::BAD_REQUEST,
             Error::Serde(_) => StatusCode::BAD_REQUEST,
+            Error::MsgpackDecode(_) => StatusCode::BAD_REQUEST,
             Error::MissingAuth => StatusCode::UNAUTHORIZED,
             Error::UnauthSession => StatusCode::UNAUTHORIZED,
+            Error::InvalidCredentials => StatusCode::UNAUTHORIZED,
+            Error::TooManyAttempts => StatusCode::TOO_MANY_REQUESTS,
+            Error::ResumeExpired => StatusCode::BAD_REQUEST,
             Error::TooBig => StatusCode::PAYLOAD_TOO_LARGE,
             Error::MissingPermissions => StatusCode::FORBIDDEN,
             Error::CantOverwrite => StatusCode::CONFLICT,
+            Error::Conflict(_) => StatusCode::CONFLICT,
             Error::ParseInt(_) => StatusCode::BAD_REQUEST,
             Error::ParseFloat(_) => StatusCode::BAD_REQUEST,
             Error::Unimplemented => StatusCode::NOT_IMPLEMENTED,
@@ -149,6 +179,8 @@ impl Error {
     pub fn fake_clone(&self) -> Error {
         match self {
             Error::Blocked => Error::Blocked,
+            Error::Banned => Error::Banned,
+            Error::Suspended => Error::Suspended,
             Error::MissingAuth => Error::MissingAuth,
             Error::BadHeader => Error::BadHeader,
             Error::UnauthSession => Error::UnauthSession,
@@ -159,6 +191,7 @@ impl Error {
             Error::TooBig => Error::TooBig,
             Error::Internal(s) => Error::Internal(s.clone()),
             Error::CantOverwrite => Error::CantOverwrite,
+            Error::Conflict(s) => Error::Conflict(s),
             Error::ParseInt(parse_int_error) => Error::ParseInt(parse_int_error.clone()),
             Error::ParseFloat(parse_float_error) => Error::ParseFloat(parse_float_error.clone()),
             Error::Figment(error) => Error::Figment(error.clone()),
@@ -170,6 +203,9 @@ impl Error {
             Error::UnknownImageFormat => Error::UnknownImageFormat,
             Error::UrlEmbedOther(s) => Error::UrlEmbedOther(s.to_string()),
             Error::Validation(validation_errors) => Error::Validation(validation_errors.clone()),
+            Error::InvalidCredentials => Error::InvalidCredentials,
+            Error::TooManyAttempts => Error::TooManyAttempts,
+            Error::ResumeExpired => Error::ResumeExpired,
             _ => Error::GenericError(self.to_string()),
         }
     }
@@ -193,16 +229,13 @@ impl IntoResponse for Error {
     }
 }
 
-impl From<Error> for Message {
+impl From<Error> for MessageEnvelope {
     fn from(val: Error) -> Self {
-        Message::text(
-            serde_json::to_string(&MessageEnvelope {
-                payload: MessagePayload::Error {
-                    error: val.to_string(),
-                },
-            })
-            .expect("error should always be able to be serialized"),
-        )
+        MessageEnvelope {
+            payload: MessagePayload::Error {
+                error: val.to_string(),
+            },
+        }
     }
 }
 
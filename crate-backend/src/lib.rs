@@ -1,3 +1,5 @@
+pub mod admin;
+pub mod bus;
 pub mod cli;
 pub mod config;
 pub mod data;
@@ -1,7 +1,7 @@
 // TEMP: will remove deprecated routes later
 #![allow(deprecated)]
 
-use std::{str::FromStr, sync::Arc, time::Duration};
+use std::{net::SocketAddr, str::FromStr, sync::Arc};
 
 use axum::{extract::DefaultBodyLimit, response::Html, routing::get, Json};
 use clap::Parser;
@@ -10,7 +10,6 @@ use http::{header, HeaderName};
 use opendal::layers::LoggingLayer;
 use opentelemetry_otlp::WithExportConfig;
 use serde_json::json;
-use sqlx::postgres::PgPoolOptions;
 use tower_http::{
     catch_panic::CatchPanicLayer, cors::CorsLayer, propagate_header::PropagateHeaderLayer,
     sensitive_headers::SetSensitiveHeadersLayer, trace::TraceLayer,
@@ -21,14 +20,17 @@ use utoipa::{openapi::extensions::Extensions, Modify, OpenApi};
 use utoipa_axum::router::OpenApiRouter;
 
 use backend::{
-    cli, config, error,
+    cli::{self, NodeRole},
+    config::{self, ConfigEventBus},
+    data::Database,
+    error,
     routes::{self},
     types::{self, MessageId, MessageSync, PaginationQuery},
     ServerState,
 };
 
 use config::Config;
-use error::Result;
+use error::{Error, Result};
 
 #[derive(OpenApi)]
 #[openapi(
@@ -138,41 +140,87 @@ async fn main() -> Result<()> {
 
     tracing::subscriber::set_global_default(subscriber)?;
 
-    match &args.command {
-        cli::Command::Serve {} => serve(config).await?,
+    match args.command {
+        cli::Command::Serve { serve: args } => {
+            serve(config, NodeRole::ALL, Some(args.listen)).await?
+        }
         cli::Command::Check {} => check(config).await?,
+        cli::Command::Admin { command } => admin(config, command).await?,
+        cli::Command::ServeApi { serve: args } => {
+            serve(config, &[NodeRole::Api], Some(args.listen)).await?
+        }
+        cli::Command::ServeSyncer { serve: args } => {
+            serve(config, &[NodeRole::Syncer], Some(args.listen)).await?
+        }
+        cli::Command::ServeMedia { serve: args } => {
+            serve(config, &[NodeRole::Media], Some(args.listen)).await?
+        }
+        cli::Command::ServeWorker {} => serve(config, &[NodeRole::Worker], None).await?,
     }
 
     Ok(())
 }
 
-/// start the main server
-async fn serve(config: Config) -> Result<()> {
-    info!("Starting server with config: {:#?}", config);
-
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .acquire_timeout(Duration::from_secs(5))
-        .connect(&config.database_url)
-        .await?;
-
-    sqlx::migrate!("./migrations").run(&pool).await?;
-
-    let blobs_builder = opendal::services::S3::default()
+fn blobs_builder(config: &Config) -> opendal::services::S3 {
+    opendal::services::S3::default()
         .bucket(&config.s3.bucket)
         .endpoint(config.s3.endpoint.as_str())
         .region(&config.s3.region)
         .access_key_id(&config.s3.access_key_id)
-        .secret_access_key(&config.s3.secret_access_key);
-    let blobs = opendal::Operator::new(blobs_builder)?
+        .secret_access_key(&config.s3.secret_access_key)
+}
+
+/// start a node with some roles, listening for http requests if any of them serve routes
+async fn serve(mut config: Config, roles: &[NodeRole], listen: Option<SocketAddr>) -> Result<()> {
+    info!("Starting {:?} node with config: {:#?}", roles, config);
+
+    // split nodes only share the database, so events have to go through it too
+    if roles != NodeRole::ALL && matches!(config.event_bus, ConfigEventBus::Local) {
+        return Err(Error::BadStatic(
+            "nodes running some roles need event_bus = \"postgres\" to reach each other",
+        ));
+    }
+
+    if roles.contains(&NodeRole::Media) {
+        // media is processed here rather than sent to another node
+        if let Some(nodes) = &mut config.nodes {
+            nodes.media_url = None;
+        }
+    }
+
+    if !roles.contains(&NodeRole::Worker) {
+        // leave queued jobs for the worker nodes
+        config.url_preview.max_parallel_jobs = 0;
+        config.email_queue_workers = 0;
+    }
+
+    let db = Database::connect_with(&config.database_url, config.database_max_connections).await?;
+    db.migrate().await?;
+
+    let blobs = opendal::Operator::new(blobs_builder(&config))?
         .layer(LoggingLayer::default())
         .finish();
     blobs.check().await?;
 
-    let state = Arc::new(ServerState::new(config, pool, blobs));
+    let state = Arc::new(ServerState::new(config, db, blobs));
+
+    let Some(listen) = listen else {
+        tokio::signal::ctrl_c().await?;
+        return Ok(());
+    };
+
+    let mut routes = OpenApiRouter::new();
+    for role in roles {
+        routes = match role {
+            NodeRole::Api => routes.merge(routes::api()),
+            NodeRole::Syncer => routes.merge(routes::syncer()),
+            NodeRole::Media => routes.merge(routes::media()),
+            NodeRole::Worker => routes,
+        };
+    }
 
     let (router, api) = OpenApiRouter::with_openapi(ApiDoc::openapi())
-        .nest("/api/v1", routes::routes())
+        .nest("/api/v1", routes)
         .with_state(state)
         .split_for_parts();
     let router = router
@@ -189,7 +237,7 @@ async fn serve(config: Config) -> Result<()> {
         .layer(PropagateHeaderLayer::new(HeaderName::from_static(
             "x-trace-id",
         )));
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:4000").await?;
+    let listener = tokio::net::TcpListener::bind(listen).await?;
     axum::serve(listener, router).await?;
     Ok(())
 }
@@ -197,21 +245,24 @@ async fn serve(config: Config) -> Result<()> {
 /// check config
 async fn check(config: Config) -> Result<()> {
     info!("Parsed config: {:#?}", config);
-    PgPoolOptions::new()
-        .max_connections(5)
-        .acquire_timeout(Duration::from_secs(5))
-        .connect(&config.database_url)
-        .await?;
+    Database::connect_with(&config.database_url, config.database_max_connections).await?;
 
-    let blobs_builder = opendal::services::S3::default()
-        .bucket(&config.s3.bucket)
-        .endpoint(config.s3.endpoint.as_str())
-        .region(&config.s3.region)
-        .access_key_id(&config.s3.access_key_id)
-        .secret_access_key(&config.s3.secret_access_key);
-    let blobs = opendal::Operator::new(blobs_builder)?
+    let blobs = opendal::Operator::new(blobs_builder(&config))?
         .layer(LoggingLayer::default())
         .finish();
     blobs.check().await?;
     Ok(())
 }
+
+/// run a server management command
+async fn admin(mut config: Config, command: cli::AdminCommand) -> Result<()> {
+    // leave queued jobs for the running nodes
+    config.url_preview.max_parallel_jobs = 0;
+    config.email_queue_workers = 0;
+    let db = Database::connect_with(&config.database_url, config.database_max_connections).await?;
+    let blobs = opendal::Operator::new(blobs_builder(&config))?
+        .layer(LoggingLayer::default())
+        .finish();
+    let state = ServerState::new(config, db, blobs);
+    backend::admin::run(&state, command).await
+}
@@ -11,6 +11,7 @@ use common::v1::types::auth::CaptchaResponse;
 use common::v1::types::auth::PasswordExec;
 use common::v1::types::auth::PasswordExecIdent;
 use common::v1::types::auth::PasswordSet;
+use common::v1::types::auth::TotpRecoveryCode;
 use common::v1::types::auth::TotpRecoveryCodes;
 use common::v1::types::auth::TotpState;
 use common::v1::types::auth::TotpStateWithSecret;
@@ -18,11 +19,16 @@ use common::v1::types::auth::TotpVerificationRequest;
 use common::v1::types::email::EmailAddr;
 use common::v1::types::util::Time;
 use common::v1::types::MessageSync;
+use common::v1::types::SessionId;
 use common::v1::types::SessionStatus;
+use common::v1::types::UserId;
 use http::StatusCode;
+use nanoid::nanoid;
 use serde::Deserialize;
 use serde::Serialize;
+use subtle::ConstantTimeEq;
 use time::Duration;
+use totp_rs::{Algorithm, Secret, TOTP};
 use tracing::debug;
 use url::Url;
 use utoipa::IntoParams;
@@ -40,6 +46,12 @@ use super::util::AuthRelaxed;
 use super::util::AuthSudo;
 use super::util::AuthWithSession;
 
+const TOTP_SECRET_LEN: usize = 20;
+const TOTP_STEP: u64 = 30;
+const TOTP_RECOVERY_CODE_COUNT: usize = 10;
+const TOTP_MAX_FAILURES: u32 = 5;
+const TOTP_LOCKOUT: std::time::Duration = std::time::Duration::from_secs(15 * 60);
+
 #[derive(Debug, Deserialize, IntoParams)]
 pub struct OauthRedirectQuery {
     state: Uuid,
@@ -244,7 +256,10 @@ async fn auth_email_reset(
     Err(Error::Unimplemented)
 }
 
-/// Auth totp init (TODO)
+/// Auth totp init
+///
+/// Generate a new totp secret. It must be verified with totp exec before it
+/// is enabled. Fails if totp is already enabled.
 #[utoipa::path(
     post,
     path = "/auth/totp/init",
@@ -252,28 +267,92 @@ async fn auth_email_reset(
     responses((status = OK, body = TotpStateWithSecret, description = "success")),
 )]
 async fn auth_totp_init(
-    AuthSudo(_auth_user_id): AuthSudo,
-    State(_s): State<Arc<ServerState>>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    AuthSudo(auth_user_id): AuthSudo,
+    State(s): State<Arc<ServerState>>,
+) -> Result<impl IntoResponse> {
+    let data = s.data();
+    if let Some(totp) = data.auth_totp_get(auth_user_id).await? {
+        if totp.is_valid {
+            return Err(Error::BadStatic("totp is already enabled"));
+        }
+    }
+    let secret = {
+        let mut secret = [0u8; TOTP_SECRET_LEN];
+        rand::fill(&mut secret);
+        secret
+    };
+    data.auth_totp_set(auth_user_id, &secret).await?;
+    Ok(Json(TotpStateWithSecret {
+        state: TotpState { is_valid: false },
+        secret: Secret::Raw(secret.to_vec()).to_encoded().to_string(),
+    }))
 }
 
-/// Auth totp execute (TODO)
+/// Auth totp execute
+///
+/// Verify a totp or recovery code and upgrade this session to sudo mode. The
+/// first successful verification after init enables totp. Recovery codes can
+/// only be used once totp is enabled.
 #[utoipa::path(
     post,
     path = "/auth/totp",
     tags = ["auth"],
-    responses((status = OK, body = TotpState, description = "success")),
+    responses(
+        (status = OK, body = TotpState, description = "success"),
+        (status = UNAUTHORIZED, description = "invalid code"),
+        (status = TOO_MANY_REQUESTS, description = "too many failed attempts"),
+    ),
 )]
 async fn auth_totp_exec(
-    Auth(_auth_user_id): Auth,
-    State(_s): State<Arc<ServerState>>,
-    Json(_json): Json<TotpVerificationRequest>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    AuthWithSession(session, auth_user_id): AuthWithSession,
+    State(s): State<Arc<ServerState>>,
+    Json(json): Json<TotpVerificationRequest>,
+) -> Result<impl IntoResponse> {
+    let data = s.data();
+    let totp = data
+        .auth_totp_get(auth_user_id)
+        .await?
+        .ok_or(Error::BadStatic("totp is not enabled"))?;
+    if !data
+        .auth_totp_attempt(auth_user_id, TOTP_MAX_FAILURES, TOTP_LOCKOUT)
+        .await?
+    {
+        return Err(Error::TooManyAttempts);
+    }
+    let code = json.code.trim();
+    let valid = if let Some(step) = totp_check(&totp.secret, code) {
+        data.auth_totp_use(auth_user_id, step).await?
+    } else if totp.is_valid {
+        // only the hint is stored in plain text, so use it to avoid checking every hash
+        let hint = totp_recovery_hint(code);
+        let hash = data
+            .auth_totp_recovery_unused(auth_user_id)
+            .await?
+            .into_iter()
+            .filter(|(_, code_hint)| Some(code_hint) == hint.as_ref())
+            .map(|(hash, _)| hash)
+            .find(|hash| argon2::verify_encoded(hash, code.as_bytes()).unwrap_or(false));
+        match hash {
+            Some(hash) => {
+                data.auth_totp_recovery_use(auth_user_id, &hash, session.id)
+                    .await?
+            }
+            None => false,
+        }
+    } else {
+        false
+    };
+    if !valid {
+        return Err(Error::InvalidCredentials);
+    }
+    data.auth_totp_attempt_reset(auth_user_id).await?;
+    session_sudo(&s, session.id, auth_user_id).await?;
+    Ok(Json(TotpState { is_valid: true }))
 }
 
-/// Auth totp recovery codes get (TODO)
+/// Auth totp recovery codes get
+///
+/// Only the last few characters of each code are returned.
 #[utoipa::path(
     get,
     path = "/auth/totp/recovery",
@@ -281,13 +360,17 @@ async fn auth_totp_exec(
     responses((status = OK, body = TotpRecoveryCodes, description = "success")),
 )]
 async fn auth_totp_recovery_get(
-    AuthSudo(_auth_user_id): AuthSudo,
-    State(_s): State<Arc<ServerState>>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    AuthSudo(auth_user_id): AuthSudo,
+    State(s): State<Arc<ServerState>>,
+) -> Result<impl IntoResponse> {
+    let items = s.data().auth_totp_recovery_list(auth_user_id).await?;
+    Ok(Json(TotpRecoveryCodes { items }))
 }
 
-/// Auth totp recovery codes rotate (TODO)
+/// Auth totp recovery codes rotate
+///
+/// Replace all recovery codes with new ones. This is the only time the full
+/// codes are returned.
 #[utoipa::path(
     post,
     path = "/auth/totp/recovery",
@@ -295,13 +378,44 @@ async fn auth_totp_recovery_get(
     responses((status = OK, body = TotpRecoveryCodes, description = "success")),
 )]
 async fn auth_totp_recovery_rotate(
-    AuthSudo(_auth_user_id): AuthSudo,
-    State(_s): State<Arc<ServerState>>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    AuthSudo(auth_user_id): AuthSudo,
+    State(s): State<Arc<ServerState>>,
+) -> Result<impl IntoResponse> {
+    let data = s.data();
+    match data.auth_totp_get(auth_user_id).await? {
+        Some(totp) if totp.is_valid => {}
+        _ => return Err(Error::BadStatic("totp is not enabled")),
+    }
+    let alphabet: Vec<char> = "abcdefghjkmnpqrstuvwxyz23456789".chars().collect();
+    let codes: Vec<String> = (0..TOTP_RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code = nanoid!(12, &alphabet);
+            format!("{}-{}-{}", &code[0..4], &code[4..8], &code[8..12])
+        })
+        .collect();
+    data.auth_totp_recovery_set(
+        auth_user_id,
+        codes
+            .iter()
+            .map(|code| {
+                (
+                    totp_recovery_hash(code),
+                    totp_recovery_hint(code).expect("generated codes are long enough"),
+                )
+            })
+            .collect(),
+    )
+    .await?;
+    let items = codes
+        .into_iter()
+        .map(|code| TotpRecoveryCode { code, used: None })
+        .collect();
+    Ok(Json(TotpRecoveryCodes { items }))
 }
 
-/// Auth totp delete (TODO)
+/// Auth totp delete
+///
+/// Disable totp and delete all recovery codes.
 #[utoipa::path(
     delete,
     path = "/auth/totp",
@@ -309,10 +423,11 @@ async fn auth_totp_recovery_rotate(
     responses((status = NO_CONTENT, description = "success")),
 )]
 async fn auth_totp_delete(
-    AuthSudo(_auth_user_id): AuthSudo,
-    State(_s): State<Arc<ServerState>>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    AuthSudo(auth_user_id): AuthSudo,
+    State(s): State<Arc<ServerState>>,
+) -> Result<impl IntoResponse> {
+    s.data().auth_totp_delete(auth_user_id).await?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
 /// Auth password set
@@ -391,7 +506,7 @@ async fn auth_password_exec(
     }
 }
 
-/// Auth status (TODO)
+/// Auth status
 #[utoipa::path(
     get,
     path = "/auth",
@@ -399,10 +514,27 @@ async fn auth_password_exec(
     responses((status = OK, body = AuthStatus, description = "success")),
 )]
 async fn auth_status(
-    Auth(_auth_user_id): Auth,
-    State(_s): State<Arc<ServerState>>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    Auth(auth_user_id): Auth,
+    State(s): State<Arc<ServerState>>,
+) -> Result<impl IntoResponse> {
+    let data = s.data();
+    let has_verified_email = data
+        .user_email_list(auth_user_id)
+        .await?
+        .iter()
+        .any(|e| e.is_verified);
+    let has_totp = data
+        .auth_totp_get(auth_user_id)
+        .await?
+        .is_some_and(|t| t.is_valid);
+    let has_password = data.auth_password_get(auth_user_id).await?.is_some();
+    let oauth_providers = data.auth_oauth_list(auth_user_id).await?;
+    Ok(Json(AuthStatus {
+        has_verified_email,
+        has_totp,
+        has_password,
+        oauth_providers,
+    }))
 }
 
 /// Auth captcha init (TODO)
@@ -439,7 +571,8 @@ async fn auth_captcha_submit(
 
 /// Auth sudo (TEMP)
 ///
-/// instantly upgrade to sudo mode; this is intended for debugging
+/// instantly upgrade to sudo mode; this is intended for debugging. users
+/// with totp enabled must use totp exec instead.
 #[utoipa::path(
     post,
     path = "/auth/_sudo",
@@ -450,17 +583,59 @@ async fn auth_sudo(
     AuthWithSession(session, auth_user_id): AuthWithSession,
     State(s): State<Arc<ServerState>>,
 ) -> Result<impl IntoResponse> {
+    let data = s.data();
+    if let Some(totp) = data.auth_totp_get(auth_user_id).await? {
+        if totp.is_valid {
+            return Err(Error::BadStatic("totp is enabled, use totp exec instead"));
+        }
+    }
+    session_sudo(&s, session.id, auth_user_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// upgrade a session to sudo mode for the next few minutes
+async fn session_sudo(s: &ServerState, session_id: SessionId, user_id: UserId) -> Result<()> {
     s.data()
         .session_set_status(
-            session.id,
+            session_id,
             SessionStatus::Sudo {
-                user_id: auth_user_id,
+                user_id,
                 expires_at: Time::now_utc().saturating_add(Duration::minutes(5)).into(),
             },
         )
         .await?;
-    s.services().sessions.invalidate(session.id).await;
-    Ok(StatusCode::NO_CONTENT)
+    s.services().sessions.invalidate(session_id).await;
+    Ok(())
+}
+
+/// check a totp code against the current time, allowing one step of skew
+///
+/// returns the matched time step
+fn totp_check(secret: &[u8], code: &str) -> Option<i64> {
+    let totp = TOTP::new_unchecked(Algorithm::SHA1, 6, 1, TOTP_STEP, secret.to_vec());
+    let now = Time::now_utc().unix_timestamp() as u64;
+    let current = now / TOTP_STEP;
+    for step in [current - 1, current, current + 1] {
+        let expected = totp.generate(step * TOTP_STEP);
+        if bool::from(expected.as_bytes().ct_eq(code.as_bytes())) {
+            return Some(step as i64);
+        }
+    }
+    None
+}
+
+fn totp_recovery_hash(code: &str) -> String {
+    let salt = {
+        let mut salt = [0u8; 16];
+        rand::fill(&mut salt);
+        salt
+    };
+    argon2::hash_encoded(code.as_bytes(), &salt, &argon2::Config::default()).unwrap()
+}
+
+/// the part of a recovery code that is shown after it was generated
+fn totp_recovery_hint(code: &str) -> Option<String> {
+    code.get(10..).map(|end| format!("****-****-{end}"))
 }
 
 pub fn routes() -> OpenApiRouter<Arc<ServerState>> {
@@ -2,11 +2,13 @@ use std::sync::Arc;
 
 use axum::{extract::State, http::StatusCode, Json};
 use common::v1::types::voice::{SignallingMessage, VoiceState};
-use common::v1::types::{MessageSync, UserId};
+use common::v1::types::{Media, MessageSync, ThreadId, UserId};
 use http::HeaderMap;
 use utoipa_axum::{router::OpenApiRouter, routes};
+use uuid::Uuid;
 
 use crate::error::Result;
+use crate::types::{DbSfu, NodeCommand};
 use crate::{Error, ServerState};
 
 // TODO: does this count as an implementation detail or should it be moved to common?
@@ -26,6 +28,17 @@ enum Command {
         old: Option<VoiceState>,
         state: Option<VoiceState>,
     },
+
+    /// add or refresh an sfu. sent periodically by every sfu.
+    #[cfg(feature = "voice")]
+    Register {
+        id: Uuid,
+
+        /// where the sfu's rpc endpoint can be reached
+        url: String,
+        region: String,
+        load: f32,
+    },
 }
 
 /// Internal rpc
@@ -45,12 +58,24 @@ async fn internal_rpc(
         .get("authorization")
         .ok_or(Error::MissingAuth)?
         .to_str()?;
-    if auth != "Server verysecrettoken" {
-        return Err(Error::MissingAuth);
-    }
+    let token_name = s
+        .config
+        .sfu_token_name(auth)
+        .ok_or(Error::MissingAuth)?
+        .to_owned();
     match json {
         #[cfg(feature = "voice")]
         Command::VoiceDispatch { user_id, payload } => {
+            let thread_id = match &payload {
+                SignallingMessage::Have { thread_id, .. } => *thread_id,
+                // everything else is for someone in a call
+                _ => s
+                    .data()
+                    .voice_member_get(user_id)
+                    .await?
+                    .ok_or(Error::MissingPermissions)?,
+            };
+            ensure_hosted(&s, thread_id, &token_name).await?;
             s.broadcast(MessageSync::VoiceDispatch { user_id, payload })?;
         }
         #[cfg(feature = "voice")]
@@ -59,6 +84,15 @@ async fn internal_rpc(
             old,
             state,
         } => {
+            for v in old.iter().chain(&state) {
+                ensure_hosted(&s, v.thread_id, &token_name).await?;
+            }
+            match (&old, &state) {
+                (_, Some(v)) => s.data().voice_member_set(user_id, v.thread_id).await?,
+                (Some(v), None) => s.data().voice_member_delete(user_id, v.thread_id).await?,
+                (None, None) => {}
+            }
+
             // TODO: deduplicate
             if let Some(v) = &old {
                 s.broadcast_thread(
@@ -82,10 +116,80 @@ async fn internal_rpc(
                 .await?;
             }
         }
+        #[cfg(feature = "voice")]
+        Command::Register {
+            id,
+            url,
+            region,
+            load,
+        } => {
+            s.services()
+                .voice
+                .sfu_register(DbSfu {
+                    id,
+                    url,
+                    region,
+                    load,
+                    token_name,
+                })
+                .await?;
+        }
     };
     Ok(StatusCode::ACCEPTED)
 }
 
+/// make sure an sfu only reports on calls that are hosted by an sfu using its token
+#[cfg(feature = "voice")]
+async fn ensure_hosted(s: &ServerState, thread_id: ThreadId, token_name: &str) -> Result<()> {
+    match s.services().voice.sfu_thread(thread_id).await {
+        Ok(sfu) if sfu.token_name == token_name => Ok(()),
+        Ok(_) | Err(Error::NotFound) => Err(Error::MissingPermissions),
+        Err(err) => Err(err),
+    }
+}
+
+/// Internal node rpc
+///
+/// lets nodes running other roles hand work to media nodes
+#[utoipa::path(
+    post,
+    path = "/internal/node",
+    tags = ["internal"],
+    responses((status = OK, body = Media, description = "ok")),
+)]
+async fn internal_node_rpc(
+    headers: HeaderMap,
+    State(s): State<Arc<ServerState>>,
+    Json(json): Json<NodeCommand>,
+) -> Result<Json<Media>> {
+    let auth = headers
+        .get("authorization")
+        .ok_or(Error::MissingAuth)?
+        .to_str()?;
+    if !s.config.node_token_valid(auth) {
+        return Err(Error::MissingAuth);
+    }
+    let media = match json {
+        NodeCommand::MediaImport {
+            user_id,
+            media,
+            max_size,
+        } => {
+            s.services()
+                .media
+                .import_from_url_with_max_size(user_id, media, max_size)
+                .await?
+        }
+    };
+    Ok(Json(media))
+}
+
+/// routes served by api nodes
 pub fn routes() -> OpenApiRouter<Arc<ServerState>> {
     OpenApiRouter::new().routes(routes!(internal_rpc))
 }
+
+/// routes served by media nodes
+pub fn media_routes() -> OpenApiRouter<Arc<ServerState>> {
+    OpenApiRouter::new().routes(routes!(internal_node_rpc))
+}
@@ -164,7 +164,7 @@ pub async fn invite_use(
     HeaderReason(reason): HeaderReason,
     State(s): State<Arc<ServerState>>,
 ) -> Result<impl IntoResponse> {
-    let d = s.data();
+    let d = s.data().begin().await?;
     let invite = d.invite_select(code.clone()).await?;
     if invite.is_dead() {
         return Err(Error::NotFound);
@@ -172,6 +172,11 @@ pub async fn invite_use(
     match invite.invite.target {
         InviteTarget::Thread { room, .. } | InviteTarget::Room { room } => {
             // TODO: any thread-specific invite things?
+            match d.room_ban_get(room.id, user_id).await {
+                Ok(_) => return Err(Error::Banned),
+                Err(Error::NotFound) => {}
+                Err(err) => return Err(err),
+            }
             d.room_member_put(
                 room.id,
                 user_id,
@@ -184,6 +189,8 @@ pub async fn invite_use(
             .await?;
             d.role_apply_default(room.id, user_id).await?;
             let member = d.room_member_get(room.id, user_id).await?;
+            d.invite_incr_use(code).await?;
+            d.commit().await?;
             s.services.perms.invalidate_room(user_id, room.id).await;
             s.services.perms.invalidate_is_mutual(user_id);
             s.broadcast_room(
@@ -196,15 +203,15 @@ pub async fn invite_use(
         }
         InviteTarget::Server => {
             // Upgrade the user to a full account
-            s.data()
-                .user_set_registered_at(user_id, Some(common::v1::types::util::Time::now_utc()))
+            d.user_set_registered_at(user_id, Some(common::v1::types::util::Time::now_utc()))
                 .await?;
+            d.invite_incr_use(code).await?;
+            d.commit().await?;
             s.services().users.invalidate(user_id).await;
             let updated_user = s.services().users.get(user_id).await?;
             s.broadcast(MessageSync::UserUpdate { user: updated_user })?;
         }
     }
-    d.invite_incr_use(code).await?;
     Ok(())
 }
 
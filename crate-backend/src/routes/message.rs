@@ -6,17 +6,18 @@ use axum::{
     response::IntoResponse,
     Json,
 };
-use common::v1::types::PaginationDirection;
+use common::v1::types::{Mentions, MessagePin, MessagesMoved, PaginationDirection, PinsReorder};
 use serde::{Deserialize, Serialize};
 use utoipa::{IntoParams, ToSchema};
 use utoipa_axum::{router::OpenApiRouter, routes};
 use validator::Validate;
 
 use crate::{
+    data::Data,
     error::Error,
     types::{
-        Message, MessageCreate, MessageId, MessagePatch, MessageSync, MessageVerId,
-        PaginationQuery, PaginationResponse, Permission, ThreadId,
+        DbMessageCreate, Message, MessageCreate, MessageId, MessagePatch, MessageSync, MessageType,
+        MessageVerId, PaginationQuery, PaginationResponse, Permission, ThreadId, UserId,
     },
     ServerState,
 };
@@ -24,6 +25,12 @@ use crate::{
 use super::util::{Auth, HeaderIdempotencyKey, HeaderReason};
 use crate::error::Result;
 
+/// the maximum number of pinned messages in a thread
+const MAX_PINS: usize = 50;
+
+/// the maximum number of messages that can be moved at once
+const MAX_MOVE: usize = 128;
+
 /// Create a message
 #[utoipa::path(
     post,
@@ -366,11 +373,18 @@ struct MessageBulkDelete {
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema, Validate)]
 struct MessageMigrate {
-    /// which messages to move
+    /// which messages to move. they can't skip any messages between them,
+    /// other than system messages which are never moved.
     #[serde(default)]
-    #[validate(length(min = 1, max = 128))]
+    #[validate(length(max = 128))]
     message_ids: Vec<MessageId>,
 
+    /// move every message from start_id to end_id (inclusive) instead of a list
+    start_id: Option<MessageId>,
+
+    /// move every message from start_id to end_id (inclusive) instead of a list
+    end_id: Option<MessageId>,
+
     /// must be in same room (for now...)
     target_id: ThreadId,
 }
@@ -499,7 +513,10 @@ async fn message_moderate(
     Ok(StatusCode::OK)
 }
 
-/// Message move (TODO)
+/// Message move
+///
+/// Move a list or a range of messages to another thread. Messages keep their
+/// ids, versions, reactions, and attachments.
 #[utoipa::path(
     post,
     path = "/thread/{thread_id}/migrate",
@@ -508,13 +525,147 @@ async fn message_moderate(
     responses((status = NO_CONTENT, description = "move success")),
 )]
 async fn message_migrate(
-    Path(_thread_id): Path<ThreadId>,
-    Auth(_user_id): Auth,
-    HeaderReason(_reason): HeaderReason,
-    State(_s): State<Arc<ServerState>>,
-    Json(_json): Json<MessageMigrate>,
-) -> Result<()> {
-    todo!()
+    Path(thread_id): Path<ThreadId>,
+    Auth(user_id): Auth,
+    HeaderReason(reason): HeaderReason,
+    State(s): State<Arc<ServerState>>,
+    Json(json): Json<MessageMigrate>,
+) -> Result<StatusCode> {
+    json.validate()?;
+    if json.target_id == thread_id {
+        return Err(Error::BadStatic("cant move messages to the same thread"));
+    }
+    let data = s.data();
+    let srv = s.services();
+    let perms = srv.perms.for_thread(user_id, thread_id).await?;
+    perms.ensure_view()?;
+    perms.ensure(Permission::MessageMove)?;
+    let target_perms = srv.perms.for_thread(user_id, json.target_id).await?;
+    target_perms.ensure_view()?;
+    target_perms.ensure(Permission::MessageMove)?;
+    let source = srv.threads.get(thread_id, None).await?;
+    let target = srv.threads.get(json.target_id, None).await?;
+    if source.room_id != target.room_id {
+        return Err(Error::BadStatic(
+            "messages can only be moved to a thread in the same room",
+        ));
+    }
+
+    // read and move everything in one transaction so the markers match what moved
+    let tx = data.begin().await?;
+    let mut message_ids = match (json.start_id, json.end_id) {
+        (Some(start_id), Some(end_id)) => {
+            if !json.message_ids.is_empty() {
+                return Err(Error::BadStatic(
+                    "use either message_ids or start_id and end_id",
+                ));
+            }
+            let ids = tx
+                .message_id_range(thread_id, start_id, end_id, MAX_MOVE as u16 + 1)
+                .await?;
+            if ids.len() > MAX_MOVE {
+                return Err(Error::BadStatic("too many messages"));
+            }
+            // system messages in the middle of a range stay where they are
+            tx.message_get_many(thread_id, &ids, user_id)
+                .await?
+                .into_iter()
+                .filter(|m| m.message_type.is_movable())
+                .map(|m| m.id)
+                .collect::<Vec<_>>()
+        }
+        (None, None) => {
+            let mut ids = json.message_ids.clone();
+            ids.sort();
+            ids.dedup();
+            let messages = tx.message_get_many(thread_id, &ids, user_id).await?;
+            if messages.len() != ids.len() {
+                return Err(Error::NotFound);
+            }
+            if messages.iter().any(|m| !m.message_type.is_movable()) {
+                return Err(Error::BadStatic("cant move that message"));
+            }
+            // the markers only record where the moved messages start and end
+            if let (Some(first), Some(last)) = (ids.first(), ids.last()) {
+                let range = tx
+                    .message_id_range(thread_id, *first, *last, MAX_MOVE as u16 + 1)
+                    .await?;
+                if range.len() > MAX_MOVE {
+                    return Err(Error::BadStatic("too many messages"));
+                }
+                let skipped = tx
+                    .message_get_many(thread_id, &range, user_id)
+                    .await?
+                    .into_iter()
+                    .any(|m| m.message_type.is_movable() && ids.binary_search(&m.id).is_err());
+                if skipped {
+                    return Err(Error::BadStatic("message_ids must be contiguous"));
+                }
+            }
+            ids
+        }
+        _ => return Err(Error::BadStatic("start_id and end_id must both be set")),
+    };
+    message_ids.sort();
+    message_ids.dedup();
+    let (Some(start_id), Some(end_id)) = (message_ids.first(), message_ids.last()) else {
+        return Err(Error::BadStatic("no messages to move"));
+    };
+    let moved = MessagesMoved {
+        start_id: *start_id,
+        end_id: *end_id,
+        source_id: thread_id,
+        target_id: json.target_id,
+        reason: reason.clone(),
+    };
+
+    tx.message_move(thread_id, json.target_id, &message_ids)
+        .await?;
+
+    // leave a marker in both threads
+    let mut markers = vec![];
+    for marker_thread_id in [thread_id, json.target_id] {
+        let marker_id = tx
+            .message_create(DbMessageCreate {
+                thread_id: marker_thread_id,
+                attachment_ids: vec![],
+                author_id: user_id,
+                embeds: vec![],
+                message_type: MessageType::MessagesMoved(moved.clone()),
+                mentions: Mentions::default(),
+                edited_at: None,
+                created_at: None,
+            })
+            .await?;
+        markers.push(tx.message_get(marker_thread_id, marker_id, user_id).await?);
+    }
+    tx.commit().await?;
+
+    for marker_thread_id in [thread_id, json.target_id] {
+        srv.threads.invalidate(marker_thread_id).await; // last version id, message count
+    }
+    s.broadcast_thread(
+        thread_id,
+        user_id,
+        reason,
+        MessageSync::MessageMoveBulk {
+            source_id: thread_id,
+            target_id: json.target_id,
+            message_ids,
+        },
+    )
+    .await?;
+    for marker in markers {
+        s.broadcast_thread(
+            marker.thread_id,
+            user_id,
+            None,
+            MessageSync::MessageCreate { message: marker },
+        )
+        .await?;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
 }
 
 #[derive(Debug, Default, Serialize, Deserialize, ToSchema, IntoParams, Validate)]
@@ -569,6 +720,213 @@ async fn message_replies(
     Ok(Json(res))
 }
 
+/// List pinned messages
+///
+/// Pinned messages are returned in pin order
+#[utoipa::path(
+    get,
+    path = "/thread/{thread_id}/pin",
+    params(("thread_id", description = "Thread id")),
+    tags = ["message"],
+    responses(
+        (status = OK, body = Vec<Message>, description = "success"),
+    )
+)]
+async fn message_pin_list(
+    Path((thread_id,)): Path<(ThreadId,)>,
+    Auth(user_id): Auth,
+    State(s): State<Arc<ServerState>>,
+) -> Result<impl IntoResponse> {
+    let data = s.data();
+    let perms = s.services().perms.for_thread(user_id, thread_id).await?;
+    perms.ensure_view()?;
+    let mut messages = data.message_pin_list(thread_id, user_id).await?;
+    for message in &mut messages {
+        s.presign_message(message).await?;
+    }
+    Ok(Json(messages))
+}
+
+/// Pin message
+///
+/// Pin a message to the start of the thread's pin list
+#[utoipa::path(
+    put,
+    path = "/thread/{thread_id}/message/{message_id}/pin",
+    params(
+        ("thread_id", description = "Thread id"),
+        ("message_id", description = "Message id")
+    ),
+    tags = ["message"],
+    responses(
+        (status = NO_CONTENT, description = "pin message success"),
+        (status = NOT_MODIFIED, description = "message was already pinned"),
+    )
+)]
+async fn message_pin_create(
+    Path((thread_id, message_id)): Path<(ThreadId, MessageId)>,
+    Auth(user_id): Auth,
+    HeaderReason(reason): HeaderReason,
+    State(s): State<Arc<ServerState>>,
+) -> Result<StatusCode> {
+    let perms = s.services().perms.for_thread(user_id, thread_id).await?;
+    perms.ensure_view()?;
+    perms.ensure(Permission::MessagePin)?;
+    let data = s.data().begin().await?;
+    let message = data.message_get(thread_id, message_id, user_id).await?;
+    if message.pinned_at.is_some() {
+        return Err(Error::NotModified);
+    }
+    if !data
+        .message_pin_create(thread_id, message_id, MAX_PINS)
+        .await?
+    {
+        return Err(Error::BadStatic("too many pinned messages"));
+    }
+    let pin_message_id = data
+        .message_create(DbMessageCreate {
+            thread_id,
+            attachment_ids: vec![],
+            author_id: user_id,
+            embeds: vec![],
+            message_type: MessageType::MessagePinned(MessagePin {
+                message_id,
+                user_id,
+                reason: reason.clone(),
+            }),
+            mentions: Mentions::default(),
+            edited_at: None,
+            created_at: None,
+        })
+        .await?;
+    let pins = pin_ids(&*data, thread_id, user_id).await?;
+    let pin_message = data.message_get(thread_id, pin_message_id, user_id).await?;
+    data.commit().await?;
+    s.services().threads.invalidate(thread_id).await; // last version id, message count
+    broadcast_pins(&s, thread_id, user_id, reason, pins).await?;
+    s.broadcast_thread(
+        thread_id,
+        user_id,
+        None,
+        MessageSync::MessageCreate {
+            message: pin_message,
+        },
+    )
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Unpin message
+#[utoipa::path(
+    delete,
+    path = "/thread/{thread_id}/message/{message_id}/pin",
+    params(
+        ("thread_id", description = "Thread id"),
+        ("message_id", description = "Message id")
+    ),
+    tags = ["message"],
+    responses(
+        (status = NO_CONTENT, description = "unpin message success"),
+        (status = NOT_MODIFIED, description = "message wasn't pinned"),
+    )
+)]
+async fn message_pin_delete(
+    Path((thread_id, message_id)): Path<(ThreadId, MessageId)>,
+    Auth(user_id): Auth,
+    HeaderReason(reason): HeaderReason,
+    State(s): State<Arc<ServerState>>,
+) -> Result<StatusCode> {
+    let perms = s.services().perms.for_thread(user_id, thread_id).await?;
+    perms.ensure_view()?;
+    perms.ensure(Permission::MessagePin)?;
+    let data = s.data().begin().await?;
+    data.message_get(thread_id, message_id, user_id).await?;
+    if !data.message_pin_delete(thread_id, message_id).await? {
+        return Err(Error::NotModified);
+    }
+    let unpin_message_id = data
+        .message_create(DbMessageCreate {
+            thread_id,
+            attachment_ids: vec![],
+            author_id: user_id,
+            embeds: vec![],
+            message_type: MessageType::MessageUnpinned(MessagePin {
+                message_id,
+                user_id,
+                reason: reason.clone(),
+            }),
+            mentions: Mentions::default(),
+            edited_at: None,
+            created_at: None,
+        })
+        .await?;
+    let pins = pin_ids(&*data, thread_id, user_id).await?;
+    let unpin_message = data
+        .message_get(thread_id, unpin_message_id, user_id)
+        .await?;
+    data.commit().await?;
+    s.services().threads.invalidate(thread_id).await; // last version id, message count
+    broadcast_pins(&s, thread_id, user_id, reason, pins).await?;
+    s.broadcast_thread(
+        thread_id,
+        user_id,
+        None,
+        MessageSync::MessageCreate {
+            message: unpin_message,
+        },
+    )
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Reorder pinned messages
+#[utoipa::path(
+    patch,
+    path = "/thread/{thread_id}/pin",
+    params(("thread_id", description = "Thread id")),
+    tags = ["message"],
+    responses(
+        (status = NO_CONTENT, description = "reorder pins success"),
+    )
+)]
+async fn message_pin_reorder(
+    Path((thread_id,)): Path<(ThreadId,)>,
+    Auth(user_id): Auth,
+    HeaderReason(reason): HeaderReason,
+    State(s): State<Arc<ServerState>>,
+    Json(json): Json<PinsReorder>,
+) -> Result<StatusCode> {
+    json.validate()?;
+    let data = s.data();
+    let perms = s.services().perms.for_thread(user_id, thread_id).await?;
+    perms.ensure_view()?;
+    perms.ensure(Permission::MessagePin)?;
+    data.message_pin_reorder(thread_id, &json.messages).await?;
+    broadcast_pins(&s, thread_id, user_id, reason, json.messages).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// the pinned messages in a thread, in pin order
+async fn pin_ids(data: &dyn Data, thread_id: ThreadId, user_id: UserId) -> Result<Vec<MessageId>> {
+    let pins = data.message_pin_list(thread_id, user_id).await?;
+    Ok(pins.into_iter().map(|m| m.id).collect())
+}
+
+/// send a thread's new pin list, since changing one pin can reorder the rest
+async fn broadcast_pins(
+    s: &ServerState,
+    thread_id: ThreadId,
+    user_id: UserId,
+    reason: Option<String>,
+    message_ids: Vec<MessageId>,
+) -> Result<()> {
+    let msg = MessageSync::MessagePinsUpdate {
+        thread_id,
+        message_ids,
+    };
+    s.broadcast_thread(thread_id, user_id, reason, msg).await
+}
+
 pub fn routes() -> OpenApiRouter<Arc<ServerState>> {
     OpenApiRouter::new()
         .routes(routes!(message_create))
@@ -584,4 +942,8 @@ pub fn routes() -> OpenApiRouter<Arc<ServerState>> {
         .routes(routes!(message_replies))
         .routes(routes!(message_moderate))
         .routes(routes!(message_migrate))
+        .routes(routes!(message_pin_list))
+        .routes(routes!(message_pin_create))
+        .routes(routes!(message_pin_delete))
+        .routes(routes!(message_pin_reorder))
 }
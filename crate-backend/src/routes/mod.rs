@@ -33,7 +33,16 @@ mod user_email;
 mod util;
 mod voice;
 
+/// every route
 pub fn routes() -> OpenApiRouter<Arc<ServerState>> {
+    OpenApiRouter::new()
+        .merge(api())
+        .merge(syncer())
+        .merge(media())
+}
+
+/// routes served by api nodes
+pub fn api() -> OpenApiRouter<Arc<ServerState>> {
     OpenApiRouter::new()
         .merge(application::routes())
         .merge(auth::routes())
@@ -41,7 +50,6 @@ pub fn routes() -> OpenApiRouter<Arc<ServerState>> {
         .merge(emoji::routes())
         .merge(internal::routes())
         .merge(invite::routes())
-        .merge(media::routes())
         .merge(message::routes())
         .merge(moderation::routes())
         .merge(notification::routes())
@@ -53,7 +61,6 @@ pub fn routes() -> OpenApiRouter<Arc<ServerState>> {
         .merge(room_member::routes())
         .merge(search::routes())
         .merge(session::routes())
-        .merge(sync::routes())
         .merge(tag::routes())
         .merge(thread::routes())
         .merge(thread_member::routes())
@@ -62,3 +69,13 @@ pub fn routes() -> OpenApiRouter<Arc<ServerState>> {
         .merge(user_email::routes())
         .merge(voice::routes())
 }
+
+/// routes served by syncing nodes
+pub fn syncer() -> OpenApiRouter<Arc<ServerState>> {
+    sync::routes()
+}
+
+/// routes served by media nodes
+pub fn media() -> OpenApiRouter<Arc<ServerState>> {
+    media::routes().merge(internal::media_routes())
+}
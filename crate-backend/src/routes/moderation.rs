@@ -1,14 +1,28 @@
 use std::sync::Arc;
 
-use axum::{extract::State, Json};
-use common::v1::types::moderation::{Report, ReportCreate};
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use common::v1::types::emoji::EmojiOwner;
+use common::v1::types::moderation::{
+    Report, ReportCreate, ReportDestination, ReportPatch, ReportStatus, ReportTarget,
+};
+use common::v1::types::{
+    MediaId, Mentions, MessageId, MessageModerationReport, Permission, RoomId, ThreadId, UserId,
+};
 use utoipa_axum::{router::OpenApiRouter, routes};
+use validator::Validate;
 
-use super::util::Auth;
+use super::util::{Auth, HeaderReason};
 use crate::error::{Error, Result};
+use crate::types::{
+    DbMessageCreate, DbThreadCreate, DbThreadType, MediaLinkType, MessageSync, MessageType,
+    Permissions, Thread, ThreadPublic,
+};
 use crate::ServerState;
 
-/// Report room (TODO)
+/// Report room
 ///
 /// Report a room
 #[utoipa::path(
@@ -19,16 +33,22 @@ use crate::ServerState;
     responses((status = OK, body = Report, description = "success"))
 )]
 async fn report_room(
-    Auth(_auth_user_id): Auth,
-    State(_s): State<Arc<ServerState>>,
-    Json(_json): Json<ReportCreate>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    Path((room_id,)): Path<(RoomId,)>,
+    Auth(auth_user_id): Auth,
+    State(s): State<Arc<ServerState>>,
+    Json(json): Json<ReportCreate>,
+) -> Result<Json<Report>> {
+    let perms = s.services().perms.for_room(auth_user_id, room_id).await?;
+    perms.ensure_view()?;
+    let target = ReportTarget::Room { target_id: room_id };
+    let report = report_create(&s, auth_user_id, target, Some(room_id), json).await?;
+    Ok(Json(report))
 }
 
-/// Report user (TODO)
+/// Report user
 ///
-/// Report a user
+/// Report a user. Users can only be reported to the server, and only by people
+/// who share a room with them.
 #[utoipa::path(
     post,
     path = "/user/{user_id}/report",
@@ -37,16 +57,33 @@ async fn report_room(
     responses((status = OK, body = Report, description = "success"))
 )]
 async fn report_user(
-    Auth(_auth_user_id): Auth,
-    State(_s): State<Arc<ServerState>>,
-    Json(_json): Json<ReportCreate>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    Path((target_user_id,)): Path<(UserId,)>,
+    Auth(auth_user_id): Auth,
+    State(s): State<Arc<ServerState>>,
+    Json(json): Json<ReportCreate>,
+) -> Result<Json<Report>> {
+    // users that can't be seen are treated as missing, so reports can't find them
+    if auth_user_id != target_user_id
+        && !s
+            .services()
+            .perms
+            .is_mutual(auth_user_id, target_user_id)
+            .await?
+    {
+        return Err(Error::NotFound);
+    }
+    s.data().user_get(target_user_id).await?;
+    let target = ReportTarget::User {
+        target_id: target_user_id,
+    };
+    let report = report_create(&s, auth_user_id, target, None, json).await?;
+    Ok(Json(report))
 }
 
-/// Report media (TODO)
-///
 /// Report media
+///
+/// Report media. Media can only be reported to the server, by whoever uploaded
+/// it or people who can see something using it.
 #[utoipa::path(
     post,
     path = "/media/{media_id}/report",
@@ -55,42 +92,105 @@ async fn report_user(
     responses((status = OK, body = Report, description = "success"))
 )]
 async fn report_media(
-    Auth(_auth_user_id): Auth,
-    State(_s): State<Arc<ServerState>>,
-    Json(_json): Json<ReportCreate>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    Path((media_id,)): Path<(MediaId,)>,
+    Auth(auth_user_id): Auth,
+    State(s): State<Arc<ServerState>>,
+    Json(json): Json<ReportCreate>,
+) -> Result<Json<Report>> {
+    let (_, uploader_id) = s.data().media_select(media_id).await?;
+    // media that can't be seen is treated as missing, like users
+    if uploader_id != auth_user_id && !media_viewable(&s, auth_user_id, media_id).await? {
+        return Err(Error::NotFound);
+    }
+    let target = ReportTarget::Media {
+        target_id: media_id,
+    };
+    let report = report_create(&s, auth_user_id, target, None, json).await?;
+    Ok(Json(report))
+}
+
+/// whether someone can see a message, avatar, or emoji that uses media
+async fn media_viewable(s: &ServerState, user_id: UserId, media_id: MediaId) -> Result<bool> {
+    let data = s.data();
+    let perms = &s.services().perms;
+    for thread_id in data.media_link_threads(media_id).await? {
+        if can_view(perms.for_thread(user_id, thread_id).await)? {
+            return Ok(true);
+        }
+    }
+    for link in data.media_link_select(media_id).await? {
+        let viewable = match link.link_type {
+            MediaLinkType::AvatarUser => perms.is_mutual(user_id, link.target_id.into()).await?,
+            MediaLinkType::AvatarRoom => {
+                can_view(perms.for_room(user_id, link.target_id.into()).await)?
+            }
+            MediaLinkType::CustomEmoji => match data.emoji_get(link.target_id.into()).await {
+                Ok(emoji) => match emoji.owner {
+                    EmojiOwner::Room { room_id } => {
+                        can_view(perms.for_room(user_id, room_id).await)?
+                    }
+                    EmojiOwner::User => perms.is_mutual(user_id, emoji.creator_id).await?,
+                },
+                Err(Error::NotFound) => false,
+                Err(err) => return Err(err),
+            },
+            // messages were checked above, and embeds belong to messages
+            MediaLinkType::Message | MediaLinkType::MessageVersion | MediaLinkType::Embed => false,
+        };
+        if viewable {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// whether permissions allow viewing something, where missing things can't be viewed
+#[allow(clippy::result_large_err)]
+fn can_view(perms: Result<Permissions>) -> Result<bool> {
+    match perms {
+        Ok(perms) => Ok(perms.has(Permission::View)),
+        Err(Error::NotFound) => Ok(false),
+        Err(err) => Err(err),
+    }
 }
 
-/// Report thread (TODO)
+/// Report thread
 ///
 /// Report a thread
 #[utoipa::path(
     post,
     path = "/thread/{thread_id}/report",
-    params(
-        ("room_id", description = "room id"),
-        ("thread_id", description = "thread id"),
-    ),
+    params(("thread_id", description = "thread id")),
     tags = ["moderation"],
     responses((status = OK, body = Report, description = "success"))
 )]
 async fn report_thread(
-    Auth(_auth_user_id): Auth,
-    State(_s): State<Arc<ServerState>>,
-    Json(_json): Json<ReportCreate>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    Path((thread_id,)): Path<(ThreadId,)>,
+    Auth(auth_user_id): Auth,
+    State(s): State<Arc<ServerState>>,
+    Json(json): Json<ReportCreate>,
+) -> Result<Json<Report>> {
+    let perms = s
+        .services()
+        .perms
+        .for_thread(auth_user_id, thread_id)
+        .await?;
+    perms.ensure_view()?;
+    let thread = s.services().threads.get(thread_id, None).await?;
+    let target = ReportTarget::Thread {
+        target_id: thread_id,
+    };
+    let report = report_create(&s, auth_user_id, target, thread.room_id, json).await?;
+    Ok(Json(report))
 }
 
-/// Report message (TODO)
+/// Report message
 ///
 /// Report a message
 #[utoipa::path(
     post,
     path = "/thread/{thread_id}/message/{message_id}/report",
     params(
-        ("room_id", description = "room id"),
         ("thread_id", description = "thread id"),
         ("message_id", description = "message id"),
     ),
@@ -98,11 +198,192 @@ async fn report_thread(
     responses((status = OK, body = Report, description = "success"))
 )]
 async fn report_message(
-    Auth(_auth_user_id): Auth,
-    State(_s): State<Arc<ServerState>>,
-    Json(_json): Json<ReportCreate>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    Path((thread_id, message_id)): Path<(ThreadId, MessageId)>,
+    Auth(auth_user_id): Auth,
+    State(s): State<Arc<ServerState>>,
+    Json(json): Json<ReportCreate>,
+) -> Result<Json<Report>> {
+    let perms = s
+        .services()
+        .perms
+        .for_thread(auth_user_id, thread_id)
+        .await?;
+    perms.ensure_view()?;
+    s.data()
+        .message_get(thread_id, message_id, auth_user_id)
+        .await?;
+    let thread = s.services().threads.get(thread_id, None).await?;
+    let target = ReportTarget::Message {
+        target_id: message_id,
+    };
+    let report = report_create(&s, auth_user_id, target, thread.room_id, json).await?;
+    Ok(Json(report))
+}
+
+/// Report update
+///
+/// Update a report thread, eg. to resolve it. Only moderators who can view the
+/// report thread can do this.
+#[utoipa::path(
+    patch,
+    path = "/thread/{thread_id}/report",
+    params(("thread_id", description = "thread id")),
+    tags = ["moderation"],
+    responses(
+        (status = OK, body = Thread, description = "success"),
+        (status = NOT_MODIFIED, description = "not modified"),
+    )
+)]
+async fn report_update(
+    Path((thread_id,)): Path<(ThreadId,)>,
+    Auth(auth_user_id): Auth,
+    State(s): State<Arc<ServerState>>,
+    HeaderReason(reason): HeaderReason,
+    Json(json): Json<ReportPatch>,
+) -> Result<Json<Thread>> {
+    let srv = s.services();
+    let perms = srv.perms.for_thread(auth_user_id, thread_id).await?;
+    perms.ensure_view()?;
+    let thread = srv.threads.get(thread_id, None).await?;
+    let ThreadPublic::Report(info) = &thread.info else {
+        return Err(Error::NotFound);
+    };
+    // overwrites can let anyone view a report, but only its moderators can handle it
+    let room_id = thread.room_id.ok_or(Error::NotFound)?;
+    let room_perms = srv.perms.for_room(auth_user_id, room_id).await?;
+    room_perms.ensure(match info.destination {
+        ReportDestination::Room => Permission::MemberBan,
+        ReportDestination::Server => Permission::ServerReports,
+    })?;
+    let Some(status) = json.status else {
+        return Err(Error::NotModified);
+    };
+    if status == info.status {
+        return Err(Error::NotModified);
+    }
+    if status == ReportStatus::Open {
+        let data = s.data();
+        if data
+            .report_thread_find(info.destination.clone(), &info.target)
+            .await?
+            .is_some()
+        {
+            return Err(Error::BadStatic(
+                "there is already an open report for this target",
+            ));
+        }
+    }
+    s.data().report_thread_set_status(thread_id, status).await?;
+    srv.threads.invalidate(thread_id).await;
+    let thread = srv.threads.get(thread_id, Some(auth_user_id)).await?;
+    s.broadcast_thread(
+        thread_id,
+        auth_user_id,
+        reason,
+        MessageSync::ThreadUpdate {
+            thread: thread.clone(),
+        },
+    )
+    .await?;
+    Ok(Json(thread))
+}
+
+/// file a report, creating a report thread for the target if there isn't an open one already
+///
+/// reports to the room are sent to `room_id`, reports to the server are sent to the
+/// configured report room. reporting the same target twice returns the existing report.
+async fn report_create(
+    s: &ServerState,
+    reporter_id: UserId,
+    target: ReportTarget,
+    room_id: Option<RoomId>,
+    json: ReportCreate,
+) -> Result<Report> {
+    json.validate()?;
+    let room_id = match json.destination {
+        ReportDestination::Room => {
+            room_id.ok_or(Error::BadStatic("this can only be reported to the server"))?
+        }
+        ReportDestination::Server => s
+            .config
+            .report_room_id
+            .ok_or(Error::BadStatic("this server doesn't accept reports"))?,
+    };
+
+    // the report thread, report, and message are created together so a failed
+    // report doesn't leave an empty report thread behind
+    let (data, thread_id, is_new) = loop {
+        let data = s.data().begin().await?;
+        if let Some(thread_id) = data
+            .report_thread_find(json.destination.clone(), &target)
+            .await?
+        {
+            if let Some(report) = data.report_get_by_reporter(thread_id, reporter_id).await? {
+                data.rollback().await?;
+                return Ok(report);
+            }
+            break (data, thread_id, false);
+        }
+        let thread_id = data
+            .thread_create(DbThreadCreate {
+                room_id: Some(room_id.into_inner()),
+                creator_id: reporter_id,
+                name: report_thread_name(&target).to_owned(),
+                description: None,
+                ty: DbThreadType::Report,
+            })
+            .await?;
+        if data
+            .report_thread_create(thread_id, json.destination.clone(), &target)
+            .await?
+        {
+            break (data, thread_id, true);
+        }
+        // someone else opened a report thread for this target first, so
+        // throw this one away and add to theirs
+        data.rollback().await?;
+    };
+
+    let report = data.report_create(thread_id, reporter_id, json).await?;
+    let message_id = data
+        .message_create(DbMessageCreate {
+            thread_id,
+            attachment_ids: vec![],
+            author_id: reporter_id,
+            embeds: vec![],
+            message_type: MessageType::ModerationReport(MessageModerationReport {
+                report: report.clone(),
+            }),
+            mentions: Mentions::default(),
+            edited_at: None,
+            created_at: None,
+        })
+        .await?;
+    data.commit().await?;
+    let data = s.data();
+    let srv = s.services();
+    srv.threads.invalidate(thread_id).await; // report count, last version id
+    let thread = srv.threads.get(thread_id, None).await?;
+    let message = data.message_get(thread_id, message_id, reporter_id).await?;
+
+    // reports skip the audit log to avoid revealing who reported what
+    if is_new {
+        s.broadcast(MessageSync::ThreadCreate { thread })?;
+    } else {
+        s.broadcast(MessageSync::ThreadUpdate { thread })?;
+    }
+    s.broadcast(MessageSync::MessageCreate { message })?;
+    Ok(report)
+}
+
+fn report_thread_name(target: &ReportTarget) -> &'static str {
+    match target {
+        ReportTarget::User { .. } => "user report",
+        ReportTarget::Room { .. } => "room report",
+        ReportTarget::Thread { .. } => "thread report",
+        ReportTarget::Message { .. } => "message report",
+        ReportTarget::Media { .. } => "media report",
+    }
 }
 
 pub fn routes() -> OpenApiRouter<Arc<ServerState>> {
@@ -110,6 +391,6 @@ pub fn routes() -> OpenApiRouter<Arc<ServerState>> {
         .routes(routes!(report_room))
         .routes(routes!(report_user))
         .routes(routes!(report_media))
-        .routes(routes!(report_thread))
+        .routes(routes!(report_thread, report_update))
         .routes(routes!(report_message))
 }
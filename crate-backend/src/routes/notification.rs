@@ -1,12 +1,17 @@
 use std::sync::Arc;
 
 use axum::extract::{Path, Query};
+use axum::response::IntoResponse;
 use axum::{extract::State, Json};
-use common::v1::types::notifications::{Notification, NotifsRoom, NotifsThread};
+use common::v1::types::notifications::{
+    Notification, NotificationMarkRead, NotifsRoom, NotifsThread,
+};
 use common::v1::types::{NotificationId, PaginationQuery, PaginationResponse, RoomId, ThreadId};
+use http::StatusCode;
 use serde::{Deserialize, Serialize};
 use utoipa::{IntoParams, ToSchema};
 use utoipa_axum::{router::OpenApiRouter, routes};
+use validator::Validate;
 
 use super::util::Auth;
 use crate::error::Result;
@@ -23,32 +28,80 @@ struct InboxListParams {
     // /// only include notifications from this thread
     // #[serde(default)]
     // thread_id: Vec<ThreadId>,
+    /// also include notifications that have been marked as read
+    #[serde(default)]
+    include_read: bool,
 }
 
-/// Inbox get (TODO)
+/// Inbox get
 ///
 /// List notifications.
 #[utoipa::path(
     get,
     path = "/inbox",
     params(
-        PaginationQuery<MessageId>,
+        PaginationQuery<NotificationId>,
         InboxListParams,
     ),
     tags = ["notification"],
     responses((status = OK, body = PaginationResponse<Notification>, description = "success"))
 )]
 async fn inbox_get(
-    Auth(_auth_user_id): Auth,
-    Query(_pagination): Query<PaginationQuery<NotificationId>>,
-    Query(_params): Query<InboxListParams>,
-    State(_s): State<Arc<ServerState>>,
-    // ) -> Result<impl IntoResponse> {
-) -> Result<Json<PaginationResponse<Notification>>> {
-    todo!()
+    Auth(auth_user_id): Auth,
+    Query(pagination): Query<PaginationQuery<NotificationId>>,
+    Query(params): Query<InboxListParams>,
+    State(s): State<Arc<ServerState>>,
+) -> Result<impl IntoResponse> {
+    let res = s
+        .data()
+        .notification_list(auth_user_id, pagination, params.include_read)
+        .await?;
+    Ok(Json(res))
 }
 
-/// Notification room configure (TODO)
+/// Inbox mark read
+///
+/// Mark notifications as read.
+#[utoipa::path(
+    post,
+    path = "/inbox/mark-read",
+    tags = ["notification"],
+    responses((status = NO_CONTENT, description = "success"))
+)]
+async fn inbox_mark_read(
+    Auth(auth_user_id): Auth,
+    State(s): State<Arc<ServerState>>,
+    Json(json): Json<NotificationMarkRead>,
+) -> Result<impl IntoResponse> {
+    json.validate()?;
+    s.data()
+        .notification_mark_read(auth_user_id, &json.notification_ids)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Inbox mark unread
+///
+/// Mark notifications as unread.
+#[utoipa::path(
+    post,
+    path = "/inbox/mark-unread",
+    tags = ["notification"],
+    responses((status = NO_CONTENT, description = "success"))
+)]
+async fn inbox_mark_unread(
+    Auth(auth_user_id): Auth,
+    State(s): State<Arc<ServerState>>,
+    Json(json): Json<NotificationMarkRead>,
+) -> Result<impl IntoResponse> {
+    json.validate()?;
+    s.data()
+        .notification_mark_unread(auth_user_id, &json.notification_ids)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Notification room configure
 ///
 /// Edit notification settings for a room.
 #[utoipa::path(
@@ -59,15 +112,23 @@ async fn inbox_get(
     responses((status = OK, body = NotifsRoom, description = "success")),
 )]
 async fn notification_room_configure(
-    Path(_room_id): Path<RoomId>,
-    Auth(_user_id): Auth,
-    State(_s): State<Arc<ServerState>>,
-    Json(_json): Json<NotifsRoom>,
-) -> Result<Json<NotifsRoom>> {
-    todo!()
+    Path(room_id): Path<RoomId>,
+    Auth(auth_user_id): Auth,
+    State(s): State<Arc<ServerState>>,
+    Json(json): Json<NotifsRoom>,
+) -> Result<impl IntoResponse> {
+    let perms = s.services().perms.for_room(auth_user_id, room_id).await?;
+    perms.ensure_view()?;
+    let data = s.data();
+    data.notification_config_room_set(auth_user_id, room_id, &json)
+        .await?;
+    let config = data
+        .notification_config_room_get(auth_user_id, room_id)
+        .await?;
+    Ok(Json(config))
 }
 
-/// Notification thread configure (TODO)
+/// Notification thread configure
 ///
 /// Edit notification settings for a thread.
 #[utoipa::path(
@@ -78,17 +139,29 @@ async fn notification_room_configure(
     responses((status = OK, body = NotifsThread, description = "success")),
 )]
 async fn notification_thread_configure(
-    Path(_thread_id): Path<ThreadId>,
-    Auth(_user_id): Auth,
-    State(_s): State<Arc<ServerState>>,
-    Json(_json): Json<NotifsThread>,
-) -> Result<Json<NotifsThread>> {
-    todo!()
+    Path(thread_id): Path<ThreadId>,
+    Auth(auth_user_id): Auth,
+    State(s): State<Arc<ServerState>>,
+    Json(json): Json<NotifsThread>,
+) -> Result<impl IntoResponse> {
+    let srv = s.services();
+    let perms = srv.perms.for_thread(auth_user_id, thread_id).await?;
+    perms.ensure_view()?;
+    let data = s.data();
+    data.notification_config_thread_set(auth_user_id, thread_id, &json)
+        .await?;
+    srv.threads.invalidate_user(thread_id, auth_user_id).await;
+    let config = data
+        .notification_config_thread_get(auth_user_id, thread_id)
+        .await?;
+    Ok(Json(config))
 }
 
 pub fn routes() -> OpenApiRouter<Arc<ServerState>> {
     OpenApiRouter::new()
         .routes(routes!(inbox_get))
+        .routes(routes!(inbox_mark_read))
+        .routes(routes!(inbox_mark_unread))
         .routes(routes!(notification_room_configure))
         .routes(routes!(notification_thread_configure))
 }
@@ -42,6 +42,10 @@ pub async fn role_create(
     let perms = s.services().perms.for_room(user_id, room_id).await?;
     perms.ensure_view()?;
     perms.ensure(Permission::RoleManage)?;
+    let srv = s.services();
+    srv.perms
+        .ensure_outranks_position(user_id, room_id, json.position)
+        .await?;
     let role = d
         .role_create(DbRoleCreate {
             room_id,
@@ -51,6 +55,7 @@ pub async fn role_create(
             is_self_applicable: json.is_self_applicable,
             is_mentionable: json.is_mentionable,
             is_default: json.is_default,
+            position: json.position,
         })
         .await?;
     let msg = MessageSync::RoleCreate { role: role.clone() };
@@ -85,6 +90,14 @@ pub async fn role_update(
     perms.ensure_view()?;
     perms.ensure(Permission::RoleManage)?;
     let role = d.role_select(room_id, role_id).await?;
+    let srv = s.services();
+    srv.perms
+        .ensure_outranks_position(
+            user_id,
+            room_id,
+            role.position.max(json.position.unwrap_or(0)),
+        )
+        .await?;
     if !json.changes(&role) {
         return Ok(StatusCode::NOT_MODIFIED.into_response());
     }
@@ -122,6 +135,11 @@ pub async fn role_delete(
     let perms = s.services().perms.for_room(user_id, room_id).await?;
     perms.ensure_view()?;
     perms.ensure(Permission::RoleManage)?;
+    let role = d.role_select(room_id, role_id).await?;
+    s.services()
+        .perms
+        .ensure_outranks_position(user_id, room_id, role.position)
+        .await?;
     let existing = d.role_member_count(role_id).await?;
     if existing == 0 || query.force {
         d.role_delete(room_id, role_id).await?;
@@ -235,6 +253,11 @@ pub async fn role_member_add(
     let perms = s.services().perms.for_room(auth_user_id, room_id).await?;
     perms.ensure_view()?;
     perms.ensure(Permission::RoleApply)?;
+    let role = d.role_select(room_id, role_id).await?;
+    s.services()
+        .perms
+        .ensure_outranks_position(auth_user_id, room_id, role.position)
+        .await?;
     d.role_member_put(target_user_id, role_id).await?;
     let member = d.room_member_get(room_id, target_user_id).await?;
     if !matches!(member.membership, RoomMembership::Join { .. }) {
@@ -275,6 +298,11 @@ pub async fn role_member_remove(
     let perms = s.services().perms.for_room(auth_user_id, room_id).await?;
     perms.ensure_view()?;
     perms.ensure(Permission::RoleApply)?;
+    let role = d.role_select(room_id, role_id).await?;
+    s.services()
+        .perms
+        .ensure_outranks_position(auth_user_id, room_id, role.position)
+        .await?;
     d.role_member_delete(target_user_id, role_id).await?;
     let member = d.room_member_get(room_id, target_user_id).await?;
     if !matches!(member.membership, RoomMembership::Join { .. }) {
@@ -3,10 +3,10 @@ use std::sync::Arc;
 use axum::extract::{Path, Query};
 use axum::response::IntoResponse;
 use axum::{extract::State, Json};
-use common::v1::types::util::Diff;
+use common::v1::types::util::{Diff, Time};
 use common::v1::types::{
-    MessageSync, PaginationQuery, PaginationResponse, Permission, RoomId, RoomMember,
-    RoomMemberPatch, RoomMemberPut, RoomMembership, UserId,
+    MessageSync, PaginationQuery, PaginationResponse, Permission, RoomBan, RoomBanCreate, RoomId,
+    RoomMember, RoomMemberPatch, RoomMemberPut, RoomMembership, UserId,
 };
 use http::StatusCode;
 use serde::{Deserialize, Serialize};
@@ -251,6 +251,10 @@ async fn room_member_delete(
     perms.ensure_view()?;
     if target_user_id != auth_user_id {
         perms.ensure(Permission::MemberKick)?;
+        s.services()
+            .perms
+            .ensure_outranks(auth_user_id, target_user_id, room_id)
+            .await?;
     }
     let start = d.room_member_get(room_id, target_user_id).await?;
     if !matches!(start.membership, RoomMembership::Join { .. }) {
@@ -274,7 +278,11 @@ async fn room_member_delete(
     Ok(StatusCode::NO_CONTENT)
 }
 
-/// Room ban create (TODO)
+/// Room ban create
+///
+/// Ban a user from a room. Banned users are removed from the room and can't
+/// rejoin until the ban is removed or expires. Banning an already banned user
+/// replaces the existing ban.
 #[utoipa::path(
     put,
     path = "/room/{room_id}/ban/{user_id}",
@@ -287,11 +295,61 @@ async fn room_member_delete(
         (status = NO_CONTENT, description = "success"),
     )
 )]
-async fn room_ban_create() -> Result<()> {
-    Err(Error::Unimplemented)
+async fn room_ban_create(
+    Path((room_id, target_user_id)): Path<(RoomId, UserId)>,
+    Auth(auth_user_id): Auth,
+    State(s): State<Arc<ServerState>>,
+    HeaderReason(reason): HeaderReason,
+    Json(json): Json<RoomBanCreate>,
+) -> Result<impl IntoResponse> {
+    if target_user_id == auth_user_id {
+        return Err(Error::BadStatic("can't ban yourself"));
+    }
+    if json
+        .expires_at
+        .as_ref()
+        .is_some_and(|t| *t <= Time::now_utc())
+    {
+        return Err(Error::BadStatic("expires_at is in the past"));
+    }
+    let srv = s.services();
+    let perms = srv.perms.for_room(auth_user_id, room_id).await?;
+    perms.ensure_view()?;
+    perms.ensure(Permission::MemberBan)?;
+    srv.users.get(target_user_id).await?;
+    srv.perms
+        .ensure_outranks(auth_user_id, target_user_id, room_id)
+        .await?;
+
+    let d = s.data();
+    d.room_ban_create(
+        room_id,
+        target_user_id,
+        auth_user_id,
+        reason.clone(),
+        json.expires_at,
+    )
+    .await?;
+    srv.perms.invalidate_room(target_user_id, room_id).await;
+    srv.perms.invalidate_is_mutual(target_user_id);
+    let member = d.room_member_get(room_id, target_user_id).await?;
+    s.broadcast_room(
+        room_id,
+        auth_user_id,
+        reason,
+        MessageSync::BanCreate {
+            room_id,
+            user_id: target_user_id,
+        },
+    )
+    .await?;
+    s.broadcast(MessageSync::RoomMemberUpsert { member })?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
-/// Room ban remove (TODO)
+/// Room ban remove
+///
+/// Requires `MemberBanManage` to remove bans created by other users.
 #[utoipa::path(
     delete,
     path = "/room/{room_id}/ban/{user_id}",
@@ -304,11 +362,41 @@ async fn room_ban_create() -> Result<()> {
         (status = NO_CONTENT, description = "success"),
     )
 )]
-async fn room_ban_remove() -> Result<()> {
-    Err(Error::Unimplemented)
+async fn room_ban_remove(
+    Path((room_id, target_user_id)): Path<(RoomId, UserId)>,
+    Auth(auth_user_id): Auth,
+    State(s): State<Arc<ServerState>>,
+    HeaderReason(reason): HeaderReason,
+) -> Result<impl IntoResponse> {
+    let srv = s.services();
+    let perms = srv.perms.for_room(auth_user_id, room_id).await?;
+    perms.ensure_view()?;
+    perms.ensure(Permission::MemberBan)?;
+
+    let d = s.data();
+    let ban = d.room_ban_get(room_id, target_user_id).await?;
+    if ban.creator_id != auth_user_id {
+        perms.ensure(Permission::MemberBanManage)?;
+    }
+    d.room_ban_delete(room_id, target_user_id).await?;
+    srv.perms.invalidate_room(target_user_id, room_id).await;
+    srv.perms.invalidate_is_mutual(target_user_id);
+    let member = d.room_member_get(room_id, target_user_id).await?;
+    s.broadcast_room(
+        room_id,
+        auth_user_id,
+        reason,
+        MessageSync::BanDelete {
+            room_id,
+            user_id: target_user_id,
+        },
+    )
+    .await?;
+    s.broadcast(MessageSync::RoomMemberUpsert { member })?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
-/// Room ban get (TODO)
+/// Room ban get
 #[utoipa::path(
     get,
     path = "/room/{room_id}/ban/{user_id}",
@@ -318,14 +406,22 @@ async fn room_ban_remove() -> Result<()> {
     ),
     tags = ["room_member"],
     responses(
-        (status = NO_CONTENT, description = "success"),
+        (status = OK, body = RoomBan, description = "success"),
     )
 )]
-async fn room_ban_get() -> Result<()> {
-    Err(Error::Unimplemented)
+async fn room_ban_get(
+    Path((room_id, target_user_id)): Path<(RoomId, UserId)>,
+    Auth(auth_user_id): Auth,
+    State(s): State<Arc<ServerState>>,
+) -> Result<impl IntoResponse> {
+    let perms = s.services().perms.for_room(auth_user_id, room_id).await?;
+    perms.ensure_view()?;
+    perms.ensure(Permission::MemberBan)?;
+    let ban = s.data().room_ban_get(room_id, target_user_id).await?;
+    Ok(Json(ban))
 }
 
-/// Room ban list (TODO)
+/// Room ban list
 #[utoipa::path(
     get,
     path = "/room/{room_id}/ban",
@@ -335,11 +431,20 @@ async fn room_ban_get() -> Result<()> {
     ),
     tags = ["room_member"],
     responses(
-        (status = NO_CONTENT, description = "success"),
+        (status = OK, body = PaginationResponse<RoomBan>, description = "success"),
     )
 )]
-async fn room_ban_list() -> Result<()> {
-    Err(Error::Unimplemented)
+async fn room_ban_list(
+    Path(room_id): Path<RoomId>,
+    Query(paginate): Query<PaginationQuery<UserId>>,
+    Auth(auth_user_id): Auth,
+    State(s): State<Arc<ServerState>>,
+) -> Result<impl IntoResponse> {
+    let perms = s.services().perms.for_room(auth_user_id, room_id).await?;
+    perms.ensure_view()?;
+    perms.ensure(Permission::MemberBan)?;
+    let res = s.data().room_ban_list(room_id, paginate).await?;
+    Ok(Json(res))
 }
 
 pub fn routes() -> OpenApiRouter<Arc<ServerState>> {
@@ -1,19 +1,22 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use axum::extract::Query;
 use axum::response::IntoResponse;
 use axum::{extract::State, Json};
-use common::v1::types::search::{SearchMessageRequest, SearchRoomsRequest, SearchThreadsRequest};
+use common::v1::types::search::{
+    SearchMessageRequest, SearchRoomsRequest, SearchThreadFeatures, SearchThreadsRequest,
+};
 use common::v1::types::{
     Message, MessageId, PaginationQuery, PaginationResponse, Room, RoomId, Thread, ThreadId,
 };
 use utoipa_axum::{router::OpenApiRouter, routes};
 use validator::Validate;
 
-use crate::{Error, ServerState};
+use crate::ServerState;
 
 use super::util::Auth;
-use crate::error::Result;
+use crate::error::{Error, Result};
 
 // maybe consider having one big search endgoint that searches *everything*?
 // or maybe that's too expensive to do, idk
@@ -34,6 +37,7 @@ pub async fn search_messages(
     Json(json): Json<SearchMessageRequest>,
 ) -> Result<impl IntoResponse> {
     json.validate()?;
+    ensure_no_pinned_threads(&json.features_thread)?;
     let data = s.data();
     let mut res = data.search_message(user_id, json, q).await?;
     for message in &mut res.items {
@@ -42,7 +46,7 @@ pub async fn search_messages(
     Ok(Json(res))
 }
 
-/// Search threads (TODO)
+/// Search threads
 #[utoipa::path(
     post,
     path = "/search/thread",
@@ -52,15 +56,33 @@ pub async fn search_messages(
     )
 )]
 pub async fn search_threads(
-    Auth(_user_id): Auth,
-    State(_s): State<Arc<ServerState>>,
-    Query(_q): Query<PaginationQuery<ThreadId>>,
-    Json(_json): Json<SearchThreadsRequest>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    Auth(user_id): Auth,
+    State(s): State<Arc<ServerState>>,
+    Query(q): Query<PaginationQuery<ThreadId>>,
+    Json(json): Json<SearchThreadsRequest>,
+) -> Result<impl IntoResponse> {
+    json.validate()?;
+    ensure_no_pinned_threads(&json.features_thread)?;
+    let data = s.data();
+    let mut res = data.search_thread(user_id, json, q).await?;
+    let ids: Vec<ThreadId> = res.items.iter().map(|t| t.id).collect();
+    let mut private: HashMap<_, _> = data
+        .thread_get_private_many(&ids, user_id)
+        .await?
+        .into_iter()
+        .collect();
+    res.items = res
+        .items
+        .into_iter()
+        .map(|thread| match private.remove(&thread.id) {
+            Some(private) => thread.with_private(private),
+            None => thread,
+        })
+        .collect();
+    Ok(Json(res))
 }
 
-/// Search rooms (TODO)
+/// Search rooms
 #[utoipa::path(
     post,
     path = "/search/room",
@@ -69,14 +91,25 @@ pub async fn search_threads(
         (status = OK, body = PaginationResponse<Room>, description = "success"),
     )
 )]
-#[axum::debug_handler]
 pub async fn search_rooms(
-    Auth(_user_id): Auth,
-    State(_s): State<Arc<ServerState>>,
-    Query(_q): Query<PaginationQuery<RoomId>>,
-    Json(_json): Json<SearchRoomsRequest>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    Auth(user_id): Auth,
+    State(s): State<Arc<ServerState>>,
+    Query(q): Query<PaginationQuery<RoomId>>,
+    Json(json): Json<SearchRoomsRequest>,
+) -> Result<impl IntoResponse> {
+    json.validate()?;
+    let data = s.data();
+    let res = data.search_room(user_id, json, q).await?;
+    Ok(Json(res))
+}
+
+/// threads can't be pinned yet, so there are never any pinned threads to find
+#[allow(clippy::result_large_err)]
+fn ensure_no_pinned_threads(features: &[SearchThreadFeatures]) -> Result<()> {
+    if features.contains(&SearchThreadFeatures::Pinned) {
+        return Err(Error::BadStatic("threads can't be pinned yet"));
+    }
+    Ok(())
 }
 
 pub fn routes() -> OpenApiRouter<Arc<ServerState>> {
@@ -14,8 +14,6 @@ use crate::error::Error;
 use crate::sync::{Connection, Timeout};
 use crate::ServerState;
 
-type WsMessage = axum::extract::ws::Message;
-
 /// Sync init
 ///
 /// Open a websocket to start syncing
@@ -40,7 +38,7 @@ async fn sync(
 async fn worker(s: Arc<ServerState>, params: SyncParams, mut ws: WebSocket) {
     let mut timeout = Timeout::for_ping();
     let mut sushi = s.inner.sushi.subscribe();
-    let mut conn = Connection::new(s.clone());
+    let mut conn = Connection::new(s.clone(), &params);
 
     loop {
         tokio::select! {
@@ -49,12 +47,12 @@ async fn worker(s: Arc<ServerState>, params: SyncParams, mut ws: WebSocket) {
                     Some(Ok(Message::Close(_))) => break,
                     Some(Ok(ws_msg)) => {
                         if let Err(err) = conn.handle_message(ws_msg, &mut ws, &mut timeout).await {
-                            let _ = ws.send(err.into()).await;
-                            let _ = ws
-                                .send(Message::text(serde_json::to_string(&MessageEnvelope {
-                                    payload: MessagePayload::Reconnect { can_resume: false },
-                                }).expect("can always serialize message")))
-                                .await;
+                            // only a fresh connection can recover from missing events
+                            let can_resume = !matches!(err, Error::ResumeExpired);
+                            let _ = conn.send(&mut ws, &err.into()).await;
+                            let _ = conn.send(&mut ws, &MessageEnvelope {
+                                payload: MessagePayload::Reconnect { can_resume },
+                            }).await;
                             let _ = ws.close().await;
                             break;
                         }
@@ -68,13 +66,11 @@ async fn worker(s: Arc<ServerState>, params: SyncParams, mut ws: WebSocket) {
                 }
             }
             _ = tokio::time::sleep_until(timeout.get_instant()) => {
-                if !handle_timeout(&mut timeout, &mut ws).await {
-                    let _ = ws.send(Error::BadStatic("connection timed out").into()).await;
-                    let _ = ws
-                        .send(Message::text(serde_json::to_string(&MessageEnvelope {
-                            payload: MessagePayload::Reconnect { can_resume: true },
-                        }).expect("can always serialize message")))
-                        .await;
+                if !handle_timeout(&mut timeout, &mut conn, &mut ws).await {
+                    let _ = conn.send(&mut ws, &Error::BadStatic("connection timed out").into()).await;
+                    let _ = conn.send(&mut ws, &MessageEnvelope {
+                        payload: MessagePayload::Reconnect { can_resume: true },
+                    }).await;
                     let _ = ws.close().await;
                     break;
                 }
@@ -83,18 +79,16 @@ async fn worker(s: Arc<ServerState>, params: SyncParams, mut ws: WebSocket) {
         let _ = conn.drain(&mut ws).await;
     }
 
-    conn.disconnect();
-    debug!("inserting syncer: {}", conn.get_id());
-    s.syncers.insert(conn.get_id().to_owned(), conn);
+    debug!("closed syncer: {}", conn.get_id());
 }
 
-async fn handle_timeout(timeout: &mut Timeout, ws: &mut WebSocket) -> bool {
+async fn handle_timeout(timeout: &mut Timeout, conn: &mut Connection, ws: &mut WebSocket) -> bool {
     match timeout {
         Timeout::Ping(_) => {
             let ping = MessageEnvelope {
                 payload: MessagePayload::Ping {},
             };
-            let _ = ws.send(serialize(&ping)).await;
+            let _ = conn.send(ws, &ping).await;
             *timeout = Timeout::for_close();
             true
         }
@@ -105,12 +99,6 @@ async fn handle_timeout(timeout: &mut Timeout, ws: &mut WebSocket) -> bool {
     }
 }
 
-fn serialize(msg: &MessageEnvelope) -> WsMessage {
-    WsMessage::text(
-        serde_json::to_string(msg).expect("server messages should always be able to be serialized"),
-    )
-}
-
 pub fn routes() -> OpenApiRouter<Arc<ServerState>> {
     OpenApiRouter::new().route("/sync", any(sync))
 }
@@ -1,20 +1,27 @@
 use std::sync::Arc;
 
 use axum::extract::{Path, Query};
+use axum::response::IntoResponse;
 use axum::{extract::State, Json};
 use common::v1::types::tag::{Tag, TagCreate, TagPatch};
-use common::v1::types::{PaginationQuery, PaginationResponse, TagId, ThreadId};
+use common::v1::types::util::Diff;
+use common::v1::types::{
+    MessageSync, PaginationQuery, PaginationResponse, Permission, RoomId, TagId, ThreadId, UserId,
+};
+use http::StatusCode;
 use serde::Deserialize;
+use utoipa::IntoParams;
 use utoipa_axum::{router::OpenApiRouter, routes};
+use validator::Validate;
 
 use crate::ServerState;
 
-use super::util::Auth;
+use super::util::{Auth, HeaderReason};
 use crate::error::{Error, Result};
 
 // NOTE: maybe use roles as tags?
 
-/// Tag create (TODO)
+/// Tag create
 #[utoipa::path(
     post,
     path = "/room/{room_id}/tag",
@@ -27,14 +34,23 @@ use crate::error::{Error, Result};
     )
 )]
 async fn tag_create(
-    Auth(_user_id): Auth,
-    State(_s): State<Arc<ServerState>>,
-    Json(_body): Json<TagCreate>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    Path(room_id): Path<RoomId>,
+    Auth(user_id): Auth,
+    State(s): State<Arc<ServerState>>,
+    HeaderReason(reason): HeaderReason,
+    Json(json): Json<TagCreate>,
+) -> Result<impl IntoResponse> {
+    json.validate()?;
+    let perms = s.services().perms.for_room(user_id, room_id).await?;
+    perms.ensure_view()?;
+    perms.ensure(Permission::TagManage)?;
+    let tag = s.data().tag_create(TagCreate { room_id, ..json }).await?;
+    let msg = MessageSync::TagCreate { tag: tag.clone() };
+    s.broadcast_room(room_id, user_id, reason, msg).await?;
+    Ok((StatusCode::CREATED, Json(tag)))
 }
 
-/// Tag get (TODO)
+/// Tag get
 #[utoipa::path(
     get,
     path = "/room/{room_id}/tag/{tag_id}",
@@ -48,14 +64,17 @@ async fn tag_create(
     )
 )]
 async fn tag_get(
-    Path(_tag_id): Path<TagId>,
-    Auth(_user_id): Auth,
-    State(_s): State<Arc<ServerState>>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    Path((room_id, tag_id)): Path<(RoomId, TagId)>,
+    Auth(user_id): Auth,
+    State(s): State<Arc<ServerState>>,
+) -> Result<impl IntoResponse> {
+    let perms = s.services().perms.for_room(user_id, room_id).await?;
+    perms.ensure_view()?;
+    let tag = get_room_tag(&s, room_id, tag_id).await?;
+    Ok(Json(tag))
 }
 
-/// Tag patch (TODO)
+/// Tag patch
 #[utoipa::path(
     patch,
     path = "/room/{room_id}/tag/{tag_id}",
@@ -70,47 +89,80 @@ async fn tag_get(
     )
 )]
 async fn tag_patch(
-    Path(_tag_id): Path<TagId>,
-    Auth(_user_id): Auth,
-    State(_s): State<Arc<ServerState>>,
-    Json(_body): Json<TagPatch>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    Path((room_id, tag_id)): Path<(RoomId, TagId)>,
+    Auth(user_id): Auth,
+    State(s): State<Arc<ServerState>>,
+    HeaderReason(reason): HeaderReason,
+    Json(json): Json<TagPatch>,
+) -> Result<impl IntoResponse> {
+    json.validate()?;
+    let perms = s.services().perms.for_room(user_id, room_id).await?;
+    perms.ensure_view()?;
+    perms.ensure(Permission::TagManage)?;
+    let tag = get_room_tag(&s, room_id, tag_id).await?;
+    if !json.changes(&tag) {
+        return Ok(StatusCode::NOT_MODIFIED.into_response());
+    }
+    let data = s.data();
+    data.tag_update(tag_id, json).await?;
+    let tag = data.tag_get(tag_id).await?;
+    let msg = MessageSync::TagUpdate { tag: tag.clone() };
+    s.broadcast_room(room_id, user_id, reason, msg).await?;
+    Ok(Json(tag).into_response())
 }
 
-// TEMP: while scaffolding
-#[allow(unused)]
-#[derive(Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 struct TagDeleteQuery {
+    /// delete the tag even if it's still applied to threads
     #[serde(default)]
     force: bool,
 }
 
-/// Tag delete (TODO)
+/// Tag delete
+///
+/// Fails with CONFLICT if the tag is still applied to threads, unless `force` is set.
 #[utoipa::path(
     delete,
     path = "/room/{room_id}/tag/{tag_id}",
     params(
+        TagDeleteQuery,
         ("room_id", description = "Room id"),
         ("tag_id", description = "Tag id"),
     ),
     tags = ["tag"],
     responses(
         (status = NO_CONTENT, description = "success"),
+        (status = CONFLICT, description = "tag is still applied to threads"),
     )
 )]
 async fn tag_delete(
-    Path(_tag_id): Path<TagId>,
-    Query(_query): Query<TagDeleteQuery>,
-    Auth(_user_id): Auth,
-    State(_s): State<Arc<ServerState>>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    Path((room_id, tag_id)): Path<(RoomId, TagId)>,
+    Query(query): Query<TagDeleteQuery>,
+    Auth(user_id): Auth,
+    State(s): State<Arc<ServerState>>,
+    HeaderReason(reason): HeaderReason,
+) -> Result<impl IntoResponse> {
+    let perms = s.services().perms.for_room(user_id, room_id).await?;
+    perms.ensure_view()?;
+    perms.ensure(Permission::TagManage)?;
+    get_room_tag(&s, room_id, tag_id).await?;
+    let data = s.data();
+    let existing = data.tag_thread_count(tag_id).await?;
+    if existing != 0 && !query.force {
+        return Err(Error::Conflict("tag is still applied to threads"));
+    }
+    data.tag_delete(tag_id).await?;
+    if existing != 0 {
+        s.services().threads.invalidate_room(room_id);
+    }
+    let msg = MessageSync::TagDelete { room_id, tag_id };
+    s.broadcast_room(room_id, user_id, reason, msg).await?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
-/// Tag list room (TODO)
+/// Tag list room
 ///
-/// List tags in a room
+/// List tags in a room, including archived tags
 #[utoipa::path(
     get,
     path = "/room/{room_id}/tag",
@@ -124,14 +176,18 @@ async fn tag_delete(
     )
 )]
 async fn tag_list(
-    Auth(_session): Auth,
-    Query(_q): Query<PaginationQuery<TagId>>,
-    State(_s): State<Arc<ServerState>>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    Path(room_id): Path<RoomId>,
+    Auth(user_id): Auth,
+    Query(q): Query<PaginationQuery<TagId>>,
+    State(s): State<Arc<ServerState>>,
+) -> Result<impl IntoResponse> {
+    let perms = s.services().perms.for_room(user_id, room_id).await?;
+    perms.ensure_view()?;
+    let res = s.data().tag_list(room_id, q).await?;
+    Ok(Json(res))
 }
 
-/// Tag thread apply (TODO)
+/// Tag thread apply
 ///
 /// Apply a tag to a thread. For bulk applying tags, consider editing the thread's tags field directly.
 #[utoipa::path(
@@ -148,14 +204,28 @@ async fn tag_list(
     )
 )]
 async fn tag_thread_apply(
-    Auth(_session): Auth,
-    Path((_thread_id, _tag_id)): Path<(ThreadId, TagId)>,
-    State(_s): State<Arc<ServerState>>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    Auth(user_id): Auth,
+    Path((thread_id, tag_id)): Path<(ThreadId, TagId)>,
+    State(s): State<Arc<ServerState>>,
+    HeaderReason(reason): HeaderReason,
+) -> Result<impl IntoResponse> {
+    let srv = s.services();
+    let perms = srv.perms.for_thread(user_id, thread_id).await?;
+    perms.ensure_view()?;
+    perms.ensure(Permission::TagApply)?;
+    let thread = srv.threads.get(thread_id, None).await?;
+    if thread.tags.contains(&tag_id) {
+        return Ok(StatusCode::NOT_MODIFIED);
+    }
+    srv.threads
+        .check_tags(thread.room_id, &[tag_id], &[])
+        .await?;
+    s.data().tag_thread_apply(thread_id, tag_id).await?;
+    broadcast_thread_update(&s, thread_id, user_id, reason).await?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
-/// Tag thread unapply (TODO)
+/// Tag thread unapply
 ///
 /// Unapply a tag from a thread. For bulk removing tags, consider editing the thread's tags field directly.
 #[utoipa::path(
@@ -172,24 +242,36 @@ async fn tag_thread_apply(
     )
 )]
 async fn tag_thread_unapply(
-    Auth(_session): Auth,
-    Path((_thread_id, _tag_id)): Path<(ThreadId, TagId)>,
-    State(_s): State<Arc<ServerState>>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    Auth(user_id): Auth,
+    Path((thread_id, tag_id)): Path<(ThreadId, TagId)>,
+    State(s): State<Arc<ServerState>>,
+    HeaderReason(reason): HeaderReason,
+) -> Result<impl IntoResponse> {
+    let srv = s.services();
+    let perms = srv.perms.for_thread(user_id, thread_id).await?;
+    perms.ensure_view()?;
+    perms.ensure(Permission::TagApply)?;
+    let thread = srv.threads.get(thread_id, None).await?;
+    if !thread.tags.contains(&tag_id) {
+        return Ok(StatusCode::NOT_MODIFIED);
+    }
+    s.data().tag_thread_unapply(thread_id, tag_id).await?;
+    broadcast_thread_update(&s, thread_id, user_id, reason).await?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
-/// Tag tag apply (TODO)
+/// Tag tag apply
 ///
 /// Apply a tag to a tag
 ///
 /// If tag a is tagged with tag b then any taggable tagged with tag a is implicitly tagged with tag b
 #[utoipa::path(
     put,
-    path = "/room/{room_id}/tag/{tag_id}/tag/{tag_id}",
+    path = "/room/{room_id}/tag/{tag_id}/tag/{with_id}",
     tags = ["tag"],
     params(
-        ("target_id", description = "Target tag id"),
+        ("room_id", description = "Room id"),
+        ("tag_id", description = "Target tag id"),
         ("with_id", description = "Tag id of tag to tag tag with"),
     ),
     responses(
@@ -198,22 +280,40 @@ async fn tag_thread_unapply(
     )
 )]
 async fn tag_tag_apply(
-    Auth(_session): Auth,
-    Path((_target_id, _with_id)): Path<(TagId, TagId)>,
-    State(_s): State<Arc<ServerState>>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    Auth(user_id): Auth,
+    Path((room_id, target_id, with_id)): Path<(RoomId, TagId, TagId)>,
+    State(s): State<Arc<ServerState>>,
+    HeaderReason(reason): HeaderReason,
+) -> Result<impl IntoResponse> {
+    let perms = s.services().perms.for_room(user_id, room_id).await?;
+    perms.ensure_view()?;
+    perms.ensure(Permission::TagManage)?;
+    let target = get_room_tag(&s, room_id, target_id).await?;
+    get_room_tag(&s, room_id, with_id).await?;
+    if target.tags.contains(&with_id) {
+        return Ok(StatusCode::NOT_MODIFIED);
+    }
+    let data = s.data();
+    if target_id == with_id || data.tag_implied(with_id).await?.contains(&target_id) {
+        return Err(Error::BadStatic("tags can't imply themselves"));
+    }
+    data.tag_tag_apply(target_id, with_id).await?;
+    let tag = data.tag_get(target_id).await?;
+    let msg = MessageSync::TagUpdate { tag };
+    s.broadcast_room(room_id, user_id, reason, msg).await?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
-/// Tag tag unapply (TODO)
+/// Tag tag unapply
 ///
 /// Unapply a tag from a tag
 #[utoipa::path(
     delete,
-    path = "/room/{room_id}/tag/{tag_id}/tag/{tag_id}",
+    path = "/room/{room_id}/tag/{tag_id}/tag/{with_id}",
     tags = ["tag"],
     params(
-        ("target_id", description = "Target tag id"),
+        ("room_id", description = "Room id"),
+        ("tag_id", description = "Target tag id"),
         ("with_id", description = "Tag id of tag to tag tag with"),
     ),
     responses(
@@ -222,11 +322,49 @@ async fn tag_tag_apply(
     )
 )]
 async fn tag_tag_unapply(
-    Auth(_session): Auth,
-    Path((_target_id, _with_id)): Path<(TagId, TagId)>,
-    State(_s): State<Arc<ServerState>>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    Auth(user_id): Auth,
+    Path((room_id, target_id, with_id)): Path<(RoomId, TagId, TagId)>,
+    State(s): State<Arc<ServerState>>,
+    HeaderReason(reason): HeaderReason,
+) -> Result<impl IntoResponse> {
+    let perms = s.services().perms.for_room(user_id, room_id).await?;
+    perms.ensure_view()?;
+    perms.ensure(Permission::TagManage)?;
+    let target = get_room_tag(&s, room_id, target_id).await?;
+    if !target.tags.contains(&with_id) {
+        return Ok(StatusCode::NOT_MODIFIED);
+    }
+    let data = s.data();
+    data.tag_tag_unapply(target_id, with_id).await?;
+    let tag = data.tag_get(target_id).await?;
+    let msg = MessageSync::TagUpdate { tag };
+    s.broadcast_room(room_id, user_id, reason, msg).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// get a tag, making sure it's in this room
+async fn get_room_tag(s: &ServerState, room_id: RoomId, tag_id: TagId) -> Result<Tag> {
+    let tag = s.data().tag_get(tag_id).await?;
+    if tag.room_id != room_id {
+        return Err(Error::NotFound);
+    }
+    Ok(tag)
+}
+
+async fn broadcast_thread_update(
+    s: &ServerState,
+    thread_id: ThreadId,
+    user_id: UserId,
+    reason: Option<String>,
+) -> Result<()> {
+    let srv = s.services();
+    srv.threads.invalidate(thread_id).await;
+    let thread = srv.threads.get(thread_id, None).await?;
+    if let Some(room_id) = thread.room_id {
+        let msg = MessageSync::ThreadUpdate { thread };
+        s.broadcast_room(room_id, user_id, reason, msg).await?;
+    }
+    Ok(())
 }
 
 pub fn routes() -> OpenApiRouter<Arc<ServerState>> {
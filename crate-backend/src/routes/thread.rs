@@ -6,9 +6,11 @@ use axum::{
     response::IntoResponse,
     Json,
 };
-use common::v1::types::{MessageId, MessageThreadUpdate, ThreadType};
+use common::v1::types::{
+    moderation::ReportDestination, Mentions, MessageId, MessageThreadUpdate, TagId, ThreadType,
+};
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 use utoipa_axum::{router::OpenApiRouter, routes};
 use validator::Validate;
 
@@ -22,7 +24,7 @@ use crate::{
 };
 
 use super::util::{Auth, HeaderReason};
-use crate::error::Result;
+use crate::error::{Error, Result};
 
 /// Create a thread
 #[utoipa::path(
@@ -56,8 +58,18 @@ async fn thread_create(
         ThreadType::Voice => {
             perms.ensure(Permission::ThreadCreateVoice)?;
         }
+        ThreadType::Report => {
+            return Err(Error::BadStatic("report threads are created by reporting"));
+        }
         _ => todo!(),
     };
+    if let Some(tags) = &json.tags {
+        perms.ensure(Permission::TagApply)?;
+        s.services()
+            .threads
+            .check_tags(Some(room_id), tags, &[])
+            .await?;
+    }
     let thread_id = data
         .thread_create(DbThreadCreate {
             room_id: Some(room_id.into_inner()),
@@ -72,6 +84,9 @@ async fn thread_create(
             },
         })
         .await?;
+    if let Some(tags) = &json.tags {
+        data.tag_thread_set(thread_id, tags).await?;
+    }
     let starter_message_id = data
         .message_create(DbMessageCreate {
             thread_id,
@@ -82,9 +97,10 @@ async fn thread_create(
                 patch: ThreadPatch {
                     name: Some(json.name),
                     description: Some(json.description),
-                    tags: None,
+                    tags: json.tags,
                 },
             }),
+            mentions: Mentions::default(),
             edited_at: None,
             created_at: None,
         })
@@ -135,12 +151,22 @@ async fn thread_get(
     Ok((StatusCode::OK, Json(thread)))
 }
 
+#[derive(Debug, Serialize, Deserialize, ToSchema, IntoParams)]
+struct ThreadListParams {
+    /// only return threads with this tag, including tags implied by tag composition
+    tag_id: Option<TagId>,
+}
+
 /// List threads in a room
 // maybe in the future i'll replace this with a more flexible "thread query/search" api
 #[utoipa::path(
     get,
     path = "/room/{room_id}/thread",
-    params(PaginationQuery<ThreadId>, ("room_id", description = "Room id")),
+    params(
+        PaginationQuery<ThreadId>,
+        ThreadListParams,
+        ("room_id", description = "Room id"),
+    ),
     tags = ["thread"],
     responses(
         (status = OK, body = PaginationResponse<Thread>, description = "List room threads success"),
@@ -149,13 +175,25 @@ async fn thread_get(
 async fn thread_list(
     Path((room_id,)): Path<(RoomId,)>,
     Query(q): Query<PaginationQuery<ThreadId>>,
+    Query(params): Query<ThreadListParams>,
     Auth(user_id): Auth,
     State(s): State<Arc<ServerState>>,
 ) -> Result<impl IntoResponse> {
     let data = s.data();
     let perms = s.services().perms.for_room(user_id, room_id).await?;
     perms.ensure_view()?;
-    let mut res = dbg!(data.thread_list(room_id, q).await?);
+    // report threads are listed to the moderators they're sent to
+    let reports = [
+        (ReportDestination::Room, Permission::MemberBan),
+        (ReportDestination::Server, Permission::ServerReports),
+    ]
+    .into_iter()
+    .filter(|(_, p)| perms.has(*p))
+    .map(|(destination, _)| destination)
+    .collect();
+    let mut res = data
+        .thread_list(room_id, user_id, q, params.tag_id, reports)
+        .await?;
     let srv = s.services();
     let mut threads = vec![];
     for t in &res.items {
@@ -250,6 +288,12 @@ async fn thread_ack(
         .threads
         .invalidate_user(thread_id, user_id)
         .await;
+    s.broadcast(MessageSync::ThreadAck {
+        thread_id,
+        message_id,
+        version_id,
+        user_id,
+    })?;
     Ok(Json(AckRes {
         message_id,
         version_id,
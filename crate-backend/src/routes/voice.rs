@@ -2,17 +2,63 @@ use std::sync::Arc;
 
 use axum::{
     extract::{Path, State},
+    http::StatusCode,
     Json,
 };
-use common::v1::types::{ThreadId, UserId};
+use common::v1::types::{
+    util::Diff,
+    voice::{
+        SignallingMessage, VoiceMemberMove, VoiceRegion, VoiceState, VoiceStatePatch,
+        VoiceStateUpdate,
+    },
+    MessageSync, Permission, Thread, ThreadId, ThreadPublic, UserId,
+};
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use super::util::{Auth, HeaderReason};
 
 use crate::error::Result;
+use crate::types::{DbSfu, DbVoiceModeration, UserIdReq};
 use crate::{Error, ServerState};
 
-/// Voice member get (TODO)
+/// get someone's voice state in a thread, along with the sfu hosting it
+async fn member_get(
+    s: &ServerState,
+    thread_id: ThreadId,
+    user_id: UserId,
+) -> Result<(DbSfu, VoiceState)> {
+    let voice = &s.services().voice;
+    let sfu = voice.sfu_thread(thread_id).await?;
+    let state = voice.sfu_voice_state(&sfu, user_id).await?;
+    if state.thread_id != thread_id {
+        return Err(Error::NotFound);
+    }
+    Ok((sfu, state))
+}
+
+/// record a moderator changing someone's voice state in the room's audit log
+async fn audit_log(
+    s: &ServerState,
+    thread: &Thread,
+    auth_user_id: UserId,
+    reason: Option<String>,
+    user_id: UserId,
+    state: Option<VoiceState>,
+) -> Result<()> {
+    if let Some(room_id) = thread.room_id {
+        s.data()
+            .audit_logs_room_append(
+                room_id,
+                auth_user_id,
+                reason,
+                MessageSync::VoiceState { user_id, state },
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// Voice member get
 #[utoipa::path(
     get,
     path = "/voice/{thread_id}/member/{user_id}",
@@ -22,18 +68,32 @@ use crate::{Error, ServerState};
     ),
     tags = ["voice"],
     responses(
-        (status = OK, body = (), description = "ok"),
+        (status = OK, body = VoiceState, description = "ok"),
     )
 )]
 async fn voice_member_get(
-    Path((_room_id, _user_id)): Path<(ThreadId, UserId)>,
-    Auth(_auth_user_id): Auth,
-    State(_s): State<Arc<ServerState>>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    Path((thread_id, target_user_id)): Path<(ThreadId, UserIdReq)>,
+    Auth(auth_user_id): Auth,
+    State(s): State<Arc<ServerState>>,
+) -> Result<Json<VoiceState>> {
+    let target_user_id = match target_user_id {
+        UserIdReq::UserSelf => auth_user_id,
+        UserIdReq::UserId(id) => id,
+    };
+    let perms = s
+        .services()
+        .perms
+        .for_thread(auth_user_id, thread_id)
+        .await?;
+    perms.ensure_view()?;
+    let (_, state) = member_get(&s, thread_id, target_user_id).await?;
+    Ok(Json(state))
 }
 
-/// Voice member update (TODO)
+/// Voice member update
+///
+/// Server mute or deafen someone in a call. This applies to every voice
+/// thread in the room, and lasts until a moderator undoes it.
 #[utoipa::path(
     patch,
     path = "/voice/{thread_id}/member/{user_id}",
@@ -43,20 +103,80 @@ async fn voice_member_get(
     ),
     tags = ["voice"],
     responses(
-        (status = OK, body = (), description = "ok"),
+        (status = OK, body = VoiceState, description = "ok"),
+        (status = NOT_MODIFIED, description = "not modified"),
     )
 )]
 async fn voice_member_patch(
-    Path((_room_id, _user_id)): Path<(ThreadId, UserId)>,
-    Auth(_auth_user_id): Auth,
-    State(_s): State<Arc<ServerState>>,
-    HeaderReason(_reason): HeaderReason,
-    Json(_json): Json<()>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    Path((thread_id, target_user_id)): Path<(ThreadId, UserIdReq)>,
+    Auth(auth_user_id): Auth,
+    HeaderReason(reason): HeaderReason,
+    State(s): State<Arc<ServerState>>,
+    Json(json): Json<VoiceStatePatch>,
+) -> Result<Json<VoiceState>> {
+    let target_user_id = match target_user_id {
+        UserIdReq::UserSelf => auth_user_id,
+        UserIdReq::UserId(id) => id,
+    };
+    let perms = s
+        .services()
+        .perms
+        .for_thread(auth_user_id, thread_id)
+        .await?;
+    perms.ensure_view()?;
+    if json.mute.is_some() {
+        perms.ensure(Permission::VoiceMute)?;
+    }
+    if json.deaf.is_some() {
+        perms.ensure(Permission::VoiceDeafen)?;
+    }
+    let srv = s.services();
+    let thread = srv.threads.get(thread_id, Some(auth_user_id)).await?;
+    let room_id = thread
+        .room_id
+        .ok_or(Error::BadStatic("can only moderate voice in rooms"))?;
+    if target_user_id != auth_user_id {
+        srv.perms
+            .ensure_outranks(auth_user_id, target_user_id, room_id)
+            .await?;
+    }
+
+    let (sfu, state) = member_get(&s, thread_id, target_user_id).await?;
+    if !json.changes(&state) {
+        return Err(Error::NotModified);
+    }
+    let state = VoiceState {
+        mute: json.mute.unwrap_or(state.mute),
+        deaf: json.deaf.unwrap_or(state.deaf),
+        ..state
+    };
+    // saved so it still applies after they leave and rejoin
+    s.data()
+        .voice_moderation_set(
+            room_id,
+            target_user_id,
+            DbVoiceModeration {
+                mute: state.mute,
+                deaf: state.deaf,
+            },
+        )
+        .await?;
+    srv.voice
+        .sfu_moderate(&sfu, target_user_id, state.mute, state.deaf)
+        .await?;
+    audit_log(
+        &s,
+        &thread,
+        auth_user_id,
+        reason,
+        target_user_id,
+        Some(state.clone()),
+    )
+    .await?;
+    Ok(Json(state))
 }
 
-/// Voice member disconnect (TODO)
+/// Voice member disconnect
 #[utoipa::path(
     delete,
     path = "/voice/{thread_id}/member/{user_id}",
@@ -70,16 +190,42 @@ async fn voice_member_patch(
     )
 )]
 async fn voice_member_disconnect(
-    Path((_room_id, _user_id)): Path<(ThreadId, UserId)>,
-    Auth(_auth_user_id): Auth,
-    State(_s): State<Arc<ServerState>>,
-    HeaderReason(_reason): HeaderReason,
-    Json(_json): Json<()>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    Path((thread_id, target_user_id)): Path<(ThreadId, UserIdReq)>,
+    Auth(auth_user_id): Auth,
+    HeaderReason(reason): HeaderReason,
+    State(s): State<Arc<ServerState>>,
+) -> Result<StatusCode> {
+    let target_user_id = match target_user_id {
+        UserIdReq::UserSelf => auth_user_id,
+        UserIdReq::UserId(id) => id,
+    };
+    let perms = s
+        .services()
+        .perms
+        .for_thread(auth_user_id, thread_id)
+        .await?;
+    perms.ensure_view()?;
+    if target_user_id != auth_user_id {
+        perms.ensure(Permission::VoiceDisconnect)?;
+    }
+
+    let srv = s.services();
+    let thread = srv.threads.get(thread_id, Some(auth_user_id)).await?;
+    if let Some(room_id) = thread.room_id.filter(|_| target_user_id != auth_user_id) {
+        srv.perms
+            .ensure_outranks(auth_user_id, target_user_id, room_id)
+            .await?;
+    }
+    let (sfu, _) = member_get(&s, thread_id, target_user_id).await?;
+    let leave = SignallingMessage::VoiceState { state: None };
+    srv.voice.sfu_send(&sfu, target_user_id, &leave).await?;
+    audit_log(&s, &thread, auth_user_id, reason, target_user_id, None).await?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
-/// Voice member move (TODO)
+/// Voice member move
+///
+/// Move someone to another voice thread in the same room
 #[utoipa::path(
     post,
     path = "/voice/{thread_id}/member/{user_id}",
@@ -89,30 +235,94 @@ async fn voice_member_disconnect(
     ),
     tags = ["voice"],
     responses(
-        (status = OK, body = (), description = "ok"),
+        (status = NO_CONTENT, description = "ok"),
     )
 )]
 async fn voice_member_move(
-    Path((_room_id, _user_id)): Path<(ThreadId, UserId)>,
-    Auth(_auth_user_id): Auth,
-    State(_s): State<Arc<ServerState>>,
-    HeaderReason(_reason): HeaderReason,
-    Json(_json): Json<()>,
-) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+    Path((thread_id, target_user_id)): Path<(ThreadId, UserIdReq)>,
+    Auth(auth_user_id): Auth,
+    HeaderReason(reason): HeaderReason,
+    State(s): State<Arc<ServerState>>,
+    Json(json): Json<VoiceMemberMove>,
+) -> Result<StatusCode> {
+    let target_user_id = match target_user_id {
+        UserIdReq::UserSelf => auth_user_id,
+        UserIdReq::UserId(id) => id,
+    };
+    let srv = s.services();
+    for id in [thread_id, json.thread_id] {
+        let perms = srv.perms.for_thread(auth_user_id, id).await?;
+        perms.ensure_view()?;
+        perms.ensure(Permission::VoiceMove)?;
+    }
+    let source = srv.threads.get(thread_id, Some(auth_user_id)).await?;
+    let target = srv.threads.get(json.thread_id, Some(auth_user_id)).await?;
+    let ThreadPublic::Voice(voice) = &target.info else {
+        return Err(Error::BadStatic("not a voice thread"));
+    };
+    let room_id = source
+        .room_id
+        .filter(|room_id| target.room_id == Some(*room_id))
+        .ok_or(Error::BadStatic("can only move members within a room"))?;
+    if target_user_id != auth_user_id {
+        srv.perms
+            .ensure_outranks(auth_user_id, target_user_id, room_id)
+            .await?;
+    }
+
+    let (old, state) = member_get(&s, thread_id, target_user_id).await?;
+    // moderators can move people into full threads, but not ones they can't join
+    let permissions = srv
+        .voice
+        .permissions(target_user_id, json.thread_id)
+        .await?;
+    let moderation = srv.voice.moderation(target_user_id, &target).await?;
+    let sfu = srv.voice.sfu_for_thread(json.thread_id, None).await?;
+    let join = SignallingMessage::VoiceState {
+        state: Some(VoiceStateUpdate {
+            thread_id: json.thread_id,
+            region: None,
+            self_mute: state.self_mute,
+            self_deaf: state.self_deaf,
+        }),
+    };
+    if old.id != sfu.id {
+        let leave = SignallingMessage::VoiceState { state: None };
+        srv.voice.sfu_send(&old, target_user_id, &leave).await?;
+    }
+    srv.voice
+        .sfu_join(&sfu, target_user_id, &join, voice, &permissions, moderation)
+        .await?;
+    let state = VoiceState {
+        thread_id: json.thread_id,
+        ..state
+    };
+    audit_log(
+        &s,
+        &source,
+        auth_user_id,
+        reason,
+        target_user_id,
+        Some(state),
+    )
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
-/// Voice region list (TODO)
+/// Voice region list
+///
+/// List every region that voice calls can be hosted in
 #[utoipa::path(
     get,
     path = "/voice/region",
     tags = ["voice"],
     responses(
-        (status = OK, body = (), description = "ok"),
+        (status = OK, body = Vec<VoiceRegion>, description = "ok"),
     )
 )]
-async fn voice_region_list(State(_s): State<Arc<ServerState>>) -> Result<Json<()>> {
-    Err(Error::Unimplemented)
+async fn voice_region_list(State(s): State<Arc<ServerState>>) -> Result<Json<Vec<VoiceRegion>>> {
+    let regions = s.services().voice.regions().await?;
+    Ok(Json(regions))
 }
 
 pub fn routes() -> OpenApiRouter<Arc<ServerState>> {
@@ -555,6 +555,7 @@ impl ServiceEmbed {
                 author_id: message.author_id,
                 embeds,
                 message_type: new_message_type,
+                mentions: message.mentions.clone(),
                 edited_at: None,
                 created_at: None,
             },
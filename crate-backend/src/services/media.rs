@@ -19,9 +19,11 @@ use tokio::{
     process::Command,
 };
 use tracing::{debug, error, info, span, trace, Instrument, Level};
+use url::Url;
 
 use crate::{
     error::{Error, Result},
+    types::NodeCommand,
     ServerStateInner,
 };
 
@@ -207,6 +209,29 @@ impl ServiceMedia {
         Ok(())
     }
 
+    /// download a media item's source and generate its thumbnails again
+    pub async fn regenerate_thumbnails(&self, media_id: MediaId) -> Result<Media> {
+        let data = self.state.data();
+        let (mut media, _) = data.media_select(media_id).await?;
+        let bytes = self.state.blobs.read(&format!("media/{media_id}")).await?;
+        let tmp = TempFile::new().await.expect("failed to create temp file!");
+        let mut f = tmp.open_rw().await?;
+        f.write_all(&bytes.to_vec()).await?;
+        f.flush().await?;
+        let p = tmp.file_path().to_owned();
+        let (meta, _) = self.get_metadata_and_mime(&p).await?;
+        let Some(meta) = meta else {
+            return Err(Error::BadStatic("can't read this media's metadata"));
+        };
+        media
+            .tracks
+            .retain(|t| !matches!(t.info, MediaTrackInfo::Thumbnail(_)));
+        self.generate_thumbnails(&mut media, &meta, &p, true)
+            .await?;
+        data.media_replace(media.clone()).await?;
+        Ok(media)
+    }
+
     pub async fn process_upload(
         &self,
         up: MediaUpload,
@@ -307,6 +332,19 @@ impl ServiceMedia {
         json: MediaCreate,
         max_size: u64,
     ) -> Result<Media> {
+        if let Some(nodes) = &self.state.config.nodes {
+            if let Some(media_url) = &nodes.media_url {
+                let command = NodeCommand::MediaImport {
+                    user_id,
+                    media: json,
+                    max_size,
+                };
+                return self
+                    .send_to_media_node(media_url, &nodes.token, &command)
+                    .await;
+            }
+        }
+
         let (_filename, size, source_url) = match &json.source {
             MediaCreateSource::Upload { .. } => unreachable!(),
             MediaCreateSource::Download {
@@ -341,6 +379,32 @@ impl ServiceMedia {
             .await
     }
 
+    /// have a media node run a command, so processing doesn't slow down this node
+    async fn send_to_media_node(
+        &self,
+        media_url: &Url,
+        token: &str,
+        command: &NodeCommand,
+    ) -> Result<Media> {
+        let res = reqwest::Client::new()
+            .post(format!(
+                "{}/api/v1/internal/node",
+                media_url.as_str().trim_end_matches('/')
+            ))
+            .header("authorization", format!("Node {token}"))
+            .json(command)
+            .send()
+            .await?;
+        if !res.status().is_success() {
+            return Err(Error::GenericError(format!(
+                "{} {}",
+                res.status(),
+                res.text().await?
+            )));
+        }
+        Ok(res.json().await?)
+    }
+
     pub async fn import_from_response(
         &self,
         user_id: UserId,
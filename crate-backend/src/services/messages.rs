@@ -4,18 +4,20 @@ use tracing::error;
 
 use common::v1::types::misc::Color;
 use common::v1::types::reaction::ReactionCounts;
+use common::v1::types::util::mentions::parse_mentions;
 use common::v1::types::util::Diff;
 use common::v1::types::UserId;
 use common::v1::types::{
-    Embed, Interactions, Message, MessageCreate, MessageDefaultMarkdown, MessageDefaultTagged,
-    MessageId, MessagePatch, MessageSync, MessageType, Permission, ThreadId, ThreadMembership,
+    Embed, Interactions, Mentions, Message, MessageCreate, MessageDefaultMarkdown,
+    MessageDefaultTagged, MessageId, MessagePatch, MessageSync, MessageType, Permission, ThreadId,
+    ThreadMembership,
 };
 use http::StatusCode;
 use linkify::LinkFinder;
 use url::Url;
 use validator::Validate;
 
-use crate::types::{DbMessageCreate, MediaLinkType};
+use crate::types::{DbMessageCreate, MediaLinkType, Permissions};
 use crate::{Error, Result, ServerStateInner};
 
 pub struct ServiceMessages {
@@ -59,6 +61,41 @@ impl ServiceMessages {
         }
     }
 
+    /// parse mentions from content, dropping any that the author isn't allowed to use
+    ///
+    /// @room, @thread, and pinging roles that aren't mentionable require
+    /// MessageMassMention
+    async fn resolve_mentions(
+        &self,
+        thread_id: ThreadId,
+        perms: &Permissions,
+        content: Option<&str>,
+    ) -> Result<Mentions> {
+        let Some(content) = content else {
+            return Ok(Mentions::default());
+        };
+        let mut mentions = parse_mentions(content);
+        let can_mass_mention = perms.has(Permission::MessageMassMention);
+        if !can_mass_mention {
+            mentions.all_in_room = false;
+            mentions.all_in_thread = false;
+        }
+        let thread = self.state.services().threads.get(thread_id, None).await?;
+        let data = self.state.data();
+        let mut roles = vec![];
+        if let Some(room_id) = thread.room_id {
+            for role_id in mentions.roles {
+                match data.role_select(room_id, role_id).await {
+                    Ok(role) if role.is_mentionable || can_mass_mention => roles.push(role_id),
+                    Ok(_) | Err(Error::NotFound) => {}
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        mentions.roles = roles;
+        Ok(mentions)
+    }
+
     pub async fn create(
         &self,
         thread_id: ThreadId,
@@ -99,6 +136,8 @@ impl ServiceMessages {
                 "at least one of content, attachments, or embeds must be defined",
             ));
         }
+        // check the media in the same transaction that links it
+        let data = data.begin().await?;
         let attachment_ids: Vec<_> = json.attachments.into_iter().map(|r| r.id).collect();
         for id in &attachment_ids {
             data.media_select(*id).await?;
@@ -108,6 +147,9 @@ impl ServiceMessages {
             }
         }
         let content = json.content.clone();
+        let mentions = self
+            .resolve_mentions(thread_id, &perms, content.as_deref())
+            .await?;
         let payload = MessageType::DefaultMarkdown(MessageDefaultMarkdown {
             content: json.content,
             attachments: vec![],
@@ -129,6 +171,7 @@ impl ServiceMessages {
                     .map(embed_from_create)
                     .collect(),
                 message_type: payload,
+                mentions,
                 edited_at: None,
                 created_at: json.created_at.map(|t| t.into()),
             })
@@ -141,12 +184,6 @@ impl ServiceMessages {
                 .await?;
         }
         let mut message = data.message_get(thread_id, message_id, user_id).await?;
-
-        if let Some(content) = &content {
-            tokio::spawn(self.handle_url_embed(message.clone(), user_id, content.clone()));
-        }
-        s.presign_message(&mut message).await?;
-        message.nonce = nonce.or(json.nonce);
         data.thread_member_put(
             thread_id,
             user_id,
@@ -156,6 +193,15 @@ impl ServiceMessages {
             },
         )
         .await?;
+        data.notification_queue_insert(thread_id, message_id, user_id)
+            .await?;
+        data.commit().await?;
+
+        if let Some(content) = &content {
+            tokio::spawn(self.handle_url_embed(message.clone(), user_id, content.clone()));
+        }
+        s.presign_message(&mut message).await?;
+        message.nonce = nonce.or(json.nonce);
         let msg = MessageSync::MessageCreate {
             message: message.clone(),
         };
@@ -178,6 +224,7 @@ impl ServiceMessages {
         let srv = s.services();
         let mut perms = s.services().perms.for_thread(user_id, thread_id).await?;
         perms.ensure_view()?;
+        let data = data.begin().await?;
         let message = data.message_get(thread_id, message_id, user_id).await?;
         if !message.message_type.is_editable() {
             return Err(Error::BadStatic("cant edit that message"));
@@ -278,6 +325,9 @@ impl ServiceMessages {
             }
             _ => return Err(Error::Unimplemented),
         }?;
+        let mentions = self
+            .resolve_mentions(thread_id, &perms, content.as_deref())
+            .await?;
         let version_id = data
             .message_update(
                 thread_id,
@@ -294,6 +344,7 @@ impl ServiceMessages {
                         .map(embed_from_create)
                         .collect(),
                     message_type: payload,
+                    mentions,
                     edited_at: None,
                     created_at: None,
                 },
@@ -306,13 +357,16 @@ impl ServiceMessages {
                 .await?;
         }
 
+        let new_message = data
+            .message_version_get(thread_id, version_id, user_id)
+            .await?;
+        data.commit().await?;
+
         if let Some(content) = &content {
             tokio::spawn(self.handle_url_embed(message.clone(), user_id, content.clone()));
         }
 
-        let mut message = data
-            .message_version_get(thread_id, version_id, user_id)
-            .await?;
+        let mut message = new_message;
 
         if let Some(embeds) = json.embeds {
             match &mut message.message_type {
@@ -4,12 +4,14 @@ use email::ServiceEmail;
 use embed::ServiceEmbed;
 use media::ServiceMedia;
 use messages::ServiceMessages;
+use notifications::ServiceNotifications;
 use oauth2::ServiceOauth;
 use permissions::ServicePermissions;
 use room::ServiceRooms;
 use sessions::ServiceSessions;
 use thread::ServiceThreads;
 use users::ServiceUsers;
+use voice::ServiceVoice;
 
 use crate::ServerStateInner;
 
@@ -17,17 +19,20 @@ pub mod email;
 pub mod embed;
 pub mod media;
 pub mod messages;
+pub mod notifications;
 pub mod oauth2;
 pub mod permissions;
 pub mod room;
 pub mod sessions;
 pub mod thread;
 pub mod users;
+pub mod voice;
 
 pub struct Services {
     pub(super) state: Arc<ServerStateInner>,
     pub media: ServiceMedia,
     pub messages: ServiceMessages,
+    pub notifications: ServiceNotifications,
     pub perms: ServicePermissions,
     pub rooms: ServiceRooms,
     pub threads: ServiceThreads,
@@ -36,6 +41,7 @@ pub struct Services {
     pub users: ServiceUsers,
     pub sessions: ServiceSessions,
     pub email: ServiceEmail,
+    pub voice: ServiceVoice,
 }
 
 impl Services {
@@ -44,6 +50,7 @@ impl Services {
             embed: ServiceEmbed::new(state.clone()),
             media: ServiceMedia::new(state.clone()),
             messages: ServiceMessages::new(state.clone()),
+            notifications: ServiceNotifications::new(state.clone()),
             perms: ServicePermissions::new(state.clone()),
             rooms: ServiceRooms::new(state.clone()),
             threads: ServiceThreads::new(state.clone()),
@@ -51,6 +58,7 @@ impl Services {
             users: ServiceUsers::new(state.clone()),
             sessions: ServiceSessions::new(state.clone()),
             email: ServiceEmail::new(state.clone()),
+            voice: ServiceVoice::new(state.clone()),
             state,
         }
     }
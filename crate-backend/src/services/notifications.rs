@@ -0,0 +1,300 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::v1::types::notifications::{
+    Mute, NotifAction, Notification, NotificationReason, NotifsGlobal, NotifsRoom, NotifsThread,
+};
+use common::v1::types::util::Time;
+use common::v1::types::{
+    Message, MessageSync, MessageType, NotificationId, PaginationDirection, PaginationQuery,
+    Permission, RoomType, UserId,
+};
+use futures::future::join_all;
+use tokio::sync::Notify;
+use tokio::time::{sleep, timeout};
+use tracing::{error, warn};
+
+use crate::error::{Error, Result};
+use crate::types::DbNotificationQueue;
+use crate::ServerStateInner;
+
+#[derive(Clone)]
+pub struct ServiceNotifications {
+    state: Arc<ServerStateInner>,
+
+    /// wakes the worker when this node queues a message
+    ///
+    /// the queue itself is in the database, so nothing is lost if this node
+    /// stops, and other nodes' workers pick up where it left off
+    wake: Arc<Notify>,
+}
+
+/// where a message was sent, which decides which global config applies to it
+enum Place {
+    /// a room that anyone can be invited to
+    Room,
+
+    /// a thread outside of any room, like a group chat
+    Private,
+
+    /// a direct message
+    Dm,
+}
+
+impl ServiceNotifications {
+    pub fn new(state: Arc<ServerStateInner>) -> Self {
+        let me = Self {
+            state,
+            wake: Arc::new(Notify::new()),
+        };
+        tokio::spawn(me.clone().worker());
+        me
+    }
+
+    /// wake the worker for a message this node queued
+    pub fn handle(&self, msg: &MessageSync) {
+        if let MessageSync::MessageCreate { .. } = msg {
+            self.wake.notify_one();
+        }
+    }
+
+    async fn worker(self) {
+        loop {
+            match self.process_queue_item().await {
+                Ok(true) => {}
+                // other nodes don't wake this worker, so check every so often
+                Ok(false) => {
+                    let _ = timeout(Duration::from_secs(5), self.wake.notified()).await;
+                }
+                Err(err) => {
+                    error!("failed to process notification queue: {err:?}");
+                    sleep(Duration::from_secs(5)).await;
+                }
+            }
+        }
+    }
+
+    async fn process_queue_item(&self) -> Result<bool> {
+        let data = self.state.data();
+        let Some(item) = data.notification_queue_claim().await? else {
+            return Ok(false);
+        };
+        match self.handle_queued(&item).await {
+            Ok(()) => {}
+            // the message was deleted before anyone was notified about it
+            Err(Error::NotFound) => data.notification_queue_finish(item.message_id).await?,
+            Err(err) => {
+                error!("failed to create notifications: {err:?}");
+                data.notification_queue_fail(err.to_string(), item.message_id)
+                    .await?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// notify everyone about a queued message and take it off the queue together,
+    /// so nobody is notified twice
+    async fn handle_queued(&self, item: &DbNotificationQueue) -> Result<()> {
+        let data = self.state.data();
+        let message = data
+            .message_get(item.thread_id, item.message_id, item.author_id)
+            .await?;
+        let notifs = self.notifications_for(message).await?;
+        let data = data.begin().await?;
+        if !notifs.is_empty() {
+            data.notification_add(notifs).await?;
+        }
+        data.notification_queue_finish(item.message_id).await?;
+        data.commit().await
+    }
+
+    /// get notifications for everyone who should be notified about a message
+    async fn notifications_for(&self, message: Message) -> Result<Vec<(UserId, Notification)>> {
+        let reply_id = match &message.message_type {
+            MessageType::DefaultMarkdown(m) => m.reply_id,
+            MessageType::DefaultTagged(m) => m.reply_id,
+            // system messages don't notify anyone
+            _ => return Ok(vec![]),
+        };
+        let data = self.state.data();
+        let srv = self.state.services();
+        let thread = srv.threads.get(message.thread_id, None).await?;
+        let room = match thread.room_id {
+            Some(room_id) => Some(srv.rooms.get(room_id, None).await?),
+            None => None,
+        };
+        let place = match &room {
+            Some(room) if matches!(room.room_type, RoomType::Dm { .. }) => Place::Dm,
+            Some(_) => Place::Room,
+            None => Place::Private,
+        };
+        // the first message in a room's thread also counts as a new thread.
+        // threads are created with a starter message, which is skipped here
+        let starts_thread = match place {
+            Place::Room => {
+                let first = data
+                    .message_list(
+                        message.thread_id,
+                        message.author_id,
+                        PaginationQuery {
+                            from: None,
+                            to: None,
+                            dir: Some(PaginationDirection::F),
+                            limit: Some(2),
+                        },
+                    )
+                    .await?;
+                first
+                    .items
+                    .iter()
+                    .find(|m| {
+                        matches!(
+                            m.message_type,
+                            MessageType::DefaultMarkdown(_) | MessageType::DefaultTagged(_)
+                        )
+                    })
+                    .is_some_and(|m| m.id == message.id)
+            }
+            Place::Private | Place::Dm => false,
+        };
+        let reply_author_id = match reply_id {
+            Some(reply_id) => data
+                .message_get(message.thread_id, reply_id, message.author_id)
+                .await
+                .ok()
+                .map(|m| m.author_id),
+            None => None,
+        };
+
+        let mut notified = vec![];
+        for recipient in data.notification_recipients(message.thread_id).await? {
+            if recipient.user_id == message.author_id {
+                continue;
+            }
+
+            let mentions = &message.mentions;
+            let reason = if mentions.users.contains(&recipient.user_id) {
+                NotificationReason::Mention
+            } else if mentions.all_in_room
+                || mentions.all_in_thread
+                || recipient.roles.iter().any(|r| mentions.roles.contains(r))
+            {
+                NotificationReason::MentionBulk
+            } else if reply_author_id == Some(recipient.user_id) {
+                NotificationReason::Reply
+            } else {
+                NotificationReason::New
+            };
+
+            let action = resolve_action(
+                &reason,
+                &recipient.global,
+                &recipient.room,
+                &recipient.thread,
+                &place,
+                starts_thread,
+            );
+            if action != NotifAction::Ignore {
+                notified.push((recipient.user_id, reason));
+            }
+        }
+
+        // only check permissions for people who would actually be notified. one
+        // failed check only skips that person, instead of everyone else too
+        let perms = join_all(
+            notified
+                .iter()
+                .map(|(user_id, _)| srv.perms.for_thread(*user_id, message.thread_id)),
+        )
+        .await;
+        Ok(notified
+            .into_iter()
+            .zip(perms)
+            .filter(|((user_id, _), perms)| match perms {
+                Ok(perms) => perms.has(Permission::View),
+                Err(err) => {
+                    warn!("failed to check if {user_id} can be notified: {err:?}");
+                    false
+                }
+            })
+            .map(|((user_id, reason), _)| {
+                let notif = Notification {
+                    id: NotificationId::new(),
+                    room_id: thread.room_id,
+                    thread_id: message.thread_id,
+                    message_id: message.id,
+                    reason,
+                    created_at: Time::now_utc(),
+                    read_at: None,
+                };
+                (user_id, notif)
+            })
+            .collect())
+    }
+}
+
+fn is_muted(mute: &Option<Mute>) -> bool {
+    mute.as_ref()
+        .is_some_and(|m| m.expires_at.as_ref().is_none_or(|t| *t > Time::now_utc()))
+}
+
+/// the more attention grabbing of two actions
+fn louder(a: &NotifAction, b: &NotifAction) -> NotifAction {
+    let rank = |action: &NotifAction| match action {
+        NotifAction::Ignore => 0,
+        NotifAction::Watching => 1,
+        NotifAction::Notify => 2,
+    };
+    if rank(a) >= rank(b) {
+        a.clone()
+    } else {
+        b.clone()
+    }
+}
+
+/// resolve the thread -> room -> global config cascade for a notification
+///
+/// muting at any level ignores everything except direct mentions. globally,
+/// new messages use the louder of `messages` and the config for where they
+/// were sent, and the first message in a thread can also use `threads`.
+fn resolve_action(
+    reason: &NotificationReason,
+    global: &NotifsGlobal,
+    room: &NotifsRoom,
+    thread: &NotifsThread,
+    place: &Place,
+    starts_thread: bool,
+) -> NotifAction {
+    let muted = is_muted(&global.mute) || is_muted(&room.mute) || is_muted(&thread.mute);
+    if muted && *reason != NotificationReason::Mention {
+        return NotifAction::Ignore;
+    }
+
+    match reason {
+        NotificationReason::Mention
+        | NotificationReason::MentionBulk
+        | NotificationReason::Reply => thread
+            .mentions
+            .clone()
+            .or_else(|| room.mentions.clone())
+            .unwrap_or_else(|| global.mentions.clone()),
+        NotificationReason::New => {
+            if let Some(action) = &thread.messages {
+                return action.clone();
+            }
+            let action = room.messages.clone().unwrap_or_else(|| {
+                let place = match place {
+                    Place::Room => &global.room_public,
+                    Place::Private => &global.room_private,
+                    Place::Dm => &global.room_dm,
+                };
+                louder(&global.messages, place)
+            });
+            if starts_thread {
+                louder(&action, room.threads.as_ref().unwrap_or(&global.threads))
+            } else {
+                action
+            }
+        }
+    }
+}
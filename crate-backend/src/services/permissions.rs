@@ -1,11 +1,14 @@
 use std::sync::Arc;
 
-use common::v1::types::{PermissionOverwriteType, RoomId, ThreadId, UserId};
+use common::v1::types::{
+    MessageSync, Permission, PermissionOverwriteType, RoomId, ThreadId, UserId,
+};
 use moka::future::Cache;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
 use uuid::Uuid;
 
-use crate::error::Result;
-use crate::types::Permissions;
+use crate::error::{Error, Result};
+use crate::types::{Permissions, Rank, SyncEvent};
 use crate::ServerStateInner;
 
 pub struct ServicePermissions {
@@ -19,7 +22,7 @@ impl ServicePermissions {
     pub fn new(state: Arc<ServerStateInner>) -> Self {
         // not sure what the best way to configure these caches are
         // (userid, roomid) seems a bit inefficient, maybe caching roles would be better
-        Self {
+        let me = Self {
             state,
             cache_perm_room: Cache::builder()
                 .max_capacity(100_000)
@@ -33,6 +36,63 @@ impl ServicePermissions {
                 .max_capacity(100_000)
                 .support_invalidation_closures()
                 .build(),
+        };
+        tokio::spawn(Self::invalidator(
+            me.state.sushi.subscribe(),
+            me.cache_perm_room.clone(),
+            me.cache_perm_thread.clone(),
+            me.cache_is_mutual.clone(),
+        ));
+        me
+    }
+
+    /// drop cached permissions when members, bans, roles or overwrites change on any node
+    async fn invalidator(
+        mut rx: Receiver<SyncEvent>,
+        cache_perm_room: Cache<(UserId, RoomId), Permissions>,
+        cache_perm_thread: Cache<(UserId, RoomId, ThreadId), Permissions>,
+        cache_is_mutual: Cache<(UserId, UserId), bool>,
+    ) {
+        loop {
+            let msg = match rx.recv().await {
+                Ok(event) => event.msg,
+                Err(RecvError::Lagged(_)) => {
+                    cache_perm_room.invalidate_all();
+                    cache_perm_thread.invalidate_all();
+                    cache_is_mutual.invalidate_all();
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            let (user_id, room_id, thread_id) = match msg {
+                MessageSync::RoomMemberUpsert { member } => {
+                    (Some(member.user_id), Some(member.room_id), None)
+                }
+                MessageSync::BanCreate { room_id, user_id }
+                | MessageSync::BanDelete { room_id, user_id } => {
+                    (Some(user_id), Some(room_id), None)
+                }
+                MessageSync::RoleCreate { role } | MessageSync::RoleUpdate { role } => {
+                    (None, Some(role.room_id), None)
+                }
+                MessageSync::RoleDelete { room_id, .. } => (None, Some(room_id), None),
+                // permission overwrites are announced as thread updates
+                MessageSync::ThreadUpdate { thread } => (None, None, Some(thread.id)),
+                MessageSync::UserDelete { id } => (Some(id), None, None),
+                _ => continue,
+            };
+            let _ = cache_perm_room.invalidate_entries_if(move |(u, r), _| {
+                user_id.is_none_or(|id| id == *u) && room_id.is_none_or(|id| id == *r)
+            });
+            let _ = cache_perm_thread.invalidate_entries_if(move |(u, r, t), _| {
+                user_id.is_none_or(|id| id == *u)
+                    && room_id.is_none_or(|id| id == *r)
+                    && thread_id.is_none_or(|id| id == *t)
+            });
+            if let Some(user_id) = user_id {
+                let _ = cache_is_mutual
+                    .invalidate_entries_if(move |(a, b), _| *a == user_id || *b == user_id);
+            }
         }
     }
 
@@ -47,6 +107,52 @@ impl ServicePermissions {
             .map_err(|err| err.fake_clone())
     }
 
+    /// get how high up a user is in a room's role hierarchy
+    pub async fn rank(&self, user_id: UserId, room_id: RoomId) -> Result<Rank> {
+        let room = self.state.services().rooms.get(room_id, None).await?;
+        if room.owner_id == Some(user_id) {
+            return Ok(Rank::Owner);
+        }
+        let position = self
+            .state
+            .data()
+            .role_position_max(room_id, user_id)
+            .await?;
+        Ok(Rank::Member(position))
+    }
+
+    /// check that a user outranks another member in a room, so members can't
+    /// moderate anyone whose highest role is positioned as high as theirs
+    pub async fn ensure_outranks(
+        &self,
+        user_id: UserId,
+        target_id: UserId,
+        room_id: RoomId,
+    ) -> Result<()> {
+        let rank = self.rank(user_id, room_id).await?;
+        let target = self.rank(target_id, room_id).await?;
+        if rank > target {
+            Ok(())
+        } else {
+            Err(Error::MissingPermissions)
+        }
+    }
+
+    /// check that a user outranks a role position, so members can't manage or
+    /// apply roles positioned as high as their own
+    pub async fn ensure_outranks_position(
+        &self,
+        user_id: UserId,
+        room_id: RoomId,
+        position: u64,
+    ) -> Result<()> {
+        if self.rank(user_id, room_id).await? > Rank::Member(position) {
+            Ok(())
+        } else {
+            Err(Error::MissingPermissions)
+        }
+    }
+
     pub async fn for_thread(&self, user_id: UserId, thread_id: ThreadId) -> Result<Permissions> {
         let t = self
             .state
@@ -114,14 +220,13 @@ impl ServicePermissions {
             .expect("failed to invalidate");
     }
 
+    /// check if a user has ServerAdmin in the admin room (`admin_room_id`)
     pub async fn is_admin(&self, user_id: UserId) -> Result<bool> {
-        let data = self.state.data();
-        // Assuming there's a way to get global permissions or check for an admin role
-        // For now, let's assume a simple check, e.g., if a user has a specific admin permission
-        // This needs to be properly implemented based on how global permissions are managed
-        // For demonstration, let's say user with ID 0 is admin
-        // TODO: Implement proper admin check
-        Ok(user_id.to_string() == "00000000-0000-0000-0000-000000000000")
+        let Some(room_id) = self.state.config.admin_room_id else {
+            return Ok(false);
+        };
+        let perms = self.for_room(user_id, room_id).await?;
+        Ok(perms.has(Permission::ServerAdmin))
     }
 
     pub async fn permission_overwrite_upsert(
@@ -48,8 +48,8 @@ impl ServiceRooms {
     }
 
     pub async fn create(&self, create: RoomCreate, creator: UserId) -> Result<Room> {
-        let data = self.state.data();
-        let room = data.room_create(create).await?;
+        let data = self.state.data().begin().await?;
+        let room = data.room_create(create, Some(creator)).await?;
         let room_id = room.id;
         let role_admin = DbRoleCreate {
             room_id,
@@ -59,6 +59,7 @@ impl ServiceRooms {
             is_self_applicable: false,
             is_mentionable: false,
             is_default: false,
+            position: 2,
         };
         let role_moderator = DbRoleCreate {
             room_id,
@@ -68,6 +69,7 @@ impl ServiceRooms {
             is_self_applicable: false,
             is_mentionable: false,
             is_default: false,
+            position: 1,
         };
         let role_everyone = DbRoleCreate {
             room_id,
@@ -77,6 +79,7 @@ impl ServiceRooms {
             is_self_applicable: false,
             is_mentionable: false,
             is_default: true,
+            position: 0,
         };
         let admin = data.role_create(role_admin).await?;
         data.role_create(role_moderator).await?;
@@ -93,17 +96,21 @@ impl ServiceRooms {
         .await?;
         data.role_member_put(creator, admin.id).await?;
         data.role_apply_default(room.id, creator).await?;
+        data.commit().await?;
         Ok(room)
     }
 
     pub async fn create_dm(&self, user_a_id: UserId, user_b_id: UserId) -> Result<Room> {
-        let data = self.state.data();
+        let data = self.state.data().begin().await?;
         let room = data
-            .room_create(RoomCreate {
-                name: "(dm)".into(),
-                description: None,
-                icon: None,
-            })
+            .room_create(
+                RoomCreate {
+                    name: "(dm)".into(),
+                    description: None,
+                    icon: None,
+                },
+                None,
+            )
             .await?;
         let room_id = room.id;
         let role_default = DbRoleCreate {
@@ -114,6 +121,7 @@ impl ServiceRooms {
             is_self_applicable: false,
             is_mentionable: false,
             is_default: true,
+            position: 0,
         };
         data.role_create(role_default).await?;
         data.room_member_put(
@@ -138,6 +146,7 @@ impl ServiceRooms {
         .await?;
         data.role_apply_default(room.id, user_a_id).await?;
         data.role_apply_default(room.id, user_b_id).await?;
+        data.commit().await?;
         Ok(room)
     }
 }
@@ -1,9 +1,10 @@
 use std::sync::Arc;
 
-use common::v1::types::{Session, SessionId, SessionToken};
+use common::v1::types::{user::Suspended, MessageSync, Session, SessionId, SessionToken};
 use moka::future::Cache;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
 
-use crate::{Result, ServerStateInner};
+use crate::{types::SyncEvent, Error, Result, ServerStateInner};
 
 pub struct ServiceSessions {
     state: Arc<ServerStateInner>,
@@ -14,8 +15,7 @@ pub struct ServiceSessions {
 
 impl ServiceSessions {
     pub fn new(state: Arc<ServerStateInner>) -> Self {
-        Self {
-            state,
+        let me = Self {
             cache_sessions: Cache::builder()
                 .max_capacity(100_000)
                 .support_invalidation_closures()
@@ -24,6 +24,38 @@ impl ServiceSessions {
                 .max_capacity(100_000)
                 .support_invalidation_closures()
                 .build(),
+            state,
+        };
+        tokio::spawn(Self::invalidator(
+            me.state.sushi.subscribe(),
+            me.cache_sessions.clone(),
+            me.cache_tokens.clone(),
+        ));
+        me
+    }
+
+    /// drop cached sessions when they're changed or deleted on any node
+    async fn invalidator(
+        mut rx: Receiver<SyncEvent>,
+        cache_sessions: Cache<SessionId, Session>,
+        cache_tokens: Cache<SessionToken, Session>,
+    ) {
+        loop {
+            let session_id = match rx.recv().await {
+                Ok(event) => match event.msg {
+                    MessageSync::SessionUpdate { session } => session.id,
+                    MessageSync::SessionDelete { id, .. } => id,
+                    _ => continue,
+                },
+                Err(RecvError::Lagged(_)) => {
+                    cache_sessions.invalidate_all();
+                    cache_tokens.invalidate_all();
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            cache_sessions.invalidate(&session_id).await;
+            let _ = cache_tokens.invalidate_entries_if(move |_, s| s.id == session_id);
         }
     }
 
@@ -34,6 +66,7 @@ impl ServiceSessions {
             .map_err(|err| err.fake_clone())
     }
 
+    /// get a session by its token, failing if its user is suspended
     pub async fn get_by_token(&self, token: SessionToken) -> Result<Session> {
         let s = self
             .cache_tokens
@@ -41,6 +74,12 @@ impl ServiceSessions {
             .await
             .map_err(|err| err.fake_clone())?;
         // self.cache_sessions.insert(s.id, s.clone()).await;
+        if let Some(user_id) = s.user_id() {
+            let user = self.state.services().users.get(user_id).await?;
+            if user.suspended.as_ref().is_some_and(Suspended::is_active) {
+                return Err(Error::Suspended);
+            }
+        }
         Ok(s)
     }
 
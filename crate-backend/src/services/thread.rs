@@ -2,13 +2,14 @@ use std::sync::Arc;
 
 use common::v1::types::util::Diff;
 use common::v1::types::{
-    MessageSync, MessageThreadUpdate, MessageType, Permission, Thread, ThreadId, ThreadPatch,
-    ThreadPrivate, UserId,
+    Mentions, MessageSync, MessageThreadUpdate, MessageType, Permission, RoomId, TagId, Thread,
+    ThreadId, ThreadPatch, ThreadPrivate, UserId,
 };
 use moka::future::Cache;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
 
 use crate::error::{Error, Result};
-use crate::types::DbMessageCreate;
+use crate::types::{DbMessageCreate, SyncEvent};
 use crate::ServerStateInner;
 
 pub struct ServiceThreads {
@@ -20,7 +21,7 @@ pub struct ServiceThreads {
 
 impl ServiceThreads {
     pub fn new(state: Arc<ServerStateInner>) -> Self {
-        Self {
+        let me = Self {
             state,
             cache_thread: Cache::builder()
                 .max_capacity(100_000)
@@ -30,6 +31,42 @@ impl ServiceThreads {
                 .max_capacity(100_000)
                 .support_invalidation_closures()
                 .build(),
+        };
+        tokio::spawn(Self::invalidator(
+            me.state.sushi.subscribe(),
+            me.cache_thread.clone(),
+            me.cache_thread_private.clone(),
+        ));
+        me
+    }
+
+    /// drop cached threads when they're changed on any node
+    async fn invalidator(
+        mut rx: Receiver<SyncEvent>,
+        cache_thread: Cache<ThreadId, Thread>,
+        cache_thread_private: Cache<(ThreadId, UserId), ThreadPrivate>,
+    ) {
+        loop {
+            match rx.recv().await {
+                Ok(event) => match event.msg {
+                    MessageSync::ThreadUpdate { thread } => {
+                        let thread_id = thread.id;
+                        cache_thread.invalidate(&thread_id).await;
+                        let _ = cache_thread_private
+                            .invalidate_entries_if(move |(t, _), _| *t == thread_id);
+                    }
+                    MessageSync::UserDelete { id } => {
+                        let _ =
+                            cache_thread_private.invalidate_entries_if(move |(_, u), _| *u == id);
+                    }
+                    _ => {}
+                },
+                Err(RecvError::Lagged(_)) => {
+                    cache_thread.invalidate_all();
+                    cache_thread_private.invalidate_all();
+                }
+                Err(RecvError::Closed) => break,
+            }
         }
     }
 
@@ -62,12 +99,44 @@ impl ServiceThreads {
             .expect("failed to invalidate");
     }
 
+    /// invalidate every cached thread in a room
+    pub fn invalidate_room(&self, room_id: RoomId) {
+        self.cache_thread
+            .invalidate_entries_if(move |_, thread| thread.room_id == Some(room_id))
+            .expect("failed to invalidate");
+    }
+
     pub async fn invalidate_user(&self, thread_id: ThreadId, user_id: UserId) {
         self.cache_thread_private
             .invalidate(&(thread_id, user_id))
             .await
     }
 
+    /// ensure that these tags can be applied to a thread in this room
+    ///
+    /// tags in `existing` are already applied, so they're allowed even if archived
+    pub async fn check_tags(
+        &self,
+        room_id: Option<RoomId>,
+        tag_ids: &[TagId],
+        existing: &[TagId],
+    ) -> Result<()> {
+        let data = self.state.data();
+        for tag_id in tag_ids {
+            if existing.contains(tag_id) {
+                continue;
+            }
+            let tag = data.tag_get(*tag_id).await?;
+            if Some(tag.room_id) != room_id {
+                return Err(Error::BadStatic("tag is from a different room"));
+            }
+            if tag.is_archived {
+                return Err(Error::BadStatic("tag is archived"));
+            }
+        }
+        Ok(())
+    }
+
     pub async fn update(
         &self,
         user_id: UserId,
@@ -88,15 +157,26 @@ impl ServiceThreads {
         if thread.creator_id == user_id {
             perms.add(Permission::ThreadEdit);
         }
-        perms.ensure(Permission::ThreadEdit)?;
+        if patch.name.changes(&thread.name) || patch.description.changes(&thread.description) {
+            perms.ensure(Permission::ThreadEdit)?;
+        }
 
         // shortcut if it wont modify the thread
         if !patch.changes(&thread) {
             return Err(Error::NotModified);
         }
 
+        let tags = patch.tags.clone().filter(|tags| *tags != thread.tags);
+        if let Some(tags) = &tags {
+            perms.ensure(Permission::TagApply)?;
+            self.check_tags(thread.room_id, tags, &thread.tags).await?;
+        }
+
         // update and refetch
         data.thread_update(thread_id, patch.clone()).await?;
+        if let Some(tags) = &tags {
+            data.tag_thread_set(thread_id, tags).await?;
+        }
         self.invalidate(thread_id).await;
         self.invalidate_user(thread_id, user_id).await;
         let thread = self.get(thread_id, Some(user_id)).await?;
@@ -112,10 +192,10 @@ impl ServiceThreads {
                     patch: ThreadPatch {
                         name: patch.name,
                         description: patch.description,
-                        // tags: patch.tags,
-                        tags: None,
+                        tags,
                     },
                 }),
+                mentions: Mentions::default(),
                 edited_at: None,
                 created_at: None,
             })
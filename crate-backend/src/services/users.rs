@@ -5,10 +5,11 @@ use common::v1::types::MessageSync;
 use common::v1::types::{User, UserId};
 use dashmap::DashMap;
 use moka::future::Cache;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
 use tokio::task::JoinHandle;
 use tracing::debug;
 
-use crate::{Result, ServerStateInner};
+use crate::{types::SyncEvent, Result, ServerStateInner};
 
 // currently relies on sync heartbeat time
 const STATUS_EXPIRE: Duration = Duration::from_secs(40);
@@ -26,13 +27,37 @@ struct OnlineState {
 
 impl ServiceUsers {
     pub fn new(state: Arc<ServerStateInner>) -> Self {
-        Self {
-            state,
+        let me = Self {
             cache_users: Cache::builder()
                 .max_capacity(100_000)
                 .support_invalidation_closures()
                 .build(),
             statuses: DashMap::new(),
+            state,
+        };
+        tokio::spawn(Self::invalidator(
+            me.state.sushi.subscribe(),
+            me.cache_users.clone(),
+        ));
+        me
+    }
+
+    /// drop cached users when they're changed or deleted on any node
+    async fn invalidator(mut rx: Receiver<SyncEvent>, cache_users: Cache<UserId, User>) {
+        loop {
+            let user_id = match rx.recv().await {
+                Ok(event) => match event.msg {
+                    MessageSync::UserUpdate { user } => user.id,
+                    MessageSync::UserDelete { id } => id,
+                    _ => continue,
+                },
+                Err(RecvError::Lagged(_)) => {
+                    cache_users.invalidate_all();
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            cache_users.invalidate(&user_id).await;
         }
     }
 
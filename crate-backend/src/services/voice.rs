@@ -0,0 +1,313 @@
+use std::{collections::BTreeSet, sync::Arc, time::Duration};
+
+use common::v1::types::{
+    thread::voice::ThreadTypeVoicePublic,
+    voice::{SignallingMessage, VoiceRegion, VoiceState},
+    Permission, Thread, ThreadId, UserId,
+};
+use uuid::Uuid;
+
+use crate::{
+    types::{DbSfu, DbVoiceModeration},
+    Error, Result, ServerStateInner,
+};
+
+/// sfus that haven't registered for this long are considered dead
+///
+/// sfus register every 10 seconds
+const SFU_MAX_AGE: Duration = Duration::from_secs(30);
+
+pub struct ServiceVoice {
+    state: Arc<ServerStateInner>,
+    http: reqwest::Client,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct SfuCommand<'a> {
+    /// the user who sent this, or None if it's from the server
+    user_id: Option<UserId>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thread: Option<&'a ThreadTypeVoicePublic>,
+
+    /// the voice permissions the user has in the thread they're joining
+    #[serde(skip_serializing_if = "Option::is_none")]
+    permissions: Option<&'a [Permission]>,
+
+    /// whether a moderator muted or deafened the user joining
+    #[serde(skip_serializing_if = "Option::is_none")]
+    moderation: Option<DbVoiceModeration>,
+
+    #[serde(flatten)]
+    inner: &'a SignallingMessage,
+}
+
+impl ServiceVoice {
+    pub fn new(state: Arc<ServerStateInner>) -> Self {
+        Self {
+            state,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// add or refresh an sfu
+    pub async fn sfu_register(&self, sfu: DbSfu) -> Result<()> {
+        self.state.data().sfu_upsert(sfu).await
+    }
+
+    /// list sfus that are alive and still have a configured token
+    async fn sfu_list(&self) -> Result<Vec<DbSfu>> {
+        let mut sfus = self.state.data().sfu_list(SFU_MAX_AGE).await?;
+        sfus.retain(|s| self.state.config.sfu_tokens.contains_key(&s.token_name));
+        Ok(sfus)
+    }
+
+    /// list every region with an sfu
+    pub async fn regions(&self) -> Result<Vec<VoiceRegion>> {
+        let sfus = self.sfu_list().await?;
+        let regions: BTreeSet<String> = sfus.into_iter().map(|s| s.region).collect();
+        Ok(regions.into_iter().map(|id| VoiceRegion { id }).collect())
+    }
+
+    pub async fn sfu_get(&self, sfu_id: Uuid) -> Result<DbSfu> {
+        let sfus = self.sfu_list().await?;
+        sfus.into_iter()
+            .find(|s| s.id == sfu_id)
+            .ok_or(Error::NotFound)
+    }
+
+    /// get the sfu hosting a voice thread's call, if there is one
+    pub async fn sfu_thread(&self, thread_id: ThreadId) -> Result<DbSfu> {
+        let sfu_id = self
+            .state
+            .data()
+            .sfu_thread_get(thread_id)
+            .await?
+            .ok_or(Error::NotFound)?;
+        self.sfu_get(sfu_id).await
+    }
+
+    /// get the sfu hosting a voice thread's call, picking one if there isn't one yet
+    ///
+    /// new calls go to the least loaded sfu, preferring ones in `region`
+    pub async fn sfu_for_thread(&self, thread_id: ThreadId, region: Option<&str>) -> Result<DbSfu> {
+        let data = self.state.data();
+        let sfus = self.sfu_list().await?;
+        let current = data.sfu_thread_get(thread_id).await?;
+        if let Some(sfu) = current.and_then(|id| sfus.iter().find(|s| s.id == id)) {
+            return Ok(sfu.clone());
+        }
+
+        let in_region: Vec<&DbSfu> = sfus
+            .iter()
+            .filter(|s| region.is_some_and(|r| s.region == r))
+            .collect();
+        let candidates = if in_region.is_empty() {
+            sfus.iter().collect()
+        } else {
+            in_region
+        };
+        let best = candidates
+            .into_iter()
+            .min_by(|a, b| a.load.total_cmp(&b.load))
+            .ok_or(Error::BadStatic("no voice servers are available"))?;
+
+        // another node may have picked an sfu at the same time
+        let sfu_id = data.sfu_thread_set(thread_id, best.id, current).await?;
+        sfus.into_iter()
+            .find(|s| s.id == sfu_id)
+            .ok_or(Error::NotFound)
+    }
+
+    /// send a signalling message from a user to an sfu
+    pub async fn sfu_send(
+        &self,
+        sfu: &DbSfu,
+        user_id: UserId,
+        payload: &SignallingMessage,
+    ) -> Result<()> {
+        self.send(
+            sfu,
+            &SfuCommand {
+                user_id: Some(user_id),
+                thread: None,
+                permissions: None,
+                moderation: None,
+                inner: payload,
+            },
+        )
+        .await
+    }
+
+    /// send a user's voice state to an sfu, along with the settings of the thread they're joining
+    ///
+    /// `permissions` limit what they can publish, see [`Self::permissions`]
+    pub async fn sfu_join(
+        &self,
+        sfu: &DbSfu,
+        user_id: UserId,
+        payload: &SignallingMessage,
+        thread: &ThreadTypeVoicePublic,
+        permissions: &[Permission],
+        moderation: DbVoiceModeration,
+    ) -> Result<()> {
+        self.send(
+            sfu,
+            &SfuCommand {
+                user_id: Some(user_id),
+                thread: Some(thread),
+                permissions: Some(permissions),
+                moderation: Some(moderation),
+                inner: payload,
+            },
+        )
+        .await
+    }
+
+    /// set what a moderator allows someone to do in a call on an sfu
+    pub async fn sfu_moderate(
+        &self,
+        sfu: &DbSfu,
+        user_id: UserId,
+        mute: bool,
+        deaf: bool,
+    ) -> Result<()> {
+        self.send(
+            sfu,
+            &SfuCommand {
+                user_id: None,
+                thread: None,
+                permissions: None,
+                moderation: None,
+                inner: &SignallingMessage::Moderate {
+                    target_id: user_id,
+                    mute,
+                    deaf,
+                },
+            },
+        )
+        .await
+    }
+
+    /// check that someone can join a voice thread, returning their voice permissions there
+    pub async fn permissions(
+        &self,
+        user_id: UserId,
+        thread_id: ThreadId,
+    ) -> Result<Vec<Permission>> {
+        let perms = self
+            .state
+            .services()
+            .perms
+            .for_thread(user_id, thread_id)
+            .await?;
+        perms.ensure_view()?;
+        perms.ensure(Permission::VoiceConnect)?;
+        let mut voice = vec![Permission::VoiceConnect];
+        if perms.has(Permission::VoiceSpeak) {
+            voice.push(Permission::VoiceSpeak);
+            if perms.has(Permission::VoicePriority) {
+                voice.push(Permission::VoicePriority);
+            }
+        }
+        if perms.has(Permission::VoiceVideo) {
+            voice.push(Permission::VoiceVideo);
+        }
+        Ok(voice)
+    }
+
+    /// get whether a moderator muted or deafened someone in a thread's calls
+    ///
+    /// moderation applies to every voice thread in a room
+    pub async fn moderation(&self, user_id: UserId, thread: &Thread) -> Result<DbVoiceModeration> {
+        match thread.room_id {
+            Some(room_id) => {
+                self.state
+                    .data()
+                    .voice_moderation_get(room_id, user_id)
+                    .await
+            }
+            None => Ok(DbVoiceModeration::default()),
+        }
+    }
+
+    /// check that there's room for someone in a voice thread's call
+    ///
+    /// the sfu enforces the limit too, since joins can race with this check
+    pub async fn ensure_capacity(
+        &self,
+        sfu: &DbSfu,
+        user_id: UserId,
+        thread_id: ThreadId,
+        thread: &ThreadTypeVoicePublic,
+    ) -> Result<()> {
+        let members = self.sfu_thread_members(sfu, thread_id).await?;
+        let joined = members.iter().any(|m| m.user_id == user_id);
+        if !joined && members.len() as u64 >= thread.user_limit {
+            return Err(Error::BadStatic("this voice thread is full"));
+        }
+        Ok(())
+    }
+
+    /// get someone's voice state from an sfu
+    pub async fn sfu_voice_state(&self, sfu: &DbSfu, user_id: UserId) -> Result<VoiceState> {
+        self.get(sfu, &format!("voice-state/{user_id}")).await
+    }
+
+    /// get everyone in a voice thread's call from an sfu
+    pub async fn sfu_thread_members(
+        &self,
+        sfu: &DbSfu,
+        thread_id: ThreadId,
+    ) -> Result<Vec<VoiceState>> {
+        self.get(sfu, &format!("thread/{thread_id}/voice-state"))
+            .await
+    }
+
+    /// the authorization header to send to an sfu, if its token is still configured
+    fn auth(&self, sfu: &DbSfu) -> Option<String> {
+        let token = self.state.config.sfu_tokens.get(&sfu.token_name)?;
+        Some(format!("Server {token}"))
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(&self, sfu: &DbSfu, path: &str) -> Result<T> {
+        let res = self
+            .http
+            .get(format!("{}/{path}", sfu.url.trim_end_matches('/')))
+            .header("authorization", self.auth(sfu).ok_or(Error::NotFound)?)
+            .send()
+            .await?;
+        if res.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(Error::NotFound);
+        }
+        if !res.status().is_success() {
+            return Err(Error::GenericError(format!(
+                "{} {}",
+                res.status(),
+                res.text().await?
+            )));
+        }
+        Ok(res.json().await?)
+    }
+
+    async fn send(&self, sfu: &DbSfu, command: &SfuCommand<'_>) -> Result<()> {
+        let res = self
+            .http
+            .post(format!("{}/rpc", sfu.url.trim_end_matches('/')))
+            .header("authorization", self.auth(sfu).ok_or(Error::NotFound)?)
+            .json(command)
+            .send()
+            .await?;
+        if res.status() == reqwest::StatusCode::CONFLICT {
+            return Err(Error::BadStatic("this voice thread is full"));
+        }
+        if !res.status().is_success() {
+            return Err(Error::GenericError(format!(
+                "{} {}",
+                res.status(),
+                res.text().await?
+            )));
+        }
+        Ok(())
+    }
+}
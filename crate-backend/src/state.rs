@@ -1,31 +1,36 @@
 use std::{
     ops::Deref,
     sync::{Arc, Weak},
+    time::Duration,
 };
 
 use common::v1::types::{Media, Message, RoomId, ThreadId, UserId};
 use common::v1::types::{MessageSync, MessageType};
-use dashmap::DashMap;
 
-use sqlx::PgPool;
 use tokio::sync::broadcast::Sender;
+use tracing::warn;
 use url::Url;
 
 use crate::{
-    config::Config,
-    data::{postgres::Postgres, Data},
+    bus::{EventBus, EventBusLocal, EventBusPostgres},
+    config::{Config, ConfigEventBus},
+    data::{Data, Database},
     services::Services,
-    sync::Connection,
+    types::SyncEvent,
     Result,
 };
 
 pub struct ServerStateInner {
     pub config: Config,
-    pub pool: PgPool,
+    pub db: Database,
     pub services: Weak<Services>,
 
     // this is fine probably
-    pub sushi: Sender<MessageSync>,
+    /// events from every node, to be sent to clients
+    pub sushi: Sender<SyncEvent>,
+
+    /// publishes events to every node's sushi
+    pub bus: Box<dyn EventBus>,
     // channel_user: Arc<DashMap<UserId, (Sender<MessageServer>, Receiver<MessageServer>)>>,
 
     // TODO: write a wrapper around this
@@ -39,16 +44,11 @@ pub struct ServerStateInner {
 pub struct ServerState {
     pub inner: Arc<ServerStateInner>,
     pub services: Arc<Services>,
-
-    // TODO: limit number of connections per user, clean up old/unused entries
-    pub syncers: Arc<DashMap<String, Connection>>,
 }
 
 impl ServerStateInner {
     pub fn data(&self) -> Box<dyn Data> {
-        Box::new(Postgres {
-            pool: self.pool.clone(),
-        })
+        self.db.data()
     }
 
     pub fn services(&self) -> Arc<Services> {
@@ -75,7 +75,7 @@ impl ServerStateInner {
                 .audit_logs_room_append(room_id, user_id, reason, msg.clone())
                 .await?;
         }
-        let _ = self.sushi.send(msg);
+        self.publish(msg);
         Ok(())
     }
 
@@ -96,16 +96,21 @@ impl ServerStateInner {
                 self.broadcast_room(room_id, user_id, reason, msg).await?;
             }
         } else {
-            let _ = self.sushi.send(msg);
+            self.publish(msg);
         }
         Ok(())
     }
 
     pub fn broadcast(&self, msg: MessageSync) -> Result<()> {
-        let _ = self.sushi.send(msg);
+        self.publish(msg);
         Ok(())
     }
 
+    fn publish(&self, msg: MessageSync) {
+        self.services().notifications.handle(&msg);
+        self.bus.publish(msg);
+    }
+
     pub fn get_s3_url(&self, path: &str) -> Result<Url> {
         let mut u = Url::parse("s3://")?;
         u.set_host(Some(&self.config.s3.bucket))?;
@@ -161,24 +166,35 @@ impl ServerStateInner {
 }
 
 impl ServerState {
-    pub fn new(config: Config, pool: PgPool, blobs: opendal::Operator) -> Self {
+    pub fn new(config: Config, db: Database, blobs: opendal::Operator) -> Self {
         // a bit hacky for now since i need to work around the existing ServerState
         // though i probably need some way to access global state/services from within them anyways
+        // maybe i should increase the limit at some point? or make it unlimited?
+        let sushi = tokio::sync::broadcast::channel(100).0;
+        let max_age = Duration::from_secs(config.sync_log_max_age_hours * 60 * 60);
+        let bus: Box<dyn EventBus> = match config.event_bus {
+            ConfigEventBus::Local => Box::new(EventBusLocal::new(&db, sushi.clone(), max_age)),
+            ConfigEventBus::Postgres => match db.pool() {
+                Some(pool) => Box::new(EventBusPostgres::new(pool.clone(), sushi.clone(), max_age)),
+                None => {
+                    warn!("the postgres event bus needs a postgres database, only sending events to this node");
+                    Box::new(EventBusLocal::new(&db, sushi.clone(), max_age))
+                }
+            },
+        };
         let services = Arc::new_cyclic(|weak| {
             let inner = Arc::new(ServerStateInner {
                 config,
-                pool,
+                db,
                 services: weak.to_owned(),
                 blobs,
-
-                // maybe i should increase the limit at some point? or make it unlimited?
-                sushi: tokio::sync::broadcast::channel(100).0,
+                sushi,
+                bus,
             });
             Services::new(inner.clone())
         });
         Self {
             inner: services.state.clone(),
-            syncers: Arc::new(DashMap::new()),
             // channel_user: Arc::new(DashMap::new()),
             services,
         }
@@ -1,27 +1,40 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::sync::Arc;
 use std::time::Duration;
-use std::{collections::VecDeque, sync::Arc};
 
 use axum::extract::ws::{Message, WebSocket};
 use common::v1::types;
-use common::v1::types::emoji::EmojiOwner;
+use common::v1::types::emoji::{Emoji, EmojiOwner};
+use common::v1::types::reaction::ReactionCounts;
 use common::v1::types::user_status::Status;
-use common::v1::types::voice::SignallingMessage;
+use common::v1::types::voice::{SignallingMessage, VoiceStateUpdate};
 use common::v1::types::{
-    InviteTarget, InviteTargetId, MessageClient, MessageEnvelope, MessageSync, Permission, RoomId,
-    Session, ThreadId, UserId,
+    InviteTarget, InviteTargetId, MessageClient, MessageEnvelope, MessageId, MessageSync,
+    MessageType, Permission, RoomId, Session, SyncCompression, SyncFilter, SyncFilterId,
+    SyncFormat, SyncParams, ThreadId, ThreadPublic, UserId,
 };
-use serde_json::Value;
+use flate2::{write::ZlibEncoder, Compression};
+use hashlink::LruCache;
 use tokio::time::Instant;
 use tracing::{debug, trace};
+use uuid::Uuid;
 
 use crate::error::{Error, Result};
+use crate::types::SyncEvent;
 use crate::ServerState;
 
 type WsMessage = axum::extract::ws::Message;
 
 pub const HEARTBEAT_TIME: Duration = Duration::from_secs(30);
 pub const CLOSE_TIME: Duration = Duration::from_secs(10);
-const MAX_QUEUE_LEN: usize = 256;
+
+/// how many events are read from the sync log at once
+const REPLAY_BATCH: u16 = 256;
+const MAX_FILTERS: usize = 256;
+
+/// how many rooms, threads, users, and messages a connection remembers things about
+const MAX_REMEMBERED: usize = 4096;
 
 pub enum Timeout {
     Ping(Instant),
@@ -31,17 +44,30 @@ pub enum Timeout {
 pub struct Connection {
     state: ConnectionState,
     s: Arc<ServerState>,
-    queue: VecDeque<(Option<u64>, MessageEnvelope)>,
-    seq_server: u64,
-    seq_client: u64,
+    queue: VecDeque<MessageEnvelope>,
+
+    /// the seq of the last event from the user's sync log that was handled
+    seq: u64,
+
+    encoder: Encoder,
+    visibility: Visibility,
+
+    /// None until the client adds a filter, which means every event is sent
+    filters: Option<HashMap<SyncFilterId, SyncFilter>>,
+
+    /// which room each thread is in, for SubRoomAll filters. threads never
+    /// change rooms, so this is never invalidated.
+    thread_rooms: LruCache<ThreadId, Option<RoomId>>,
     id: String,
+
+    /// the sfu hosting the voice call this connection is in
+    voice_sfu: Option<Uuid>,
 }
 
 #[derive(Debug, Clone)]
 enum ConnectionState {
     Unauthed,
     Authenticated { session: Session },
-    Disconnected { session: Session },
 }
 
 #[derive(Debug)]
@@ -50,46 +76,28 @@ enum AuthCheck {
     Room(RoomId),
     RoomOrUser(RoomId, UserId),
     ThreadOrUser(ThreadId, UserId),
+    ThreadOrThread(ThreadId, ThreadId),
     User(UserId),
     UserMutual(UserId),
     Thread(ThreadId),
 }
 
 impl Connection {
-    pub fn new(s: Arc<ServerState>) -> Self {
+    pub fn new(s: Arc<ServerState>, params: &SyncParams) -> Self {
         Self {
             state: ConnectionState::Unauthed,
             s,
+            encoder: Encoder::new(params),
+            visibility: Visibility::new(),
             queue: VecDeque::new(),
-            seq_server: 0,
-            seq_client: 0,
+            seq: 0,
+            filters: None,
+            thread_rooms: LruCache::new(MAX_REMEMBERED),
+            voice_sfu: None,
             id: format!("{}", uuid::Uuid::new_v4().hyphenated()),
         }
     }
 
-    pub fn disconnect(&mut self) {
-        // surely there's a way to do this with zero copies
-        self.state = match &self.state {
-            ConnectionState::Authenticated { session } => ConnectionState::Disconnected {
-                session: session.clone(),
-            },
-            s => s.to_owned(),
-        };
-    }
-
-    pub fn rewind(&mut self, seq: u64) -> Result<()> {
-        let is_still_valid = self
-            .queue
-            .iter()
-            .any(|(seq, _)| seq.is_some_and(|s| s <= self.seq_client));
-        if is_still_valid {
-            self.seq_client = seq;
-            Ok(())
-        } else {
-            Err(Error::BadStatic("too old"))
-        }
-    }
-
     pub async fn handle_message(
         &mut self,
         ws_msg: Message,
@@ -101,7 +109,10 @@ impl Connection {
                 let msg: MessageClient = serde_json::from_str(&utf8_bytes)?;
                 self.handle_message_client(msg, ws, timeout).await
             }
-            Message::Binary(_) => Err(Error::BadStatic("doesn't support binary sorry")),
+            Message::Binary(bytes) => {
+                let msg: MessageClient = rmp_serde::from_slice(&bytes)?;
+                self.handle_message_client(msg, ws, timeout).await
+            }
             _ => Ok(()),
         }
     }
@@ -130,31 +141,30 @@ impl Connection {
                         other => other,
                     })?;
 
-                // TODO: more forgiving reconnections
                 if let Some(r) = reconnect {
                     debug!("attempting to resume");
-                    if let Some((_, mut conn)) = self.s.syncers.remove(&r.conn) {
-                        debug!("resume conn exists");
-                        if let Some(recon_session) = conn.state.session() {
-                            debug!("resume session exists");
-                            if session.id == recon_session.id {
-                                debug!("session id matches, resuming");
-                                conn.rewind(r.seq)?;
-                                conn.push(
-                                    MessageEnvelope {
-                                        payload: types::MessagePayload::Resumed,
-                                    },
-                                    None,
-                                );
-                                std::mem::swap(self, &mut conn);
-                                return Ok(());
-                            }
-                        }
+                    let user_id = session
+                        .user_id()
+                        .ok_or(Error::BadStatic("only authenticated sessions can resume"))?;
+                    let pos = self.s.data().sync_log_position(user_id).await?;
+                    // the events after r.seq were trimmed or never existed
+                    if r.seq > pos.seq || r.seq < pos.trimmed_seq {
+                        return Err(Error::ResumeExpired);
+                    }
+                    debug!("resuming from seq {}", r.seq);
+                    self.state = ConnectionState::Authenticated { session };
+                    self.seq = r.seq;
+                    self.push(MessageEnvelope {
+                        payload: types::MessagePayload::Resumed,
+                    });
+                    while self.replay(user_id, None).await? {
+                        self.drain(ws).await?;
                     }
-                    return Err(Error::BadStatic("bad or expired reconnection info"));
+                    return Ok(());
                 }
 
                 let user = if let Some(user_id) = session.user_id() {
+                    self.seq = self.s.data().sync_log_position(user_id).await?.seq;
                     let srv = self.s.services();
                     let user = srv
                         .users
@@ -175,23 +185,18 @@ impl Connection {
                         user,
                         session: session.clone(),
                         conn: self.get_id().to_owned(),
-                        seq: 0,
+                        seq: self.seq,
                     },
                 };
 
-                ws.send(WsMessage::text(serde_json::to_string(&msg)?))
-                    .await?;
+                self.send(ws, &msg).await?;
 
-                self.seq_server += 1;
                 self.state = ConnectionState::Authenticated { session };
             }
             MessageClient::Status { status } => {
                 let session = match &self.state {
                     ConnectionState::Unauthed => return Err(Error::MissingAuth),
                     ConnectionState::Authenticated { session } => session,
-                    ConnectionState::Disconnected { .. } => {
-                        panic!("somehow recv msg while disconnected?")
-                    }
                 };
                 let srv = self.s.services();
                 let user_id = session.user_id().ok_or(Error::UnauthSession)?;
@@ -203,60 +208,183 @@ impl Connection {
                 let session = match &self.state {
                     ConnectionState::Unauthed => return Err(Error::MissingAuth),
                     ConnectionState::Authenticated { session } => session,
-                    ConnectionState::Disconnected { .. } => {
-                        panic!("somehow recv msg while disconnected?")
-                    }
                 };
                 let srv = self.s.services();
                 let user_id = session.user_id().ok_or(Error::UnauthSession)?;
                 srv.users.status_ping(user_id).await?;
                 *timeout = Timeout::Ping(Instant::now() + HEARTBEAT_TIME);
             }
-            MessageClient::VoiceDispatch { user_id, payload } => {
-                #[derive(Debug, serde::Serialize)]
-                struct Request {
-                    user_id: UserId,
-
-                    #[serde(flatten)]
-                    inner: Value,
+            MessageClient::Filter { filter } => {
+                if matches!(self.state, ConnectionState::Unauthed) {
+                    return Err(Error::MissingAuth);
                 }
+                let filters = self.filters.get_or_insert_default();
+                match filter {
+                    SyncFilter::Unsub { id } => {
+                        filters.remove(&id);
+                    }
+                    filter => {
+                        if filters.len() >= MAX_FILTERS && !filters.contains_key(filter.id()) {
+                            return Err(Error::BadStatic("too many filters"));
+                        }
+                        filters.insert(filter.id().to_owned(), filter);
+                    }
+                }
+            }
+            MessageClient::VoiceDispatch {
+                user_id: claimed_id,
+                payload,
+            } => {
+                let session = match &self.state {
+                    ConnectionState::Unauthed => return Err(Error::MissingAuth),
+                    ConnectionState::Authenticated { session } => session,
+                };
+                let user_id = session.user_id().ok_or(Error::UnauthSession)?;
+                if claimed_id != user_id {
+                    return Err(Error::BadStatic(
+                        "can't send voice messages for other users",
+                    ));
+                }
+                let payload: SignallingMessage = serde_json::from_value(payload)?;
+                let srv = self.s.services();
+                match &payload {
+                    SignallingMessage::VoiceState { state: Some(state) } => {
+                        // a rejected join is reported but leaves the connection usable
+                        if let Err(err) = self.voice_join(user_id, state, &payload).await {
+                            if !err.is_client_error() {
+                                return Err(err);
+                            }
+                            self.send(ws, &err.into()).await?;
+                        }
+                    }
+                    SignallingMessage::VoiceState { state: None } => {
+                        if let Some(sfu_id) = self.voice_sfu.take() {
+                            let sfu = srv.voice.sfu_get(sfu_id).await?;
+                            srv.voice.sfu_send(&sfu, user_id, &payload).await?;
+                        }
+                    }
+                    SignallingMessage::Have { .. } | SignallingMessage::Moderate { .. } => {
+                        return Err(Error::BadStatic("this message is sent by the server only"));
+                    }
+                    _ => {
+                        let sfu_id = self
+                            .voice_sfu
+                            .ok_or(Error::BadStatic("not connected to voice"))?;
+                        let sfu = srv.voice.sfu_get(sfu_id).await?;
+                        srv.voice.sfu_send(&sfu, user_id, &payload).await?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
 
-                let req = reqwest::Client::new()
-                    .post("http://localhost:4001/rpc")
-                    .json(&Request {
-                        user_id,
-                        inner: payload,
-                    })
-                    .send()
-                    .await?;
-                if !req.status().is_success() {
-                    return Err(Error::GenericError(format!(
-                        "{} {}",
-                        req.status(),
-                        req.text().await?
-                    )));
+    /// join a voice thread, leaving the call on any other sfu
+    async fn voice_join(
+        &mut self,
+        user_id: UserId,
+        state: &VoiceStateUpdate,
+        payload: &SignallingMessage,
+    ) -> Result<()> {
+        let srv = self.s.services();
+        let permissions = srv.voice.permissions(user_id, state.thread_id).await?;
+        let thread = srv.threads.get(state.thread_id, Some(user_id)).await?;
+        let ThreadPublic::Voice(voice) = &thread.info else {
+            return Err(Error::BadStatic("not a voice thread"));
+        };
+        let sfu = srv
+            .voice
+            .sfu_for_thread(state.thread_id, state.region.as_deref())
+            .await?;
+        srv.voice
+            .ensure_capacity(&sfu, user_id, state.thread_id, voice)
+            .await?;
+        if let Some(old_id) = self.voice_sfu.filter(|id| *id != sfu.id) {
+            // leave the call on the old sfu before joining the new one
+            match srv.voice.sfu_get(old_id).await {
+                Ok(old) => {
+                    let leave = SignallingMessage::VoiceState { state: None };
+                    srv.voice.sfu_send(&old, user_id, &leave).await?;
                 }
+                Err(Error::NotFound) => {}
+                Err(err) => return Err(err),
             }
         }
+        let moderation = srv.voice.moderation(user_id, &thread).await?;
+        srv.voice
+            .sfu_join(&sfu, user_id, payload, voice, &permissions, moderation)
+            .await?;
+        self.voice_sfu = Some(sfu.id);
         Ok(())
     }
 
-    #[tracing::instrument(level = "debug", skip(self), fields(id = self.get_id()))]
-    pub async fn queue_message(&mut self, msg: MessageSync) -> Result<()> {
+    #[tracing::instrument(level = "debug", skip(self, event), fields(id = self.get_id()))]
+    pub async fn queue_message(&mut self, event: SyncEvent) -> Result<()> {
+        let ConnectionState::Authenticated { session } = &self.state else {
+            return Ok(());
+        };
+        let user_id = session.user_id();
+        let seq = match (&event.seqs, user_id) {
+            (Some(seqs), Some(user_id)) => match seqs.get(&user_id) {
+                Some(seq) => Some(*seq),
+                // not in this user's log
+                None => return Ok(()),
+            },
+            _ => None,
+        };
+
+        if let (Some(seq), Some(user_id)) = (seq, user_id) {
+            if seq <= self.seq {
+                // already sent while replaying
+                return Ok(());
+            }
+            if seq > self.seq + 1 {
+                // events were missed, eg. if this connection fell behind
+                while self.replay(user_id, Some(seq)).await? {}
+            }
+        }
+
+        self.handle_sync(event.msg, seq).await
+    }
+
+    /// handle a batch of events from the sync log, returning true if there may be more
+    async fn replay(&mut self, user_id: UserId, until: Option<u64>) -> Result<bool> {
+        let events = self
+            .s
+            .data()
+            .sync_log_replay(user_id, self.seq, REPLAY_BATCH)
+            .await?;
+        let has_more = events.len() == REPLAY_BATCH as usize;
+        for (seq, msg) in events {
+            if until.is_some_and(|until| seq >= until) {
+                return Ok(false);
+            }
+            self.handle_sync(msg, Some(seq)).await?;
+        }
+        Ok(has_more)
+    }
+
+    /// check if this connection can see an event and queue it if so
+    async fn handle_sync(&mut self, msg: MessageSync, seq: Option<u64>) -> Result<()> {
         let mut session = match &self.state {
-            ConnectionState::Authenticated { session }
-            | ConnectionState::Disconnected { session } => session.clone(),
+            ConnectionState::Authenticated { session } => session.clone(),
             _ => return Ok(()),
         };
 
-        match &self.state {
-            ConnectionState::Disconnected { .. }
-                if self.seq_server > self.seq_client + MAX_QUEUE_LEN as u64 =>
-            {
-                self.s.syncers.remove(&self.id);
-                return Err(Error::BadStatic("expired session"));
+        // advance even if this event fails so it isn't retried forever
+        if let Some(seq) = seq {
+            self.seq = seq;
+        }
+
+        // follow moves made by moderators, so signalling goes to the right sfu
+        if let MessageSync::VoiceState {
+            user_id,
+            state: Some(state),
+        } = &msg
+        {
+            if session.user_id() == Some(*user_id) {
+                self.voice_sfu = self.s.data().sfu_thread_get(state.thread_id).await?;
             }
-            _ => {}
         }
 
         let auth_check = match &msg {
@@ -275,6 +403,8 @@ impl Connection {
             MessageSync::ThreadMemberUpsert { member } => {
                 AuthCheck::ThreadOrUser(member.thread_id, member.user_id)
             }
+            MessageSync::BanCreate { room_id, .. } => AuthCheck::Room(*room_id),
+            MessageSync::BanDelete { room_id, .. } => AuthCheck::Room(*room_id),
             MessageSync::SessionCreate {
                 session: upserted_session,
             } => {
@@ -299,6 +429,9 @@ impl Connection {
             }
             MessageSync::RoleCreate { role } => AuthCheck::Room(role.room_id),
             MessageSync::RoleUpdate { role } => AuthCheck::Room(role.room_id),
+            MessageSync::TagCreate { tag } => AuthCheck::Room(tag.room_id),
+            MessageSync::TagUpdate { tag } => AuthCheck::Room(tag.room_id),
+            MessageSync::TagDelete { room_id, .. } => AuthCheck::Room(*room_id),
             MessageSync::InviteCreate { invite } => match &invite.invite.target {
                 InviteTarget::Room { room } => AuthCheck::Room(room.id),
                 InviteTarget::Thread { thread, .. } => AuthCheck::Thread(thread.id),
@@ -330,13 +463,19 @@ impl Connection {
                 InviteTargetId::Server => unreachable!("events aren't emitted for server invites"),
             },
             MessageSync::ThreadTyping { thread_id, .. } => AuthCheck::Thread(*thread_id),
-            MessageSync::ThreadAck { .. } => todo!(),
+            MessageSync::ThreadAck { user_id, .. } => AuthCheck::User(*user_id),
             MessageSync::RelationshipUpsert { user_id, .. } => AuthCheck::User(*user_id),
             MessageSync::RelationshipDelete { user_id } => AuthCheck::User(*user_id),
             MessageSync::ReactionCreate { thread_id, .. } => AuthCheck::Thread(*thread_id),
             MessageSync::ReactionDelete { thread_id, .. } => AuthCheck::Thread(*thread_id),
             MessageSync::ReactionPurge { thread_id, .. } => AuthCheck::Thread(*thread_id),
             MessageSync::MessageDeleteBulk { thread_id, .. } => AuthCheck::Thread(*thread_id),
+            MessageSync::MessageMoveBulk {
+                source_id,
+                target_id,
+                ..
+            } => AuthCheck::ThreadOrThread(*source_id, *target_id),
+            MessageSync::MessagePinsUpdate { thread_id, .. } => AuthCheck::Thread(*thread_id),
             MessageSync::VoiceDispatch { user_id, payload } => match payload {
                 SignallingMessage::Have { thread_id, .. } => AuthCheck::Thread(*thread_id),
                 _ => AuthCheck::User(*user_id),
@@ -361,117 +500,267 @@ impl Connection {
             //     EmojiOwner::User => AuthCheck::User(emoji.creator_id),
             // },
         };
+        if let Some(user_id) = session.user_id() {
+            self.visibility.invalidate(&msg, user_id);
+        }
+        if let MessageSync::ThreadCreate { thread } | MessageSync::ThreadUpdate { thread } = &msg {
+            self.thread_rooms.insert(thread.id, thread.room_id);
+        }
+        if !self
+            .is_subscribed(&msg, &auth_check, session.user_id())
+            .await?
+        {
+            return Ok(());
+        }
         let should_send = match (session.user_id(), auth_check) {
             (Some(user_id), AuthCheck::Room(room_id)) => {
-                let perms = self.s.services().perms.for_room(user_id, room_id).await?;
-                perms.has(Permission::View)
+                self.can_view_room(user_id, room_id).await?
             }
             (Some(auth_user_id), AuthCheck::RoomOrUser(room_id, target_user_id)) => {
-                if auth_user_id == target_user_id {
-                    true
-                } else {
-                    let perms = self
-                        .s
-                        .services()
-                        .perms
-                        .for_room(auth_user_id, room_id)
-                        .await?;
-                    perms.has(Permission::View)
-                }
+                auth_user_id == target_user_id || self.can_view_room(auth_user_id, room_id).await?
             }
             (Some(user_id), AuthCheck::Thread(thread_id)) => {
-                let perms = self
-                    .s
-                    .services()
-                    .perms
-                    .for_thread(user_id, thread_id)
-                    .await?;
-                perms.has(Permission::View)
+                self.can_view_thread(user_id, thread_id).await?
             }
             (Some(auth_user_id), AuthCheck::ThreadOrUser(thread_id, target_user_id)) => {
-                if auth_user_id == target_user_id {
-                    true
-                } else {
-                    let perms = self
-                        .s
-                        .services()
-                        .perms
-                        .for_thread(auth_user_id, thread_id)
-                        .await?;
-                    perms.has(Permission::View)
-                }
+                auth_user_id == target_user_id
+                    || self.can_view_thread(auth_user_id, thread_id).await?
+            }
+            (Some(user_id), AuthCheck::ThreadOrThread(a, b)) => {
+                self.can_view_thread(user_id, a).await? || self.can_view_thread(user_id, b).await?
             }
             (Some(auth_user_id), AuthCheck::User(target_user_id)) => auth_user_id == target_user_id,
             (Some(auth_user_id), AuthCheck::UserMutual(target_user_id)) => {
-                if auth_user_id == target_user_id {
-                    true
-                } else {
-                    self.s
-                        .services()
-                        .perms
-                        .is_mutual(auth_user_id, target_user_id)
-                        .await?
-                }
+                auth_user_id == target_user_id
+                    || self.is_mutual(auth_user_id, target_user_id).await?
             }
             (_, AuthCheck::Custom(b)) => b,
             (None, _) => false,
         };
         if should_send {
-            let d = self.s.data();
-            let srv = self.s.services();
-            let msg = match msg {
-                MessageSync::ThreadCreate { thread } => MessageSync::ThreadCreate {
-                    thread: srv.threads.get(thread.id, session.user_id()).await?,
-                },
-                MessageSync::ThreadUpdate { thread } => MessageSync::ThreadUpdate {
-                    thread: srv.threads.get(thread.id, session.user_id()).await?,
-                },
-                MessageSync::MessageCreate { message } => MessageSync::MessageCreate {
-                    message: d
-                        .message_get(message.thread_id, message.id, session.user_id().unwrap())
-                        .await?,
-                },
-                MessageSync::MessageUpdate { message } => MessageSync::MessageUpdate {
-                    message: d
-                        .message_get(message.thread_id, message.id, session.user_id().unwrap())
-                        .await?,
-                },
-                m => m,
+            let msg = match self.personalize(msg, session.user_id()).await {
+                Ok(msg) => msg,
+                // the event is out of date, eg. a message that was later deleted
+                Err(Error::NotFound) => return Ok(()),
+                Err(err) => return Err(err),
             };
             self.push_sync(msg);
         }
         Ok(())
     }
 
+    async fn can_view_room(&mut self, user_id: UserId, room_id: RoomId) -> Result<bool> {
+        if let Some(can_view) = self.visibility.rooms.get(&room_id) {
+            return Ok(*can_view);
+        }
+        let can_view = match self.s.services().perms.for_room(user_id, room_id).await {
+            Ok(perms) => perms.has(Permission::View),
+            Err(Error::NotFound) => false,
+            Err(err) => return Err(err),
+        };
+        self.visibility.rooms.insert(room_id, can_view);
+        Ok(can_view)
+    }
+
+    async fn can_view_thread(&mut self, user_id: UserId, thread_id: ThreadId) -> Result<bool> {
+        if let Some(can_view) = self.visibility.threads.get(&thread_id) {
+            return Ok(*can_view);
+        }
+        let can_view = match self.s.services().perms.for_thread(user_id, thread_id).await {
+            Ok(perms) => perms.has(Permission::View),
+            // not remembered, since the thread may not have reached this node yet
+            Err(Error::NotFound) => return Ok(false),
+            Err(err) => return Err(err),
+        };
+        self.visibility.threads.insert(thread_id, can_view);
+        if !can_view {
+            self.visibility.forget_reactions(thread_id);
+        }
+        Ok(can_view)
+    }
+
+    async fn is_mutual(&mut self, user_id: UserId, other_id: UserId) -> Result<bool> {
+        if let Some(is_mutual) = self.visibility.mutuals.get(&other_id) {
+            return Ok(*is_mutual);
+        }
+        let is_mutual = self.s.services().perms.is_mutual(user_id, other_id).await?;
+        self.visibility.mutuals.insert(other_id, is_mutual);
+        Ok(is_mutual)
+    }
+
+    /// check if an event matches this connection's filters
+    async fn is_subscribed(
+        &mut self,
+        msg: &MessageSync,
+        auth_check: &AuthCheck,
+        user_id: Option<UserId>,
+    ) -> Result<bool> {
+        if self.filters.is_none() {
+            return Ok(true);
+        }
+        let subscribed = match auth_check {
+            AuthCheck::Room(room_id) => self.is_subscribed_room(*room_id),
+            AuthCheck::RoomOrUser(room_id, target_user_id) => {
+                user_id == Some(*target_user_id) || self.is_subscribed_room(*room_id)
+            }
+            AuthCheck::Thread(thread_id) => match msg {
+                // threads themselves are part of the room's state
+                MessageSync::ThreadCreate { thread } | MessageSync::ThreadUpdate { thread } => {
+                    thread
+                        .room_id
+                        .is_some_and(|room_id| self.is_subscribed_room(room_id))
+                        || self.is_subscribed_thread(*thread_id).await?
+                }
+                _ => self.is_subscribed_thread(*thread_id).await?,
+            },
+            AuthCheck::ThreadOrUser(thread_id, target_user_id) => {
+                user_id == Some(*target_user_id) || self.is_subscribed_thread(*thread_id).await?
+            }
+            AuthCheck::ThreadOrThread(a, b) => {
+                self.is_subscribed_thread(*a).await? || self.is_subscribed_thread(*b).await?
+            }
+            AuthCheck::User(_) | AuthCheck::UserMutual(_) | AuthCheck::Custom(_) => true,
+        };
+        Ok(subscribed)
+    }
+
+    fn filters(&self) -> impl Iterator<Item = &SyncFilter> {
+        self.filters.iter().flat_map(|f| f.values())
+    }
+
+    fn is_subscribed_room(&self, room_id: RoomId) -> bool {
+        self.filters().any(|f| match f {
+            SyncFilter::SubAll { .. } => true,
+            SyncFilter::SubRoom { room_id: r, .. } | SyncFilter::SubRoomAll { room_id: r, .. } => {
+                *r == room_id
+            }
+            _ => false,
+        })
+    }
+
+    async fn is_subscribed_thread(&mut self, thread_id: ThreadId) -> Result<bool> {
+        let direct = self.filters().any(|f| match f {
+            SyncFilter::SubAll { .. } => true,
+            SyncFilter::SubThread { thread_id: t, .. } => *t == thread_id,
+            _ => false,
+        });
+        if direct {
+            return Ok(true);
+        }
+        if !self
+            .filters()
+            .any(|f| matches!(f, SyncFilter::SubRoomAll { .. }))
+        {
+            return Ok(false);
+        }
+        let room_id = match self.thread_rooms.get(&thread_id) {
+            Some(room_id) => *room_id,
+            None => {
+                let room_id = match self.s.services().threads.get(thread_id, None).await {
+                    Ok(thread) => thread.room_id,
+                    // the thread is gone, or isn't visible to this node yet
+                    Err(Error::NotFound) => return Ok(false),
+                    Err(err) => return Err(err),
+                };
+                self.thread_rooms.insert(thread_id, room_id);
+                room_id
+            }
+        };
+        Ok(room_id.is_some_and(|room_id| {
+            self.filters()
+                .any(|f| matches!(f, SyncFilter::SubRoomAll { room_id: r, .. } if *r == room_id))
+        }))
+    }
+
+    /// fetch the version of an event's contents that this session should see
+    async fn personalize(
+        &mut self,
+        msg: MessageSync,
+        user_id: Option<UserId>,
+    ) -> Result<MessageSync> {
+        let srv = self.s.services();
+        let msg = match (msg, user_id) {
+            (MessageSync::ThreadCreate { thread }, _) => MessageSync::ThreadCreate {
+                thread: srv.threads.get(thread.id, user_id).await?,
+            },
+            (MessageSync::ThreadUpdate { thread }, _) => MessageSync::ThreadUpdate {
+                thread: srv.threads.get(thread.id, user_id).await?,
+            },
+            (MessageSync::MessageCreate { message }, Some(user_id)) => MessageSync::MessageCreate {
+                message: self.personalize_message(message, user_id).await?,
+            },
+            (MessageSync::MessageUpdate { message }, Some(user_id)) => MessageSync::MessageUpdate {
+                message: self.personalize_message(message, user_id).await?,
+            },
+            (m, _) => m,
+        };
+        Ok(msg)
+    }
+
+    /// set which reactions on a message are the user's own
+    ///
+    /// everything else is the same for everyone, so the database is only read
+    /// the first time the connection sees a message with reactions
+    async fn personalize_message(
+        &mut self,
+        mut message: types::Message,
+        user_id: UserId,
+    ) -> Result<types::Message> {
+        let message_id = message.id;
+        let Some(reactions) = reactions_mut(&mut message).filter(|r| !r.0.is_empty()) else {
+            return Ok(message);
+        };
+        if let Some((_, reacted)) = self.visibility.reactions.get(&message_id) {
+            for reaction in &mut reactions.0 {
+                reaction.self_reacted = reacted.contains(&reaction.key);
+            }
+            return Ok(message);
+        }
+        let mut message = self
+            .s
+            .data()
+            .message_get(message.thread_id, message.id, user_id)
+            .await?;
+        let reacted = reactions_mut(&mut message)
+            .map(|r| {
+                r.0.iter()
+                    .filter(|r| r.self_reacted)
+                    .map(|r| r.key.clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+        self.visibility
+            .reactions
+            .insert(message.id, (message.thread_id, reacted));
+        Ok(message)
+    }
+
     fn push_sync(&mut self, sync: MessageSync) {
-        let seq = self.seq_server;
         let msg = MessageEnvelope {
-            payload: types::MessagePayload::Sync { data: sync, seq },
+            payload: types::MessagePayload::Sync {
+                data: sync,
+                seq: self.seq,
+            },
         };
-        self.push(msg, Some(seq));
-        self.seq_server += 1;
+        self.push(msg);
     }
 
-    fn push(&mut self, msg: MessageEnvelope, seq: Option<u64>) {
-        self.queue.push_front((seq, msg));
-        self.queue.truncate(MAX_QUEUE_LEN);
+    fn push(&mut self, msg: MessageEnvelope) {
+        self.queue.push_back(msg);
     }
 
     #[tracing::instrument(level = "debug", skip(self, ws), fields(id = self.get_id()))]
     pub async fn drain(&mut self, ws: &mut WebSocket) -> Result<()> {
-        let last_seen = self.seq_client;
-        let mut high_water_mark = last_seen;
-        for (seq, msg) in self.queue.iter().rev() {
-            if seq.is_none_or(|s| s > last_seen) {
-                let json = serde_json::to_string(&msg)?;
-                ws.send(WsMessage::text(json)).await?;
-                if let Some(seq) = *seq {
-                    high_water_mark = high_water_mark.max(seq);
-                }
-            }
+        while let Some(msg) = self.queue.pop_front() {
+            self.send(ws, &msg).await?;
         }
-        self.seq_client = high_water_mark;
-        self.queue.retain(|(seq, _)| seq.is_some());
+        Ok(())
+    }
+
+    /// send a message immediately, skipping the queue
+    pub async fn send(&mut self, ws: &mut WebSocket, msg: &MessageEnvelope) -> Result<()> {
+        let ws_msg = self.encoder.encode(msg)?;
+        ws.send(ws_msg).await?;
         Ok(())
     }
 
@@ -480,12 +769,170 @@ impl Connection {
     }
 }
 
-impl ConnectionState {
-    pub fn session(&self) -> Option<&Session> {
-        match self {
-            ConnectionState::Unauthed => None,
-            ConnectionState::Authenticated { session } => Some(session),
-            ConnectionState::Disconnected { session } => Some(session),
+fn reactions_mut(message: &mut types::Message) -> Option<&mut ReactionCounts> {
+    match &mut message.message_type {
+        MessageType::DefaultMarkdown(m) => Some(&mut m.reactions),
+        MessageType::DefaultTagged(m) => Some(&mut m.reactions),
+        _ => None,
+    }
+}
+
+/// what a connection's user can see, so permissions don't need to be checked
+/// for every event
+///
+/// only the most recently used entries are kept
+struct Visibility {
+    /// whether the user can view each room
+    rooms: LruCache<RoomId, bool>,
+
+    /// whether the user can view each thread
+    threads: LruCache<ThreadId, bool>,
+
+    /// whether the user shares a room with each user
+    mutuals: LruCache<UserId, bool>,
+
+    /// the thread each message is in and the reactions the user added to it
+    reactions: LruCache<MessageId, (ThreadId, Vec<Emoji>)>,
+}
+
+impl Visibility {
+    fn new() -> Self {
+        Self {
+            rooms: LruCache::new(MAX_REMEMBERED),
+            threads: LruCache::new(MAX_REMEMBERED),
+            mutuals: LruCache::new(MAX_REMEMBERED),
+            reactions: LruCache::new(MAX_REMEMBERED),
+        }
+    }
+
+    /// forget anything an event may have changed
+    fn invalidate(&mut self, msg: &MessageSync, user_id: UserId) {
+        match msg {
+            MessageSync::RoomMemberUpsert { member } if member.user_id == user_id => {
+                self.invalidate_room(member.room_id);
+                self.mutuals.clear();
+            }
+            MessageSync::RoomMemberUpsert { member } => {
+                self.mutuals.remove(&member.user_id);
+            }
+            MessageSync::BanCreate {
+                room_id,
+                user_id: target_user_id,
+            }
+            | MessageSync::BanDelete {
+                room_id,
+                user_id: target_user_id,
+            } if *target_user_id == user_id => {
+                self.invalidate_room(*room_id);
+            }
+            MessageSync::ThreadMemberUpsert { member } if member.user_id == user_id => {
+                self.threads.remove(&member.thread_id);
+            }
+            // permission overwrites are sent as thread updates, and threads
+            // this node hadn't heard of yet may have been looked up already
+            MessageSync::ThreadCreate { thread } | MessageSync::ThreadUpdate { thread } => {
+                self.threads.remove(&thread.id);
+            }
+            MessageSync::RoleCreate { role } | MessageSync::RoleUpdate { role } => {
+                self.invalidate_room(role.room_id);
+            }
+            MessageSync::RoleDelete { room_id, .. } => {
+                self.invalidate_room(*room_id);
+            }
+            MessageSync::UserDelete { id } => {
+                self.mutuals.remove(id);
+            }
+            MessageSync::ReactionCreate {
+                user_id: reactor_id,
+                message_id,
+                key,
+                ..
+            } if *reactor_id == user_id => {
+                if let Some((_, reacted)) = self.reactions.get_mut(message_id) {
+                    if !reacted.contains(&key.0) {
+                        reacted.push(key.0.clone());
+                    }
+                }
+            }
+            MessageSync::ReactionDelete {
+                user_id: reactor_id,
+                message_id,
+                key,
+                ..
+            } if *reactor_id == user_id => {
+                if let Some((_, reacted)) = self.reactions.get_mut(message_id) {
+                    reacted.retain(|k| *k != key.0);
+                }
+            }
+            MessageSync::ReactionPurge { message_id, .. } => {
+                if let Some((_, reacted)) = self.reactions.get_mut(message_id) {
+                    reacted.clear();
+                }
+            }
+            MessageSync::MessageDelete { message_id, .. } => {
+                self.reactions.remove(message_id);
+            }
+            _ => {}
+        }
+    }
+
+    fn invalidate_room(&mut self, room_id: RoomId) {
+        self.rooms.remove(&room_id);
+        // threads don't remember which room they're in, but room permission changes are rare
+        self.threads.clear();
+    }
+
+    /// forget reactions in a thread the user can no longer see
+    fn forget_reactions(&mut self, thread_id: ThreadId) {
+        let message_ids: Vec<MessageId> = self
+            .reactions
+            .iter()
+            .filter(|(_, (t, _))| *t == thread_id)
+            .map(|(id, _)| *id)
+            .collect();
+        for message_id in message_ids {
+            self.reactions.remove(&message_id);
+        }
+    }
+}
+
+/// encodes messages sent to the client in the format it asked for
+struct Encoder {
+    format: SyncFormat,
+
+    /// the compression context is shared by every message on the connection
+    zlib: Option<ZlibEncoder<Vec<u8>>>,
+}
+
+impl Encoder {
+    fn new(params: &SyncParams) -> Self {
+        Self {
+            format: params.format,
+            zlib: params.compression.map(|c| match c {
+                SyncCompression::Zlib => ZlibEncoder::new(Vec::new(), Compression::default()),
+            }),
+        }
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn encode(&mut self, msg: &MessageEnvelope) -> Result<WsMessage> {
+        let bytes = match self.format {
+            SyncFormat::Json => serde_json::to_vec(msg)?,
+            SyncFormat::Msgpack => rmp_serde::to_vec_named(msg)?,
+        };
+        match &mut self.zlib {
+            Some(zlib) => {
+                zlib.write_all(&bytes)?;
+                // sync flush, so the client can decode each message as it arrives
+                zlib.flush()?;
+                Ok(WsMessage::binary(std::mem::take(zlib.get_mut())))
+            }
+            None => match self.format {
+                SyncFormat::Json => Ok(WsMessage::text(
+                    String::from_utf8(bytes).expect("json is always valid utf8"),
+                )),
+                SyncFormat::Msgpack => Ok(WsMessage::binary(bytes)),
+            },
         }
     }
 }
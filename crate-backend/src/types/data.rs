@@ -1,13 +1,18 @@
+use std::{collections::HashMap, sync::Arc};
+
 use common::v1::types::{
+    moderation::{ReportDestination, ReportStatus, ReportTarget},
+    notifications::{NotificationReason, NotifsGlobal, NotifsRoom, NotifsThread},
     thread::{
         chat::{ThreadTypeChatPrivate, ThreadTypeChatPublic},
+        report::{ThreadTypeReportPrivate, ThreadTypeReportPublic},
         voice::{ThreadTypeVoicePrivate, ThreadTypeVoicePublic},
     },
     util::Time,
-    Bot, Embed, MediaId, MessageId, MessageType, MessageVerId, Permission, Puppet, Role, RoleId,
-    RoleVerId, Room, RoomId, RoomMembership, RoomType, Session, SessionStatus, SessionToken,
-    Thread, ThreadId, ThreadMembership, ThreadPrivate, ThreadPublic, ThreadTypeForumPublic,
-    ThreadVerId, UserId,
+    Bot, Embed, MediaCreate, MediaId, Mentions, MessageId, MessageSync, MessageType, MessageVerId,
+    Permission, Puppet, Role, RoleId, RoleVerId, Room, RoomId, RoomMembership, RoomType, Session,
+    SessionStatus, SessionToken, Thread, ThreadId, ThreadMembership, ThreadPrivate, ThreadPublic,
+    ThreadTypeForumPublic, ThreadVerId, UserId,
 };
 use serde::{Deserialize, Serialize};
 use time::PrimitiveDateTime;
@@ -23,6 +28,7 @@ pub struct DbRoom {
     pub dm_uid_a: Option<Uuid>,
     pub dm_uid_b: Option<Uuid>,
     pub icon: Option<Uuid>,
+    pub owner_id: Option<Uuid>,
 }
 
 pub struct DbUserCreate {
@@ -34,7 +40,7 @@ pub struct DbUserCreate {
     pub registered_at: Option<Time>,
 }
 
-#[derive(sqlx::Type)]
+#[derive(Debug, Clone, Copy, sqlx::Type, PartialEq, Eq)]
 #[sqlx(type_name = "membership")]
 pub enum DbMembership {
     Join,
@@ -42,6 +48,15 @@ pub enum DbMembership {
     Ban,
 }
 
+#[derive(sqlx::Type)]
+#[sqlx(type_name = "notification_reason")]
+pub enum DbNotificationReason {
+    Mention,
+    MentionBulk,
+    Reply,
+    New,
+}
+
 #[derive(sqlx::Type)]
 #[sqlx(type_name = "room_type")]
 pub enum DbRoomType {
@@ -65,6 +80,7 @@ impl From<DbRoom> for Room {
             } else {
                 RoomType::Default
             },
+            owner_id: row.owner_id.map(Into::into),
 
             // FIXME: add to db or calculate
             member_count: Default::default(),
@@ -87,6 +103,17 @@ pub struct DbThread {
     pub last_version_id: MessageVerId,
     pub message_count: i64,
     pub permission_overwrites: serde_json::Value,
+    pub tags: Vec<Uuid>,
+    pub report: Option<serde_json::Value>,
+}
+
+/// extra data for report threads
+#[derive(Deserialize)]
+pub struct DbThreadReport {
+    pub target: ReportTarget,
+    pub destination: ReportDestination,
+    pub status: ReportStatus,
+    pub report_count: u64,
 }
 
 #[derive(Deserialize)]
@@ -95,6 +122,8 @@ pub struct DbThreadPrivate {
     pub ty: DbThreadType,
     pub last_read_id: Option<Uuid>,
     pub is_unread: bool,
+    pub mention_count: i64,
+    pub notifications: Option<serde_json::Value>,
 }
 
 pub struct DbThreadCreate {
@@ -105,12 +134,58 @@ pub struct DbThreadCreate {
     pub ty: DbThreadType,
 }
 
-#[derive(sqlx::Type, Deserialize)]
+#[derive(Debug, Clone, Copy, sqlx::Type, Deserialize)]
 #[sqlx(type_name = "thread_type")]
 pub enum DbThreadType {
     Chat,
     Forum,
     Voice,
+    Report,
+}
+
+#[derive(Debug, sqlx::Type)]
+#[sqlx(type_name = "report_destination")]
+pub enum DbReportDestination {
+    Room,
+    Server,
+}
+
+#[derive(Debug, sqlx::Type)]
+#[sqlx(type_name = "report_status")]
+pub enum DbReportStatus {
+    Open,
+    Duplicate,
+    Invalid,
+    Resolved,
+}
+
+impl From<ReportDestination> for DbReportDestination {
+    fn from(value: ReportDestination) -> Self {
+        match value {
+            ReportDestination::Room => DbReportDestination::Room,
+            ReportDestination::Server => DbReportDestination::Server,
+        }
+    }
+}
+
+impl From<DbReportDestination> for ReportDestination {
+    fn from(value: DbReportDestination) -> Self {
+        match value {
+            DbReportDestination::Room => ReportDestination::Room,
+            DbReportDestination::Server => ReportDestination::Server,
+        }
+    }
+}
+
+impl From<ReportStatus> for DbReportStatus {
+    fn from(value: ReportStatus) -> Self {
+        match value {
+            ReportStatus::Open => DbReportStatus::Open,
+            ReportStatus::Duplicate => DbReportStatus::Duplicate,
+            ReportStatus::Invalid => DbReportStatus::Invalid,
+            ReportStatus::Resolved => DbReportStatus::Resolved,
+        }
+    }
 }
 
 impl From<DbThread> for Thread {
@@ -131,6 +206,20 @@ impl From<DbThread> for Thread {
                 bitrate: 64000,
                 user_limit: 100,
             }),
+            DbThreadType::Report => {
+                let report: DbThreadReport = row
+                    .report
+                    .and_then(|r| serde_json::from_value(r).ok())
+                    .expect("invalid data in db!");
+                ThreadPublic::Report(ThreadTypeReportPublic {
+                    last_version_id: row.last_version_id,
+                    message_count: row.message_count.try_into().expect("count is negative?"),
+                    target: report.target,
+                    destination: report.destination,
+                    status: report.status,
+                    report_count: report.report_count,
+                })
+            }
         };
 
         Thread {
@@ -147,7 +236,7 @@ impl From<DbThread> for Thread {
             member_count: 0,
             // FIXME: calculate field
             online_count: 0,
-            tags: Default::default(),
+            tags: row.tags.into_iter().map(Into::into).collect(),
             is_locked: Default::default(),
             is_announcement: Default::default(),
             reactions: Default::default(),
@@ -213,6 +302,7 @@ pub struct DbRole {
     pub is_self_applicable: bool,
     pub is_mentionable: bool,
     pub is_default: bool,
+    pub position: i64,
 }
 
 impl From<DbRole> for Role {
@@ -227,6 +317,7 @@ impl From<DbRole> for Role {
             is_self_applicable: row.is_self_applicable,
             is_mentionable: row.is_mentionable,
             is_default: row.is_default,
+            position: row.position as u64,
         }
     }
 }
@@ -239,6 +330,7 @@ pub struct DbRoleCreate {
     pub is_self_applicable: bool,
     pub is_mentionable: bool,
     pub is_default: bool,
+    pub position: u64,
 }
 
 pub struct DbMessageCreate {
@@ -247,6 +339,7 @@ pub struct DbMessageCreate {
     pub author_id: UserId,
     pub embeds: Vec<Embed>,
     pub message_type: MessageType,
+    pub mentions: Mentions,
     pub edited_at: Option<time::PrimitiveDateTime>,
     pub created_at: Option<time::PrimitiveDateTime>,
 }
@@ -258,6 +351,10 @@ impl DbMessageCreate {
             MessageType::DefaultMarkdown(msg) => msg.content.clone(),
             MessageType::DefaultTagged(msg) => msg.content.clone(),
             MessageType::ThreadUpdate(_patch) => Some("(thread update)".to_owned()),
+            MessageType::ModerationReport(_) => Some("(report)".to_owned()),
+            MessageType::MessagePinned(_) => Some("(message pinned)".to_owned()),
+            MessageType::MessageUnpinned(_) => Some("(message unpinned)".to_owned()),
+            MessageType::MessagesMoved(_) => Some("(messages moved)".to_owned()),
             _ => None,
         }
     }
@@ -266,6 +363,10 @@ impl DbMessageCreate {
         match &self.message_type {
             MessageType::DefaultMarkdown(msg) => msg.metadata.clone(),
             MessageType::ThreadUpdate(patch) => Some(serde_json::to_value(patch).ok()?),
+            MessageType::ModerationReport(report) => Some(serde_json::to_value(report).ok()?),
+            MessageType::MessagePinned(pin) => Some(serde_json::to_value(pin).ok()?),
+            MessageType::MessageUnpinned(pin) => Some(serde_json::to_value(pin).ok()?),
+            MessageType::MessagesMoved(moved) => Some(serde_json::to_value(moved).ok()?),
             _ => None,
         }
     }
@@ -428,7 +529,7 @@ pub enum DbThreadState {
     Deleted,
 }
 
-#[derive(sqlx::Type, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, sqlx::Type, PartialEq, Eq)]
 #[sqlx(type_name = "media_link_type")]
 pub enum MediaLinkType {
     Message,
@@ -440,13 +541,14 @@ pub enum MediaLinkType {
 }
 
 // TODO: surely there's a better way than manually managing media links/references
+#[derive(Clone)]
 pub struct MediaLink {
     pub media_id: MediaId,
     pub target_id: Uuid,
     pub link_type: MediaLinkType,
 }
 
-#[derive(Debug, sqlx::FromRow)]
+#[derive(Debug, Clone, sqlx::FromRow)]
 pub struct UrlEmbedQueue {
     pub id: Uuid,
     pub message_ref: Option<serde_json::Value>,
@@ -464,7 +566,7 @@ pub struct MessageRef {
     pub thread_id: ThreadId,
 }
 
-#[derive(sqlx::FromRow)]
+#[derive(Clone, sqlx::FromRow)]
 pub struct DbEmailQueue {
     pub id: Uuid,
     pub to_addr: String,
@@ -474,22 +576,151 @@ pub struct DbEmailQueue {
     pub html_body: Option<String>,
 }
 
+/// a voice server
+#[derive(Debug, Clone)]
+pub struct DbSfu {
+    pub id: Uuid,
+
+    /// where the sfu's rpc endpoint can be reached
+    pub url: String,
+    pub region: String,
+
+    /// system load, where 1.0 means every cpu is busy
+    pub load: f32,
+
+    /// the name of the configured token the sfu authenticated with
+    pub token_name: String,
+}
+
+/// a command sent to another node's internal rpc
+#[derive(Debug, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(tag = "type")]
+pub enum NodeCommand {
+    /// download media from a url and process it. handled by media nodes.
+    MediaImport {
+        user_id: UserId,
+        media: MediaCreate,
+        max_size: u64,
+    },
+}
+
+/// what a moderator allows a room member to do in voice calls
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct DbVoiceModeration {
+    pub mute: bool,
+    pub deaf: bool,
+}
+
+/// someone who may be notified about messages in a thread
+#[derive(Debug, Clone)]
+pub struct DbNotificationRecipient {
+    pub user_id: UserId,
+
+    /// their roles in the thread's room
+    pub roles: Vec<RoleId>,
+
+    pub global: NotifsGlobal,
+    pub room: NotifsRoom,
+    pub thread: NotifsThread,
+}
+
+/// a message waiting to have notifications created for it
+#[derive(Debug, Clone)]
+pub struct DbNotificationQueue {
+    pub message_id: MessageId,
+    pub thread_id: ThreadId,
+    pub author_id: UserId,
+}
+
+#[derive(Clone)]
+pub struct DbTotp {
+    pub secret: Vec<u8>,
+    pub is_valid: bool,
+    pub last_used_step: Option<i64>,
+}
+
+/// who an event should be logged for
+#[derive(Debug, Clone, Copy)]
+pub enum SyncAudience {
+    /// every member of a room
+    Room(RoomId),
+
+    /// every member of a thread, or of the room it is in
+    Thread(ThreadId),
+
+    /// a single user
+    User(UserId),
+
+    /// a user and everyone who shares a room with them
+    UserMutual(UserId),
+}
+
+/// an event and its position in each recipient's sync log
+#[derive(Debug, Clone)]
+pub struct SyncEvent {
+    pub msg: MessageSync,
+
+    /// users without an entry here don't receive this event. this is None for
+    /// events that aren't logged, like typing and voice signalling.
+    pub seqs: Option<Arc<HashMap<UserId, u64>>>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SyncLogPosition {
+    /// the seq of the most recent event in the log
+    pub seq: u64,
+
+    /// events up to and including this seq have been removed from the log
+    pub trimmed_seq: u64,
+}
+
 impl From<DbThreadPrivate> for ThreadPrivate {
     fn from(row: DbThreadPrivate) -> Self {
+        let notifications: NotifsThread = row
+            .notifications
+            .and_then(|v| serde_json::from_value(v).ok())
+            .unwrap_or_default();
         match row.ty {
             DbThreadType::Chat => ThreadPrivate::Chat(ThreadTypeChatPrivate {
                 is_unread: row.is_unread,
                 last_read_id: row.last_read_id.map(Into::into),
-                mention_count: 0,
-                notifications: Default::default(),
+                mention_count: row.mention_count as u64,
+                notifications: notifications.clone(),
             }),
             DbThreadType::Forum => ThreadPrivate::Forum(ThreadTypeChatPrivate {
                 is_unread: row.is_unread,
                 last_read_id: row.last_read_id.map(Into::into),
-                mention_count: 0,
-                notifications: Default::default(),
+                mention_count: row.mention_count as u64,
+                notifications,
             }),
             DbThreadType::Voice => ThreadPrivate::Voice(ThreadTypeVoicePrivate {}),
+            DbThreadType::Report => ThreadPrivate::Report(ThreadTypeReportPrivate {
+                is_unread: row.is_unread,
+                last_read_id: row.last_read_id.map(Into::into),
+                mention_count: row.mention_count as u64,
+            }),
+        }
+    }
+}
+
+impl From<NotificationReason> for DbNotificationReason {
+    fn from(value: NotificationReason) -> Self {
+        match value {
+            NotificationReason::Mention => DbNotificationReason::Mention,
+            NotificationReason::MentionBulk => DbNotificationReason::MentionBulk,
+            NotificationReason::Reply => DbNotificationReason::Reply,
+            NotificationReason::New => DbNotificationReason::New,
+        }
+    }
+}
+
+impl From<DbNotificationReason> for NotificationReason {
+    fn from(value: DbNotificationReason) -> Self {
+        match value {
+            DbNotificationReason::Mention => NotificationReason::Mention,
+            DbNotificationReason::MentionBulk => NotificationReason::MentionBulk,
+            DbNotificationReason::Reply => NotificationReason::Reply,
+            DbNotificationReason::New => NotificationReason::New,
         }
     }
 }
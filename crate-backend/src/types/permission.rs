@@ -50,6 +50,16 @@ impl Permissions {
     }
 }
 
+/// how high up a member is in a room, for deciding who can moderate who
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rank {
+    /// the position of their highest role
+    Member(u64),
+
+    /// the room's owner outranks everyone
+    Owner,
+}
+
 impl FromIterator<Permission> for Permissions {
     fn from_iter<T: IntoIterator<Item = Permission>>(iter: T) -> Self {
         let mut p = HashSet::new();
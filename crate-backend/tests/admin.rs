@@ -0,0 +1,274 @@
+//! the `backend admin` commands
+
+use std::time::Duration;
+
+use backend::{
+    admin,
+    cli::{
+        AdminCommand, AdminPermsCommand, AdminSessionCommand, AdminUserCommand, ServerPermission,
+    },
+    config::ConfigEventBus,
+    data::Database,
+    types::{PaginationQuery, Permission},
+    Error, ServerState,
+};
+use common::v1::types::{RoomCreate, UserId};
+use util::{config, create_user, database_url, database_urls, login, node, node_with};
+
+mod util;
+
+/// create a node with an admin room for server permissions
+async fn node_with_admin_room(database_url: &str) -> ServerState {
+    let db = Database::connect(database_url)
+        .await
+        .expect("failed to connect to database");
+    let room = db
+        .data()
+        .room_create(
+            RoomCreate {
+                name: "admin".to_owned(),
+                description: None,
+                icon: None,
+            },
+            None,
+        )
+        .await
+        .expect("failed to create room");
+    let mut config = config(database_url);
+    config.admin_room_id = Some(room.id);
+    node_with(db, config)
+}
+
+async fn session_count(s: &ServerState, user_id: UserId) -> usize {
+    s.data()
+        .session_list(user_id, PaginationQuery::default())
+        .await
+        .expect("failed to list sessions")
+        .items
+        .len()
+}
+
+#[tokio::test]
+async fn users_can_be_suspended() {
+    let url = database_url();
+    let s = node(&url).await;
+    // a running node that has already cached the user's session
+    let other = node(&url).await;
+    let user_id = create_user(&s).await;
+    let old_token = login(&s, user_id).await;
+    other
+        .services
+        .sessions
+        .get_by_token(old_token.clone())
+        .await
+        .expect("session is valid");
+
+    let suspend = AdminUserCommand::Suspend {
+        user_id,
+        reason: "spam".to_owned(),
+        until: None,
+    };
+    admin::run(&s, AdminCommand::User { command: suspend })
+        .await
+        .expect("failed to suspend");
+    let user = s.data().user_get(user_id).await.expect("user exists");
+    let suspended = user.suspended.expect("user should be suspended");
+    assert_eq!(suspended.reason, "spam");
+    assert_eq!(suspended.until, None);
+    assert_eq!(session_count(&s, user_id).await, 0);
+    // the other node drops the revoked session once it hears about it
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while other
+            .services
+            .sessions
+            .get_by_token(old_token.clone())
+            .await
+            .is_ok()
+        {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("revoked session still works");
+    let token = login(&s, user_id).await;
+    assert!(matches!(
+        s.services.sessions.get_by_token(token.clone()).await,
+        Err(Error::Suspended)
+    ));
+
+    let unsuspend = AdminUserCommand::Unsuspend { user_id };
+    admin::run(&s, AdminCommand::User { command: unsuspend })
+        .await
+        .expect("failed to unsuspend");
+    let user = s.data().user_get(user_id).await.expect("user exists");
+    assert!(user.suspended.is_none());
+    // cached users are refreshed once the update reaches this node
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while s
+            .services
+            .sessions
+            .get_by_token(token.clone())
+            .await
+            .is_err()
+        {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("user is still suspended");
+}
+
+#[tokio::test]
+async fn server_permissions_are_granted_in_the_admin_room() {
+    let url = database_url();
+    let s = node_with_admin_room(&url).await;
+    let room_id = s.config.admin_room_id.expect("admin room is set");
+    let user_id = create_user(&s).await;
+
+    let grant = AdminPermsCommand::Grant {
+        user_id,
+        permission: ServerPermission::Admin,
+    };
+    admin::run(&s, AdminCommand::Perms { command: grant })
+        .await
+        .expect("failed to grant");
+    let perms = s
+        .data()
+        .permission_room_get(user_id, room_id)
+        .await
+        .expect("failed to get permissions");
+    assert!(perms.has(Permission::ServerAdmin));
+    assert!(s.services.perms.is_admin(user_id).await.unwrap());
+
+    let revoke = AdminPermsCommand::Revoke {
+        user_id,
+        permission: ServerPermission::Admin,
+    };
+    admin::run(&s, AdminCommand::Perms { command: revoke })
+        .await
+        .expect("failed to revoke");
+    let perms = s
+        .data()
+        .permission_room_get(user_id, room_id)
+        .await
+        .expect("failed to get permissions");
+    assert!(!perms.has(Permission::ServerAdmin));
+}
+
+#[tokio::test]
+async fn server_permission_changes_reach_running_nodes() {
+    let url = database_url();
+    let s = node_with_admin_room(&url).await;
+    // a running node that has already cached the user's permissions
+    let db = Database::connect(&url)
+        .await
+        .expect("failed to connect to database");
+    let mut other_config = config(&url);
+    other_config.admin_room_id = s.config.admin_room_id;
+    let other = node_with(db, other_config);
+    let user_id = create_user(&s).await;
+    assert!(!other.services.perms.is_admin(user_id).await.unwrap());
+
+    let grant = AdminPermsCommand::Grant {
+        user_id,
+        permission: ServerPermission::Admin,
+    };
+    admin::run(&s, AdminCommand::Perms { command: grant })
+        .await
+        .expect("failed to grant");
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !other.services.perms.is_admin(user_id).await.unwrap() {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("granted permission isn't seen");
+
+    let revoke = AdminPermsCommand::Revoke {
+        user_id,
+        permission: ServerPermission::Admin,
+    };
+    admin::run(&s, AdminCommand::Perms { command: revoke })
+        .await
+        .expect("failed to revoke");
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while other.services.perms.is_admin(user_id).await.unwrap() {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("revoked permission still works");
+}
+
+#[tokio::test]
+async fn server_permissions_need_a_server_room() {
+    for url in database_urls() {
+        let s = node(&url).await;
+        let user_id = create_user(&s).await;
+        for permission in [ServerPermission::Admin, ServerPermission::Reports] {
+            let grant = AdminPermsCommand::Grant {
+                user_id,
+                permission,
+            };
+            assert!(admin::run(&s, AdminCommand::Perms { command: grant })
+                .await
+                .is_err());
+        }
+
+        // reports are managed in the report room, not the admin room
+        let s = node_with_admin_room(&url).await;
+        let grant = AdminPermsCommand::Grant {
+            user_id: create_user(&s).await,
+            permission: ServerPermission::Reports,
+        };
+        assert!(admin::run(&s, AdminCommand::Perms { command: grant })
+            .await
+            .is_err());
+    }
+}
+
+#[tokio::test]
+async fn deleting_a_user_revokes_their_sessions() {
+    let url = database_url();
+    let s = node(&url).await;
+    let user_id = create_user(&s).await;
+
+    for _ in 0..2 {
+        let create = AdminSessionCommand::Create { user_id };
+        admin::run(&s, AdminCommand::Session { command: create })
+            .await
+            .expect("failed to create session");
+    }
+    assert_eq!(session_count(&s, user_id).await, 2);
+
+    let delete = AdminUserCommand::Delete { user_id };
+    admin::run(&s, AdminCommand::User { command: delete })
+        .await
+        .expect("failed to delete user");
+    assert_eq!(session_count(&s, user_id).await, 0);
+}
+
+#[tokio::test]
+async fn user_commands_still_apply_without_the_postgres_event_bus() {
+    for url in database_urls() {
+        let db = Database::connect(&url)
+            .await
+            .expect("failed to connect to database");
+        let mut config = config(&url);
+        config.event_bus = ConfigEventBus::Local;
+        let s = node_with(db, config);
+        let user_id = create_user(&s).await;
+
+        // running nodes won't find out, but the change is still made
+        let suspend = AdminUserCommand::Suspend {
+            user_id,
+            reason: "spam".to_owned(),
+            until: None,
+        };
+        admin::run(&s, AdminCommand::User { command: suspend })
+            .await
+            .expect("suspend works with the local event bus");
+        let user = s.data().user_get(user_id).await.expect("user exists");
+        assert!(user.suspended.is_some());
+    }
+}
@@ -0,0 +1,192 @@
+//! totp verification and sudo mode
+
+use std::{sync::Arc, time::Duration};
+
+use common::v1::types::{
+    auth::{TotpRecoveryCodes, TotpState, TotpStateWithSecret},
+    SessionToken,
+};
+use http::StatusCode;
+use serde_json::json;
+use totp_rs::{Algorithm, Secret, TOTP};
+use util::{create_user, database_urls, login, node, serve};
+
+mod util;
+
+#[tokio::test]
+async fn totp_attempts_are_limited() {
+    for url in database_urls() {
+        let s = node(&url).await;
+        let user_id = create_user(&s).await;
+        let data = s.data();
+        data.auth_totp_set(user_id, b"secret").await.unwrap();
+
+        let lockout = Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(data.auth_totp_attempt(user_id, 3, lockout).await.unwrap());
+        }
+        assert!(!data.auth_totp_attempt(user_id, 3, lockout).await.unwrap());
+
+        // the lockout only lasts until it expires, then counting starts over
+        assert!(data
+            .auth_totp_attempt(user_id, 3, Duration::ZERO)
+            .await
+            .unwrap());
+        for _ in 0..2 {
+            assert!(data.auth_totp_attempt(user_id, 3, lockout).await.unwrap());
+        }
+        assert!(!data.auth_totp_attempt(user_id, 3, lockout).await.unwrap());
+
+        // setting up totp again starts counting again
+        data.auth_totp_set(user_id, b"secret").await.unwrap();
+        for _ in 0..3 {
+            assert!(data.auth_totp_attempt(user_id, 3, lockout).await.unwrap());
+        }
+        assert!(!data.auth_totp_attempt(user_id, 3, lockout).await.unwrap());
+
+        // a successful attempt starts counting again
+        data.auth_totp_attempt_reset(user_id).await.unwrap();
+        assert!(data.auth_totp_attempt(user_id, 3, lockout).await.unwrap());
+    }
+}
+
+/// post to an auth route, returning the status and body
+async fn post(
+    http: &reqwest::Client,
+    url: String,
+    token: &SessionToken,
+    body: serde_json::Value,
+) -> (StatusCode, String) {
+    let res = http
+        .post(url)
+        .bearer_auth(&token.0)
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    (res.status(), res.text().await.unwrap())
+}
+
+#[tokio::test]
+async fn totp_can_be_enabled_and_used_for_sudo() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let user_id = create_user(&s).await;
+        let token = login(&s, user_id).await;
+        let api = serve(s.clone()).await;
+        let http = reqwest::Client::new();
+
+        // setting up totp needs sudo, which is instant until totp is enabled
+        let (status, _) = post(&http, format!("{api}/auth/totp/init"), &token, json!({})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = post(&http, format!("{api}/auth/_sudo"), &token, json!({})).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, body) = post(&http, format!("{api}/auth/totp/init"), &token, json!({})).await;
+        assert_eq!(status, StatusCode::OK);
+        let init: TotpStateWithSecret = serde_json::from_str(&body).unwrap();
+        assert!(!init.state.is_valid);
+
+        let secret = Secret::Encoded(init.secret).to_bytes().unwrap();
+        let totp = TOTP::new_unchecked(Algorithm::SHA1, 6, 1, 30, secret);
+        let code = totp.generate_current().unwrap();
+        let (status, _) = post(
+            &http,
+            format!("{api}/auth/totp"),
+            &token,
+            json!({ "code": "000000x" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, body) = post(
+            &http,
+            format!("{api}/auth/totp"),
+            &token,
+            json!({ "code": code }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let state: TotpState = serde_json::from_str(&body).unwrap();
+        assert!(state.is_valid);
+
+        // codes can't be replayed
+        let (status, _) = post(
+            &http,
+            format!("{api}/auth/totp"),
+            &token,
+            json!({ "code": code }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // and sudo can't skip totp anymore
+        let other = login(&s, user_id).await;
+        let (status, _) = post(&http, format!("{api}/auth/_sudo"), &other, json!({})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = post(&http, format!("{api}/auth/totp/init"), &other, json!({})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
+
+#[tokio::test]
+async fn totp_recovery_codes_can_only_be_used_once() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let user_id = create_user(&s).await;
+        let token = login(&s, user_id).await;
+        let api = serve(s.clone()).await;
+        let http = reqwest::Client::new();
+
+        post(&http, format!("{api}/auth/_sudo"), &token, json!({})).await;
+        let (_, body) = post(&http, format!("{api}/auth/totp/init"), &token, json!({})).await;
+        let init: TotpStateWithSecret = serde_json::from_str(&body).unwrap();
+        let secret = Secret::Encoded(init.secret).to_bytes().unwrap();
+        let totp = TOTP::new_unchecked(Algorithm::SHA1, 6, 1, 30, secret);
+        let code = totp.generate_current().unwrap();
+        let (status, _) = post(
+            &http,
+            format!("{api}/auth/totp"),
+            &token,
+            json!({ "code": code }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = post(
+            &http,
+            format!("{api}/auth/totp/recovery"),
+            &token,
+            json!({}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let codes: TotpRecoveryCodes = serde_json::from_str(&body).unwrap();
+        let recovery = &codes.items[0].code;
+
+        let other = login(&s, user_id).await;
+        let (status, _) = post(
+            &http,
+            format!("{api}/auth/totp"),
+            &other,
+            json!({ "code": recovery }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = post(
+            &http,
+            format!("{api}/auth/totp"),
+            &other,
+            json!({ "code": recovery }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // the other codes still work
+        let (status, _) = post(
+            &http,
+            format!("{api}/auth/totp"),
+            &other,
+            json!({ "code": codes.items[1].code }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
@@ -0,0 +1,320 @@
+//! banning members, and keeping banned users out of the room
+
+use std::sync::Arc;
+
+use backend::{types::DbRoleCreate, ServerState};
+use common::v1::types::{
+    InviteCode, PaginationQuery, PaginationResponse, Permission, RoleId, RoomBan, RoomCreate,
+    RoomId, RoomMembership, UserId,
+};
+use reqwest::StatusCode;
+use util::{create_user, database_urls, login, node, serve};
+use uuid::Uuid;
+
+mod util;
+
+struct Room {
+    s: Arc<ServerState>,
+    api: String,
+    http: reqwest::Client,
+    room_id: RoomId,
+    invite: InviteCode,
+}
+
+impl Room {
+    async fn create(s: Arc<ServerState>, owner: UserId) -> Room {
+        let room = s
+            .services
+            .rooms
+            .create(
+                RoomCreate {
+                    name: "bans".to_owned(),
+                    description: None,
+                    icon: None,
+                },
+                owner,
+            )
+            .await
+            .expect("failed to create room");
+        let invite = InviteCode(Uuid::new_v4().simple().to_string());
+        s.data()
+            .invite_insert_room(room.id, owner, invite.clone(), None, None)
+            .await
+            .expect("failed to create invite");
+        Room {
+            api: serve(s.clone()).await,
+            s,
+            http: reqwest::Client::new(),
+            room_id: room.id,
+            invite,
+        }
+    }
+
+    async fn join(&self, user_id: UserId) -> StatusCode {
+        let token = login(&self.s, user_id).await;
+        self.http
+            .post(format!("{}/invite/{}", self.api, self.invite.0))
+            .bearer_auth(&token.0)
+            .send()
+            .await
+            .unwrap()
+            .status()
+    }
+
+    async fn ban(&self, by: UserId, user_id: UserId) -> StatusCode {
+        let token = login(&self.s, by).await;
+        self.http
+            .put(format!("{}/room/{}/ban/{user_id}", self.api, self.room_id))
+            .bearer_auth(&token.0)
+            .json(&serde_json::json!({}))
+            .send()
+            .await
+            .unwrap()
+            .status()
+    }
+
+    async fn unban(&self, by: UserId, user_id: UserId) -> StatusCode {
+        let token = login(&self.s, by).await;
+        self.http
+            .delete(format!("{}/room/{}/ban/{user_id}", self.api, self.room_id))
+            .bearer_auth(&token.0)
+            .send()
+            .await
+            .unwrap()
+            .status()
+    }
+
+    async fn kick(&self, by: UserId, user_id: UserId) -> StatusCode {
+        let token = login(&self.s, by).await;
+        self.http
+            .delete(format!(
+                "{}/room/{}/member/{user_id}",
+                self.api, self.room_id
+            ))
+            .bearer_auth(&token.0)
+            .send()
+            .await
+            .unwrap()
+            .status()
+    }
+
+    async fn bans(&self, by: UserId) -> Vec<RoomBan> {
+        let token = login(&self.s, by).await;
+        let res: PaginationResponse<RoomBan> = self
+            .http
+            .get(format!("{}/room/{}/ban", self.api, self.room_id))
+            .bearer_auth(&token.0)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        res.items
+    }
+
+    async fn is_member(&self, user_id: UserId) -> bool {
+        self.s
+            .data()
+            .room_member_get(self.room_id, user_id)
+            .await
+            .is_ok_and(|m| matches!(m.membership, RoomMembership::Join { .. }))
+    }
+
+    /// give a member the room's moderator role
+    async fn promote(&self, user_id: UserId) {
+        let data = self.s.data();
+        let roles = data
+            .role_list(self.room_id, PaginationQuery::default())
+            .await
+            .unwrap();
+        let moderator = roles
+            .items
+            .into_iter()
+            .find(|r| r.name == "moderator")
+            .expect("rooms have a moderator role");
+        data.role_member_put(user_id, moderator.id).await.unwrap();
+        self.s
+            .services
+            .perms
+            .invalidate_room(user_id, self.room_id)
+            .await;
+    }
+}
+
+#[tokio::test]
+async fn bans_can_be_created_listed_and_removed() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let owner = create_user(&s).await;
+        let user_id = create_user(&s).await;
+        let room = Room::create(s.clone(), owner).await;
+        assert!(room.join(user_id).await.is_success());
+        assert!(room.bans(owner).await.is_empty());
+
+        assert_eq!(room.ban(owner, user_id).await, StatusCode::NO_CONTENT);
+        assert!(!room.is_member(user_id).await);
+        let bans = room.bans(owner).await;
+        assert_eq!(bans.len(), 1);
+        assert_eq!(bans[0].user_id, user_id);
+        assert_eq!(bans[0].creator_id, owner);
+        assert_eq!(bans[0].expires_at, None);
+
+        assert_eq!(room.unban(owner, user_id).await, StatusCode::NO_CONTENT);
+        assert!(room.bans(owner).await.is_empty());
+        assert_eq!(room.unban(owner, user_id).await, StatusCode::NOT_FOUND);
+    }
+}
+
+#[tokio::test]
+async fn banned_users_cant_rejoin() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let owner = create_user(&s).await;
+        let user_id = create_user(&s).await;
+        let stranger = create_user(&s).await;
+        let room = Room::create(s.clone(), owner).await;
+        assert!(room.join(user_id).await.is_success());
+
+        assert_eq!(room.ban(owner, user_id).await, StatusCode::NO_CONTENT);
+        assert_eq!(room.join(user_id).await, StatusCode::FORBIDDEN);
+        assert!(!room.is_member(user_id).await);
+
+        // users can be banned before they ever join
+        assert_eq!(room.ban(owner, stranger).await, StatusCode::NO_CONTENT);
+        assert_eq!(room.join(stranger).await, StatusCode::FORBIDDEN);
+
+        assert_eq!(room.unban(owner, user_id).await, StatusCode::NO_CONTENT);
+        assert!(room.join(user_id).await.is_success());
+        assert!(room.is_member(user_id).await);
+    }
+}
+
+#[tokio::test]
+async fn members_cant_ban_or_kick_their_peers_or_superiors() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let owner = create_user(&s).await;
+        let moderator = create_user(&s).await;
+        let other_moderator = create_user(&s).await;
+        let user_id = create_user(&s).await;
+        let room = Room::create(s.clone(), owner).await;
+        for id in [moderator, other_moderator, user_id] {
+            assert!(room.join(id).await.is_success());
+        }
+        room.promote(moderator).await;
+        room.promote(other_moderator).await;
+
+        // regular members can't ban at all
+        assert_eq!(room.ban(user_id, moderator).await, StatusCode::FORBIDDEN);
+        assert_eq!(room.ban(moderator, owner).await, StatusCode::FORBIDDEN);
+        assert_eq!(room.kick(moderator, owner).await, StatusCode::FORBIDDEN);
+        assert_eq!(
+            room.ban(moderator, other_moderator).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            room.kick(moderator, other_moderator).await,
+            StatusCode::FORBIDDEN
+        );
+        assert!(room.is_member(owner).await);
+        assert!(room.is_member(other_moderator).await);
+
+        assert_eq!(room.kick(moderator, user_id).await, StatusCode::NO_CONTENT);
+        assert!(!room.is_member(user_id).await);
+        assert_eq!(room.ban(moderator, user_id).await, StatusCode::NO_CONTENT);
+        assert_eq!(
+            room.ban(owner, other_moderator).await,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(room.bans(owner).await.len(), 2);
+    }
+}
+
+#[tokio::test]
+async fn members_are_ranked_by_their_highest_role() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let owner = create_user(&s).await;
+        let moderator = create_user(&s).await;
+        let admin = create_user(&s).await;
+        let user_id = create_user(&s).await;
+        let room = Room::create(s.clone(), owner).await;
+        for id in [moderator, admin, user_id] {
+            assert!(room.join(id).await.is_success());
+        }
+        room.promote(moderator).await;
+
+        // a low role with a permission moderators lack doesn't protect anyone
+        let data = s.data();
+        let stray = data
+            .role_create(DbRoleCreate {
+                room_id: room.room_id,
+                name: "tagger".to_owned(),
+                description: None,
+                permissions: vec![Permission::TagManage],
+                is_self_applicable: false,
+                is_mentionable: false,
+                is_default: false,
+                position: 0,
+            })
+            .await
+            .unwrap();
+        data.role_member_put(user_id, stray.id).await.unwrap();
+        assert_eq!(room.kick(moderator, user_id).await, StatusCode::NO_CONTENT);
+
+        // the owner outranks even people with the highest role
+        let roles = data
+            .role_list(room.room_id, PaginationQuery::default())
+            .await
+            .unwrap();
+        let top = roles.items.iter().map(|r| r.position).max().unwrap();
+        let admin_role = roles.items.iter().find(|r| r.position == top).unwrap();
+        data.role_member_put(admin, admin_role.id).await.unwrap();
+        assert_eq!(room.kick(admin, owner).await, StatusCode::FORBIDDEN);
+        assert_eq!(room.kick(owner, admin).await, StatusCode::NO_CONTENT);
+    }
+}
+
+#[tokio::test]
+async fn members_cant_apply_roles_as_high_as_their_own() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let owner = create_user(&s).await;
+        let moderator = create_user(&s).await;
+        let user_id = create_user(&s).await;
+        let room = Room::create(s.clone(), owner).await;
+        for id in [moderator, user_id] {
+            assert!(room.join(id).await.is_success());
+        }
+        room.promote(moderator).await;
+        let roles = s
+            .data()
+            .role_list(room.room_id, PaginationQuery::default())
+            .await
+            .unwrap();
+        let role = |name: &str| roles.items.iter().find(|r| r.name == name).unwrap().id;
+
+        let apply = |by: UserId, role_id: RoleId| {
+            let room = &room;
+            async move {
+                let token = login(&room.s, by).await;
+                room.http
+                    .put(format!(
+                        "{}/room/{}/role/{role_id}/member/{user_id}",
+                        room.api, room.room_id
+                    ))
+                    .bearer_auth(&token.0)
+                    .send()
+                    .await
+                    .unwrap()
+                    .status()
+            }
+        };
+        assert_eq!(
+            apply(moderator, role("moderator")).await,
+            StatusCode::FORBIDDEN
+        );
+        assert!(apply(owner, role("moderator")).await.is_success());
+    }
+}
@@ -0,0 +1,488 @@
+//! nodes sharing a database should receive each other's sync events
+
+use std::{collections::BTreeSet, sync::Arc, time::Duration};
+
+use backend::{
+    config::ConfigEventBus,
+    data::Database,
+    types::{DbThreadType, SyncAudience, SyncEvent},
+    ServerState,
+};
+use common::v1::types::{
+    user_config::UserConfig, util::Time, MessageSync, RoomMembership, ThreadId, UserId,
+};
+use tokio::sync::broadcast::Receiver;
+use util::{
+    config, create_thread, create_user, database_url, database_urls, login, node, node_with, serve,
+};
+use uuid::Uuid;
+
+mod util;
+
+fn user_config(user_id: UserId) -> MessageSync {
+    MessageSync::UserConfig {
+        user_id,
+        config: UserConfig::default(),
+    }
+}
+
+/// wait for a user's config event
+async fn recv_config(rx: &mut Receiver<SyncEvent>, user_id: UserId) -> SyncEvent {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Ok(event) = rx.recv().await {
+                if matches!(event.msg, MessageSync::UserConfig { user_id: id, .. } if id == user_id)
+                {
+                    break event;
+                }
+            }
+        }
+    })
+    .await
+    .expect("event wasn't delivered")
+}
+
+/// wait until every node is listening for events from `s`
+///
+/// nodes only forward events announced after they start listening
+async fn listening(s: &ServerState, rxs: &mut [&mut Receiver<SyncEvent>]) {
+    let user_id = create_user(s).await;
+    tokio::time::timeout(Duration::from_secs(5), async {
+        for rx in rxs {
+            loop {
+                s.broadcast(user_config(user_id))
+                    .expect("failed to broadcast");
+                let wait = Duration::from_millis(100);
+                if tokio::time::timeout(wait, recv_config(rx, user_id))
+                    .await
+                    .is_ok()
+                {
+                    break;
+                }
+            }
+        }
+    })
+    .await
+    .expect("nodes didn't start listening");
+}
+
+#[tokio::test]
+async fn events_reach_every_node() {
+    let database_url = database_url();
+
+    let a = node(&database_url).await;
+    let b = node(&database_url).await;
+    let mut rx_a = a.sushi.subscribe();
+    let mut rx_b = b.sushi.subscribe();
+
+    listening(&a, &mut [&mut rx_a, &mut rx_b]).await;
+
+    let user_id = create_user(&a).await;
+    a.broadcast(user_config(user_id))
+        .expect("failed to broadcast");
+
+    for rx in [&mut rx_a, &mut rx_b] {
+        let event = recv_config(rx, user_id).await;
+        let seqs = event.seqs.expect("event should be logged");
+        assert_eq!(seqs.get(&user_id), Some(&1));
+    }
+}
+
+#[tokio::test]
+async fn events_can_be_replayed_from_any_node() {
+    let database_url = database_url();
+
+    let a = node(&database_url).await;
+    let b = node(&database_url).await;
+    let mut rx_a = a.sushi.subscribe();
+    let user_id = create_user(&a).await;
+    for _ in 0..3 {
+        a.broadcast(user_config(user_id))
+            .expect("failed to broadcast");
+    }
+
+    // wait for the events to be logged
+    tokio::time::timeout(Duration::from_secs(5), async {
+        let mut count = 0;
+        while count < 3 {
+            if let Ok(event) = rx_a.recv().await {
+                if matches!(event.msg, MessageSync::UserConfig { user_id: id, .. } if id == user_id)
+                {
+                    count += 1;
+                }
+            }
+        }
+    })
+    .await
+    .expect("events weren't logged");
+
+    let data = b.data();
+    let pos = data
+        .sync_log_position(user_id)
+        .await
+        .expect("failed to get log position");
+    assert_eq!(pos.seq, 3);
+    assert_eq!(pos.trimmed_seq, 0);
+    let events = data
+        .sync_log_replay(user_id, 1, 100)
+        .await
+        .expect("failed to replay log");
+    let seqs: Vec<u64> = events.iter().map(|(seq, _)| *seq).collect();
+    assert_eq!(seqs, [2, 3]);
+}
+
+#[tokio::test]
+async fn batched_events_are_logged_in_order() {
+    use SyncAudience::User;
+
+    for url in database_urls() {
+        let s = node(&url).await;
+        let a = create_user(&s).await;
+        let b = create_user(&s).await;
+        let data = s.data();
+        let events = data
+            .sync_log_append(vec![
+                (Uuid::now_v7(), user_config(a), vec![User(a), User(b)]),
+                (Uuid::now_v7(), user_config(b), vec![User(b)]),
+                (
+                    Uuid::now_v7(),
+                    user_config(a),
+                    vec![User(a), User(b), User(a)],
+                ),
+            ])
+            .await
+            .expect("failed to log events");
+        let seqs: Vec<_> = events
+            .iter()
+            .map(|event| {
+                let seqs = event.seqs.as_ref().expect("event should be logged");
+                (seqs.get(&a).copied(), seqs.get(&b).copied())
+            })
+            .collect();
+        assert_eq!(
+            seqs,
+            [(Some(1), Some(1)), (None, Some(2)), (Some(2), Some(3))]
+        );
+
+        let replayed = data
+            .sync_log_replay(b, 1, 100)
+            .await
+            .expect("failed to replay log");
+        assert!(matches!(
+            replayed.as_slice(),
+            [(2, MessageSync::UserConfig { user_id: first, .. }), (3, MessageSync::UserConfig { user_id: second, .. })]
+                if *first == b && *second == a
+        ));
+    }
+}
+
+#[tokio::test]
+async fn failed_log_writes_are_retried() {
+    let database_url = database_url();
+    let db = Database::connect(&database_url)
+        .await
+        .expect("failed to connect to database");
+    let pool = db.pool().expect("not a postgres database").clone();
+    let mut config = config(&database_url);
+    config.event_bus = ConfigEventBus::Local;
+    let s = node_with(db, config);
+    let mut rx = s.sushi.subscribe();
+    let user_id = create_user(&s).await;
+
+    // refuse to log this user's events for a while
+    let name = format!("fail_{}", Uuid::new_v4().simple());
+    let sql = format!(
+        r#"
+        CREATE FUNCTION {name}() RETURNS trigger LANGUAGE plpgsql AS $$
+        BEGIN
+            IF new.payload->>'user_id' = '{user_id}' THEN
+                RAISE EXCEPTION 'injected failure';
+            END IF;
+            RETURN new;
+        END $$;
+        CREATE TRIGGER {name} BEFORE INSERT ON sync_event
+            FOR EACH ROW EXECUTE FUNCTION {name}();
+        "#
+    );
+    sqlx::raw_sql(&sql).execute(&pool).await.unwrap();
+    s.broadcast(user_config(user_id))
+        .expect("failed to broadcast");
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let sql = format!("DROP TRIGGER {name} ON sync_event; DROP FUNCTION {name}();");
+    sqlx::raw_sql(&sql).execute(&pool).await.unwrap();
+
+    let event = recv_config(&mut rx, user_id).await;
+    let seqs = event.seqs.expect("event should be logged");
+    assert_eq!(seqs.get(&user_id), Some(&1));
+}
+
+#[tokio::test]
+async fn ephemeral_events_dont_wait_for_the_log() {
+    let database_url = database_url();
+    let db = Database::connect(&database_url)
+        .await
+        .expect("failed to connect to database");
+    let pool = db.pool().expect("not a postgres database").clone();
+    let mut config = config(&database_url);
+    config.event_bus = ConfigEventBus::Local;
+    let s = node_with(db, config);
+    let mut rx = s.sushi.subscribe();
+    let user_id = create_user(&s).await;
+
+    // hold up logging this user's events until the typing event arrives
+    let name = format!("fail_{}", Uuid::new_v4().simple());
+    let sql = format!(
+        r#"
+        CREATE FUNCTION {name}() RETURNS trigger LANGUAGE plpgsql AS $$
+        BEGIN
+            IF new.payload->>'user_id' = '{user_id}' THEN
+                RAISE EXCEPTION 'injected failure';
+            END IF;
+            RETURN new;
+        END $$;
+        CREATE TRIGGER {name} BEFORE INSERT ON sync_event
+            FOR EACH ROW EXECUTE FUNCTION {name}();
+        "#
+    );
+    sqlx::raw_sql(&sql).execute(&pool).await.unwrap();
+    s.broadcast(user_config(user_id))
+        .expect("failed to broadcast");
+    // let the publisher start retrying the write
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let thread_id = ThreadId::new();
+    s.broadcast(MessageSync::ThreadTyping {
+        thread_id,
+        user_id,
+        until: Time::now_utc(),
+    })
+    .expect("failed to broadcast");
+    let typing = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            match rx.recv().await {
+                Ok(event) if matches!(event.msg, MessageSync::UserConfig { user_id: id, .. } if id == user_id) => {
+                    break false
+                }
+                Ok(event) if matches!(event.msg, MessageSync::ThreadTyping { thread_id: id, .. } if id == thread_id) => {
+                    break true
+                }
+                _ => {}
+            }
+        }
+    })
+    .await;
+    let sql = format!("DROP TRIGGER {name} ON sync_event; DROP FUNCTION {name}();");
+    sqlx::raw_sql(&sql).execute(&pool).await.unwrap();
+    assert_eq!(
+        typing,
+        Ok(true),
+        "typing should arrive before the logged event"
+    );
+
+    recv_config(&mut rx, user_id).await;
+}
+
+#[tokio::test]
+async fn ephemeral_events_are_not_written() {
+    let database_url = database_url();
+
+    let a = node(&database_url).await;
+    let b = node(&database_url).await;
+    let mut rx_b = b.sushi.subscribe();
+    listening(&a, &mut [&mut rx_b]).await;
+
+    let thread_id = ThreadId::new();
+    a.broadcast(MessageSync::ThreadTyping {
+        thread_id,
+        user_id: UserId::new(),
+        until: Time::now_utc(),
+    })
+    .expect("failed to broadcast");
+    let event = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Ok(event) = rx_b.recv().await {
+                if matches!(event.msg, MessageSync::ThreadTyping { thread_id: id, .. } if id == thread_id) {
+                    break event;
+                }
+            }
+        }
+    })
+    .await
+    .expect("event wasn't delivered");
+    assert!(event.seqs.is_none());
+
+    let written: i64 =
+        sqlx::query_scalar("SELECT count(*) FROM sync_event WHERE payload->>'thread_id' = $1")
+            .bind(thread_id.to_string())
+            .fetch_one(a.db.pool().expect("not a postgres database"))
+            .await
+            .unwrap();
+    assert_eq!(written, 0);
+}
+
+#[tokio::test]
+async fn events_published_while_reconnecting_are_caught_up() {
+    let database_url = database_url();
+
+    let a = node(&database_url).await;
+    let b = node(&database_url).await;
+    let mut rx_b = b.sushi.subscribe();
+    listening(&a, &mut [&mut rx_b]).await;
+
+    // disconnect every listener, including other tests' which will reconnect too
+    let pool = a.db.pool().expect("not a postgres database");
+    let terminated: i64 = sqlx::query_scalar(
+        "SELECT count(pg_terminate_backend(pid)) FROM pg_stat_activity WHERE query LIKE 'LISTEN%'",
+    )
+    .fetch_one(pool)
+    .await
+    .unwrap();
+    assert!(terminated >= 2, "both nodes should have been listening");
+    let user_id = create_user(&a).await;
+    a.broadcast(user_config(user_id))
+        .expect("failed to broadcast");
+
+    let event = recv_config(&mut rx_b, user_id).await;
+    let seqs = event.seqs.expect("event should be logged");
+    assert_eq!(seqs.get(&user_id), Some(&1));
+}
+
+#[tokio::test]
+async fn late_writes_are_listed_after_earlier_ones() {
+    for url in database_urls() {
+        let s = node(&url).await;
+        let user_id = create_user(&s).await;
+        let data = s.data();
+        // created before the event below, but written after it
+        let late = Uuid::now_v7();
+        let early = Uuid::now_v7();
+        data.sync_log_append(vec![(early, user_config(user_id), vec![])])
+            .await
+            .expect("failed to log event");
+        let pos = data
+            .sync_log_last_pos()
+            .await
+            .unwrap()
+            .expect("an event was logged");
+        data.sync_log_append(vec![(late, user_config(user_id), vec![])])
+            .await
+            .expect("failed to log event");
+
+        let after = data.sync_log_list_after(pos).await.unwrap();
+        assert!(after.contains(&late), "late write should be listed");
+        assert!(!after.contains(&early));
+    }
+}
+
+#[tokio::test]
+async fn events_are_logged_for_their_audience() {
+    use SyncAudience::{Room, Thread, User, UserMutual};
+
+    for url in database_urls() {
+        let s = node(&url).await;
+        let owner = create_user(&s).await;
+        let member = create_user(&s).await;
+        let stranger = create_user(&s).await;
+        let thread_id = create_thread(&s, owner, DbThreadType::Chat).await;
+        let data = s.data();
+        let room_id = data.thread_get(thread_id).await.unwrap().room_id.unwrap();
+        let membership = RoomMembership::Join {
+            override_name: None,
+            override_description: None,
+            roles: vec![],
+        };
+        data.room_member_put(room_id, member, membership)
+            .await
+            .unwrap();
+
+        let audiences = [
+            vec![Room(room_id)],
+            vec![Thread(thread_id)],
+            vec![User(stranger)],
+            vec![UserMutual(member)],
+            vec![User(stranger), UserMutual(stranger)],
+            vec![],
+        ];
+        let events = data
+            .sync_log_append(
+                audiences
+                    .into_iter()
+                    .map(|audience| (Uuid::now_v7(), user_config(owner), audience))
+                    .collect(),
+            )
+            .await
+            .expect("failed to log events");
+        let logged: Vec<BTreeSet<UserId>> = events
+            .iter()
+            .map(|event| {
+                let seqs = event.seqs.as_ref().expect("event should be logged");
+                seqs.keys().copied().collect()
+            })
+            .collect();
+        let everyone = BTreeSet::from([owner, member]);
+        assert_eq!(
+            logged,
+            [
+                everyone.clone(),
+                everyone.clone(),
+                BTreeSet::from([stranger]),
+                everyone,
+                BTreeSet::from([stranger]),
+                BTreeSet::new(),
+            ]
+        );
+    }
+}
+
+#[tokio::test]
+async fn permission_changes_reach_every_node() {
+    let database_url = database_url();
+
+    let a = Arc::new(node(&database_url).await);
+    let b = node(&database_url).await;
+    let mut rx_b = b.sushi.subscribe();
+    listening(&a, &mut [&mut rx_b]).await;
+
+    let owner = create_user(&a).await;
+    let member = create_user(&a).await;
+    let thread_id = create_thread(&a, owner, DbThreadType::Chat).await;
+    let data = a.data();
+    let room_id = data.thread_get(thread_id).await.unwrap().room_id.unwrap();
+    let membership = RoomMembership::Join {
+        override_name: None,
+        override_description: None,
+        roles: vec![],
+    };
+    data.room_member_put(room_id, member, membership)
+        .await
+        .unwrap();
+    data.role_apply_default(room_id, member).await.unwrap();
+
+    // cache the member's permissions on the other node
+    let perms = b.services().perms.for_thread(member, thread_id).await;
+    perms
+        .unwrap()
+        .ensure_view()
+        .expect("member should see the thread");
+
+    let api = serve(a.clone()).await;
+    let res = reqwest::Client::new()
+        .put(format!("{api}/thread/{thread_id}/permission/{member}"))
+        .bearer_auth(&login(&a, owner).await.0)
+        .json(&serde_json::json!({ "allow": [], "deny": ["View"] }))
+        .send()
+        .await
+        .unwrap();
+    assert!(res.status().is_success());
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let perms = b.services().perms.for_thread(member, thread_id).await;
+            if perms.unwrap().ensure_view().is_err() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("other node still lets the member view the thread");
+}
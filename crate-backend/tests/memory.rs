@@ -0,0 +1,143 @@
+//! the in-memory database should be usable without postgres
+
+#![cfg(feature = "memory")]
+
+use backend::{
+    data::Database,
+    types::{DbThreadType, DbUserCreate},
+    Error,
+};
+use common::v1::types::{
+    search::SearchMessageRequest, MessageCreate, MessageType, PaginationQuery, Thread, ThreadPublic,
+};
+use util::{create_thread, create_user, node};
+
+mod util;
+
+fn message_create(content: &str) -> MessageCreate {
+    serde_json::from_value(serde_json::json!({ "content": content })).expect("message is valid")
+}
+
+fn message_count(thread: &Thread) -> u64 {
+    match &thread.info {
+        ThreadPublic::Chat(chat) => chat.message_count,
+        _ => panic!("not a chat thread"),
+    }
+}
+
+#[tokio::test]
+async fn messages_can_be_sent_and_listed() {
+    let s = node("memory://").await;
+    let user_id = create_user(&s).await;
+    let thread_id = create_thread(&s, user_id, DbThreadType::Chat).await;
+
+    let message = s
+        .services
+        .messages
+        .create(thread_id, user_id, None, None, message_create("hello"))
+        .await
+        .expect("failed to create message");
+
+    let res = s
+        .data()
+        .message_list(thread_id, user_id, PaginationQuery::default())
+        .await
+        .expect("failed to list messages");
+    // the starter message and the new message
+    assert_eq!(res.total, 2);
+    let last = res.items.last().expect("message was listed");
+    assert_eq!(last.id, message.id);
+    match &last.message_type {
+        MessageType::DefaultMarkdown(m) => assert_eq!(m.content.as_deref(), Some("hello")),
+        _ => panic!("wrong message type"),
+    }
+
+    let thread = s
+        .data()
+        .thread_get(thread_id)
+        .await
+        .expect("failed to get thread");
+    assert_eq!(message_count(&thread), 2);
+}
+
+#[tokio::test]
+async fn search_only_returns_visible_messages() {
+    let s = node("memory://").await;
+    let alice = create_user(&s).await;
+    let bob = create_user(&s).await;
+    let thread_id = create_thread(&s, alice, DbThreadType::Chat).await;
+    s.services
+        .messages
+        .create(thread_id, alice, None, None, message_create("hello world"))
+        .await
+        .expect("failed to create message");
+
+    let query: SearchMessageRequest =
+        serde_json::from_value(serde_json::json!({ "query": "WORLD" })).expect("query is valid");
+    let res = s
+        .data()
+        .search_message(alice, query.clone(), PaginationQuery::default())
+        .await
+        .expect("failed to search");
+    assert_eq!(res.items.len(), 1);
+
+    let res = s
+        .data()
+        .search_message(bob, query, PaginationQuery::default())
+        .await
+        .expect("failed to search");
+    assert!(res.items.is_empty());
+}
+
+#[tokio::test]
+async fn transactions_are_replayed_after_concurrent_writes() {
+    let s = node("memory://").await;
+    let tx = s.data().begin().await.expect("failed to begin");
+    let a = create_user(&s).await;
+    let b = tx
+        .user_create(DbUserCreate {
+            parent_id: None,
+            name: "in transaction".to_owned(),
+            description: None,
+            bot: None,
+            puppet: None,
+            registered_at: None,
+        })
+        .await
+        .expect("failed to create user")
+        .id;
+    tx.commit().await.expect("failed to commit");
+
+    for user_id in [a, b] {
+        s.data()
+            .user_get(user_id)
+            .await
+            .expect("both users should exist");
+    }
+}
+
+#[tokio::test]
+async fn transactions_are_isolated_from_the_store() {
+    let s = node("memory://").await;
+    let before = create_user(&s).await;
+    let tx = s.data().begin().await.expect("failed to begin");
+    let during = create_user(&s).await;
+    tx.user_delete(before).await.expect("failed to delete user");
+
+    // the transaction and the store only see their own writes
+    assert!(matches!(tx.user_get(during).await, Err(Error::NotFound)));
+    let user = s.data().user_get(before).await.expect("user exists");
+    assert!(user.deleted_at.is_none());
+
+    tx.rollback().await.expect("failed to roll back");
+    let user = s.data().user_get(before).await.expect("user exists");
+    assert!(user.deleted_at.is_none());
+}
+
+#[tokio::test]
+async fn unknown_database_schemes_are_rejected() {
+    assert!(matches!(
+        Database::connect("sqlite://test.db").await,
+        Err(Error::Internal(_))
+    ));
+}
@@ -0,0 +1,182 @@
+//! moving messages between threads
+
+use std::{sync::Arc, time::Duration};
+
+use backend::{types::DbThreadType, Error};
+use common::v1::types::{pagination::PaginationQuery, MessageSync, MessageType};
+use reqwest::StatusCode;
+use util::{
+    create_message, create_thread, create_thread_in, create_user, database_urls, login, node, serve,
+};
+
+mod util;
+
+#[tokio::test]
+async fn messages_move_to_another_thread() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let user_id = create_user(&s).await;
+        let source = create_thread(&s, user_id, DbThreadType::Chat).await;
+        let room_id = s
+            .services
+            .threads
+            .get(source, None)
+            .await
+            .unwrap()
+            .room_id
+            .unwrap();
+        let target = create_thread_in(&s, user_id, room_id, DbThreadType::Chat).await;
+        let first = create_message(&s, source, user_id).await;
+        let second = create_message(&s, source, user_id).await;
+        let stays = create_message(&s, source, user_id).await;
+        let mut events = s.sushi.subscribe();
+
+        let api = serve(s.clone()).await;
+        let token = login(&s, user_id).await;
+        let res = reqwest::Client::new()
+            .post(format!("{api}/thread/{source}/migrate"))
+            .bearer_auth(&token.0)
+            .header("X-Reason", "off topic")
+            .json(&serde_json::json!({ "target_id": target, "start_id": first, "end_id": second }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        // messages keep their ids
+        let data = s.data();
+        for id in [first, second] {
+            assert_eq!(data.message_get(target, id, user_id).await.unwrap().id, id);
+            assert!(matches!(
+                data.message_get(source, id, user_id).await,
+                Err(Error::NotFound)
+            ));
+        }
+        data.message_get(source, stays, user_id).await.unwrap();
+
+        // one move event, then a marker in each thread
+        let (moved, markers) = tokio::time::timeout(Duration::from_secs(5), async {
+            let mut moved = None;
+            let mut markers = vec![];
+            while moved.is_none() || markers.len() < 2 {
+                match events.recv().await.unwrap().msg {
+                    MessageSync::MessageMoveBulk {
+                        source_id,
+                        target_id,
+                        message_ids,
+                    } if source_id == source => {
+                        assert_eq!(target_id, target);
+                        moved = Some(message_ids);
+                    }
+                    MessageSync::MessageCreate { message }
+                        if message.thread_id == source || message.thread_id == target =>
+                    {
+                        // the messages sent above may still be on their way
+                        let MessageType::MessagesMoved(m) = message.message_type else {
+                            continue;
+                        };
+                        assert_eq!((m.start_id, m.end_id), (first, second));
+                        assert_eq!((m.source_id, m.target_id), (source, target));
+                        assert_eq!(m.reason.as_deref(), Some("off topic"));
+                        markers.push(message.thread_id);
+                    }
+                    _ => {}
+                }
+            }
+            (moved.unwrap(), markers)
+        })
+        .await
+        .expect("move wasn't sent");
+        assert_eq!(moved, vec![first, second]);
+        assert_eq!(markers, vec![source, target]);
+
+        // the reason ends up in the audit log
+        let logs = data
+            .audit_logs_room_fetch(room_id, PaginationQuery::default())
+            .await
+            .unwrap();
+        let log = logs
+            .items
+            .iter()
+            .find(|l| matches!(*l.payload, MessageSync::MessageMoveBulk { .. }))
+            .expect("move wasn't logged");
+        assert_eq!(log.reason.as_deref(), Some("off topic"));
+    }
+}
+
+#[tokio::test]
+async fn messages_only_move_to_threads_the_user_can_move_to() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let user_id = create_user(&s).await;
+        let other_id = create_user(&s).await;
+        let source = create_thread(&s, user_id, DbThreadType::Chat).await;
+        let target = create_thread(&s, other_id, DbThreadType::Chat).await;
+        let message_id = create_message(&s, source, user_id).await;
+
+        let api = serve(s.clone()).await;
+        let token = login(&s, user_id).await;
+        let res = reqwest::Client::new()
+            .post(format!("{api}/thread/{source}/migrate"))
+            .bearer_auth(&token.0)
+            .json(&serde_json::json!({ "target_id": target, "message_ids": [message_id] }))
+            .send()
+            .await
+            .unwrap();
+        assert!(res.status().is_client_error());
+
+        // nothing moved and no markers were left behind
+        let data = s.data();
+        data.message_get(source, message_id, user_id).await.unwrap();
+        let messages = data
+            .message_list(target, other_id, PaginationQuery::default())
+            .await
+            .unwrap();
+        assert_eq!(messages.items.len(), 1, "only the starter message");
+    }
+}
+
+#[tokio::test]
+async fn moved_lists_cant_skip_messages() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let user_id = create_user(&s).await;
+        let source = create_thread(&s, user_id, DbThreadType::Chat).await;
+        let room_id = s
+            .services
+            .threads
+            .get(source, None)
+            .await
+            .unwrap()
+            .room_id
+            .unwrap();
+        let target = create_thread_in(&s, user_id, room_id, DbThreadType::Chat).await;
+        let first = create_message(&s, source, user_id).await;
+        let skipped = create_message(&s, source, user_id).await;
+        let last = create_message(&s, source, user_id).await;
+
+        let api = serve(s.clone()).await;
+        let token = login(&s, user_id).await;
+        let migrate = |message_ids: serde_json::Value| {
+            reqwest::Client::new()
+                .post(format!("{api}/thread/{source}/migrate"))
+                .bearer_auth(&token.0)
+                .json(&serde_json::json!({ "target_id": target, "message_ids": message_ids }))
+                .send()
+        };
+        let res = migrate(serde_json::json!([first, last])).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let data = s.data();
+        for id in [first, skipped, last] {
+            data.message_get(source, id, user_id).await.unwrap();
+        }
+
+        let res = migrate(serde_json::json!([last, first, skipped]))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        for id in [first, skipped, last] {
+            data.message_get(target, id, user_id).await.unwrap();
+        }
+    }
+}
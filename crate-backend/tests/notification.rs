@@ -0,0 +1,168 @@
+//! messages are added to the inbox of everyone who wants to be notified
+
+use std::time::Duration;
+
+use backend::types::{DbMessageCreate, DbThreadType};
+use common::v1::types::{
+    notifications::{NotifAction, NotifsRoom},
+    user_config::UserConfig,
+    Mentions, PaginationQuery, RoomMembership, UserId,
+};
+use util::{create_message, create_thread, create_user, database_urls, node};
+
+mod util;
+
+#[tokio::test]
+async fn tagged_messages_notify_everyone_watching_them() {
+    for url in database_urls() {
+        let s = node(&url).await;
+        let author = create_user(&s).await;
+        let watcher = create_user(&s).await;
+        let ignorer = create_user(&s).await;
+        let thread_id = create_thread(&s, author, DbThreadType::Chat).await;
+        let data = s.data();
+        let room_id = data.thread_get(thread_id).await.unwrap().room_id.unwrap();
+        for user_id in [watcher, ignorer] {
+            let membership = RoomMembership::Join {
+                override_name: None,
+                override_description: None,
+                roles: vec![],
+            };
+            data.room_member_put(room_id, user_id, membership)
+                .await
+                .unwrap();
+        }
+        let watch = NotifsRoom {
+            messages: Some(NotifAction::Watching),
+            ..Default::default()
+        };
+        data.notification_config_room_set(watcher, room_id, &watch)
+            .await
+            .unwrap();
+        // this message starts the thread, which would notify people watching threads
+        let ignore = NotifsRoom {
+            messages: Some(NotifAction::Ignore),
+            threads: Some(NotifAction::Ignore),
+            ..Default::default()
+        };
+        data.notification_config_room_set(ignorer, room_id, &ignore)
+            .await
+            .unwrap();
+
+        let recipients = data.notification_recipients(thread_id).await.unwrap();
+        let config = recipients
+            .iter()
+            .find(|r| r.user_id == ignorer)
+            .map(|r| &r.room);
+        assert_eq!(config, Some(&ignore));
+
+        let message_type = serde_json::from_value(serde_json::json!({
+            "type": "DefaultTagged",
+            "content": "hello",
+            "attachments": [],
+            "metadata": null,
+            "reply_id": null,
+            "embeds": [],
+        }))
+        .expect("message type is valid");
+        let message_id = data
+            .message_create(DbMessageCreate {
+                thread_id,
+                attachment_ids: vec![],
+                author_id: author,
+                embeds: vec![],
+                message_type,
+                mentions: Mentions::default(),
+                edited_at: None,
+                created_at: None,
+            })
+            .await
+            .unwrap();
+        // nothing wakes the worker, so it has to find the message in the queue
+        data.notification_queue_insert(thread_id, message_id, author)
+            .await
+            .unwrap();
+
+        let inbox = |user_id: UserId| {
+            let data = &data;
+            async move {
+                data.notification_list(user_id, PaginationQuery::default(), true)
+                    .await
+                    .unwrap()
+                    .items
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while inbox(watcher).await.is_empty() {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("watcher wasn't notified");
+        assert_eq!(inbox(watcher).await[0].message_id, message_id);
+        assert!(inbox(ignorer).await.is_empty());
+        assert!(inbox(author).await.is_empty());
+    }
+}
+
+#[tokio::test]
+async fn new_threads_notify_people_watching_threads() {
+    for url in database_urls() {
+        let s = node(&url).await;
+        let author = create_user(&s).await;
+        let watcher = create_user(&s).await;
+        // watches every message, to know when both have been handled
+        let control = create_user(&s).await;
+        let thread_id = create_thread(&s, author, DbThreadType::Chat).await;
+        let data = s.data();
+        let room_id = data.thread_get(thread_id).await.unwrap().room_id.unwrap();
+        for user_id in [watcher, control] {
+            let membership = RoomMembership::Join {
+                override_name: None,
+                override_description: None,
+                roles: vec![],
+            };
+            data.room_member_put(room_id, user_id, membership)
+                .await
+                .unwrap();
+        }
+        let mut config = UserConfig::default();
+        config.notifs.messages = NotifAction::Ignore;
+        config.notifs.room_public = NotifAction::Ignore;
+        config.notifs.threads = NotifAction::Watching;
+        data.user_config_set(watcher, &config).await.unwrap();
+        let watch = NotifsRoom {
+            messages: Some(NotifAction::Watching),
+            ..Default::default()
+        };
+        data.notification_config_room_set(control, room_id, &watch)
+            .await
+            .unwrap();
+
+        let first = create_message(&s, thread_id, author).await;
+        create_message(&s, thread_id, author).await;
+
+        let inbox = |user_id: UserId| {
+            let data = &data;
+            async move {
+                data.notification_list(user_id, PaginationQuery::default(), true)
+                    .await
+                    .unwrap()
+                    .items
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while inbox(control).await.len() < 2 {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("messages weren't handled");
+        let notified: Vec<_> = inbox(watcher)
+            .await
+            .into_iter()
+            .map(|n| n.message_id)
+            .collect();
+        assert_eq!(notified, [first]);
+    }
+}
@@ -0,0 +1,198 @@
+//! pinning, unpinning, and the pin limit
+
+use std::{sync::Arc, time::Duration};
+
+use backend::types::DbThreadType;
+use common::v1::types::{Message, MessageId, MessageSync, MessageType};
+use reqwest::StatusCode;
+use util::{
+    create_message, create_thread, create_user, database_url, database_urls, login, node, serve,
+};
+
+mod util;
+
+#[tokio::test]
+async fn messages_can_be_pinned_and_unpinned() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let user_id = create_user(&s).await;
+        let thread_id = create_thread(&s, user_id, DbThreadType::Chat).await;
+        let first = create_message(&s, thread_id, user_id).await;
+        let second = create_message(&s, thread_id, user_id).await;
+        let mut events = s.sushi.subscribe();
+
+        let api = serve(s.clone()).await;
+        let http = reqwest::Client::new();
+        let token = login(&s, user_id).await;
+        let pin = |message_id: MessageId| {
+            http.put(format!("{api}/thread/{thread_id}/message/{message_id}/pin"))
+                .bearer_auth(&token.0)
+                .send()
+        };
+        let pins = || async {
+            let pins: Vec<Message> = http
+                .get(format!("{api}/thread/{thread_id}/pin"))
+                .bearer_auth(&token.0)
+                .send()
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+            pins.into_iter().map(|m| m.id).collect::<Vec<_>>()
+        };
+
+        assert_eq!(pin(first).await.unwrap().status(), StatusCode::NO_CONTENT);
+        assert_eq!(pin(second).await.unwrap().status(), StatusCode::NO_CONTENT);
+        assert_eq!(pin(first).await.unwrap().status(), StatusCode::NOT_MODIFIED);
+        // new pins go first
+        assert_eq!(pins().await, vec![second, first]);
+
+        // every pin change is one event with the whole list
+        let updates = tokio::time::timeout(Duration::from_secs(5), async {
+            let mut updates = vec![];
+            while updates.len() < 2 {
+                match events.recv().await.unwrap().msg {
+                    MessageSync::MessagePinsUpdate {
+                        thread_id: id,
+                        message_ids,
+                    } if id == thread_id => updates.push(message_ids),
+                    MessageSync::MessageUpdate { message } if message.thread_id == thread_id => {
+                        panic!("pins are sent as a list")
+                    }
+                    _ => {}
+                }
+            }
+            updates
+        })
+        .await
+        .expect("pins weren't sent");
+        assert_eq!(updates, vec![vec![first], vec![second, first]]);
+
+        let res = http
+            .delete(format!("{api}/thread/{thread_id}/message/{second}/pin"))
+            .bearer_auth(&token.0)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(pins().await, vec![first]);
+        let unpinned = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let MessageSync::MessageCreate { message } = events.recv().await.unwrap().msg {
+                    if let MessageType::MessageUnpinned(pin) = message.message_type {
+                        break pin.message_id;
+                    }
+                }
+            }
+        })
+        .await
+        .expect("unpinning didn't send a message");
+        assert_eq!(unpinned, second);
+        let res = http
+            .delete(format!("{api}/thread/{thread_id}/message/{second}/pin"))
+            .bearer_auth(&token.0)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    }
+}
+
+#[tokio::test]
+async fn pins_are_limited() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let user_id = create_user(&s).await;
+        let thread_id = create_thread(&s, user_id, DbThreadType::Chat).await;
+        let data = s.data();
+        for _ in 0..50 {
+            let message_id = create_message(&s, thread_id, user_id).await;
+            assert!(data
+                .message_pin_create(thread_id, message_id, 50)
+                .await
+                .unwrap());
+        }
+
+        let message_id = create_message(&s, thread_id, user_id).await;
+        let api = serve(s.clone()).await;
+        let token = login(&s, user_id).await;
+        let res = reqwest::Client::new()
+            .put(format!("{api}/thread/{thread_id}/message/{message_id}/pin"))
+            .bearer_auth(&token.0)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let pins = data.message_pin_list(thread_id, user_id).await.unwrap();
+        assert_eq!(pins.len(), 50);
+    }
+}
+
+/// the in-memory database replays transactions on commit, so this is postgres only
+#[tokio::test]
+async fn concurrent_pins_respect_the_limit() {
+    let s = node(&database_url()).await;
+    let user_id = create_user(&s).await;
+    let thread_id = create_thread(&s, user_id, DbThreadType::Chat).await;
+    let a = create_message(&s, thread_id, user_id).await;
+    let b = create_message(&s, thread_id, user_id).await;
+
+    let (tx_a, tx_b) = (
+        s.data().begin().await.unwrap(),
+        s.data().begin().await.unwrap(),
+    );
+    let pin_a = async {
+        let pinned = tx_a.message_pin_create(thread_id, a, 1).await.unwrap();
+        tx_a.commit().await.unwrap();
+        pinned
+    };
+    let pin_b = async {
+        let pinned = tx_b.message_pin_create(thread_id, b, 1).await.unwrap();
+        tx_b.commit().await.unwrap();
+        pinned
+    };
+    let (pinned_a, pinned_b) = tokio::join!(pin_a, pin_b);
+    assert!(pinned_a != pinned_b, "exactly one pin fits");
+    let pins = s.data().message_pin_list(thread_id, user_id).await.unwrap();
+    assert_eq!(pins.len(), 1);
+}
+
+/// the in-memory database replays transactions on commit, so this is postgres only
+#[tokio::test]
+async fn reorders_wait_for_pins_being_added() {
+    let s = node(&database_url()).await;
+    let user_id = create_user(&s).await;
+    let thread_id = create_thread(&s, user_id, DbThreadType::Chat).await;
+    let a = create_message(&s, thread_id, user_id).await;
+    let b = create_message(&s, thread_id, user_id).await;
+    let c = create_message(&s, thread_id, user_id).await;
+    let data = s.data();
+    for message_id in [a, b] {
+        assert!(data
+            .message_pin_create(thread_id, message_id, 50)
+            .await
+            .unwrap());
+    }
+
+    let tx = s.data().begin().await.unwrap();
+    let pin = async {
+        assert!(tx.message_pin_create(thread_id, c, 50).await.unwrap());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        tx.commit().await.unwrap();
+    };
+    let reorder = async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        data.message_pin_reorder(thread_id, &[a, b]).await
+    };
+    let ((), reordered) = tokio::join!(pin, reorder);
+    assert!(reordered.is_err(), "the new pin was left out");
+    let pins: Vec<_> = data
+        .message_pin_list(thread_id, user_id)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|m| m.id)
+        .collect();
+    assert_eq!(pins, vec![c, b, a]);
+}
@@ -0,0 +1,295 @@
+//! reports about the same thing share one open report thread
+
+use std::sync::Arc;
+
+use backend::{
+    data::Database,
+    types::{DbThreadType, MediaLinkType},
+};
+use common::v1::types::{
+    media::{Media, MediaSize, MediaTrack, MediaTrackInfo, TrackSource},
+    moderation::{ReportDestination, ReportTarget},
+    MediaId, PaginationResponse, Permission, PermissionOverwriteType, RoomCreate, RoomId,
+    RoomMembership, ThreadId, UserId,
+};
+use reqwest::StatusCode;
+use serde_json::json;
+use util::{
+    config, create_message, create_thread, create_thread_in, create_user, database_urls, login,
+    node, node_with, serve,
+};
+use uuid::Uuid;
+
+mod util;
+
+#[tokio::test]
+async fn only_one_report_thread_is_open_per_target() {
+    for url in database_urls() {
+        let s = node(&url).await;
+        let user_id = create_user(&s).await;
+        let target = ReportTarget::User {
+            target_id: create_user(&s).await,
+        };
+        let first = create_thread(&s, user_id, DbThreadType::Report).await;
+        let second = create_thread(&s, user_id, DbThreadType::Report).await;
+
+        let data = s.data();
+        assert!(data
+            .report_thread_create(first, ReportDestination::Server, &target)
+            .await
+            .unwrap());
+        assert!(!data
+            .report_thread_create(second, ReportDestination::Server, &target)
+            .await
+            .unwrap());
+        assert_eq!(
+            data.report_thread_find(ReportDestination::Server, &target)
+                .await
+                .unwrap(),
+            Some(first),
+        );
+    }
+}
+
+#[tokio::test]
+async fn thread_lists_only_count_visible_threads() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let owner = create_user(&s).await;
+        let member = create_user(&s).await;
+        let visible = create_thread(&s, owner, DbThreadType::Chat).await;
+        let data = s.data();
+        let room_id = data.thread_get(visible).await.unwrap().room_id.unwrap();
+        let membership = RoomMembership::Join {
+            override_name: None,
+            override_description: None,
+            roles: vec![],
+        };
+        data.room_member_put(room_id, member, membership)
+            .await
+            .unwrap();
+        data.role_apply_default(room_id, member).await.unwrap();
+
+        let hidden = create_thread_in(&s, owner, room_id, DbThreadType::Chat).await;
+        data.permission_overwrite_upsert(
+            hidden,
+            *member,
+            PermissionOverwriteType::User,
+            vec![],
+            vec![Permission::View],
+        )
+        .await
+        .unwrap();
+        let report = create_thread_in(&s, owner, room_id, DbThreadType::Report).await;
+        let target = ReportTarget::User { target_id: owner };
+        data.report_thread_create(report, ReportDestination::Room, &target)
+            .await
+            .unwrap();
+        let also_visible = create_thread_in(&s, owner, room_id, DbThreadType::Chat).await;
+
+        let api = serve(s.clone()).await;
+        let http = reqwest::Client::new();
+        let list = |user_id| {
+            let http = &http;
+            let s = &s;
+            let url = format!("{api}/room/{room_id}/thread?dir=f&limit=2");
+            async move {
+                let res: PaginationResponse<serde_json::Value> = http
+                    .get(url)
+                    .bearer_auth(&login(s, user_id).await.0)
+                    .send()
+                    .await
+                    .unwrap()
+                    .json()
+                    .await
+                    .unwrap();
+                let ids: Vec<ThreadId> = res
+                    .items
+                    .iter()
+                    .map(|t| serde_json::from_value(t["id"].clone()).unwrap())
+                    .collect();
+                (ids, res.total, res.has_more)
+            }
+        };
+
+        // hidden threads don't leave gaps in pages
+        assert_eq!(list(member).await, (vec![visible, also_visible], 2, false));
+        // moderators see the reports sent to them
+        assert_eq!(list(owner).await, (vec![visible, hidden], 4, true));
+    }
+}
+
+/// add a user to a room with its default roles
+async fn join(s: &backend::ServerState, room_id: RoomId, user_id: UserId) {
+    let data = s.data();
+    let membership = RoomMembership::Join {
+        override_name: None,
+        override_description: None,
+        roles: vec![],
+    };
+    data.room_member_put(room_id, user_id, membership)
+        .await
+        .unwrap();
+    data.role_apply_default(room_id, user_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn users_can_only_report_people_they_share_a_room_with() {
+    for url in database_urls() {
+        let db = Database::connect(&url).await.unwrap();
+        let create = RoomCreate {
+            name: "reports".to_owned(),
+            description: None,
+            icon: None,
+        };
+        let report_room = db.data().room_create(create.clone(), None).await.unwrap();
+        let mut config = config(&url);
+        config.report_room_id = Some(report_room.id);
+        let s = Arc::new(node_with(db, config));
+        let reporter = create_user(&s).await;
+        let target = create_user(&s).await;
+        let stranger = create_user(&s).await;
+        let room = s.services.rooms.create(create, reporter).await.unwrap();
+        join(&s, room.id, target).await;
+
+        let api = serve(s.clone()).await;
+        let http = reqwest::Client::new();
+        let token = login(&s, reporter).await;
+        let report = |user_id: Uuid| {
+            http.post(format!("{api}/user/{user_id}/report"))
+                .bearer_auth(&token.0)
+                .json(&json!({ "reason": "UnsolicitedSpam", "destination": ReportDestination::Server }))
+                .send()
+        };
+
+        // people who can't be seen look the same as people who don't exist
+        let status = |res: reqwest::Result<reqwest::Response>| res.unwrap().status();
+        assert_eq!(status(report(*stranger).await), StatusCode::NOT_FOUND);
+        assert_eq!(status(report(Uuid::new_v4()).await), StatusCode::NOT_FOUND);
+        assert_eq!(status(report(*target).await), StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn media_can_only_be_reported_by_people_who_can_see_it() {
+    for url in database_urls() {
+        let db = Database::connect(&url).await.unwrap();
+        let create = RoomCreate {
+            name: "reports".to_owned(),
+            description: None,
+            icon: None,
+        };
+        let report_room = db.data().room_create(create.clone(), None).await.unwrap();
+        let mut config = config(&url);
+        config.report_room_id = Some(report_room.id);
+        let s = Arc::new(node_with(db, config));
+        let uploader = create_user(&s).await;
+        let member = create_user(&s).await;
+        let stranger = create_user(&s).await;
+        let room = s.services.rooms.create(create, uploader).await.unwrap();
+        join(&s, room.id, member).await;
+        let thread_id = create_thread_in(&s, uploader, room.id, DbThreadType::Chat).await;
+        let message_id = create_message(&s, thread_id, uploader).await;
+
+        let data = s.data();
+        let media_id = MediaId::new();
+        let media = Media {
+            id: media_id,
+            filename: "cat.txt".to_owned(),
+            alt: None,
+            source: MediaTrack {
+                info: MediaTrackInfo::Other,
+                url: format!("http://localhost/media/{media_id}")
+                    .parse()
+                    .unwrap(),
+                size: MediaSize::Bytes(1),
+                mime: "text/plain".parse().unwrap(),
+                source: TrackSource::Uploaded,
+            },
+            tracks: vec![],
+        };
+        data.media_insert(uploader, media).await.unwrap();
+        data.media_link_insert(media_id, *message_id, MediaLinkType::Message)
+            .await
+            .unwrap();
+
+        let api = serve(s.clone()).await;
+        let http = reqwest::Client::new();
+        let report = |user_id: UserId| {
+            let http = http.clone();
+            let url = format!("{api}/media/{media_id}/report");
+            let s = s.clone();
+            async move {
+                let token = login(&s, user_id).await;
+                http.post(url)
+                    .bearer_auth(&token.0)
+                    .json(&json!({ "reason": "UnsolicitedSpam", "destination": ReportDestination::Server }))
+                    .send()
+                    .await
+                    .unwrap()
+                    .status()
+            }
+        };
+        assert_eq!(report(stranger).await, StatusCode::NOT_FOUND);
+        assert_eq!(report(member).await, StatusCode::OK);
+        assert_eq!(report(uploader).await, StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn only_moderators_can_update_reports() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let owner = create_user(&s).await;
+        let member = create_user(&s).await;
+        let thread_id = create_thread(&s, owner, DbThreadType::Chat).await;
+        let data = s.data();
+        let room_id = data.thread_get(thread_id).await.unwrap().room_id.unwrap();
+        let room_id = RoomId::from(room_id);
+        join(&s, room_id, member).await;
+
+        let api = serve(s.clone()).await;
+        let http = reqwest::Client::new();
+        let res = http
+            .post(format!("{api}/room/{room_id}/report"))
+            .bearer_auth(&login(&s, member).await.0)
+            .json(&json!({ "reason": "UnsolicitedSpam", "destination": ReportDestination::Room }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let target = ReportTarget::Room { target_id: room_id };
+        let report = data
+            .report_thread_find(ReportDestination::Room, &target)
+            .await
+            .unwrap()
+            .expect("the report thread is open");
+
+        // letting someone view a report doesn't let them handle it
+        data.permission_overwrite_upsert(
+            report,
+            *member,
+            PermissionOverwriteType::User,
+            vec![Permission::View, Permission::MemberBan],
+            vec![],
+        )
+        .await
+        .unwrap();
+        let update = |user_id| {
+            let http = &http;
+            let s = &s;
+            let url = format!("{api}/thread/{report}/report");
+            async move {
+                http.patch(url)
+                    .bearer_auth(&login(s, user_id).await.0)
+                    .json(&json!({ "status": "Resolved" }))
+                    .send()
+                    .await
+                    .unwrap()
+                    .status()
+            }
+        };
+        assert_eq!(update(member).await, StatusCode::FORBIDDEN);
+        assert_eq!(update(owner).await, StatusCode::OK);
+    }
+}
@@ -0,0 +1,142 @@
+//! routes are split between node roles
+
+use std::collections::BTreeSet;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+
+use axum::{http::HeaderMap, routing::post, Json};
+use backend::{
+    config::ConfigNodes,
+    data::Database,
+    routes,
+    types::{MediaCreate, MediaCreateSource},
+    Error, ServerState,
+};
+use http::{Method, StatusCode};
+use tokio::sync::mpsc;
+use util::{config, create_user, database_url, node, node_with};
+use utoipa_axum::router::OpenApiRouter;
+
+mod util;
+
+fn paths(router: OpenApiRouter<Arc<ServerState>>) -> BTreeSet<String> {
+    router.into_openapi().paths.paths.into_keys().collect()
+}
+
+/// serve one role's routes on a local port, returning the base url
+async fn serve_role(s: Arc<ServerState>, router: OpenApiRouter<Arc<ServerState>>) -> String {
+    let (router, _) = router.with_state(s).split_for_parts();
+    let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .await
+        .expect("failed to bind");
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    url
+}
+
+#[test]
+fn every_documented_route_is_served_by_a_role() {
+    let mut split = paths(routes::api());
+    split.extend(paths(routes::syncer()));
+    split.extend(paths(routes::media()));
+    assert_eq!(split, paths(routes::routes()));
+}
+
+#[tokio::test]
+async fn routes_are_only_served_by_their_role() {
+    let s = Arc::new(node(&database_url()).await);
+    let api = serve_role(s.clone(), routes::api()).await;
+    let syncer = serve_role(s.clone(), routes::syncer()).await;
+    let media = serve_role(s, routes::media()).await;
+    let http = reqwest::Client::new();
+
+    // the sync route isn't documented, so it can only be found by requesting it
+    let routes = [
+        (Method::GET, "/sync", &syncer),
+        (Method::POST, "/internal/rpc", &api),
+        (Method::POST, "/internal/node", &media),
+        (Method::GET, "/auth", &api),
+        (Method::POST, "/media", &media),
+    ];
+    for (method, path, served_by) in routes {
+        for base in [&api, &syncer, &media] {
+            let status = http
+                .request(method.clone(), format!("{base}{path}"))
+                .send()
+                .await
+                .unwrap()
+                .status();
+            if base == served_by {
+                assert_ne!(status, StatusCode::NOT_FOUND, "{method} {path}");
+            } else {
+                assert_eq!(status, StatusCode::NOT_FOUND, "{method} {path}");
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn media_is_imported_by_media_nodes() {
+    // pretend to be a media node that's too busy to do anything
+    let (send, mut recv) = mpsc::unbounded_channel();
+    let router = axum::Router::new().route(
+        "/api/v1/internal/node",
+        post(
+            move |headers: HeaderMap, Json(json): Json<serde_json::Value>| {
+                let auth = headers["authorization"].to_str().unwrap().to_owned();
+                send.send((auth, json)).unwrap();
+                async { (StatusCode::SERVICE_UNAVAILABLE, "busy") }
+            },
+        ),
+    );
+    let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .await
+        .expect("failed to bind");
+    let media_url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+
+    let database_url = database_url();
+    let db = Database::connect(&database_url)
+        .await
+        .expect("failed to connect to database");
+    let mut config = config(&database_url);
+    config.nodes = Some(ConfigNodes {
+        token: "hunter2".to_owned(),
+        media_url: Some(media_url.parse().unwrap()),
+    });
+    let s = Arc::new(node_with(db, config));
+    let user_id = create_user(&s).await;
+    let create = MediaCreate {
+        alt: None,
+        source: MediaCreateSource::Download {
+            filename: None,
+            size: None,
+            source_url: "https://example.com/cat.png".parse().unwrap(),
+        },
+    };
+    let res = s.services.media.import_from_url(user_id, create).await;
+    assert!(matches!(res, Err(Error::GenericError(err)) if err.contains("busy")));
+    let (auth, command) = recv.recv().await.unwrap();
+    assert_eq!(auth, "Node hunter2");
+    assert_eq!(command["type"], "MediaImport");
+    assert_eq!(command["user_id"], user_id.to_string());
+    assert_eq!(
+        command["media"]["source_url"],
+        "https://example.com/cat.png"
+    );
+
+    // media nodes only take commands from other nodes
+    let media = serve_role(s, routes::media()).await;
+    let http = reqwest::Client::new();
+    for auth in ["Node hunter3", "Server test", "hunter2"] {
+        let status = http
+            .post(format!("{media}/internal/node"))
+            .header("authorization", auth)
+            .json(&command)
+            .send()
+            .await
+            .unwrap()
+            .status();
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{auth}");
+    }
+}
@@ -0,0 +1,293 @@
+//! searching and paginating through search results
+
+use std::sync::Arc;
+
+use backend::{
+    types::{DbMessageCreate, DbThreadCreate, DbThreadType, Mentions},
+    Error,
+};
+use common::v1::types::{
+    search::{SearchMessageRequest, SearchOrder, SearchThreadsRequest},
+    MessageCreate, MessageId, MessageThreadUpdate, MessageType, PaginationDirection,
+    PaginationQuery, Permission, PermissionOverwriteType, ThreadId, ThreadPatch,
+};
+use reqwest::StatusCode;
+use util::{create_thread, create_user, database_urls, login, node, serve};
+use uuid::Uuid;
+
+mod util;
+
+fn page(from: Option<MessageId>, dir: Option<PaginationDirection>) -> PaginationQuery<MessageId> {
+    PaginationQuery {
+        from,
+        to: None,
+        dir,
+        limit: Some(2),
+    }
+}
+
+#[tokio::test]
+async fn search_results_are_paginated_by_id() {
+    for url in database_urls() {
+        let s = node(&url).await;
+        let user_id = create_user(&s).await;
+        let thread_id = create_thread(&s, user_id, DbThreadType::Chat).await;
+        // other tests may search the same database
+        let word = format!("word{}", Uuid::new_v4().simple());
+        let mut ids = vec![];
+        for _ in 0..5 {
+            let create: MessageCreate =
+                serde_json::from_value(serde_json::json!({ "content": word }))
+                    .expect("message is valid");
+            let message = s
+                .services
+                .messages
+                .create(thread_id, user_id, None, None, create)
+                .await
+                .expect("failed to create message");
+            ids.push(message.id);
+        }
+        let search = |order_by: SearchOrder, paginate| {
+            let query: SearchMessageRequest = serde_json::from_value(serde_json::json!({
+                "query": word,
+                "order_by": order_by,
+            }))
+            .expect("query is valid");
+            let s = &s;
+            async move {
+                let res = s.data().search_message(user_id, query, paginate).await?;
+                let ids: Vec<_> = res.items.iter().map(|m| m.id).collect();
+                Ok::<_, Error>((ids, res.has_more))
+            }
+        };
+
+        // newest first, with each page continuing from the last one
+        let res = search(SearchOrder::Newest, page(None, None)).await.unwrap();
+        assert_eq!(res, (vec![ids[4], ids[3]], true));
+        let res = search(SearchOrder::Newest, page(Some(ids[3]), None))
+            .await
+            .unwrap();
+        assert_eq!(res, (vec![ids[2], ids[1]], true));
+        let res = search(SearchOrder::Newest, page(Some(ids[1]), None))
+            .await
+            .unwrap();
+        assert_eq!(res, (vec![ids[0]], false));
+
+        // and going back the other way
+        let res = search(
+            SearchOrder::Newest,
+            page(Some(ids[1]), Some(PaginationDirection::F)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(res, (vec![ids[3], ids[2]], true));
+
+        let res = search(SearchOrder::Oldest, page(Some(ids[1]), None))
+            .await
+            .unwrap();
+        assert_eq!(res, (vec![ids[2], ids[3]], true));
+
+        // every result matches equally well, so ties are broken by id
+        let res = search(SearchOrder::Relevance, page(None, None))
+            .await
+            .unwrap();
+        assert_eq!(res, (vec![ids[4], ids[3]], true));
+        let err = search(SearchOrder::Relevance, page(Some(ids[3]), None))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::BadStatic(_)));
+    }
+}
+
+#[tokio::test]
+async fn thread_search_only_counts_visible_threads() {
+    for url in database_urls() {
+        let s = node(&url).await;
+        let user_id = create_user(&s).await;
+        let data = s.data();
+        let word = format!("word{}", Uuid::new_v4().simple());
+        let mut ids = vec![];
+        for _ in 0..3 {
+            let thread_id = create_thread(&s, user_id, DbThreadType::Chat).await;
+            let patch = ThreadPatch {
+                name: Some(word.clone()),
+                description: None,
+                tags: None,
+            };
+            data.thread_update(thread_id, patch).await.unwrap();
+            ids.push(thread_id);
+        }
+        let deny_view = |target_id: ThreadId| {
+            let data = &data;
+            async move {
+                data.permission_overwrite_upsert(
+                    target_id,
+                    *user_id,
+                    PermissionOverwriteType::User,
+                    vec![],
+                    vec![Permission::View],
+                )
+                .await
+                .unwrap();
+            }
+        };
+        deny_view(ids[1]).await;
+        // allowing a thread overrides the room denying it
+        let room_id = data.thread_get(ids[2]).await.unwrap().room_id.unwrap();
+        deny_view((*room_id).into()).await;
+        data.permission_overwrite_upsert(
+            ids[2],
+            *user_id,
+            PermissionOverwriteType::User,
+            vec![Permission::View],
+            vec![],
+        )
+        .await
+        .unwrap();
+        let search = |features: &[&str]| {
+            let query: SearchThreadsRequest = serde_json::from_value(serde_json::json!({
+                "query": word,
+                "features_thread": features,
+                "order_by": "Oldest",
+            }))
+            .expect("query is valid");
+            let data = &data;
+            async move {
+                let res = data
+                    .search_thread(user_id, query, PaginationQuery::default())
+                    .await
+                    .unwrap();
+                let ids: Vec<_> = res.items.iter().map(|t| t.id).collect();
+                (ids, res.total)
+            }
+        };
+        assert_eq!(search(&["All"]).await, (vec![ids[0], ids[2]], 2));
+    }
+}
+
+#[tokio::test]
+async fn threads_outside_rooms_can_be_searched() {
+    for url in database_urls() {
+        let s = node(&url).await;
+        let user_id = create_user(&s).await;
+        let data = s.data();
+        let word = format!("word{}", Uuid::new_v4().simple());
+        let mut ids = vec![];
+        for _ in 0..2 {
+            let thread_id = data
+                .thread_create(DbThreadCreate {
+                    room_id: None,
+                    creator_id: user_id,
+                    name: word.clone(),
+                    description: None,
+                    ty: DbThreadType::Chat,
+                })
+                .await
+                .unwrap();
+            data.message_create(DbMessageCreate {
+                thread_id,
+                attachment_ids: vec![],
+                author_id: user_id,
+                embeds: vec![],
+                message_type: MessageType::ThreadUpdate(MessageThreadUpdate {
+                    patch: ThreadPatch {
+                        name: Some(word.clone()),
+                        description: None,
+                        tags: None,
+                    },
+                }),
+                mentions: Mentions::default(),
+                edited_at: None,
+                created_at: None,
+            })
+            .await
+            .unwrap();
+            ids.push(thread_id);
+        }
+        // there's no room to inherit from, so only the thread's overwrites count
+        data.permission_overwrite_upsert(
+            ids[0],
+            *user_id,
+            PermissionOverwriteType::User,
+            vec![Permission::View],
+            vec![],
+        )
+        .await
+        .unwrap();
+        let search = |features_room: &[&str]| {
+            let query: SearchThreadsRequest = serde_json::from_value(serde_json::json!({
+                "query": word,
+                "features_thread": ["All"],
+                "features_room": features_room,
+            }))
+            .expect("query is valid");
+            let data = &data;
+            async move {
+                let res = data
+                    .search_thread(user_id, query, PaginationQuery::default())
+                    .await
+                    .unwrap();
+                let ids: Vec<_> = res.items.iter().map(|t| t.id).collect();
+                (ids, res.total)
+            }
+        };
+        assert_eq!(search(&[]).await, (vec![ids[0]], 1));
+        assert_eq!(search(&["NotDm"]).await, (vec![ids[0]], 1));
+        assert_eq!(search(&["Dm"]).await, (vec![], 0));
+    }
+}
+
+#[tokio::test]
+async fn pinned_threads_cant_be_searched_for() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let user_id = create_user(&s).await;
+        let api = serve(s.clone()).await;
+        let http = reqwest::Client::new();
+        let token = login(&s, user_id).await;
+
+        for kind in ["thread", "message"] {
+            let res = http
+                .post(format!("{api}/search/{kind}"))
+                .bearer_auth(&token.0)
+                .json(&serde_json::json!({ "query": "hello", "features_thread": ["Pinned"] }))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+}
+
+#[tokio::test]
+async fn searched_threads_include_private_data() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let user_id = create_user(&s).await;
+        let thread_id = create_thread(&s, user_id, DbThreadType::Chat).await;
+        let word = format!("word{}", Uuid::new_v4().simple());
+        let patch = ThreadPatch {
+            name: Some(word.clone()),
+            description: None,
+            tags: None,
+        };
+        s.data().thread_update(thread_id, patch).await.unwrap();
+        let api = serve(s.clone()).await;
+
+        let res: serde_json::Value = reqwest::Client::new()
+            .post(format!("{api}/search/thread"))
+            .bearer_auth(&login(&s, user_id).await.0)
+            .json(&serde_json::json!({ "query": word, "features_thread": ["All"] }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(res["total"], 1);
+        let thread = &res["items"][0];
+        assert_eq!(thread["id"], thread_id.to_string());
+        assert_eq!(thread["is_unread"], true);
+        assert_eq!(thread["mention_count"], 0);
+    }
+}
@@ -0,0 +1,515 @@
+//! what each sync connection is sent
+
+use std::{io::Write, sync::Arc, time::Duration};
+
+use backend::{
+    types::{DbMessageCreate, DbThreadCreate, DbThreadType},
+    ServerState,
+};
+use common::v1::types::{
+    util::Time, MessageClient, MessageEnvelope, MessageId, MessagePayload, MessageSync,
+    MessageThreadUpdate, MessageType, MessageVerId, RoomId, RoomMembership, SessionToken, ThreadId,
+    ThreadPatch, UserId,
+};
+use flate2::write::ZlibDecoder;
+use futures_util::{SinkExt, StreamExt};
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use util::{
+    create_message, create_thread, create_thread_in, create_user, database_urls, exchange, login,
+    node, serve, sync, sync_with, Ws,
+};
+
+mod util;
+
+/// add someone to a room with its default roles
+async fn join(s: &ServerState, room_id: RoomId, user_id: UserId) {
+    let data = s.data();
+    let membership = RoomMembership::Join {
+        override_name: None,
+        override_description: None,
+        roles: vec![],
+    };
+    data.room_member_put(room_id, user_id, membership)
+        .await
+        .unwrap();
+    data.role_apply_default(room_id, user_id).await.unwrap();
+}
+
+/// open an authenticated sync connection
+async fn connect(s: &Arc<ServerState>, token: &SessionToken) -> Ws {
+    let mut ws = sync(s.clone()).await;
+    let hello = serde_json::json!({ "type": "Hello", "token": token });
+    exchange(&mut ws, hello, "Ready").await;
+    ws
+}
+
+/// wait for the next event of a type
+async fn next(ws: &mut Ws, ty: &str) -> serde_json::Value {
+    let event = async {
+        loop {
+            let WsMessage::Text(text) = ws.next().await.unwrap().unwrap() else {
+                continue;
+            };
+            let msg: serde_json::Value = serde_json::from_str(&text).unwrap();
+            if msg["op"] == "Sync" && msg["data"]["type"] == ty {
+                return msg["data"].clone();
+            }
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(5), event)
+        .await
+        .unwrap_or_else(|_| panic!("no {ty} event"))
+}
+
+#[tokio::test]
+async fn reactions_are_personalized_for_each_connection() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let author = create_user(&s).await;
+        let reactor = create_user(&s).await;
+        let thread_id = create_thread(&s, author, DbThreadType::Chat).await;
+        let room_id = s
+            .data()
+            .thread_get(thread_id)
+            .await
+            .unwrap()
+            .room_id
+            .unwrap();
+        join(&s, room_id, reactor).await;
+        let message_id = create_message(&s, thread_id, author).await;
+
+        let api = serve(s.clone()).await;
+        let http = reqwest::Client::new();
+        let (author_token, reactor_token) = (login(&s, author).await, login(&s, reactor).await);
+        let mut author_ws = connect(&s, &author_token).await;
+        let mut reactor_ws = connect(&s, &reactor_token).await;
+        let reaction = format!("{api}/thread/{thread_id}/message/{message_id}/reaction/👍");
+        let edit = |content: &str| {
+            http.patch(format!("{api}/thread/{thread_id}/message/{message_id}"))
+                .bearer_auth(&author_token.0)
+                .json(&serde_json::json!({ "content": content }))
+                .send()
+        };
+
+        let res = http
+            .put(&reaction)
+            .bearer_auth(&reactor_token.0)
+            .send()
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let res = edit("edited").await.unwrap();
+        assert!(res.status().is_success(), "{}", res.text().await.unwrap());
+        let update = next(&mut author_ws, "MessageUpdate").await;
+        assert_eq!(update["message"]["reactions"][0]["self"], false);
+        let update = next(&mut reactor_ws, "MessageUpdate").await;
+        assert_eq!(update["message"]["reactions"][0]["self"], true);
+
+        // removing a reaction updates what the connection remembers
+        let other = format!("{api}/thread/{thread_id}/message/{message_id}/reaction/👀");
+        let res = http
+            .put(&other)
+            .bearer_auth(&author_token.0)
+            .send()
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let res = http
+            .delete(&reaction)
+            .bearer_auth(&reactor_token.0)
+            .send()
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        next(&mut reactor_ws, "ReactionDelete").await;
+        assert!(edit("edited again").await.unwrap().status().is_success());
+        let update = next(&mut reactor_ws, "MessageUpdate").await;
+        assert_eq!(update["message"]["reactions"][0]["self"], false);
+        let update = next(&mut author_ws, "MessageUpdate").await;
+        assert_eq!(update["message"]["reactions"][0]["self"], true);
+    }
+}
+
+#[tokio::test]
+async fn visibility_follows_permission_changes() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let owner = create_user(&s).await;
+        let member = create_user(&s).await;
+        let thread_id = create_thread(&s, owner, DbThreadType::Chat).await;
+        let room_id = s
+            .data()
+            .thread_get(thread_id)
+            .await
+            .unwrap()
+            .room_id
+            .unwrap();
+        join(&s, room_id, member).await;
+
+        let api = serve(s.clone()).await;
+        let http = reqwest::Client::new();
+        let owner_token = login(&s, owner).await;
+        let mut ws = connect(&s, &login(&s, member).await).await;
+        let overwrite = format!("{api}/thread/{thread_id}/permission/{member}");
+
+        let visible = create_message(&s, thread_id, owner).await;
+        let create = next(&mut ws, "MessageCreate").await;
+        assert_eq!(create["message"]["id"], visible.to_string());
+
+        // hiding the thread is seen even though the connection remembers it was visible
+        let res = http
+            .put(&overwrite)
+            .bearer_auth(&owner_token.0)
+            .json(&serde_json::json!({ "allow": [], "deny": ["View"] }))
+            .send()
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let mut events = s.sushi.subscribe();
+        let hidden = create_message(&s, thread_id, owner).await;
+        // who can see an event is worked out when it's published, so wait for that
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                match events.recv().await.unwrap().msg {
+                    MessageSync::MessageCreate { message } if message.id == hidden => break,
+                    _ => {}
+                }
+            }
+        })
+        .await
+        .expect("message wasn't published");
+
+        let res = http
+            .delete(&overwrite)
+            .bearer_auth(&owner_token.0)
+            .send()
+            .await
+            .unwrap();
+        assert!(res.status().is_success());
+        let shown = create_message(&s, thread_id, owner).await;
+        let create = next(&mut ws, "MessageCreate").await;
+        assert_ne!(create["message"]["id"], hidden.to_string());
+        assert_eq!(create["message"]["id"], shown.to_string());
+    }
+}
+
+/// send a room update, a message in each thread, and then an event that's
+/// always sent, returning the ids of the rooms and threads that were sent
+async fn received(
+    s: &ServerState,
+    ws: &mut Ws,
+    user_id: UserId,
+    rooms: &[RoomId],
+    threads: &[ThreadId],
+) -> Vec<String> {
+    for room_id in rooms {
+        let room = s.services.rooms.get(*room_id, None).await.unwrap();
+        s.broadcast(MessageSync::RoomUpdate { room }).unwrap();
+    }
+    for thread_id in threads {
+        create_message(s, *thread_id, user_id).await;
+    }
+    let thread_id = threads[0];
+    s.broadcast(MessageSync::ThreadAck {
+        thread_id,
+        message_id: MessageId::new(),
+        version_id: MessageVerId::new(),
+        user_id,
+    })
+    .unwrap();
+
+    let mut sent = vec![];
+    loop {
+        let WsMessage::Text(text) = tokio::time::timeout(Duration::from_secs(5), ws.next())
+            .await
+            .expect("no ThreadAck event")
+            .unwrap()
+            .unwrap()
+        else {
+            continue;
+        };
+        let msg: serde_json::Value = serde_json::from_str(&text).unwrap();
+        let data = &msg["data"];
+        match data["type"].as_str() {
+            Some("RoomUpdate") => sent.push(data["room"]["id"].as_str().unwrap().to_owned()),
+            Some("MessageCreate") => {
+                sent.push(data["message"]["thread_id"].as_str().unwrap().to_owned())
+            }
+            Some("ThreadAck") => return sent,
+            _ => {}
+        }
+    }
+}
+
+#[tokio::test]
+async fn filters_pick_which_events_are_sent() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let user_id = create_user(&s).await;
+        let a1 = create_thread(&s, user_id, DbThreadType::Chat).await;
+        let room_a = s.data().thread_get(a1).await.unwrap().room_id.unwrap();
+        let a2 = create_thread_in(&s, user_id, room_a, DbThreadType::Chat).await;
+        let b1 = create_thread(&s, user_id, DbThreadType::Chat).await;
+        let room_b = s.data().thread_get(b1).await.unwrap().room_id.unwrap();
+        let (rooms, threads) = ([room_a, room_b], [a1, a2, b1]);
+        let mut ws = connect(&s, &login(&s, user_id).await).await;
+        let (room_a, room_b) = (room_a.to_string(), room_b.to_string());
+        let (a1, a2, b1) = (a1.to_string(), a2.to_string(), b1.to_string());
+        let everything = vec![room_a.clone(), room_b, a1.clone(), a2.clone(), b1];
+
+        // everything is sent until a filter is added
+        let sent = received(&s, &mut ws, user_id, &rooms, &threads).await;
+        assert_eq!(sent, everything);
+
+        let cases = [
+            (serde_json::json!({ "type": "SubAll" }), everything.clone()),
+            (
+                serde_json::json!({ "type": "SubRoom", "room_id": room_a }),
+                vec![room_a.clone()],
+            ),
+            (
+                serde_json::json!({ "type": "SubRoomAll", "room_id": room_a }),
+                vec![room_a.clone(), a1.clone(), a2.clone()],
+            ),
+            (
+                serde_json::json!({ "type": "SubThread", "thread_id": a1 }),
+                vec![a1.clone()],
+            ),
+            // no filters left, so room and thread events aren't sent
+            (serde_json::json!({ "type": "Unsub" }), vec![]),
+        ];
+        for (i, (mut filter, expected)) in cases.into_iter().enumerate() {
+            filter["id"] = "test".into();
+            let msg = serde_json::json!({ "type": "Filter", "filter": filter });
+            ws.send(WsMessage::text(msg.to_string())).await.unwrap();
+            // filters aren't acknowledged, but messages are handled in order and
+            // changing status sends an event back
+            let status = if i % 2 == 0 { "Away" } else { "Online" };
+            let msg = serde_json::json!({ "type": "Status", "status": { "type": status } });
+            ws.send(WsMessage::text(msg.to_string())).await.unwrap();
+            next(&mut ws, "UserUpdate").await;
+            let sent = received(&s, &mut ws, user_id, &rooms, &threads).await;
+            assert_eq!(sent, expected, "{filter}");
+        }
+    }
+}
+
+#[tokio::test]
+async fn events_for_missing_threads_dont_break_room_filters() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let user_id = create_user(&s).await;
+        let thread_id = create_thread(&s, user_id, DbThreadType::Chat).await;
+        let room_id = s
+            .data()
+            .thread_get(thread_id)
+            .await
+            .unwrap()
+            .room_id
+            .unwrap();
+        let mut ws = connect(&s, &login(&s, user_id).await).await;
+        let filter = serde_json::json!({ "type": "SubRoomAll", "room_id": room_id, "id": "test" });
+        let msg = serde_json::json!({ "type": "Filter", "filter": filter });
+        ws.send(WsMessage::text(msg.to_string())).await.unwrap();
+        let msg = serde_json::json!({ "type": "Status", "status": { "type": "Away" } });
+        ws.send(WsMessage::text(msg.to_string())).await.unwrap();
+        next(&mut ws, "UserUpdate").await;
+
+        // eg. a thread that was deleted after the event was sent
+        s.broadcast(MessageSync::MessageDelete {
+            room_id: None,
+            thread_id: ThreadId::new(),
+            message_id: MessageId::new(),
+        })
+        .unwrap();
+        let sent = received(&s, &mut ws, user_id, &[room_id], &[thread_id]).await;
+        assert_eq!(sent, vec![room_id.to_string(), thread_id.to_string()]);
+    }
+}
+
+#[tokio::test]
+async fn threads_this_node_looked_up_too_early_are_seen_once_they_exist() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let user_id = create_user(&s).await;
+        let thread_id = create_thread(&s, user_id, DbThreadType::Chat).await;
+        let room_id = s
+            .data()
+            .thread_get(thread_id)
+            .await
+            .unwrap()
+            .room_id
+            .map(|r| *r);
+        let tx = s.data().begin().await.unwrap();
+        let late = tx
+            .thread_create(DbThreadCreate {
+                room_id,
+                creator_id: user_id,
+                name: "late".to_owned(),
+                description: None,
+                ty: DbThreadType::Chat,
+            })
+            .await
+            .unwrap();
+        tx.message_create(DbMessageCreate {
+            thread_id: late,
+            attachment_ids: vec![],
+            author_id: user_id,
+            embeds: vec![],
+            message_type: MessageType::ThreadUpdate(MessageThreadUpdate {
+                patch: ThreadPatch {
+                    name: Some("late".to_owned()),
+                    description: None,
+                    tags: None,
+                },
+            }),
+            mentions: Default::default(),
+            edited_at: None,
+            created_at: None,
+        })
+        .await
+        .unwrap();
+        let mut ws = connect(&s, &login(&s, user_id).await).await;
+
+        // the event arrives before the thread is committed
+        let typing = MessageSync::ThreadTyping {
+            thread_id: late,
+            user_id,
+            until: Time::now_utc(),
+        };
+        s.broadcast(typing.clone()).unwrap();
+        received(&s, &mut ws, user_id, &[], &[thread_id]).await;
+
+        tx.commit().await.unwrap();
+        s.broadcast(typing).unwrap();
+        let event = next(&mut ws, "ThreadTyping").await;
+        assert_eq!(event["thread_id"], late.to_string());
+    }
+}
+
+/// decode messages sent on a sync connection
+struct Decoder {
+    msgpack: bool,
+
+    /// like the server, one stream is shared by every message
+    zlib: Option<ZlibDecoder<Vec<u8>>>,
+}
+
+impl Decoder {
+    fn decode(&mut self, msg: WsMessage) -> Option<MessageEnvelope> {
+        let bytes = match msg {
+            WsMessage::Text(text) => {
+                assert!(
+                    !self.msgpack && self.zlib.is_none(),
+                    "expected a binary frame"
+                );
+                text.as_bytes().to_vec()
+            }
+            WsMessage::Binary(bytes) => {
+                assert!(self.msgpack || self.zlib.is_some(), "expected a text frame");
+                bytes.to_vec()
+            }
+            _ => return None,
+        };
+        let bytes = match &mut self.zlib {
+            Some(zlib) => {
+                // every message ends with a sync flush, so each one decompresses fully
+                zlib.write_all(&bytes).unwrap();
+                zlib.flush().unwrap();
+                std::mem::take(zlib.get_mut())
+            }
+            None => bytes,
+        };
+        Some(if self.msgpack {
+            rmp_serde::from_slice(&bytes).expect("invalid msgpack")
+        } else {
+            serde_json::from_slice(&bytes).expect("invalid json")
+        })
+    }
+
+    /// wait for the next message that `f` picks something out of
+    async fn next<T>(&mut self, ws: &mut Ws, f: impl Fn(MessagePayload) -> Option<T>) -> T {
+        let next = async {
+            loop {
+                let msg = ws.next().await.unwrap().unwrap();
+                if let Some(picked) = self.decode(msg).and_then(|e| f(e.payload)) {
+                    return picked;
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), next)
+            .await
+            .expect("no matching message")
+    }
+}
+
+#[tokio::test]
+async fn messages_are_encoded_as_negotiated() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let user_id = create_user(&s).await;
+        let thread_id = create_thread(&s, user_id, DbThreadType::Chat).await;
+        let token = login(&s, user_id).await;
+
+        for (format, compression) in [
+            ("json", None),
+            ("msgpack", None),
+            ("json", Some("zlib")),
+            ("msgpack", Some("zlib")),
+        ] {
+            let mut query = format!("version=1&format={format}");
+            if let Some(compression) = compression {
+                query += &format!("&compression={compression}");
+            }
+            let mut ws = sync_with(s.clone(), &query).await;
+            let mut decoder = Decoder {
+                msgpack: format == "msgpack",
+                zlib: compression.map(|_| ZlibDecoder::new(vec![])),
+            };
+
+            // clients send messages in the same format, but never compressed
+            let hello = MessageClient::Hello {
+                token: token.clone(),
+                resume: None,
+                status: None,
+            };
+            let hello = if decoder.msgpack {
+                WsMessage::binary(rmp_serde::to_vec_named(&hello).unwrap())
+            } else {
+                WsMessage::text(serde_json::to_string(&hello).unwrap())
+            };
+            ws.send(hello).await.unwrap();
+            let user = decoder
+                .next(&mut ws, |p| match p {
+                    MessagePayload::Ready { user, .. } => Some(user),
+                    _ => None,
+                })
+                .await;
+            assert_eq!(user.map(|u| u.id), Some(user_id));
+
+            // several messages, so later ones depend on the shared compression context
+            for _ in 0..3 {
+                let message_id = create_message(&s, thread_id, user_id).await;
+                let sent = s
+                    .data()
+                    .message_get(thread_id, message_id, user_id)
+                    .await
+                    .unwrap();
+                let message = decoder
+                    .next(&mut ws, |p| match p {
+                        MessagePayload::Sync {
+                            data: MessageSync::MessageCreate { message },
+                            ..
+                        } if message.id == message_id => Some(message),
+                        _ => None,
+                    })
+                    .await;
+                assert_eq!(
+                    serde_json::to_value(&message).unwrap(),
+                    serde_json::to_value(&sent).unwrap(),
+                    "{query}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! creating, editing, and deleting room tags
+
+use std::sync::Arc;
+
+use backend::types::DbThreadType;
+use common::v1::types::{tag::Tag, PaginationResponse, TagId};
+use reqwest::StatusCode;
+use serde_json::json;
+use util::{create_thread, create_user, database_urls, login, node, serve};
+
+mod util;
+
+#[tokio::test]
+async fn tags_can_be_created_updated_and_deleted() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let user_id = create_user(&s).await;
+        let thread_id = create_thread(&s, user_id, DbThreadType::Forum).await;
+        let room_id = s
+            .data()
+            .thread_get(thread_id)
+            .await
+            .unwrap()
+            .room_id
+            .unwrap();
+        let api = serve(s.clone()).await;
+        let http = reqwest::Client::new();
+        let token = login(&s, user_id).await;
+        let tags = format!("{api}/room/{room_id}/tag");
+
+        let res = http
+            .post(&tags)
+            .bearer_auth(&token.0)
+            .json(&json!({ "room_id": room_id, "name": "bug" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let tag: Tag = res.json().await.unwrap();
+        assert_eq!(tag.room_id, room_id);
+        assert_eq!(tag.name, "bug");
+        assert!(!tag.is_archived);
+        let tag_url = format!("{tags}/{}", tag.id);
+
+        let listed: PaginationResponse<Tag> = http
+            .get(&tags)
+            .bearer_auth(&token.0)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(listed.items, vec![tag.clone()]);
+
+        let patch = json!({ "name": "defect", "description": "something broke" });
+        let res = http
+            .patch(&tag_url)
+            .bearer_auth(&token.0)
+            .json(&patch)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let updated: Tag = res.json().await.unwrap();
+        assert_eq!(updated.name, "defect");
+        assert_eq!(updated.description.as_deref(), Some("something broke"));
+        assert_ne!(updated.version_id, tag.version_id);
+        let res = http
+            .patch(&tag_url)
+            .bearer_auth(&token.0)
+            .json(&patch)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        let fetched: Tag = http
+            .get(&tag_url)
+            .bearer_auth(&token.0)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(fetched, updated);
+
+        let res = http.delete(&tag_url).bearer_auth(&token.0).send().await;
+        assert_eq!(res.unwrap().status(), StatusCode::NO_CONTENT);
+        let res = http.get(&tag_url).bearer_auth(&token.0).send().await;
+        assert_eq!(res.unwrap().status(), StatusCode::NOT_FOUND);
+    }
+}
+
+#[tokio::test]
+async fn applied_tags_are_only_deleted_when_forced() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let user_id = create_user(&s).await;
+        let thread_id = create_thread(&s, user_id, DbThreadType::Forum).await;
+        let room_id = s
+            .data()
+            .thread_get(thread_id)
+            .await
+            .unwrap()
+            .room_id
+            .unwrap();
+        let api = serve(s.clone()).await;
+        let http = reqwest::Client::new();
+        let token = login(&s, user_id).await;
+        let thread_tags = || async {
+            let thread: serde_json::Value = http
+                .get(format!("{api}/thread/{thread_id}"))
+                .bearer_auth(&token.0)
+                .send()
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+            serde_json::from_value::<Vec<TagId>>(thread["tags"].clone()).unwrap()
+        };
+
+        let tag: Tag = http
+            .post(format!("{api}/room/{room_id}/tag"))
+            .bearer_auth(&token.0)
+            .json(&json!({ "room_id": room_id, "name": "solved" }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let tag_url = format!("{api}/room/{room_id}/tag/{}", tag.id);
+        let res = http
+            .put(format!("{api}/thread/{thread_id}/tag/{}", tag.id))
+            .bearer_auth(&token.0)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(thread_tags().await, vec![tag.id]);
+
+        let res = http.delete(&tag_url).bearer_auth(&token.0).send().await;
+        assert_eq!(res.unwrap().status(), StatusCode::CONFLICT);
+        assert_eq!(thread_tags().await, vec![tag.id]);
+
+        let res = http
+            .delete(format!("{tag_url}?force=true"))
+            .bearer_auth(&token.0)
+            .send()
+            .await;
+        assert_eq!(res.unwrap().status(), StatusCode::NO_CONTENT);
+        assert!(thread_tags().await.is_empty());
+    }
+}
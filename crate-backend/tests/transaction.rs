@@ -0,0 +1,402 @@
+//! changes made in a transaction should only be persisted once it's committed
+
+use std::sync::Arc;
+
+use backend::{types::DbThreadType, Error, ServerState};
+use common::v1::types::{
+    pagination::PaginationQuery, InviteCode, MessageCreate, MessagePatch, MessageType, RoomCreate,
+    RoomId, UserId,
+};
+use util::{create_thread, create_user, database_url, database_urls, login, node, serve};
+use uuid::Uuid;
+
+mod util;
+
+fn room_create(name: &str) -> RoomCreate {
+    RoomCreate {
+        name: name.to_owned(),
+        description: None,
+        icon: None,
+    }
+}
+
+async fn room_exists(s: &ServerState, room_id: RoomId) -> bool {
+    match s.data().room_get(room_id).await {
+        Ok(_) => true,
+        Err(Error::NotFound) => false,
+        Err(err) => panic!("failed to get room: {err}"),
+    }
+}
+
+fn pool(s: &ServerState) -> &sqlx::PgPool {
+    s.db.pool().expect("not a postgres database")
+}
+
+async fn count_rooms(s: &ServerState, name: &str) -> i64 {
+    sqlx::query_scalar("SELECT count(*) FROM room WHERE name = $1")
+        .bind(name)
+        .fetch_one(pool(s))
+        .await
+        .expect("failed to count rooms")
+}
+
+/// make an operation (`INSERT` or `UPDATE`) on a table fail for rows where a column has a value
+///
+/// returns the name of the trigger, to pass to `remove_failure`
+async fn inject_failure(
+    s: &ServerState,
+    op: &str,
+    table: &str,
+    column: &str,
+    value: &str,
+) -> String {
+    let name = format!("fail_{}", Uuid::new_v4().simple());
+    let sql = format!(
+        r#"
+        CREATE FUNCTION {name}() RETURNS trigger LANGUAGE plpgsql AS $$
+        BEGIN
+            IF new.{column}::text = '{value}' THEN
+                RAISE EXCEPTION 'injected failure';
+            END IF;
+            RETURN new;
+        END $$;
+        CREATE TRIGGER {name} BEFORE {op} ON {table}
+            FOR EACH ROW EXECUTE FUNCTION {name}();
+        "#
+    );
+    sqlx::raw_sql(&sql)
+        .execute(pool(s))
+        .await
+        .expect("failed to inject failure");
+    name
+}
+
+async fn remove_failure(s: &ServerState, table: &str, name: &str) {
+    let sql = format!("DROP TRIGGER {name} ON {table}; DROP FUNCTION {name}();");
+    sqlx::raw_sql(&sql)
+        .execute(pool(s))
+        .await
+        .expect("failed to remove failure");
+}
+
+/// how many rooms a user is in, and how many roles they have
+async fn count_memberships(s: &ServerState, user_id: UserId) -> (i64, i64) {
+    let rooms = sqlx::query_scalar("SELECT count(*) FROM room_member WHERE user_id = $1")
+        .bind(*user_id)
+        .fetch_one(pool(s))
+        .await
+        .expect("failed to count rooms");
+    let roles = sqlx::query_scalar("SELECT count(*) FROM role_member WHERE user_id = $1")
+        .bind(*user_id)
+        .fetch_one(pool(s))
+        .await
+        .expect("failed to count roles");
+    (rooms, roles)
+}
+
+#[tokio::test]
+async fn committed_transactions_are_persisted() {
+    for database_url in database_urls() {
+        let s = node(&database_url).await;
+        let tx = s.data().begin().await.expect("failed to begin");
+        let room = tx
+            .room_create(room_create("committed"), None)
+            .await
+            .expect("failed to create room");
+        assert!(!room_exists(&s, room.id).await);
+        tx.commit().await.expect("failed to commit");
+        assert!(room_exists(&s, room.id).await);
+    }
+}
+
+#[tokio::test]
+async fn dropped_transactions_are_rolled_back() {
+    for database_url in database_urls() {
+        let s = node(&database_url).await;
+        let tx = s.data().begin().await.expect("failed to begin");
+        let room = tx
+            .room_create(room_create("dropped"), None)
+            .await
+            .expect("failed to create room");
+        drop(tx);
+        assert!(!room_exists(&s, room.id).await);
+
+        let tx = s.data().begin().await.expect("failed to begin");
+        let room = tx
+            .room_create(room_create("rolled back"), None)
+            .await
+            .expect("failed to create room");
+        tx.rollback().await.expect("failed to roll back");
+        assert!(!room_exists(&s, room.id).await);
+    }
+}
+
+#[tokio::test]
+async fn nested_transactions_join_the_outer_one() {
+    for database_url in database_urls() {
+        let s = node(&database_url).await;
+        let outer = s.data().begin().await.expect("failed to begin");
+        let inner = outer.begin().await.expect("failed to begin");
+        let room = inner
+            .room_create(room_create("nested"), None)
+            .await
+            .expect("failed to create room");
+        inner.commit().await.expect("failed to commit");
+        assert!(!room_exists(&s, room.id).await);
+        drop(outer);
+        assert!(!room_exists(&s, room.id).await);
+    }
+}
+
+#[tokio::test]
+async fn nested_rollbacks_stop_the_outer_transaction_committing() {
+    for database_url in database_urls() {
+        let s = node(&database_url).await;
+        let outer = s.data().begin().await.expect("failed to begin");
+        let room = outer
+            .room_create(room_create("undone"), None)
+            .await
+            .expect("failed to create room");
+        let inner = outer.begin().await.expect("failed to begin");
+        inner.rollback().await.expect("failed to roll back");
+        assert!(matches!(outer.commit().await, Err(Error::Internal(_))));
+        assert!(!room_exists(&s, room.id).await);
+    }
+}
+
+#[tokio::test]
+async fn outer_transactions_commit_while_nested_ones_are_alive() {
+    for database_url in database_urls() {
+        let s = node(&database_url).await;
+        let outer = s.data().begin().await.expect("failed to begin");
+        let inner = outer.begin().await.expect("failed to begin");
+        let room = inner
+            .room_create(room_create("outlived"), None)
+            .await
+            .expect("failed to create room");
+        outer.commit().await.expect("failed to commit");
+        assert!(room_exists(&s, room.id).await);
+
+        // the transaction is over, so the nested handle can't add to it
+        assert!(inner
+            .room_create(room_create("too late"), None)
+            .await
+            .is_err());
+        inner.commit().await.expect("failed to commit");
+        assert!(room_exists(&s, room.id).await);
+    }
+}
+
+#[tokio::test]
+async fn overlapping_queries_in_a_transaction_fail_instead_of_waiting() {
+    let s = node(&database_url()).await;
+    let tx = s.data().begin().await.expect("failed to begin");
+    let inner = tx.begin().await.expect("failed to begin");
+    let (a, b) = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        futures_util::future::join(
+            tx.room_create(room_create("overlapping"), None),
+            inner.room_create(room_create("overlapping"), None),
+        ),
+    )
+    .await
+    .expect("queries deadlocked");
+    assert!(a.is_ok());
+    assert!(matches!(b, Err(Error::Internal(_))));
+}
+
+#[tokio::test]
+async fn failed_room_create_persists_nothing() {
+    let database_url = database_url();
+
+    let s = node(&database_url).await;
+    let name = format!("atomic {}", Uuid::new_v4());
+
+    // the room and roles are created before the missing creator is added as a member
+    let res = s
+        .services
+        .rooms
+        .create(room_create(&name), UserId::new())
+        .await;
+    assert!(res.is_err());
+    assert_eq!(count_rooms(&s, &name).await, 0);
+
+    let creator = create_user(&s).await;
+    s.services
+        .rooms
+        .create(room_create(&name), creator)
+        .await
+        .expect("failed to create room");
+    assert_eq!(count_rooms(&s, &name).await, 1);
+}
+
+#[tokio::test]
+async fn failed_dm_create_persists_nothing() {
+    let s = node(&database_url()).await;
+    let a = create_user(&s).await;
+    let b = create_user(&s).await;
+
+    // the last write is giving the second user their roles
+    let failure = inject_failure(&s, "INSERT", "role_member", "user_id", &b.to_string()).await;
+    assert!(s.services.rooms.create_dm(a, b).await.is_err());
+    remove_failure(&s, "role_member", &failure).await;
+    assert_eq!(count_memberships(&s, a).await, (0, 0));
+    assert_eq!(count_memberships(&s, b).await, (0, 0));
+
+    s.services
+        .rooms
+        .create_dm(a, b)
+        .await
+        .expect("failed to create dm");
+    assert_eq!(count_memberships(&s, a).await, (1, 1));
+}
+
+#[tokio::test]
+async fn failed_invite_use_persists_nothing() {
+    let s = Arc::new(node(&database_url()).await);
+    let owner = create_user(&s).await;
+    let user_id = create_user(&s).await;
+    let room = s
+        .services
+        .rooms
+        .create(room_create("invited"), owner)
+        .await
+        .expect("failed to create room");
+    let code = InviteCode(Uuid::new_v4().simple().to_string());
+    s.data()
+        .invite_insert_room(room.id, owner, code.clone(), None, None)
+        .await
+        .expect("failed to create invite");
+
+    let api = serve(s.clone()).await;
+    let token = login(&s, user_id).await;
+    let use_invite = || {
+        reqwest::Client::new()
+            .post(format!("{api}/invite/{}", code.0))
+            .bearer_auth(&token.0)
+            .send()
+    };
+
+    // the last write is counting the use
+    let failure = inject_failure(&s, "UPDATE", "invite", "code", &code.0).await;
+    let res = use_invite().await.unwrap();
+    remove_failure(&s, "invite", &failure).await;
+    assert!(res.status().is_server_error());
+    assert_eq!(count_memberships(&s, user_id).await, (0, 0));
+    let invite = s.data().invite_select(code.clone()).await.unwrap();
+    assert_eq!(invite.uses, 0);
+
+    assert!(use_invite().await.unwrap().status().is_success());
+    assert_eq!(count_memberships(&s, user_id).await.0, 1);
+    let invite = s.data().invite_select(code).await.unwrap();
+    assert_eq!(invite.uses, 1);
+}
+
+#[tokio::test]
+async fn failed_message_create_persists_nothing() {
+    let s = node(&database_url()).await;
+    let owner = create_user(&s).await;
+    let user_id = create_user(&s).await;
+    let thread_id = create_thread(&s, owner, DbThreadType::Chat).await;
+    let room_id = s
+        .data()
+        .thread_get(thread_id)
+        .await
+        .unwrap()
+        .room_id
+        .unwrap();
+    let membership = common::v1::types::RoomMembership::Join {
+        override_name: None,
+        override_description: None,
+        roles: vec![],
+    };
+    s.data()
+        .room_member_put(room_id, user_id, membership)
+        .await
+        .unwrap();
+    s.data().role_apply_default(room_id, user_id).await.unwrap();
+    let create = || -> MessageCreate {
+        serde_json::from_value(serde_json::json!({ "content": "hello" })).unwrap()
+    };
+    let count = || async {
+        s.data()
+            .message_list(thread_id, user_id, PaginationQuery::default())
+            .await
+            .unwrap()
+            .items
+            .len()
+    };
+    let before = count().await;
+
+    // the last write is adding the author to the thread
+    let failure = inject_failure(
+        &s,
+        "INSERT",
+        "thread_member",
+        "user_id",
+        &user_id.to_string(),
+    )
+    .await;
+    let res = s
+        .services
+        .messages
+        .create(thread_id, user_id, None, None, create())
+        .await;
+    remove_failure(&s, "thread_member", &failure).await;
+    assert!(res.is_err());
+    assert_eq!(count().await, before);
+
+    s.services
+        .messages
+        .create(thread_id, user_id, None, None, create())
+        .await
+        .expect("failed to create message");
+    assert_eq!(count().await, before + 1);
+}
+
+#[tokio::test]
+async fn failed_message_edit_persists_nothing() {
+    let s = node(&database_url()).await;
+    let user_id = create_user(&s).await;
+    let thread_id = create_thread(&s, user_id, DbThreadType::Chat).await;
+    let create: MessageCreate =
+        serde_json::from_value(serde_json::json!({ "content": "hello" })).unwrap();
+    let message = s
+        .services
+        .messages
+        .create(thread_id, user_id, None, None, create)
+        .await
+        .expect("failed to create message");
+    let content = || async {
+        let message = s
+            .data()
+            .message_get(thread_id, message.id, user_id)
+            .await
+            .expect("message should exist");
+        match message.message_type {
+            MessageType::DefaultMarkdown(m) => m.content,
+            _ => panic!("wrong message type"),
+        }
+    };
+    let patch = || -> MessagePatch {
+        serde_json::from_value(serde_json::json!({ "content": "edited" })).unwrap()
+    };
+
+    // the old version is marked as outdated before the new one is inserted
+    let failure = inject_failure(&s, "INSERT", "message", "id", &message.id.to_string()).await;
+    let res = s
+        .services
+        .messages
+        .edit(thread_id, message.id, user_id, None, patch())
+        .await;
+    remove_failure(&s, "message", &failure).await;
+    assert!(res.is_err());
+    assert_eq!(content().await.as_deref(), Some("hello"));
+
+    s.services
+        .messages
+        .edit(thread_id, message.id, user_id, None, patch())
+        .await
+        .expect("failed to edit message");
+    assert_eq!(content().await.as_deref(), Some("edited"));
+}
@@ -0,0 +1,254 @@
+//! shared setup for tests that need a database
+
+// not every test uses every helper
+#![allow(dead_code)]
+
+use std::{net::Ipv4Addr, sync::Arc, time::Duration};
+
+use backend::{
+    config::Config,
+    data::Database,
+    routes,
+    types::{DbMessageCreate, DbThreadCreate, DbThreadType, DbUserCreate},
+    ServerState,
+};
+use common::v1::types::{
+    Mentions, MessageCreate, MessageId, MessageThreadUpdate, MessageType, RoomCreate, RoomId,
+    SessionStatus, SessionToken, ThreadId, ThreadPatch, UserId,
+};
+use figment::providers::{Format, Toml};
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{tungstenite::Message as WsMessage, MaybeTlsStream, WebSocketStream};
+use uuid::Uuid;
+
+/// a client's sync connection
+pub type Ws = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// get the postgres database to run tests against
+///
+/// panics if DATABASE_URL isn't set, so postgres-only tests can't silently pass
+pub fn database_url() -> String {
+    let _ = dotenvy::dotenv();
+    std::env::var("DATABASE_URL").expect("DATABASE_URL must be set to test against postgres")
+}
+
+/// every database to run tests against
+///
+/// includes the in-memory database with the `memory` feature, and postgres if
+/// DATABASE_URL is set. panics if that leaves nothing to test against.
+pub fn database_urls() -> Vec<String> {
+    let _ = dotenvy::dotenv();
+    let mut urls = vec![];
+    if cfg!(feature = "memory") {
+        urls.push("memory://".to_owned());
+    }
+    urls.extend(std::env::var("DATABASE_URL").ok());
+    assert!(
+        !urls.is_empty(),
+        "no database to test against, set DATABASE_URL or enable the memory feature",
+    );
+    urls
+}
+
+pub fn config(database_url: &str) -> Config {
+    let toml = format!(
+        r#"
+        rust_log = "info"
+        database_url = "{database_url}"
+        api_url = "http://localhost:4000"
+        cdn_url = "http://localhost:4000"
+        event_bus = "postgres"
+
+        [sfu_tokens]
+        test = "test"
+
+        [s3]
+        bucket = "test"
+        endpoint = "http://localhost:9000"
+        region = "test"
+        access_key_id = "test"
+        secret_access_key = "test"
+
+        [oauth_provider]
+
+        [url_preview]
+        user_agent = "test"
+        deny = []
+        max_parallel_jobs = 1
+
+        [smtp]
+        username = "test"
+        password = "test"
+        host = "localhost"
+        from = "test@localhost"
+        "#
+    );
+    figment::Figment::new()
+        .merge(Toml::string(&toml))
+        .extract()
+        .expect("test config is valid")
+}
+
+pub async fn node(database_url: &str) -> ServerState {
+    let db = Database::connect(database_url)
+        .await
+        .expect("failed to connect to database");
+    node_with(db, config(database_url))
+}
+
+/// create a node with an existing database and custom config
+pub fn node_with(db: Database, config: Config) -> ServerState {
+    let blobs = opendal::Operator::new(opendal::services::Memory::default())
+        .expect("failed to create blob storage")
+        .finish();
+    ServerState::new(config, db, blobs)
+}
+
+/// serve a node's api routes on a local port, returning the base url
+pub async fn serve(s: Arc<ServerState>) -> String {
+    let (router, _) = routes::api().with_state(s).split_for_parts();
+    let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .await
+        .expect("failed to bind");
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    url
+}
+
+/// open a sync connection to a node, returning the socket
+pub async fn sync(s: Arc<ServerState>) -> Ws {
+    sync_with(s, "version=1").await
+}
+
+/// open a sync connection to a node with custom query parameters
+pub async fn sync_with(s: Arc<ServerState>, query: &str) -> Ws {
+    let (router, _) = routes::syncer().with_state(s).split_for_parts();
+    let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .await
+        .unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    let (ws, _) = tokio_tungstenite::connect_async(format!("ws://{addr}/sync?{query}"))
+        .await
+        .unwrap();
+    ws
+}
+
+/// send a message and wait for a reply with a specific op
+pub async fn exchange(ws: &mut Ws, msg: serde_json::Value, op: &str) -> serde_json::Value {
+    ws.send(WsMessage::text(msg.to_string())).await.unwrap();
+    let reply = async {
+        loop {
+            let WsMessage::Text(text) = ws.next().await.unwrap().unwrap() else {
+                continue;
+            };
+            let reply: serde_json::Value = serde_json::from_str(&text).unwrap();
+            if reply["op"] == op {
+                return reply;
+            }
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(5), reply)
+        .await
+        .unwrap_or_else(|_| panic!("no {op} reply"))
+}
+
+/// create an authorized session for a user
+pub async fn login(s: &ServerState, user_id: UserId) -> SessionToken {
+    let data = s.data();
+    let token = SessionToken(Uuid::new_v4().to_string());
+    let session = data
+        .session_create(token.clone(), None)
+        .await
+        .expect("failed to create session");
+    data.session_set_status(session.id, SessionStatus::Authorized { user_id })
+        .await
+        .expect("failed to authorize session");
+    token
+}
+
+pub async fn create_user(s: &ServerState) -> UserId {
+    s.data()
+        .user_create(DbUserCreate {
+            parent_id: None,
+            name: "test".to_owned(),
+            description: None,
+            bot: None,
+            puppet: None,
+            registered_at: None,
+        })
+        .await
+        .expect("failed to create user")
+        .id
+}
+
+/// create a thread of the given type in a new room
+pub async fn create_thread(s: &ServerState, user_id: UserId, ty: DbThreadType) -> ThreadId {
+    let room = s
+        .services
+        .rooms
+        .create(
+            RoomCreate {
+                name: "test".to_owned(),
+                description: None,
+                icon: None,
+            },
+            user_id,
+        )
+        .await
+        .expect("failed to create room");
+    create_thread_in(s, user_id, room.id, ty).await
+}
+
+/// create a thread of the given type in an existing room
+pub async fn create_thread_in(
+    s: &ServerState,
+    user_id: UserId,
+    room_id: RoomId,
+    ty: DbThreadType,
+) -> ThreadId {
+    let data = s.data();
+    let thread_id = data
+        .thread_create(DbThreadCreate {
+            room_id: Some(*room_id),
+            creator_id: user_id,
+            name: "test".to_owned(),
+            description: None,
+            ty,
+        })
+        .await
+        .expect("failed to create thread");
+    // threads are created with a starter message, and can't be fetched without one
+    data.message_create(DbMessageCreate {
+        thread_id,
+        attachment_ids: vec![],
+        author_id: user_id,
+        embeds: vec![],
+        message_type: MessageType::ThreadUpdate(MessageThreadUpdate {
+            patch: ThreadPatch {
+                name: Some("test".to_owned()),
+                description: None,
+                tags: None,
+            },
+        }),
+        mentions: Mentions::default(),
+        edited_at: None,
+        created_at: None,
+    })
+    .await
+    .expect("failed to create starter message");
+    thread_id
+}
+
+/// send a message as a user
+pub async fn create_message(s: &ServerState, thread_id: ThreadId, user_id: UserId) -> MessageId {
+    let create: MessageCreate = serde_json::from_value(serde_json::json!({ "content": "hello" }))
+        .expect("message is valid");
+    s.services
+        .messages
+        .create(thread_id, user_id, None, None, create)
+        .await
+        .expect("failed to create message")
+        .id
+}
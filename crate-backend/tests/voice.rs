@@ -0,0 +1,671 @@
+//! picking sfus to host voice calls
+
+use std::{net::Ipv4Addr, sync::Arc, time::Duration};
+
+use axum::{
+    extract::Path,
+    http::StatusCode,
+    routing::{get, post},
+    Json,
+};
+use backend::{
+    data::Database,
+    types::{DbRoleCreate, DbSfu, DbThreadType, DbVoiceModeration},
+    Error, ServerState,
+};
+use common::v1::types::{
+    thread::voice::ThreadTypeVoicePublic,
+    util::Time,
+    voice::{SignallingMessage, VoiceRegion, VoiceState, VoiceStateUpdate},
+    Permission, PermissionOverwriteType, RoomMembership, SessionStatus, SessionToken, ThreadId,
+    ThreadPublic, UserId,
+};
+use futures_util::{SinkExt, StreamExt};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use util::{
+    create_thread, create_thread_in, create_user, database_urls, exchange, login, node, node_with,
+    serve, sync,
+};
+use uuid::Uuid;
+
+mod util;
+
+async fn register(s: &ServerState, url: &str, region: &str, load: f32) -> Uuid {
+    let id = Uuid::now_v7();
+    s.services
+        .voice
+        .sfu_register(DbSfu {
+            id,
+            url: url.to_owned(),
+            region: region.to_owned(),
+            load,
+            token_name: "test".to_owned(),
+        })
+        .await
+        .expect("failed to register sfu");
+    id
+}
+
+#[tokio::test]
+async fn calls_are_hosted_in_the_requested_region() {
+    for url in database_urls() {
+        let s = node(&url).await;
+        let user_id = create_user(&s).await;
+        // other tests may register sfus in the same database
+        let mars = format!("mars-{}", Uuid::new_v4());
+        let venus = format!("venus-{}", Uuid::new_v4());
+        let busy = register(&s, &format!("http://{mars}"), &mars, 0.9).await;
+        let idle = register(&s, &format!("http://{venus}"), &venus, 0.0).await;
+
+        let regions = s.services.voice.regions().await.unwrap();
+        assert!(regions.contains(&VoiceRegion { id: mars.clone() }));
+
+        let thread_id = create_thread(&s, user_id, DbThreadType::Voice).await;
+        let sfu = s
+            .services
+            .voice
+            .sfu_for_thread(thread_id, Some(&mars))
+            .await
+            .unwrap();
+        assert_eq!(sfu.id, busy);
+
+        // the call stays where it is once it's started
+        let sfu = s
+            .services
+            .voice
+            .sfu_for_thread(thread_id, Some(&venus))
+            .await
+            .unwrap();
+        assert_eq!(sfu.id, busy);
+
+        // falls back to the least loaded sfu anywhere
+        let thread_id = create_thread(&s, user_id, DbThreadType::Voice).await;
+        let sfu = s
+            .services
+            .voice
+            .sfu_for_thread(thread_id, Some("jupiter"))
+            .await
+            .unwrap();
+        assert!(sfu.id == idle || sfu.load == 0.0);
+    }
+}
+
+#[tokio::test]
+async fn calls_move_when_their_sfu_restarts() {
+    for url in database_urls() {
+        let s = node(&url).await;
+        let user_id = create_user(&s).await;
+        let region = format!("mars-{}", Uuid::new_v4());
+        let sfu_url = format!("http://{region}");
+        let old = register(&s, &sfu_url, &region, 0.9).await;
+        let thread_id = create_thread(&s, user_id, DbThreadType::Voice).await;
+        let sfu = s
+            .services
+            .voice
+            .sfu_for_thread(thread_id, Some(&region))
+            .await
+            .unwrap();
+        assert_eq!(sfu.id, old);
+
+        let new = register(&s, &sfu_url, &region, 0.9).await;
+        assert!(s.services.voice.sfu_get(old).await.is_err());
+        let sfu = s
+            .services
+            .voice
+            .sfu_for_thread(thread_id, Some(&region))
+            .await
+            .unwrap();
+        assert_eq!(sfu.id, new);
+    }
+}
+
+#[tokio::test]
+async fn sfus_authenticate_with_any_configured_token() {
+    for url in database_urls() {
+        let mut config = util::config(&url);
+        config
+            .sfu_tokens
+            .insert("other".to_owned(), "hunter2".to_owned());
+        assert_eq!(config.sfu_token_name("Server test"), Some("test"));
+        assert_eq!(config.sfu_token_name("Server hunter2"), Some("other"));
+        assert_eq!(config.sfu_token_name("Server hunter"), None);
+        assert_eq!(config.sfu_token_name("hunter2"), None);
+
+        // sfus using a removed token don't get calls
+        let s = node(&url).await;
+        let region = format!("pluto-{}", Uuid::new_v4());
+        s.services
+            .voice
+            .sfu_register(DbSfu {
+                id: Uuid::now_v7(),
+                url: format!("http://{region}"),
+                region: region.clone(),
+                load: 0.0,
+                token_name: "removed".to_owned(),
+            })
+            .await
+            .expect("failed to register sfu");
+        let regions = s.services.voice.regions().await.unwrap();
+        assert!(!regions.contains(&VoiceRegion { id: region }));
+    }
+}
+
+fn voice_state(user_id: UserId, thread_id: ThreadId) -> VoiceState {
+    VoiceState {
+        user_id,
+        thread_id,
+        joined_at: Time::now_utc(),
+        subscribed: None,
+        mute: false,
+        deaf: false,
+        self_mute: false,
+        self_deaf: false,
+        suppress: false,
+        priority: false,
+    }
+}
+
+/// pretend to be an sfu where one user is in a call
+///
+/// returns the sfu's url and every rpc command sent to it
+async fn fake_sfu(state: VoiceState) -> (String, mpsc::UnboundedReceiver<serde_json::Value>) {
+    let (send, recv) = mpsc::unbounded_channel();
+    let members = state.clone();
+    let router = axum::Router::new()
+        .route(
+            "/rpc",
+            post(|Json(command): Json<serde_json::Value>| async move {
+                _ = send.send(command);
+                StatusCode::ACCEPTED
+            }),
+        )
+        .route(
+            "/voice-state/{user_id}",
+            get(|Path(user_id): Path<UserId>| async move {
+                if user_id == state.user_id {
+                    Ok(Json(state))
+                } else {
+                    Err(StatusCode::NOT_FOUND)
+                }
+            }),
+        )
+        .route(
+            "/thread/{thread_id}/voice-state",
+            get(|Path(thread_id): Path<ThreadId>| async move {
+                let members: Vec<VoiceState> = [members]
+                    .into_iter()
+                    .filter(|m| m.thread_id == thread_id)
+                    .collect();
+                Json(members)
+            }),
+        );
+    let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .await
+        .unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    (url, recv)
+}
+
+#[tokio::test]
+async fn moderation_is_sent_to_the_sfu() {
+    for url in database_urls() {
+        let s = node(&url).await;
+        let user_id = create_user(&s).await;
+        let thread_id = create_thread(&s, user_id, DbThreadType::Voice).await;
+        let (sfu_url, mut commands) = fake_sfu(voice_state(user_id, thread_id)).await;
+        let region = format!("mars-{}", Uuid::new_v4());
+        let sfu_id = register(&s, &sfu_url, &region, 0.0).await;
+        let voice = &s.services.voice;
+
+        // nobody is in the call yet
+        assert!(matches!(
+            voice.sfu_thread(thread_id).await,
+            Err(Error::NotFound)
+        ));
+        voice
+            .sfu_for_thread(thread_id, Some(&region))
+            .await
+            .unwrap();
+        let sfu = voice.sfu_thread(thread_id).await.unwrap();
+        assert_eq!(sfu.id, sfu_id);
+
+        let state = voice.sfu_voice_state(&sfu, user_id).await.unwrap();
+        assert_eq!(state.thread_id, thread_id);
+        let other: UserId = Uuid::now_v7().into();
+        assert!(matches!(
+            voice.sfu_voice_state(&sfu, other).await,
+            Err(Error::NotFound)
+        ));
+
+        voice
+            .sfu_moderate(&sfu, user_id, true, false)
+            .await
+            .unwrap();
+        let command = commands.recv().await.unwrap();
+        assert_eq!(
+            command,
+            serde_json::json!({
+                "user_id": null,
+                "type": "Moderate",
+                "target_id": user_id,
+                "mute": true,
+                "deaf": false,
+            })
+        );
+    }
+}
+
+#[tokio::test]
+async fn joining_needs_permission_and_room() {
+    for url in database_urls() {
+        let s = node(&url).await;
+        let user_id = create_user(&s).await;
+        let stranger = create_user(&s).await;
+        let thread_id = create_thread(&s, user_id, DbThreadType::Voice).await;
+        let (sfu_url, _) = fake_sfu(voice_state(user_id, thread_id)).await;
+        let region = format!("mars-{}", Uuid::new_v4());
+        register(&s, &sfu_url, &region, 0.0).await;
+        let voice = &s.services.voice;
+
+        let permissions = voice.permissions(user_id, thread_id).await.unwrap();
+        assert!(permissions.contains(&Permission::VoiceConnect));
+        assert!(permissions.contains(&Permission::VoiceSpeak));
+
+        // people outside the room can't tell the thread exists
+        assert!(matches!(
+            voice.permissions(stranger, thread_id).await,
+            Err(Error::NotFound)
+        ));
+
+        let sfu = voice
+            .sfu_for_thread(thread_id, Some(&region))
+            .await
+            .unwrap();
+        let full = ThreadTypeVoicePublic {
+            bitrate: 64000,
+            user_limit: 1,
+        };
+        let other: UserId = Uuid::now_v7().into();
+        assert!(matches!(
+            voice.ensure_capacity(&sfu, other, thread_id, &full).await,
+            Err(Error::BadStatic(_))
+        ));
+
+        // people already in the call can always rejoin
+        voice
+            .ensure_capacity(&sfu, user_id, thread_id, &full)
+            .await
+            .unwrap();
+        let roomy = ThreadTypeVoicePublic {
+            bitrate: 64000,
+            user_limit: 2,
+        };
+        voice
+            .ensure_capacity(&sfu, other, thread_id, &roomy)
+            .await
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn voice_is_only_dispatched_as_the_session_user() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let user_id = create_user(&s).await;
+        let victim = create_user(&s).await;
+        let token = SessionToken(Uuid::new_v4().to_string());
+        let session = s.data().session_create(token.clone(), None).await.unwrap();
+        s.data()
+            .session_set_status(session.id, SessionStatus::Authorized { user_id })
+            .await
+            .unwrap();
+        let leave = serde_json::json!({ "type": "VoiceState", "state": null });
+
+        let mut ws = sync(s.clone()).await;
+        let dispatch = serde_json::json!({
+            "type": "VoiceDispatch",
+            "user_id": victim,
+            "payload": leave,
+        });
+        let reply = exchange(&mut ws, dispatch, "Error").await;
+        assert_eq!(reply["error"], Error::MissingAuth.to_string());
+
+        let mut ws = sync(s).await;
+        let hello = serde_json::json!({ "type": "Hello", "token": token });
+        exchange(&mut ws, hello, "Ready").await;
+        let dispatch = serde_json::json!({
+            "type": "VoiceDispatch",
+            "user_id": victim,
+            "payload": leave,
+        });
+        let reply = exchange(&mut ws, dispatch, "Error").await;
+        let err = Error::BadStatic("can't send voice messages for other users");
+        assert_eq!(reply["error"], err.to_string());
+    }
+}
+
+#[tokio::test]
+async fn moderation_is_remembered_between_calls() {
+    for url in database_urls() {
+        let s = node(&url).await;
+        let user_id = create_user(&s).await;
+        let stranger = create_user(&s).await;
+        let thread_id = create_thread(&s, user_id, DbThreadType::Voice).await;
+        let (sfu_url, mut commands) = fake_sfu(voice_state(user_id, thread_id)).await;
+        let region = format!("mars-{}", Uuid::new_v4());
+        register(&s, &sfu_url, &region, 0.0).await;
+        let voice = &s.services.voice;
+        let thread = s
+            .services
+            .threads
+            .get(thread_id, Some(user_id))
+            .await
+            .unwrap();
+        let room_id = thread.room_id.unwrap();
+        assert_eq!(
+            voice.moderation(user_id, &thread).await.unwrap(),
+            DbVoiceModeration::default()
+        );
+
+        let muted = DbVoiceModeration {
+            mute: true,
+            deaf: false,
+        };
+        s.data()
+            .voice_moderation_set(room_id, user_id, muted)
+            .await
+            .unwrap();
+        assert!(matches!(
+            s.data()
+                .voice_moderation_set(room_id, stranger, muted)
+                .await,
+            Err(Error::NotFound)
+        ));
+        let moderation = voice.moderation(user_id, &thread).await.unwrap();
+        assert_eq!(moderation, muted);
+
+        // rejoining doesn't clear it
+        let sfu = voice
+            .sfu_for_thread(thread_id, Some(&region))
+            .await
+            .unwrap();
+        let ThreadPublic::Voice(info) = &thread.info else {
+            panic!("not a voice thread");
+        };
+        let join = SignallingMessage::VoiceState {
+            state: Some(VoiceStateUpdate {
+                thread_id,
+                region: None,
+                self_mute: false,
+                self_deaf: false,
+            }),
+        };
+        voice
+            .sfu_join(&sfu, user_id, &join, info, &[], moderation)
+            .await
+            .unwrap();
+        let command = commands.recv().await.unwrap();
+        assert_eq!(
+            command["moderation"],
+            serde_json::json!({ "mute": true, "deaf": false })
+        );
+    }
+}
+
+#[tokio::test]
+async fn rejected_joins_keep_the_connection_open() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let owner = create_user(&s).await;
+        let user_id = create_user(&s).await;
+        let thread_id = create_thread(&s, owner, DbThreadType::Voice).await;
+        let room_id = s
+            .data()
+            .thread_get(thread_id)
+            .await
+            .unwrap()
+            .room_id
+            .unwrap();
+        let membership = RoomMembership::Join {
+            override_name: None,
+            override_description: None,
+            roles: vec![],
+        };
+        s.data()
+            .room_member_put(room_id, user_id, membership)
+            .await
+            .unwrap();
+        s.data().role_apply_default(room_id, user_id).await.unwrap();
+        s.services
+            .perms
+            .permission_overwrite_upsert(
+                thread_id,
+                *user_id,
+                PermissionOverwriteType::User,
+                vec![],
+                vec![Permission::VoiceConnect],
+            )
+            .await
+            .unwrap();
+
+        let mut ws = sync(s.clone()).await;
+        let hello = serde_json::json!({ "type": "Hello", "token": login(&s, user_id).await });
+        exchange(&mut ws, hello, "Ready").await;
+        let dispatch = serde_json::json!({
+            "type": "VoiceDispatch",
+            "user_id": user_id,
+            "payload": { "type": "VoiceState", "state": { "thread_id": thread_id } },
+        });
+        let reply = exchange(&mut ws, dispatch, "Error").await;
+        assert_eq!(reply["error"], Error::MissingPermissions.to_string());
+
+        // the connection is still usable afterwards
+        let status = serde_json::json!({ "type": "Status", "status": { "type": "Away" } });
+        ws.send(WsMessage::text(status.to_string())).await.unwrap();
+        let updated = async {
+            loop {
+                let WsMessage::Text(text) = ws.next().await.unwrap().unwrap() else {
+                    continue;
+                };
+                let msg: serde_json::Value = serde_json::from_str(&text).unwrap();
+                assert_ne!(msg["op"], "Reconnect");
+                if msg["data"]["type"] == "UserUpdate" {
+                    break;
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), updated)
+            .await
+            .expect("no UserUpdate event");
+    }
+}
+
+#[tokio::test]
+async fn sfus_only_control_calls_hosted_with_their_token() {
+    for url in database_urls() {
+        let db = Database::connect(&url)
+            .await
+            .expect("failed to connect to database");
+        let mut config = util::config(&url);
+        config
+            .sfu_tokens
+            .insert("other".to_owned(), "hunter2".to_owned());
+        let s = Arc::new(node_with(db, config));
+        let user_id = create_user(&s).await;
+        let thread_id = create_thread(&s, user_id, DbThreadType::Voice).await;
+        let region = format!("ceres-{}", Uuid::new_v4());
+        let sfu_url = format!("http://{region}");
+        let sfu_id = register(&s, &sfu_url, &region, 0.0).await;
+        s.services
+            .voice
+            .sfu_for_thread(thread_id, Some(&region))
+            .await
+            .unwrap();
+
+        // the sfu can't be taken over with another token
+        for (id, url) in [
+            (sfu_id, format!("http://{region}-2")),
+            (Uuid::now_v7(), sfu_url),
+        ] {
+            let takeover = s
+                .services
+                .voice
+                .sfu_register(DbSfu {
+                    id,
+                    url,
+                    region: region.clone(),
+                    load: 0.0,
+                    token_name: "other".to_owned(),
+                })
+                .await;
+            assert!(matches!(takeover, Err(Error::MissingPermissions)));
+        }
+        assert_eq!(
+            s.services.voice.sfu_thread(thread_id).await.unwrap().id,
+            sfu_id
+        );
+
+        // or report on calls it doesn't host
+        let base = serve(s.clone()).await;
+        let http = reqwest::Client::new();
+        let rpc = serde_json::json!({
+            "type": "VoiceState",
+            "user_id": user_id,
+            "old": null,
+            "state": voice_state(user_id, thread_id),
+        });
+        for (token, status) in [
+            ("hunter2", StatusCode::FORBIDDEN),
+            ("test", StatusCode::ACCEPTED),
+        ] {
+            let res = http
+                .post(format!("{base}/internal/rpc"))
+                .header("authorization", format!("Server {token}"))
+                .json(&rpc)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(res.status(), status);
+        }
+
+        // or signal people in calls it doesn't host
+        let send = |token: &'static str, rpc: serde_json::Value| {
+            let req = http
+                .post(format!("{base}/internal/rpc"))
+                .header("authorization", format!("Server {token}"))
+                .json(&rpc);
+            async move { req.send().await.unwrap().status() }
+        };
+        let dispatch = |user_id: UserId| {
+            serde_json::json!({
+                "type": "VoiceDispatch",
+                "user_id": user_id,
+                "payload": { "type": "Want", "tracks": [] },
+            })
+        };
+        let stranger = create_user(&s).await;
+        assert_eq!(
+            send("hunter2", dispatch(user_id)).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            send("test", dispatch(stranger)).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(send("test", dispatch(user_id)).await, StatusCode::ACCEPTED);
+        let leave = serde_json::json!({
+            "type": "VoiceState",
+            "user_id": user_id,
+            "old": voice_state(user_id, thread_id),
+            "state": null,
+        });
+        assert_eq!(send("test", leave).await, StatusCode::ACCEPTED);
+        assert_eq!(send("test", dispatch(user_id)).await, StatusCode::FORBIDDEN);
+    }
+}
+
+#[tokio::test]
+async fn moderators_only_moderate_members_they_outrank() {
+    for url in database_urls() {
+        let s = Arc::new(node(&url).await);
+        let owner = create_user(&s).await;
+        let moderator = create_user(&s).await;
+        let member = create_user(&s).await;
+        let thread_id = create_thread(&s, owner, DbThreadType::Voice).await;
+        let room_id = s
+            .data()
+            .thread_get(thread_id)
+            .await
+            .unwrap()
+            .room_id
+            .unwrap();
+        let other_thread = create_thread_in(&s, owner, room_id, DbThreadType::Voice).await;
+        let role = s
+            .data()
+            .role_create(DbRoleCreate {
+                room_id,
+                name: "voice moderator".to_owned(),
+                description: None,
+                permissions: vec![
+                    Permission::VoiceMute,
+                    Permission::VoiceDeafen,
+                    Permission::VoiceDisconnect,
+                    Permission::VoiceMove,
+                ],
+                is_self_applicable: false,
+                is_mentionable: false,
+                is_default: false,
+                // above the default role everyone has
+                position: 1,
+            })
+            .await
+            .unwrap();
+        for user_id in [moderator, member] {
+            let membership = RoomMembership::Join {
+                override_name: None,
+                override_description: None,
+                roles: vec![],
+            };
+            s.data()
+                .room_member_put(room_id, user_id, membership)
+                .await
+                .unwrap();
+            s.data().role_apply_default(room_id, user_id).await.unwrap();
+        }
+        s.data().role_member_put(moderator, role.id).await.unwrap();
+        let (sfu_url, _) = fake_sfu(voice_state(member, thread_id)).await;
+        let region = format!("io-{}", Uuid::new_v4());
+        register(&s, &sfu_url, &region, 0.0).await;
+        for id in [thread_id, other_thread] {
+            s.services
+                .voice
+                .sfu_for_thread(id, Some(&region))
+                .await
+                .unwrap();
+        }
+
+        let api = serve(s.clone()).await;
+        let http = reqwest::Client::new();
+        let token = login(&s, moderator).await;
+        let mute = serde_json::json!({ "mute": true });
+        let moves = serde_json::json!({ "thread_id": other_thread });
+        for target in [owner, member] {
+            let url = format!("{api}/voice/{thread_id}/member/{target}");
+            let requests = [
+                http.patch(&url).json(&mute),
+                http.delete(&url),
+                http.post(&url).json(&moves),
+            ];
+            for req in requests {
+                let status = req.bearer_auth(&token.0).send().await.unwrap().status();
+                if target == owner {
+                    assert_eq!(status, StatusCode::FORBIDDEN);
+                } else {
+                    assert!(status.is_success(), "{status}");
+                }
+            }
+        }
+    }
+}
@@ -51,8 +51,12 @@ pub struct Message {
 
     pub mentions: Mentions,
 
-    // pub pinned_at: Option<Time>,
-    // pub pinned_order: Option<u8>,
+    /// when this message was pinned, if it's pinned
+    pub pinned_at: Option<Time>,
+
+    /// where this message is in the thread's pin list, starting from 0
+    pub pinned_order: Option<u8>,
+
     // pub moved_at: Option<Time>,
     // pub moved_from: Option<(ThreadId, MessageId)>,
     pub created_at: Option<Time>,
@@ -64,13 +68,13 @@ pub struct Message {
 }
 
 /// who/what this message notified on send
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[cfg_attr(feature = "utoipa", derive(ToSchema))]
 pub struct Mentions {
     pub users: Vec<UserId>,
     pub roles: Vec<RoleId>,
     pub threads: Vec<ThreadId>,
-    pub rooms: Vec<ThreadId>,
+    pub rooms: Vec<RoomId>,
 
     /// if this mentioned everyone in the room
     pub all_in_room: bool,
@@ -215,14 +219,14 @@ pub enum MessageType {
     /// (TODO) a message copied from somewhere else
     Forward(MessageDefaultTagged),
 
-    /// (TODO) a message was pinned
+    /// a message was pinned
     MessagePinned(MessagePin),
 
-    /// (TODO) a message was unpinned
+    /// a message was unpinned
     MessageUnpinned(MessagePin),
 
     #[cfg(feature = "feat_message_move")]
-    /// (TODO) one or more messages were moved
+    /// one or more messages were moved
     MessagesMoved(MessagesMoved),
 
     /// (TODO) a member was added to the thread (what about room?)
@@ -261,8 +265,7 @@ pub enum MessageType {
     #[cfg(feature = "feat_automod")]
     ModerationAuto(MessageModerationAuto),
 
-    /// (TODO) implement a reporting system? uncertain (reports are certain, but reports-as-messages vs as-threads idk)
-    // #[deprecated = "reports will be impl'd as threads"]
+    /// a report filed in a report thread
     ModerationReport(MessageModerationReport),
 
     /// (TODO) important message from the system/server
@@ -280,6 +283,17 @@ pub struct MessagePin {
     pub reason: Option<String>,
 }
 
+/// reorder the pinned messages in a thread
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(ToSchema))]
+#[cfg_attr(feature = "validator", derive(Validate))]
+pub struct PinsReorder {
+    /// every pinned message in the thread, in the new order
+    #[cfg_attr(feature = "utoipa", schema(max_length = 50))]
+    #[cfg_attr(feature = "validator", validate(length(max = 50)))]
+    pub messages: Vec<MessageId>,
+}
+
 /// Information about a thread being updated
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[cfg_attr(feature = "utoipa", derive(ToSchema))]
@@ -303,7 +317,7 @@ pub struct MessageThreadPingback {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[cfg_attr(feature = "utoipa", derive(ToSchema))]
 pub struct MessagesMoved {
-    // do messages keep their ids when being moved?
+    // messages keep their ids when being moved
     pub start_id: MessageId,
     pub end_id: MessageId,
     pub source_id: ThreadId,
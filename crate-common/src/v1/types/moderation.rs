@@ -150,6 +150,15 @@ pub struct ReportCreate {
     pub destination: ReportDestination,
 }
 
+/// update a report thread
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(ToSchema))]
+pub struct ReportPatch {
+    /// move the report to this status. only open reports receive new duplicate reports.
+    #[cfg_attr(feature = "utoipa", schema(required = false))]
+    pub status: Option<ReportStatus>,
+}
+
 impl ReportStatus {
     pub fn is_active(&self) -> bool {
         *self == ReportStatus::Open
@@ -3,7 +3,10 @@ use serde::{Deserialize, Serialize};
 #[cfg(feature = "utoipa")]
 use utoipa::ToSchema;
 
-use crate::v1::types::{util::Time, MessageId, NotificationId, ThreadId};
+#[cfg(feature = "validator")]
+use validator::Validate;
+
+use crate::v1::types::{util::Time, MessageId, NotificationId, RoomId, ThreadId};
 
 /// how to handle an event
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -24,11 +27,23 @@ pub enum NotifAction {
 #[cfg_attr(feature = "utoipa", derive(ToSchema))]
 pub struct NotifsGlobal {
     pub mute: Option<Mute>,
+
+    /// new messages anywhere
     pub messages: NotifAction,
+
+    /// messages that mention or reply to you
     pub mentions: NotifAction,
+
+    /// the first message in a new thread in a room
     pub threads: NotifAction,
+
+    /// new messages in rooms, if louder than `messages`
     pub room_public: NotifAction,
+
+    /// new messages in threads outside of rooms, if louder than `messages`
     pub room_private: NotifAction,
+
+    /// new direct messages, if louder than `messages`
     pub room_dm: NotifAction,
 }
 
@@ -75,6 +90,9 @@ pub struct Mute {
 pub struct Notification {
     pub id: NotificationId,
 
+    /// the room this message was sent in
+    pub room_id: Option<RoomId>,
+
     /// the thread this message was sent in
     pub thread_id: ThreadId,
 
@@ -86,6 +104,19 @@ pub struct Notification {
 
     /// when this was created
     pub created_at: Time,
+
+    /// when this was marked as read
+    pub read_at: Option<Time>,
+}
+
+/// mark notifications as read or unread
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(ToSchema))]
+#[cfg_attr(feature = "validator", derive(Validate))]
+pub struct NotificationMarkRead {
+    #[cfg_attr(feature = "utoipa", schema(min_length = 1, max_length = 1024))]
+    #[cfg_attr(feature = "validator", validate(length(min = 1, max = 1024)))]
+    pub notification_ids: Vec<NotificationId>,
 }
 
 // in order of precedence
@@ -63,10 +63,10 @@ pub enum Permission {
     /// implies InviteCreate
     InviteManage,
 
-    /// (unimplemented) ban members and unban members they have banned
+    /// ban members and unban members they have banned
     MemberBan,
 
-    /// (unimplemented) unban any member
+    /// unban any member
     /// implies MemberBan
     // TODO: remove, not worth it
     MemberBanManage,
@@ -102,14 +102,14 @@ pub enum Permission {
     /// requires MessageCreate
     MessageEmbeds,
 
-    /// (unimplemented) mention @room, @thread, and all roles
+    /// mention @room, @thread, and all roles
     /// requires MessageCreate
     MessageMassMention,
 
-    /// (unimplemented) move messages between threads
+    /// move messages between threads
     MessageMove,
 
-    /// (unimplemented) pin (and unpin) messages
+    /// pin, unpin, and reorder pinned messages
     MessagePin,
 
     /// (unimplemented) use custom avatar (otherwise use default avatar)
@@ -149,11 +149,11 @@ pub enum Permission {
     /// (server) access reports
     ServerReports,
 
-    /// (unimplemented) apply tags to threads
+    /// apply tags to threads
     /// applying tags to rooms would probably be a RoomEdit thing
     TagApply,
 
-    /// (unimplemented) create and delete tags
+    /// create, edit, and delete tags
     TagManage,
 
     /// archive (and unarchive) threads
@@ -200,7 +200,7 @@ pub enum Permission {
     /// (unimplemented) lock (and unlock) threads
     ThreadLock,
 
-    /// (unimplemented) pin (and unpin) threads
+    /// pin (and unpin) threads
     ThreadPin,
 
     /// (unimplemented) create announcements
@@ -226,33 +226,33 @@ pub enum Permission {
     /// view audit log
     ViewAuditLog,
 
-    /// (unimplemented) connect and listen to voice threads
+    /// connect and listen to voice threads
     VoiceConnect,
 
-    /// (unimplemented) stop someone from listening
+    /// stop someone from listening
     // remove?
     VoiceDeafen,
 
-    /// (unimplemented) disconnect members from voice threads
+    /// disconnect members from voice threads
     // merge with VoiceMove?
     VoiceDisconnect,
 
-    /// (unimplemented) move members between voice threads
+    /// move members between voice threads
     VoiceMove,
 
-    /// (unimplemented) stop someone from talking
+    /// stop someone from talking
     // remove?
     VoiceMute,
 
-    /// (unimplemented) talk louder
+    /// talk louder
     /// requires VoiceSpeak
     VoicePriority,
 
-    /// (unimplemented) talk in voice threads
+    /// talk in voice threads
     /// requires VoiceConnect
     VoiceSpeak,
 
-    /// (unimplemented) stream video and screenshare in voice threads
+    /// stream video and screenshare in voice threads
     /// requires VoiceConnect
     VoiceVideo,
 }
@@ -343,7 +343,6 @@ pub struct PermissionOverwriteSet {
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
 #[cfg_attr(feature = "utoipa", derive(ToSchema))]
-#[serde(untagged)]
 pub enum PermissionOverwriteType {
     /// permission overrides for a role
     Role,
@@ -32,9 +32,9 @@ pub struct Role {
     pub is_self_applicable: bool,
     pub is_mentionable: bool,
     pub is_default: bool,
-    // FIXME(#114): at least some sort of hierarchy
-    // pub priority: u64,
 
+    /// members with a higher positioned role can moderate members with lower ones
+    pub position: u64,
     // pub includes: Vec<Role>,
     // pub member_count: u64,
 }
@@ -70,6 +70,10 @@ pub struct RoleCreate {
     /// if this role is applied by default to all new members
     #[serde(default)]
     pub is_default: bool,
+
+    /// members with a higher positioned role can moderate members with lower ones
+    #[serde(default)]
+    pub position: u64,
     // the main reason this doesn't exist yet is because i've seen in
     // discord how the ui can become extremely unreadable, cluttered, and
     // in general color vomit. plus there's the whole "illegable contrast
@@ -107,6 +111,7 @@ pub struct RolePatch {
     pub is_self_applicable: Option<bool>,
     pub is_mentionable: Option<bool>,
     pub is_default: Option<bool>,
+    pub position: Option<u64>,
 }
 
 impl Diff<Role> for RolePatch {
@@ -116,6 +121,7 @@ impl Diff<Role> for RolePatch {
             || self.is_self_applicable.changes(&other.is_self_applicable)
             || self.is_mentionable.changes(&other.is_mentionable)
             || self.is_default.changes(&other.is_default)
+            || self.position.changes(&other.position)
             || self.permissions.changes(&other.permissions)
     }
 }
@@ -44,6 +44,9 @@ pub struct Room {
     #[serde(flatten)]
     pub room_type: RoomType,
 
+    /// the user who owns this room, and outranks everyone else in it
+    pub owner_id: Option<UserId>,
+
     /// number of people in this room
     pub member_count: u64,
 
@@ -113,6 +113,34 @@ pub enum RoomMembership {
     },
 }
 
+/// a ban preventing a user from (re)joining a room
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(ToSchema))]
+pub struct RoomBan {
+    pub user_id: UserId,
+    pub room_id: RoomId,
+
+    /// the user who created this ban
+    pub creator_id: UserId,
+
+    /// user supplied reason why this user was banned
+    pub reason: Option<String>,
+
+    pub created_at: Time,
+
+    /// when this ban stops applying, or None if it's permanent
+    pub expires_at: Option<Time>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(ToSchema))]
+#[cfg_attr(feature = "validator", derive(Validate))]
+pub struct RoomBanCreate {
+    /// when this ban stops applying, or None if it's permanent
+    #[serde(default)]
+    pub expires_at: Option<Time>,
+}
+
 // #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 // #[cfg_attr(feature = "utoipa", derive(ToSchema))]
 // #[serde(tag = "origin")]
@@ -22,36 +22,30 @@ pub struct SearchMessageRequest {
     pub query: String,
 
     /// Only return messages in these rooms. Defaults to all rooms.
-    #[cfg_attr(feature = "utoipa", schema(ignore))]
     #[serde(default)]
     pub room_id: Vec<RoomId>,
 
     /// Only return messages in these threads. Defaults to all threads.
-    #[cfg_attr(feature = "utoipa", schema(ignore))]
     #[serde(default)]
     pub thread_id: Vec<ThreadId>,
 
-    /// Only return messages from these users. Defaults to all threads.
-    #[cfg_attr(feature = "utoipa", schema(ignore))]
+    /// Only return messages from these users. Defaults to all users.
     #[serde(default)]
     pub user_id: Vec<UserId>,
 
     /// Only return messages that have these features. Defaults to returning all messages.
-    #[cfg_attr(feature = "utoipa", schema(ignore))]
     #[serde(default)]
     pub features_message: Vec<SearchMessageFeatures>,
 
     /// Only return messages from threads that have these features. Defaults to searching all threads.
-    #[cfg_attr(feature = "utoipa", schema(ignore))]
     #[serde(default)]
     pub features_thread: Vec<SearchThreadFeatures>,
 
     /// Only return messages from rooms that have these features. Defaults to searching all rooms.
-    #[cfg_attr(feature = "utoipa", schema(ignore))]
     #[serde(default)]
     pub features_room: Vec<SearchRoomFeatures>,
 
-    #[cfg_attr(feature = "utoipa", schema(ignore))]
+    /// How to order results. Pages go in this order unless `dir` is set.
     #[serde(default)]
     pub order_by: SearchOrder,
 }
@@ -67,6 +61,8 @@ pub enum SearchOrder {
     Oldest,
 
     /// Return the most relevant matching items first
+    ///
+    /// Results ordered by relevance can't be paginated with `from` or `to`.
     Relevance,
 }
 
@@ -97,7 +93,7 @@ pub enum SearchMessageFeatures {
     /// Include messages from ignored users. By default these are filtered out.
     Ignored,
 
-    /// Include messages from ignored users. By default these are filtered out. Implicitly includes `Ignored`.
+    /// Include messages from blocked users. By default these are filtered out. Implicitly includes `Ignored`.
     Blocked,
 }
 
@@ -130,7 +126,6 @@ pub enum SearchRoomFeatures {
     Public,
 }
 
-// TODO(#76): thread searching
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[cfg_attr(feature = "utoipa", derive(ToSchema))]
 #[cfg_attr(feature = "validator", derive(Validate))]
@@ -144,31 +139,26 @@ pub struct SearchThreadsRequest {
     pub query: String,
 
     /// Only return threads that have these features. Defaults to searching all threads.
-    #[cfg_attr(feature = "utoipa", schema(ignore))]
     #[serde(default)]
     pub features_thread: Vec<SearchThreadFeatures>,
 
     /// Only return threads from rooms that have these features. Defaults to searching all rooms.
-    #[cfg_attr(feature = "utoipa", schema(ignore))]
     #[serde(default)]
     pub features_room: Vec<SearchRoomFeatures>,
 
     /// Only return threads in these rooms. Defaults to all rooms.
-    #[cfg_attr(feature = "utoipa", schema(ignore))]
     #[serde(default)]
     pub room_id: Vec<RoomId>,
 
     /// Only return threads with these tags.
-    #[cfg_attr(feature = "utoipa", schema(ignore))]
     #[serde(default)]
     pub tag_id: Vec<TagId>,
 
-    #[cfg_attr(feature = "utoipa", schema(ignore))]
+    /// How to order results. Pages go in this order unless `dir` is set.
     #[serde(default)]
     pub order_by: SearchOrder,
 }
 
-// TODO(#77): room searching
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[cfg_attr(feature = "utoipa", derive(ToSchema))]
 #[cfg_attr(feature = "validator", derive(Validate))]
@@ -182,11 +172,10 @@ pub struct SearchRoomsRequest {
     pub query: String,
 
     /// Only return rooms that have these features. Defaults to searching all rooms.
-    #[cfg_attr(feature = "utoipa", schema(ignore))]
     #[serde(default)]
     pub features_room: Vec<SearchRoomFeatures>,
 
-    #[cfg_attr(feature = "utoipa", schema(ignore))]
+    /// How to order results. Pages go in this order unless `dir` is set.
     #[serde(default)]
     pub order_by: SearchOrder,
 }
@@ -12,15 +12,16 @@ use crate::v1::types::{
 use super::{
     emoji::EmojiCustom,
     reaction::ReactionKey,
+    tag::Tag,
     user_config::UserConfig,
     voice::{SignallingMessage, VoiceState},
     EmojiId, InviteCode, Message, MessageId, MessageVerId, Role, RoleId, Room, RoomId, RoomMember,
-    Session, SessionId, SessionToken, Thread, ThreadId, User, UserId,
+    Session, SessionId, SessionToken, TagId, Thread, ThreadId, User, UserId,
 };
 
 mod sync2;
 
-pub use sync2::{SyncCompression, SyncFormat, SyncParams, SyncVersion};
+pub use sync2::{SyncCompression, SyncFilter, SyncFilterId, SyncFormat, SyncParams, SyncVersion};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[cfg_attr(feature = "utoipa", derive(ToSchema))]
@@ -42,6 +43,9 @@ pub enum MessageClient {
     /// heartbeat
     Pong,
 
+    /// add or remove a filter for the events sent on this connection
+    Filter { filter: SyncFilter },
+
     #[cfg(feature = "feat_voice")]
     /// send arbitrary data to a voice server
     // TEMP: for prototyping
@@ -61,7 +65,11 @@ pub enum MessageClient {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[cfg_attr(feature = "utoipa", derive(ToSchema))]
 pub struct SyncResume {
+    /// the id of the previous connection
     pub conn: String,
+
+    /// the seq of the last Sync message received. events after this are
+    /// replayed, on any server, until they're too old to be kept around.
     pub seq: u64,
 }
 
@@ -142,6 +150,9 @@ pub enum MessageSync {
         thread_id: ThreadId,
         message_id: MessageId,
         version_id: MessageVerId,
+
+        /// the user who read the thread. only sent to this user's sessions.
+        user_id: UserId,
     },
 
     MessageCreate {
@@ -175,10 +186,35 @@ pub enum MessageSync {
         message_ids: Vec<MessageId>,
     },
 
+    /// move multiple messages to another thread at once
+    MessageMoveBulk {
+        source_id: ThreadId,
+        target_id: ThreadId,
+        message_ids: Vec<MessageId>,
+    },
+
+    /// the pinned messages in a thread changed
+    MessagePinsUpdate {
+        thread_id: ThreadId,
+
+        /// every pinned message in the thread, in pin order
+        message_ids: Vec<MessageId>,
+    },
+
     RoomMemberUpsert {
         member: RoomMember,
     },
 
+    BanCreate {
+        room_id: RoomId,
+        user_id: UserId,
+    },
+
+    BanDelete {
+        room_id: RoomId,
+        user_id: UserId,
+    },
+
     ThreadMemberUpsert {
         member: ThreadMember,
     },
@@ -196,6 +232,19 @@ pub enum MessageSync {
         role_id: RoleId,
     },
 
+    TagCreate {
+        tag: Tag,
+    },
+
+    TagUpdate {
+        tag: Tag,
+    },
+
+    TagDelete {
+        room_id: RoomId,
+        tag_id: TagId,
+    },
+
     InviteCreate {
         invite: InviteWithMetadata,
     },
@@ -311,13 +360,20 @@ impl MessageSync {
                 | MessageSync::RoleCreate { .. }
                 | MessageSync::RoleUpdate { .. }
                 | MessageSync::RoleDelete { .. }
+                | MessageSync::TagCreate { .. }
+                | MessageSync::TagUpdate { .. }
+                | MessageSync::TagDelete { .. }
                 | MessageSync::InviteCreate { .. }
                 | MessageSync::MessageDelete { .. }
                 | MessageSync::MessageVersionDelete { .. }
+                | MessageSync::MessagePinsUpdate { .. }
+                | MessageSync::MessageMoveBulk { .. }
                 | MessageSync::InviteDelete { .. }
                 | MessageSync::ReactionPurge { .. }
                 | MessageSync::EmojiCreate { .. }
                 | MessageSync::EmojiDelete { .. }
+                | MessageSync::BanCreate { .. }
+                | MessageSync::BanDelete { .. }
         )
     }
 
@@ -338,13 +394,23 @@ impl MessageSync {
             MessageSync::RoleCreate { role } => Some(role.id.to_string()),
             MessageSync::RoleUpdate { role } => Some(role.id.to_string()),
             MessageSync::RoleDelete { role_id, .. } => Some(role_id.to_string()),
+            MessageSync::TagCreate { tag } => Some(tag.id.to_string()),
+            MessageSync::TagUpdate { tag } => Some(tag.id.to_string()),
+            MessageSync::TagDelete { tag_id, .. } => Some(tag_id.to_string()),
             MessageSync::InviteCreate { invite } => Some(invite.invite.code.to_string()),
             MessageSync::InviteUpdate { invite } => Some(invite.invite.code.to_string()),
             MessageSync::InviteDelete { code, .. } => Some(code.to_string()),
             MessageSync::MessageDelete { message_id, .. } => Some(message_id.to_string()),
             MessageSync::MessageVersionDelete { message_id, .. } => Some(message_id.to_string()),
+            MessageSync::MessagePinsUpdate { thread_id, .. } => Some(thread_id.to_string()),
+            MessageSync::MessageMoveBulk { source_id, .. } => Some(source_id.to_string()),
             MessageSync::EmojiCreate { emoji } => Some(emoji.id.to_string()),
             MessageSync::EmojiDelete { emoji_id, .. } => Some(emoji_id.to_string()),
+            MessageSync::BanCreate { user_id, .. } => Some(user_id.to_string()),
+            MessageSync::BanDelete { user_id, .. } => Some(user_id.to_string()),
+
+            // only logged when a moderator changes someone's voice state
+            MessageSync::VoiceState { user_id, .. } => Some(user_id.to_string()),
 
             // HACK: prob. should impl thread-specific audit logs?
             MessageSync::ThreadMemberUpsert { member } => {
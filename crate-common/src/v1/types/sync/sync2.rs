@@ -50,45 +50,52 @@ impl<'de> Deserialize<'de> for SyncVersion {
     }
 }
 
-// TODO(#249): websocket msgpack
+/// how messages on a sync connection are encoded
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[cfg_attr(feature = "utoipa", derive(ToSchema))]
+#[serde(rename_all = "lowercase")]
 pub enum SyncFormat {
+    /// text frames containing json
     #[default]
     Json,
-    // Msgpack,
+
+    /// binary frames containing msgpack, in both directions
+    Msgpack,
 }
 
-// TODO(#209): implement websocket compression
+/// how messages sent by the server are compressed
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[cfg_attr(feature = "utoipa", derive(ToSchema))]
+#[serde(rename_all = "lowercase")]
 pub enum SyncCompression {
-    // Zlib, // new DecompressionStream("deflate")
+    /// a single zlib stream shared across every message, each ending with a sync
+    /// flush. every message is sent in a binary frame. messages sent by the client
+    /// aren't compressed.
+    Zlib, // new DecompressionStream("deflate")
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// a client chosen id for a filter
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[cfg_attr(feature = "utoipa", derive(ToSchema))]
 pub struct SyncFilterId(pub String);
 
+/// limit which room and thread events are sent on a sync connection
+///
+/// connections receive every event until they add a filter. afterwards, room
+/// and thread events are only sent if they match at least one filter. events that
+/// aren't tied to a room or thread (users, sessions, relationships) are always sent.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[cfg_attr(feature = "utoipa", derive(ToSchema))]
+#[serde(tag = "type")]
 pub enum SyncFilter {
     /// subscribe to **everything**
-    SubAll {
-        id: SyncFilterId,
-    },
+    SubAll { id: SyncFilterId },
 
     /// subscribe to events in a room (excluding child thread events)
-    SubRoom {
-        id: SyncFilterId,
-        room_id: RoomId,
-    },
+    SubRoom { id: SyncFilterId, room_id: RoomId },
 
     /// subscribe to events in a room (including child thread events)
-    SubRoomAll {
-        id: SyncFilterId,
-        room_id: RoomId,
-    },
+    SubRoomAll { id: SyncFilterId, room_id: RoomId },
 
     /// subscribe to events in a thread
     SubThread {
@@ -97,9 +104,8 @@ pub enum SyncFilter {
     },
 
     // SubEvents { id: SyncFilterId, want: SyncEventType },
-    Unsub {
-        id: SyncFilterId,
-    },
+    /// remove a filter
+    Unsub { id: SyncFilterId },
 }
 
 // #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -120,6 +126,18 @@ pub enum SyncFilter {
 //     Voice,
 // }
 
+impl SyncFilter {
+    pub fn id(&self) -> &SyncFilterId {
+        match self {
+            SyncFilter::SubAll { id }
+            | SyncFilter::SubRoom { id, .. }
+            | SyncFilter::SubRoomAll { id, .. }
+            | SyncFilter::SubThread { id, .. }
+            | SyncFilter::Unsub { id } => id,
+        }
+    }
+}
+
 /// how to receive events
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[cfg_attr(feature = "utoipa", derive(ToSchema))]
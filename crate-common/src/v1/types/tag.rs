@@ -6,7 +6,11 @@ use utoipa::ToSchema;
 #[cfg(feature = "validator")]
 use validator::Validate;
 
-use crate::v1::types::{misc::Color, util::some_option, Room, RoomId, TagId, TagVerId, Thread};
+use crate::v1::types::{
+    misc::Color,
+    util::{some_option, Diff},
+    Room, RoomId, TagId, TagVerId, Thread,
+};
 
 // hmm, should i be able to apply tags to other tags?
 // tagception!
@@ -34,6 +38,9 @@ pub struct Tag {
 
     /// whether this tag is archived. cant be applied to any new threads or appear in pickers but still exists.
     pub is_archived: bool,
+
+    /// tags applied to this tag. anything tagged with this tag is implicitly tagged with these too.
+    pub tags: Vec<TagId>,
     // /// whether this tag is exclusive. functions similarly to forgejo
     // pub is_exclusive: bool,
 
@@ -96,6 +103,15 @@ pub struct TagPatch {
     pub is_archived: Option<bool>,
 }
 
+impl Diff<Tag> for TagPatch {
+    fn changes(&self, other: &Tag) -> bool {
+        self.name.changes(&other.name)
+            || self.description.changes(&other.description)
+            || self.color.changes(&other.color)
+            || self.is_archived.changes(&other.is_archived)
+    }
+}
+
 /// something that can a tag can be applied to
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[cfg_attr(feature = "utoipa", derive(ToSchema))]
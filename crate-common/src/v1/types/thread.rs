@@ -93,7 +93,6 @@ pub struct Thread {
     /// does not not update with ThreadSync
     pub online_count: u64,
 
-    // TODO(#72): tags
     /// tags that are applied to this thread
     #[cfg_attr(feature = "validator", validate(length(min = 1, max = 256)))]
     pub tags: Vec<TagId>,
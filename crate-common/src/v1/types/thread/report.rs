@@ -3,13 +3,27 @@ use serde::{Deserialize, Serialize};
 #[cfg(feature = "utoipa")]
 use utoipa::ToSchema;
 
-use crate::v1::types::{moderation::Report, MessageVerId};
+use crate::v1::types::{
+    moderation::{ReportDestination, ReportStatus, ReportTarget},
+    MessageVerId,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[cfg_attr(feature = "utoipa", derive(ToSchema))]
 pub struct ThreadTypeReportPublic {
     pub last_version_id: MessageVerId,
     pub message_count: u64,
+
+    /// what's being reported
+    pub target: ReportTarget,
+
+    /// who this report was sent to
+    pub destination: ReportDestination,
+
+    pub status: ReportStatus,
+
+    /// number of reports for this target. each report is posted as a message in this thread.
+    pub report_count: u64,
     // /// the first report there may be multiple reports
     // pub initial_report: Report,
     // pub initial_report_message_id: MessageId,
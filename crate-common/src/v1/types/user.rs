@@ -230,6 +230,15 @@ pub enum UserState {
     Deleted,
 }
 
+impl Suspended {
+    /// whether this suspension hasn't expired yet
+    pub fn is_active(&self) -> bool {
+        self.until
+            .as_ref()
+            .is_none_or(|until| *until > Time::now_utc())
+    }
+}
+
 impl Diff<User> for UserPatch {
     fn changes(&self, other: &User) -> bool {
         self.name.changes(&other.name)
@@ -1,5 +1,6 @@
 use serde::{Deserialize, Deserializer};
 
+pub mod mentions;
 pub mod truncate;
 
 // TEMP: pub use here for compatibility
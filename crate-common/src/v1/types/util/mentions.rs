@@ -0,0 +1,160 @@
+use uuid::Uuid;
+
+use crate::v1::types::Mentions;
+
+/// extract mentions from message content
+///
+/// the syntax is the same for markdown and tagged text:
+///
+/// - `<@user_id>` mentions a user
+/// - `<@&role_id>` mentions a role
+/// - `<#thread_id>` mentions a thread
+/// - `<##room_id>` mentions a room
+/// - `@room` and `@thread` mention everyone in the room or thread
+///
+/// anything inside backtick code spans or code blocks is ignored. this only
+/// parses syntax; it doesn't check whether the ids exist or whether the author
+/// is allowed to use them.
+pub fn parse_mentions(content: &str) -> Mentions {
+    let mut mentions = Mentions::default();
+    for text in without_code(content) {
+        parse_text(text, &mut mentions);
+    }
+    mentions
+}
+
+/// split content into the parts that aren't inside code spans or blocks
+fn without_code(content: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut rest = content;
+    while let Some(start) = rest.find('`') {
+        parts.push(&rest[..start]);
+        let fence_len = rest[start..].len() - rest[start..].trim_start_matches('`').len();
+        let fence = &rest[start..start + fence_len];
+        let after = &rest[start + fence_len..];
+        match after.find(fence) {
+            Some(end) => rest = &after[end + fence_len..],
+            // unclosed code span, treat the backticks as text
+            None => {
+                parts.push(fence);
+                rest = after;
+            }
+        }
+    }
+    parts.push(rest);
+    parts
+}
+
+fn parse_text(text: &str, mentions: &mut Mentions) {
+    for (idx, ch) in text.char_indices() {
+        match ch {
+            '<' => {
+                let Some(end) = text[idx..].find('>') else {
+                    continue;
+                };
+                parse_tag(&text[idx + 1..idx + end], mentions);
+            }
+            '@' => {
+                let is_word_start = text[..idx]
+                    .chars()
+                    .next_back()
+                    .is_none_or(|c| !c.is_alphanumeric() && c != '_');
+                if !is_word_start {
+                    continue;
+                }
+                let word: &str = text[idx + 1..]
+                    .split(|c: char| !c.is_alphanumeric() && c != '_')
+                    .next()
+                    .unwrap_or_default();
+                match word {
+                    "room" => mentions.all_in_room = true,
+                    "thread" => mentions.all_in_thread = true,
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn parse_tag(tag: &str, mentions: &mut Mentions) {
+    if let Some(id) = tag.strip_prefix("@&") {
+        if let Ok(id) = id.parse::<Uuid>() {
+            push_unique(&mut mentions.roles, id.into());
+        }
+    } else if let Some(id) = tag.strip_prefix('@') {
+        if let Ok(id) = id.parse::<Uuid>() {
+            push_unique(&mut mentions.users, id.into());
+        }
+    } else if let Some(id) = tag.strip_prefix("##") {
+        if let Ok(id) = id.parse::<Uuid>() {
+            push_unique(&mut mentions.rooms, id.into());
+        }
+    } else if let Some(id) = tag.strip_prefix('#') {
+        if let Ok(id) = id.parse::<Uuid>() {
+            push_unique(&mut mentions.threads, id.into());
+        }
+    }
+}
+
+fn push_unique<T: PartialEq>(items: &mut Vec<T>, item: T) {
+    if !items.contains(&item) {
+        items.push(item);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v1::types::{RoleId, RoomId, ThreadId, UserId};
+
+    const ID_A: &str = "01940b7c-6b1a-7d7e-9f1c-2a8b4c9e0d11";
+    const ID_B: &str = "01940b7c-6b1a-7d7e-9f1c-2a8b4c9e0d22";
+
+    fn id(s: &str) -> Uuid {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_ids() {
+        let m = parse_mentions(&format!(
+            "hi <@{ID_A}> and <@&{ID_B}>, see <#{ID_A}> in <##{ID_B}>"
+        ));
+        assert_eq!(m.users, vec![UserId::from(id(ID_A))]);
+        assert_eq!(m.roles, vec![RoleId::from(id(ID_B))]);
+        assert_eq!(m.threads, vec![ThreadId::from(id(ID_A))]);
+        assert_eq!(m.rooms, vec![RoomId::from(id(ID_B))]);
+        assert!(!m.all_in_room);
+        assert!(!m.all_in_thread);
+    }
+
+    #[test]
+    fn test_parse_dedup_and_invalid() {
+        let m = parse_mentions(&format!("<@{ID_A}> <@{ID_A}> <@not-a-uuid> <@{ID_B}"));
+        assert_eq!(m.users, vec![UserId::from(id(ID_A))]);
+    }
+
+    #[test]
+    fn test_parse_mass_mentions() {
+        let m = parse_mentions("hey @room, and @thread!");
+        assert!(m.all_in_room);
+        assert!(m.all_in_thread);
+
+        let m = parse_mentions("email@room.com @rooms @threadless");
+        assert!(!m.all_in_room);
+        assert!(!m.all_in_thread);
+    }
+
+    #[test]
+    fn test_parse_ignores_code() {
+        let m = parse_mentions(&format!(
+            "`@room` ```\n<@{ID_A}>\n``` ``@thread`` <@{ID_B}>"
+        ));
+        assert!(!m.all_in_room);
+        assert!(!m.all_in_thread);
+        assert_eq!(m.users, vec![UserId::from(id(ID_B))]);
+
+        let m = parse_mentions("unclosed ` @room");
+        assert!(m.all_in_room);
+    }
+}
@@ -12,7 +12,10 @@ use serde::{Deserialize, Serialize};
 #[cfg(feature = "utoipa")]
 use utoipa::ToSchema;
 
-use crate::v1::types::{util::Time, UserId};
+use crate::v1::types::{
+    util::{Diff, Time},
+    UserId,
+};
 
 use super::ThreadId;
 
@@ -51,12 +54,103 @@ pub struct VoiceState {
     // pub session_id: (),
     /// when this person joined the call
     pub joined_at: Time,
+
+    /// the tracks this user is receiving
+    ///
+    /// None if they haven't picked any, in which case every track in the thread is sent
+    #[serde(default)]
+    pub subscribed: Option<Vec<TrackRef>>,
+
+    /// whether a moderator stopped this user from talking
+    #[serde(default)]
+    pub mute: bool,
+
+    /// whether a moderator stopped this user from listening
+    #[serde(default)]
+    pub deaf: bool,
+
+    /// whether this user muted themselves
+    #[serde(default)]
+    pub self_mute: bool,
+
+    /// whether this user deafened themselves
+    #[serde(default)]
+    pub self_deaf: bool,
+
+    /// whether this user isn't allowed to talk in this thread
+    #[serde(default)]
+    pub suppress: bool,
+
+    /// whether this user is a priority speaker
+    ///
+    /// clients should turn everyone else down while they talk
+    #[serde(default)]
+    pub priority: bool,
+}
+
+impl VoiceState {
+    /// whether the sfu should drop this user's audio
+    pub fn is_muted(&self) -> bool {
+        self.mute || self.self_mute || self.suppress
+    }
+
+    /// whether the sfu should stop sending audio to this user
+    pub fn is_deafened(&self) -> bool {
+        self.deaf || self.self_deaf
+    }
+}
+
+/// change what someone is allowed to do in a call
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(ToSchema))]
+pub struct VoiceStatePatch {
+    /// requires VoiceMute
+    #[serde(default)]
+    pub mute: Option<bool>,
+
+    /// requires VoiceDeafen
+    #[serde(default)]
+    pub deaf: Option<bool>,
+}
+
+impl Diff<VoiceState> for VoiceStatePatch {
+    fn changes(&self, other: &VoiceState) -> bool {
+        self.mute.changes(&other.mute) || self.deaf.changes(&other.deaf)
+    }
+}
+
+/// move someone to another voice thread
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(ToSchema))]
+pub struct VoiceMemberMove {
+    /// the voice thread to move them to, in the same room
+    pub thread_id: ThreadId,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[cfg_attr(feature = "utoipa", derive(ToSchema))]
 pub struct VoiceStateUpdate {
     pub thread_id: ThreadId,
+
+    /// where to host the call if it hasn't started yet
+    ///
+    /// if unset or unavailable, the least loaded voice server is used
+    #[serde(default)]
+    pub region: Option<String>,
+
+    #[serde(default)]
+    pub self_mute: bool,
+
+    #[serde(default)]
+    pub self_deaf: bool,
+}
+
+/// a region where voice calls can be hosted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(ToSchema))]
+pub struct VoiceRegion {
+    /// the region's id, as configured on its voice servers
+    pub id: String,
 }
 
 // if i move stuff perms into voice member/states
@@ -93,6 +187,17 @@ pub struct TrackMetadata {
     pub key: String,
 }
 
+/// a track published by someone in a call
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(ToSchema))]
+pub struct TrackRef {
+    /// the user who published this track
+    pub user_id: UserId,
+
+    /// the track's mid, as sent in `Have`
+    pub mid: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[cfg_attr(feature = "utoipa", derive(ToSchema))]
 #[serde(tag = "type")]
@@ -121,14 +226,24 @@ pub enum SignallingMessage {
         tracks: Vec<TrackMetadata>,
     },
 
-    /// sent by server and client
-    Want {
-        // tracks: Vec<Mid>,
-        tracks: Vec<String>,
-    },
+    /// sent by client. pick which tracks to receive.
+    ///
+    /// replaces any previous selection. until this is sent, every track in
+    /// the thread is received. changing the selection makes the server send a
+    /// new offer, where tracks that are no longer wanted are inactive rather
+    /// than removed.
+    Want { tracks: Vec<TrackRef> },
 
     /// sent by client.
     VoiceState { state: Option<VoiceStateUpdate> },
+
+    /// sent by server only. set what a moderator allows someone to do in a call.
+    Moderate {
+        /// the user being moderated
+        target_id: UserId,
+        mute: bool,
+        deaf: bool,
+    },
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -1,8 +1,9 @@
 use async_trait::async_trait;
 use common::v1::types::{
-    user_config::UserConfig, util::Time, voice::SignallingMessage, InviteCode, InviteWithMetadata,
-    Message, MessageId, MessagePayload, MessageSync, MessageVerId, Role, RoleId, Room, RoomId,
-    RoomMember, Session, SessionId, Thread, ThreadId, ThreadMember, User, UserId,
+    tag::Tag, user_config::UserConfig, util::Time, voice::SignallingMessage, InviteCode,
+    InviteWithMetadata, Message, MessageId, MessagePayload, MessageSync, MessageVerId, Role,
+    RoleId, Room, RoomId, RoomMember, Session, SessionId, TagId, Thread, ThreadId, ThreadMember,
+    User, UserId,
 };
 use std::future::{ready, Future};
 
@@ -80,6 +81,22 @@ pub trait EventHandler: Send {
         ready(Ok(()))
     }
 
+    fn ban_create(
+        &mut self,
+        room_id: RoomId,
+        user_id: UserId,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        ready(Ok(()))
+    }
+
+    fn ban_delete(
+        &mut self,
+        room_id: RoomId,
+        user_id: UserId,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        ready(Ok(()))
+    }
+
     fn session_create(
         &mut self,
         session: Session,
@@ -192,6 +209,23 @@ pub trait EventHandler: Send {
         ready(Ok(()))
     }
 
+    fn message_move_bulk(
+        &mut self,
+        source_id: ThreadId,
+        target_id: ThreadId,
+        message_ids: Vec<MessageId>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        ready(Ok(()))
+    }
+
+    fn message_pins_update(
+        &mut self,
+        thread_id: ThreadId,
+        message_ids: Vec<MessageId>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        ready(Ok(()))
+    }
+
     fn reaction_create(
         &mut self,
         user_id: UserId,
@@ -235,6 +269,22 @@ pub trait EventHandler: Send {
         ready(Ok(()))
     }
 
+    fn tag_create(&mut self, tag: Tag) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        ready(Ok(()))
+    }
+
+    fn tag_update(&mut self, tag: Tag) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        ready(Ok(()))
+    }
+
+    fn tag_delete(
+        &mut self,
+        room_id: RoomId,
+        tag_id: TagId,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        ready(Ok(()))
+    }
+
     fn voice_dispatch(
         &mut self,
         user_id: UserId,
@@ -389,6 +439,12 @@ where
                 MessageSync::ThreadMemberUpsert { member } => {
                     self.thread_member_upsert(member).await
                 }
+                MessageSync::BanCreate { room_id, user_id } => {
+                    self.ban_create(room_id, user_id).await
+                }
+                MessageSync::BanDelete { room_id, user_id } => {
+                    self.ban_delete(room_id, user_id).await
+                }
                 MessageSync::SessionCreate { session } => self.session_create(session).await,
                 MessageSync::SessionUpdate { session } => self.session_update(session).await,
                 MessageSync::RoleCreate { role } => self.role_create(role).await,
@@ -425,11 +481,24 @@ where
                     thread_id,
                     message_id,
                     version_id,
+                    ..
                 } => self.thread_ack(thread_id, message_id, version_id).await,
                 MessageSync::MessageDeleteBulk {
                     thread_id,
                     message_ids,
                 } => self.message_delete_bulk(thread_id, message_ids).await,
+                MessageSync::MessageMoveBulk {
+                    source_id,
+                    target_id,
+                    message_ids,
+                } => {
+                    self.message_move_bulk(source_id, target_id, message_ids)
+                        .await
+                }
+                MessageSync::MessagePinsUpdate {
+                    thread_id,
+                    message_ids,
+                } => self.message_pins_update(thread_id, message_ids).await,
                 MessageSync::ReactionCreate {
                     user_id,
                     thread_id,
@@ -456,6 +525,11 @@ where
                 MessageSync::EmojiDelete { emoji_id, room_id } => {
                     self.emoji_delete(emoji_id, room_id).await
                 }
+                MessageSync::TagCreate { tag } => self.tag_create(tag).await,
+                MessageSync::TagUpdate { tag } => self.tag_update(tag).await,
+                MessageSync::TagDelete { room_id, tag_id } => {
+                    self.tag_delete(room_id, tag_id).await
+                }
                 MessageSync::VoiceDispatch { user_id, payload } => {
                     self.voice_dispatch(user_id, payload).await
                 }
@@ -1,7 +1,12 @@
+use std::io::Write;
 use std::time::Duration;
 
 use anyhow::Result;
-use common::v1::types::{MessageClient, MessageEnvelope, MessagePayload, SessionToken, SyncResume};
+use common::v1::types::{
+    MessageClient, MessageEnvelope, MessagePayload, SessionToken, SyncCompression, SyncFormat,
+    SyncResume,
+};
+use flate2::write::ZlibDecoder;
 use futures_util::{SinkExt, StreamExt};
 use reqwest::Url;
 use tokio_tungstenite::tungstenite::Message as WsMessage;
@@ -13,6 +18,8 @@ pub struct Syncer {
     handler: Box<dyn ErasedHandler>,
     token: SessionToken,
     base_url: Url,
+    format: SyncFormat,
+    compression: Option<SyncCompression>,
 }
 
 const DEFAULT_BASE: &str = "wss://chat.celery.eu.org/";
@@ -24,6 +31,8 @@ impl Syncer {
             token,
             base_url,
             handler: Box::new(EmptyHandler),
+            format: SyncFormat::Json,
+            compression: None,
         }
     }
 
@@ -35,32 +44,47 @@ impl Syncer {
         Self { handler, ..self }
     }
 
+    pub fn with_format(self, format: SyncFormat) -> Self {
+        Self { format, ..self }
+    }
+
+    pub fn with_compression(self, compression: Option<SyncCompression>) -> Self {
+        Self {
+            compression,
+            ..self
+        }
+    }
+
     pub async fn connect(&mut self) -> Result<()> {
         let mut resume: Option<SyncResume> = None;
         loop {
-            let url = self.base_url.join("/api/v1/sync?version=1")?;
+            let mut url = self.base_url.join("/api/v1/sync?version=1")?;
+            if self.format == SyncFormat::Msgpack {
+                url.query_pairs_mut().append_pair("format", "msgpack");
+            }
+            if let Some(SyncCompression::Zlib) = self.compression {
+                url.query_pairs_mut().append_pair("compression", "zlib");
+            }
             let Ok((mut client, _)) = tokio_tungstenite::connect_async(url.as_str()).await else {
                 warn!("websocket failed to connect, retrying in 1 second...");
                 tokio::time::sleep(Duration::from_secs(1)).await;
                 continue;
             };
+            let mut decoder = Decoder::new(self.format, self.compression);
             let hello = MessageClient::Hello {
                 token: self.token.clone(),
                 resume: resume.clone(),
                 status: None,
             };
-            client
-                .send(WsMessage::text(serde_json::to_string(&hello)?))
-                .await?;
+            client.send(encode(self.format, &hello)?).await?;
             while let Some(Ok(msg)) = client.next().await {
-                let WsMessage::Text(text) = msg else { continue };
-                let msg: MessageEnvelope = serde_json::from_str(&text)?;
+                let Some(msg) = decoder.decode(msg)? else {
+                    continue;
+                };
                 match &msg.payload {
                     MessagePayload::Ping => {
                         client
-                            .send(WsMessage::text(serde_json::to_string(
-                                &MessageClient::Pong,
-                            )?))
+                            .send(encode(self.format, &MessageClient::Pong)?)
                             .await?;
                     }
                     MessagePayload::Error { error } => {
@@ -92,3 +116,98 @@ impl Syncer {
         }
     }
 }
+
+fn encode(format: SyncFormat, msg: &MessageClient) -> Result<WsMessage> {
+    Ok(match format {
+        SyncFormat::Json => WsMessage::text(serde_json::to_string(msg)?),
+        SyncFormat::Msgpack => WsMessage::binary(rmp_serde::to_vec_named(msg)?),
+    })
+}
+
+/// decodes messages from the server, keeping the zlib context between messages
+struct Decoder {
+    format: SyncFormat,
+    zlib: Option<ZlibDecoder<Vec<u8>>>,
+}
+
+impl Decoder {
+    fn new(format: SyncFormat, compression: Option<SyncCompression>) -> Self {
+        Self {
+            format,
+            zlib: compression.map(|c| match c {
+                SyncCompression::Zlib => ZlibDecoder::new(Vec::new()),
+            }),
+        }
+    }
+
+    fn decode(&mut self, msg: WsMessage) -> Result<Option<MessageEnvelope>> {
+        let bytes = match msg {
+            WsMessage::Text(text) => return Ok(Some(serde_json::from_str(&text)?)),
+            WsMessage::Binary(bytes) => bytes,
+            _ => return Ok(None),
+        };
+        let bytes = match &mut self.zlib {
+            Some(zlib) => {
+                zlib.write_all(&bytes)?;
+                zlib.flush()?;
+                std::mem::take(zlib.get_mut())
+            }
+            None => bytes.to_vec(),
+        };
+        let msg = match self.format {
+            SyncFormat::Json => serde_json::from_slice(&bytes)?,
+            SyncFormat::Msgpack => rmp_serde::from_slice(&bytes)?,
+        };
+        Ok(Some(msg))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use common::v1::types::{MessageClient, MessageEnvelope, MessagePayload, SyncResume};
+    use flate2::{write::ZlibEncoder, Compression};
+    use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+    use super::*;
+
+    #[test]
+    fn test_decode_msgpack_zlib_stream() {
+        let mut zlib = ZlibEncoder::new(Vec::new(), Compression::default());
+        let mut decoder = Decoder::new(SyncFormat::Msgpack, Some(SyncCompression::Zlib));
+        for error in ["first", "second", "third"] {
+            let msg = MessageEnvelope {
+                payload: MessagePayload::Error {
+                    error: error.to_owned(),
+                },
+            };
+            zlib.write_all(&rmp_serde::to_vec_named(&msg).unwrap())
+                .unwrap();
+            zlib.flush().unwrap();
+            let frame = WsMessage::binary(std::mem::take(zlib.get_mut()));
+            let decoded = decoder.decode(frame).unwrap().unwrap();
+            assert!(matches!(decoded.payload, MessagePayload::Error { error: e } if e == error));
+        }
+    }
+
+    #[test]
+    fn test_encode_msgpack_hello() {
+        let hello = MessageClient::Hello {
+            token: "token".to_owned().into(),
+            status: None,
+            resume: Some(SyncResume {
+                conn: "conn".to_owned(),
+                seq: 3,
+            }),
+        };
+        let WsMessage::Binary(bytes) = encode(SyncFormat::Msgpack, &hello).unwrap() else {
+            panic!("msgpack should be sent in binary frames");
+        };
+        let decoded: MessageClient = rmp_serde::from_slice(&bytes).unwrap();
+        let MessageClient::Hello { resume, .. } = decoded else {
+            panic!("wrong message type");
+        };
+        assert_eq!(resume.map(|r| r.seq), Some(3));
+    }
+}
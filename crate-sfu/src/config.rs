@@ -1,3 +1,5 @@
+use std::net::IpAddr;
+
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize, Clone)]
@@ -8,13 +10,34 @@ pub struct Config {
     /// The url of the backend api
     pub api_url: String,
 
-    /// The token to authenticate with the backend
+    /// The url the backend uses to reach this SFU
+    pub url: String,
+
+    /// The region this SFU is in, for hosting calls near their members
+    #[serde(default = "default_region")]
+    pub region: String,
+
+    /// The token to authenticate with the backend, one of its `sfu_tokens`
     pub token: String,
 
+    /// The ipv4 address to accept webrtc connections on. Detected from the
+    /// network interfaces if unset.
+    #[serde(default)]
+    pub rtc_ipv4: Option<IpAddr>,
+
+    /// The ipv6 address to accept webrtc connections on. Detected from the
+    /// network interfaces if unset.
+    #[serde(default)]
+    pub rtc_ipv6: Option<IpAddr>,
+
     #[serde(default = "default_rust_log")]
     pub rust_log: String,
 }
 
+fn default_region() -> String {
+    "default".to_string()
+}
+
 fn default_rust_log() -> String {
     "info,voice=trace".to_string()
 }
@@ -1,18 +1,24 @@
 use std::{sync::Arc, time::Instant};
 
 use common::v1::types::{
+    thread::voice::ThreadTypeVoicePublic,
     voice::{SignallingMessage, VoiceState},
-    ThreadId, UserId,
+    Permission, ThreadId, UserId,
 };
 use serde::{Deserialize, Serialize};
 use str0m::{
     format::PayloadParams,
-    media::{MediaKind, MediaTime, Mid},
+    media::{KeyframeRequestKind, MediaKind, MediaTime, Mid, Rid},
 };
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use crate::simulcast::Layer;
 
 pub mod config;
 pub mod peer;
 pub mod sfu;
+pub mod simulcast;
 pub mod util;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -20,11 +26,36 @@ pub struct SfuCommand {
     /// the user who sent this, or None if this is from the server
     pub user_id: Option<UserId>,
 
+    /// the voice thread's settings, sent by the backend when a user joins
+    #[serde(default)]
+    pub thread: Option<ThreadTypeVoicePublic>,
+
+    /// the user's voice permissions in the thread, sent by the backend when they join
+    ///
+    /// None allows nothing, so private threads stay private if it's missing
+    #[serde(default)]
+    pub permissions: Option<Vec<Permission>>,
+
+    /// whether a moderator muted or deafened the user, sent by the backend when they join
+    #[serde(default)]
+    pub moderation: Option<VoiceModeration>,
+
     #[serde(flatten)]
     pub inner: SignallingMessage,
+
+    /// where to report whether the command was accepted, if the sender waits for it
+    #[serde(skip)]
+    pub reply: Option<oneshot::Sender<Result<(), Error>>>,
 }
 
-#[derive(Debug, serde::Serialize)]
+/// what a moderator allows someone to do in calls
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct VoiceModeration {
+    pub mute: bool,
+    pub deaf: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum SfuEvent {
     VoiceDispatch {
@@ -36,6 +67,14 @@ pub enum SfuEvent {
         old: Option<VoiceState>,
         state: Option<VoiceState>,
     },
+
+    /// tell the backend that this sfu exists
+    Register {
+        id: Uuid,
+        url: String,
+        region: String,
+        load: f32,
+    },
 }
 
 #[derive(Debug)]
@@ -49,6 +88,8 @@ pub enum PeerEvent {
     Signalling(SignallingMessage),
     MediaAdded(SfuTrack),
     MediaData(MediaData),
+    KeyframeRequest(KeyframeRequest),
+    Stats(PeerStats),
     Dead,
 }
 
@@ -57,6 +98,13 @@ pub enum PeerCommand {
     Signalling(SignallingMessage),
     MediaAdded(SfuTrack),
     MediaData(MediaData),
+    KeyframeRequest(KeyframeRequest),
+
+    /// the most to send to this peer, in bits per second
+    MaxBitrate(Option<u64>),
+
+    /// the voice permissions this peer has, or None for none of them
+    Permissions(Option<Vec<Permission>>),
     Kill,
 }
 
@@ -68,6 +116,24 @@ pub struct MediaData {
     pub time: MediaTime,
     pub data: Arc<[u8]>,
     pub params: PayloadParams,
+
+    /// the simulcast layer this is from
+    pub rid: Option<Rid>,
+
+    /// whether a decoder can start from this frame. true if the codec doesn't say.
+    pub keyframe: bool,
+}
+
+/// ask a publisher for a keyframe
+#[derive(Debug, Clone)]
+pub struct KeyframeRequest {
+    /// the user who published the track
+    pub peer_id: UserId,
+
+    /// the track's mid on the publisher's side
+    pub mid: Mid,
+    pub rid: Option<Rid>,
+    pub kind: KeyframeRequestKind,
 }
 
 #[derive(Debug, Clone)]
@@ -114,6 +180,44 @@ pub struct TrackOut {
     pub needs_keyframe: bool,
     pub thread_id: ThreadId,
     pub key: String,
+
+    /// the simulcast layers the publisher is sending
+    pub layers: Vec<Layer>,
+
+    /// the layer being forwarded
+    pub rid: Option<Rid>,
+
+    /// the layer to switch to at its next keyframe
+    pub target_rid: Option<Rid>,
+}
+
+/// what is being sent to a peer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerStats {
+    pub user_id: UserId,
+
+    /// estimated bandwidth to the peer, in bits per second
+    pub egress_estimate: Option<u64>,
+
+    /// the most the peer will be sent, from the voice thread's bitrate
+    pub max_bitrate: Option<u64>,
+
+    pub tracks: Vec<TrackStats>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackStats {
+    /// the user who published this track
+    pub peer_id: UserId,
+
+    /// the track's mid on the publisher's side
+    pub mid: String,
+
+    /// the simulcast layer being forwarded, if the publisher sends layers
+    pub rid: Option<String>,
+
+    /// the forwarded layer's bitrate, in bits per second
+    pub bitrate: Option<u64>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -121,6 +225,10 @@ pub enum Error {
     /// no voice state exists for this user
     #[error("no voice state exists for this user")]
     NotConnected,
+
+    /// the voice thread already has as many people as its user limit allows
+    #[error("this voice thread is full")]
+    ThreadFull,
 }
 
 // impl From<MediaKindSerde> for MediaKind {
@@ -1,35 +1,109 @@
-use std::str::FromStr;
+use std::{str::FromStr, sync::Arc};
 
 use anyhow::Result;
 use axum::{
-    http::StatusCode,
+    extract::Path,
+    http::{HeaderMap, StatusCode},
     routing::{get, post},
     Json,
 };
+use common::v1::types::{voice::VoiceState, ThreadId, UserId};
+use dashmap::DashMap;
 use figment::providers::{Env, Format, Toml};
-use tokio::sync::mpsc::UnboundedSender;
+use subtle::ConstantTimeEq;
+use tokio::sync::{mpsc::UnboundedSender, oneshot};
 use tracing::error;
 use tracing_subscriber::EnvFilter;
-use voice::{config::Config, sfu::Sfu, SfuCommand};
+use voice::{config::Config, sfu::Sfu, Error, PeerStats, SfuCommand};
 
-async fn start_http(config: &Config, wheel: UnboundedSender<SfuCommand>) -> Result<()> {
+async fn start_http(
+    config: &Config,
+    wheel: UnboundedSender<SfuCommand>,
+    stats: Arc<DashMap<UserId, PeerStats>>,
+    voice_states: Arc<DashMap<UserId, VoiceState>>,
+) -> Result<()> {
+    let auth: Arc<str> = format!("Server {}", config.token).into();
+    let rpc_auth = Arc::clone(&auth);
+    let stats_auth = Arc::clone(&auth);
+    let state_auth = Arc::clone(&auth);
+    let thread_states = Arc::clone(&voice_states);
     let router = axum::Router::new()
         .route(
             "/rpc",
-            post(|Json(req): Json<SfuCommand>| async move {
-                // handles events proxied through the websocket
-                if let Err(err) = wheel.send(req) {
-                    error!("error while sending command: {err}");
-                };
-                StatusCode::ACCEPTED
+            post(
+                |headers: HeaderMap, Json(mut req): Json<SfuCommand>| async move {
+                    if !authorized(&headers, &rpc_auth) {
+                        return Err((StatusCode::UNAUTHORIZED, String::new()));
+                    }
+
+                    // handles events proxied through the websocket
+                    let (reply, accepted) = oneshot::channel();
+                    req.reply = Some(reply);
+                    if let Err(err) = wheel.send(req) {
+                        error!("error while sending command: {err}");
+                    };
+                    match accepted.await {
+                        Ok(Err(err @ Error::ThreadFull)) => {
+                            Err((StatusCode::CONFLICT, err.to_string()))
+                        }
+                        Ok(Err(err @ Error::NotConnected)) => {
+                            Err((StatusCode::NOT_FOUND, err.to_string()))
+                        }
+                        _ => Ok(StatusCode::ACCEPTED),
+                    }
+                },
+            ),
+        )
+        .route(
+            "/stats",
+            get(|headers: HeaderMap| async move {
+                if !authorized(&headers, &stats_auth) {
+                    return Err(StatusCode::UNAUTHORIZED);
+                }
+                let stats: Vec<PeerStats> = stats.iter().map(|s| s.value().clone()).collect();
+                Ok(Json(stats))
             }),
         )
+        .route(
+            "/voice-state/{user_id}",
+            get(
+                |headers: HeaderMap, Path(user_id): Path<UserId>| async move {
+                    if !authorized(&headers, &state_auth) {
+                        return Err(StatusCode::UNAUTHORIZED);
+                    }
+                    let state = voice_states.get(&user_id).ok_or(StatusCode::NOT_FOUND)?;
+                    Ok(Json(state.clone()))
+                },
+            ),
+        )
+        .route(
+            "/thread/{thread_id}/voice-state",
+            get(
+                |headers: HeaderMap, Path(thread_id): Path<ThreadId>| async move {
+                    if !authorized(&headers, &auth) {
+                        return Err(StatusCode::UNAUTHORIZED);
+                    }
+                    let states: Vec<VoiceState> = thread_states
+                        .iter()
+                        .filter(|s| s.thread_id == thread_id)
+                        .map(|s| s.value().clone())
+                        .collect();
+                    Ok(Json(states))
+                },
+            ),
+        )
         .route("/ping", get(|| async { StatusCode::NO_CONTENT }));
     let listener = tokio::net::TcpListener::bind(&config.host).await?;
     axum::serve(listener, router).await?;
     Ok(())
 }
 
+fn authorized(headers: &HeaderMap, auth: &str) -> bool {
+    headers
+        .get("authorization")
+        .is_some_and(|h| bool::from(h.as_bytes().ct_eq(auth.as_bytes())))
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let config: Config = figment::Figment::new()
@@ -42,8 +116,11 @@ async fn main() -> Result<()> {
         .finish();
     tracing::subscriber::set_global_default(sub)?;
 
-    let wheel = Sfu::new(config.clone()).spawn();
-    let _ = start_http(&config, wheel).await;
+    let sfu = Sfu::new(config.clone());
+    let stats = sfu.stats();
+    let voice_states = sfu.voice_states();
+    let wheel = sfu.spawn();
+    let _ = start_http(&config, wheel, stats, voice_states).await;
 
     Ok(())
 }
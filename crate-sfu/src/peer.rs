@@ -1,14 +1,23 @@
-use std::{collections::HashMap, time::Instant};
+use std::{
+    collections::{HashMap, HashSet},
+    time::Instant,
+};
 
-use crate::{MediaData, PeerEvent, SfuTrack, SignallingMessage};
+use crate::{
+    config::Config,
+    simulcast::{self, Layer},
+    KeyframeRequest, MediaData, PeerEvent, PeerStats, SfuTrack, SignallingMessage, TrackStats,
+};
 use anyhow::Result;
 use common::v1::types::{
-    voice::{MediaKindSerde, SessionDescription, TrackMetadata, VoiceState},
-    UserId,
+    voice::{MediaKindSerde, SessionDescription, TrackMetadata, TrackRef, VoiceState},
+    Permission, UserId,
 };
 use str0m::{
+    bwe::{Bitrate, BweKind},
     change::{SdpAnswer, SdpOffer, SdpPendingOffer},
-    media::{Direction, MediaKind, Mid},
+    format::CodecExtra,
+    media::{Direction, KeyframeRequestKind, MediaKind, Mid, Rid},
     net::{Protocol, Receive},
     Candidate, Event, Input, Output, Rtc, RtcConfig,
 };
@@ -22,6 +31,9 @@ use tracing::{debug, error, info, trace, warn};
 
 use crate::{PeerCommand, PeerEventEnvelope, TrackIn, TrackOut, TrackState};
 
+/// the bandwidth to assume a client has before it's been estimated
+const INITIAL_BITRATE: Bitrate = Bitrate::kbps(300);
+
 #[derive(Debug)]
 pub struct Peer {
     rtc: Rtc,
@@ -31,6 +43,14 @@ pub struct Peer {
     outbound: Vec<TrackOut>,
     // outbound: HashMap<Mid, TrackOut>,
     sdp_pending: Option<SdpPendingOffer>,
+    /// the (peer, mid) of each track this peer wants, or None for all of them
+    wanted: Option<HashSet<(UserId, Mid)>>,
+    /// estimated bandwidth to the client, in bits per second
+    egress_estimate: Option<u64>,
+    /// the most to send to the client, in bits per second
+    max_bitrate: Option<u64>,
+    /// the voice permissions the client has, or None for none of them
+    permissions: Option<Vec<Permission>>,
     user_id: UserId,
     voice_state: VoiceState,
     commands: UnboundedReceiver<PeerCommand>,
@@ -39,6 +59,7 @@ pub struct Peer {
 
 impl Peer {
     pub async fn spawn(
+        config: &Config,
         sfu_send: UnboundedSender<PeerEventEnvelope>,
         user_id: UserId,
         voice_state: VoiceState,
@@ -47,16 +68,28 @@ impl Peer {
 
         let mut rtc = RtcConfig::new()
             .set_ice_lite(true)
+            // only codecs whose keyframes can be detected, since layer
+            // switches wait for one
+            .clear_codecs()
+            .enable_opus(true)
+            .enable_vp8(true)
+            .enable_h264(true)
+            .enable_vp9(true)
+            .enable_bwe(Some(INITIAL_BITRATE))
             // .set_stats_interval(Some(Duration::from_secs(5)))
             .build();
 
-        let addr = crate::util::select_host_address_ipv4();
+        let addr = config
+            .rtc_ipv4
+            .unwrap_or_else(crate::util::select_host_address_ipv4);
         let socket_v4 = UdpSocket::bind(format!("{addr}:0")).await?;
         let candidate = Candidate::host(socket_v4.local_addr()?, "udp")?;
         debug!("listen on {}", socket_v4.local_addr().unwrap());
         rtc.add_local_candidate(candidate.clone());
 
-        let addr = crate::util::select_host_address_ipv6();
+        let addr = config
+            .rtc_ipv6
+            .unwrap_or_else(crate::util::select_host_address_ipv6);
         let socket_v6 = UdpSocket::bind(format!("[{addr}]:0")).await?;
         let candidate = Candidate::host(socket_v6.local_addr()?, "udp")?;
         debug!("listen on {}", socket_v6.local_addr().unwrap());
@@ -71,6 +104,10 @@ impl Peer {
             inbound: HashMap::new(),
             outbound: vec![],
             sdp_pending: None,
+            wanted: None,
+            egress_estimate: None,
+            max_bitrate: None,
+            permissions: None,
             user_id,
             voice_state,
             commands: recv,
@@ -82,6 +119,8 @@ impl Peer {
                 error!("while running peer: {err:?}");
             }
             debug!("dead!");
+            // lets the sfu tell this peer apart from one that replaced it
+            peer.commands.close();
             _ = peer.emit(PeerEvent::Dead);
         });
 
@@ -114,10 +153,15 @@ impl Peer {
                         Event::Connected => debug!("connected!"),
 
                         Event::MediaAdded(m) => {
-                            // TODO: enforce max bitrate, resolution
+                            // TODO: enforce max resolution
                             debug!("media added {m:?}");
 
                             let mid = m.mid;
+                            if self.inbound.contains_key(&mid) && !self.may_publish(m.kind) {
+                                warn!("refusing {:?} track from {}", m.kind, self.user_id);
+                                self.inbound.remove(&mid);
+                                continue;
+                            }
 
                             let mut events = vec![];
                             if let Some(track) = self.inbound.get_mut(&mid) {
@@ -154,12 +198,19 @@ impl Peer {
 
                         Event::MediaData(m) => self.handle_media_data(m)?,
 
-                        Event::KeyframeRequest(_r) => todo!(),
+                        Event::KeyframeRequest(r) => self.handle_keyframe_request(r)?,
+
+                        Event::EgressBitrateEstimate(
+                            BweKind::Twcc(bitrate) | BweKind::Remb(_, bitrate),
+                        ) => {
+                            trace!("egress estimate {bitrate}");
+                            self.egress_estimate = Some(bitrate.as_u64());
+                            self.select_layers()?;
+                        }
 
                         Event::PeerStats(_)
                         | Event::MediaIngressStats(_)
-                        | Event::MediaEgressStats(_)
-                        | Event::EgressBitrateEstimate(_) => {
+                        | Event::MediaEgressStats(_) => {
                             debug!("{v:?}");
                         }
 
@@ -234,62 +285,243 @@ impl Peer {
             PeerCommand::MediaAdded(t) => {
                 debug!("handle peer command {t:?}");
 
+                let enabled = self.wants(t.peer_id, t.mid);
                 self.outbound.push(TrackOut {
                     kind: t.kind,
                     state: TrackState::Pending,
                     peer_id: t.peer_id,
                     source_mid: t.mid,
-                    enabled: false,
-                    needs_keyframe: false,
+                    enabled,
+                    needs_keyframe: true,
                     thread_id: t.thread_id,
                     key: t.key,
+                    layers: vec![],
+                    rid: None,
+                    target_rid: None,
                 });
             }
-            PeerCommand::MediaData(d) => self.handle_remote_media_data(d),
+            PeerCommand::MediaData(d) => self.handle_remote_media_data(d)?,
+            PeerCommand::KeyframeRequest(r) => self.request_keyframe(r),
+            PeerCommand::MaxBitrate(max) => {
+                self.max_bitrate = max;
+                self.select_layers()?;
+            }
+            PeerCommand::Permissions(permissions) => {
+                self.permissions = permissions;
+                let inbound = std::mem::take(&mut self.inbound);
+                self.inbound = inbound
+                    .into_iter()
+                    .filter(|(_, t)| self.may_publish(t.kind))
+                    .collect();
+            }
             PeerCommand::Kill => self.rtc.disconnect(),
         }
 
         Ok(())
     }
 
-    fn handle_remote_media_data(&mut self, d: MediaData) {
-        let Some(track) = self
+    fn handle_remote_media_data(&mut self, d: MediaData) -> Result<()> {
+        let Some(idx) = self
             .outbound
-            .iter_mut()
-            .find(|t| t.peer_id == d.peer_id && t.source_mid == d.mid)
+            .iter()
+            .position(|t| t.peer_id == d.peer_id && t.source_mid == d.mid)
         else {
-            return;
+            return Ok(());
         };
 
-        // if !track.enabled {
-        //     return;
-        // }
+        if !self.outbound[idx].enabled {
+            return Ok(());
+        }
+
+        if let Some(rid) = d.rid {
+            if self.measure_layer(idx, rid, d.data.len()) {
+                self.select_layers()?;
+            }
+
+            let track = &mut self.outbound[idx];
+            if d.keyframe && track.target_rid == Some(rid) && track.rid != Some(rid) {
+                debug!("switch {} to layer {rid}", track.source_mid);
+                track.rid = Some(rid);
+                self.emit_stats()?;
+            }
+
+            if self.outbound[idx].rid != Some(rid) {
+                return Ok(());
+            }
+        }
+
+        let track = &mut self.outbound[idx];
 
         let Some(mid) = track.state.mid() else {
-            return;
+            return Ok(());
         };
 
         let Some(writer) = self.rtc.writer(mid) else {
-            return;
+            return Ok(());
         };
 
         let Some(pt) = writer.match_params(d.params) else {
-            return;
+            return Ok(());
         };
 
-        // FIXME: keyframe requests
-        // if track.needs_keyframe {
-        //     if let Err(err) = writer.request_keyframe(None, KeyframeRequestKind::Pli) {
-        //         warn!("failed to generate keyframe: {:?}", err);
-        //     } else {
-        //         track.needs_keyframe = false;
-        //     }
-        // }
+        // the client can't decode anything until it gets a keyframe, so ask
+        // the publisher for one instead of waiting for the client to
+        let keyframe = if track.needs_keyframe && track.kind == MediaKind::Video {
+            track.needs_keyframe = false;
+            Some(KeyframeRequest {
+                peer_id: track.peer_id,
+                mid: track.source_mid,
+                rid: track.rid,
+                kind: KeyframeRequestKind::Pli,
+            })
+        } else {
+            None
+        };
 
         if let Err(err) = writer.write(pt, d.network_time, d.time, d.data.to_vec()) {
             warn!("client ({}) failed: {:?}", self.user_id, err);
             self.rtc.disconnect();
         }
+
+        if let Some(keyframe) = keyframe {
+            self.emit(PeerEvent::KeyframeRequest(keyframe))?;
+        }
+
+        Ok(())
+    }
+
+    /// forward a keyframe request from a client to the track's publisher
+    fn handle_keyframe_request(&self, req: str0m::media::KeyframeRequest) -> Result<()> {
+        let Some(track) = self
+            .outbound
+            .iter()
+            .find(|t| t.state.mid() == Some(req.mid))
+        else {
+            debug!("keyframe request for unknown track {}", req.mid);
+            return Ok(());
+        };
+
+        // the client only sees one layer, so ask for a keyframe on that one
+        self.emit(PeerEvent::KeyframeRequest(KeyframeRequest {
+            peer_id: track.peer_id,
+            mid: track.source_mid,
+            rid: track.rid,
+            kind: req.kind,
+        }))
+    }
+
+    /// count a frame of a simulcast layer, returning true if its bitrate changed
+    fn measure_layer(&mut self, idx: usize, rid: Rid, len: usize) -> bool {
+        let now = Instant::now();
+        let track = &mut self.outbound[idx];
+        match track.layers.iter_mut().find(|l| l.rid == rid) {
+            Some(layer) => layer.record(len, now),
+            None => {
+                let mut layer = Layer::new(rid, now);
+                layer.record(len, now);
+                track.layers.push(layer);
+                true
+            }
+        }
+    }
+
+    /// pick which simulcast layer to forward for each track
+    ///
+    /// the budget is the client's estimated bandwidth, capped to the voice
+    /// thread's bitrate, and is split evenly between video tracks
+    fn select_layers(&mut self) -> Result<()> {
+        let budget = match (self.egress_estimate, self.max_bitrate) {
+            (Some(estimate), Some(max)) => Some(estimate.min(max)),
+            (estimate, max) => estimate.or(max),
+        };
+        let videos = self
+            .outbound
+            .iter()
+            .filter(|t| t.enabled && t.kind == MediaKind::Video)
+            .count()
+            .max(1) as u64;
+        let share = budget.map(|b| b / videos);
+
+        let mut requests = vec![];
+        let mut current = 0;
+        let mut desired = 0;
+        for track in self.outbound.iter_mut().filter(|t| t.enabled) {
+            let Some(first) = track.layers.first() else {
+                continue;
+            };
+            let target = simulcast::pick(&track.layers, share)
+                .or(track.target_rid)
+                .or(Some(first.rid));
+            if target != track.target_rid {
+                debug!("pick layer {target:?} for {}", track.source_mid);
+                track.target_rid = target;
+                if track.rid != target {
+                    // the switch happens on the new layer's next keyframe
+                    requests.push(KeyframeRequest {
+                        peer_id: track.peer_id,
+                        mid: track.source_mid,
+                        rid: target,
+                        kind: KeyframeRequestKind::Pli,
+                    });
+                }
+            }
+            current += layer_bitrate(track, target);
+            desired += track
+                .layers
+                .iter()
+                .filter_map(|l| l.bitrate)
+                .max()
+                .unwrap_or(0);
+        }
+
+        // probe for enough bandwidth to send the best layers
+        let desired = self.max_bitrate.map_or(desired, |max| desired.min(max));
+        self.rtc.bwe().set_current_bitrate(Bitrate::bps(current));
+        self.rtc.bwe().set_desired_bitrate(Bitrate::bps(desired));
+
+        for req in requests {
+            self.emit(PeerEvent::KeyframeRequest(req))?;
+        }
+        self.emit_stats()
+    }
+
+    fn emit_stats(&self) -> Result<()> {
+        let tracks = self
+            .outbound
+            .iter()
+            .filter(|t| t.enabled && t.kind == MediaKind::Video)
+            .map(|t| TrackStats {
+                peer_id: t.peer_id,
+                mid: t.source_mid.to_string(),
+                rid: t.rid.map(|r| r.to_string()),
+                bitrate: t
+                    .layers
+                    .iter()
+                    .find(|l| Some(l.rid) == t.rid)
+                    .and_then(|l| l.bitrate),
+            })
+            .collect();
+        self.emit(PeerEvent::Stats(PeerStats {
+            user_id: self.user_id,
+            egress_estimate: self.egress_estimate,
+            max_bitrate: self.max_bitrate,
+            tracks,
+        }))
+    }
+
+    /// ask this peer's client for a keyframe on one of its tracks
+    fn request_keyframe(&mut self, req: KeyframeRequest) {
+        if !self.inbound.contains_key(&req.mid) {
+            return;
+        }
+
+        let Some(mut writer) = self.rtc.writer(req.mid) else {
+            return;
+        };
+
+        if let Err(err) = writer.request_keyframe(req.rid, req.kind) {
+            warn!("failed to request keyframe: {err:?}");
+        }
     }
 
     async fn handle_signalling(&mut self, command: SignallingMessage) -> Result<()> {
@@ -303,8 +535,10 @@ impl Peer {
                     warn!("invalid candidate: {candidate:?}")
                 }
             }
-            SignallingMessage::Want { tracks: _ } => todo!(),
-            SignallingMessage::Have { .. } => panic!("server only"),
+            SignallingMessage::Want { tracks } => self.handle_want(tracks)?,
+            SignallingMessage::Have { .. } | SignallingMessage::Moderate { .. } => {
+                warn!("drop: peer got a server only message");
+            }
             SignallingMessage::VoiceState { state } => {
                 self.voice_state.thread_id = state.unwrap().thread_id;
             }
@@ -312,6 +546,52 @@ impl Peer {
         Ok(())
     }
 
+    /// change which tracks are forwarded, renegotiating if the selection changed
+    ///
+    /// m-lines can't be removed from a session, so unwanted tracks are
+    /// renegotiated as inactive and wanted ones are added or resumed
+    fn handle_want(&mut self, tracks: Vec<TrackRef>) -> Result<()> {
+        let wanted: HashSet<_> = tracks
+            .into_iter()
+            .map(|t| (t.user_id, Mid::from(t.mid.as_str())))
+            .collect();
+        if self.wanted.as_ref() == Some(&wanted) {
+            return Ok(());
+        }
+        for track in &mut self.outbound {
+            let enabled = wanted.contains(&(track.peer_id, track.source_mid));
+            if enabled && !track.enabled {
+                // the receiver can't decode anything until the next keyframe
+                track.needs_keyframe = true;
+            }
+            track.enabled = enabled;
+        }
+        self.wanted = Some(wanted);
+
+        // an offer that's already pending is followed up once it's answered
+        self.negotiate_if_needed()?;
+
+        // the bandwidth is split between a different number of tracks now
+        self.select_layers()
+    }
+
+    /// whether the client is allowed to send a kind of track
+    fn may_publish(&self, kind: MediaKind) -> bool {
+        let needs = match kind {
+            MediaKind::Audio => Permission::VoiceSpeak,
+            MediaKind::Video => Permission::VoiceVideo,
+        };
+        self.permissions
+            .as_ref()
+            .is_some_and(|p| p.contains(&needs))
+    }
+
+    fn wants(&self, peer_id: UserId, mid: Mid) -> bool {
+        self.wanted
+            .as_ref()
+            .is_none_or(|w| w.contains(&(peer_id, mid)))
+    }
+
     fn handle_answer(&mut self, sdp: SessionDescription) -> Result<()> {
         if let Some(pending) = self.sdp_pending.take() {
             let answer = SdpAnswer::from_sdp_string(&sdp)?;
@@ -400,7 +680,8 @@ impl Peer {
         let mut change = self.rtc.sdp_api();
 
         for track in &mut self.outbound {
-            if track.state == TrackState::Pending {
+            // tracks that aren't wanted aren't negotiated until they are
+            if track.state == TrackState::Pending && track.enabled {
                 let mid = change.add_media(
                     track.kind,
                     Direction::SendOnly,
@@ -410,6 +691,14 @@ impl Peer {
                     None,
                 );
                 track.state = TrackState::Negotiating(mid);
+            } else if let TrackState::Open(mid) = track.state {
+                // pause or resume tracks that were (un)selected after being negotiated
+                let dir = if track.enabled {
+                    Direction::SendOnly
+                } else {
+                    Direction::Inactive
+                };
+                change.set_direction(mid, dir);
             }
         }
 
@@ -439,6 +728,15 @@ impl Peer {
             return Ok(());
         };
 
+        let keyframe = match data.codec_extra {
+            CodecExtra::Vp8(extra) => extra.is_keyframe,
+            CodecExtra::H264(extra) => extra.is_keyframe,
+            CodecExtra::Vp9(extra) => extra.is_keyframe,
+            // audio frames decode on their own, and only the video codecs
+            // above are negotiated
+            CodecExtra::None => track.kind == MediaKind::Audio,
+        };
+
         self.emit(PeerEvent::MediaData(MediaData {
             mid: data.mid,
             peer_id: self.user_id,
@@ -446,6 +744,8 @@ impl Peer {
             time: data.time,
             data: data.data.into(),
             params: data.params,
+            rid: data.rid,
+            keyframe,
         }))?;
 
         Ok(())
@@ -459,3 +759,13 @@ impl Peer {
         Ok(())
     }
 }
+
+/// the bitrate of a track's layer, or 0 if it isn't known
+fn layer_bitrate(track: &TrackOut, rid: Option<Rid>) -> u64 {
+    track
+        .layers
+        .iter()
+        .find(|l| Some(l.rid) == rid)
+        .and_then(|l| l.bitrate)
+        .unwrap_or(0)
+}
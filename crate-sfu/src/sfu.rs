@@ -1,18 +1,40 @@
 use crate::{
-    config::Config, peer::Peer, PeerCommand, PeerEvent, PeerEventEnvelope, SfuCommand, SfuEvent,
-    SfuTrack, SignallingMessage,
+    config::Config, peer::Peer, util::system_load, Error, PeerCommand, PeerEvent,
+    PeerEventEnvelope, PeerStats, SfuCommand, SfuEvent, SfuTrack, SignallingMessage,
+    VoiceModeration,
 };
 use anyhow::Result;
-use common::v1::types::{util::Time, voice::VoiceState, UserId};
+use common::v1::types::{util::Time, voice::VoiceState, Permission, UserId};
 use dashmap::DashMap;
-use std::fmt::Debug;
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use str0m::media::{Mid, Rid};
 use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 use tracing::{debug, error, trace, warn};
+use uuid::Uuid;
+
+/// how often to register with the backend
+///
+/// the backend forgets sfus that haven't registered for 30 seconds
+const REGISTER_INTERVAL: Duration = Duration::from_secs(10);
+
+/// the minimum time between keyframe requests sent to a publisher for one track
+///
+/// every subscriber asks for a keyframe when it joins or loses packets, and
+/// keyframes are large, so requests within this interval are dropped
+const KEYFRAME_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
 
 pub struct Sfu {
     peers: DashMap<UserId, UnboundedSender<PeerCommand>>,
-    voice_states: DashMap<UserId, VoiceState>,
+    voice_states: Arc<DashMap<UserId, VoiceState>>,
     tracks: Vec<SfuTrack>,
+    /// when a keyframe was last requested for each (publisher, mid, rid)
+    keyframe_requests: HashMap<(UserId, Mid, Option<Rid>), Instant>,
+    stats: Arc<DashMap<UserId, PeerStats>>,
     config: Config,
 }
 
@@ -30,25 +52,69 @@ impl Sfu {
     pub fn new(config: Config) -> Self {
         Self {
             peers: DashMap::new(),
-            voice_states: DashMap::new(),
+            voice_states: Arc::new(DashMap::new()),
             tracks: Vec::new(),
+            keyframe_requests: HashMap::new(),
+            stats: Arc::new(DashMap::new()),
             config,
         }
     }
 
+    /// what is being sent to each peer, updated as the sfu runs
+    pub fn stats(&self) -> Arc<DashMap<UserId, PeerStats>> {
+        Arc::clone(&self.stats)
+    }
+
+    /// everyone connected to this sfu
+    pub fn voice_states(&self) -> Arc<DashMap<UserId, VoiceState>> {
+        Arc::clone(&self.voice_states)
+    }
+
     pub fn spawn(self) -> UnboundedSender<SfuCommand> {
         let (send, recv) = mpsc::unbounded_channel();
+        tokio::spawn(Self::register(self.config.clone()));
         tokio::spawn(self.run(recv));
         send
     }
 
+    /// periodically register with the backend, so it can send calls here
+    async fn register(config: Config) {
+        let id = Uuid::now_v7();
+        loop {
+            let event = SfuEvent::Register {
+                id,
+                url: config.url.clone(),
+                region: config.region.clone(),
+                load: system_load(),
+            };
+            if let Err(err) = emit(&config, &event).await {
+                error!("failed to register with backend: {err}");
+            }
+            tokio::time::sleep(REGISTER_INTERVAL).await;
+        }
+    }
+
     async fn run(mut self, mut a: UnboundedReceiver<SfuCommand>) -> Result<()> {
         let (peer_send, mut peer_events) = tokio::sync::mpsc::unbounded_channel();
         loop {
             tokio::select! {
-                Some(req) = a.recv() => {
-                    if let Err(err) = self.handle_command(req, peer_send.clone()).await {
-                        error!("error handling peer command: {err}");
+                Some(mut req) = a.recv() => {
+                    let reply = req.reply.take();
+                    let res = match self.handle_command(req, peer_send.clone()).await {
+                        Ok(()) => Ok(()),
+                        Err(err) => match err.downcast::<Error>() {
+                            Ok(err) => {
+                                debug!("rejected command: {err}");
+                                Err(err)
+                            }
+                            Err(err) => {
+                                error!("error handling peer command: {err}");
+                                Ok(())
+                            }
+                        },
+                    };
+                    if let Some(reply) = reply {
+                        _ = reply.send(res);
                     }
                 }
                 Some(envelope) = peer_events.recv() => {
@@ -61,22 +127,22 @@ impl Sfu {
     }
 
     async fn handle_command(
-        &self,
+        &mut self,
         req: SfuCommand,
         peer_send: UnboundedSender<PeerEventEnvelope>,
     ) -> Result<()> {
         trace!("new rpc message {req:?}");
 
-        let user_id = req.user_id.unwrap();
+        let Some(user_id) = req.user_id else {
+            return self.handle_server_command(req.inner).await;
+        };
 
         match &req.inner {
             SignallingMessage::VoiceState { state } => {
                 // user disconnected
                 let Some(state) = state else {
                     let old = self.voice_states.remove(&user_id).map(|s| s.1);
-                    if let Some((_, peer)) = self.peers.remove(&user_id) {
-                        peer.send(PeerCommand::Kill)?
-                    };
+                    self.kill_peer(user_id)?;
                     self.emit(SfuEvent::VoiceState {
                         user_id,
                         state: None,
@@ -87,42 +153,86 @@ impl Sfu {
                     return Ok(());
                 };
 
-                // user connected or moved
-                // let state = VoiceState {
-                //     user_id,
-                //     thread_id: state.thread_id,
-                //     joined_at: Time::now_utc(),
-                // };
-                // let old = self.voice_states.insert(user_id, state.clone());
-                // debug!("got voice state {state:?}");
-                let new_state = VoiceState {
-                    user_id,
-                    thread_id: state.thread_id,
-                    joined_at: Time::now_utc(),
+                // user connected, moved, or changed their own state
+                let old = self.voice_states.get(&user_id).map(|s| s.clone());
+                let moved = old.as_ref().is_some_and(|o| o.thread_id != state.thread_id);
+                if let Some(thread) = &req.thread {
+                    // checked here so joins racing each other can't overfill a call
+                    let members = self
+                        .voice_states
+                        .iter()
+                        .filter(|s| s.thread_id == state.thread_id)
+                        .count();
+                    let joined = old.is_some() && !moved;
+                    if !joined && members as u64 >= thread.user_limit {
+                        return Err(Error::ThreadFull.into());
+                    }
+                }
+                if moved {
+                    // the peer's tracks belong to the old thread, so the client starts over
+                    self.kill_peer(user_id)?;
+                }
+                let permits = |p| req.permissions.as_ref().is_some_and(|ps| ps.contains(&p));
+                let suppress = !permits(Permission::VoiceSpeak);
+                let priority = permits(Permission::VoicePriority) && !suppress;
+                // moderation follows members between threads
+                let moderation = req.moderation.unwrap_or(VoiceModeration {
+                    mute: old.as_ref().is_some_and(|o| o.mute),
+                    deaf: old.as_ref().is_some_and(|o| o.deaf),
+                });
+                let new_state = match old.as_ref().filter(|_| !moved) {
+                    Some(old) => VoiceState {
+                        mute: moderation.mute,
+                        deaf: moderation.deaf,
+                        self_mute: state.self_mute,
+                        self_deaf: state.self_deaf,
+                        suppress,
+                        priority,
+                        ..old.clone()
+                    },
+                    None => VoiceState {
+                        user_id,
+                        thread_id: state.thread_id,
+                        joined_at: Time::now_utc(),
+                        subscribed: None,
+                        mute: moderation.mute,
+                        deaf: moderation.deaf,
+                        self_mute: state.self_mute,
+                        self_deaf: state.self_deaf,
+                        suppress,
+                        priority,
+                    },
                 };
-                let old = self.voice_states.insert(user_id, new_state.clone());
+                self.voice_states.insert(user_id, new_state.clone());
                 debug!("got voice state {new_state:?}");
 
-                // broadcast all tracks in a thread to the user
+                let fresh = !self.peers.contains_key(&user_id);
                 let peer = self
                     .ensure_peer(user_id, peer_send.clone(), &new_state)
                     .await?;
-                for track in &self.tracks {
-                    if track.peer_id == user_id {
-                        continue;
-                    }
+                let max_bitrate = req.thread.as_ref().map(|t| t.bitrate);
+                peer.send(PeerCommand::MaxBitrate(max_bitrate))?;
+                peer.send(PeerCommand::Permissions(req.permissions.clone()))?;
 
-                    let Some(other) = self.voice_states.get(&track.peer_id) else {
-                        warn!("dead track not cleaned up for peer {}", track.peer_id);
-                        continue;
-                    };
+                // broadcast all tracks in a thread to the user
+                if fresh {
+                    for track in &self.tracks {
+                        if track.peer_id == user_id {
+                            continue;
+                        }
 
-                    if state.thread_id != other.thread_id {
-                        continue;
-                    }
+                        let Some(other) = self.voice_states.get(&track.peer_id) else {
+                            warn!("dead track not cleaned up for peer {}", track.peer_id);
+                            continue;
+                        };
 
-                    if let Err(e) = peer.send(PeerCommand::MediaAdded(track.clone())) {
-                        warn!("failed to send MediaAdded to peer {}: {}", user_id, e);
+                        if state.thread_id != other.thread_id {
+                            continue;
+                        }
+
+                        if let Err(e) = peer.send(PeerCommand::MediaAdded(track.clone())) {
+                            warn!("failed to send MediaAdded to peer {}: {}", user_id, e);
+                        }
                     }
                 }
 
@@ -134,6 +244,30 @@ impl Sfu {
                 })
                 .await?;
             }
+            SignallingMessage::Want { tracks } => {
+                let Some(mut state) = self.voice_states.get_mut(&user_id) else {
+                    warn!("no voice state for {user_id}");
+                    return Ok(());
+                };
+                let old = state.clone();
+                state.subscribed = Some(tracks.clone());
+                let new_state = state.clone();
+                drop(state);
+
+                let peer = self.ensure_peer(user_id, peer_send, &new_state).await?;
+                peer.send(PeerCommand::Signalling(req.inner))?;
+
+                // tell everyone who is watching what
+                self.emit(SfuEvent::VoiceState {
+                    user_id,
+                    state: Some(new_state),
+                    old: Some(old),
+                })
+                .await?;
+            }
+            SignallingMessage::Have { .. } | SignallingMessage::Moderate { .. } => {
+                warn!("drop: {user_id} sent a server only message");
+            }
             _ => {
                 let Some(voice_state) = self.voice_states.get(&user_id) else {
                     warn!("no voice state for {user_id}");
@@ -150,6 +284,36 @@ impl Sfu {
         Ok(())
     }
 
+    /// handle a message sent by the backend on its own behalf
+    async fn handle_server_command(&self, command: SignallingMessage) -> Result<()> {
+        match command {
+            SignallingMessage::Moderate {
+                target_id: user_id,
+                mute,
+                deaf,
+            } => {
+                let Some(mut state) = self.voice_states.get_mut(&user_id) else {
+                    warn!("no voice state for {user_id}");
+                    return Ok(());
+                };
+                let old = state.clone();
+                state.mute = mute;
+                state.deaf = deaf;
+                let new_state = state.clone();
+                drop(state);
+
+                self.emit(SfuEvent::VoiceState {
+                    user_id,
+                    state: Some(new_state),
+                    old: Some(old),
+                })
+                .await?;
+            }
+            command => warn!("drop: unexpected server message {command:?}"),
+        }
+        Ok(())
+    }
+
     async fn handle_event(&mut self, envelope: PeerEventEnvelope) -> Result<()> {
         let user_id = envelope.user_id;
         let event = envelope.payload;
@@ -192,6 +356,11 @@ impl Sfu {
                     warn!("user has no voice state");
                     return Ok(());
                 };
+                let is_audio = m.params.spec().codec.is_audio();
+                if is_audio && my_state.is_muted() {
+                    trace!("drop: publisher is muted");
+                    return Ok(());
+                }
                 for a in &self.peers {
                     if a.key() == &user_id {
                         continue;
@@ -205,20 +374,73 @@ impl Sfu {
                         continue;
                     }
 
+                    if !is_subscribed(&state, user_id, m.mid) {
+                        continue;
+                    }
+
+                    if is_audio && state.is_deafened() {
+                        continue;
+                    }
+
                     a.value().send(PeerCommand::MediaData(m.clone()))?;
                 }
             }
 
+            PeerEvent::KeyframeRequest(req) => {
+                let now = Instant::now();
+                let key = (req.peer_id, req.mid, req.rid);
+                if self
+                    .keyframe_requests
+                    .get(&key)
+                    .is_some_and(|last| now.duration_since(*last) < KEYFRAME_REQUEST_INTERVAL)
+                {
+                    trace!("drop: keyframe recently requested");
+                    return Ok(());
+                }
+
+                let Some(publisher) = self.peers.get(&req.peer_id) else {
+                    debug!("drop: publisher is gone");
+                    return Ok(());
+                };
+
+                self.keyframe_requests.insert(key, now);
+                publisher.send(PeerCommand::KeyframeRequest(req))?;
+            }
+
+            PeerEvent::Stats(stats) => {
+                self.stats.insert(user_id, stats);
+            }
+
             PeerEvent::Dead => {
                 debug!("peerevent::dead");
-                self.peers.remove(&user_id);
-                self.tracks.retain(|a| a.peer_id != user_id);
+                if self.peers.get(&user_id).is_some_and(|p| !p.is_closed()) {
+                    debug!("drop: peer was already replaced");
+                    return Ok(());
+                }
+                self.remove_peer(user_id);
             }
         }
 
         Ok(())
     }
 
+    /// forget a peer and everything it was publishing
+    fn remove_peer(&mut self, user_id: UserId) -> Option<UnboundedSender<PeerCommand>> {
+        self.tracks.retain(|a| a.peer_id != user_id);
+        self.keyframe_requests
+            .retain(|(peer_id, _, _), _| *peer_id != user_id);
+        self.stats.remove(&user_id);
+        self.peers.remove(&user_id).map(|p| p.1)
+    }
+
+    /// tear down a user's peer connection
+    fn kill_peer(&mut self, user_id: UserId) -> Result<()> {
+        if let Some(peer) = self.remove_peer(user_id) {
+            peer.send(PeerCommand::Kill)?;
+        }
+        Ok(())
+    }
+
     async fn ensure_peer(
         &self,
         user_id: UserId,
@@ -228,7 +450,8 @@ impl Sfu {
         match self.peers.entry(user_id) {
             dashmap::Entry::Occupied(entry) => Ok(entry.get().clone()),
             dashmap::Entry::Vacant(entry) => {
-                let peer_sender = Peer::spawn(peer_send, user_id, voice_state.clone()).await?;
+                let peer_sender =
+                    Peer::spawn(&self.config, peer_send, user_id, voice_state.clone()).await?;
                 entry.insert(peer_sender.clone());
                 Ok(peer_sender)
             }
@@ -236,13 +459,27 @@ impl Sfu {
     }
 
     async fn emit(&self, event: SfuEvent) -> Result<()> {
-        reqwest::Client::new()
-            .post(format!("{}/api/v1/internal/rpc", self.config.api_url))
-            .header("authorization", format!("Server {}", self.config.token))
-            .json(&event)
-            .send()
-            .await?
-            .error_for_status()?;
-        Ok(())
+        emit(&self.config, &event).await
     }
 }
+
+/// whether a user wants to receive a track
+fn is_subscribed(state: &VoiceState, peer_id: UserId, mid: Mid) -> bool {
+    match &state.subscribed {
+        Some(tracks) => tracks
+            .iter()
+            .any(|t| t.user_id == peer_id && Mid::from(t.mid.as_str()) == mid),
+        None => true,
+    }
+}
+
+async fn emit(config: &Config, event: &SfuEvent) -> Result<()> {
+    reqwest::Client::new()
+        .post(format!("{}/api/v1/internal/rpc", config.api_url))
+        .header("authorization", format!("Server {}", config.token))
+        .json(event)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,110 @@
+//! picking which simulcast layer to forward to each subscriber
+
+use std::time::{Duration, Instant};
+
+use str0m::media::Rid;
+
+/// how long to measure a layer's bitrate over
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// a simulcast layer of a published track, as seen by a subscriber
+#[derive(Debug)]
+pub struct Layer {
+    pub rid: Rid,
+
+    /// bits per second over the last full window, or None until one has passed
+    pub bitrate: Option<u64>,
+
+    bytes: u64,
+    since: Instant,
+}
+
+impl Layer {
+    pub fn new(rid: Rid, now: Instant) -> Self {
+        Self {
+            rid,
+            bitrate: None,
+            bytes: 0,
+            since: now,
+        }
+    }
+
+    /// count a frame of this layer, returning true if the bitrate was updated
+    pub fn record(&mut self, len: usize, now: Instant) -> bool {
+        self.bytes += len as u64;
+        let elapsed = now.duration_since(self.since);
+        if elapsed < WINDOW {
+            return false;
+        }
+        let millis = elapsed.as_millis().max(1) as u64;
+        self.bitrate = Some(self.bytes * 8 * 1000 / millis);
+        self.bytes = 0;
+        self.since = now;
+        true
+    }
+}
+
+/// pick the best layer that fits in a budget, in bits per second
+///
+/// falls back to the cheapest layer if none fit. layers that haven't been
+/// measured yet are ignored, so this is None until one has been.
+pub fn pick(layers: &[Layer], budget: Option<u64>) -> Option<Rid> {
+    let measured = layers
+        .iter()
+        .filter_map(|l| l.bitrate.map(|bitrate| (l.rid, bitrate)));
+    let fits = measured
+        .clone()
+        .filter(|(_, bitrate)| budget.is_none_or(|b| *bitrate <= b))
+        .max_by_key(|(_, bitrate)| *bitrate);
+    fits.or_else(|| measured.min_by_key(|(_, bitrate)| *bitrate))
+        .map(|(rid, _)| rid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(rid: &str, bitrate: Option<u64>) -> Layer {
+        Layer {
+            rid: rid.into(),
+            bitrate,
+            bytes: 0,
+            since: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn picks_the_best_layer_that_fits() {
+        let layers = [
+            layer("l", Some(100_000)),
+            layer("m", Some(500_000)),
+            layer("h", Some(1_500_000)),
+        ];
+        assert_eq!(pick(&layers, Some(1_000_000)), Some("m".into()));
+        assert_eq!(pick(&layers, Some(2_000_000)), Some("h".into()));
+        assert_eq!(pick(&layers, None), Some("h".into()));
+    }
+
+    #[test]
+    fn falls_back_to_the_cheapest_layer() {
+        let layers = [layer("h", Some(1_500_000)), layer("l", Some(100_000))];
+        assert_eq!(pick(&layers, Some(64_000)), Some("l".into()));
+    }
+
+    #[test]
+    fn ignores_unmeasured_layers() {
+        let layers = [layer("l", Some(100_000)), layer("h", None)];
+        assert_eq!(pick(&layers, None), Some("l".into()));
+        assert_eq!(pick(&[layer("h", None)], None), None);
+    }
+
+    #[test]
+    fn measures_bitrate_over_a_window() {
+        let start = Instant::now();
+        let mut layer = Layer::new("l".into(), start);
+        assert!(!layer.record(1000, start + Duration::from_millis(500)));
+        assert_eq!(layer.bitrate, None);
+        assert!(layer.record(1000, start + Duration::from_secs(1)));
+        assert_eq!(layer.bitrate, Some(16_000));
+    }
+}
@@ -35,3 +35,12 @@ pub fn select_host_address_ipv6() -> IpAddr {
 
     panic!("Found no usable network interface");
 }
+
+/// system load, where 1.0 means every cpu is busy
+pub fn system_load() -> f32 {
+    let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+    match System::new().load_average() {
+        Ok(load) => load.one / cpus as f32,
+        Err(_) => 0.0,
+    }
+}
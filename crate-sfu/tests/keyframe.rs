@@ -0,0 +1,54 @@
+//! keyframe requests from subscribers should reach the publisher
+
+use std::time::{Duration, Instant};
+
+use util::{Call, ClientCommand, ClientEvent, Layer};
+
+mod util;
+
+fn keyframe_requests(events: &[ClientEvent]) -> usize {
+    events
+        .iter()
+        .filter(|e| matches!(e, ClientEvent::KeyframeRequest))
+        .count()
+}
+
+#[tokio::test]
+async fn keyframe_requests_reach_the_publisher() {
+    let layers = vec![Layer {
+        rid: None,
+        frame_size: 10,
+    }];
+    let mut call = Call::start(layers, None).await;
+
+    // bob can't decode anything yet, so the sfu asks alice for a keyframe
+    let ClientEvent::MediaData(forwarded, _) = call
+        .subscriber
+        .wait_for(|e| matches!(e, ClientEvent::MediaData(..)))
+        .await
+    else {
+        unreachable!()
+    };
+    call.publisher
+        .wait_for(|e| matches!(e, ClientEvent::KeyframeRequest))
+        .await;
+    let first = Instant::now();
+
+    // requests right after the last one are dropped
+    call.subscriber
+        .commands
+        .send(ClientCommand::RequestKeyframe(forwarded))
+        .unwrap();
+    let events = call.publisher.events_for(Duration::from_millis(500)).await;
+    assert_eq!(keyframe_requests(&events), 0);
+
+    // but requests after the interval get through
+    tokio::time::sleep_until((first + Duration::from_millis(1100)).into()).await;
+    call.subscriber
+        .commands
+        .send(ClientCommand::RequestKeyframe(forwarded))
+        .unwrap();
+    call.publisher
+        .wait_for(|e| matches!(e, ClientEvent::KeyframeRequest))
+        .await;
+}
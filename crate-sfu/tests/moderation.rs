@@ -0,0 +1,179 @@
+//! moderators can mute, deafen, and move people in calls
+
+use std::time::Duration;
+
+use common::v1::types::{
+    voice::{SignallingMessage, VoiceStateUpdate},
+    ThreadId, UserId,
+};
+use util::{command, voice_state, Call, ClientEvent, Layer};
+use uuid::Uuid;
+use voice::{SfuCommand, VoiceModeration};
+
+mod util;
+
+/// frames already queued when something changes may still arrive
+const DRAIN: Duration = Duration::from_millis(500);
+
+fn moderate(user_id: UserId, mute: bool, deaf: bool) -> SfuCommand {
+    SfuCommand {
+        user_id: None,
+        thread: None,
+        permissions: None,
+        moderation: None,
+        reply: None,
+        inner: SignallingMessage::Moderate {
+            target_id: user_id,
+            mute,
+            deaf,
+        },
+    }
+}
+
+fn media(events: &[ClientEvent]) -> usize {
+    events
+        .iter()
+        .filter(|e| matches!(e, ClientEvent::MediaData(..)))
+        .count()
+}
+
+#[tokio::test]
+async fn muted_audio_is_dropped() {
+    let mut call = Call::start_audio().await;
+    call.subscriber
+        .wait_for(|e| matches!(e, ClientEvent::MediaData(..)))
+        .await;
+
+    // people can't unmute themselves
+    let unmute = SignallingMessage::Moderate {
+        target_id: call.alice,
+        mute: false,
+        deaf: false,
+    };
+    call.sfu.send(moderate(call.alice, true, false)).unwrap();
+    let state = voice_state(&mut call.events, call.alice).await.unwrap();
+    assert!(state.mute);
+    call.sfu.send(command(call.alice, unmute)).unwrap();
+
+    call.subscriber.events_for(DRAIN).await;
+    let events = call.subscriber.events_for(Duration::from_secs(1)).await;
+    assert_eq!(media(&events), 0);
+    assert!(call.voice_states.get(&call.alice).unwrap().mute);
+
+    call.sfu.send(moderate(call.alice, false, false)).unwrap();
+    call.subscriber
+        .wait_for(|e| matches!(e, ClientEvent::MediaData(..)))
+        .await;
+}
+
+#[tokio::test]
+async fn deafened_members_receive_no_audio() {
+    let mut call = Call::start_audio().await;
+    call.subscriber
+        .wait_for(|e| matches!(e, ClientEvent::MediaData(..)))
+        .await;
+
+    call.sfu.send(moderate(call.bob, false, true)).unwrap();
+    let state = voice_state(&mut call.events, call.bob).await.unwrap();
+    assert!(state.deaf);
+
+    call.subscriber.events_for(DRAIN).await;
+    let events = call.subscriber.events_for(Duration::from_secs(1)).await;
+    assert_eq!(media(&events), 0);
+}
+
+#[tokio::test]
+async fn muting_does_not_drop_video() {
+    let layers = vec![Layer {
+        rid: None,
+        frame_size: 10,
+    }];
+    let mut call = Call::start(layers, None).await;
+    call.sfu.send(moderate(call.alice, true, false)).unwrap();
+    voice_state(&mut call.events, call.alice).await;
+
+    call.subscriber.events_for(DRAIN).await;
+    call.subscriber
+        .wait_for(|e| matches!(e, ClientEvent::MediaData(..)))
+        .await;
+}
+
+#[tokio::test]
+async fn moving_tears_down_the_peer() {
+    let mut call = Call::start_audio().await;
+    call.subscriber
+        .wait_for(|e| matches!(e, ClientEvent::MediaData(..)))
+        .await;
+    call.sfu.send(moderate(call.bob, true, false)).unwrap();
+    voice_state(&mut call.events, call.bob).await;
+
+    let thread_id: ThreadId = Uuid::now_v7().into();
+    let join = SignallingMessage::VoiceState {
+        state: Some(VoiceStateUpdate {
+            thread_id,
+            region: None,
+            self_mute: false,
+            self_deaf: false,
+        }),
+    };
+    call.sfu.send(command(call.bob, join)).unwrap();
+    let state = voice_state(&mut call.events, call.bob).await.unwrap();
+    assert_eq!(state.thread_id, thread_id);
+    assert_ne!(state.thread_id, call.thread_id);
+
+    // moderation follows them to the new thread
+    assert!(state.mute);
+
+    call.subscriber.events_for(DRAIN).await;
+    let events = call.subscriber.events_for(Duration::from_secs(1)).await;
+    assert_eq!(media(&events), 0);
+}
+
+#[tokio::test]
+async fn moderation_is_kept_when_rejoining() {
+    let mut call = Call::start_audio().await;
+    let leave = SignallingMessage::VoiceState { state: None };
+    call.sfu.send(command(call.bob, leave)).unwrap();
+    while voice_state(&mut call.events, call.bob).await.is_some() {}
+
+    // the backend remembers who was muted, and says so when they come back
+    let join = SfuCommand {
+        moderation: Some(VoiceModeration {
+            mute: true,
+            deaf: true,
+        }),
+        ..command(
+            call.bob,
+            SignallingMessage::VoiceState {
+                state: Some(VoiceStateUpdate {
+                    thread_id: call.thread_id,
+                    region: None,
+                    self_mute: false,
+                    self_deaf: false,
+                }),
+            },
+        )
+    };
+    call.sfu.send(join).unwrap();
+    let state = voice_state(&mut call.events, call.bob).await.unwrap();
+    assert!(state.mute);
+    assert!(state.deaf);
+}
+
+#[test]
+fn server_commands_have_no_user() {
+    let target_id: UserId = Uuid::now_v7().into();
+    let json = serde_json::json!({
+        "user_id": null,
+        "type": "Moderate",
+        "target_id": target_id,
+        "mute": true,
+        "deaf": false,
+    });
+    let command: SfuCommand = serde_json::from_value(json).unwrap();
+    assert_eq!(command.user_id, None);
+    assert!(matches!(
+        command.inner,
+        SignallingMessage::Moderate { target_id: t, mute: true, deaf: false } if t == target_id
+    ));
+}
@@ -0,0 +1,69 @@
+//! members can only publish what their voice permissions allow
+
+use std::time::Duration;
+
+use common::v1::types::{
+    voice::{MediaKindSerde, SignallingMessage},
+    Permission,
+};
+use tokio::time::timeout;
+use util::{dispatch, Call, Client, ClientEvent, Layer};
+
+mod util;
+
+fn video() -> Vec<Layer> {
+    vec![Layer {
+        rid: None,
+        frame_size: 10,
+    }]
+}
+
+/// check that the sfu never offers alice's track to bob
+async fn assert_not_forwarded(call: &mut Call) {
+    let offer = dispatch(&mut call.events, call.bob, |m| {
+        matches!(m, SignallingMessage::Offer { .. })
+    });
+    assert!(timeout(Duration::from_secs(2), offer).await.is_err());
+}
+
+#[tokio::test]
+async fn video_needs_permission() {
+    let publisher = Client::publisher(video()).await;
+    let permissions = Some(vec![Permission::VoiceSpeak]);
+    let mut call = Call::connect(publisher, MediaKindSerde::Video, None, permissions).await;
+    assert_not_forwarded(&mut call).await;
+}
+
+#[tokio::test]
+async fn audio_needs_permission() {
+    let publisher = Client::audio_publisher().await;
+    let permissions = Some(vec![Permission::VoiceVideo, Permission::VoicePriority]);
+    let mut call = Call::connect(publisher, MediaKindSerde::Audio, None, permissions).await;
+    let state = call.voice_states.get(&call.alice).unwrap().clone();
+    assert!(state.suppress);
+    assert!(!state.priority);
+    assert_not_forwarded(&mut call).await;
+}
+
+#[tokio::test]
+async fn missing_permissions_allow_nothing() {
+    let publisher = Client::audio_publisher().await;
+    let mut call = Call::connect(publisher, MediaKindSerde::Audio, None, None).await;
+    let state = call.voice_states.get(&call.alice).unwrap().clone();
+    assert!(state.suppress);
+    assert_not_forwarded(&mut call).await;
+}
+
+#[tokio::test]
+async fn permitted_tracks_are_forwarded() {
+    let publisher = Client::audio_publisher().await;
+    let permissions = Some(vec![Permission::VoiceSpeak, Permission::VoicePriority]);
+    let mut call = Call::connect(publisher, MediaKindSerde::Audio, None, permissions).await;
+    let state = call.voice_states.get(&call.alice).unwrap().clone();
+    assert!(!state.suppress);
+    assert!(state.priority);
+    call.subscribe().await;
+    call.subscriber
+        .wait_for(|e| matches!(e, ClientEvent::MediaData(..)))
+        .await;
+}
@@ -0,0 +1,89 @@
+//! the sfu should forward the simulcast layer that fits each subscriber
+
+use std::time::Duration;
+
+use common::v1::types::thread::voice::ThreadTypeVoicePublic;
+use util::{Call, ClientEvent, Layer, TIMEOUT};
+
+mod util;
+
+/// frame sizes, which make the high layer ~240kbps and the low layer ~24kbps
+const HIGH: usize = 1000;
+const LOW: usize = 100;
+
+fn layers() -> Vec<Layer> {
+    vec![
+        Layer {
+            rid: Some("h"),
+            frame_size: HIGH,
+        },
+        Layer {
+            rid: Some("l"),
+            frame_size: LOW,
+        },
+    ]
+}
+
+/// wait for the stats to say bob is being sent a layer
+async fn wait_for_layer(call: &Call, rid: &str) {
+    let deadline = tokio::time::Instant::now() + TIMEOUT;
+    loop {
+        let current = call
+            .stats
+            .get(&call.bob)
+            .and_then(|s| s.tracks.first().and_then(|t| t.rid.clone()));
+        if current.as_deref() == Some(rid) {
+            return;
+        }
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "never switched to {rid}"
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+fn frames(events: &[ClientEvent], size: usize) -> usize {
+    events
+        .iter()
+        .filter(|e| matches!(e, ClientEvent::MediaData(_, s) if *s == size))
+        .count()
+}
+
+#[tokio::test]
+async fn layers_follow_the_bandwidth_estimate() {
+    let mut call = Call::start(layers(), None).await;
+
+    // the estimate starts low, so the low layer is picked once it's measured
+    wait_for_layer(&call, "l").await;
+
+    // and the high layer once the estimate grows enough
+    wait_for_layer(&call, "h").await;
+    call.subscriber
+        .wait_for(|e| matches!(e, ClientEvent::MediaData(_, HIGH)))
+        .await;
+}
+
+#[tokio::test]
+async fn bitrate_is_capped_to_the_thread() {
+    let thread = ThreadTypeVoicePublic {
+        bitrate: 64000,
+        user_limit: 100,
+    };
+    let mut call = Call::start(layers(), Some(thread)).await;
+    wait_for_layer(&call, "l").await;
+
+    // frames sent before the switch may still be queued
+    call.subscriber
+        .wait_for(|e| matches!(e, ClientEvent::MediaData(_, LOW)))
+        .await;
+
+    // without the cap, the estimate would have grown enough for the high layer by now
+    let events = call.subscriber.events_for(Duration::from_secs(4)).await;
+    assert!(frames(&events, LOW) > 0);
+    assert_eq!(frames(&events, HIGH), 0);
+
+    let stats = call.stats.get(&call.bob).unwrap();
+    assert_eq!(stats.max_bitrate, Some(64000));
+    assert_eq!(stats.tracks[0].rid.as_deref(), Some("l"));
+}
@@ -0,0 +1,83 @@
+//! calls can't have more people than their thread's user limit
+
+use common::v1::types::{
+    thread::voice::ThreadTypeVoicePublic,
+    voice::{MediaKindSerde, SignallingMessage, VoiceStateUpdate},
+    ThreadId, UserId,
+};
+use tokio::sync::oneshot;
+use util::{command, speaker, voice_state, Call, Client};
+use uuid::Uuid;
+use voice::{Error, SfuCommand};
+
+mod util;
+
+/// join a thread, waiting for the sfu to accept or reject it
+async fn join(
+    call: &Call,
+    user_id: UserId,
+    thread_id: ThreadId,
+    thread: &ThreadTypeVoicePublic,
+) -> Result<(), Error> {
+    let (reply, accepted) = oneshot::channel();
+    let join = SfuCommand {
+        thread: Some(thread.clone()),
+        reply: Some(reply),
+        ..command(
+            user_id,
+            SignallingMessage::VoiceState {
+                state: Some(VoiceStateUpdate {
+                    thread_id,
+                    region: None,
+                    self_mute: false,
+                    self_deaf: false,
+                }),
+            },
+        )
+    };
+    call.sfu.send(join).unwrap();
+    accepted.await.expect("sfu died")
+}
+
+#[tokio::test]
+async fn full_calls_reject_new_members() {
+    let thread = ThreadTypeVoicePublic {
+        bitrate: 64000,
+        user_limit: 2,
+    };
+    let publisher = Client::audio_publisher().await;
+    let mut call = Call::connect(
+        publisher,
+        MediaKindSerde::Audio,
+        Some(thread.clone()),
+        speaker(),
+    )
+    .await;
+    let carol: UserId = Uuid::now_v7().into();
+
+    let res = join(&call, carol, call.thread_id, &thread).await;
+    assert!(matches!(res, Err(Error::ThreadFull)));
+    assert!(!call.voice_states.contains_key(&carol));
+
+    // people already in the call can still update their state
+    join(&call, call.alice, call.thread_id, &thread)
+        .await
+        .expect("alice is already in the call");
+
+    // other threads have their own limit
+    join(&call, carol, Uuid::now_v7().into(), &thread)
+        .await
+        .expect("the other thread is empty");
+
+    // and there's room again once someone leaves
+    let leave = SignallingMessage::VoiceState { state: None };
+    call.sfu.send(command(call.bob, leave)).unwrap();
+    while voice_state(&mut call.events, call.bob).await.is_some() {}
+    join(&call, carol, call.thread_id, &thread)
+        .await
+        .expect("bob left the call");
+    assert_eq!(
+        call.voice_states.get(&carol).unwrap().thread_id,
+        call.thread_id
+    );
+}
@@ -0,0 +1,497 @@
+//! runs a real sfu with clients over loopback udp
+//!
+//! the backend is faked with a small http server that hands sfu events to the test.
+
+// not every test uses every helper
+#![allow(dead_code)]
+
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::{routing::post, Json};
+use common::v1::types::{
+    thread::voice::ThreadTypeVoicePublic,
+    voice::{
+        MediaKindSerde, SessionDescription, SignallingMessage, TrackMetadata, TrackRef, VoiceState,
+        VoiceStateUpdate,
+    },
+    Permission, ThreadId, UserId,
+};
+use dashmap::DashMap;
+use str0m::{
+    change::{SdpAnswer, SdpOffer, SdpPendingOffer},
+    format::Codec,
+    media::{Direction, Frequency, KeyframeRequestKind, MediaKind, MediaTime, Mid, Rid, Simulcast},
+    net::{Protocol, Receive},
+    Candidate, Event, Input, Output, Rtc, RtcConfig,
+};
+use tokio::{
+    net::UdpSocket,
+    select,
+    sync::{mpsc, oneshot},
+    time::{sleep_until, timeout},
+};
+use uuid::Uuid;
+use voice::{config::Config, sfu::Sfu, PeerStats, SfuCommand, SfuEvent};
+
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// how often the publisher sends a video frame
+const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+
+pub enum ClientCommand {
+    AcceptOffer(SessionDescription, oneshot::Sender<SessionDescription>),
+    AcceptAnswer(SessionDescription),
+    RequestKeyframe(Mid),
+}
+
+#[derive(Debug)]
+pub enum ClientEvent {
+    /// a frame was received, with its size
+    MediaData(Mid, usize),
+    KeyframeRequest,
+}
+
+/// a simulcast layer for a publisher to send
+pub struct Layer {
+    /// None to send without simulcast
+    pub rid: Option<&'static str>,
+
+    /// the size of every frame, which lets tests tell layers apart
+    pub frame_size: usize,
+}
+
+pub struct Client {
+    pub commands: mpsc::UnboundedSender<ClientCommand>,
+    pub events: mpsc::UnboundedReceiver<ClientEvent>,
+}
+
+impl Client {
+    /// spawn a client that publishes a video track
+    ///
+    /// returns the offer to send to the sfu and the published track's mid
+    pub async fn publisher(layers: Vec<Layer>) -> (Self, SessionDescription, Mid) {
+        Self::publish(MediaKind::Video, layers).await
+    }
+
+    /// spawn a client that publishes an audio track
+    pub async fn audio_publisher() -> (Self, SessionDescription, Mid) {
+        let layers = vec![Layer {
+            rid: None,
+            frame_size: 10,
+        }];
+        Self::publish(MediaKind::Audio, layers).await
+    }
+
+    async fn publish(kind: MediaKind, layers: Vec<Layer>) -> (Self, SessionDescription, Mid) {
+        let (mut rtc, socket) = rtc().await;
+        let rids: Vec<Rid> = layers.iter().filter_map(|l| l.rid).map(Rid::from).collect();
+        let simulcast = (!rids.is_empty()).then(|| Simulcast {
+            send: rids,
+            recv: vec![],
+        });
+        let mut change = rtc.sdp_api();
+        let mid = change.add_media(kind, Direction::SendOnly, None, None, simulcast);
+        let (offer, pending) = change.apply().unwrap();
+        let client = Self::spawn(rtc, socket, Some(pending), Some((mid, layers)));
+        (client, SessionDescription(offer.to_sdp_string()), mid)
+    }
+
+    /// spawn a client that only receives
+    pub async fn subscriber() -> Self {
+        let (rtc, socket) = rtc().await;
+        Self::spawn(rtc, socket, None, None)
+    }
+
+    fn spawn(
+        rtc: Rtc,
+        socket: UdpSocket,
+        pending: Option<SdpPendingOffer>,
+        sending: Option<(Mid, Vec<Layer>)>,
+    ) -> Self {
+        let (command_send, command_recv) = mpsc::unbounded_channel();
+        let (event_send, event_recv) = mpsc::unbounded_channel();
+        tokio::spawn(run(rtc, socket, pending, sending, command_recv, event_send));
+        Client {
+            commands: command_send,
+            events: event_recv,
+        }
+    }
+
+    /// wait for the next event matching a filter
+    pub async fn wait_for(&mut self, mut f: impl FnMut(&ClientEvent) -> bool) -> ClientEvent {
+        timeout(TIMEOUT, async {
+            loop {
+                let event = self.events.recv().await.expect("client died");
+                if f(&event) {
+                    return event;
+                }
+            }
+        })
+        .await
+        .expect("timed out waiting for client event")
+    }
+
+    /// collect the events received within a duration
+    pub async fn events_for(&mut self, duration: Duration) -> Vec<ClientEvent> {
+        let deadline = tokio::time::Instant::now() + duration;
+        let mut events = vec![];
+        loop {
+            select! {
+                _ = sleep_until(deadline) => return events,
+                event = self.events.recv() => events.extend(event),
+            }
+        }
+    }
+}
+
+async fn rtc() -> (Rtc, UdpSocket) {
+    let mut rtc = RtcConfig::new().build();
+    let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let candidate = Candidate::host(socket.local_addr().unwrap(), "udp").unwrap();
+    rtc.add_local_candidate(candidate);
+    (rtc, socket)
+}
+
+async fn run(
+    mut rtc: Rtc,
+    socket: UdpSocket,
+    mut pending: Option<SdpPendingOffer>,
+    sending: Option<(Mid, Vec<Layer>)>,
+    mut commands: mpsc::UnboundedReceiver<ClientCommand>,
+    events: mpsc::UnboundedSender<ClientEvent>,
+) {
+    let mut connected = false;
+    let mut next_frame = Instant::now();
+    let mut frame = 0u64;
+    let mut buf = [0u8; 2000];
+    loop {
+        let timeout = match rtc.poll_output().unwrap() {
+            Output::Timeout(t) => t,
+            Output::Transmit(t) => {
+                socket.send_to(&t.contents, t.destination).await.unwrap();
+                continue;
+            }
+            Output::Event(e) => {
+                let event = match e {
+                    Event::Connected => {
+                        connected = true;
+                        None
+                    }
+                    Event::MediaData(m) => Some(ClientEvent::MediaData(m.mid, m.data.len())),
+                    Event::KeyframeRequest(_) => Some(ClientEvent::KeyframeRequest),
+                    _ => None,
+                };
+                if let Some(event) = event {
+                    if events.send(event).is_err() {
+                        return;
+                    }
+                }
+                continue;
+            }
+        };
+
+        if let Some((mid, layers)) = sending.as_ref().filter(|_| connected) {
+            if Instant::now() >= next_frame {
+                for layer in layers {
+                    write_frame(&mut rtc, *mid, layer, frame);
+                }
+                frame += 1;
+                next_frame = Instant::now() + FRAME_INTERVAL;
+                continue;
+            }
+        }
+
+        let wake = if sending.is_some() && connected {
+            timeout.min(next_frame)
+        } else {
+            timeout
+        };
+
+        let input = select! {
+            _ = sleep_until(wake.into()) => Input::Timeout(Instant::now()),
+            recv = socket.recv_from(&mut buf) => {
+                let (n, source) = recv.unwrap();
+                Input::Receive(
+                    Instant::now(),
+                    Receive {
+                        proto: Protocol::Udp,
+                        source,
+                        destination: socket.local_addr().unwrap(),
+                        contents: buf[..n].try_into().unwrap(),
+                    },
+                )
+            }
+            command = commands.recv() => {
+                match command {
+                    Some(ClientCommand::AcceptOffer(sdp, reply)) => {
+                        let offer = SdpOffer::from_sdp_string(&sdp).unwrap();
+                        let answer = rtc.sdp_api().accept_offer(offer).unwrap();
+                        _ = reply.send(SessionDescription(answer.to_sdp_string()));
+                    }
+                    Some(ClientCommand::AcceptAnswer(sdp)) => {
+                        let answer = SdpAnswer::from_sdp_string(&sdp).unwrap();
+                        let pending = pending.take().expect("no offer was sent");
+                        rtc.sdp_api().accept_answer(pending, answer).unwrap();
+                    }
+                    Some(ClientCommand::RequestKeyframe(mid)) => {
+                        rtc.writer(mid)
+                            .unwrap()
+                            .request_keyframe(None, KeyframeRequestKind::Pli)
+                            .unwrap();
+                    }
+                    None => return,
+                }
+                continue;
+            }
+        };
+        rtc.handle_input(input).unwrap();
+    }
+}
+
+fn write_frame(rtc: &mut Rtc, mid: Mid, layer: &Layer, frame: u64) {
+    let mut writer = rtc.writer(mid).unwrap();
+    let params = writer
+        .payload_params()
+        .find(|p| matches!(p.spec().codec, Codec::Vp8 | Codec::Opus))
+        .expect("vp8 or opus was negotiated");
+    let pt = params.pt();
+    let time = match params.spec().codec {
+        Codec::Opus => MediaTime::new(frame * 1600, Frequency::FORTY_EIGHT_KHZ),
+        _ => MediaTime::from_90khz(frame * 3000),
+    };
+    if let Some(rid) = layer.rid {
+        writer = writer.rid(rid.into());
+    }
+    // every video frame is a vp8 keyframe. the rest of the contents don't matter,
+    // only that the sfu forwards them.
+    let mut data = vec![0u8; layer.frame_size];
+    data[0] = 0x10;
+    writer.write(pt, Instant::now(), time, data).unwrap();
+}
+
+/// pretend to be the backend, forwarding every event from the sfu
+async fn backend() -> (String, mpsc::UnboundedReceiver<SfuEvent>) {
+    let (send, recv) = mpsc::unbounded_channel();
+    let router = axum::Router::new().route(
+        "/api/v1/internal/rpc",
+        post(|Json(event): Json<SfuEvent>| async move {
+            _ = send.send(event);
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .await
+        .unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    (url, recv)
+}
+
+fn config(api_url: String) -> Config {
+    Config {
+        host: "127.0.0.1:0".to_owned(),
+        api_url,
+        url: "http://127.0.0.1:0".to_owned(),
+        region: "default".to_owned(),
+        token: "test".to_owned(),
+        rtc_ipv4: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+        rtc_ipv6: Some(IpAddr::V6(Ipv6Addr::LOCALHOST)),
+        rust_log: "info".to_owned(),
+    }
+}
+
+/// a call where alice publishes a track and bob receives it
+pub struct Call {
+    pub sfu: mpsc::UnboundedSender<SfuCommand>,
+    pub stats: Arc<DashMap<UserId, PeerStats>>,
+    pub voice_states: Arc<DashMap<UserId, VoiceState>>,
+    pub thread_id: ThreadId,
+    pub events: mpsc::UnboundedReceiver<SfuEvent>,
+    pub alice: UserId,
+    pub bob: UserId,
+    pub publisher: Client,
+    pub subscriber: Client,
+}
+
+impl Call {
+    /// start a call, returning once bob has answered the sfu's offer
+    pub async fn start(layers: Vec<Layer>, thread: Option<ThreadTypeVoicePublic>) -> Self {
+        let publisher = Client::publisher(layers).await;
+        let mut call = Self::connect(publisher, MediaKindSerde::Video, thread, speaker()).await;
+        call.subscribe().await;
+        call
+    }
+
+    /// start a call where alice publishes an audio track instead
+    pub async fn start_audio() -> Self {
+        let publisher = Client::audio_publisher().await;
+        let mut call = Self::connect(publisher, MediaKindSerde::Audio, None, speaker()).await;
+        call.subscribe().await;
+        call
+    }
+
+    /// join alice and bob to a call, returning once alice's offer is answered
+    ///
+    /// `permissions` are alice's. bob isn't sent anything until `subscribe`.
+    pub async fn connect(
+        publisher: (Client, SessionDescription, Mid),
+        kind: MediaKindSerde,
+        thread: Option<ThreadTypeVoicePublic>,
+        permissions: Option<Vec<Permission>>,
+    ) -> Self {
+        Self::connect_wanting(publisher, kind, thread, permissions, None).await
+    }
+
+    /// like `connect`, but bob picks which tracks to receive before alice publishes
+    pub async fn connect_wanting(
+        (publisher, sdp, mid): (Client, SessionDescription, Mid),
+        kind: MediaKindSerde,
+        thread: Option<ThreadTypeVoicePublic>,
+        permissions: Option<Vec<Permission>>,
+        bob_wants: Option<Vec<TrackRef>>,
+    ) -> Self {
+        let (api_url, mut events) = backend().await;
+        let sfu = Sfu::new(config(api_url));
+        let stats = sfu.stats();
+        let voice_states = sfu.voice_states();
+        let sfu = sfu.spawn();
+        let thread_id: ThreadId = Uuid::now_v7().into();
+        let alice: UserId = Uuid::now_v7().into();
+        let bob: UserId = Uuid::now_v7().into();
+
+        for (user_id, permissions) in [(alice, permissions), (bob, None)] {
+            let join = SignallingMessage::VoiceState {
+                state: Some(VoiceStateUpdate {
+                    thread_id,
+                    region: None,
+                    self_mute: false,
+                    self_deaf: false,
+                }),
+            };
+            sfu.send(SfuCommand {
+                user_id: Some(user_id),
+                thread: thread.clone(),
+                permissions,
+                moderation: None,
+                reply: None,
+                inner: join,
+            })
+            .unwrap();
+        }
+
+        if let Some(tracks) = bob_wants {
+            sfu.send(command(bob, SignallingMessage::Want { tracks }))
+                .unwrap();
+        }
+
+        let tracks = vec![TrackMetadata {
+            mid: mid.to_string(),
+            kind,
+            key: "user".to_owned(),
+        }];
+        sfu.send(command(alice, SignallingMessage::Offer { sdp, tracks }))
+            .unwrap();
+        let SignallingMessage::Answer { sdp } = dispatch(&mut events, alice, |m| {
+            matches!(m, SignallingMessage::Answer { .. })
+        })
+        .await
+        else {
+            unreachable!()
+        };
+        publisher
+            .commands
+            .send(ClientCommand::AcceptAnswer(sdp))
+            .unwrap();
+
+        Call {
+            sfu,
+            stats,
+            voice_states,
+            thread_id,
+            events,
+            alice,
+            bob,
+            publisher,
+            subscriber: Client::subscriber().await,
+        }
+    }
+
+    /// wait for the sfu to offer alice's track to bob, and answer it
+    ///
+    /// returns the offer
+    pub async fn subscribe(&mut self) -> SessionDescription {
+        let SignallingMessage::Offer { sdp, .. } = dispatch(&mut self.events, self.bob, |m| {
+            matches!(m, SignallingMessage::Offer { .. })
+        })
+        .await
+        else {
+            unreachable!()
+        };
+        let (reply, answer) = oneshot::channel();
+        self.subscriber
+            .commands
+            .send(ClientCommand::AcceptOffer(sdp.clone(), reply))
+            .unwrap();
+        let answer = answer.await.unwrap();
+        self.sfu
+            .send(command(self.bob, SignallingMessage::Answer { sdp: answer }))
+            .unwrap();
+        sdp
+    }
+}
+
+/// the permissions to publish audio and video
+pub fn speaker() -> Option<Vec<Permission>> {
+    Some(vec![Permission::VoiceSpeak, Permission::VoiceVideo])
+}
+
+pub fn command(user_id: UserId, inner: SignallingMessage) -> SfuCommand {
+    SfuCommand {
+        user_id: Some(user_id),
+        thread: None,
+        permissions: None,
+        moderation: None,
+        reply: None,
+        inner,
+    }
+}
+
+/// wait for the sfu to send a signalling message to a user
+pub async fn dispatch(
+    events: &mut mpsc::UnboundedReceiver<SfuEvent>,
+    to: UserId,
+    mut f: impl FnMut(&SignallingMessage) -> bool,
+) -> SignallingMessage {
+    timeout(TIMEOUT, async {
+        loop {
+            match events.recv().await.expect("sfu died") {
+                SfuEvent::VoiceDispatch { user_id, payload } if user_id == to && f(&payload) => {
+                    return payload
+                }
+                _ => {}
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for sfu")
+}
+
+/// wait for the sfu to change a user's voice state
+pub async fn voice_state(
+    events: &mut mpsc::UnboundedReceiver<SfuEvent>,
+    of: UserId,
+) -> Option<VoiceState> {
+    timeout(TIMEOUT, async {
+        loop {
+            match events.recv().await.expect("sfu died") {
+                SfuEvent::VoiceState { user_id, state, .. } if user_id == of => return state,
+                _ => {}
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for sfu")
+}
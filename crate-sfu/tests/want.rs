@@ -0,0 +1,87 @@
+//! members only receive the tracks they pick
+
+use std::time::Duration;
+
+use common::v1::types::voice::{MediaKindSerde, SignallingMessage, TrackRef};
+use tokio::time::timeout;
+use util::{command, dispatch, speaker, Call, Client, ClientEvent, Layer};
+
+mod util;
+
+/// frames already queued when something changes may still arrive
+const DRAIN: Duration = Duration::from_millis(500);
+
+fn media(events: &[ClientEvent]) -> usize {
+    events
+        .iter()
+        .filter(|e| matches!(e, ClientEvent::MediaData(..)))
+        .count()
+}
+
+fn want(call: &Call, tracks: Vec<TrackRef>) {
+    call.sfu
+        .send(command(call.bob, SignallingMessage::Want { tracks }))
+        .unwrap();
+}
+
+#[tokio::test]
+async fn only_wanted_tracks_are_sent() {
+    let layers = vec![Layer {
+        rid: None,
+        frame_size: 10,
+    }];
+    let (publisher, sdp, mid) = Client::publisher(layers).await;
+    let mut call = Call::connect_wanting(
+        (publisher, sdp, mid),
+        MediaKindSerde::Video,
+        None,
+        speaker(),
+        Some(vec![]),
+    )
+    .await;
+
+    // bob picked nothing before alice published, so her track isn't offered
+    let offer = dispatch(&mut call.events, call.bob, |m| {
+        matches!(m, SignallingMessage::Offer { .. })
+    });
+    assert!(timeout(Duration::from_secs(2), offer).await.is_err());
+
+    let track = TrackRef {
+        user_id: call.alice,
+        mid: mid.to_string(),
+    };
+    want(&call, vec![track.clone()]);
+    let offer = call.subscribe().await;
+    assert!(offer.contains("a=sendonly"));
+    call.subscriber
+        .wait_for(|e| matches!(e, ClientEvent::MediaData(..)))
+        .await;
+
+    // picking the same tracks again doesn't renegotiate
+    want(&call, vec![track.clone()]);
+    let offer = dispatch(&mut call.events, call.bob, |m| {
+        matches!(m, SignallingMessage::Offer { .. })
+    });
+    assert!(timeout(Duration::from_secs(1), offer).await.is_err());
+
+    // unpicking a negotiated track renegotiates it as inactive
+    want(&call, vec![]);
+    let offer = call.subscribe().await;
+    assert!(offer.contains("a=inactive"));
+    assert!(!offer.contains("a=sendonly"));
+    call.subscriber.events_for(DRAIN).await;
+    let events = call.subscriber.events_for(Duration::from_secs(1)).await;
+    assert_eq!(media(&events), 0);
+
+    // bob can't decode anything after picking it again until alice sends a keyframe
+    while call.publisher.events.try_recv().is_ok() {}
+    want(&call, vec![track]);
+    let offer = call.subscribe().await;
+    assert!(offer.contains("a=sendonly"));
+    call.publisher
+        .wait_for(|e| matches!(e, ClientEvent::KeyframeRequest))
+        .await;
+    call.subscriber
+        .wait_for(|e| matches!(e, ClientEvent::MediaData(..)))
+        .await;
+}